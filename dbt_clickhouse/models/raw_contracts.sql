@@ -0,0 +1,27 @@
+{{ config(
+    materialized='table',
+    engine='MergeTree()',
+    order_by=['block_number', 'address'],
+    unique_key='address'
+) }}
+
+-- Debug information
+{{ log("Starting raw_contracts model", info=True) }}
+
+with source as (
+    select * from file('../indexer/raw_data/contracts/*.json', 'JSONEachRow',
+    'address String,
+     creator String,
+     transaction_hash String,
+     block_number UInt64,
+     init_code_hash String,
+     datetime DateTime')
+)
+
+select 
+    *,
+    '{{ invocation_id }}' as _invocation_id
+from source
+{% if is_incremental() %}
+where datetime > (select max(datetime) from {{ this }})
+{% endif %}
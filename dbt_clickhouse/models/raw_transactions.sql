@@ -18,6 +18,8 @@ with source as (
      gas_price UInt64,
      hash String,
      input String,
+     method_id Nullable(String),
+     method_signature Nullable(String),
      nonce UInt64,
      r String,
      s String,
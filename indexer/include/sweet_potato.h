@@ -0,0 +1,46 @@
+/*
+ * C interface to the sweet-potato indexer, exported by libindexer when built with
+ * `cargo build --release --features ffi`.
+ *
+ * Functions return SP_OK on success and SP_ERROR on failure; sp_last_error() then
+ * describes the failure for the calling thread. Records are handed to callbacks as
+ * NUL-terminated JSON strings that are only valid during the callback.
+ */
+#ifndef SWEET_POTATO_H
+#define SWEET_POTATO_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SP_OK 0
+#define SP_ERROR -1
+
+/* Return non-zero to stop the call that is invoking the callback. */
+typedef int (*sp_record_callback)(const char *record_json, void *user_data);
+
+const char *sp_version(void);
+
+/* Message for the last failed call on this thread, or NULL; valid until the next call. */
+const char *sp_last_error(void);
+
+/* Fetch blocks [start, start + count) and write them under raw_data_path.
+ * NULL arguments select the defaults: RAW_DATA_PATH, "json", "none". */
+int sp_index_range(uint64_t start, uint64_t count, const char *raw_data_path,
+                   const char *output_format, const char *compress);
+
+/* Stream {"block", "transactions", "receipts"} for every block from start onwards,
+ * polling for new blocks every poll_interval_ms once the chain head is reached. */
+int sp_follow(uint64_t start, uint64_t poll_interval_ms, sp_record_callback callback, void *user_data);
+
+/* Read stored "blocks", "transactions", "receipts", "contracts", "data_usage", "ordering_analysis", "nft_transfers", "token_transfers", "balances" or "contract_state" records for blocks [first, last]. */
+int sp_query(const char *dataset, uint64_t first, uint64_t last, const char *raw_data_path,
+             sp_record_callback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SWEET_POTATO_H */
@@ -0,0 +1,284 @@
+//! Which datasets and fields are read through the query layers, to find stored data nobody uses.
+//!
+//! - GraphQL counts the fields each query selects per dataset
+//! - `query` counts, per dataset named in the SQL, the columns it mentions; `*` reads whole records
+//! - REST routes and Arrow Flight return whole records
+//!
+//! Counts are kept in memory and merged into `raw_data/access_stats.json` by [`flush`], which
+//! `serve` does every `ACCESS_STATS_FLUSH_SECS` and `query` after each statement. [`report`] weighs
+//! the fields no query named by their share of a sample of the stored records, heaviest first:
+//! those are the candidates for leaving out of the indexed output.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+use crate::clock;
+use crate::sinks::clickhouse::columns_for;
+use crate::store::{self, DATASETS};
+
+// Records sampled per dataset to weigh its fields
+const SAMPLE_RECORDS: usize = 500;
+// Unused fields below this share of a dataset's bytes are left out of the periodic report
+pub const HEAVY_SHARE: f64 = 5.0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetAccess {
+    pub reads: u64,
+    // Reads that returned every field, without naming any
+    pub whole_record_reads: u64,
+    pub fields: BTreeMap<String, u64>,
+    pub last_read: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccessStats {
+    pub since: Option<DateTime<Utc>>,
+    pub datasets: BTreeMap<String, DatasetAccess>,
+}
+
+impl AccessStats {
+    pub fn merge(&mut self, pending: BTreeMap<String, DatasetAccess>) {
+        for (dataset, access) in pending {
+            self.since.get_or_insert(access.last_read.unwrap_or_else(clock::now));
+            let total = self.datasets.entry(dataset).or_default();
+            total.reads += access.reads;
+            total.whole_record_reads += access.whole_record_reads;
+            for (field, reads) in access.fields {
+                *total.fields.entry(field).or_default() += reads;
+            }
+            total.last_read = total.last_read.max(access.last_read);
+        }
+    }
+}
+
+static PENDING: LazyLock<Mutex<BTreeMap<String, DatasetAccess>>> = LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+fn add(dataset: &str, fields: Option<Vec<String>>) {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    let access = pending.entry(dataset.to_string()).or_default();
+    access.reads += 1;
+    access.last_read = Some(clock::now());
+    match fields {
+        Some(fields) => {
+            for field in fields {
+                *access.fields.entry(field).or_default() += 1;
+            }
+        },
+        None => access.whole_record_reads += 1,
+    }
+}
+
+// A read of `dataset` that used these fields
+pub fn record_fields(dataset: &str, fields: impl IntoIterator<Item = String>) {
+    add(dataset, Some(fields.into_iter().collect()));
+}
+
+// A read of `dataset` that returned whole records
+pub fn record_whole(dataset: &str) {
+    add(dataset, None);
+}
+
+// Reserved words, which name a column (e.g. `from`) only when double-quoted
+const SQL_KEYWORDS: &[&str] = &[
+    "all", "and", "as", "asc", "by", "case", "desc", "distinct", "else", "end", "from", "group", "having", "in", "inner", "is",
+    "join", "left", "limit", "not", "null", "on", "or", "order", "outer", "right", "select", "then", "union", "when", "where", "with",
+];
+
+// Words of a statement outside string literals, lowercased and flagged when double-quoted
+fn sql_words(sql: &str) -> Vec<(String, bool)> {
+    let mut words = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => while chars.next().is_some_and(|c| c != '\'') {},
+            '"' => words.push((chars.by_ref().take_while(|c| *c != '"').collect::<String>().to_lowercase(), true)),
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = c.to_ascii_lowercase().to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    word.push(c.to_ascii_lowercase());
+                }
+                words.push((word, false));
+            },
+            _ => {},
+        }
+    }
+    words
+}
+
+// The datasets a SQL statement reads, each with the columns it mentions, or None when it selects
+// `*`. Columns are matched by name, so one named in any clause counts as read.
+pub fn sql_references(sql: &str) -> Vec<(&'static str, Option<Vec<String>>)> {
+    let words = sql_words(sql);
+    let keyword = |(word, quoted): &(String, bool)| !quoted && SQL_KEYWORDS.contains(&word.as_str());
+    // Table aliases follow the dataset name, optionally after `as`
+    let mut aliases = BTreeSet::new();
+    for (i, (word, _)) in words.iter().enumerate() {
+        if DATASETS.contains(&word.as_str()) {
+            let next = words[i + 1..].iter().find(|word| word.0 != "as" || word.1);
+            aliases.extend(next.filter(|next| !keyword(next)).map(|next| next.0.as_str()));
+        }
+    }
+    let identifiers: BTreeSet<&str> =
+        words.iter().filter(|word| !keyword(word) && !aliases.contains(word.0.as_str())).map(|word| word.0.as_str()).collect();
+    let code = sql.to_lowercase().split_whitespace().collect::<String>();
+    let selects_all = ["select*", ",*", ".*"].iter().any(|star| code.contains(star));
+    DATASETS
+        .iter()
+        .filter(|dataset| identifiers.contains(**dataset))
+        .map(|dataset| {
+            let columns = columns_for(dataset).iter().map(|(column, _)| *column).filter(|column| identifiers.contains(column));
+            (*dataset, (!selects_all).then(|| columns.map(str::to_string).collect()))
+        })
+        .collect()
+}
+
+pub fn record_sql(sql: &str) {
+    for (dataset, fields) in sql_references(sql) {
+        add(dataset, fields);
+    }
+}
+
+fn stats_path(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("access_stats.json")
+}
+
+pub fn load(raw_data_path: &str) -> Result<AccessStats> {
+    match fs::read(stats_path(raw_data_path)) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AccessStats::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Adds the reads counted since the last flush to the stats file
+pub fn flush(raw_data_path: &str) -> Result<()> {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let mut stats = load(raw_data_path)?;
+    stats.merge(pending);
+    let path = stats_path(raw_data_path);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&stats)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// Serialized bytes per top-level field over a sample of the dataset's stored records
+pub fn field_bytes(raw_data_path: &str, dataset: &str) -> Result<BTreeMap<String, u64>> {
+    let mut bytes = BTreeMap::new();
+    let mut sampled = 0;
+    for path in store::dataset_files(raw_data_path, dataset)? {
+        for record in store::read_records(&path)? {
+            let Value::Object(fields) = record else { continue };
+            for (field, value) in fields {
+                *bytes.entry(field).or_default() += value.to_string().len() as u64;
+            }
+            sampled += 1;
+        }
+        if sampled >= SAMPLE_RECORDS {
+            break;
+        }
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnusedField {
+    pub field: String,
+    // Share of the dataset's stored bytes, in percent
+    pub share: f64,
+}
+
+// Stored fields no read named, heaviest first
+pub fn unused_fields(access: &DatasetAccess, bytes: &BTreeMap<String, u64>) -> Vec<UnusedField> {
+    let total = bytes.values().sum::<u64>().max(1) as f64;
+    let mut unused: Vec<UnusedField> = bytes
+        .iter()
+        .filter(|(field, _)| !access.fields.contains_key(*field))
+        .map(|(field, bytes)| UnusedField { field: field.clone(), share: *bytes as f64 * 100.0 / total })
+        .collect();
+    unused.sort_by(|a, b| b.share.total_cmp(&a.share));
+    unused
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetReport {
+    pub dataset: &'static str,
+    pub access: DatasetAccess,
+    pub unused: Vec<UnusedField>,
+}
+
+// Reads and unused fields of every stored dataset
+pub fn report(raw_data_path: &str) -> Result<(AccessStats, Vec<DatasetReport>)> {
+    let stats = load(raw_data_path)?;
+    let mut reports = Vec::new();
+    for dataset in DATASETS {
+        let bytes = field_bytes(raw_data_path, dataset)?;
+        if bytes.is_empty() {
+            continue;
+        }
+        let access = stats.datasets.get(dataset).cloned().unwrap_or_default();
+        let unused = unused_fields(&access, &bytes);
+        reports.push(DatasetReport { dataset, access, unused });
+    }
+    Ok((stats, reports))
+}
+
+pub fn render(report: &DatasetReport, min_share: f64) -> String {
+    let access = &report.access;
+    let mut line = match access.reads {
+        0 => format!("{}: never read", report.dataset),
+        reads => format!("{}: {} reads, {} of whole records", report.dataset, reads, access.whole_record_reads),
+    };
+    let unused: Vec<String> = report
+        .unused
+        .iter()
+        .filter(|field| field.share >= min_share)
+        .map(|field| format!("{} ({:.1}%)", field.field, field.share))
+        .collect();
+    if access.reads > 0 && !unused.is_empty() {
+        line.push_str(&format!("; never named: {}", unused.join(", ")));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql_references_and_unused_fields() {
+        let sql = "SELECT hash, gas_price FROM transactions t JOIN receipts r ON t.hash = r.transaction_hash WHERE t.input <> 'blocks'";
+        let references = sql_references(sql);
+        let columns = ["gas_price", "hash", "input"].map(str::to_string).to_vec();
+        assert_eq!(references[0], ("transactions", Some(columns)));
+        assert_eq!(references[1].0, "receipts");
+        assert_eq!(sql_references("select count(*) from blocks"), vec![("blocks", Some(Vec::new()))]);
+        assert_eq!(sql_references("select * from blocks"), vec![("blocks", None)]);
+
+        let mut stats = AccessStats::default();
+        let read = |fields: &[&str], whole| DatasetAccess {
+            reads: 1,
+            whole_record_reads: whole,
+            fields: fields.iter().map(|field| (field.to_string(), 1)).collect(),
+            last_read: None,
+        };
+        stats.merge(BTreeMap::from([("blocks".to_string(), read(&["number"], 0))]));
+        stats.merge(BTreeMap::from([("blocks".to_string(), read(&["number", "hash"], 1))]));
+        let blocks = &stats.datasets["blocks"];
+        assert_eq!((blocks.reads, blocks.whole_record_reads, blocks.fields["number"]), (2, 1, 2));
+
+        let bytes = [("number", 10), ("hash", 30), ("logs_bloom", 50), ("miner", 10)].map(|(field, bytes)| (field.to_string(), bytes));
+        let unused = unused_fields(blocks, &BTreeMap::from(bytes));
+        let shares: Vec<(&str, f64)> = unused.iter().map(|field| (field.field.as_str(), field.share)).collect();
+        assert_eq!(shares, vec![("logs_bloom", 50.0), ("miner", 10.0)]);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::OpenOptions;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::models::{ActivityRole, AddressActivity, TransformedReceipt};
+use crate::store;
+
+// Directory under RAW_DATA_PATH holding the index, one append-only ndjson shard per address prefix
+pub const INDEX_DIR: &str = "address_index";
+// ClickHouse table, ordered by address so lookups read a single range of the table
+pub const INDEX_TABLE: &str = "address_activity";
+
+// Shard of an address: the first byte after `0x`, so a lookup only reads 1/256 of the index
+fn shard_path(raw_data_path: &str, address: &str) -> PathBuf {
+    let address = address.to_lowercase();
+    let prefix = address.strip_prefix("0x").and_then(|hex| hex.get(..2)).unwrap_or("xx");
+    Path::new(raw_data_path).join(INDEX_DIR).join(format!("{}.ndjson", prefix))
+}
+
+// Every address touched by the receipts' transactions. Receipts carry `from`/`to` themselves,
+// so this works for receipt-only batches; a created contract is recorded as the `to` side.
+pub fn extract(receipts: &[TransformedReceipt]) -> Vec<AddressActivity> {
+    let mut entries = BTreeSet::new();
+    for receipt in receipts {
+        let entry = |address: &str, role| AddressActivity {
+            address: address.to_lowercase(),
+            block_number: receipt.block_number,
+            transaction_hash: receipt.transaction_hash.clone(),
+            role,
+        };
+        entries.insert(entry(&receipt.from, ActivityRole::From));
+        if let Some(to) = receipt.to.as_deref().or(receipt.contract_address.as_deref()) {
+            entries.insert(entry(to, ActivityRole::To));
+        }
+        for log in &receipt.logs {
+            if let Some(address) = log["address"].as_str() {
+                entries.insert(entry(address, ActivityRole::LogEmitter));
+            }
+        }
+    }
+    entries.into_iter().collect()
+}
+
+// Appends entries to their shards. Re-indexing a range appends duplicates, which `lookup` drops.
+pub fn append(raw_data_path: &str, entries: &[AddressActivity]) -> Result<()> {
+    let mut shards: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+    for entry in entries {
+        let data = shards.entry(shard_path(raw_data_path, &entry.address)).or_default();
+        serde_json::to_writer(&mut *data, entry)?;
+        data.push(b'\n');
+    }
+    store::ensure_directory(&format!("{}/{}", raw_data_path, INDEX_DIR))?;
+    for (path, data) in shards {
+        OpenOptions::new().create(true).append(true).open(path)?.write_all(&data)?;
+    }
+    Ok(())
+}
+
+// Entries for one address ordered by block, optionally limited to one role
+pub fn lookup(raw_data_path: &str, address: &str, role: Option<ActivityRole>) -> Result<Vec<AddressActivity>> {
+    let path = shard_path(raw_data_path, address);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let address = address.to_lowercase();
+    let mut entries = BTreeSet::new();
+    for line in store::open_file(&path)?.lines() {
+        let line = line?;
+        if line.trim().is_empty() || !line.contains(address.as_str()) {
+            continue;
+        }
+        let entry: AddressActivity = serde_json::from_str(&line)?;
+        if entry.address == address && role.is_none_or(|role| entry.role == role) {
+            entries.insert(entry);
+        }
+    }
+    // Same address throughout, so the derived order is by block, then transaction and role
+    Ok(entries.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::fs;
+
+    fn receipt(hash: &str, block_number: u64, from: &str, to: Option<&str>, emitters: &[&str]) -> TransformedReceipt {
+        TransformedReceipt {
+            from: from.to_string(),
+            logs: emitters.iter().map(|address| serde_json::json!({ "address": address })).collect(),
+            to: to.map(str::to_string),
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(block_number, 0)
+        }
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let root = "test_address_index";
+        let _ = fs::remove_dir_all(root);
+        let receipts = vec![
+            receipt("0x1", 5, "0xAB01", Some("0xcd02"), &["0xcd02", "0xcd02"]),
+            receipt("0x2", 3, "0xcd02", Some("0xab01"), &[]),
+        ];
+        let entries = extract(&receipts);
+        assert_eq!(entries.len(), 5);
+
+        append(root, &entries).unwrap();
+        append(root, &entries).unwrap();
+
+        let found = lookup(root, "0xAB01", None).unwrap();
+        assert_eq!(
+            found.iter().map(|e| (e.block_number, e.role)).collect::<Vec<_>>(),
+            vec![(3, ActivityRole::To), (5, ActivityRole::From)]
+        );
+        let emitted = lookup(root, "0xcd02", Some(ActivityRole::LogEmitter)).unwrap();
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].transaction_hash, "0x1");
+        assert!(lookup(root, "0xef03", None).unwrap().is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
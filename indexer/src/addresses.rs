@@ -0,0 +1,233 @@
+//! The `addresses` dimension: every address the indexer has seen, whether it is a contract or a
+//! token, the block it was first seen in and a label, for warehouse models to join facts against.
+//!
+//! The current rows are kept in `raw_data/addresses.json`. Each batch updates them from its
+//! receipts (senders, recipients, created contracts, log emitters), contracts and token transfers,
+//! and only the rows that are new or changed go into the batch's `addresses` dataset, versioned by
+//! `updated_block`. Labels come from `ADDRESS_LABELS_PATH` (`address,label` lines), else from the
+//! token's name or symbol.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::AddressDimension;
+use crate::pipeline::TransformedBatch;
+
+fn state_path(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("addresses.json")
+}
+
+// `address,label` per line; blank lines and `#` comments are skipped
+pub fn parse_labels(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(','))
+        .map(|(address, label)| (address.trim().to_lowercase(), label.trim().to_string()))
+        .filter(|(_, label)| !label.is_empty())
+        .collect()
+}
+
+// What one batch shows about an address
+#[derive(Debug)]
+struct Seen {
+    first: (u64, DateTime<Utc>),
+    last: (u64, DateTime<Utc>),
+    is_contract: bool,
+    is_token: bool,
+    token_label: Option<String>,
+}
+
+fn note<'a>(seen: &'a mut BTreeMap<String, Seen>, address: &str, block: u64, datetime: DateTime<Utc>) -> &'a mut Seen {
+    let entry = seen.entry(address.to_lowercase()).or_insert(Seen {
+        first: (block, datetime),
+        last: (block, datetime),
+        is_contract: false,
+        is_token: false,
+        token_label: None,
+    });
+    entry.first = entry.first.min((block, datetime));
+    entry.last = entry.last.max((block, datetime));
+    entry
+}
+
+fn observe(batch: &TransformedBatch) -> BTreeMap<String, Seen> {
+    let mut seen = BTreeMap::new();
+    for receipt in &batch.receipts {
+        note(&mut seen, &receipt.from, receipt.block_number, receipt.datetime);
+        if let Some(to) = &receipt.to {
+            note(&mut seen, to, receipt.block_number, receipt.datetime);
+        }
+        if let Some(created) = &receipt.contract_address {
+            note(&mut seen, created, receipt.block_number, receipt.datetime).is_contract = true;
+        }
+        for emitter in receipt.logs.iter().filter_map(|log| log["address"].as_str()) {
+            note(&mut seen, emitter, receipt.block_number, receipt.datetime).is_contract = true;
+        }
+    }
+    for contract in &batch.contracts {
+        note(&mut seen, &contract.address, contract.block_number, contract.datetime).is_contract = true;
+        note(&mut seen, &contract.creator, contract.block_number, contract.datetime);
+    }
+    for transfer in &batch.token_transfers {
+        note(&mut seen, &transfer.from, transfer.block_number, transfer.datetime);
+        note(&mut seen, &transfer.to, transfer.block_number, transfer.datetime);
+        let token = note(&mut seen, &transfer.token_address, transfer.block_number, transfer.datetime);
+        token.is_contract = true;
+        token.is_token = true;
+        if token.token_label.is_none() {
+            token.token_label = transfer.token_name.clone().or_else(|| transfer.token_symbol.clone());
+        }
+    }
+    for transfer in &batch.nft_transfers {
+        note(&mut seen, &transfer.from, transfer.block_number, transfer.datetime);
+        note(&mut seen, &transfer.to, transfer.block_number, transfer.datetime);
+        let token = note(&mut seen, &transfer.token_address, transfer.block_number, transfer.datetime);
+        token.is_contract = true;
+        token.is_token = true;
+    }
+    seen
+}
+
+pub struct Dimension {
+    path: PathBuf,
+    labels: HashMap<String, String>,
+    rows: BTreeMap<String, AddressDimension>,
+}
+
+impl Dimension {
+    // The dimension as last saved under `raw_data_path`, empty before the first save
+    pub fn load(raw_data_path: &str) -> Result<Self> {
+        let path = state_path(raw_data_path);
+        let rows: Vec<AddressDimension> = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let labels = match env::var("ADDRESS_LABELS_PATH") {
+            Ok(labels_path) => {
+                let contents = fs::read_to_string(&labels_path)
+                    .map_err(|e| anyhow::anyhow!("Could not read ADDRESS_LABELS_PATH {}: {}", labels_path, e))?;
+                parse_labels(&contents)
+            },
+            Err(_) => HashMap::new(),
+        };
+        Ok(Self { path, labels, rows: rows.into_iter().map(|row| (row.address.clone(), row)).collect() })
+    }
+
+    pub fn with_labels(self, labels: HashMap<String, String>) -> Self {
+        Self { labels, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn get(&self, address: &str) -> Option<&AddressDimension> {
+        self.rows.get(&address.to_lowercase())
+    }
+
+    // Folds the batch into the dimension and returns the rows that are new or changed. A changed
+    // row's `updated_block` never goes backwards, so re-indexing an earlier range (which can only
+    // move `first_seen_block` back) still produces the newest version.
+    pub fn update(&mut self, batch: &TransformedBatch) -> Vec<AddressDimension> {
+        let mut changed = Vec::new();
+        for (address, seen) in observe(batch) {
+            let before = self.rows.get(&address).cloned();
+            let mut row = before.clone().unwrap_or_else(|| AddressDimension {
+                address: address.clone(),
+                is_contract: false,
+                is_token: false,
+                label: None,
+                first_seen_block: seen.first.0,
+                first_seen_at: seen.first.1,
+                updated_block: seen.last.0,
+                datetime: seen.last.1,
+            });
+            if seen.first.0 < row.first_seen_block {
+                (row.first_seen_block, row.first_seen_at) = seen.first;
+            }
+            row.is_contract |= seen.is_contract;
+            row.is_token |= seen.is_token;
+            row.label = self.labels.get(&address).cloned().or(row.label).or(seen.token_label);
+            if before.as_ref() == Some(&row) {
+                continue;
+            }
+            if seen.last.0 > row.updated_block {
+                (row.updated_block, row.datetime) = seen.last;
+            }
+            self.rows.insert(address, row.clone());
+            changed.push(row);
+        }
+        changed
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let rows: Vec<&AddressDimension> = self.rows.values().collect();
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&rows)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn batch(block: u64, token: &str, holder: &str) -> TransformedBatch {
+        let datetime = format!("2024-01-01T00:00:{:02}Z", block);
+        TransformedBatch {
+            contracts: vec![serde_json::from_value(json!({
+                "address": token, "creator": "0xdeployer", "transaction_hash": "0x1", "block_number": block,
+                "init_code_hash": "0x2", "datetime": datetime,
+            }))
+            .unwrap()],
+            token_transfers: vec![serde_json::from_value(json!({
+                "token_address": token, "from": "0xdeployer", "to": holder, "value": "1", "token_symbol": "TKN",
+                "token_name": null, "token_decimals": 18, "value_scaled": "0.000000000000000001", "block_number": block,
+                "transaction_hash": "0x1", "log_index": 0, "datetime": datetime,
+            }))
+            .unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_incremental_rows() {
+        let root = "test_address_dimension";
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root).unwrap();
+        let labels = parse_labels("# team wallets\n0xDEPLOYER, Deployer\n0xbad\n");
+        let mut dimension = Dimension::load(root).unwrap().with_labels(labels);
+
+        let rows = dimension.update(&batch(20, "0xToken", "0xa"));
+        assert_eq!(rows.len(), 3);
+        let token = dimension.get("0xtoken").unwrap();
+        assert!(token.is_contract && token.is_token);
+        assert_eq!(token.label.as_deref(), Some("TKN"));
+        assert_eq!(dimension.get("0xdeployer").unwrap().label.as_deref(), Some("Deployer"));
+
+        // Nothing new about known addresses: no rows
+        assert!(dimension.update(&batch(30, "0xtoken", "0xa")).is_empty());
+        // A new holder, and an earlier range moving first_seen back without lowering the version
+        dimension.save().unwrap();
+        let mut dimension = Dimension::load(root).unwrap();
+        let rows = dimension.update(&batch(10, "0xtoken", "0xb"));
+        let addresses: Vec<_> = rows.iter().map(|row| (row.address.as_str(), row.first_seen_block, row.updated_block)).collect();
+        assert_eq!(addresses, vec![("0xb", 10, 10), ("0xdeployer", 10, 20), ("0xtoken", 10, 20)]);
+        assert_eq!(dimension.len(), 4);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! Lag alerts: with `LAG_ALERT_BLOCKS` set, the blocks this replica is behind the chain head
+//! (`blocks_behind_head` in `metrics`) are checked at every head poll. Going over the threshold
+//! fires an alert, repeated every `LAG_ALERT_REPEAT_SECS` while it lasts, and falling back to it
+//! resolves the alert.
+//!
+//! Every alert is logged, POSTed as JSON to `LAG_ALERT_WEBHOOK` and passed to `LAG_ALERT_COMMAND`
+//! (run with `sh -c`) in the `LAG_ALERT` variable, with `LAG_ALERT_STATUS` and `LAG_BLOCKS` for
+//! scripts that do not parse JSON. A failing hook is logged and does not affect indexing.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::config::env_or;
+use crate::{clock, coordination, metrics};
+
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Default)]
+pub struct LagAlertConfig {
+    // Blocks behind the head above which the alert fires; None disables alerts
+    pub threshold: Option<u64>,
+    pub webhook: Option<String>,
+    pub command: Option<String>,
+    // How often lag is checked, the head poll interval
+    pub interval: Duration,
+    // Zero sends a firing alert once
+    pub repeat: Duration,
+}
+
+impl LagAlertConfig {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+        Ok(Self {
+            threshold: var("LAG_ALERT_BLOCKS").map(|blocks| blocks.trim().parse()).transpose()?,
+            webhook: var("LAG_ALERT_WEBHOOK"),
+            command: var("LAG_ALERT_COMMAND"),
+            interval: Duration::from_secs(env_or("METRICS_HEAD_POLL_SECS", 15)?.max(1)),
+            repeat: Duration::from_secs(env_or("LAG_ALERT_REPEAT_SECS", 3600)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LagAlarm {
+    firing: bool,
+    last_sent: Option<DateTime<Utc>>,
+}
+
+impl LagAlarm {
+    // The alert a lag reading calls for, if any
+    pub fn update(&mut self, lag: u64, threshold: u64, repeat: Duration, now: DateTime<Utc>) -> Option<AlertStatus> {
+        if lag <= threshold {
+            self.last_sent = None;
+            return std::mem::take(&mut self.firing).then_some(AlertStatus::Resolved);
+        }
+        let due = match self.last_sent {
+            Some(_) if repeat.is_zero() => false,
+            Some(sent) => (now - sent).to_std().unwrap_or_default() >= repeat,
+            None => true,
+        };
+        self.firing = true;
+        if due {
+            self.last_sent = Some(now);
+        }
+        due.then_some(AlertStatus::Firing)
+    }
+}
+
+async fn send(config: &LagAlertConfig, status: AlertStatus, lag: u64, alert: &Value) {
+    if let Some(url) = &config.webhook {
+        let response = reqwest::Client::new().post(url).json(alert).timeout(Duration::from_secs(10)).send().await;
+        match response {
+            Ok(response) if !response.status().is_success() => log::warn!("Lag alert webhook {} answered {}", url, response.status()),
+            Ok(_) => {},
+            Err(e) => log::warn!("Could not deliver the lag alert to {}: {}", url, e),
+        }
+    }
+    if let Some(command) = &config.command {
+        let result = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("LAG_ALERT", alert.to_string())
+            .env("LAG_ALERT_STATUS", status.as_str())
+            .env("LAG_BLOCKS", lag.to_string())
+            .status()
+            .await;
+        match result {
+            Ok(exit) if !exit.success() => log::warn!("Lag alert command exited with {}", exit),
+            Ok(_) => {},
+            Err(e) => log::warn!("Could not run the lag alert command: {}", e),
+        }
+    }
+}
+
+// Checks the lag in the background once per process, when a threshold is configured
+pub fn watch(config: LagAlertConfig) {
+    let Some(threshold) = config.threshold else {
+        return;
+    };
+    if WATCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    metrics::poll_head(config.interval);
+    tokio::spawn(async move {
+        let mut alarm = LagAlarm::default();
+        loop {
+            clock::sleep(config.interval).await;
+            // Before the first fetched block the lag is the whole chain, not a delay
+            if metrics::highest_fetched() == 0 {
+                continue;
+            }
+            let lag = metrics::blocks_behind_head();
+            let Some(status) = alarm.update(lag, threshold, config.repeat, clock::now()) else {
+                continue;
+            };
+            metrics::set_lag_alert_firing(status == AlertStatus::Firing);
+            match status {
+                AlertStatus::Firing => log::warn!("Lag alert: {} blocks behind the head (threshold {})", lag, threshold),
+                AlertStatus::Resolved => log::info!("Lag alert resolved: {} blocks behind the head", lag),
+            }
+            let alert = json!({
+                "status": status,
+                "blocks_behind_head": lag,
+                "threshold": threshold,
+                "head_block": metrics::head_block(),
+                "highest_fetched_block": metrics::highest_fetched(),
+                "replica": coordination::replica_id(),
+                "at": clock::now(),
+            });
+            send(&config, status, lag, &alert).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_lag_alarm() {
+        let at = |minute| Utc.with_ymd_and_hms(2024, 1, 1, 0, minute, 0).unwrap();
+        let repeat = Duration::from_secs(600);
+        let mut alarm = LagAlarm::default();
+        assert_eq!(alarm.update(5, 10, repeat, at(0)), None);
+        assert_eq!(alarm.update(11, 10, repeat, at(1)), Some(AlertStatus::Firing));
+        assert_eq!(alarm.update(50, 10, repeat, at(5)), None);
+        assert_eq!(alarm.update(50, 10, repeat, at(11)), Some(AlertStatus::Firing));
+        assert_eq!(alarm.update(10, 10, repeat, at(12)), Some(AlertStatus::Resolved));
+        assert_eq!(alarm.update(3, 10, repeat, at(13)), None);
+
+        let mut once = LagAlarm::default();
+        assert_eq!(once.update(11, 10, Duration::ZERO, at(0)), Some(AlertStatus::Firing));
+        assert_eq!(once.update(11, 10, Duration::ZERO, at(59)), None);
+    }
+}
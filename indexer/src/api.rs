@@ -0,0 +1,402 @@
+//! REST API (`api` feature) over the indexed output under RAW_DATA_PATH.
+//!
+//! - `GET /blocks?limit=`: the latest indexed blocks, newest first
+//! - `GET /blocks/{number}`
+//! - `GET /txs/{hash}`
+//! - `GET /receipts/{hash}`
+//! - `GET /address/{address}/txs?limit=&offset=` (newest first)
+//! - `GET /events`: every topic0 written so far with its signature and log count (see `topics`)
+//! - `GET /events/{topic0}`
+//! - `POST /graphql` (schema in `graphql`), with a playground on `GET /graphql`
+//!
+//! - `GET /`: a read-only explorer page over these routes (off with `API_EXPLORER=false`)
+//!
+//! Responses are the stored JSON records; results go through the query cache in `cache`.
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::access_stats;
+use crate::activity;
+use crate::cache::{CacheConfig, QueryCache, Scope};
+use crate::clock;
+use crate::config::env_or;
+use crate::graphql::{self, ApiSchema};
+use crate::shutdown;
+use crate::store;
+use crate::topics;
+
+const MAX_PAGE_SIZE: usize = 1000;
+
+struct ApiState {
+    raw_data_path: String,
+    cache: QueryCache,
+    schema: ApiSchema,
+}
+
+enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Internal(e) => {
+                log::error!("API request failed: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            },
+        };
+        (status, axum::Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+fn json_response(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+// Runs store reads on the blocking pool and caches the serialized result; `null` means not found
+async fn cached<F>(state: &ApiState, key: String, scope: Scope, read: F) -> Result<String, ApiError>
+where
+    F: FnOnce(&str) -> Result<Value> + Send + 'static,
+{
+    let raw_data_path = state.raw_data_path.clone();
+    let body = state
+        .cache
+        .get_or_compute(&key, scope, || async move {
+            let value = tokio::task::spawn_blocking(move || read(&raw_data_path)).await??;
+            Ok(serde_json::to_string(&value)?)
+        })
+        .await?;
+    if body == "null" {
+        return Err(ApiError::NotFound(format!("{} not found", key)));
+    }
+    Ok(body)
+}
+
+fn normalize_hash(value: &str) -> Result<String, ApiError> {
+    let value = value.to_lowercase();
+    match value.strip_prefix("0x") {
+        Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(value),
+        _ => Err(ApiError::BadRequest(format!("Expected a 0x-prefixed hex value, got {}", value))),
+    }
+}
+
+async fn get_latest_blocks(State(state): State<Arc<ApiState>>, Query(page): Query<Page>) -> Result<Response, ApiError> {
+    access_stats::record_whole("blocks");
+    let limit = page.limit.unwrap_or(25).min(MAX_PAGE_SIZE);
+    let body = cached(&state, format!("latest blocks {}", limit), Scope::Head, move |raw_data_path| {
+        let numbers: Vec<u64> = store::indexed_block_numbers(raw_data_path)?.into_iter().rev().take(limit).collect();
+        let (Some(&last), Some(&first)) = (numbers.first(), numbers.last()) else { return Ok(json!([])) };
+        let mut blocks: Vec<Value> = store::read_range(raw_data_path, "blocks", first, last)?;
+        blocks.sort_by_key(|block| std::cmp::Reverse(block["number"].as_u64()));
+        Ok(Value::Array(blocks))
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+async fn get_block(State(state): State<Arc<ApiState>>, Path(number): Path<u64>) -> Result<Response, ApiError> {
+    access_stats::record_whole("blocks");
+    let body = cached(&state, format!("block {}", number), Scope::UpTo(number), move |raw_data_path| {
+        let blocks: Vec<Value> = store::read_range(raw_data_path, "blocks", number, number)?;
+        Ok(blocks.into_iter().next().unwrap_or(Value::Null))
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+async fn get_transaction(State(state): State<Arc<ApiState>>, Path(hash): Path<String>) -> Result<Response, ApiError> {
+    let hash = normalize_hash(&hash)?;
+    access_stats::record_whole("transactions");
+    let body = cached(&state, format!("transaction {}", hash), Scope::Head, move |raw_data_path| {
+        let record = store::find_record(raw_data_path, "transactions", &format!("tx_{}", hash), "hash", &hash, None)?;
+        Ok(record.unwrap_or(Value::Null))
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+async fn get_receipt(State(state): State<Arc<ApiState>>, Path(hash): Path<String>) -> Result<Response, ApiError> {
+    let hash = normalize_hash(&hash)?;
+    access_stats::record_whole("receipts");
+    let body = cached(&state, format!("receipt {}", hash), Scope::Head, move |raw_data_path| {
+        let file_name = format!("receipt_{}", hash);
+        let record = store::find_record(raw_data_path, "receipts", &file_name, "transaction_hash", &hash, None)?;
+        Ok(record.unwrap_or(Value::Null))
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+// Every topic0 written so far with its signature and log count
+async fn get_events(State(state): State<Arc<ApiState>>) -> Result<Response, ApiError> {
+    let body = cached(&state, "events".to_string(), Scope::Head, move |raw_data_path| {
+        Ok(serde_json::to_value(topics::read_index(raw_data_path)?)?)
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+// The signature of a topic0, from the index or else the signature database
+async fn get_event(State(state): State<Arc<ApiState>>, Path(topic0): Path<String>) -> Result<Response, ApiError> {
+    let topic0 = normalize_hash(&topic0)?;
+    let body = cached(&state, format!("event {}", topic0), Scope::Head, move |raw_data_path| {
+        let indexed = topics::read_index(raw_data_path)?.remove(&topic0);
+        let signature = indexed.as_ref().and_then(|event| event.signature.clone()).or_else(|| topics::lookup(&topic0));
+        Ok(match (indexed, signature) {
+            (None, None) => Value::Null,
+            (indexed, signature) => json!({ "topic0": topic0, "signature": signature, "logs": indexed.map_or(0, |event| event.logs) }),
+        })
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+#[derive(Deserialize)]
+struct Page {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+// Transactions sent from or to an address, newest first. Uses the address activity index when
+// the store has one and falls back to scanning every transaction file otherwise.
+fn address_transactions(raw_data_path: &str, address: &str, limit: usize, offset: usize) -> Result<Value> {
+    let index = std::path::Path::new(raw_data_path).join(activity::INDEX_DIR);
+    if index.exists() {
+        let hashes: BTreeSet<(u64, String)> = activity::lookup(raw_data_path, address, None)?
+            .into_iter()
+            .map(|entry| (entry.block_number, entry.transaction_hash))
+            .collect();
+        let mut transactions = Vec::new();
+        for (block_number, hash) in hashes.into_iter().rev().skip(offset).take(limit) {
+            let file_name = format!("tx_{}", hash);
+            if let Some(tx) = store::find_record(raw_data_path, "transactions", &file_name, "hash", &hash, Some(block_number))? {
+                transactions.push(tx);
+            }
+        }
+        return Ok(Value::Array(transactions));
+    }
+
+    let mut transactions = Vec::new();
+    for path in store::dataset_files(raw_data_path, "transactions")? {
+        for tx in store::read_records(&path)? {
+            let touches = [&tx["from"], &tx["to"]]
+                .iter()
+                .any(|value| value.as_str().is_some_and(|value| value.eq_ignore_ascii_case(address)));
+            if touches {
+                transactions.push(tx);
+            }
+        }
+    }
+    let order = |tx: &Value| (tx["block_number"].as_u64(), tx["transaction_index"].as_u64());
+    transactions.sort_by_key(|tx| std::cmp::Reverse(order(tx)));
+    Ok(Value::Array(transactions.into_iter().skip(offset).take(limit).collect()))
+}
+
+async fn get_address_transactions(
+    State(state): State<Arc<ApiState>>,
+    Path(address): Path<String>,
+    Query(page): Query<Page>,
+) -> Result<Response, ApiError> {
+    let address = normalize_hash(&address)?;
+    access_stats::record_whole("transactions");
+    let limit = page.limit.unwrap_or(100).min(MAX_PAGE_SIZE);
+    let offset = page.offset.unwrap_or(0);
+    let key = format!("address {} txs {} {}", address, limit, offset);
+    let body = cached(&state, key, Scope::Head, move |raw_data_path| {
+        address_transactions(raw_data_path, &address, limit, offset)
+    })
+    .await?;
+    Ok(json_response(body))
+}
+
+// Whole GraphQL responses are cached by request body, errors included
+async fn post_graphql(State(state): State<Arc<ApiState>>, body: String) -> Result<Response, ApiError> {
+    let request: async_graphql::Request =
+        serde_json::from_str(&body).map_err(|e| ApiError::BadRequest(format!("Invalid GraphQL request: {}", e)))?;
+    let schema = state.schema.clone();
+    let body = state
+        .cache
+        .get_or_compute(&format!("graphql {}", body), Scope::Head, || async move {
+            Ok(serde_json::to_string(&schema.execute(request).await)?)
+        })
+        .await?;
+    Ok(json_response(body))
+}
+
+async fn graphql_playground() -> Html<String> {
+    Html(async_graphql::http::playground_source(async_graphql::http::GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+// Static, so it is compiled into the binary and needs no asset directory
+async fn explorer() -> Html<&'static str> {
+    Html(include_str!("explorer.html"))
+}
+
+fn router(state: Arc<ApiState>, explorer_page: bool) -> Router {
+    let router = match explorer_page {
+        true => Router::new().route("/", get(explorer)),
+        false => Router::new(),
+    };
+    router
+        .route("/blocks", get(get_latest_blocks))
+        .route("/blocks/:number", get(get_block))
+        .route("/txs/:hash", get(get_transaction))
+        .route("/receipts/:hash", get(get_receipt))
+        .route("/address/:address/txs", get(get_address_transactions))
+        .route("/events", get(get_events))
+        .route("/events/:topic0", get(get_event))
+        .route("/graphql", get(graphql_playground).post(post_graphql))
+        .with_state(state)
+}
+
+// Feeds the highest indexed block and its hash to the cache so head-dependent results expire
+async fn watch_head(state: Arc<ApiState>, interval: Duration) {
+    loop {
+        let raw_data_path = state.raw_data_path.clone();
+        let head = tokio::task::spawn_blocking(move || -> Result<Option<(u64, String)>> {
+            let Some(number) = store::indexed_block_numbers(&raw_data_path)?.last().copied() else {
+                return Ok(None);
+            };
+            let blocks: Vec<Value> = store::read_range(&raw_data_path, "blocks", number, number)?;
+            let hash = blocks.first().and_then(|block| block["hash"].as_str()).unwrap_or_default().to_string();
+            Ok(Some((number, hash)))
+        })
+        .await;
+        match head {
+            Ok(Ok(Some((number, hash)))) => {
+                state.cache.observe_head(number, &hash).await;
+            },
+            Ok(Ok(None)) => {},
+            Ok(Err(e)) => log::warn!("Could not read the indexed head: {}", e),
+            Err(e) => log::warn!("Head watcher failed: {}", e),
+        }
+        clock::sleep(interval).await;
+    }
+}
+
+// Writes the counted dataset reads to the stats file, and logs the heavy fields no query names
+async fn flush_access_stats(raw_data_path: String, interval: Duration, report_interval: Duration) {
+    let mut since_report = Duration::ZERO;
+    loop {
+        clock::sleep(interval).await;
+        let raw_data_path = raw_data_path.clone();
+        since_report += interval;
+        let report = since_report >= report_interval;
+        if report {
+            since_report = Duration::ZERO;
+        }
+        let flushed = tokio::task::spawn_blocking(move || -> Result<()> {
+            access_stats::flush(&raw_data_path)?;
+            if report {
+                for dataset in access_stats::report(&raw_data_path)?.1 {
+                    log::info!("Access stats: {}", access_stats::render(&dataset, access_stats::HEAVY_SHARE));
+                }
+            }
+            Ok(())
+        })
+        .await;
+        match flushed {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => log::warn!("Could not write access stats: {}", e),
+            Err(e) => log::warn!("Access stats writer failed: {}", e),
+        }
+    }
+}
+
+pub async fn serve(addr: SocketAddr, raw_data_path: String) -> Result<()> {
+    let cache = QueryCache::connect(CacheConfig::from_env()?).await?;
+    let schema = graphql::schema(raw_data_path.clone());
+    let state = Arc::new(ApiState { raw_data_path, cache, schema });
+    let poll = Duration::from_secs(env_or("API_HEAD_POLL_SECS", 5)?);
+    tokio::spawn(watch_head(Arc::clone(&state), poll));
+    let flush = Duration::from_secs(env_or("ACCESS_STATS_FLUSH_SECS", 60)?);
+    let report = Duration::from_secs(env_or("ACCESS_REPORT_SECS", 86400)?);
+    tokio::spawn(flush_access_stats(state.raw_data_path.clone(), flush, report));
+
+    let explorer_page = env_or("API_EXPLORER", true)?;
+    let app = router(state, explorer_page);
+
+    log::info!("Serving the REST API on http://{}", addr);
+    if explorer_page {
+        log::info!("Explorer on http://{}/", addr);
+    }
+    shutdown::listen();
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).with_graceful_shutdown(shutdown::wait()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::TransformedTransaction;
+    use crate::pipeline::TransformedBatch;
+    use crate::store::{OutputConfig, OutputDirs};
+
+    #[tokio::test]
+    async fn test_routes() {
+        let root = "test_api_store";
+        let _ = std::fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let tx = |hash: &str, block_number: u64, from: &str| TransformedTransaction {
+            hash: hash.to_string(),
+            from: from.to_string(),
+            to: Some("0xbeef".to_string()),
+            ..fixtures::transaction(block_number, 0)
+        };
+        let batch = TransformedBatch { transactions: vec![tx("0xa1", 1, "0xcafe"), tx("0xa2", 2, "0xf00d")], ..Default::default() };
+        store::persist(&dirs, OutputConfig::default(), None, &batch, 2).await.unwrap();
+
+        let config = CacheConfig { capacity: 10, ttl: Duration::from_secs(60), redis_url: None };
+        let cache = QueryCache::connect(config).await.unwrap();
+        let state = Arc::new(ApiState { raw_data_path: root.to_string(), cache, schema: graphql::schema(root.to_string()) });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state, true)).await });
+
+        let get = |path: &str| reqwest::get(format!("http://{}{}", addr, path));
+        let response = get("/txs/0xA1").await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.json::<Value>().await.unwrap()["from"], "0xcafe");
+
+        let history: Value = get("/address/0xBEEF/txs?limit=1").await.unwrap().json().await.unwrap();
+        assert_eq!(history.as_array().unwrap().len(), 1);
+        assert_eq!(history[0]["hash"], "0xa2");
+        assert!(get("/").await.unwrap().text().await.unwrap().contains("sweet-potato explorer"));
+        assert_eq!(get("/blocks").await.unwrap().json::<Value>().await.unwrap(), json!([]));
+
+        assert_eq!(get("/receipts/0xa1").await.unwrap().status(), 404);
+        assert_eq!(get("/txs/nothex").await.unwrap().status(), 400);
+        let event: Value = get(&format!("/events/{}", crate::events::APPROVAL_TOPIC)).await.unwrap().json().await.unwrap();
+        assert_eq!((event["signature"].as_str(), event["logs"].as_u64()), (Some("Approval(address,address,uint256)"), Some(0)));
+        assert_eq!(get("/events/0x01").await.unwrap().status(), 404);
+
+        let query = serde_json::json!({ "query": "{ transaction(hash: \"0xa2\") { from } }" });
+        let response = reqwest::Client::new().post(format!("http://{}/graphql", addr)).json(&query).send().await.unwrap();
+        assert_eq!(response.json::<Value>().await.unwrap()["data"]["transaction"]["from"], "0xf00d");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
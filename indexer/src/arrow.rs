@@ -0,0 +1,217 @@
+//! Arrow record batches of the datasets (`arrow` feature), for Polars, DataFusion or pyarrow users
+//! embedding the indexer, for `--output-format arrow` and for the Flight server.
+//!
+//! Each dataset's schema mirrors its ClickHouse columns, plus `extras`: the fields a dataset does
+//! not name (the RPC extras) as a JSON string, as in the Avro files. Structured array elements
+//! such as receipt logs are JSON strings too. [`datasets`] turns a transformed batch into record
+//! batches without writing anything, so the rows can be handed over in memory.
+//!
+//! `--output-format arrow` writes one Arrow IPC file (Feather v2) per dataset per block range,
+//! `<dataset>_<first>_<last>.arrow`, which `pyarrow.feather.read_table` or `polars.read_ipc` open
+//! directly. The files are not compressed; `--compress` does not apply to them.
+
+use anyhow::Result;
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+pub use arrow_array::RecordBatch;
+pub use arrow_schema::SchemaRef;
+
+use crate::pipeline::TransformedBatch;
+use crate::sinks::clickhouse::{columns_for, EXTRAS_COLUMN};
+use crate::store::Record;
+
+pub const BATCH_ROWS: usize = 8192;
+
+// Arrow schema mirroring the ClickHouse table definition of a dataset, then `extras`
+pub fn dataset_schema(dataset: &str) -> SchemaRef {
+    let fields: Vec<Field> = columns_for(dataset)
+        .iter()
+        .map(|(name, column_type)| {
+            let (inner, nullable) = match column_type.strip_prefix("Nullable(").and_then(|t| t.strip_suffix(')')) {
+                Some(inner) => (inner, true),
+                None => (*column_type, false),
+            };
+            let data_type = match inner {
+                "UInt64" => DataType::UInt64,
+                "UInt8" => DataType::UInt8,
+                "Float64" => DataType::Float64,
+                "Bool" => DataType::Boolean,
+                "DateTime" => DataType::Timestamp(TimeUnit::Second, Some("+00:00".into())),
+                "Array(String)" => DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                _ => DataType::Utf8,
+            };
+            Field::new(*name, data_type, nullable)
+        })
+        .chain([Field::new(EXTRAS_COLUMN, DataType::Utf8, true)])
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+// Array(String) columns hold JSON text for structured elements such as receipt logs, and unnamed
+// fields move to `extras`
+fn normalize(schema: &Schema, record: Value) -> Value {
+    let Value::Object(mut fields) = record else { return record };
+    let mut row = Map::new();
+    for field in schema.fields().iter().filter(|field| field.name() != EXTRAS_COLUMN) {
+        let Some(mut value) = fields.remove(field.name()) else { continue };
+        if let (DataType::List(_), Value::Array(items)) = (field.data_type(), &mut value) {
+            for item in items.iter_mut().filter(|item| !item.is_string()) {
+                *item = Value::String(item.to_string());
+            }
+        }
+        row.insert(field.name().clone(), value);
+    }
+    if !fields.is_empty() {
+        row.insert(EXTRAS_COLUMN.to_string(), Value::String(Value::Object(fields).to_string()));
+    }
+    Value::Object(row)
+}
+
+// The reverse of `normalize`, for records read back from Arrow
+fn denormalize(schema: &Schema, record: Value) -> Value {
+    let Value::Object(mut fields) = record else { return record };
+    for field in schema.fields() {
+        if let (DataType::List(_), Some(Value::Array(items))) = (field.data_type(), fields.get_mut(field.name())) {
+            for item in items.iter_mut() {
+                let structured = item.as_str().is_some_and(|text| text.starts_with('{') || text.starts_with('['));
+                if let Some(parsed) = structured.then(|| serde_json::from_str(item.as_str().unwrap_or_default()).ok()).flatten() {
+                    *item = parsed;
+                }
+            }
+        }
+    }
+    if let Some(Value::String(extras)) = fields.remove(EXTRAS_COLUMN) {
+        if let Ok(Value::Object(extras)) = serde_json::from_str(&extras) {
+            fields.extend(extras);
+        }
+    }
+    Value::Object(fields)
+}
+
+pub fn to_record_batches(schema: SchemaRef, records: Vec<Value>) -> Result<Vec<RecordBatch>> {
+    let mut batches = Vec::new();
+    let records: Vec<Value> = records.into_iter().map(|record| normalize(&schema, record)).collect();
+    for chunk in records.chunks(BATCH_ROWS) {
+        let mut decoder = ReaderBuilder::new(Arc::clone(&schema)).with_batch_size(BATCH_ROWS).build_decoder()?;
+        decoder.serialize(chunk)?;
+        if let Some(batch) = decoder.flush()? {
+            batches.push(batch);
+        }
+    }
+    Ok(batches)
+}
+
+// Record batches of one dataset's records
+pub fn record_batches<T: Record>(records: &[T]) -> Result<Vec<RecordBatch>> {
+    let records = records.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+    to_record_batches(dataset_schema(T::DATASET), records)
+}
+
+// Every dataset of a transformed batch that has records, as (dataset, record batches)
+pub fn datasets(batch: &TransformedBatch) -> Result<Vec<(&'static str, Vec<RecordBatch>)>> {
+    fn add<T: Record>(out: &mut Vec<(&'static str, Vec<RecordBatch>)>, records: &[T]) -> Result<()> {
+        if !records.is_empty() {
+            out.push((T::DATASET, record_batches(records)?));
+        }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    add(&mut out, &batch.blocks)?;
+    add(&mut out, &batch.transactions)?;
+    add(&mut out, &batch.receipts)?;
+    add(&mut out, &batch.logs)?;
+    add(&mut out, &batch.contracts)?;
+    add(&mut out, &batch.data_usage)?;
+    add(&mut out, &batch.ordering_analysis)?;
+    add(&mut out, &batch.nft_transfers)?;
+    add(&mut out, &batch.token_transfers)?;
+    add(&mut out, &batch.bridge_messages)?;
+    add(&mut out, &batch.balances)?;
+    add(&mut out, &batch.contract_state)?;
+    add(&mut out, &batch.addresses)?;
+    add(&mut out, &batch.data_quality)?;
+    Ok(out)
+}
+
+// An Arrow IPC file (Feather v2) holding the records of a dataset
+pub fn write_file(dataset: &str, records: &[Value]) -> Result<Vec<u8>> {
+    let schema = dataset_schema(dataset);
+    let mut writer = FileWriter::try_new(Vec::new(), &schema)?;
+    for batch in to_record_batches(Arc::clone(&schema), records.to_vec())? {
+        writer.write(&batch)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+// The records of an Arrow IPC file written by `write_file`
+pub fn read_file(data: &[u8]) -> Result<Vec<Value>> {
+    let reader = FileReader::try_new(std::io::Cursor::new(data), None)?;
+    let schema = reader.schema();
+    let mut lines = arrow_json::WriterBuilder::new().with_explicit_nulls(true).build::<_, arrow_json::writer::LineDelimited>(Vec::new());
+    for batch in reader {
+        lines.write(&batch?)?;
+    }
+    lines.finish()?;
+    let data = lines.into_inner();
+    let records = data.split(|byte| *byte == b'\n').filter(|line| !line.is_empty());
+    records.map(|line| Ok(denormalize(&schema, serde_json::from_slice(line)?))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::{TransformedBlock, TransformedReceipt};
+    use serde_json::json;
+
+    #[test]
+    fn test_batches_and_ipc_round_trip() {
+        let block = TransformedBlock {
+            base_fee_per_gas: Some(7),
+            gas_limit: 30_000_000,
+            gas_used: 21000,
+            transaction_hashes: vec![fixtures::transaction_hash(7, 0)],
+            extra: json!({ "l1BatchNumber": "0x2" }).as_object().unwrap().clone(),
+            ..fixtures::block(7)
+        };
+        let receipt = TransformedReceipt {
+            cumulative_gas_used: 21000,
+            effective_gas_price: 1,
+            gas_used: 21000,
+            logs: vec![json!({ "address": "0xa", "topics": ["0x1"] })],
+            ..fixtures::receipt(7, 0)
+        };
+        let batch = TransformedBatch { blocks: vec![block], receipts: vec![receipt], ..Default::default() };
+
+        let datasets = datasets(&batch).unwrap();
+        assert_eq!(datasets.iter().map(|(dataset, _)| *dataset).collect::<Vec<_>>(), vec!["blocks", "receipts"]);
+        let blocks = &datasets[0].1[0];
+        assert_eq!((blocks.num_rows(), blocks.schema()), (1, dataset_schema("blocks")));
+        assert_eq!(blocks.column_by_name("extras").map(|column| column.is_null(0)), Some(false));
+
+        // Written and read back, records are what was written
+        for (dataset, records) in [("blocks", serde_json::to_value(&batch.blocks).unwrap()), ("receipts", serde_json::to_value(&batch.receipts).unwrap())] {
+            let records = records.as_array().unwrap().clone();
+            let data = write_file(dataset, &records).unwrap();
+            assert_eq!(&data[..6], b"ARROW1");
+            let read = read_file(&data).unwrap();
+            assert_eq!(read.len(), 1);
+            match dataset {
+                "blocks" => {
+                    let read: TransformedBlock = serde_json::from_value(read[0].clone()).unwrap();
+                    assert_eq!(serde_json::to_value(read).unwrap(), records[0]);
+                },
+                _ => {
+                    let read: TransformedReceipt = serde_json::from_value(read[0].clone()).unwrap();
+                    assert_eq!(serde_json::to_value(read).unwrap(), records[0]);
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+//! Apache Avro object container files for `--output-format avro`, for Kafka Connect and
+//! schema-registry pipelines that expect typed records rather than JSON.
+//!
+//! Each dataset's schema is generated from its ClickHouse columns (`avro-schema <dataset>` prints
+//! it) and embedded in every file, so the files are self-describing. Wei amounts that can exceed a
+//! signed long are `decimal(20, 0)`, uint256 values stay decimal strings, `datetime` is
+//! `timestamp-micros`, and receipt logs are nested `log` records with their typed indexed topics.
+//! Fields a schema does not name (the RPC extras) are kept as a JSON string in `extras`.
+//!
+//! `--compress` selects the Avro codec (`deflate` for gzip, `zstandard` for zstd) rather than
+//! wrapping the file, so any Avro reader can open it. Only the schema subset written here is read.
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+use crate::sinks::clickhouse;
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+const NAMESPACE: &str = "sweet_potato";
+// Records per container block
+const BLOCK_RECORDS: usize = 1000;
+// Top-level records keep unnamed fields under this key; nested records merge them back in place
+const EXTRAS_KEY_ATTRIBUTE: &str = "extrasKey";
+
+// uint64 columns holding wei amounts, which do not fit a signed long
+pub(crate) const WEI_COLUMNS: &[&str] =
+    &["base_fee_per_gas", "difficulty", "total_difficulty", "gas_price", "effective_gas_price", "min_priority_fee", "max_priority_fee"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Double,
+    String,
+    // An unsigned 64-bit integer as `decimal(20, 0)` bytes
+    Decimal,
+    TimestampMicros,
+    Array(Box<Schema>),
+    Union(Vec<Schema>),
+    Record(RecordSchema),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordSchema {
+    pub name: String,
+    pub fields: Vec<(String, Schema)>,
+    pub extras_key: Option<String>,
+}
+
+fn nullable(schema: Schema) -> Schema {
+    Schema::Union(vec![Schema::Null, schema])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Null,
+    Deflate,
+    Zstandard,
+}
+
+impl Codec {
+    fn name(&self) -> &'static str {
+        match self {
+            Codec::Null => "null",
+            Codec::Deflate => "deflate",
+            Codec::Zstandard => "zstandard",
+        }
+    }
+
+    fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Codec::Null => Ok(data),
+            Codec::Deflate => {
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&data)?;
+                Ok(encoder.finish()?)
+            },
+            Codec::Zstandard => Ok(zstd::encode_all(data.as_slice(), 0)?),
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Codec::Null => Ok(data.to_vec()),
+            Codec::Deflate => {
+                let mut out = Vec::new();
+                flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?;
+                Ok(out)
+            },
+            Codec::Zstandard => Ok(zstd::decode_all(data)?),
+        }
+    }
+}
+
+fn log_schema() -> Schema {
+    let indexed = Schema::Record(RecordSchema {
+        name: "indexed_topic".to_string(),
+        fields: vec![
+            ("name".to_string(), nullable(Schema::String)),
+            ("type".to_string(), Schema::String),
+            ("value".to_string(), Schema::Union(vec![Schema::String, Schema::Boolean])),
+        ],
+        extras_key: None,
+    });
+    let strings = ["address", "data", "blockHash", "blockNumber", "transactionHash", "transactionIndex", "logIndex"];
+    let mut fields: Vec<(String, Schema)> = strings.iter().map(|name| (name.to_string(), nullable(Schema::String))).collect();
+    fields.insert(1, ("topics".to_string(), nullable(Schema::Array(Box::new(Schema::String)))));
+    fields.extend([
+        ("removed".to_string(), nullable(Schema::Boolean)),
+        ("topic0".to_string(), nullable(Schema::String)),
+        ("event_signature".to_string(), nullable(Schema::String)),
+        ("indexed".to_string(), nullable(Schema::Array(Box::new(indexed)))),
+        ("extras".to_string(), nullable(Schema::String)),
+    ]);
+    Schema::Record(RecordSchema { name: "log".to_string(), fields, extras_key: None })
+}
+
+fn column_schema(name: &str, column_type: &str) -> Result<Schema> {
+    if let Some(inner) = column_type.strip_prefix("Nullable(").and_then(|t| t.strip_suffix(')')) {
+        return Ok(nullable(column_schema(name, inner)?));
+    }
+    if let Some(inner) = column_type.strip_prefix("Array(").and_then(|t| t.strip_suffix(')')) {
+        return Ok(Schema::Array(Box::new(column_schema(name, inner)?)));
+    }
+    Ok(match column_type {
+        "String" | "UInt256" => Schema::String,
+        "UInt64" if WEI_COLUMNS.contains(&name) => Schema::Decimal,
+        "UInt64" | "UInt32" => Schema::Long,
+        "UInt8" => Schema::Int,
+        "Bool" => Schema::Boolean,
+        "Float64" => Schema::Double,
+        "DateTime" => Schema::TimestampMicros,
+        other => return Err(anyhow::anyhow!("No Avro type for column {} of type {}", name, other)),
+    })
+}
+
+// The schema files of a dataset are written with
+pub fn dataset_schema(dataset: &str) -> Result<Schema> {
+    let columns = clickhouse::columns_for(dataset);
+    if columns.is_empty() {
+        return Err(anyhow::anyhow!("Unknown dataset {}", dataset));
+    }
+    let mut fields = Vec::new();
+    for (name, column_type) in columns {
+        let schema = match (dataset, *name) {
+            ("receipts", "logs") => Schema::Array(Box::new(log_schema())),
+            _ => column_schema(name, column_type)?,
+        };
+        fields.push((name.to_string(), schema));
+    }
+    fields.push(("extras".to_string(), nullable(Schema::String)));
+    Ok(Schema::Record(RecordSchema { name: dataset.to_string(), fields, extras_key: Some("extra".to_string()) }))
+}
+
+impl Schema {
+    // The Avro schema document
+    pub fn to_json(&self) -> Value {
+        match self {
+            Schema::Null => json!("null"),
+            Schema::Boolean => json!("boolean"),
+            Schema::Int => json!("int"),
+            Schema::Long => json!("long"),
+            Schema::Double => json!("double"),
+            Schema::String => json!("string"),
+            Schema::Decimal => json!({ "type": "bytes", "logicalType": "decimal", "precision": 20, "scale": 0 }),
+            Schema::TimestampMicros => json!({ "type": "long", "logicalType": "timestamp-micros" }),
+            Schema::Array(items) => json!({ "type": "array", "items": items.to_json() }),
+            Schema::Union(branches) => Value::Array(branches.iter().map(Schema::to_json).collect()),
+            Schema::Record(record) => {
+                let fields: Vec<Value> = record
+                    .fields
+                    .iter()
+                    .map(|(name, schema)| match schema {
+                        Schema::Union(branches) if branches.first() == Some(&Schema::Null) => {
+                            json!({ "name": name, "type": schema.to_json(), "default": null })
+                        },
+                        _ => json!({ "name": name, "type": schema.to_json() }),
+                    })
+                    .collect();
+                let mut document = json!({ "type": "record", "name": record.name, "namespace": NAMESPACE, "fields": fields });
+                if let Some(key) = &record.extras_key {
+                    document[EXTRAS_KEY_ATTRIBUTE] = json!(key);
+                }
+                document
+            },
+        }
+    }
+
+    // Reads back the schema subset `to_json` writes
+    pub fn from_json(document: &Value) -> Result<Self> {
+        let unsupported = || anyhow::anyhow!("Unsupported Avro schema {}", document);
+        match document {
+            Value::String(name) => Ok(match name.as_str() {
+                "null" => Schema::Null,
+                "boolean" => Schema::Boolean,
+                "int" => Schema::Int,
+                "long" => Schema::Long,
+                "double" => Schema::Double,
+                "string" => Schema::String,
+                _ => return Err(unsupported()),
+            }),
+            Value::Array(branches) => Ok(Schema::Union(branches.iter().map(Schema::from_json).collect::<Result<_>>()?)),
+            Value::Object(object) => match (object.get("type").and_then(Value::as_str), object.get("logicalType").and_then(Value::as_str)) {
+                (Some("bytes"), Some("decimal")) => Ok(Schema::Decimal),
+                (Some("long"), Some("timestamp-micros")) => Ok(Schema::TimestampMicros),
+                (Some("array"), _) => Ok(Schema::Array(Box::new(Schema::from_json(&object["items"])?))),
+                (Some("record"), _) => {
+                    let fields = object["fields"]
+                        .as_array()
+                        .ok_or_else(unsupported)?
+                        .iter()
+                        .map(|field| Ok((field["name"].as_str().ok_or_else(unsupported)?.to_string(), Schema::from_json(&field["type"])?)))
+                        .collect::<Result<_>>()?;
+                    Ok(Schema::Record(RecordSchema {
+                        name: object["name"].as_str().unwrap_or_default().to_string(),
+                        fields,
+                        extras_key: object.get(EXTRAS_KEY_ATTRIBUTE).and_then(Value::as_str).map(str::to_string),
+                    }))
+                },
+                (Some(_), _) => Schema::from_json(&object["type"]),
+                _ => Err(unsupported()),
+            },
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+fn write_long(out: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push((zigzag as u8) | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(out, bytes.len() as i64);
+    out.extend_from_slice(bytes);
+}
+
+// Integers as JSON numbers or decimal/`0x` strings
+fn integer(value: &Value) -> Option<i128> {
+    match value {
+        Value::Number(number) => number.as_i64().map(i128::from).or_else(|| number.as_u64().map(i128::from)),
+        Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok().map(i128::from),
+            None => text.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+fn matches(schema: &Schema, value: &Value) -> bool {
+    matches!(
+        (schema, value),
+        (Schema::Null, Value::Null)
+            | (Schema::Boolean, Value::Bool(_))
+            | (Schema::String, Value::String(_))
+            | (Schema::Int | Schema::Long | Schema::Decimal | Schema::Double, Value::Number(_))
+            | (Schema::TimestampMicros, Value::String(_) | Value::Number(_))
+            | (Schema::Array(_), Value::Array(_))
+            | (Schema::Record(_), Value::Object(_))
+    )
+}
+
+fn encode(schema: &Schema, value: &Value, path: &str, out: &mut Vec<u8>) -> Result<()> {
+    let mismatch = || anyhow::anyhow!("{} does not fit Avro type {}: {}", path, schema.to_json(), value);
+    match schema {
+        Schema::Null => {},
+        Schema::Boolean => out.push(value.as_bool().ok_or_else(mismatch)? as u8),
+        Schema::Int => write_long(out, integer(value).filter(|v| i32::try_from(*v).is_ok()).ok_or_else(mismatch)? as i64),
+        Schema::Long => write_long(out, integer(value).and_then(|v| i64::try_from(v).ok()).ok_or_else(mismatch)?),
+        Schema::Double => out.extend_from_slice(&value.as_f64().ok_or_else(mismatch)?.to_le_bytes()),
+        // Anything else is kept as its JSON text
+        Schema::String => match value {
+            Value::String(text) => write_bytes(out, text.as_bytes()),
+            other => write_bytes(out, other.to_string().as_bytes()),
+        },
+        Schema::Decimal => {
+            let unscaled = integer(value).and_then(|v| u64::try_from(v).ok()).ok_or_else(mismatch)?;
+            // Big-endian two's complement, shortest form that keeps the sign
+            let bytes = [&[0u8][..], &unscaled.to_be_bytes()].concat();
+            let start = (0..bytes.len() - 1).find(|i| bytes[*i] != 0 || bytes[*i + 1] & 0x80 != 0).unwrap_or(bytes.len() - 1);
+            write_bytes(out, &bytes[start..]);
+        },
+        Schema::TimestampMicros => {
+            let micros = match value {
+                Value::String(text) => DateTime::parse_from_rfc3339(text).map_err(|_| mismatch())?.timestamp_micros(),
+                other => other.as_i64().ok_or_else(mismatch)?,
+            };
+            write_long(out, micros);
+        },
+        Schema::Array(items) => {
+            let values = value.as_array().ok_or_else(mismatch)?;
+            if !values.is_empty() {
+                write_long(out, values.len() as i64);
+                for (i, item) in values.iter().enumerate() {
+                    encode(items, item, &format!("{}[{}]", path, i), out)?;
+                }
+            }
+            write_long(out, 0);
+        },
+        Schema::Union(branches) => {
+            // The branch of the value's own type, else the first that can take it (e.g. a string for a number)
+            let index = branches
+                .iter()
+                .position(|branch| matches(branch, value))
+                .or_else(|| branches.iter().position(|branch| *branch != Schema::Null && !value.is_null()))
+                .ok_or_else(mismatch)?;
+            write_long(out, index as i64);
+            encode(&branches[index], value, path, out)?;
+        },
+        Schema::Record(record) => {
+            let object = value.as_object().ok_or_else(mismatch)?;
+            let mut extras = Map::new();
+            for (key, field) in object {
+                match (&record.extras_key, field) {
+                    (Some(extras_key), Value::Object(nested)) if key == extras_key => extras.extend(nested.clone()),
+                    _ if record.fields.iter().any(|(name, _)| name == key) => {},
+                    _ => {
+                        extras.insert(key.clone(), field.clone());
+                    },
+                }
+            }
+            for (name, schema) in &record.fields {
+                let field = match name.as_str() {
+                    "extras" if !extras.is_empty() => Value::String(Value::Object(extras.clone()).to_string()),
+                    "extras" => Value::Null,
+                    _ => object.get(name).cloned().unwrap_or(Value::Null),
+                };
+                encode(schema, &field, &format!("{}.{}", path, name), out)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+// The binary encoding of one record
+pub fn encode_record(schema: &Schema, record: &Value) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let name = match schema {
+        Schema::Record(record) => record.name.as_str(),
+        _ => "record",
+    };
+    encode(schema, record, name, &mut out)?;
+    Ok(out)
+}
+
+// An object container file holding the records, schema embedded
+pub fn write_container(schema: &Schema, records: &[Value], codec: Codec) -> Result<Vec<u8>> {
+    let schema_json = schema.to_json().to_string();
+    // Derived from the schema so the same records always give the same file
+    let sync: [u8; 16] = Sha256::digest(schema_json.as_bytes())[..16].try_into()?;
+
+    let mut out = MAGIC.to_vec();
+    write_long(&mut out, 2);
+    for (key, value) in [("avro.schema", schema_json.as_bytes()), ("avro.codec", codec.name().as_bytes())] {
+        write_bytes(&mut out, key.as_bytes());
+        write_bytes(&mut out, value);
+    }
+    write_long(&mut out, 0);
+    out.extend_from_slice(&sync);
+
+    for block in records.chunks(BLOCK_RECORDS) {
+        let mut data = Vec::new();
+        for record in block {
+            data.extend(encode_record(schema, record)?);
+        }
+        let data = codec.compress(data)?;
+        write_long(&mut out, block.len() as i64);
+        write_long(&mut out, data.len() as i64);
+        out.extend(data);
+        out.extend_from_slice(&sync);
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.data.len());
+        let end = end.ok_or_else(|| anyhow::anyhow!("Avro data ends early"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn long(&mut self) -> Result<i64> {
+        let (mut zigzag, mut shift) = (0u64, 0);
+        loop {
+            let byte = self.take(1)?[0];
+            if shift >= 64 {
+                return Err(anyhow::anyhow!("Avro long is too long"));
+            }
+            zigzag |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+            }
+            shift += 7;
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = usize::try_from(self.long()?)?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.bytes()?.to_vec())?)
+    }
+
+    // Array and map block item counts; a negative count is followed by the block's size in bytes
+    fn count(&mut self) -> Result<usize> {
+        let count = self.long()?;
+        if count < 0 {
+            self.long()?;
+        }
+        Ok(usize::try_from(count.unsigned_abs())?)
+    }
+
+    fn is_done(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+fn decode(schema: &Schema, reader: &mut Reader) -> Result<Value> {
+    Ok(match schema {
+        Schema::Null => Value::Null,
+        Schema::Boolean => Value::Bool(reader.take(1)?[0] != 0),
+        Schema::Int | Schema::Long => json!(reader.long()?),
+        Schema::Double => json!(f64::from_le_bytes(reader.take(8)?.try_into()?)),
+        Schema::String => Value::String(reader.string()?),
+        Schema::Decimal => {
+            let bytes = reader.bytes()?;
+            let negative = bytes.first().is_some_and(|byte| byte & 0x80 != 0);
+            let unscaled = bytes.iter().fold(if negative { -1i128 } else { 0 }, |acc, byte| (acc << 8) | *byte as i128);
+            match u64::try_from(unscaled) {
+                Ok(value) => json!(value),
+                Err(_) => json!(unscaled.to_string()),
+            }
+        },
+        Schema::TimestampMicros => {
+            let micros = reader.long()?;
+            let datetime = DateTime::from_timestamp_micros(micros).ok_or_else(|| anyhow::anyhow!("Timestamp {} is out of range", micros))?;
+            json!(datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        },
+        Schema::Array(items) => {
+            let mut values = Vec::new();
+            loop {
+                let count = reader.count()?;
+                if count == 0 {
+                    break;
+                }
+                for _ in 0..count {
+                    values.push(decode(items, reader)?);
+                }
+            }
+            Value::Array(values)
+        },
+        Schema::Union(branches) => {
+            let index = usize::try_from(reader.long()?)?;
+            let branch = branches.get(index).ok_or_else(|| anyhow::anyhow!("Avro union has no branch {}", index))?;
+            decode(branch, reader)?
+        },
+        Schema::Record(record) => {
+            let mut object = Map::new();
+            for (name, schema) in &record.fields {
+                let value = decode(schema, reader)?;
+                match (name.as_str(), value) {
+                    ("extras", Value::Null) => {},
+                    ("extras", Value::String(extras)) => match (serde_json::from_str(&extras)?, &record.extras_key) {
+                        (Value::Object(extras), Some(key)) => {
+                            object.insert(key.clone(), Value::Object(extras));
+                        },
+                        (Value::Object(extras), None) => object.extend(extras),
+                        _ => return Err(anyhow::anyhow!("Avro extras of {} are not a JSON object", record.name)),
+                    },
+                    (_, value) => {
+                        object.insert(name.clone(), value);
+                    },
+                }
+            }
+            Value::Object(object)
+        },
+    })
+}
+
+// Schema and codec of a container file, leaving the reader at the sync marker
+fn read_header(reader: &mut Reader) -> Result<(Schema, Codec)> {
+    if reader.take(4)? != MAGIC {
+        return Err(anyhow::anyhow!("Not an Avro object container file"));
+    }
+    let mut metadata = Map::new();
+    loop {
+        let count = reader.count()?;
+        if count == 0 {
+            break;
+        }
+        for _ in 0..count {
+            let key = reader.string()?;
+            metadata.insert(key, Value::String(String::from_utf8_lossy(reader.bytes()?).to_string()));
+        }
+    }
+    let schema_json = metadata.get("avro.schema").and_then(Value::as_str).ok_or_else(|| anyhow::anyhow!("Avro file has no schema"))?;
+    let schema = Schema::from_json(&serde_json::from_str(schema_json)?)?;
+    let codec = match metadata.get("avro.codec").and_then(Value::as_str).unwrap_or("null") {
+        "null" => Codec::Null,
+        "deflate" => Codec::Deflate,
+        "zstandard" => Codec::Zstandard,
+        other => return Err(anyhow::anyhow!("Unsupported Avro codec {}", other)),
+    };
+    Ok((schema, codec))
+}
+
+// The codec a container file's blocks are compressed with
+pub fn container_codec(data: &[u8]) -> Result<Codec> {
+    Ok(read_header(&mut Reader { data, position: 0 })?.1)
+}
+
+// Records of an object container file, as JSON, using the schema embedded in it
+pub fn read_container(data: &[u8]) -> Result<Vec<Value>> {
+    let mut reader = Reader { data, position: 0 };
+    let (schema, codec) = read_header(&mut reader)?;
+    let sync = reader.take(16)?;
+
+    let mut records = Vec::new();
+    while !reader.is_done() {
+        let count = usize::try_from(reader.long()?)?;
+        let data = codec.decompress(reader.bytes()?)?;
+        let mut block = Reader { data: &data, position: 0 };
+        for _ in 0..count {
+            records.push(decode(&schema, &mut block)?);
+        }
+        if reader.take(16)? != sync {
+            return Err(anyhow::anyhow!("Avro block is not followed by the file's sync marker"));
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let receipt = json!({
+            "id": 7_000_000, "block_hash": "0xb", "block_number": 7, "contract_address": null, "cumulative_gas_used": 21000,
+            "effective_gas_price": u64::MAX, "from": "0xf", "gas_used": 21000,
+            "logs": [{
+                "address": "0xc", "topics": ["0xt"], "data": "0x", "blockHash": "0xb", "blockNumber": "0x7",
+                "transactionHash": "0xh", "transactionIndex": "0x0", "logIndex": "0x2", "removed": false,
+                "topic0": "0xt", "event_signature": null,
+                "indexed": [{ "name": "flag", "type": "bool", "value": true }, { "name": null, "type": "bytes32", "value": "0x01" }],
+                "l2Field": 1,
+            }],
+            "logs_bloom": "0x", "status": true, "to": "0x2", "transaction_hash": "0xh", "transaction_index": 0, "tx_type": 2,
+            "datetime": "2024-01-31T12:00:00Z", "extra": { "l1Fee": "0x10" },
+        });
+        let schema = dataset_schema("receipts").unwrap();
+        assert_eq!(Schema::from_json(&schema.to_json()).unwrap(), schema);
+
+        for codec in [Codec::Null, Codec::Deflate, Codec::Zstandard] {
+            let file = write_container(&schema, &[receipt.clone(), receipt.clone()], codec).unwrap();
+            assert_eq!(read_container(&file).unwrap(), vec![receipt.clone(), receipt.clone()]);
+        }
+        // 2^63 and above need the ninth decimal byte's sign padding
+        let mut out = Vec::new();
+        encode(&Schema::Decimal, &json!(1u64 << 63), "value", &mut out).unwrap();
+        assert_eq!(out, [18, 0, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut wrong = receipt.clone();
+        wrong["block_number"] = json!("seven");
+        assert!(encode_record(&schema, &wrong).unwrap_err().to_string().contains("receipts.block_number"));
+    }
+}
@@ -0,0 +1,247 @@
+//! Background backfill of decoders enabled on a store that already holds history.
+//!
+//! `raw_data/decoders.json` records, per optional decoder, the hash of its configuration and the
+//! block it was first enabled at. When a run enables a decoder that is not recorded (or whose
+//! configuration changed) and blocks below its start are already indexed, those blocks are decoded
+//! in the background while indexing goes on, so the derived dataset becomes complete without a
+//! manual replay:
+//!
+//! - `token_metadata`: stored `token_transfers` files are enriched from the metadata cache (fetching
+//!   unknown tokens) and re-flagged for spam, then rewritten in place
+//! - `state_calls`: the calls are run at the blocks of each stored `blocks` file (needs an archive
+//!   node) and written to `contract_state` under the same block ranges
+//!
+//! Progress is kept after every file, so a backfill cut short by a shutdown resumes with the next
+//! run. A run without the decoder forgets it, and enabling it again backfills everything indexed.
+//! Backfilled rows go to the files only, not to the database sinks.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::models::{TokenTransfer, TransformedBlock};
+use crate::pipeline::TransformedBatch;
+use crate::spam::{self, SpamConfig, SpamRules};
+use crate::store::{self, OutputConfig, OutputDirs};
+use crate::trie::encode_hex;
+use crate::{shutdown, state_calls, token_metadata};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoder {
+    TokenMetadata,
+    StateCalls,
+}
+
+impl Decoder {
+    // Dataset whose files a backfill walks
+    fn source(self) -> &'static str {
+        match self {
+            Decoder::TokenMetadata => "token_transfers",
+            Decoder::StateCalls => "blocks",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecoderState {
+    pub fingerprint: String,
+    // Blocks below this were indexed without the decoder
+    pub enabled_from: u64,
+    // Last source file backfilled, relative to RAW_DATA_PATH; files are walked in path order
+    pub backfilled_through: Option<String>,
+    pub complete: bool,
+}
+
+pub type DecoderStates = BTreeMap<Decoder, DecoderState>;
+
+// Serialises the read-modify-write of the state file between a run and its backfill
+static STATES: Mutex<()> = Mutex::new(());
+static RUNNING: LazyLock<Mutex<Option<JoinHandle<()>>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn fingerprint(config: &[u8]) -> String {
+    encode_hex(&Sha256::digest(config))
+}
+
+fn state_path(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("decoders.json")
+}
+
+pub fn load(raw_data_path: &str) -> Result<DecoderStates> {
+    match fs::read(state_path(raw_data_path)) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DecoderStates::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn update<T>(raw_data_path: &str, change: impl FnOnce(&mut DecoderStates) -> T) -> Result<T> {
+    let _guard = STATES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut states = load(raw_data_path)?;
+    let result = change(&mut states);
+    let path = state_path(raw_data_path);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&states)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(result)
+}
+
+// Records the decoders (with their configuration fingerprints) of a run starting at `start` and
+// returns the ones with indexed history left to backfill
+pub fn register(raw_data_path: &str, start: u64, enabled: &[(Decoder, String)]) -> Result<Vec<Decoder>> {
+    let history = store::indexed_block_numbers(raw_data_path)?.range(..start).next().is_some();
+    update(raw_data_path, |states| {
+        states.retain(|decoder, _| enabled.iter().any(|(enabled, _)| enabled == decoder));
+        for (decoder, fingerprint) in enabled {
+            if states.get(decoder).is_some_and(|state| state.fingerprint == *fingerprint) {
+                continue;
+            }
+            let state = DecoderState { fingerprint: fingerprint.clone(), enabled_from: start, backfilled_through: None, complete: !history };
+            states.insert(*decoder, state);
+        }
+        states.iter().filter(|(_, state)| !state.complete).map(|(decoder, _)| *decoder).collect()
+    })
+}
+
+// What a backfill needs from the run that started it
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    pub raw_data_path: String,
+    pub output: OutputConfig,
+    pub state_calls: Option<PathBuf>,
+    pub spam: SpamConfig,
+    pub concurrency: usize,
+}
+
+impl Backfill {
+    async fn run(&self, decoder: Decoder) -> Result<()> {
+        let Some(state) = load(&self.raw_data_path)?.remove(&decoder) else { return Ok(()) };
+        log::info!("Backfilling {:?} below block {}", decoder, state.enabled_from);
+        let mut files = 0;
+        for path in store::dataset_files(&self.raw_data_path, decoder.source())? {
+            if shutdown::is_requested() {
+                log::info!("Stopped backfilling {:?} after {} files; the next run resumes it", decoder, files);
+                return Ok(());
+            }
+            let relative = path.strip_prefix(&self.raw_data_path).unwrap_or(&path).to_string_lossy().to_string();
+            if state.backfilled_through.as_ref().is_some_and(|done| relative <= *done) {
+                continue;
+            }
+            match decoder {
+                Decoder::TokenMetadata => self.token_metadata(&path, state.enabled_from).await?,
+                Decoder::StateCalls => self.state_calls(&path, state.enabled_from).await?,
+            }
+            files += 1;
+            let fingerprint = state.fingerprint.clone();
+            update(&self.raw_data_path, |states| {
+                // A run that disabled or reconfigured the decoder meanwhile starts over
+                if let Some(state) = states.get_mut(&decoder).filter(|state| state.fingerprint == fingerprint) {
+                    state.backfilled_through = Some(relative);
+                }
+            })?;
+        }
+        update(&self.raw_data_path, |states| {
+            if let Some(done) = states.get_mut(&decoder).filter(|done| done.fingerprint == state.fingerprint) {
+                done.complete = true;
+            }
+        })?;
+        log::info!("Backfilled {:?} over {} files", decoder, files);
+        Ok(())
+    }
+
+    async fn token_metadata(&self, path: &Path, enabled_from: u64) -> Result<()> {
+        let mut transfers: Vec<TokenTransfer> =
+            store::read_records(path)?.into_iter().map(serde_json::from_value).collect::<Result<_, _>>()?;
+        if transfers.iter().all(|transfer| transfer.block_number >= enabled_from) {
+            return Ok(());
+        }
+        token_metadata::enrich(&mut transfers, &self.raw_data_path, self.concurrency, true).await?;
+        spam::flag(&mut transfers, &SpamRules::load(&self.spam)?, true);
+        let records = transfers.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()?;
+        store::rewrite_records(path, "token_transfers", &records)
+    }
+
+    async fn state_calls(&self, path: &Path, enabled_from: u64) -> Result<()> {
+        let Some(calls) = self.state_calls.as_deref().map(state_calls::load).transpose()? else { return Ok(()) };
+        let mut block_times = Vec::new();
+        for record in store::read_records(path)? {
+            let block: TransformedBlock = serde_json::from_value(record)?;
+            if block.number < enabled_from {
+                block_times.push((block.number, block.datetime));
+            }
+        }
+        if block_times.is_empty() {
+            return Ok(());
+        }
+        let contract_state = state_calls::sample(&calls, &block_times, self.concurrency).await;
+        let batch = TransformedBatch { contract_state, ..Default::default() };
+        let output = OutputConfig { address_index: false, ..self.output };
+        store::persist(&OutputDirs::new(&self.raw_data_path), output, None, &batch, 1).await
+    }
+}
+
+// Starts backfilling `decoders` unless a backfill is still running; the next run picks up what it
+// leaves
+pub fn spawn(backfill: Backfill, decoders: Vec<Decoder>) {
+    let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+    if decoders.is_empty() || running.as_ref().is_some_and(|handle| !handle.is_finished()) {
+        return;
+    }
+    // The state call and metadata futures are not Send, so the backfill drives them from a
+    // blocking thread of the runtime
+    let runtime = tokio::runtime::Handle::current();
+    *running = Some(tokio::task::spawn_blocking(move || {
+        runtime.block_on(async {
+            for decoder in decoders {
+                if let Err(e) = backfill.run(decoder).await {
+                    log::error!("Backfilling {:?} failed, the next run retries it: {}", decoder, e);
+                }
+            }
+        })
+    }));
+}
+
+// Waits for a running backfill, which stops early on shutdown
+pub async fn finish() {
+    let handle = RUNNING.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(handle) = handle {
+        if !handle.is_finished() {
+            log::info!("Waiting for the decoder backfill to finish (Ctrl-C stops it, the next run resumes it)");
+        }
+        let _ = handle.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_decoders() {
+        let root = "test_decoder_backfill";
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(format!("{}/blocks", root)).unwrap();
+        fs::write(format!("{}/blocks/block_5.json", root), "{}").unwrap();
+        let calls = (Decoder::StateCalls, fingerprint(b"[]"));
+        let metadata = (Decoder::TokenMetadata, fingerprint(b""));
+
+        // Nothing indexed below the start: decoded live only
+        assert!(register(root, 0, std::slice::from_ref(&calls)).unwrap().is_empty());
+        // Newly enabled over history
+        assert_eq!(register(root, 10, &[calls.clone(), metadata.clone()]).unwrap(), vec![Decoder::TokenMetadata]);
+        assert_eq!(load(root).unwrap()[&Decoder::TokenMetadata].enabled_from, 10);
+        // Reconfigured, and a run without token metadata forgets it
+        let changed = (Decoder::StateCalls, fingerprint(b"[{}]"));
+        assert_eq!(register(root, 20, &[changed]).unwrap(), vec![Decoder::StateCalls]);
+        let states = load(root).unwrap();
+        assert_eq!(states.keys().collect::<Vec<_>>(), vec![&Decoder::StateCalls]);
+        assert_eq!((states[&Decoder::StateCalls].enabled_from, states[&Decoder::StateCalls].complete), (20, false));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use primitive_types::U256;
+use serde_json::json;
+
+use crate::models::Balance;
+use crate::rpc;
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct BalanceConfig {
+    /// Addresses whose native balance is snapshotted into the `balances` dataset
+    #[arg(long = "balance-address", env = "BALANCE_ADDRESSES", value_delimiter = ',')]
+    pub balance_addresses: Vec<String>,
+
+    /// Snapshot balances at block numbers divisible by this
+    #[arg(long = "balance-every", env = "BALANCE_EVERY", default_value_t = 1)]
+    pub balance_every: u64,
+}
+
+// Block numbers that get a snapshot
+pub fn snapshot_blocks(blocks: &[(u64, DateTime<Utc>)], every: u64) -> Vec<(u64, DateTime<Utc>)> {
+    let every = every.max(1);
+    blocks.iter().copied().filter(|(number, _)| number % every == 0).collect()
+}
+
+async fn get_balance(address: &str, block_number: u64) -> Result<U256> {
+    let result = rpc::rpc_call("eth_getBalance", json!([address, format!("0x{:x}", block_number)])).await?;
+    let hex = result.as_str().ok_or_else(|| anyhow::anyhow!("eth_getBalance returned {}", result))?;
+    Ok(U256::from_str_radix(hex.trim_start_matches("0x"), 16)?)
+}
+
+// One row per address and snapshot block. Older blocks need an archive node; a failed lookup fails
+// the run rather than leaving holes in the series.
+pub async fn fetch(config: &BalanceConfig, blocks: &[(u64, DateTime<Utc>)], concurrency: usize) -> Result<Vec<Balance>> {
+    let lookups: Vec<(String, u64, DateTime<Utc>)> = snapshot_blocks(blocks, config.balance_every)
+        .into_iter()
+        .flat_map(|(number, datetime)| {
+            config.balance_addresses.iter().map(move |address| (address.to_lowercase(), number, datetime))
+        })
+        .collect();
+    stream::iter(lookups)
+        .map(|(address, block_number, datetime)| async move {
+            let balance = get_balance(&address, block_number)
+                .await
+                .map_err(|e| anyhow::anyhow!("Balance of {} at block {}: {}", address, block_number, e))?;
+            Ok::<_, anyhow::Error>(Balance { address, block_number, balance: balance.to_string(), datetime })
+        })
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_blocks() {
+        let now = Utc::now();
+        let blocks: Vec<(u64, DateTime<Utc>)> = (98..=105).map(|n| (n, now)).collect();
+        let numbers = |every| snapshot_blocks(&blocks, every).into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        assert_eq!(numbers(1).len(), 8);
+        assert_eq!(numbers(0).len(), 8);
+        assert_eq!(numbers(5), vec![100, 105]);
+        assert_eq!(numbers(1000), Vec::<u64>::new());
+    }
+}
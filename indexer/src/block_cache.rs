@@ -0,0 +1,230 @@
+//! Disk cache of raw block and receipt responses, so runs over overlapping ranges do not download
+//! the same blocks again. Enabled with `BLOCK_CACHE_PATH`.
+//!
+//! Entries are content addressed by chain id and block hash:
+//! `<BLOCK_CACHE_PATH>/<chain id>/<hash[2..4]>/<hash>.json.zst`. Before fetching a block the
+//! indexer asks for its header only (`eth_getBlockByNumber` without transactions) and reads the
+//! full block and its receipts from the cache when it holds that hash. A reorged block has another
+//! hash, so the cache never serves a block the chain no longer has, and several indexers or chains
+//! can share one directory.
+//!
+//! The cache is kept under `BLOCK_CACHE_MAX_MB`: once a write takes it over the limit, the least
+//! recently used entries (by modification time, refreshed on every hit) are deleted until it is
+//! back under 90% of it.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::sync::OnceCell;
+
+use crate::config::env_or;
+use crate::raw::RawBlock;
+use crate::rpc;
+
+static CACHE: OnceLock<BlockCache> = OnceLock::new();
+static CHAIN_ID: OnceCell<u64> = OnceCell::const_new();
+
+#[derive(Debug, Clone)]
+pub struct BlockCacheConfig {
+    // None disables the cache
+    pub path: Option<String>,
+    pub max_bytes: u64,
+}
+
+impl BlockCacheConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            path: std::env::var("BLOCK_CACHE_PATH").ok().filter(|path| !path.trim().is_empty()),
+            max_bytes: env_or("BLOCK_CACHE_MAX_MB", 10_240u64)? * 1024 * 1024,
+        })
+    }
+}
+
+pub struct BlockCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    // Bytes on disk, counted at startup and kept up to date by writes and evictions
+    size: AtomicU64,
+    evicting: Mutex<()>,
+}
+
+// Entry files under `dir` with their size and last use
+fn entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(listing) = fs::read_dir(&dir) else { continue };
+        for entry in listing.flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if entry.path().to_string_lossy().ends_with(".json.zst") {
+                found.push((entry.path(), metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+            }
+        }
+    }
+    found
+}
+
+impl BlockCache {
+    pub fn open(dir: &str, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("Could not create the block cache {}: {}", dir, e))?;
+        let size = entries(Path::new(dir)).iter().map(|(_, bytes, _)| bytes).sum();
+        Ok(Self { dir: PathBuf::from(dir), max_bytes, size: AtomicU64::new(size), evicting: Mutex::new(()) })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size.load(Ordering::SeqCst)
+    }
+
+    fn path(&self, chain_id: u64, hash: &str) -> PathBuf {
+        let hash = hash.to_lowercase();
+        let shard = hash.strip_prefix("0x").and_then(|hex| hex.get(..2)).unwrap_or("xx");
+        self.dir.join(chain_id.to_string()).join(shard).join(format!("{}.json.zst", hash))
+    }
+
+    pub fn get(&self, chain_id: u64, hash: &str) -> Result<Option<RawBlock>> {
+        let path = self.path(chain_id, hash);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // Refresh the entry's last use for eviction
+        fs::File::options().write(true).open(&path)?.set_modified(SystemTime::now())?;
+        Ok(Some(serde_json::from_slice(&zstd::decode_all(data.as_slice())?)?))
+    }
+
+    // Stores a fetched block under the hash it came with, then evicts if the cache is too large
+    pub fn put(&self, chain_id: u64, raw: &RawBlock) -> Result<()> {
+        let Some(hash) = raw.block["hash"].as_str() else {
+            return Ok(());
+        };
+        let path = self.path(chain_id, hash);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = zstd::encode_all(serde_json::to_vec(raw)?.as_slice(), 0)?;
+        let replaced = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        let tmp = path.with_extension("zst.tmp");
+        fs::write(&tmp, &data)?;
+        fs::rename(&tmp, &path)?;
+        let size = self.size.fetch_add(data.len() as u64, Ordering::SeqCst) + data.len() as u64;
+        self.size.fetch_sub(replaced.min(size), Ordering::SeqCst);
+        if self.size() > self.max_bytes {
+            self.evict();
+        }
+        Ok(())
+    }
+
+    // Deletes the least recently used entries until the cache is under 90% of its limit
+    pub fn evict(&self) {
+        let Ok(_evicting) = self.evicting.try_lock() else {
+            return;
+        };
+        let mut entries = entries(&self.dir);
+        entries.sort_by_key(|(_, _, used)| *used);
+        let mut size: u64 = entries.iter().map(|(_, bytes, _)| bytes).sum();
+        let target = self.max_bytes / 10 * 9;
+        let mut removed = 0;
+        for (path, bytes, _) in entries {
+            if size <= target {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                size -= bytes;
+                removed += 1;
+            }
+        }
+        self.size.store(size, Ordering::SeqCst);
+        log::info!("Evicted {} blocks from the block cache, {} MB left", removed, size / 1024 / 1024);
+    }
+}
+
+// Opens the cache for this process when BLOCK_CACHE_PATH is set
+pub fn configure(config: &BlockCacheConfig) -> Result<()> {
+    if let Some(path) = &config.path {
+        let cache = BlockCache::open(path, config.max_bytes)?;
+        log::info!("Block cache {}: {} MB of {} MB", path, cache.size() / 1024 / 1024, config.max_bytes / 1024 / 1024);
+        let _ = CACHE.set(cache);
+    }
+    Ok(())
+}
+
+async fn chain_id() -> Result<u64> {
+    CHAIN_ID.get_or_try_init(rpc::get_chain_id).await.copied()
+}
+
+// The cached responses of a block, looked up by the hash of its current header
+pub async fn lookup(number: u64) -> Option<RawBlock> {
+    let cache = CACHE.get()?;
+    let header = async { Ok::<_, anyhow::Error>((chain_id().await?, rpc::get_raw_block_with(number, false).await?)) };
+    let (chain_id, header) = match header.await {
+        Ok(found) => found,
+        Err(e) => {
+            log::debug!("Block cache lookup of block {} failed: {}", number, e);
+            return None;
+        },
+    };
+    match cache.get(chain_id, header["hash"].as_str()?) {
+        Ok(Some(raw)) => {
+            log::info!("Block {} read from the block cache", number);
+            Some(raw)
+        },
+        Ok(None) => None,
+        Err(e) => {
+            log::warn!("Could not read block {} from the block cache: {}", number, e);
+            None
+        },
+    }
+}
+
+// Adds fetched responses to the cache; a failure only costs a later download
+pub async fn store(raw: &RawBlock) {
+    let Some(cache) = CACHE.get() else { return };
+    let Ok(chain_id) = chain_id().await else { return };
+    let raw = raw.clone();
+    let result = tokio::task::spawn_blocking(move || cache.put(chain_id, &raw)).await;
+    if let Err(e) = result.map_err(anyhow::Error::from).and_then(|result| result) {
+        log::warn!("Could not add a block to the block cache: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_put_get_and_evict() {
+        let root = "test_block_cache";
+        let _ = fs::remove_dir_all(root);
+        let raw = |hash: &str| RawBlock { block: json!({ "hash": hash, "transactions": vec!["0x1"; 64] }), receipts: json!([]) };
+
+        let cache = BlockCache::open(root, 1 << 20).unwrap();
+        cache.put(59144, &raw("0xAB01")).unwrap();
+        assert_eq!(cache.get(59144, "0xab01").unwrap(), Some(raw("0xAB01")));
+        assert!(Path::new(root).join("59144/ab/0xab01.json.zst").exists());
+        assert_eq!(cache.get(1, "0xab01").unwrap(), None);
+        let entry = cache.size();
+        assert!(entry > 0);
+        cache.put(59144, &raw("0xAB01")).unwrap();
+        assert_eq!(cache.size(), entry);
+
+        // Room for two entries: the third write evicts the least recently used one
+        let limit = entry * 2 + entry / 2;
+        let cache = BlockCache::open(root, limit).unwrap();
+        assert_eq!(cache.size(), entry);
+        cache.put(59144, &raw("0xcd02")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.get(59144, "0xab01").unwrap();
+        cache.put(59144, &raw("0xef03")).unwrap();
+        assert!(cache.get(59144, "0xcd02").unwrap().is_none());
+        assert!(cache.get(59144, "0xab01").unwrap().is_some());
+        assert!(cache.size() <= limit / 10 * 9);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
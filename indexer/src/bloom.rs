@@ -0,0 +1,62 @@
+use crate::trie::{decode_hex, keccak256};
+
+// Bit positions an item sets in a 2048-bit logs bloom (yellow paper M3:2048)
+pub fn bloom_bits(item: &[u8]) -> [usize; 3] {
+    let hash = keccak256(item);
+    [0, 2, 4].map(|i| ((hash[i] as usize) << 8 | hash[i + 1] as usize) & 2047)
+}
+
+// Logs bloom of a set of items (log addresses and topics), as 256 bytes
+pub fn compute_bloom<'a>(items: impl IntoIterator<Item = &'a [u8]>) -> [u8; 256] {
+    let mut bloom = [0u8; 256];
+    for item in items {
+        for bit in bloom_bits(item) {
+            bloom[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    bloom
+}
+
+// Whether all of the bit positions are set, for checking one item against many blooms
+pub fn bloom_has_bits(bloom: &[u8], bits: &[usize; 3]) -> bool {
+    bloom.len() == 256 && bits.iter().all(|bit| bloom[255 - bit / 8] & (1 << (bit % 8)) != 0)
+}
+
+// Whether the bloom may contain the item; false positives are possible, false negatives are not
+pub fn bloom_contains(bloom: &[u8], item: &[u8]) -> bool {
+    bloom_has_bits(bloom, &bloom_bits(item))
+}
+
+// Whether a hex logs bloom may contain any of the hex addresses or topics. Unparseable blooms
+// match, so a malformed header never hides a block.
+pub fn bloom_matches_any(bloom_hex: &str, items: &[String]) -> bool {
+    match decode_hex(bloom_hex) {
+        Ok(bloom) if bloom.len() == 256 => items
+            .iter()
+            .filter_map(|item| decode_hex(item).ok())
+            .any(|item| bloom_contains(&bloom, &item)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::encode_hex;
+
+    #[test]
+    fn test_bloom_membership() {
+        let token = decode_hex("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+        let transfer = decode_hex("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap();
+        let bloom = compute_bloom([token.as_slice(), transfer.as_slice()]);
+        assert!(bloom_contains(&bloom, &token));
+        assert!(bloom_contains(&bloom, &transfer));
+        assert_eq!(bloom.iter().map(|byte| byte.count_ones()).sum::<u32>(), 6);
+
+        let bloom_hex = encode_hex(&bloom);
+        assert!(bloom_matches_any(&bloom_hex, &["0xA0B86991C6218B36C1D19D4A2E9EB0CE3606EB48".to_string()]));
+        assert!(!bloom_matches_any(&bloom_hex, &["0x0000000000000000000000000000000000000001".to_string()]));
+        assert!(!bloom_matches_any(&encode_hex(&[0u8; 256]), &["0xdead".to_string()]));
+        assert!(bloom_matches_any("0x", &[]));
+    }
+}
@@ -0,0 +1,109 @@
+//! Decoding of the Linea message service events behind the `bridge_messages` dataset.
+//!
+//! Linea's L2MessageService emits `MessageSent` for every message sent to Ethereum (withdrawals
+//! included) and `MessageClaimed` when a message sent from Ethereum is delivered on Linea. Only
+//! events of the known message service deployments are decoded: anyone can emit an event with the
+//! same signature, and such copies say nothing about the bridge.
+
+use primitive_types::U256;
+use serde_json::Value;
+
+use crate::events::{topic_address, word};
+use crate::trie::decode_hex;
+
+// keccak256("MessageSent(address,address,uint256,uint256,uint256,bytes,bytes32)")
+pub const MESSAGE_SENT_TOPIC: &str = "0xe856c2b8bd4eb0027ce32eeaf595c21b0b6b4644b326e5b7bd80a1cf8db72e6c";
+// keccak256("MessageClaimed(bytes32)")
+pub const MESSAGE_CLAIMED_TOPIC: &str = "0xa4c827e719e911e8f19393ccdb85b5102f08f0910604d340ba38390b7ff2ab0e";
+
+// L2MessageService proxies on Linea mainnet and Linea Sepolia
+pub const MESSAGE_SERVICES: &[&str] = &["0x508ca82df566dcd1b0de8296e70a96332cd644ec", "0x971e727e956690b9957be6d51ec16e73acac83a7"];
+
+pub const L2_TO_L1: &str = "l2_to_l1";
+pub const L1_TO_L2: &str = "l1_to_l2";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BridgeEvent {
+    // A message leaving Linea; `calldata` is what the recipient is called with on Ethereum
+    Sent { contract: String, message_hash: String, from: String, to: String, fee: U256, value: U256, nonce: U256, calldata: String },
+    // A message from Ethereum delivered on Linea
+    Claimed { contract: String, message_hash: String },
+}
+
+// ABI `bytes` whose head word sits at `index` in `data`, as 0x-prefixed hex
+fn bytes_at(data: &[u8], index: usize) -> Option<String> {
+    let word_at = |offset: usize| data.get(offset..offset + 32).map(U256::from_big_endian);
+    let small = |word: U256| (word <= U256::from(u32::MAX)).then(|| word.as_usize());
+    let offset = small(word_at(index * 32)?)?;
+    let length = small(word_at(offset)?)?;
+    let bytes = data.get(offset + 32..(offset + 32).checked_add(length)?)?;
+    Some(format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+}
+
+// A bytes32 topic, checked to be one
+fn hash(topic: &str) -> Option<String> {
+    word(topic).map(|_| topic.to_string())
+}
+
+pub fn decode(log: &Value) -> Option<BridgeEvent> {
+    let contract = log["address"].as_str()?.to_lowercase();
+    if !MESSAGE_SERVICES.contains(&contract.as_str()) {
+        return None;
+    }
+    let topics: Vec<String> = log["topics"].as_array()?.iter().filter_map(Value::as_str).map(str::to_lowercase).collect();
+    match (topics.first()?.as_str(), topics.len()) {
+        (MESSAGE_SENT_TOPIC, 4) => {
+            let data = decode_hex(log["data"].as_str().unwrap_or("0x")).ok()?;
+            let word_at = |index: usize| data.get(index * 32..index * 32 + 32).map(U256::from_big_endian);
+            Some(BridgeEvent::Sent {
+                contract,
+                from: topic_address(&topics[1])?,
+                to: topic_address(&topics[2])?,
+                message_hash: hash(&topics[3])?,
+                fee: word_at(0)?,
+                value: word_at(1)?,
+                nonce: word_at(2)?,
+                calldata: bytes_at(&data, 3)?,
+            })
+        },
+        (MESSAGE_CLAIMED_TOPIC, 2) => Some(BridgeEvent::Claimed { contract, message_hash: hash(&topics[1])? }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_message_events() {
+        let service = "0x508Ca82Df566dCD1B0DE8296e70a96332cD644ec";
+        let hash = format!("0x{}", "ab".repeat(32));
+        let padded = |address: &str| format!("0x{:0>64}", address.trim_start_matches("0x"));
+        // fee 5, value 1 ETH, nonce 7, then calldata 0xdeadbeef
+        let data = format!(
+            "0x{:064x}{:064x}{:064x}{:064x}{:064x}{:0<64}",
+            5, 1_000_000_000_000_000_000u64, 7, 128, 4, "deadbeef"
+        );
+        let sent = json!({
+            "address": service, "data": data,
+            "topics": [MESSAGE_SENT_TOPIC, padded("0xaa"), padded("0xbb"), hash],
+        });
+        let Some(BridgeEvent::Sent { contract, message_hash, from, to, fee, value, nonce, calldata }) = decode(&sent) else {
+            panic!("MessageSent not decoded");
+        };
+        assert_eq!((contract.as_str(), message_hash.as_str()), (MESSAGE_SERVICES[0], hash.as_str()));
+        assert_eq!((from.as_str(), to.as_str()), ("0x00000000000000000000000000000000000000aa", "0x00000000000000000000000000000000000000bb"));
+        assert_eq!((fee, value, nonce), (U256::from(5), U256::exp10(18), U256::from(7)));
+        assert_eq!(calldata, "0xdeadbeef");
+
+        let claimed = json!({ "address": service, "data": "0x", "topics": [MESSAGE_CLAIMED_TOPIC, hash] });
+        assert_eq!(decode(&claimed), Some(BridgeEvent::Claimed { contract: MESSAGE_SERVICES[0].to_string(), message_hash: hash.clone() }));
+
+        // The same event from another contract, and a calldata length past the end of the data
+        assert_eq!(decode(&json!({ "address": "0x00000000000000000000000000000000000000cc", "data": "0x", "topics": [MESSAGE_CLAIMED_TOPIC, hash] })), None);
+        let truncated = json!({ "address": service, "data": &data[..data.len() - 64], "topics": sent["topics"] });
+        assert_eq!(decode(&truncated), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Query result cache for the HTTP API (`api` feature).
+//!
+//! Results are kept in memory and, when `API_CACHE_REDIS_URL` is set, in Redis so several API
+//! replicas share them. Every entry records the highest block its result depends on: results
+//! over a closed block range survive the indexed head moving forward, while "latest" style
+//! results are dropped whenever the head advances. A reorg drops everything at or above the
+//! first replaced block.
+
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::env_or;
+
+const REDIS_PREFIX: &str = "sweet-potato:cache";
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // Entries kept in memory before the oldest are evicted
+    pub capacity: usize,
+    pub ttl: Duration,
+    pub redis_url: Option<String>,
+}
+
+impl CacheConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(CacheConfig {
+            capacity: env_or("API_CACHE_CAPACITY", 10_000)?,
+            ttl: Duration::from_secs(env_or("API_CACHE_TTL_SECS", 300)?),
+            redis_url: std::env::var("API_CACHE_REDIS_URL").ok().filter(|url| !url.trim().is_empty()),
+        })
+    }
+}
+
+// Blocks a cached result depends on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    // Changes whenever the indexed head moves (address history, "latest" queries)
+    Head,
+    // Only depends on blocks up to and including this one
+    UpTo(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeadChange {
+    Unchanged,
+    Advanced,
+    // First block whose contents were replaced
+    Reorg(u64),
+}
+
+struct Entry {
+    value: String,
+    max_block: Option<u64>,
+    inserted: Instant,
+}
+
+#[derive(Default)]
+struct HeadState {
+    number: Option<u64>,
+    hash: String,
+    // Bumped on every reorg; part of the Redis key so replicas stop reading replaced results
+    epoch: u64,
+}
+
+pub struct QueryCache {
+    config: CacheConfig,
+    memory: Mutex<HashMap<String, Entry>>,
+    head: Mutex<HeadState>,
+    redis: Option<ConnectionManager>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl QueryCache {
+    pub async fn connect(config: CacheConfig) -> Result<Self> {
+        let redis = match &config.redis_url {
+            Some(url) => {
+                log::info!("Caching API results in Redis at {}", url);
+                Some(ConnectionManager::new(redis::Client::open(url.as_str())?).await?)
+            },
+            None => None,
+        };
+        Ok(QueryCache {
+            config,
+            memory: Mutex::new(HashMap::new()),
+            head: Mutex::new(HeadState::default()),
+            redis,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    // (hits, misses) since startup
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+
+    // Records the indexed head and invalidates what it affects. A head that moves backwards,
+    // or the same height with a different hash, is treated as a reorg from that height.
+    pub async fn observe_head(&self, number: u64, hash: &str) -> HeadChange {
+        let change = {
+            let mut head = self.head.lock().unwrap();
+            let change = match head.number {
+                None => HeadChange::Advanced,
+                Some(previous) if number > previous => HeadChange::Advanced,
+                Some(previous) if number == previous && hash == head.hash => HeadChange::Unchanged,
+                Some(previous) => HeadChange::Reorg(number.min(previous)),
+            };
+            head.number = Some(number);
+            head.hash = hash.to_string();
+            change
+        };
+
+        match change {
+            HeadChange::Unchanged => {},
+            HeadChange::Advanced => self.retain(|max_block| max_block.is_some()),
+            HeadChange::Reorg(from) => {
+                log::warn!("Indexed head reorganised from block {}, invalidating cached results", from);
+                self.retain(|max_block| max_block.is_some_and(|max_block| max_block < from));
+                self.bump_epoch().await;
+            },
+        }
+        change
+    }
+
+    // Cached result for `key`, computing and storing it on a miss. Redis failures are logged
+    // and fall back to computing the result.
+    pub async fn get_or_compute<F, Fut>(&self, key: &str, scope: Scope, compute: F) -> Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let (max_block, redis_key) = self.resolve(key, scope);
+
+        if let Some(value) = self.memory_get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(value);
+        }
+        if let Some(mut redis) = self.redis.clone() {
+            match redis.get::<_, Option<String>>(&redis_key).await {
+                Ok(Some(value)) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    self.memory_put(key, &value, max_block);
+                    return Ok(value);
+                },
+                Ok(None) => {},
+                Err(e) => log::warn!("Redis cache read failed: {}", e),
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = compute().await?;
+        self.memory_put(key, &value, max_block);
+        if let Some(mut redis) = self.redis.clone() {
+            if let Err(e) = redis.set_ex::<_, _, ()>(&redis_key, &value, self.config.ttl.as_secs().max(1)).await {
+                log::warn!("Redis cache write failed: {}", e);
+            }
+        }
+        Ok(value)
+    }
+
+    // Results over ranges that reach the head are as volatile as head-scoped ones
+    fn resolve(&self, key: &str, scope: Scope) -> (Option<u64>, String) {
+        let head = self.head.lock().unwrap();
+        let max_block = match (scope, head.number) {
+            (Scope::UpTo(block), Some(number)) if block < number => Some(block),
+            _ => None,
+        };
+        let generation = match max_block {
+            Some(_) => "closed".to_string(),
+            None => format!("h{}", head.number.unwrap_or_default()),
+        };
+        (max_block, format!("{}:{}:{}:{}", REDIS_PREFIX, head.epoch, generation, key))
+    }
+
+    fn memory_get(&self, key: &str) -> Option<String> {
+        let memory = self.memory.lock().unwrap();
+        memory
+            .get(key)
+            .filter(|entry| entry.inserted.elapsed() < self.config.ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn memory_put(&self, key: &str, value: &str, max_block: Option<u64>) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let mut memory = self.memory.lock().unwrap();
+        if memory.len() >= self.config.capacity && !memory.contains_key(key) {
+            memory.retain(|_, entry| entry.inserted.elapsed() < self.config.ttl);
+            if memory.len() >= self.config.capacity {
+                let oldest = memory.iter().min_by_key(|(_, entry)| entry.inserted).map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    memory.remove(&oldest);
+                }
+            }
+        }
+        memory.insert(key.to_string(), Entry { value: value.to_string(), max_block, inserted: Instant::now() });
+    }
+
+    fn retain(&self, keep: impl Fn(Option<u64>) -> bool) {
+        self.memory.lock().unwrap().retain(|_, entry| keep(entry.max_block));
+    }
+
+    // The epoch lives in Redis so every replica switches keys after a reorg any of them saw
+    async fn bump_epoch(&self) {
+        let epoch = match self.redis.clone() {
+            Some(mut redis) => match redis.incr::<_, _, u64>(format!("{}:epoch", REDIS_PREFIX), 1).await {
+                Ok(epoch) => epoch,
+                Err(e) => {
+                    log::warn!("Redis cache epoch update failed: {}", e);
+                    self.head.lock().unwrap().epoch + 1
+                },
+            },
+            None => self.head.lock().unwrap().epoch + 1,
+        };
+        self.head.lock().unwrap().epoch = epoch;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn cached(cache: &QueryCache, key: &str, scope: Scope, value: &str) -> String {
+        let value = value.to_string();
+        cache.get_or_compute(key, scope, || async move { Ok(value) }).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_on_head_and_reorg() {
+        let config = CacheConfig { capacity: 100, ttl: Duration::from_secs(60), redis_url: None };
+        let cache = QueryCache::connect(config).await.unwrap();
+        assert_eq!(cache.observe_head(100, "0xa").await, HeadChange::Advanced);
+
+        assert_eq!(cached(&cache, "history", Scope::Head, "v1").await, "v1");
+        assert_eq!(cached(&cache, "range", Scope::UpTo(90), "v1").await, "v1");
+        assert_eq!(cached(&cache, "history", Scope::Head, "v2").await, "v1");
+        assert_eq!(cache.stats(), (1, 2));
+
+        // Head moves: head-scoped results are recomputed, closed ranges are kept
+        assert_eq!(cache.observe_head(101, "0xb").await, HeadChange::Advanced);
+        assert_eq!(cache.observe_head(101, "0xb").await, HeadChange::Unchanged);
+        assert_eq!(cached(&cache, "history", Scope::Head, "v2").await, "v2");
+        assert_eq!(cached(&cache, "range", Scope::UpTo(90), "v2").await, "v1");
+
+        // Reorg below the cached range drops it too
+        assert_eq!(cache.observe_head(85, "0xc").await, HeadChange::Reorg(85));
+        assert_eq!(cached(&cache, "range", Scope::UpTo(90), "v3").await, "v3");
+    }
+}
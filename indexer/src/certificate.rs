@@ -0,0 +1,225 @@
+//! Signed completion certificates for indexed ranges, so datasets shared between teams can be
+//! checked for integrity and provenance without re-indexing them.
+//!
+//! When a run finishes its whole range and `CERT_SIGNING_KEY` (a hex ed25519 seed) or
+//! `CERT_SIGNING_KEY_PATH` is set, the indexer writes to `raw_data/certificates/`:
+//!
+//! - `manifest_<first>_<last>.json`: per dataset, the number of records in the range and the
+//!   SHA-256 of their JSON lines in sorted order. It depends on the records only, not on the file
+//!   layout or compression they were written with.
+//! - `certificate_<first>_<last>.json`: the range, the dataset checksums, the manifest's SHA-256,
+//!   the signer's public key and an ed25519 signature over all of them.
+//!
+//! `certificate verify` checks the signature and recomputes the checksums from the local store.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::clock;
+use crate::store::{self, DATASETS};
+use crate::trie::{decode_hex, encode_hex};
+
+pub const VERSION: u32 = 1;
+
+// The signing key from CERT_SIGNING_KEY, or the file CERT_SIGNING_KEY_PATH points to
+pub fn signing_key_from_env() -> Result<Option<SigningKey>> {
+    let hex = match (std::env::var("CERT_SIGNING_KEY"), std::env::var("CERT_SIGNING_KEY_PATH")) {
+        (Ok(key), _) if !key.trim().is_empty() => key,
+        (_, Ok(path)) if !path.trim().is_empty() => {
+            std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path, e))?
+        },
+        _ => return Ok(None),
+    };
+    parse_signing_key(&hex).map(Some)
+}
+
+fn parse_signing_key(hex: &str) -> Result<SigningKey> {
+    let seed: [u8; 32] = decode_hex(hex.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("The certificate signing key must be a 32-byte hex ed25519 seed"))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+pub fn parse_public_key(hex: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] =
+        decode_hex(hex.trim())?.try_into().map_err(|_| anyhow::anyhow!("An ed25519 public key is 32 bytes of hex"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatasetChecksum {
+    pub records: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub datasets: BTreeMap<String, DatasetChecksum>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Certificate {
+    pub version: u32,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub datasets: BTreeMap<String, DatasetChecksum>,
+    pub manifest_sha256: String,
+    pub indexer_version: String,
+    pub created_at: DateTime<Utc>,
+    pub public_key: String,
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl Certificate {
+    // What the signature covers: the certificate without its signature
+    fn message(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&Certificate { signature: String::new(), ..self.clone() })?)
+    }
+
+    pub fn sign(manifest: &Manifest, key: &SigningKey) -> Result<Self> {
+        let mut certificate = Certificate {
+            version: VERSION,
+            first_block: manifest.first_block,
+            last_block: manifest.last_block,
+            datasets: manifest.datasets.clone(),
+            manifest_sha256: encode_hex(&Sha256::digest(serde_json::to_vec_pretty(manifest)?)),
+            indexer_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: clock::now(),
+            public_key: encode_hex(key.verifying_key().as_bytes()),
+            signature: String::new(),
+        };
+        certificate.signature = encode_hex(&key.sign(&certificate.message()?).to_bytes());
+        Ok(certificate)
+    }
+
+    // Checks the signature against the embedded public key, or against `trusted` when given
+    pub fn verify_signature(&self, trusted: Option<&VerifyingKey>) -> Result<()> {
+        let public_key = parse_public_key(&self.public_key)?;
+        if trusted.is_some_and(|trusted| *trusted != public_key) {
+            return Err(anyhow::anyhow!("Certificate is signed by {}, not the trusted key", self.public_key));
+        }
+        let signature: [u8; 64] =
+            decode_hex(&self.signature)?.try_into().map_err(|_| anyhow::anyhow!("Certificate signature is not 64 bytes"))?;
+        public_key
+            .verify(&self.message()?, &Signature::from_bytes(&signature))
+            .map_err(|_| anyhow::anyhow!("Certificate signature does not match its contents"))
+    }
+
+    // Checks that the manifest, as recomputed from the store, is the one that was signed
+    pub fn verify_manifest(&self, manifest: &Manifest) -> Result<()> {
+        if (manifest.first_block, manifest.last_block) != (self.first_block, self.last_block) {
+            return Err(anyhow::anyhow!("Manifest covers another range than the certificate"));
+        }
+        for (dataset, signed) in &self.datasets {
+            match manifest.datasets.get(dataset) {
+                Some(local) if local == signed => {},
+                Some(local) => {
+                    return Err(anyhow::anyhow!(
+                        "{} differs: {} records ({}) locally, {} records ({}) certified",
+                        dataset,
+                        local.records,
+                        local.sha256,
+                        signed.records,
+                        signed.sha256
+                    ))
+                },
+                None => return Err(anyhow::anyhow!("{} is certified but not in the manifest", dataset)),
+            }
+        }
+        if encode_hex(&Sha256::digest(serde_json::to_vec_pretty(manifest)?)) != self.manifest_sha256 {
+            return Err(anyhow::anyhow!("Manifest hash does not match the certificate"));
+        }
+        Ok(())
+    }
+}
+
+// Record count and SHA-256 of the sorted JSON lines of records
+pub fn checksum(records: &[Value]) -> Result<DatasetChecksum> {
+    let mut lines = records.iter().map(serde_json::to_string).collect::<serde_json::Result<Vec<_>>>()?;
+    lines.sort();
+    let mut hasher = Sha256::new();
+    for line in &lines {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(DatasetChecksum { records: lines.len() as u64, sha256: encode_hex(&hasher.finalize()) })
+}
+
+pub fn manifest(raw_data_path: &str, first: u64, last: u64) -> Result<Manifest> {
+    let mut datasets = BTreeMap::new();
+    for dataset in DATASETS {
+        let records: Vec<Value> = store::read_range(raw_data_path, dataset, first, last)?;
+        datasets.insert(dataset.to_string(), checksum(&records)?);
+    }
+    Ok(Manifest { first_block: first, last_block: last, datasets })
+}
+
+fn dir(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("certificates")
+}
+
+// Checksums a range of the store, signs it and writes the manifest and certificate, returning
+// the certificate's path
+pub fn certify(raw_data_path: &str, first: u64, last: u64, key: &SigningKey) -> Result<PathBuf> {
+    let manifest = manifest(raw_data_path, first, last)?;
+    let certificate = Certificate::sign(&manifest, key)?;
+    let dir = dir(raw_data_path);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(format!("manifest_{}_{}.json", first, last)), serde_json::to_vec_pretty(&manifest)?)?;
+    let path = dir.join(format!("certificate_{}_{}.json", first, last));
+    std::fs::write(&path, serde_json::to_vec_pretty(&certificate)?)?;
+    Ok(path)
+}
+
+// Verifies a certificate file against the local store
+pub fn verify(raw_data_path: &str, path: &Path, trusted: Option<&VerifyingKey>) -> Result<Certificate> {
+    let certificate: Certificate = serde_json::from_slice(&std::fs::read(path)?)?;
+    if certificate.version != VERSION {
+        return Err(anyhow::anyhow!("Unsupported certificate version {}", certificate.version));
+    }
+    certificate.verify_signature(trusted)?;
+    certificate.verify_manifest(&manifest(raw_data_path, certificate.first_block, certificate.last_block)?)?;
+    Ok(certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = parse_signing_key(&format!("0x{}", "07".repeat(32))).unwrap();
+        let blocks = [json!({ "number": 2, "hash": "0xb" }), json!({ "number": 1, "hash": "0xa" })];
+        let mut manifest = Manifest {
+            first_block: 1,
+            last_block: 2,
+            datasets: [("blocks".to_string(), checksum(&blocks).unwrap())].into(),
+        };
+        // Independent of the order records were read in
+        assert_eq!(manifest.datasets["blocks"], checksum(&[blocks[1].clone(), blocks[0].clone()]).unwrap());
+
+        let certificate = Certificate::sign(&manifest, &key).unwrap();
+        certificate.verify_signature(None).unwrap();
+        certificate.verify_signature(Some(&key.verifying_key())).unwrap();
+        certificate.verify_manifest(&manifest).unwrap();
+        let other = parse_signing_key(&"08".repeat(32)).unwrap();
+        assert!(certificate.verify_signature(Some(&other.verifying_key())).is_err());
+
+        let mut forged = certificate.clone();
+        forged.last_block = 3;
+        assert!(forged.verify_signature(None).is_err());
+
+        manifest.datasets.insert("blocks".to_string(), checksum(&blocks[..1]).unwrap());
+        assert!(certificate.verify_manifest(&manifest).unwrap_err().to_string().contains("blocks differs"));
+    }
+}
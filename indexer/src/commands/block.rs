@@ -0,0 +1,202 @@
+//! `block <number|hash>`: fetches one block with its receipts the way `index` does (block cache
+//! and linkage checks included) and prints an explorer-style summary. Nothing is written.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::networks::currency;
+use crate::rpc;
+use crate::transform::transform_block_data;
+
+const WEI_PER_ETH: f64 = 1e18;
+// Largest native value transfers listed
+const TOP_TRANSFERS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockId {
+    Number(u64),
+    Hash,
+}
+
+// A 32-byte hex string is a hash; anything else a decimal or 0x-prefixed block number
+pub fn parse_id(id: &str) -> Result<BlockId> {
+    let invalid = || anyhow::anyhow!("{} is neither a block number nor a block hash", id);
+    match id.strip_prefix("0x") {
+        Some(digits) if digits.len() == 64 && digits.chars().all(|c| c.is_ascii_hexdigit()) => Ok(BlockId::Hash),
+        Some(digits) => u64::from_str_radix(digits, 16).map(BlockId::Number).map_err(|_| invalid()),
+        None => id.parse().map(BlockId::Number).map_err(|_| invalid()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValueTransfer {
+    pub hash: String,
+    pub from: String,
+    pub to: Option<String>,
+    // Wei as a decimal string
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockSummary {
+    pub number: u64,
+    pub hash: String,
+    pub parent_hash: String,
+    pub datetime: DateTime<Utc>,
+    pub miner: String,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    // Share of the gas limit that was used
+    pub gas_utilization: f64,
+    pub base_fee_per_gas: Option<u64>,
+    pub transactions: u64,
+    pub failed_transactions: u64,
+    pub contracts_created: u64,
+    pub logs: u64,
+    pub total_value: String,
+    // Wei as decimal strings, null when a receipt is missing
+    pub total_fees: Option<String>,
+    pub burnt_fees: Option<String>,
+    pub top_transfers: Vec<ValueTransfer>,
+}
+
+pub fn summarize(block: &TransformedBlock, transactions: &[TransformedTransaction], receipts: &[TransformedReceipt]) -> BlockSummary {
+    let mut transfers: Vec<&TransformedTransaction> = transactions.iter().filter(|tx| !tx.value_wei().is_zero()).collect();
+    transfers.sort_by(|a, b| b.value_wei().cmp(&a.value_wei()).then(a.transaction_index.cmp(&b.transaction_index)));
+    BlockSummary {
+        number: block.number,
+        hash: block.hash.clone(),
+        parent_hash: block.parent_hash.clone(),
+        datetime: block.datetime,
+        miner: block.miner.clone(),
+        gas_used: block.gas_used,
+        gas_limit: block.gas_limit,
+        gas_utilization: if block.gas_limit > 0 { block.gas_used as f64 / block.gas_limit as f64 } else { 0.0 },
+        base_fee_per_gas: block.base_fee_per_gas,
+        transactions: transactions.len() as u64,
+        failed_transactions: receipts.iter().filter(|receipt| !receipt.status).count() as u64,
+        contracts_created: receipts.iter().filter(|receipt| receipt.contract_address.is_some()).count() as u64,
+        logs: receipts.iter().map(|receipt| receipt.logs.len() as u64).sum(),
+        total_value: transactions.iter().fold(primitive_types::U256::zero(), |total, tx| total.saturating_add(tx.value_wei())).to_string(),
+        total_fees: block.total_fees.clone(),
+        burnt_fees: block.burnt_fees.clone(),
+        top_transfers: transfers
+            .into_iter()
+            .take(TOP_TRANSFERS)
+            .map(|tx| ValueTransfer { hash: tx.hash.clone(), from: tx.from.clone(), to: tx.to.clone(), value: tx.value.clone() })
+            .collect(),
+    }
+}
+
+fn eth(wei: &str) -> f64 {
+    wei.parse::<f64>().unwrap_or_default() / WEI_PER_ETH
+}
+
+pub fn render(summary: &BlockSummary) -> String {
+    let mut lines = vec![
+        format!("block             {}", summary.number),
+        format!("hash              {}", summary.hash),
+        format!("parent            {}", summary.parent_hash),
+        format!("time              {}", summary.datetime.to_rfc3339()),
+        format!("miner             {}", summary.miner),
+        format!("gas used          {} of {} ({:.1}%)", summary.gas_used, summary.gas_limit, summary.gas_utilization * 100.0),
+    ];
+    if let Some(base_fee) = summary.base_fee_per_gas {
+        lines.push(format!("base fee          {} wei", base_fee));
+    }
+    lines.push(format!("transactions      {} ({} failed)", summary.transactions, summary.failed_transactions));
+    lines.push(format!("contracts created {}", summary.contracts_created));
+    lines.push(format!("logs              {}", summary.logs));
+    lines.push(format!("value moved       {:.6} {}", eth(&summary.total_value), currency()));
+    if let (Some(total), Some(burnt)) = (&summary.total_fees, &summary.burnt_fees) {
+        lines.push(format!("fees              {0:.6} {1} ({2:.6} {1} burnt)", eth(total), currency(), eth(burnt)));
+    }
+    for transfer in &summary.top_transfers {
+        let to = transfer.to.as_deref().unwrap_or("(contract creation)");
+        lines.push(format!("top transfer      {:.6} {} {} -> {} ({})", eth(&transfer.value), currency(), transfer.from, to, transfer.hash));
+    }
+    lines.join("\n")
+}
+
+pub async fn fetch(id: &str) -> Result<BlockSummary> {
+    let kind = parse_id(id)?;
+    let number = match kind {
+        BlockId::Number(number) => number,
+        BlockId::Hash => rpc::get_block_number_by_hash(id).await?.ok_or_else(|| anyhow::anyhow!("Block {} not found", id))?,
+    };
+    let (block, transactions, receipts) = rpc::fetch_block_data(number).await?;
+    let (block, transactions, receipts) = transform_block_data(&block, &transactions, &receipts);
+    // The block at that height can change between the two calls
+    if kind == BlockId::Hash && !block.hash.eq_ignore_ascii_case(id) {
+        return Err(anyhow::anyhow!("Block {} is no longer canonical; block {} is now {}", id, number, block.hash));
+    }
+    Ok(summarize(&block, &transactions, &receipts))
+}
+
+pub async fn run(id: &str, json: bool) -> Result<()> {
+    let summary = fetch(id).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        println!("{}", render(&summary));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Block, Receipt, Transaction};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_id_and_summarize() {
+        assert_eq!(parse_id("1234").unwrap(), BlockId::Number(1234));
+        assert_eq!(parse_id("0x10").unwrap(), BlockId::Number(16));
+        assert_eq!(parse_id(&format!("0x{}", "ab".repeat(32))).unwrap(), BlockId::Hash);
+        assert!(parse_id("latest").is_err());
+
+        let block: Block = serde_json::from_value(json!({
+            "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x100", "gasUsed": "0x40", "hash": "0xb",
+            "logsBloom": "0x", "miner": "0x00000000000000000000000000000000000000aa", "number": "0x5", "parentHash": "0xa",
+            "receiptsRoot": "0x0", "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0", "timestamp": "0x65920080",
+            "transactions": ["0x1", "0x2", "0x3"], "transactionsRoot": "0x0", "uncles": [], "baseFeePerGas": "0x7",
+        }))
+        .unwrap();
+        let tx = |hash: &str, index: u64, value: &str| -> Transaction {
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x5", "chainId": "0xe708", "from": "0xf", "gas": "0x5208",
+                "gasPrice": "0x1", "hash": hash, "input": "0x", "nonce": "0x0", "r": "0x", "s": "0x", "to": "0xc",
+                "transactionIndex": format!("0x{:x}", index), "type": "0x2", "v": "0x", "value": value,
+            }))
+            .unwrap()
+        };
+        let receipt = |hash: &str, index: u64, status: &str| -> Receipt {
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x5", "contractAddress": null, "cumulativeGasUsed": "0x0",
+                "effectiveGasPrice": "0x1", "from": "0xf", "gasUsed": "0x0", "logs": [], "logsBloom": "0x", "status": status,
+                "to": "0xc", "transactionHash": hash, "transactionIndex": format!("0x{:x}", index), "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let transactions = [tx("0x1", 0, "0x0"), tx("0x2", 1, "0xde0b6b3a7640000"), tx("0x3", 2, "0x29a2241af62c0000")];
+        let receipts = [receipt("0x1", 0, "0x1"), receipt("0x2", 1, "0x0"), receipt("0x3", 2, "0x1")];
+        let (block, transactions, receipts) = transform_block_data(&block, &transactions, &receipts);
+
+        let summary = summarize(&block, &transactions, &receipts);
+        assert_eq!((summary.transactions, summary.failed_transactions), (3, 1));
+        assert_eq!(summary.gas_utilization, 0.25);
+        assert_eq!(summary.total_value, "4000000000000000000");
+        assert_eq!((summary.total_fees.as_deref(), summary.burnt_fees.as_deref()), (Some("0"), Some("0")));
+        let top: Vec<&str> = summary.top_transfers.iter().map(|transfer| transfer.hash.as_str()).collect();
+        assert_eq!(top, vec!["0x3", "0x2"]);
+
+        let text = render(&summary);
+        assert!(text.contains("time              2024-01-01T00:00:00+00:00"));
+        assert!(text.contains("gas used          64 of 256 (25.0%)"));
+        assert!(text.contains("transactions      3 (1 failed)"));
+        assert!(text.contains("top transfer      3.000000 ETH 0xf -> 0xc (0x3)"));
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::bloom::{bloom_bits, bloom_has_bits, compute_bloom};
+use crate::models::{TransformedBlock, TransformedReceipt};
+use crate::store;
+use crate::trie::decode_hex;
+
+#[derive(Debug, Default)]
+pub struct BloomAudit {
+    pub blocks: usize,
+    // Blocks stored without all of their receipts (e.g. indexed with `--hydrate hashes`)
+    pub skipped: usize,
+    pub exact: usize,
+    // Header blooms lacking bits their logs set: `--hydrate bloom` could skip these blocks
+    pub missing_bits: Vec<u64>,
+    // Header blooms setting bits none of their logs set
+    pub extra_bits: Vec<u64>,
+    pub invalid: Vec<u64>,
+    // Receipts whose own bloom disagrees with their logs
+    pub receipt_mismatches: Vec<String>,
+    pub probes: usize,
+    // Probe checks against blocks without the probe in their logs, and how many of those the
+    // header bloom matched anyway
+    pub absent_checks: u64,
+    pub false_positives: u64,
+    // Sum over the audited blocks of (bits set / 2048)^3, the chance an unrelated item matches
+    expected: f64,
+}
+
+impl BloomAudit {
+    pub fn false_positive_rate(&self) -> f64 {
+        self.false_positives as f64 / self.absent_checks.max(1) as f64
+    }
+
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        self.expected / self.blocks.max(1) as f64
+    }
+}
+
+// Addresses and topics of the logs, the items a logs bloom is built from
+fn log_items(logs: &[Value]) -> Vec<Vec<u8>> {
+    logs.iter()
+        .flat_map(|log| {
+            let topics = log["topics"].as_array().into_iter().flatten().filter_map(Value::as_str);
+            log["address"].as_str().into_iter().chain(topics)
+        })
+        .filter_map(|item| decode_hex(item).ok())
+        .collect()
+}
+
+// Rebuilds every block's bloom from its stored logs and compares it with the header, then
+// measures how often the header blooms match probe items their blocks have no log for. Without
+// probes, every log-emitting address of the range is one.
+pub fn compute(blocks: &[TransformedBlock], receipts: &[TransformedReceipt], probes: &[String]) -> BloomAudit {
+    let mut by_block: HashMap<u64, Vec<&TransformedReceipt>> = HashMap::new();
+    for receipt in receipts {
+        by_block.entry(receipt.block_number).or_default().push(receipt);
+    }
+
+    let mut audit = BloomAudit::default();
+    let mut audited: Vec<(Vec<u8>, HashSet<Vec<u8>>)> = Vec::new();
+    for block in blocks {
+        let block_receipts = by_block.remove(&block.number).unwrap_or_default();
+        if block_receipts.len() != block.transaction_hashes.len() {
+            audit.skipped += 1;
+            continue;
+        }
+        let header = match decode_hex(&block.logs_bloom) {
+            Ok(header) if header.len() == 256 => header,
+            _ => {
+                audit.invalid.push(block.number);
+                continue;
+            },
+        };
+        let mut items = HashSet::new();
+        for receipt in block_receipts {
+            let receipt_items = log_items(&receipt.logs);
+            let computed = compute_bloom(receipt_items.iter().map(Vec::as_slice));
+            if decode_hex(&receipt.logs_bloom).ok().as_deref() != Some(computed.as_slice()) {
+                audit.receipt_mismatches.push(receipt.transaction_hash.clone());
+            }
+            items.extend(receipt_items);
+        }
+        let computed = compute_bloom(items.iter().map(Vec::as_slice));
+        let missing = computed.iter().zip(&header).any(|(c, h)| c & !h != 0);
+        let extra = computed.iter().zip(&header).any(|(c, h)| h & !c != 0);
+        if missing {
+            audit.missing_bits.push(block.number);
+        }
+        if extra {
+            audit.extra_bits.push(block.number);
+        }
+        if !missing && !extra {
+            audit.exact += 1;
+        }
+        let fill = header.iter().map(|byte| byte.count_ones()).sum::<u32>() as f64 / 2048.0;
+        audit.expected += fill.powi(3);
+        audit.blocks += 1;
+        audited.push((header, items));
+    }
+
+    let probes: BTreeSet<Vec<u8>> = if probes.is_empty() {
+        receipts
+            .iter()
+            .flat_map(|receipt| &receipt.logs)
+            .filter_map(|log| log["address"].as_str())
+            .filter_map(|address| decode_hex(address).ok())
+            .collect()
+    } else {
+        probes.iter().filter_map(|probe| decode_hex(probe).ok()).collect()
+    };
+    audit.probes = probes.len();
+    for probe in &probes {
+        let bits = bloom_bits(probe);
+        for (header, items) in &audited {
+            if !items.contains(probe) {
+                audit.absent_checks += 1;
+                audit.false_positives += bloom_has_bits(header, &bits) as u64;
+            }
+        }
+    }
+    audit
+}
+
+// Audits the stored blocks of a range, failing when a header bloom misses bits of its own logs
+pub fn run(raw_data_path: &str, first: u64, last: u64, probes: &[String]) -> Result<()> {
+    let blocks: Vec<TransformedBlock> = store::read_range(raw_data_path, "blocks", first, last)?;
+    if blocks.is_empty() {
+        return Err(anyhow::anyhow!("No indexed blocks found between {} and {} in {}", first, last, raw_data_path));
+    }
+    let receipts: Vec<TransformedReceipt> = store::read_range(raw_data_path, "receipts", first, last)?;
+    let audit = compute(&blocks, &receipts, probes);
+
+    for number in &audit.missing_bits {
+        println!("Block {}: header logsBloom lacks bits set by its logs", number);
+    }
+    for number in &audit.extra_bits {
+        println!("Block {}: header logsBloom sets bits none of its logs set", number);
+    }
+    for number in &audit.invalid {
+        println!("Block {}: header logsBloom is not 256 bytes of hex", number);
+    }
+    for hash in &audit.receipt_mismatches {
+        println!("Receipt {}: logsBloom does not match its logs", hash);
+    }
+    println!(
+        "Audited {} blocks ({} without all receipts skipped): {} exact, {} missing bits, {} extra bits, {} invalid; {} receipt mismatches",
+        audit.blocks,
+        audit.skipped,
+        audit.exact,
+        audit.missing_bits.len(),
+        audit.extra_bits.len(),
+        audit.invalid.len(),
+        audit.receipt_mismatches.len()
+    );
+    println!(
+        "False positives: {} of {} checks of {} probes ({:.4}%, expected {:.4}% from bloom fill)",
+        audit.false_positives,
+        audit.absent_checks,
+        audit.probes,
+        audit.false_positive_rate() * 100.0,
+        audit.expected_false_positive_rate() * 100.0
+    );
+
+    if !audit.missing_bits.is_empty() {
+        return Err(anyhow::anyhow!("Header blooms miss logged items in blocks {:?}", audit.missing_bits));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::trie::encode_hex;
+    use serde_json::json;
+
+    #[test]
+    fn test_audit() {
+        let token = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        let transfer = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+        let bloom_of = |items: &[&str]| {
+            let items: Vec<Vec<u8>> = items.iter().map(|item| decode_hex(item).unwrap()).collect();
+            encode_hex(&compute_bloom(items.iter().map(Vec::as_slice)))
+        };
+        let block = |number: u64, bloom: &str, hashes: &[&str]| TransformedBlock {
+            logs_bloom: bloom.to_string(),
+            transaction_hashes: hashes.iter().map(|hash| hash.to_string()).collect(),
+            ..fixtures::block(number)
+        };
+        let receipt = |number: u64, hash: &str, logs: Value, bloom: &str| TransformedReceipt {
+            logs: serde_json::from_value(logs).unwrap(),
+            logs_bloom: bloom.to_string(),
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(number, 0)
+        };
+        let logs = json!([{ "address": token, "topics": [transfer] }]);
+        let good = bloom_of(&[token, transfer]);
+        let blocks = vec![
+            block(1, &good, &["0x1"]),
+            // The header lacks the topic's bits
+            block(2, &bloom_of(&[token]), &["0x2"]),
+            block(3, &bloom_of(&[]), &[]),
+            // Receipts never stored
+            block(4, &good, &["0x4"]),
+        ];
+        let receipts = vec![receipt(1, "0x1", logs.clone(), &good), receipt(2, "0x2", logs, "0x")];
+
+        let audit = compute(&blocks, &receipts, &[]);
+        assert_eq!((audit.blocks, audit.skipped, audit.exact), (3, 1, 2));
+        assert_eq!(audit.missing_bits, vec![2]);
+        assert!(audit.extra_bits.is_empty());
+        assert_eq!(audit.receipt_mismatches, vec!["0x2"]);
+        // The token probe is absent only from block 3, whose empty bloom cannot match
+        assert_eq!((audit.probes, audit.absent_checks, audit.false_positives), (1, 1, 0));
+
+        // Probes are matched case-insensitively; only the unrelated one counts as a check
+        let audit = compute(&blocks[..1], &receipts, &[token.to_uppercase().replace("0X", "0x"), "0x01".to_string()]);
+        assert_eq!(audit.probes, 2);
+        assert_eq!(audit.absent_checks, 1);
+        assert!(audit.expected_false_positive_rate() > 0.0);
+    }
+}
@@ -0,0 +1,563 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::models::{TransformedBlock, TransformedContract, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::selectors;
+use crate::sinks::clickhouse::ClickHouseSink;
+use crate::store::{self, OutputConfig, OutputDirs};
+use crate::transform::{extract_bridge_messages, extract_logs, extract_nft_transfers, extract_token_transfers, hex_to_decimal, init_code_hash, row_id};
+
+// Rows written per batch while streaming through a dataset
+const BATCH_SIZE: usize = 10_000;
+
+// Blocks per spilled file of transaction summaries, and how many of those files are kept in memory
+const SPILL_BLOCKS: u64 = 1_000;
+const SPILL_CACHE: usize = 2;
+
+type Row = Map<String, Value>;
+
+// Public exports (e.g. ethereum-etl / BigQuery `crypto_ethereum`) and our own output
+// use different column names, so each field is looked up through a list of aliases.
+fn lookup<'a>(row: &'a Row, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().filter_map(|key| row.get(*key)).find(|value| !value.is_null())
+}
+
+fn text(row: &Row, keys: &[&str]) -> Option<String> {
+    match lookup(row, keys)? {
+        Value::String(s) if s.is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn text_or_empty(row: &Row, keys: &[&str]) -> String {
+    text(row, keys).unwrap_or_default()
+}
+
+// Accepts decimal and 0x-prefixed hex, like the RPC-derived values
+fn number(row: &Row, keys: &[&str]) -> Option<u64> {
+    match lookup(row, keys)? {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)),
+        Value::String(s) if s.starts_with("0x") => u64::from_str_radix(&s[2..], 16).ok(),
+        Value::String(s) => s.trim().parse::<u64>().ok().or_else(|| s.trim().parse::<f64>().ok().map(|f| f as u64)),
+        Value::Bool(b) => Some(*b as u64),
+        _ => None,
+    }
+}
+
+// Wei amounts overflow u64 and are kept as decimal strings; hex is converted, fractions dropped
+fn decimal(row: &Row, keys: &[&str]) -> String {
+    match text(row, keys) {
+        Some(s) if s.starts_with("0x") => hex_to_decimal(&s),
+        Some(s) => s.split('.').next().filter(|digits| !digits.is_empty()).unwrap_or("0").to_string(),
+        None => "0".to_string(),
+    }
+}
+
+fn number_or_zero(row: &Row, keys: &[&str]) -> u64 {
+    number(row, keys).unwrap_or(0)
+}
+
+fn parse_datetime(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(n) => Utc.timestamp_opt(n.as_i64()?, 0).single(),
+        Value::String(s) => {
+            let s = s.trim();
+            if let Ok(ts) = s.parse::<i64>() {
+                return Utc.timestamp_opt(ts, 0).single();
+            }
+            if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+                return Some(dt.with_timezone(&Utc));
+            }
+            let naive = s.trim_end_matches(" UTC");
+            NaiveDateTime::parse_from_str(naive, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|dt| Utc.from_utc_datetime(&dt))
+        },
+        _ => None,
+    }
+}
+
+fn datetime(row: &Row, keys: &[&str]) -> DateTime<Utc> {
+    lookup(row, keys).and_then(parse_datetime).unwrap_or_default()
+}
+
+fn string_list(row: &Row, keys: &[&str]) -> Vec<String> {
+    match lookup(row, keys) {
+        Some(Value::Array(items)) => items.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        Some(Value::String(s)) => serde_json::from_str::<Vec<String>>(s).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+const BLOCK_NUMBER: &[&str] = &["block_number", "blockNumber"];
+const TIMESTAMP: &[&str] = &["datetime", "block_timestamp", "timestamp"];
+
+pub fn block_from_row(row: &Row, transaction_hashes: Vec<String>) -> TransformedBlock {
+    let listed = string_list(row, &["transaction_hashes"]);
+    TransformedBlock {
+        base_fee_per_gas: number(row, &["base_fee_per_gas", "baseFeePerGas"]),
+        difficulty: number_or_zero(row, &["difficulty"]),
+        extra_data: text_or_empty(row, &["extra_data", "extraData"]),
+        gas_limit: number_or_zero(row, &["gas_limit", "gasLimit"]),
+        gas_used: number_or_zero(row, &["gas_used", "gasUsed"]),
+        hash: text_or_empty(row, &["hash"]),
+        logs_bloom: text_or_empty(row, &["logs_bloom", "logsBloom"]),
+        miner: text_or_empty(row, &["miner"]),
+        mix_hash: text_or_empty(row, &["mix_hash", "mixHash"]),
+        nonce: text_or_empty(row, &["nonce"]),
+        number: number_or_zero(row, &["number"]),
+        parent_hash: text_or_empty(row, &["parent_hash", "parentHash"]),
+        receipts_root: text_or_empty(row, &["receipts_root", "receiptsRoot"]),
+        sha3_uncles: text_or_empty(row, &["sha3_uncles", "sha3Uncles"]),
+        size: number_or_zero(row, &["size"]),
+        state_root: text_or_empty(row, &["state_root", "stateRoot"]),
+        datetime: datetime(row, TIMESTAMP),
+        total_difficulty: number_or_zero(row, &["total_difficulty", "totalDifficulty"]),
+        transaction_hashes: if listed.is_empty() { transaction_hashes } else { listed },
+        transactions_root: text_or_empty(row, &["transactions_root", "transactionsRoot"]),
+        uncles: string_list(row, &["uncles"]),
+        total_fees: None,
+        burnt_fees: None,
+        base_fee_per_gas_gwei: None,
+        total_fees_ether: None,
+        burnt_fees_ether: None,
+        extra: Default::default(),
+    }
+}
+
+pub fn transaction_from_row(row: &Row, chain_id: u64) -> TransformedTransaction {
+    let input = text_or_empty(row, &["input"]);
+    let method_id = selectors::method_id(&input);
+    let (block_number, transaction_index) = (number_or_zero(row, BLOCK_NUMBER), number_or_zero(row, &["transaction_index", "transactionIndex"]));
+    TransformedTransaction {
+        id: row_id(block_number, transaction_index),
+        block_hash: text_or_empty(row, &["block_hash", "blockHash"]),
+        block_number,
+        chain_id: number(row, &["chain_id", "chainId"]).unwrap_or(chain_id),
+        from: text_or_empty(row, &["from", "from_address"]),
+        gas: number_or_zero(row, &["gas"]),
+        gas_price: number_or_zero(row, &["gas_price", "gasPrice"]),
+        hash: text_or_empty(row, &["hash", "transaction_hash"]),
+        method_signature: method_id.as_deref().and_then(selectors::lookup),
+        method_id,
+        input,
+        nonce: number_or_zero(row, &["nonce"]),
+        r: text_or_empty(row, &["r"]),
+        s: text_or_empty(row, &["s"]),
+        to: text(row, &["to", "to_address"]),
+        transaction_index,
+        tx_type: number_or_zero(row, &["tx_type", "transaction_type", "type"]),
+        v: text_or_empty(row, &["v"]),
+        value: decimal(row, &["value"]),
+        // Exports that join receipts into transactions (e.g. the BigQuery public datasets)
+        status: number(row, &["status", "receipt_status"]).map(|status| status == 1),
+        gas_used: number(row, &["gas_used", "receipt_gas_used"]),
+        effective_gas_price: number(row, &["effective_gas_price", "receipt_effective_gas_price"]),
+        contract_address: text(row, &["contract_address", "receipt_contract_address"]),
+        fee: None,
+        burnt_fee: None,
+        priority_fee: None,
+        value_ether: None,
+        gas_price_gwei: None,
+        effective_gas_price_gwei: None,
+        fee_ether: None,
+        burnt_fee_ether: None,
+        priority_fee_ether: None,
+        datetime: datetime(row, TIMESTAMP),
+        extra: Default::default(),
+    }
+}
+
+// Receipt exports rarely carry from/to/type, those are joined from the transactions dataset
+pub fn receipt_from_row(row: &Row, tx: Option<&TxSummary>) -> TransformedReceipt {
+    let logs = match lookup(row, &["logs"]) {
+        Some(Value::Array(logs)) => logs.clone(),
+        Some(Value::String(s)) => serde_json::from_str(s).unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    let (block_number, transaction_index) = (number_or_zero(row, BLOCK_NUMBER), number_or_zero(row, &["transaction_index", "transactionIndex"]));
+    TransformedReceipt {
+        id: row_id(block_number, transaction_index),
+        block_hash: text_or_empty(row, &["block_hash", "blockHash"]),
+        block_number,
+        contract_address: text(row, &["contract_address", "contractAddress"]),
+        cumulative_gas_used: number_or_zero(row, &["cumulative_gas_used", "cumulativeGasUsed"]),
+        effective_gas_price: number_or_zero(row, &["effective_gas_price", "effectiveGasPrice"]),
+        from: text(row, &["from", "from_address"]).or_else(|| tx.map(|tx| tx.from.clone())).unwrap_or_default(),
+        gas_used: number_or_zero(row, &["gas_used", "gasUsed"]),
+        logs,
+        logs_bloom: text_or_empty(row, &["logs_bloom", "logsBloom"]),
+        status: number_or_zero(row, &["status"]) == 1,
+        to: text(row, &["to", "to_address"]).or_else(|| tx.and_then(|tx| tx.to.clone())),
+        transaction_hash: text_or_empty(row, &["transaction_hash", "transactionHash", "hash"]),
+        transaction_index,
+        tx_type: number(row, &["tx_type", "transaction_type", "type"]).or(tx.map(|tx| tx.tx_type)).unwrap_or(0),
+        datetime: tx.map(|tx| tx.datetime).unwrap_or_else(|| datetime(row, TIMESTAMP)),
+        extra: Default::default(),
+    }
+}
+
+// What later datasets need to know about a transaction
+#[derive(Serialize, Deserialize)]
+pub struct TxSummary {
+    from: String,
+    to: Option<String>,
+    tx_type: u64,
+    datetime: DateTime<Utc>,
+    // Only kept for contract creations
+    init_code_hash: Option<String>,
+}
+
+impl TxSummary {
+    fn of(tx: &TransformedTransaction) -> Self {
+        TxSummary {
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+            tx_type: tx.tx_type,
+            datetime: tx.datetime,
+            init_code_hash: tx.to.is_none().then(|| init_code_hash(&tx.input)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpilledTx {
+    block_number: u64,
+    transaction_index: u64,
+    hash: String,
+    summary: TxSummary,
+}
+
+// The transactions of SPILL_BLOCKS blocks, as receipts and blocks look them up
+#[derive(Default)]
+struct SpillBucket {
+    summaries: HashMap<String, TxSummary>,
+    hashes: HashMap<u64, BTreeMap<u64, String>>,
+}
+
+// Transaction summaries written to disk by block range while the transactions are read, so the
+// receipts and blocks passes only hold the ranges they are at. Exports ordered by block read each
+// range once; the scratch directory is removed when the spill is dropped.
+struct TxSpill {
+    dir: PathBuf,
+    cache: VecDeque<(u64, SpillBucket)>,
+}
+
+impl TxSpill {
+    fn create(root: &str) -> Result<Self> {
+        let dir = Path::new(root).join(format!(".bootstrap-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        Ok(TxSpill { dir, cache: VecDeque::new() })
+    }
+
+    fn bucket_path(&self, bucket: u64) -> PathBuf {
+        self.dir.join(format!("{}.ndjson", bucket))
+    }
+
+    fn add(&mut self, transactions: &[TransformedTransaction]) -> Result<()> {
+        let mut buckets: BTreeMap<u64, Vec<&TransformedTransaction>> = BTreeMap::new();
+        for tx in transactions {
+            buckets.entry(tx.block_number / SPILL_BLOCKS).or_default().push(tx);
+        }
+        for (bucket, transactions) in buckets {
+            let file = fs::OpenOptions::new().create(true).append(true).open(self.bucket_path(bucket))?;
+            let mut writer = BufWriter::new(file);
+            for tx in transactions {
+                let spilled = SpilledTx { block_number: tx.block_number, transaction_index: tx.transaction_index, hash: tx.hash.clone(), summary: TxSummary::of(tx) };
+                serde_json::to_writer(&mut writer, &spilled)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    // The range holding `block_number`, read from disk unless it is cached
+    fn bucket(&mut self, block_number: u64) -> Result<&mut SpillBucket> {
+        let bucket = block_number / SPILL_BLOCKS;
+        if let Some(position) = self.cache.iter().position(|(cached, _)| *cached == bucket) {
+            return Ok(&mut self.cache[position].1);
+        }
+        let mut loaded = SpillBucket::default();
+        let path = self.bucket_path(bucket);
+        if path.exists() {
+            for line in BufReader::new(fs::File::open(&path)?).lines() {
+                let spilled: SpilledTx = serde_json::from_str(&line?)?;
+                loaded.hashes.entry(spilled.block_number).or_default().insert(spilled.transaction_index, spilled.hash.clone());
+                loaded.summaries.insert(spilled.hash, spilled.summary);
+            }
+        }
+        if self.cache.len() >= SPILL_CACHE {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((bucket, loaded));
+        Ok(&mut self.cache.back_mut().expect("just pushed").1)
+    }
+
+    fn summary(&mut self, block_number: u64, hash: &str) -> Result<Option<&TxSummary>> {
+        Ok(self.bucket(block_number)?.summaries.get(hash))
+    }
+
+    // Hashes of a block's transactions in index order, pruned once the block has them
+    fn take_hashes(&mut self, block_number: u64) -> Result<Vec<String>> {
+        let hashes = self.bucket(block_number)?.hashes.remove(&block_number);
+        Ok(hashes.map(|hashes| hashes.into_values().collect()).unwrap_or_default())
+    }
+}
+
+impl Drop for TxSpill {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn contract_from_receipt(receipt: &TransformedReceipt, tx: &TxSummary) -> Option<TransformedContract> {
+    Some(TransformedContract {
+        address: receipt.contract_address.clone()?,
+        creator: tx.from.clone(),
+        transaction_hash: receipt.transaction_hash.clone(),
+        block_number: receipt.block_number,
+        init_code_hash: tx.init_code_hash.clone()?,
+        datetime: tx.datetime,
+    })
+}
+
+fn read_csv(path: &Path, mut on_row: impl FnMut(Row) -> Result<()>) -> Result<()> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    for record in reader.records() {
+        let record = record?;
+        let row: Row = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+        on_row(row)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &Path, mut on_row: impl FnMut(Row) -> Result<()>) -> Result<()> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let reader = SerializedFileReader::new(std::fs::File::open(path)?)?;
+    for row in reader.get_row_iter(None)? {
+        if let Value::Object(row) = row?.to_json_value() {
+            on_row(row)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(path: &Path, _on_row: impl FnMut(Row) -> Result<()>) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Cannot read {}: this build was compiled without the `parquet` feature",
+        path.display()
+    ))
+}
+
+fn read_rows(path: &Path, on_row: impl FnMut(Row) -> Result<()>) -> Result<()> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => read_csv(path, on_row),
+        Some("parquet") => read_parquet(path, on_row),
+        _ => Err(anyhow::anyhow!("Unsupported dataset format: {} (expected .csv or .parquet)", path.display())),
+    }
+}
+
+// Reads a dataset on the blocking pool and hands it over in batches, so large exports are never fully in memory
+fn spawn_reader(path: PathBuf) -> (mpsc::Receiver<Vec<Row>>, JoinHandle<Result<()>>) {
+    let (sender, receiver) = mpsc::channel(2);
+    let reader = tokio::task::spawn_blocking(move || {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        read_rows(&path, |row| {
+            batch.push(row);
+            if batch.len() >= BATCH_SIZE {
+                sender
+                    .blocking_send(std::mem::take(&mut batch))
+                    .map_err(|_| anyhow::anyhow!("Bootstrap writer stopped"))?;
+            }
+            Ok(())
+        })?;
+        if !batch.is_empty() {
+            sender
+                .blocking_send(batch)
+                .map_err(|_| anyhow::anyhow!("Bootstrap writer stopped"))?;
+        }
+        Ok(())
+    });
+    (receiver, reader)
+}
+
+pub struct BootstrapInput<'a> {
+    pub blocks: &'a Path,
+    pub transactions: Option<&'a Path>,
+    pub receipts: Option<&'a Path>,
+    pub up_to: u64,
+    pub chain_id: u64,
+}
+
+// Streams the datasets into storage in batches; returns the highest block ingested
+pub async fn ingest(
+    input: BootstrapInput<'_>,
+    dirs: &OutputDirs,
+    output: OutputConfig,
+    clickhouse: Option<&ClickHouseSink>,
+    write_concurrency: usize,
+) -> Result<Option<u64>> {
+    let mut spill = TxSpill::create(&dirs.root)?;
+
+    if let Some(path) = input.transactions {
+        log::info!("Bootstrapping transactions from {}", path.display());
+        let (mut batches, reader) = spawn_reader(path.to_path_buf());
+        let mut total = 0;
+        while let Some(rows) = batches.recv().await {
+            let transactions: Vec<TransformedTransaction> = rows
+                .iter()
+                .map(|row| transaction_from_row(row, input.chain_id))
+                .filter(|tx| tx.block_number <= input.up_to)
+                .collect();
+            spill.add(&transactions)?;
+            total += transactions.len();
+            let batch = TransformedBatch { transactions, ..Default::default() };
+            store::persist(dirs, output, clickhouse, &batch, write_concurrency).await?;
+        }
+        reader.await??;
+        log::info!("Bootstrapped {} transactions", total);
+    }
+
+    if let Some(path) = input.receipts {
+        log::info!("Bootstrapping receipts from {}", path.display());
+        let (mut batches, reader) = spawn_reader(path.to_path_buf());
+        let (mut total, mut contracts) = (0, 0);
+        while let Some(rows) = batches.recv().await {
+            let (mut receipts, mut created) = (Vec::new(), Vec::new());
+            for row in &rows {
+                let hash = text_or_empty(row, &["transaction_hash", "transactionHash", "hash"]);
+                let tx = spill.summary(number_or_zero(row, BLOCK_NUMBER), &hash)?;
+                let receipt = receipt_from_row(row, tx);
+                if receipt.block_number > input.up_to {
+                    continue;
+                }
+                created.extend(tx.and_then(|tx| contract_from_receipt(&receipt, tx)));
+                receipts.push(receipt);
+            }
+            total += receipts.len();
+            contracts += created.len();
+            let logs = extract_logs(&receipts);
+            let nft_transfers = extract_nft_transfers(&receipts);
+            let token_transfers = extract_token_transfers(&receipts);
+            let bridge_messages = extract_bridge_messages(&receipts);
+            let batch = TransformedBatch { receipts, logs, contracts: created, nft_transfers, token_transfers, bridge_messages, ..Default::default() };
+            store::persist(dirs, output, clickhouse, &batch, write_concurrency).await?;
+        }
+        reader.await??;
+        log::info!("Bootstrapped {} receipts and {} contracts", total, contracts);
+    }
+
+    log::info!("Bootstrapping blocks from {}", input.blocks.display());
+    let (mut batches, reader) = spawn_reader(input.blocks.to_path_buf());
+    let mut highest = None;
+    let mut total = 0;
+    while let Some(rows) = batches.recv().await {
+        let mut blocks: Vec<TransformedBlock> = Vec::new();
+        for row in rows.iter().filter(|row| number_or_zero(row, &["number"]) <= input.up_to) {
+            let number = number_or_zero(row, &["number"]);
+            blocks.push(block_from_row(row, spill.take_hashes(number)?));
+        }
+        highest = blocks.iter().map(|b| b.number).max().max(highest);
+        total += blocks.len();
+        let batch = TransformedBatch { blocks, ..Default::default() };
+        store::persist(dirs, output, clickhouse, &batch, write_concurrency).await?;
+    }
+    reader.await??;
+    log::info!("Bootstrapped {} blocks", total);
+
+    Ok(highest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn row(value: Value) -> Row {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_block_from_ethereum_etl_row() {
+        let block = block_from_row(
+            &row(json!({
+                "number": "46147",
+                "hash": "0x4e3a",
+                "gas_used": "21000",
+                "base_fee_per_gas": "",
+                "timestamp": "2015-08-07 03:30:33 UTC"
+            })),
+            vec!["0xtx".to_string()],
+        );
+        assert_eq!(block.number, 46147);
+        assert_eq!(block.gas_used, 21000);
+        assert_eq!(block.base_fee_per_gas, None);
+        assert_eq!(block.datetime, Utc.with_ymd_and_hms(2015, 8, 7, 3, 30, 33).unwrap());
+        assert_eq!(block.transaction_hashes, vec!["0xtx".to_string()]);
+    }
+
+    #[test]
+    fn test_receipt_joins_transaction_fields() {
+        let tx = transaction_from_row(
+            &row(json!({"hash": "0xaa", "from_address": "0x1", "to_address": "0x2", "transaction_type": "2", "block_timestamp": "1438918233"})),
+            59141,
+        );
+        assert_eq!(tx.chain_id, 59141);
+        assert_eq!(tx.to.as_deref(), Some("0x2"));
+
+        let summary = TxSummary::of(&tx);
+        let receipt = receipt_from_row(&row(json!({"transaction_hash": "0xaa", "status": "1", "gas_used": "0x5208", "block_number": "12", "transaction_index": "3"})), Some(&summary));
+        assert!(receipt.status);
+        assert_eq!(receipt.gas_used, 21000);
+        assert_eq!(receipt.from, "0x1");
+        assert_eq!(receipt.tx_type, 2);
+        assert_eq!(receipt.id, 12_000_003);
+        assert_eq!(receipt.datetime, Utc.timestamp_opt(1438918233, 0).unwrap());
+    }
+
+    #[test]
+    fn test_spill_joins_by_block_range() {
+        let root = "test_bootstrap_spill";
+        let _ = fs::remove_dir_all(root);
+        let mut spill = TxSpill::create(root).unwrap();
+        let far = 5 * SPILL_BLOCKS;
+        // Written over two batches, out of order, across three ranges
+        spill.add(&[fixtures::transaction(7, 1), fixtures::transaction(far, 0)]).unwrap();
+        spill.add(&[fixtures::transaction(7, 0), fixtures::transaction(SPILL_BLOCKS, 0)]).unwrap();
+
+        assert_eq!(spill.summary(7, &fixtures::transaction_hash(7, 1)).unwrap().unwrap().from, "0xf");
+        assert!(spill.summary(SPILL_BLOCKS, &fixtures::transaction_hash(7, 1)).unwrap().is_none());
+        assert_eq!(spill.take_hashes(7).unwrap(), vec![fixtures::transaction_hash(7, 0), fixtures::transaction_hash(7, 1)]);
+        assert!(spill.take_hashes(7).unwrap().is_empty());
+        // Only SPILL_CACHE ranges stay in memory; an evicted one is read again
+        spill.take_hashes(SPILL_BLOCKS).unwrap();
+        assert_eq!(spill.take_hashes(far).unwrap(), vec![fixtures::transaction_hash(far, 0)]);
+        assert_eq!(spill.cache.len(), SPILL_CACHE);
+        assert_eq!(spill.take_hashes(7).unwrap().len(), 2);
+
+        let dir = spill.dir.clone();
+        drop(spill);
+        assert!(!dir.exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+//! Data dictionary for `describe`: every field of every written dataset with its type, the RPC
+//! field or computation it comes from and the transform applied. Types are the ClickHouse column
+//! types the sinks, Avro and Flight schemas are built from; the test keeps the entries below in
+//! step with those columns, so a new field cannot ship undocumented.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::sinks::clickhouse::columns_for;
+
+// Transforms shared by many fields
+const COPIED: &str = "as returned";
+const HEX_INT: &str = "hex quantity to integer";
+const HEX_DECIMAL: &str = "hex quantity to decimal string (256 bits)";
+const BLOCK_TIME: &str = "block timestamp (unix seconds) to UTC time";
+const FROM_RECEIPT: &str = "copied from the receipt";
+
+// (dataset, summary, [(field, source, transform)])
+type Entry = (&'static str, &'static str, &'static [(&'static str, &'static str, &'static str)]);
+
+const DICTIONARY: &[Entry] = &[
+    (
+        "blocks",
+        "One row per block header (eth_getBlockByNumber)",
+        &[
+            ("base_fee_per_gas", "baseFeePerGas", "hex quantity to integer, null before London"),
+            ("difficulty", "difficulty", HEX_INT),
+            ("extra_data", "extraData", COPIED),
+            ("gas_limit", "gasLimit", HEX_INT),
+            ("gas_used", "gasUsed", HEX_INT),
+            ("hash", "hash", COPIED),
+            ("logs_bloom", "logsBloom", COPIED),
+            ("miner", "miner", COPIED),
+            ("mix_hash", "mixHash", COPIED),
+            ("nonce", "nonce", COPIED),
+            ("number", "number", HEX_INT),
+            ("parent_hash", "parentHash", COPIED),
+            ("receipts_root", "receiptsRoot", COPIED),
+            ("sha3_uncles", "sha3Uncles", COPIED),
+            ("size", "size", HEX_INT),
+            ("state_root", "stateRoot", COPIED),
+            ("datetime", "timestamp", BLOCK_TIME),
+            ("total_difficulty", "totalDifficulty", "hex quantity to integer, 0 when the node omits it"),
+            ("transaction_hashes", "transactions", "hashes of the block's transactions, in block order"),
+            ("transactions_root", "transactionsRoot", COPIED),
+            ("uncles", "uncles", COPIED),
+            ("total_fees", "receipts", "sum of fee in wei over every transaction, filtered out ones included; null unless all have receipts"),
+            ("burnt_fees", "receipts", "sum of burnt_fee in wei over every transaction, filtered out ones included; null unless all have receipts"),
+            ("base_fee_per_gas_gwei", "baseFeePerGas", "base_fee_per_gas in gwei with --native-units, null otherwise"),
+            ("total_fees_ether", "transactions", "total_fees in ether with --native-units, null otherwise"),
+            ("burnt_fees_ether", "transactions", "burnt_fees in ether with --native-units, null otherwise"),
+        ],
+    ),
+    (
+        "transactions",
+        "One row per transaction (eth_getBlockByNumber with full transactions)",
+        &[
+            ("id", "block number, transactionIndex", "block_number * 1000000 + transaction_index"),
+            ("block_hash", "blockHash", COPIED),
+            ("block_number", "blockNumber", HEX_INT),
+            ("chain_id", "chainId", "hex quantity to integer, 0 for pre-EIP-155 transactions"),
+            ("from", "from", COPIED),
+            ("gas", "gas", HEX_INT),
+            ("gas_price", "gasPrice", HEX_INT),
+            ("hash", "hash", COPIED),
+            ("input", "input", COPIED),
+            ("method_id", "input", "first four bytes as a lowercase selector, null for shorter input"),
+            ("method_signature", "input", "selector looked up in the bundled signature database"),
+            ("nonce", "nonce", HEX_INT),
+            ("r", "r", COPIED),
+            ("s", "s", COPIED),
+            ("to", "to", "as returned, null for contract creations"),
+            ("transaction_index", "transactionIndex", HEX_INT),
+            ("tx_type", "type", HEX_INT),
+            ("v", "v", COPIED),
+            ("value", "value", HEX_DECIMAL),
+            ("status", "receipt status", "0x1 to true, anything else to false; null without a receipt"),
+            ("gas_used", "receipt gasUsed", "hex quantity to integer, null without a receipt"),
+            ("effective_gas_price", "receipt effectiveGasPrice", "hex quantity to integer, null without a receipt"),
+            ("contract_address", "receipt contractAddress", "set for contract creations, null otherwise"),
+            ("fee", "receipt gasUsed, effectiveGasPrice", "gas_used * effective_gas_price in wei, null without a receipt"),
+            ("burnt_fee", "receipt gasUsed, block baseFeePerGas", "gas_used * base fee (at most the gas price) in wei, 0 before London"),
+            ("priority_fee", "receipt gasUsed, effectiveGasPrice", "fee - burnt_fee in wei"),
+            ("value_ether", "value", "value in ether with --native-units, null otherwise"),
+            ("gas_price_gwei", "gasPrice", "gas_price in gwei with --native-units, null otherwise"),
+            ("effective_gas_price_gwei", "receipt effectiveGasPrice", "effective_gas_price in gwei with --native-units, null otherwise"),
+            ("fee_ether", "receipt gasUsed, effectiveGasPrice", "fee in ether with --native-units, null otherwise"),
+            ("burnt_fee_ether", "receipt gasUsed, block baseFeePerGas", "burnt_fee in ether with --native-units, null otherwise"),
+            ("priority_fee_ether", "receipt gasUsed, effectiveGasPrice", "priority_fee in ether with --native-units, null otherwise"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "receipts",
+        "One row per transaction receipt (eth_getBlockReceipts or eth_getTransactionReceipt)",
+        &[
+            ("id", "block number, transactionIndex", "block_number * 1000000 + transaction_index"),
+            ("block_hash", "blockHash", COPIED),
+            ("block_number", "blockNumber", HEX_INT),
+            ("contract_address", "contractAddress", "as returned, set for contract creations"),
+            ("cumulative_gas_used", "cumulativeGasUsed", HEX_INT),
+            ("effective_gas_price", "effectiveGasPrice", HEX_INT),
+            ("from", "from", COPIED),
+            ("gas_used", "gasUsed", HEX_INT),
+            ("logs", "logs", "stored empty unless --embed-receipt-logs, readers rebuild it from the logs dataset; each log with topic0, event_signature and its decoded indexed topics added"),
+            ("logs_bloom", "logsBloom", COPIED),
+            ("status", "status", "0x1 to true, anything else to false"),
+            ("to", "to", COPIED),
+            ("transaction_hash", "transactionHash", COPIED),
+            ("transaction_index", "transactionIndex", HEX_INT),
+            ("tx_type", "type", HEX_INT),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "logs",
+        "One row per receipt log, linked to its transaction by transaction_hash and log_index",
+        &[
+            ("id", "block number, log logIndex", "block_number * 1000000 + log_index"),
+            ("block_number", "receipt", FROM_RECEIPT),
+            ("block_hash", "receipt", FROM_RECEIPT),
+            ("transaction_hash", "receipt", FROM_RECEIPT),
+            ("transaction_index", "receipt", FROM_RECEIPT),
+            ("log_index", "log logIndex", HEX_INT),
+            ("address", "log address", COPIED),
+            ("topic0", "log topics[0]", "event signature hash, null for anonymous events"),
+            ("topic1", "log topics[1]", "null when the log has fewer topics"),
+            ("topic2", "log topics[2]", "null when the log has fewer topics"),
+            ("topic3", "log topics[3]", "null when the log has fewer topics"),
+            ("data", "log data", COPIED),
+            ("event_signature", "topic0", "known signature of topic0, null otherwise"),
+            ("removed", "log removed", "false when missing"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "contracts",
+        "Contracts deployed by top-level creation transactions",
+        &[
+            ("address", "receipt contractAddress", COPIED),
+            ("creator", "transaction from", COPIED),
+            ("transaction_hash", "transaction hash", COPIED),
+            ("block_number", "transaction blockNumber", HEX_INT),
+            ("init_code_hash", "transaction input", "keccak256 of the creation input"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "data_usage",
+        "Calldata and blob usage per block, for data availability cost modelling",
+        &[
+            ("block_number", "block number", HEX_INT),
+            ("datetime", "block timestamp", BLOCK_TIME),
+            ("size", "block size", HEX_INT),
+            ("gas_used", "block gasUsed", HEX_INT),
+            ("transaction_count", "block transactions", "count"),
+            ("calldata_bytes", "transaction input", "bytes summed over the block"),
+            ("calldata_zero_bytes", "transaction input", "zero bytes summed over the block"),
+            ("calldata_nonzero_bytes", "transaction input", "non-zero bytes summed over the block"),
+            ("calldata_gas", "transaction input", "4 gas per zero byte plus 16 per non-zero byte (EIP-2028)"),
+            ("blob_transactions", "transaction blobVersionedHashes", "transactions carrying blobs"),
+            ("blob_count", "transaction blobVersionedHashes", "blobs summed over the block"),
+            ("blob_gas_used", "block blobGasUsed", "hex quantity to integer, null before Cancun"),
+            ("excess_blob_gas", "block excessBlobGas", "hex quantity to integer, null before Cancun"),
+        ],
+    ),
+    (
+        "ordering_analysis",
+        "How closely a block's transaction order follows priority fees and mempool arrival",
+        &[
+            ("block_number", "block number", HEX_INT),
+            ("datetime", "block timestamp", BLOCK_TIME),
+            ("transaction_count", "receipts", "count"),
+            ("base_fee_per_gas", "block baseFeePerGas", HEX_INT),
+            ("min_priority_fee", "receipt effectiveGasPrice", "lowest effective gas price minus base fee"),
+            ("max_priority_fee", "receipt effectiveGasPrice", "highest effective gas price minus base fee"),
+            ("priority_fee_inversions", "receipt effectiveGasPrice", "pairs where a later transaction pays a higher priority fee"),
+            ("priority_fee_adherence", "receipt effectiveGasPrice", "1 - inversions / pairs, null below 2 transactions"),
+            ("first_seen_count", "mempool subscription", "transactions with a first-seen time"),
+            ("first_seen_inversions", "mempool subscription", "pairs included out of arrival order"),
+            ("first_seen_adherence", "mempool subscription", "1 - inversions / pairs, null below 2 transactions"),
+        ],
+    ),
+    (
+        "nft_transfers",
+        "ERC-721 Transfer and ERC-1155 TransferSingle/TransferBatch events, one row per token id",
+        &[
+            ("id", "block number, log logIndex", "the log's id, shared by the rows of a TransferBatch"),
+            ("token_address", "log address", COPIED),
+            ("token_id", "log topics/data", "uint256 to decimal string"),
+            ("from", "log topics/data", "address"),
+            ("to", "log topics/data", "address"),
+            ("amount", "log data", "uint256 to decimal string, 1 for ERC-721"),
+            ("standard", "log topics", "erc721 or erc1155"),
+            ("operator", "log topics", "ERC-1155 operator, null for ERC-721"),
+            ("block_number", "receipt", FROM_RECEIPT),
+            ("transaction_hash", "receipt", FROM_RECEIPT),
+            ("log_index", "log logIndex", HEX_INT),
+            ("batch_index", "log data", "position within a TransferBatch, 0 otherwise"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "token_transfers",
+        "ERC-20 Transfer events",
+        &[
+            ("id", "block number, log logIndex", "block_number * 1000000 + log_index"),
+            ("token_address", "log address", COPIED),
+            ("from", "log topic1", "address"),
+            ("to", "log topic2", "address"),
+            ("value", "log data", "uint256 to decimal string"),
+            ("token_symbol", "symbol() eth_call", "with --token-metadata, null otherwise"),
+            ("token_name", "name() eth_call", "with --token-metadata, null otherwise"),
+            ("token_decimals", "decimals() eth_call", "with --token-metadata, null otherwise"),
+            ("value_scaled", "log data", "value / 10^decimals with --token-metadata, null otherwise"),
+            ("spam", "spam rules", "true when a spam heuristic matched"),
+            ("spam_reasons", "spam rules", "the heuristics that matched"),
+            ("block_number", "receipt", FROM_RECEIPT),
+            ("transaction_hash", "receipt", FROM_RECEIPT),
+            ("log_index", "log logIndex", HEX_INT),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "bridge_messages",
+        "Linea message service MessageSent and MessageClaimed events",
+        &[
+            ("id", "block number, log logIndex", "block_number * 1000000 + log_index"),
+            ("block_number", "receipt", FROM_RECEIPT),
+            ("block_hash", "receipt", FROM_RECEIPT),
+            ("transaction_hash", "receipt", FROM_RECEIPT),
+            ("transaction_index", "receipt", FROM_RECEIPT),
+            ("log_index", "log logIndex", HEX_INT),
+            ("contract_address", "log address", "a known L2MessageService deployment"),
+            ("event", "log topic0", "MessageSent or MessageClaimed"),
+            ("direction", "log topic0", "l2_to_l1 for MessageSent, l1_to_l2 for MessageClaimed"),
+            ("message_hash", "log topic3 (MessageSent) or topic1 (MessageClaimed)", COPIED),
+            ("sender", "log topic1", "address, MessageSent only"),
+            ("recipient", "log topic2", "address, MessageSent only"),
+            ("fee", "log data", "uint256 to decimal string, MessageSent only"),
+            ("value", "log data", "uint256 to decimal string, MessageSent only"),
+            ("nonce", "log data", "uint256 to decimal string, MessageSent only"),
+            ("calldata", "log data", "bytes as hex, MessageSent only"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "balances",
+        "Native balances of the --balance-address accounts at every --balance-every-th block",
+        &[
+            ("address", "configuration", "lowercased"),
+            ("block_number", "block number", HEX_INT),
+            ("balance", "eth_getBalance", HEX_DECIMAL),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "contract_state",
+        "Results of the --state-calls eth_calls at each block",
+        &[
+            ("name", "calls file", COPIED),
+            ("contract", "calls file", COPIED),
+            ("function", "calls file", COPIED),
+            ("block_number", "block number", HEX_INT),
+            ("values", "eth_call", "ABI-decoded outputs as strings: integers in decimal, addresses and bytes in hex"),
+            ("error", "eth_call", "error message when the call failed, null otherwise"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "address_activity",
+        "Index of the transactions each address took part in (--address-index)",
+        &[
+            ("address", "transaction from/to, log address", "lowercased"),
+            ("block_number", "transaction blockNumber", HEX_INT),
+            ("transaction_hash", "transaction hash", COPIED),
+            ("role", "derived", "from, to or log_emitter"),
+        ],
+    ),
+    (
+        "addresses",
+        "Address dimension, one versioned row per new or changed address (--address-dimension)",
+        &[
+            ("address", "transactions, receipts, transfers", "lowercased"),
+            ("is_contract", "contracts, receipt contractAddress", "true once seen deploying or deployed"),
+            ("is_token", "token and NFT transfers", "true once seen emitting a transfer"),
+            ("label", "labels file or token metadata", "configured label, else the token name"),
+            ("first_seen_block", "derived", "lowest block the address appeared in"),
+            ("first_seen_at", "block timestamp", BLOCK_TIME),
+            ("updated_block", "derived", "block of the latest change; the highest per address is current"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+    (
+        "data_quality",
+        "Blocks and receipts that failed a consistency check",
+        &[
+            ("block_number", "block number", HEX_INT),
+            ("check", "derived", "cumulative_gas_used, receipt_gas_used or effective_gas_price"),
+            ("transaction_hash", "receipt transactionHash", "set for checks of a single receipt"),
+            ("expected", "derived", "decimal string"),
+            ("actual", "derived", "decimal string"),
+            ("datetime", "block timestamp", BLOCK_TIME),
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDoc {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub column_type: &'static str,
+    pub source: &'static str,
+    pub transform: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetDoc {
+    pub dataset: &'static str,
+    pub summary: &'static str,
+    pub fields: Vec<FieldDoc>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescribeFormat {
+    Markdown,
+    Json,
+}
+
+// The dictionary of one dataset, or of all of them
+pub fn describe(dataset: Option<&str>) -> Result<Vec<DatasetDoc>> {
+    let docs: Vec<DatasetDoc> = DICTIONARY
+        .iter()
+        .filter(|(name, _, _)| dataset.is_none_or(|dataset| dataset == *name))
+        .map(|(name, summary, fields)| DatasetDoc {
+            dataset: name,
+            summary,
+            fields: columns_for(name)
+                .iter()
+                .zip(fields.iter())
+                .map(|((_, column_type), (field, source, transform))| FieldDoc { name: field, column_type, source, transform })
+                .collect(),
+        })
+        .collect();
+    if docs.is_empty() {
+        let known: Vec<&str> = DICTIONARY.iter().map(|(name, _, _)| *name).collect();
+        return Err(anyhow::anyhow!("Unknown dataset {}; available: {}", dataset.unwrap_or_default(), known.join(", ")));
+    }
+    Ok(docs)
+}
+
+pub fn render(docs: &[DatasetDoc], format: DescribeFormat) -> Result<String> {
+    if format == DescribeFormat::Json {
+        return Ok(serde_json::to_string_pretty(docs)?);
+    }
+    let mut out = String::new();
+    for doc in docs {
+        out.push_str(&format!("## {}\n\n{}\n\n| Field | Type | Source | Transform |\n|---|---|---|---|\n", doc.dataset, doc.summary));
+        for field in &doc.fields {
+            out.push_str(&format!("| `{}` | {} | {} | {} |\n", field.name, field.column_type, field.source, field.transform));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::clickhouse::TABLES;
+    use crate::transform::ROW_ID_STRIDE;
+
+    #[test]
+    fn test_dictionary_matches_columns() {
+        let documented: Vec<&str> = DICTIONARY.iter().map(|(name, _, _)| *name).collect();
+        let tables: Vec<&str> = TABLES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(documented, tables);
+        for (dataset, _, fields) in DICTIONARY {
+            let fields: Vec<&str> = fields.iter().map(|(field, _, _)| *field).collect();
+            let columns: Vec<&str> = columns_for(dataset).iter().map(|(column, _)| *column).collect();
+            assert_eq!(fields, columns, "{} is documented with other fields than its columns", dataset);
+        }
+        // The surrogate key formulas spell out transform::ROW_ID_STRIDE
+        let formulas: Vec<&str> = DICTIONARY.iter().flat_map(|(_, _, fields)| fields.iter()).filter(|(field, _, _)| *field == "id").map(|(_, _, transform)| *transform).collect();
+        assert_eq!(formulas.len(), 6);
+        assert!(formulas.iter().filter(|transform| transform.starts_with("block_number * ")).all(|transform| transform.contains(&format!(" * {} + ", ROW_ID_STRIDE))));
+
+        let docs = describe(Some("transactions")).unwrap();
+        assert_eq!(docs[0].fields[18].column_type, "UInt256");
+        assert!(render(&docs, DescribeFormat::Markdown).unwrap().contains("| `value` | UInt256 | value | hex quantity to decimal string (256 bits) |"));
+        assert_eq!(describe(Some("traces")).err().unwrap().to_string().split(';').next(), Some("Unknown dataset traces"));
+    }
+}
@@ -0,0 +1,220 @@
+use primitive_types::U256;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::events::{self, TokenEvent, ZERO_ADDRESS};
+use crate::models::TransformedReceipt;
+
+// Token state replayed from transfer and approval events. Balances keep credits and debits apart
+// so a holder whose earlier history was not indexed shows up with a negative balance instead of
+// wrapping around.
+#[derive(Debug, Default, Clone)]
+pub struct TokenState {
+    balances: HashMap<(String, String), (U256, U256)>,
+    owners: HashMap<(String, U256), String>,
+    allowances: HashMap<(String, String, String), U256>,
+}
+
+#[derive(Debug, Default)]
+struct Touched {
+    balances: BTreeSet<(String, String)>,
+    owners: BTreeSet<(String, U256)>,
+    allowances: BTreeSet<(String, String, String)>,
+}
+
+impl TokenState {
+    // Applies an event, recording the keys it changed when `touched` is given
+    fn apply(&mut self, event: TokenEvent, mut touched: Option<&mut Touched>) {
+        match event {
+            TokenEvent::Transfer { token, from, to, value } => {
+                for (holder, credit) in [(from, false), (to, true)] {
+                    if holder == ZERO_ADDRESS {
+                        continue;
+                    }
+                    let key = (token.clone(), holder);
+                    let entry = self.balances.entry(key.clone()).or_default();
+                    if credit {
+                        entry.0 = entry.0.saturating_add(value);
+                    } else {
+                        entry.1 = entry.1.saturating_add(value);
+                    }
+                    if let Some(touched) = touched.as_deref_mut() {
+                        touched.balances.insert(key);
+                    }
+                }
+            },
+            TokenEvent::NftTransfer { token, to, token_id, .. } => {
+                let key = (token, token_id);
+                if to == ZERO_ADDRESS {
+                    self.owners.remove(&key);
+                } else {
+                    self.owners.insert(key.clone(), to);
+                }
+                if let Some(touched) = touched {
+                    touched.owners.insert(key);
+                }
+            },
+            TokenEvent::Approval { token, owner, spender, value } => {
+                let key = (token, owner, spender);
+                self.allowances.insert(key.clone(), value);
+                if let Some(touched) = touched {
+                    touched.allowances.insert(key);
+                }
+            },
+            // ERC-1155 balances are not tracked
+            TokenEvent::MultiTransfer { .. } => {},
+        }
+    }
+
+    // Signed decimal balance
+    fn balance(&self, key: &(String, String)) -> String {
+        match self.balances.get(key) {
+            Some((credit, debit)) if credit >= debit => (*credit - *debit).to_string(),
+            Some((credit, debit)) => format!("-{}", *debit - *credit),
+            None => "0".to_string(),
+        }
+    }
+
+    fn holds(&self, key: &(String, String)) -> bool {
+        self.balances.get(key).is_some_and(|(credit, debit)| credit > debit)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HolderChange {
+    New,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StateChange {
+    Balance { token: String, holder: String, before: String, after: String, change: HolderChange },
+    Ownership { token: String, token_id: String, before: Option<String>, after: Option<String> },
+    Allowance { token: String, owner: String, spender: String, before: String, after: String },
+}
+
+// Token events of the receipts in block order, optionally limited to some token contracts
+fn events_of<'a>(receipts: &'a [TransformedReceipt], tokens: &'a [String]) -> impl Iterator<Item = (u64, TokenEvent)> + 'a {
+    receipts.iter().flat_map(move |receipt| {
+        receipt
+            .logs
+            .iter()
+            .filter(move |log| {
+                let address = log["address"].as_str().unwrap_or_default();
+                tokens.is_empty() || tokens.iter().any(|token| token.eq_ignore_ascii_case(address))
+            })
+            .filter_map(move |log| events::decode(log).map(|event| (receipt.block_number, event)))
+    })
+}
+
+// What changed between the state after block `from` and the state after block `to`. The state is
+// replayed from the first receipt given, so holders are only accurate when the receipts reach back
+// to the tokens' deployment.
+pub fn compute(receipts: &mut [TransformedReceipt], from: u64, to: u64, tokens: &[String]) -> Vec<StateChange> {
+    receipts.sort_by_key(|receipt| (receipt.block_number, receipt.transaction_index));
+
+    let mut state = TokenState::default();
+    let mut touched = Touched::default();
+    let mut before: Option<TokenState> = None;
+    for (block_number, event) in events_of(receipts, tokens) {
+        if block_number > to {
+            break;
+        }
+        if block_number <= from {
+            state.apply(event, None);
+        } else {
+            before.get_or_insert_with(|| state.clone());
+            state.apply(event, Some(&mut touched));
+        }
+    }
+    let before = before.unwrap_or_default();
+
+    let mut changes = Vec::new();
+    for key in touched.balances {
+        let (was, is) = (before.holds(&key), state.holds(&key));
+        let (old, new) = (before.balance(&key), state.balance(&key));
+        if old == new {
+            continue;
+        }
+        let change = match (was, is) {
+            (false, true) => HolderChange::New,
+            (true, false) => HolderChange::Removed,
+            _ => HolderChange::Changed,
+        };
+        changes.push(StateChange::Balance { token: key.0, holder: key.1, before: old, after: new, change });
+    }
+    for key in touched.owners {
+        let (old, new) = (before.owners.get(&key).cloned(), state.owners.get(&key).cloned());
+        if old != new {
+            changes.push(StateChange::Ownership { token: key.0, token_id: key.1.to_string(), before: old, after: new });
+        }
+    }
+    for key in touched.allowances {
+        let old = before.allowances.get(&key).copied().unwrap_or_default();
+        let new = state.allowances.get(&key).copied().unwrap_or_default();
+        if old != new {
+            let (token, owner, spender) = key;
+            changes.push(StateChange::Allowance { token, owner, spender, before: old.to_string(), after: new.to_string() });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{APPROVAL_TOPIC, TRANSFER_TOPIC};
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    fn receipt(block_number: u64, logs: Vec<serde_json::Value>) -> TransformedReceipt {
+        TransformedReceipt { logs, transaction_hash: format!("0x{}", block_number), ..fixtures::receipt(block_number, 0) }
+    }
+
+    fn transfer(from: &str, to: &str, value: u64) -> serde_json::Value {
+        json!({ "address": "0xtoken", "topics": [TRANSFER_TOPIC, topic(from), topic(to)], "data": topic(&format!("{:x}", value)) })
+    }
+
+    #[test]
+    fn test_diff_between_heights() {
+        let holder = |suffix: &str| format!("0x{:0>40}", suffix);
+        let mut receipts = vec![
+            receipt(3, vec![transfer("0xa", "0xb", 5)]),
+            receipt(1, vec![transfer(ZERO_ADDRESS, "0xa", 10)]),
+            receipt(4, vec![
+                json!({ "address": "0xtoken", "topics": [APPROVAL_TOPIC, topic("0xb"), topic("0xc")], "data": topic("64") }),
+                json!({ "address": "0xnft", "topics": [TRANSFER_TOPIC, topic("0x0"), topic("0xb"), topic("0x9")], "data": "0x" }),
+            ]),
+            receipt(5, vec![transfer("0xa", "0xb", 5)]),
+            receipt(9, vec![transfer("0xb", "0xc", 1)]),
+        ];
+
+        let changes = compute(&mut receipts, 2, 5, &[]);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[0],
+            StateChange::Balance {
+                token: "0xtoken".to_string(),
+                holder: holder("a"),
+                before: "10".to_string(),
+                after: "0".to_string(),
+                change: HolderChange::Removed,
+            }
+        );
+        assert!(matches!(&changes[1], StateChange::Balance { holder: h, after, change: HolderChange::New, .. } if *h == holder("b") && after == "10"));
+        assert_eq!(
+            changes[2],
+            StateChange::Ownership { token: "0xnft".to_string(), token_id: "9".to_string(), before: None, after: Some(holder("b")) }
+        );
+        assert!(matches!(&changes[3], StateChange::Allowance { before, after, .. } if before == "0" && after == "100"));
+
+        assert!(compute(&mut receipts, 2, 5, &["0xother".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+
+use crate::activity;
+use crate::pipeline::TransformedBatch;
+use crate::sinks::fanout::FanOut;
+use crate::store::{self, OutputConfig, OutputDirs, WritePolicy};
+
+// Prints what the batch would write to the output directory and every sink, checking the sinks'
+// schemas without changing them. Fails when a sink cannot be reached.
+pub async fn report(dirs: &OutputDirs, output: OutputConfig, sinks: &FanOut, batch: &TransformedBatch) -> Result<()> {
+    let plans = store::plan_writes(dirs, output, batch)?;
+    // Sinks get every row of the batch; skip-existing only filters them against their own contents
+    let rows = store::plan_writes(dirs, OutputConfig { write_policy: WritePolicy::Overwrite, ..output }, batch)?;
+
+    println!("Dry run of blocks {:?} to {:?}: nothing was written", batch.blocks.first().map(|b| b.number), batch.blocks.last().map(|b| b.number));
+    println!("{:<18} {:>10} {:>14} {:>7}  destination", "dataset", "records", "bytes", "files");
+    for plan in &plans {
+        let destination = match (plan.files.first(), plan.files.last()) {
+            (Some(first), Some(last)) if first != last => format!("{} .. {}", first, last),
+            (Some(first), _) => first.clone(),
+            _ => format!("{}/", dirs.dataset(plan.dataset)),
+        };
+        println!("{:<18} {:>10} {:>14} {:>7}  {}", plan.dataset, plan.records, plan.bytes, plan.files.len(), destination);
+    }
+    println!(
+        "Total: {} records, {} bytes uncompressed in {} files",
+        plans.iter().map(|p| p.records).sum::<usize>(),
+        plans.iter().map(|p| p.bytes).sum::<usize>(),
+        plans.iter().map(|p| p.files.len()).sum::<usize>()
+    );
+    let activity = match output.address_index {
+        true => activity::extract(&batch.receipts).len(),
+        false => 0,
+    };
+    if output.address_index {
+        println!("Address index: {} entries", activity);
+    }
+
+    let mut unreachable = Vec::new();
+    for sink in sinks.sinks() {
+        match sink.preview(&rows, activity).await {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(e) => {
+                println!("{}: unreachable: {}", sink.describe(), e);
+                unreachable.push(sink.name());
+            },
+        }
+    }
+    if !unreachable.is_empty() {
+        return Err(anyhow::anyhow!("Sinks unreachable: {}", unreachable.join(", ")));
+    }
+    Ok(())
+}
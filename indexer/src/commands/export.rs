@@ -0,0 +1,173 @@
+use anyhow::Result;
+use rust_xlsxwriter::{Color, Format, FormatAlign, FormatBorder, Workbook, Worksheet};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::pipeline::TransformedBatch;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Xlsx,
+}
+
+// Excel limits
+const MAX_ROWS: usize = 1_048_575;
+const MAX_CELL_CHARS: usize = 32_767;
+// Integers above 2^53 lose precision as spreadsheet numbers, so they are written as text
+const MAX_EXACT_NUMBER: u64 = 1 << 53;
+
+fn header_format() -> Format {
+    Format::new()
+        .set_bold()
+        .set_font_color(Color::White)
+        .set_background_color(Color::RGB(0x1F4E78))
+        .set_border_bottom(FormatBorder::Thin)
+        .set_align(FormatAlign::Center)
+}
+
+fn to_rows<T: Serialize>(records: &[T]) -> Result<Vec<serde_json::Map<String, Value>>> {
+    records
+        .iter()
+        .map(|record| match serde_json::to_value(record)? {
+            Value::Object(row) => Ok(row),
+            other => Err(anyhow::anyhow!("Expected a JSON object, got {}", other)),
+        })
+        .collect()
+}
+
+fn write_cell(sheet: &mut Worksheet, row: u32, col: u16, value: &Value, integer: &Format) -> Result<()> {
+    match value {
+        Value::Null => {},
+        Value::Bool(b) => {
+            sheet.write_boolean(row, col, *b)?;
+        },
+        Value::Number(n) => match n.as_u64() {
+            Some(u) if u > MAX_EXACT_NUMBER => {
+                sheet.write_string(row, col, u.to_string())?;
+            },
+            Some(u) => {
+                sheet.write_number_with_format(row, col, u as f64, integer)?;
+            },
+            None => {
+                sheet.write_number(row, col, n.as_f64().unwrap_or_default())?;
+            },
+        },
+        Value::String(s) => {
+            sheet.write_string(row, col, s.chars().take(MAX_CELL_CHARS).collect::<String>())?;
+        },
+        nested => {
+            sheet.write_string(row, col, nested.to_string().chars().take(MAX_CELL_CHARS).collect::<String>())?;
+        },
+    }
+    Ok(())
+}
+
+fn add_sheet(workbook: &mut Workbook, name: &str, rows: &[serde_json::Map<String, Value>]) -> Result<()> {
+    if rows.len() > MAX_ROWS {
+        return Err(anyhow::anyhow!(
+            "{} has {} rows, more than a worksheet can hold; export a smaller range",
+            name,
+            rows.len()
+        ));
+    }
+
+    let header = header_format();
+    let integer = Format::new().set_num_format("#,##0");
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(name)?;
+
+    // Rows can differ in their optional fields (`extra` is only present when non-empty)
+    let mut columns: Vec<String> = Vec::new();
+    for name in rows.iter().flat_map(|row| row.keys()) {
+        if !columns.contains(name) {
+            columns.push(name.clone());
+        }
+    }
+    for (col, column) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, column, &header)?;
+    }
+    for (index, row) in rows.iter().enumerate() {
+        for (col, column) in columns.iter().enumerate() {
+            write_cell(sheet, index as u32 + 1, col as u16, row.get(column).unwrap_or(&Value::Null), &integer)?;
+        }
+    }
+
+    if !columns.is_empty() {
+        sheet.set_freeze_panes(1, 0)?;
+        sheet.autofilter(0, 0, rows.len() as u32, columns.len() as u16 - 1)?;
+        sheet.autofit();
+    }
+    Ok(())
+}
+
+// Logs are nested in receipts; the workbook gets them as their own sheet
+fn log_rows(batch: &TransformedBatch) -> Vec<serde_json::Map<String, Value>> {
+    batch
+        .receipts
+        .iter()
+        .flat_map(|receipt| {
+            receipt.logs.iter().map(move |log| {
+                let row = json!({
+                    "block_number": receipt.block_number,
+                    "transaction_hash": receipt.transaction_hash,
+                    "log_index": log["logIndex"].as_str().map(crate::transform::hex_to_u64),
+                    "address": log["address"],
+                    "topics": log["topics"],
+                    "data": log["data"],
+                });
+                row.as_object().cloned().unwrap_or_default()
+            })
+        })
+        .collect()
+}
+
+pub fn write_xlsx(batch: &TransformedBatch, path: &Path) -> Result<()> {
+    let mut workbook = Workbook::new();
+    add_sheet(&mut workbook, "Blocks", &to_rows(&batch.blocks)?)?;
+    add_sheet(&mut workbook, "Transactions", &to_rows(&batch.transactions)?)?;
+    add_sheet(&mut workbook, "Receipts", &to_rows(&batch.receipts)?)?;
+    add_sheet(&mut workbook, "Logs", &log_rows(batch))?;
+    workbook.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::models::TransformedReceipt;
+
+    #[test]
+    fn test_write_xlsx_with_logs_sheet() {
+        let receipt = TransformedReceipt {
+            id: 5_000_000,
+            block_hash: "0xb".to_string(),
+            block_number: 5,
+            contract_address: None,
+            cumulative_gas_used: 21000,
+            effective_gas_price: u64::MAX,
+            from: "0x1".to_string(),
+            gas_used: 21000,
+            logs: vec![json!({"address": "0xc", "topics": ["0xt"], "data": "0x", "logIndex": "0x2"})],
+            logs_bloom: "0x".to_string(),
+            status: true,
+            to: Some("0x2".to_string()),
+            transaction_hash: "0xh".to_string(),
+            transaction_index: 0,
+            tx_type: 2,
+            datetime: Utc::now(),
+            extra: Default::default(),
+        };
+        let batch = TransformedBatch { receipts: vec![receipt], ..Default::default() };
+
+        let logs = log_rows(&batch);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0]["log_index"], json!(2));
+
+        let path = Path::new("test_export.xlsx");
+        write_xlsx(&batch, path).unwrap();
+        assert!(std::fs::metadata(path).unwrap().len() > 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+// Missing blocks in `start..=end` as inclusive (first, last) ranges
+pub fn find_gaps(present: &BTreeSet<u64>, start: u64, end: u64) -> Vec<(u64, u64)> {
+    let mut gaps = Vec::new();
+    let mut gap_start: Option<u64> = None;
+
+    for number in start..=end {
+        match (present.contains(&number), gap_start) {
+            (false, None) => gap_start = Some(number),
+            (true, Some(first)) => {
+                gaps.push((first, number - 1));
+                gap_start = None;
+            },
+            _ => {}
+        }
+    }
+    if let Some(first) = gap_start {
+        gaps.push((first, end));
+    }
+    gaps
+}
+
+// Resolves the scanned range, defaulting to the lowest and highest indexed block
+pub fn scan_range(present: &BTreeSet<u64>, start: Option<u64>, count: Option<u64>) -> Result<(u64, u64)> {
+    let first = start.or_else(|| present.first().copied());
+    let last = match (first, count) {
+        (Some(first), Some(count)) if count > 0 => Some(first + count - 1),
+        (Some(_), Some(_)) => return Err(anyhow::anyhow!("--count must be greater than 0")),
+        _ => present.last().copied(),
+    };
+    match (first, last) {
+        (Some(first), Some(last)) if first <= last => Ok((first, last)),
+        (Some(_), Some(_)) => Err(anyhow::anyhow!("Nothing indexed at or after the requested start block")),
+        _ => Err(anyhow::anyhow!("No indexed blocks found, pass --start and --count to scan a range")),
+    }
+}
+
+pub fn print_gaps(gaps: &[(u64, u64)], first: u64, last: u64) {
+    let missing: u64 = gaps.iter().map(|(a, b)| b - a + 1).sum();
+    println!("Scanned blocks {} to {}: {} missing in {} gaps", first, last, missing, gaps.len());
+    for (a, b) in gaps {
+        if a == b {
+            println!("  {}", a);
+        } else {
+            println!("  {}-{} ({} blocks)", a, b, b - a + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_gaps() {
+        let present: BTreeSet<u64> = [2, 3, 6, 8].into_iter().collect();
+        assert_eq!(find_gaps(&present, 1, 10), vec![(1, 1), (4, 5), (7, 7), (9, 10)]);
+        assert_eq!(find_gaps(&present, 2, 3), vec![]);
+    }
+
+    #[test]
+    fn test_scan_range_defaults_to_indexed_bounds() {
+        let present: BTreeSet<u64> = [5, 9].into_iter().collect();
+        assert_eq!(scan_range(&present, None, None).unwrap(), (5, 9));
+        assert_eq!(scan_range(&present, Some(1), Some(20)).unwrap(), (1, 20));
+        assert!(scan_range(&BTreeSet::new(), None, None).is_err());
+    }
+}
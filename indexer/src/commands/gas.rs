@@ -0,0 +1,230 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use crate::commands::report::percentile;
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+
+const TOP_N: usize = 20;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum GasReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+// CSV holds one table, so it is either the per-block rows or the contract ranking
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum GasTable {
+    #[default]
+    Blocks,
+    Contracts,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockGas {
+    pub block_number: u64,
+    pub datetime: DateTime<Utc>,
+    pub transactions: u64,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub utilization: f64,
+    pub base_fee_per_gas: Option<u64>,
+    // Effective gas price percentiles of the block's transactions, in wei
+    pub gas_price_p50: Option<u64>,
+    pub gas_price_p90: Option<u64>,
+    pub gas_price_p99: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContractGas {
+    pub address: String,
+    pub calls: u64,
+    pub gas_used: u64,
+    // Fraction of all gas used in the range
+    pub share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GasSummary {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub gas_used: u64,
+    pub avg_utilization: f64,
+    pub base_fee_min: Option<u64>,
+    pub base_fee_avg: Option<f64>,
+    pub base_fee_max: Option<u64>,
+    pub gas_price_p50: Option<u64>,
+    pub gas_price_p90: Option<u64>,
+    pub gas_price_p99: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GasReport {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub summary: GasSummary,
+    pub blocks: Vec<BlockGas>,
+    pub top_contracts: Vec<ContractGas>,
+}
+
+fn percentiles(prices: &mut [u64]) -> [Option<u64>; 3] {
+    prices.sort_unstable();
+    [50.0, 90.0, 99.0].map(|p| (!prices.is_empty()).then(|| percentile(prices, p)))
+}
+
+pub fn compute(
+    blocks: &[TransformedBlock],
+    transactions: &[TransformedTransaction],
+    receipts: &[TransformedReceipt],
+) -> GasReport {
+    let mut prices_by_block: HashMap<u64, Vec<u64>> = HashMap::new();
+    for receipt in receipts {
+        prices_by_block.entry(receipt.block_number).or_default().push(receipt.effective_gas_price);
+    }
+
+    let mut rows: BTreeMap<u64, BlockGas> = BTreeMap::new();
+    for block in blocks {
+        let [p50, p90, p99] = percentiles(prices_by_block.get_mut(&block.number).map(Vec::as_mut_slice).unwrap_or_default());
+        rows.insert(
+            block.number,
+            BlockGas {
+                block_number: block.number,
+                datetime: block.datetime,
+                transactions: block.transaction_hashes.len() as u64,
+                gas_used: block.gas_used,
+                gas_limit: block.gas_limit,
+                utilization: if block.gas_limit > 0 { block.gas_used as f64 / block.gas_limit as f64 } else { 0.0 },
+                base_fee_per_gas: block.base_fee_per_gas,
+                gas_price_p50: p50,
+                gas_price_p90: p90,
+                gas_price_p99: p99,
+            },
+        );
+    }
+    let blocks: Vec<BlockGas> = rows.into_values().collect();
+    let gas_used: u64 = blocks.iter().map(|b| b.gas_used).sum();
+
+    // Same rule as the network report: a call carrying calldata to an address is a contract call
+    let receipt_by_hash: HashMap<&str, &TransformedReceipt> =
+        receipts.iter().map(|r| (r.transaction_hash.as_str(), r)).collect();
+    let mut contracts: HashMap<&str, ContractGas> = HashMap::new();
+    for tx in transactions {
+        if let Some(to) = tx.to.as_deref().filter(|_| tx.input.len() > 2) {
+            let stats = contracts.entry(to).or_insert(ContractGas { address: to.to_string(), calls: 0, gas_used: 0, share: 0.0 });
+            stats.calls += 1;
+            stats.gas_used += receipt_by_hash.get(tx.hash.as_str()).map(|r| r.gas_used).unwrap_or_default();
+        }
+    }
+    let mut top_contracts: Vec<ContractGas> = contracts.into_values().collect();
+    top_contracts.sort_by(|a, b| b.gas_used.cmp(&a.gas_used).then(a.address.cmp(&b.address)));
+    top_contracts.truncate(TOP_N);
+    for contract in &mut top_contracts {
+        contract.share = if gas_used > 0 { contract.gas_used as f64 / gas_used as f64 } else { 0.0 };
+    }
+
+    let base_fees: Vec<u64> = blocks.iter().filter_map(|b| b.base_fee_per_gas).collect();
+    let mut prices: Vec<u64> = receipts.iter().map(|r| r.effective_gas_price).collect();
+    let [p50, p90, p99] = percentiles(&mut prices);
+    let summary = GasSummary {
+        blocks: blocks.len() as u64,
+        transactions: blocks.iter().map(|b| b.transactions).sum(),
+        gas_used,
+        avg_utilization: if blocks.is_empty() {
+            0.0
+        } else {
+            blocks.iter().map(|b| b.utilization).sum::<f64>() / blocks.len() as f64
+        },
+        base_fee_min: base_fees.iter().min().copied(),
+        base_fee_avg: (!base_fees.is_empty())
+            .then(|| base_fees.iter().map(|f| *f as f64).sum::<f64>() / base_fees.len() as f64),
+        base_fee_max: base_fees.iter().max().copied(),
+        gas_price_p50: p50,
+        gas_price_p90: p90,
+        gas_price_p99: p99,
+    };
+
+    GasReport {
+        first_block: blocks.first().map(|b| b.block_number).unwrap_or_default(),
+        last_block: blocks.last().map(|b| b.block_number).unwrap_or_default(),
+        summary,
+        blocks,
+        top_contracts,
+    }
+}
+
+pub fn write(report: &GasReport, format: GasReportFormat, table: GasTable, out: impl Write) -> Result<()> {
+    match format {
+        GasReportFormat::Json => serde_json::to_writer_pretty(out, report)?,
+        GasReportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            match table {
+                GasTable::Blocks => report.blocks.iter().try_for_each(|row| writer.serialize(row))?,
+                GasTable::Contracts => report.top_contracts.iter().try_for_each(|row| writer.serialize(row))?,
+            }
+            writer.flush()?;
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_gas_report() {
+        let block = |number: u64, gas_used: u64, base_fee: u64| TransformedBlock {
+            base_fee_per_gas: Some(base_fee),
+            gas_limit: 1000,
+            gas_used,
+            transaction_hashes: vec!["0xa".to_string(), "0xb".to_string()],
+            ..fixtures::block(number)
+        };
+        let receipt = |hash: &str, block: u64, gas_used: u64, price: u64| TransformedReceipt {
+            cumulative_gas_used: gas_used,
+            effective_gas_price: price,
+            gas_used,
+            to: Some("0xc".to_string()),
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(block, 0)
+        };
+        let transaction = |hash: &str, to: &str, input: &str| TransformedTransaction {
+            gas_price: 1,
+            hash: hash.to_string(),
+            input: input.to_string(),
+            to: Some(to.to_string()),
+            ..fixtures::transaction(1, 0)
+        };
+
+        let blocks = vec![block(2, 250, 7), block(1, 500, 10)];
+        let receipts = vec![
+            receipt("0xa", 1, 300, 12),
+            receipt("0xb", 1, 200, 30),
+            receipt("0xc", 2, 250, 9),
+        ];
+        let transactions = vec![
+            transaction("0xa", "0xpool", "0x1234"),
+            transaction("0xb", "0xwallet", "0x"),
+            transaction("0xc", "0xpool", "0xabcd"),
+        ];
+
+        let report = compute(&blocks, &transactions, &receipts);
+        assert_eq!((report.first_block, report.last_block), (1, 2));
+        assert_eq!(report.blocks[0].utilization, 0.5);
+        assert_eq!((report.blocks[0].gas_price_p50, report.blocks[0].gas_price_p99), (Some(12), Some(30)));
+        assert_eq!(report.summary.gas_used, 750);
+        assert_eq!((report.summary.base_fee_min, report.summary.base_fee_max), (Some(7), Some(10)));
+        assert_eq!(report.summary.gas_price_p50, Some(12));
+        assert_eq!(report.top_contracts.len(), 1);
+        assert_eq!((report.top_contracts[0].calls, report.top_contracts[0].gas_used), (2, 550));
+
+        let mut csv = Vec::new();
+        write(&report, GasReportFormat::Csv, GasTable::Contracts, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "address,calls,gas_used,share\n0xpool,2,550,0.7333333333333333\n");
+    }
+}
@@ -0,0 +1,17 @@
+pub mod block;
+pub mod bloom_audit;
+pub mod bootstrap;
+pub mod describe;
+pub mod diff;
+pub mod dry_run;
+#[cfg(feature = "xlsx")]
+pub mod export;
+pub mod gas;
+pub mod gaps;
+pub mod nonces;
+pub mod peek;
+pub mod plan;
+pub mod report;
+pub mod transform;
+pub mod tx;
+pub mod verify;
@@ -0,0 +1,164 @@
+//! Indexing of the transactions one address sent in a nonce range, for wallet-history
+//! reconstruction without scanning every block. Each nonce is located either by binary search over
+//! `eth_getTransactionCount` at historical blocks (any archive node, about log2(head) calls per
+//! nonce, narrowing as nonces increase) or in one call through the Otterscan API.
+//!
+//! Only the located transactions are written, with their receipts and the contracts and transfers
+//! derived from them; the rest of their blocks is not. Nonces raised without a transaction of the
+//! address (contract creations by a contract, EIP-7702 authorizations) are reported as missing.
+
+use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::future::Future;
+use std::ops::RangeInclusive;
+
+use crate::pipeline::TransformedBatch;
+use crate::rpc;
+use crate::store::{self, OutputConfig, OutputDirs};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Locator {
+    // Binary search over `eth_getTransactionCount` (needs an archive node)
+    #[default]
+    BinarySearch,
+    // `ots_getTransactionBySenderAndNonce`
+    Otterscan,
+}
+
+// The first block in `low..=high` by whose end the sender's nonce exceeds `nonce`, which is the
+// block of its transaction with that nonce; None when not even `high` is past it
+pub async fn first_block_past<F, Fut>(nonce: u64, mut low: u64, mut high: u64, mut nonce_at: F) -> Result<Option<u64>>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    if nonce_at(high).await? <= nonce {
+        return Ok(None);
+    }
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match nonce_at(middle).await? > nonce {
+            true => high = middle,
+            false => low = middle + 1,
+        }
+    }
+    Ok(Some(low))
+}
+
+// Block of every nonce in the range that has been used by `head`, in nonce order
+pub async fn locate(address: &str, nonces: RangeInclusive<u64>, locator: Locator, head: u64) -> Result<Vec<(u64, u64)>> {
+    let mut located = Vec::new();
+    let mut low = 0;
+    for nonce in nonces {
+        let block = match locator {
+            Locator::BinarySearch => first_block_past(nonce, low, head, |block| rpc::get_transaction_count(address, block)).await?,
+            Locator::Otterscan => rpc::get_block_by_sender_and_nonce(address, nonce).await?,
+        };
+        let Some(block) = block else {
+            log::info!("{} has not used nonce {} yet", address, nonce);
+            break;
+        };
+        log::debug!("Nonce {} of {} is in block {}", nonce, address, block);
+        located.push((nonce, block));
+        // Later nonces cannot be in earlier blocks
+        low = block;
+    }
+    Ok(located)
+}
+
+// The transactions of the batch sent by `address` with a nonce in the range, with their receipts,
+// logs, contracts and transfers
+pub fn sent_by(batch: TransformedBatch, address: &str, nonces: &RangeInclusive<u64>) -> TransformedBatch {
+    let transactions: Vec<_> = batch
+        .transactions
+        .into_iter()
+        .filter(|tx| tx.from.eq_ignore_ascii_case(address) && nonces.contains(&tx.nonce))
+        .collect();
+    let hashes: HashSet<String> = transactions.iter().map(|tx| tx.hash.clone()).collect();
+    TransformedBatch {
+        transactions,
+        receipts: batch.receipts.into_iter().filter(|r| hashes.contains(&r.transaction_hash)).collect(),
+        logs: batch.logs.into_iter().filter(|l| hashes.contains(&l.transaction_hash)).collect(),
+        contracts: batch.contracts.into_iter().filter(|c| hashes.contains(&c.transaction_hash)).collect(),
+        nft_transfers: batch.nft_transfers.into_iter().filter(|t| hashes.contains(&t.transaction_hash)).collect(),
+        token_transfers: batch.token_transfers.into_iter().filter(|t| hashes.contains(&t.transaction_hash)).collect(),
+        bridge_messages: batch.bridge_messages.into_iter().filter(|m| hashes.contains(&m.transaction_hash)).collect(),
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NonceReport {
+    pub located: usize,
+    pub blocks: usize,
+    pub written: usize,
+    // Located nonces without a transaction of the address in their block
+    pub missing: Vec<u64>,
+}
+
+// Locates, fetches and writes the address's transactions in the nonce range (files only)
+pub async fn index(
+    address: &str,
+    nonces: RangeInclusive<u64>,
+    locator: Locator,
+    dirs: &OutputDirs,
+    output: OutputConfig,
+    fetch_concurrency: usize,
+    write_concurrency: usize,
+) -> Result<NonceReport> {
+    let head = rpc::get_latest_block_number().await?;
+    let located = locate(address, nonces.clone(), locator, head).await?;
+    let blocks: BTreeSet<u64> = located.iter().map(|(_, block)| *block).collect();
+    let fetched: Vec<_> = stream::iter(blocks.iter().copied())
+        .map(rpc::fetch_block_data)
+        .buffered(fetch_concurrency)
+        .try_collect()
+        .await?;
+
+    let mut batch = TransformedBatch::default();
+    for (block, transactions, receipts) in &fetched {
+        batch.push_block(block, transactions, receipts);
+    }
+    let batch = sent_by(batch, address, &nonces);
+    let written: BTreeMap<u64, &str> = batch.transactions.iter().map(|tx| (tx.nonce, tx.hash.as_str())).collect();
+    let missing: Vec<u64> = located.iter().map(|(nonce, _)| *nonce).filter(|nonce| !written.contains_key(nonce)).collect();
+    if !missing.is_empty() {
+        log::warn!("{} has no transactions for nonces {:?}; they were raised by contract creations or authorizations", address, missing);
+    }
+    store::persist(dirs, output, None, &batch, write_concurrency).await?;
+    Ok(NonceReport { located: located.len(), blocks: blocks.len(), written: batch.transactions.len(), missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::TransformedTransaction;
+
+    #[tokio::test]
+    async fn test_locate_and_select() {
+        // Nonce of the sender at the end of blocks 0..=9: transactions in blocks 2, 5, 5 and 8
+        let history = [0, 0, 1, 1, 1, 3, 3, 3, 4, 4];
+        let nonce_at = |block: u64| async move { Ok(history[block as usize]) };
+        let mut blocks = Vec::new();
+        for nonce in 0..5 {
+            blocks.push(first_block_past(nonce, 0, 9, nonce_at).await.unwrap());
+        }
+        assert_eq!(blocks, vec![Some(2), Some(5), Some(5), Some(8), None]);
+        assert_eq!(first_block_past(1, 6, 9, nonce_at).await.unwrap(), Some(6));
+
+        let tx = |from: &str, nonce: u64, hash: &str| TransformedTransaction {
+            from: from.to_string(),
+            nonce,
+            hash: hash.to_string(),
+            ..fixtures::transaction(5, 0)
+        };
+        let batch = TransformedBatch {
+            transactions: vec![tx("0xAB", 1, "0x1"), tx("0xab", 9, "0x2"), tx("0xcd", 1, "0x3")],
+            ..Default::default()
+        };
+        let selected = sent_by(batch, "0xab", &(0..=5));
+        assert_eq!(selected.transactions.iter().map(|tx| tx.hash.as_str()).collect::<Vec<_>>(), vec!["0x1"]);
+    }
+}
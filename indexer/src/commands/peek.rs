@@ -0,0 +1,148 @@
+use serde::Serialize;
+
+use crate::commands::report;
+use crate::networks::{self, currency};
+use crate::pipeline::TransformedBatch;
+
+#[derive(Debug, Serialize)]
+pub struct ContractCalls {
+    pub address: String,
+    pub calls: u64,
+}
+
+// Summary of a fetched range; nothing here is written anywhere
+#[derive(Debug, Serialize)]
+pub struct PeekStats {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub blocks: u64,
+    pub transactions: u64,
+    pub failed_transactions: u64,
+    pub contracts_created: u64,
+    pub avg_block_time: f64,
+    pub transactions_per_second: f64,
+    pub gas_used: u64,
+    // Share of the summed gas limit that was used
+    pub gas_utilization: f64,
+    // (min, avg, max) in wei
+    pub base_fee: Option<(u64, f64, u64)>,
+    // (p50, p90) in wei
+    pub gas_price: Option<(u64, u64)>,
+    pub total_fees_eth: f64,
+    pub calldata_bytes: u64,
+    pub blob_count: u64,
+    // Mean over blocks with at least two transactions
+    pub priority_fee_adherence: Option<f64>,
+    pub top_contracts: Vec<ContractCalls>,
+}
+
+pub fn compute(batch: &TransformedBatch) -> PeekStats {
+    let report = report::compute(&batch.blocks, &batch.transactions, &batch.receipts);
+    let gas_used: u64 = batch.blocks.iter().map(|b| b.gas_used).sum();
+    let gas_limit: u64 = batch.blocks.iter().map(|b| b.gas_limit).sum();
+    let span = report.avg_block_time * report.blocks as f64;
+    let adherence: Vec<f64> = batch.ordering_analysis.iter().filter_map(|o| o.priority_fee_adherence).collect();
+
+    PeekStats {
+        first_block: report.first_block,
+        last_block: report.last_block,
+        blocks: report.blocks,
+        transactions: report.transactions,
+        failed_transactions: report.failed_transactions,
+        contracts_created: batch.contracts.len() as u64,
+        avg_block_time: report.avg_block_time,
+        transactions_per_second: if span > 0.0 { report.transactions as f64 / span } else { 0.0 },
+        gas_used,
+        gas_utilization: if gas_limit > 0 { gas_used as f64 / gas_limit as f64 } else { 0.0 },
+        base_fee: report.base_fee,
+        gas_price: report.gas_price,
+        total_fees_eth: report.total_fees,
+        calldata_bytes: batch.data_usage.iter().map(|u| u.calldata_bytes).sum(),
+        blob_count: batch.data_usage.iter().map(|u| u.blob_count).sum(),
+        priority_fee_adherence: (!adherence.is_empty()).then(|| adherence.iter().sum::<f64>() / adherence.len() as f64),
+        top_contracts: report
+            .top_contracts
+            .into_iter()
+            .map(|c| ContractCalls { address: c.address, calls: c.calls })
+            .collect(),
+    }
+}
+
+pub fn render(stats: &PeekStats) -> String {
+    let mut lines = vec![
+        format!("blocks            {} ({}..={})", stats.blocks, stats.first_block, stats.last_block),
+        format!("transactions      {} ({} failed)", stats.transactions, stats.failed_transactions),
+        format!("contracts created {}", stats.contracts_created),
+        match networks::selected() {
+            Some(network) => format!(
+                "block time        {:.2}s avg ({}s target on {}), {:.2} tx/s",
+                stats.avg_block_time,
+                network.block_time_ms as f64 / 1000.0,
+                network.name,
+                stats.transactions_per_second
+            ),
+            None => format!("block time        {:.2}s avg, {:.2} tx/s", stats.avg_block_time, stats.transactions_per_second),
+        },
+        format!("gas used          {} ({:.1}% of limit)", stats.gas_used, stats.gas_utilization * 100.0),
+    ];
+    if let Some((min, avg, max)) = stats.base_fee {
+        lines.push(format!("base fee (wei)    min {} / avg {:.0} / max {}", min, avg, max));
+    }
+    if let Some((p50, p90)) = stats.gas_price {
+        lines.push(format!("gas price (wei)   p50 {} / p90 {}", p50, p90));
+    }
+    lines.push(format!("fees paid         {:.6} {}", stats.total_fees_eth, currency()));
+    lines.push(format!("calldata          {} bytes, {} blobs", stats.calldata_bytes, stats.blob_count));
+    if let Some(adherence) = stats.priority_fee_adherence {
+        lines.push(format!("fee ordering      {:.3} adherence", adherence));
+    }
+    for contract in &stats.top_contracts {
+        lines.push(format!("top contract      {} ({} calls)", contract.address, contract.calls));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BlockDataUsage, TransformedBlock};
+
+    #[test]
+    fn test_compute_and_render() {
+        let block = |number: u64, timestamp: u64, gas_used: u64| -> TransformedBlock {
+            let block = serde_json::from_value(serde_json::json!({
+                "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x64", "gasUsed": format!("0x{:x}", gas_used),
+                "hash": "0xa", "logsBloom": "0x", "miner": "0x0", "number": format!("0x{:x}", number),
+                "parentHash": "0x0", "receiptsRoot": "0x0", "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0",
+                "timestamp": format!("0x{:x}", timestamp), "transactions": ["0x1", "0x2"], "transactionsRoot": "0x0",
+                "uncles": [], "baseFeePerGas": "0x7",
+            }))
+            .unwrap();
+            crate::transform::transform_block(&block)
+        };
+        let usage: BlockDataUsage = serde_json::from_value(serde_json::json!({
+            "block_number": 1, "datetime": "2024-01-01T00:00:00Z", "size": 1, "gas_used": 50, "transaction_count": 2,
+            "calldata_bytes": 40, "calldata_zero_bytes": 10, "calldata_nonzero_bytes": 30, "calldata_gas": 520,
+            "blob_transactions": 1, "blob_count": 2, "blob_gas_used": null, "excess_blob_gas": null,
+        }))
+        .unwrap();
+        let batch = TransformedBatch {
+            blocks: vec![block(1, 100, 50), block(2, 102, 25)],
+            data_usage: vec![usage],
+            ..Default::default()
+        };
+
+        let stats = compute(&batch);
+        assert_eq!((stats.first_block, stats.last_block, stats.blocks), (1, 2, 2));
+        assert_eq!(stats.gas_used, 75);
+        assert_eq!(stats.gas_utilization, 0.375);
+        assert_eq!(stats.avg_block_time, 2.0);
+        assert_eq!((stats.calldata_bytes, stats.blob_count), (40, 2));
+        assert_eq!(stats.base_fee, Some((7, 7.0, 7)));
+        assert!(stats.priority_fee_adherence.is_none());
+
+        let text = render(&stats);
+        assert!(text.contains("gas used          75 (37.5% of limit)"));
+        assert!(!text.contains("gas price"));
+    }
+}
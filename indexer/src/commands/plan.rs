@@ -0,0 +1,183 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::pipeline::TransformedBatch;
+use crate::progress::format_duration;
+use crate::rpc::{self, HydrationConfig};
+use crate::store::{self, OutputConfig, OutputDirs};
+
+// What fetching and writing the sampled blocks took
+#[derive(Debug, Clone, Copy)]
+pub struct SampleMeasurement {
+    pub blocks: u64,
+    pub requests: u64,
+    pub bytes: u64,
+    // Summed over blocks fetched one at a time
+    pub fetch_time: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlanInputs {
+    // Blocks that will be indexed (after any `--sample`)
+    pub blocks: u64,
+    // Balance lookups and state calls per block, on top of what the hydration level needs
+    pub extra_requests_per_block: f64,
+    pub rps: u32,
+    pub fetch_concurrency: usize,
+    pub cost_per_million_requests: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub blocks: u64,
+    pub sampled_blocks: u64,
+    pub requests_per_block: f64,
+    pub total_requests: u64,
+    pub estimated_cost: Option<f64>,
+    pub runtime_secs: u64,
+    // "rps" when the rate limit is the bottleneck, "latency" when the RPC's response time is
+    pub runtime_bound: &'static str,
+    pub bytes_per_block: u64,
+    pub storage_bytes: u64,
+}
+
+// `samples` block numbers spread evenly over the range, first and last included
+pub fn sample_blocks(start: u64, count: u64, samples: u64) -> Vec<u64> {
+    let samples = samples.clamp(1, count.max(1));
+    if samples == 1 {
+        return vec![start];
+    }
+    let mut blocks: Vec<u64> = (0..samples).map(|i| start + i * (count - 1) / (samples - 1)).collect();
+    blocks.dedup();
+    blocks
+}
+
+// Fetches the sample blocks one at a time and writes them to a scratch directory in the configured
+// output format, so the storage estimate includes formatting and compression
+pub async fn measure(blocks: &[u64], hydration: &HydrationConfig, output: OutputConfig) -> Result<SampleMeasurement> {
+    let requests_before = rpc::request_count();
+    let mut fetch_time = Duration::ZERO;
+    let mut batch = TransformedBatch::default();
+    for block_number in blocks {
+        let started = Instant::now();
+        let (block, transactions, receipts) = rpc::fetch_block_data_with(*block_number, hydration).await?;
+        fetch_time += started.elapsed();
+        batch.push_block(&block, &transactions, &receipts);
+    }
+    let requests = rpc::request_count() - requests_before;
+
+    let scratch = std::env::temp_dir().join(format!("sweet-potato-plan-{}", std::process::id()));
+    let scratch_path = scratch.to_string_lossy().to_string();
+    let written = async {
+        let dirs = OutputDirs::create(&scratch_path)?;
+        store::persist(&dirs, output, None, &batch, 4).await?;
+        directory_size(&scratch)
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    Ok(SampleMeasurement { blocks: blocks.len() as u64, requests, bytes: written?, fetch_time })
+}
+
+fn directory_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() { directory_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+pub fn estimate(sample: &SampleMeasurement, inputs: &PlanInputs) -> Plan {
+    let sampled = sample.blocks.max(1);
+    let requests_per_block = sample.requests as f64 / sampled as f64 + inputs.extra_requests_per_block;
+    let total_requests = (requests_per_block * inputs.blocks as f64).ceil() as u64;
+
+    // Blocks are fetched `fetch_concurrency` at a time; the rate limit caps that when set
+    let latency_secs = sample.fetch_time.as_secs_f64() / sampled as f64 * inputs.blocks as f64 / inputs.fetch_concurrency.max(1) as f64;
+    let rps_secs = if inputs.rps > 0 { total_requests as f64 / inputs.rps as f64 } else { 0.0 };
+    let (runtime, runtime_bound) = if rps_secs > latency_secs { (rps_secs, "rps") } else { (latency_secs, "latency") };
+
+    let bytes_per_block = sample.bytes / sampled;
+    Plan {
+        blocks: inputs.blocks,
+        sampled_blocks: sample.blocks,
+        requests_per_block,
+        total_requests,
+        estimated_cost: (inputs.cost_per_million_requests > 0.0)
+            .then(|| total_requests as f64 / 1_000_000.0 * inputs.cost_per_million_requests),
+        runtime_secs: runtime.ceil() as u64,
+        runtime_bound,
+        bytes_per_block,
+        storage_bytes: bytes_per_block * inputs.blocks,
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+pub fn render(plan: &Plan) -> String {
+    let mut lines = vec![
+        format!("blocks            {} (estimated from {} sampled)", plan.blocks, plan.sampled_blocks),
+        format!("rpc requests      {} ({:.1} per block)", plan.total_requests, plan.requests_per_block),
+    ];
+    if let Some(cost) = plan.estimated_cost {
+        lines.push(format!("provider cost     {:.2}", cost));
+    }
+    lines.push(format!(
+        "runtime           {} (bound by {})",
+        format_duration(Duration::from_secs(plan.runtime_secs)),
+        if plan.runtime_bound == "rps" { "the RPC_RPS limit" } else { "RPC latency at FETCH_CONCURRENCY" }
+    ));
+    lines.push(format!("storage           {} ({} per block)", format_bytes(plan.storage_bytes), format_bytes(plan.bytes_per_block)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        assert_eq!(sample_blocks(100, 1000, 5), vec![100, 349, 599, 849, 1099]);
+        assert_eq!(sample_blocks(100, 2, 5), vec![100, 101]);
+        assert_eq!(sample_blocks(100, 1, 5), vec![100]);
+
+        let sample = SampleMeasurement { blocks: 4, requests: 8, bytes: 40_000, fetch_time: Duration::from_secs(2) };
+        let inputs = PlanInputs {
+            blocks: 1_000_000,
+            extra_requests_per_block: 1.0,
+            rps: 50,
+            fetch_concurrency: 10,
+            cost_per_million_requests: 2.5,
+        };
+        let plan = estimate(&sample, &inputs);
+        assert_eq!(plan.total_requests, 3_000_000);
+        assert_eq!(plan.estimated_cost, Some(7.5));
+        assert_eq!((plan.runtime_secs, plan.runtime_bound), (60_000, "rps"));
+        assert_eq!(plan.storage_bytes, 10_000_000_000);
+
+        let unlimited = estimate(&sample, &PlanInputs { rps: 0, cost_per_million_requests: 0.0, ..inputs });
+        assert_eq!((unlimited.runtime_secs, unlimited.runtime_bound), (50_000, "latency"));
+        assert!(unlimited.estimated_cost.is_none());
+
+        let text = render(&plan);
+        assert!(text.contains("runtime           16h40m00s (bound by the RPC_RPS limit)"));
+        assert!(text.contains("storage           9.3 GiB (9.8 KiB per block)"));
+    }
+}
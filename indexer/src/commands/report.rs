@@ -0,0 +1,528 @@
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use primitive_types::U256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+
+const WEI_PER_GWEI: f64 = 1e9;
+const WEI_PER_ETH: f64 = 1e18;
+const TOP_N: usize = 10;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+struct Labels {
+    title: &'static str,
+    overview: &'static str,
+    range: &'static str,
+    period: &'static str,
+    blocks: &'static str,
+    transactions: &'static str,
+    failed: &'static str,
+    avg_block_time: &'static str,
+    activity: &'static str,
+    hour: &'static str,
+    gas_used: &'static str,
+    top_contracts: &'static str,
+    contract: &'static str,
+    calls: &'static str,
+    fees: &'static str,
+    base_fee: &'static str,
+    gas_price: &'static str,
+    total_fees: &'static str,
+    min: &'static str,
+    avg: &'static str,
+    max: &'static str,
+    notable_transfers: &'static str,
+    block: &'static str,
+    from: &'static str,
+    to: &'static str,
+    value: &'static str,
+}
+
+impl Locale {
+    fn labels(&self) -> Labels {
+        match self {
+            Locale::En => Labels {
+                title: "Network report",
+                overview: "Overview",
+                range: "Block range",
+                period: "Period",
+                blocks: "Blocks",
+                transactions: "Transactions",
+                failed: "Failed transactions",
+                avg_block_time: "Average block time (s)",
+                activity: "Activity per hour",
+                hour: "Hour (UTC)",
+                gas_used: "Gas used",
+                top_contracts: "Top contracts",
+                contract: "Contract",
+                calls: "Calls",
+                fees: "Fees",
+                base_fee: "Base fee (gwei)",
+                gas_price: "Effective gas price (gwei)",
+                total_fees: "Total fees paid (ETH)",
+                min: "Min",
+                avg: "Average",
+                max: "Max",
+                notable_transfers: "Largest transfers",
+                block: "Block",
+                from: "From",
+                to: "To",
+                value: "Value (ETH)",
+            },
+            Locale::De => Labels {
+                title: "Netzwerkbericht",
+                overview: "Überblick",
+                range: "Blockbereich",
+                period: "Zeitraum",
+                blocks: "Blöcke",
+                transactions: "Transaktionen",
+                failed: "Fehlgeschlagene Transaktionen",
+                avg_block_time: "Durchschnittliche Blockzeit (s)",
+                activity: "Aktivität pro Stunde",
+                hour: "Stunde (UTC)",
+                gas_used: "Verbrauchtes Gas",
+                top_contracts: "Meistgenutzte Verträge",
+                contract: "Vertrag",
+                calls: "Aufrufe",
+                fees: "Gebühren",
+                base_fee: "Basisgebühr (gwei)",
+                gas_price: "Effektiver Gaspreis (gwei)",
+                total_fees: "Gezahlte Gebühren gesamt (ETH)",
+                min: "Min",
+                avg: "Durchschnitt",
+                max: "Max",
+                notable_transfers: "Größte Überweisungen",
+                block: "Block",
+                from: "Von",
+                to: "An",
+                value: "Wert (ETH)",
+            },
+            Locale::Fr => Labels {
+                title: "Rapport réseau",
+                overview: "Vue d'ensemble",
+                range: "Plage de blocs",
+                period: "Période",
+                blocks: "Blocs",
+                transactions: "Transactions",
+                failed: "Transactions échouées",
+                avg_block_time: "Temps de bloc moyen (s)",
+                activity: "Activité par heure",
+                hour: "Heure (UTC)",
+                gas_used: "Gaz utilisé",
+                top_contracts: "Contrats les plus appelés",
+                contract: "Contrat",
+                calls: "Appels",
+                fees: "Frais",
+                base_fee: "Frais de base (gwei)",
+                gas_price: "Prix effectif du gaz (gwei)",
+                total_fees: "Total des frais payés (ETH)",
+                min: "Min",
+                avg: "Moyenne",
+                max: "Max",
+                notable_transfers: "Plus gros transferts",
+                block: "Bloc",
+                from: "De",
+                to: "À",
+                value: "Valeur (ETH)",
+            },
+            Locale::Es => Labels {
+                title: "Informe de red",
+                overview: "Resumen",
+                range: "Rango de bloques",
+                period: "Periodo",
+                blocks: "Bloques",
+                transactions: "Transacciones",
+                failed: "Transacciones fallidas",
+                avg_block_time: "Tiempo medio de bloque (s)",
+                activity: "Actividad por hora",
+                hour: "Hora (UTC)",
+                gas_used: "Gas usado",
+                top_contracts: "Contratos más usados",
+                contract: "Contrato",
+                calls: "Llamadas",
+                fees: "Comisiones",
+                base_fee: "Comisión base (gwei)",
+                gas_price: "Precio efectivo del gas (gwei)",
+                total_fees: "Comisiones pagadas en total (ETH)",
+                min: "Mín",
+                avg: "Media",
+                max: "Máx",
+                notable_transfers: "Mayores transferencias",
+                block: "Bloque",
+                from: "De",
+                to: "A",
+                value: "Valor (ETH)",
+            },
+        }
+    }
+
+    // (thousands separator, decimal mark)
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self {
+            Locale::En => (",", "."),
+            Locale::De | Locale::Es => (".", ","),
+            Locale::Fr => ("\u{202f}", ","),
+        }
+    }
+
+    pub fn format_number(&self, value: f64, decimals: usize) -> String {
+        let (group, decimal) = self.separators();
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push_str(group);
+            }
+            grouped.push(digit);
+        }
+        let sign = if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') { "-" } else { "" };
+        if fraction.is_empty() {
+            format!("{}{}", sign, grouped)
+        } else {
+            format!("{}{}{}{}", sign, grouped, decimal, fraction)
+        }
+    }
+}
+
+// Nearest-rank percentile of an ascending slice
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub struct ActivityBucket {
+    pub hour: DateTime<Utc>,
+    pub blocks: u64,
+    pub transactions: u64,
+    pub gas_used: u64,
+}
+
+pub struct ContractStats {
+    pub address: String,
+    pub calls: u64,
+    pub gas_used: u64,
+}
+
+pub struct Transfer {
+    pub hash: String,
+    pub block: u64,
+    pub from: String,
+    pub to: String,
+    pub value: U256,
+}
+
+pub struct ReportData {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub blocks: u64,
+    pub transactions: u64,
+    pub failed_transactions: u64,
+    pub avg_block_time: f64,
+    pub activity: Vec<ActivityBucket>,
+    pub top_contracts: Vec<ContractStats>,
+    // (min, avg, max) in wei
+    pub base_fee: Option<(u64, f64, u64)>,
+    // (p50, p90) in wei
+    pub gas_price: Option<(u64, u64)>,
+    pub total_fees: f64,
+    pub notable_transfers: Vec<Transfer>,
+}
+
+pub fn compute(
+    blocks: &[TransformedBlock],
+    transactions: &[TransformedTransaction],
+    receipts: &[TransformedReceipt],
+) -> ReportData {
+    let start_time = blocks.iter().map(|b| b.datetime).min();
+    let end_time = blocks.iter().map(|b| b.datetime).max();
+    let avg_block_time = match (start_time, end_time) {
+        (Some(start), Some(end)) if blocks.len() > 1 => (end - start).num_seconds() as f64 / (blocks.len() - 1) as f64,
+        _ => 0.0,
+    };
+
+    let mut activity: BTreeMap<DateTime<Utc>, ActivityBucket> = BTreeMap::new();
+    for block in blocks {
+        let hour = block.datetime.duration_trunc(TimeDelta::hours(1)).unwrap_or(block.datetime);
+        let bucket = activity.entry(hour).or_insert(ActivityBucket { hour, blocks: 0, transactions: 0, gas_used: 0 });
+        bucket.blocks += 1;
+        bucket.transactions += block.transaction_hashes.len() as u64;
+        bucket.gas_used += block.gas_used;
+    }
+
+    // A call carrying calldata to an address is counted as a contract call
+    let receipt_by_hash: HashMap<&str, &TransformedReceipt> =
+        receipts.iter().map(|r| (r.transaction_hash.as_str(), r)).collect();
+    let mut contracts: HashMap<&str, ContractStats> = HashMap::new();
+    for tx in transactions {
+        if let Some(to) = tx.to.as_deref().filter(|_| tx.input.len() > 2) {
+            let stats = contracts.entry(to).or_insert(ContractStats { address: to.to_string(), calls: 0, gas_used: 0 });
+            stats.calls += 1;
+            stats.gas_used += receipt_by_hash.get(tx.hash.as_str()).map(|r| r.gas_used).unwrap_or_default();
+        }
+    }
+    let mut top_contracts: Vec<ContractStats> = contracts.into_values().collect();
+    top_contracts.sort_by(|a, b| b.calls.cmp(&a.calls).then(b.gas_used.cmp(&a.gas_used)).then(a.address.cmp(&b.address)));
+    top_contracts.truncate(TOP_N);
+
+    let base_fees: Vec<u64> = blocks.iter().filter_map(|b| b.base_fee_per_gas).collect();
+    let base_fee = if base_fees.is_empty() {
+        None
+    } else {
+        let sum: f64 = base_fees.iter().map(|f| *f as f64).sum();
+        Some((
+            *base_fees.iter().min().unwrap_or(&0),
+            sum / base_fees.len() as f64,
+            *base_fees.iter().max().unwrap_or(&0),
+        ))
+    };
+
+    let mut gas_prices: Vec<u64> = receipts.iter().map(|r| r.effective_gas_price).collect();
+    gas_prices.sort_unstable();
+    let gas_price = (!gas_prices.is_empty()).then(|| (percentile(&gas_prices, 50.0), percentile(&gas_prices, 90.0)));
+    let total_fees = receipts
+        .iter()
+        .map(|r| r.gas_used as f64 * r.effective_gas_price as f64)
+        .sum::<f64>()
+        / WEI_PER_ETH;
+
+    let mut notable_transfers: Vec<Transfer> = transactions
+        .iter()
+        .filter(|tx| !tx.value_wei().is_zero())
+        .map(|tx| Transfer {
+            hash: tx.hash.clone(),
+            block: tx.block_number,
+            from: tx.from.clone(),
+            to: tx.to.clone().unwrap_or_default(),
+            value: tx.value_wei(),
+        })
+        .collect();
+    notable_transfers.sort_by(|a, b| b.value.cmp(&a.value).then(a.hash.cmp(&b.hash)));
+    notable_transfers.truncate(TOP_N);
+
+    let failed: HashSet<&str> = receipts.iter().filter(|r| !r.status).map(|r| r.transaction_hash.as_str()).collect();
+
+    ReportData {
+        first_block: blocks.iter().map(|b| b.number).min().unwrap_or_default(),
+        last_block: blocks.iter().map(|b| b.number).max().unwrap_or_default(),
+        start_time,
+        end_time,
+        blocks: blocks.len() as u64,
+        transactions: transactions.len() as u64,
+        failed_transactions: failed.len() as u64,
+        avg_block_time,
+        activity: activity.into_values().collect(),
+        top_contracts,
+        base_fee,
+        gas_price,
+        total_fees,
+        notable_transfers,
+    }
+}
+
+struct Table {
+    title: String,
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    // Per-row share of the largest value, drawn as a bar in HTML
+    bars: Option<Vec<f64>>,
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn tables(data: &ReportData, locale: Locale) -> (String, Vec<Table>) {
+    let l = locale.labels();
+    let n = |value: f64, decimals: usize| locale.format_number(value, decimals);
+    let gwei = |wei: f64| n(wei / WEI_PER_GWEI, 3);
+
+    let mut tables = vec![Table {
+        title: l.overview.to_string(),
+        headers: vec![String::new(), String::new()],
+        rows: vec![
+            vec![l.range.to_string(), format!("{} – {}", data.first_block, data.last_block)],
+            vec![l.period.to_string(), format!("{} – {}", format_time(data.start_time), format_time(data.end_time))],
+            vec![l.blocks.to_string(), n(data.blocks as f64, 0)],
+            vec![l.transactions.to_string(), n(data.transactions as f64, 0)],
+            vec![l.failed.to_string(), n(data.failed_transactions as f64, 0)],
+            vec![l.avg_block_time.to_string(), n(data.avg_block_time, 2)],
+        ],
+        bars: None,
+    }];
+
+    let max_txs = data.activity.iter().map(|b| b.transactions).max().unwrap_or_default().max(1) as f64;
+    tables.push(Table {
+        title: l.activity.to_string(),
+        headers: vec![l.hour.to_string(), l.blocks.to_string(), l.transactions.to_string(), l.gas_used.to_string()],
+        rows: data
+            .activity
+            .iter()
+            .map(|b| vec![b.hour.format("%Y-%m-%d %H:00").to_string(), n(b.blocks as f64, 0), n(b.transactions as f64, 0), n(b.gas_used as f64, 0)])
+            .collect(),
+        bars: Some(data.activity.iter().map(|b| b.transactions as f64 / max_txs).collect()),
+    });
+
+    tables.push(Table {
+        title: l.top_contracts.to_string(),
+        headers: vec![l.contract.to_string(), l.calls.to_string(), l.gas_used.to_string()],
+        rows: data
+            .top_contracts
+            .iter()
+            .map(|c| vec![c.address.clone(), n(c.calls as f64, 0), n(c.gas_used as f64, 0)])
+            .collect(),
+        bars: None,
+    });
+
+    let mut fee_rows = Vec::new();
+    if let Some((min, avg, max)) = data.base_fee {
+        fee_rows.push(vec![format!("{} ({})", l.base_fee, l.min), gwei(min as f64)]);
+        fee_rows.push(vec![format!("{} ({})", l.base_fee, l.avg), gwei(avg)]);
+        fee_rows.push(vec![format!("{} ({})", l.base_fee, l.max), gwei(max as f64)]);
+    }
+    if let Some((p50, p90)) = data.gas_price {
+        fee_rows.push(vec![format!("{} (p50)", l.gas_price), gwei(p50 as f64)]);
+        fee_rows.push(vec![format!("{} (p90)", l.gas_price), gwei(p90 as f64)]);
+    }
+    fee_rows.push(vec![l.total_fees.to_string(), n(data.total_fees, 6)]);
+    tables.push(Table { title: l.fees.to_string(), headers: vec![String::new(), String::new()], rows: fee_rows, bars: None });
+
+    tables.push(Table {
+        title: l.notable_transfers.to_string(),
+        headers: vec!["Tx".to_string(), l.block.to_string(), l.from.to_string(), l.to.to_string(), l.value.to_string()],
+        rows: data
+            .notable_transfers
+            .iter()
+            .map(|t| vec![t.hash.clone(), t.block.to_string(), t.from.clone(), t.to.clone(), n(u128::try_from(t.value).map_or(f64::MAX, |wei| wei as f64) / WEI_PER_ETH, 6)])
+            .collect(),
+        bars: None,
+    });
+
+    (l.title.to_string(), tables)
+}
+
+fn render_markdown(title: &str, tables: &[Table]) -> String {
+    let mut out = format!("# {}\n", title);
+    for table in tables {
+        out.push_str(&format!("\n## {}\n\n", table.title));
+        if table.rows.is_empty() {
+            out.push_str("-\n");
+            continue;
+        }
+        out.push_str(&format!("| {} |\n", table.headers.join(" | ")));
+        out.push_str(&format!("|{}\n", " --- |".repeat(table.headers.len())));
+        for row in &table.rows {
+            out.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn render_html(title: &str, tables: &[Table], locale: Locale) -> String {
+    let lang = format!("{:?}", locale).to_lowercase();
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ font-family: sans-serif; margin: 2rem; color: #222; }}\n\
+         table {{ border-collapse: collapse; margin-bottom: 2rem; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 0.3rem 0.6rem; text-align: left; font-variant-numeric: tabular-nums; }}\n\
+         th {{ background: #1f4e78; color: #fff; }}\n\
+         .bar {{ background: #5b9bd5; height: 0.8rem; }}\n\
+         </style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        lang,
+        escape_html(title),
+        escape_html(title)
+    );
+    for table in tables {
+        out.push_str(&format!("<h2>{}</h2>\n<table>\n", escape_html(&table.title)));
+        if table.headers.iter().any(|h| !h.is_empty()) {
+            out.push_str("<tr>");
+            for header in &table.headers {
+                out.push_str(&format!("<th>{}</th>", escape_html(header)));
+            }
+            if table.bars.is_some() {
+                out.push_str("<th></th>");
+            }
+            out.push_str("</tr>\n");
+        }
+        for (i, row) in table.rows.iter().enumerate() {
+            out.push_str("<tr>");
+            for cell in row {
+                out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+            }
+            if let Some(bars) = &table.bars {
+                out.push_str(&format!(
+                    "<td style=\"width: 12rem\"><div class=\"bar\" style=\"width: {:.1}%\"></div></td>",
+                    bars[i] * 100.0
+                ));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub fn render(data: &ReportData, format: ReportFormat, locale: Locale) -> String {
+    let (title, tables) = tables(data, locale);
+    match format {
+        ReportFormat::Markdown => render_markdown(&title, &tables),
+        ReportFormat::Html => render_html(&title, &tables, locale),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_number_formatting() {
+        assert_eq!(Locale::En.format_number(1234567.891, 2), "1,234,567.89");
+        assert_eq!(Locale::De.format_number(1234567.891, 2), "1.234.567,89");
+        assert_eq!(Locale::Fr.format_number(1234.5, 1), "1\u{202f}234,5");
+        assert_eq!(Locale::En.format_number(999.0, 0), "999");
+        assert_eq!(Locale::En.format_number(-1000.0, 0), "-1,000");
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(percentile(&values, 50.0), 5);
+        assert_eq!(percentile(&values, 90.0), 9);
+        assert_eq!(percentile(&values, 100.0), 10);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn test_empty_report_renders() {
+        let data = compute(&[], &[], &[]);
+        let markdown = render(&data, ReportFormat::Markdown, Locale::En);
+        assert!(markdown.starts_with("# Network report"));
+        let html = render(&data, ReportFormat::Html, Locale::De);
+        assert!(html.contains("<html lang=\"de\">"));
+        assert!(html.contains("Netzwerkbericht"));
+    }
+}
@@ -0,0 +1,98 @@
+//! `transform`: rebuilds the datasets of a block range from the raw RPC responses kept by
+//! `index --keep-raw`, without calling the RPC, so a schema or decoder change can be applied to
+//! history offline. Blocks are transformed as `index` would (registered transformers and data
+//! quality checks included) and their files rewritten; steps that need the RPC or outside state
+//! (token metadata, balances, state calls, the address dimension) are left out.
+
+use anyhow::Result;
+
+use crate::manifest;
+use crate::pipeline::TransformedBatch;
+use crate::raw;
+use crate::rpc::{parse_block, parse_receipts};
+use crate::store::{self, OutputConfig, OutputDirs};
+use crate::transformers;
+
+// The batch of the blocks of `start..start + count` that were kept, and the numbers of those that were not
+pub fn transform_range(raw_data_path: &str, start: u64, count: u64) -> Result<(TransformedBatch, Vec<u64>)> {
+    let mut batch = TransformedBatch::default();
+    let mut missing = Vec::new();
+    for number in start..start + count {
+        let Some(kept) = raw::load(raw_data_path, number)? else {
+            missing.push(number);
+            continue;
+        };
+        let (block, transactions) = parse_block(kept.block).map_err(|e| anyhow::anyhow!("Invalid raw block {}: {}", number, e))?;
+        let receipts = parse_receipts(kept.receipts).map_err(|e| anyhow::anyhow!("Invalid raw receipts of block {}: {}", number, e))?;
+        batch.push_block(&block, &transactions, &receipts);
+    }
+    transformers::apply(&mut batch)?;
+    Ok((batch, missing))
+}
+
+pub async fn run(raw_data_path: &str, start: u64, count: u64, output: OutputConfig, write_concurrency: usize) -> Result<()> {
+    let (batch, missing) = transform_range(raw_data_path, start, count)?;
+    if batch.blocks.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No raw responses kept for blocks {} to {}; index them with --keep-raw first",
+            start,
+            (start + count).saturating_sub(1)
+        ));
+    }
+    if !missing.is_empty() {
+        log::warn!("{} blocks have no raw responses and were skipped, first {}", missing.len(), missing[0]);
+    }
+    let dirs = OutputDirs::create(raw_data_path)?;
+    let plans = store::plan_writes(&dirs, output, &batch)?;
+    store::persist(&dirs, output, None, &batch, write_concurrency).await?;
+
+    let (first, last) = (batch.blocks[0].number, batch.blocks[batch.blocks.len() - 1].number);
+    let chain_id = batch.transactions.iter().map(|tx| tx.chain_id).find(|chain_id| *chain_id != 0);
+    let run_manifest = manifest::build(raw_data_path, output, &plans, chain_id, first, last)?;
+    log::info!("Wrote run manifest {}", manifest::write(raw_data_path, &run_manifest)?.display());
+    println!(
+        "Transformed {} blocks ({} transactions, {} receipts) from raw responses into {}",
+        batch.blocks.len(),
+        batch.transactions.len(),
+        batch.receipts.len(),
+        raw_data_path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawBlock;
+    use serde_json::json;
+
+    #[test]
+    fn test_transform_range_from_raw() {
+        let root = "test_transform_raw";
+        let _ = std::fs::remove_dir_all(root);
+        let tx = json!({
+            "blockHash": "0xb", "blockNumber": "0x5", "chainId": "0xe708", "from": "0xf", "gas": "0x5208",
+            "gasPrice": "0x1", "hash": "0xh", "input": "0x", "nonce": "0x0", "r": "0x", "s": "0x", "to": "0xa",
+            "transactionIndex": "0x0", "type": "0x2", "v": "0x", "value": "0x1", "newField": "0x7",
+        });
+        let block = json!({
+            "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x1c9c380", "gasUsed": "0x5208", "hash": "0xb",
+            "logsBloom": "0x", "miner": "0x0", "mixHash": "0x0", "nonce": "0x0", "number": "0x5", "parentHash": "0xa",
+            "receiptsRoot": "0x0", "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0", "timestamp": "0x1",
+            "totalDifficulty": "0x0", "transactions": [tx], "transactionsRoot": "0x0", "uncles": [],
+        });
+        let receipts = json!([{
+            "blockHash": "0xb", "blockNumber": "0x5", "contractAddress": null, "cumulativeGasUsed": "0x5208",
+            "effectiveGasPrice": "0x1", "from": "0xf", "gasUsed": "0x5208", "logs": [], "logsBloom": "0x", "status": "0x1",
+            "to": "0xa", "transactionHash": "0xh", "transactionIndex": "0x0", "type": "0x2",
+        }]);
+        raw::write(root, 5, &RawBlock { block, receipts }).unwrap();
+
+        let (batch, missing) = transform_range(root, 5, 2).unwrap();
+        assert_eq!(missing, vec![6]);
+        assert_eq!(batch.blocks[0].transaction_hashes, vec!["0xh".to_string()]);
+        assert_eq!((batch.transactions[0].chain_id, batch.transactions[0].extra["newField"].as_str()), (59144, Some("0x7")));
+        assert_eq!((batch.receipts.len(), batch.data_quality.len()), (1, 0));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
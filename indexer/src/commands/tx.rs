@@ -0,0 +1,185 @@
+//! `tx <hash>`: fetches one transaction and its receipt, runs them through the same transforms as
+//! `index` and prints them with the decoded method, logs and token transfers, for debugging and
+//! support. Nothing is written.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::{NftTransfer, Receipt, TokenTransfer, Transaction, TransformedReceipt, TransformedTransaction};
+use crate::networks::currency;
+use crate::rpc;
+use crate::transform::{apply_native_units, attach_receipts, extract_nft_transfers, extract_token_transfers, hex_to_u64, set_fees, transform_block, transform_receipt, transform_transaction};
+
+const WEI_PER_ETH: f64 = 1e18;
+
+#[derive(Debug, Serialize)]
+pub struct TxDetails {
+    pub transaction: TransformedTransaction,
+    pub receipt: TransformedReceipt,
+    pub token_transfers: Vec<TokenTransfer>,
+    pub nft_transfers: Vec<NftTransfer>,
+}
+
+// Transforms a fetched transaction and receipt, stamped with their block's time and with fees split
+// at its base fee
+pub fn details(tx: &Transaction, receipt: &Receipt, datetime: DateTime<Utc>, base_fee_per_gas: Option<u64>) -> TxDetails {
+    let receipt = transform_receipt(receipt, datetime);
+    let receipts = std::slice::from_ref(&receipt);
+    let mut transaction = transform_transaction(tx, datetime);
+    attach_receipts(std::slice::from_mut(&mut transaction), receipts);
+    set_fees(&mut transaction, base_fee_per_gas);
+    apply_native_units(&mut [], std::slice::from_mut(&mut transaction));
+    TxDetails {
+        transaction,
+        token_transfers: extract_token_transfers(receipts),
+        nft_transfers: extract_nft_transfers(receipts),
+        receipt,
+    }
+}
+
+pub async fn fetch(hash: &str) -> Result<TxDetails> {
+    let raw = rpc::get_raw_transaction(hash).await?;
+    if raw.is_null() {
+        return Err(anyhow::anyhow!("Transaction {} not found", hash));
+    }
+    if raw["blockNumber"].is_null() {
+        return Err(anyhow::anyhow!("Transaction {} is still pending", hash));
+    }
+    let tx: Transaction = serde_json::from_value(raw).map_err(|e| anyhow::anyhow!("Invalid transaction {}: {}", hash, e))?;
+    let receipts = rpc::get_raw_transaction_receipts(std::slice::from_ref(&tx.hash)).await?;
+    let receipt = match receipts.as_array().and_then(|receipts| receipts.first()) {
+        Some(receipt) if !receipt.is_null() => receipt.clone(),
+        _ => return Err(anyhow::anyhow!("No receipt for transaction {}", hash)),
+    };
+    let receipt: Receipt = serde_json::from_value(receipt).map_err(|e| anyhow::anyhow!("Invalid receipt of {}: {}", hash, e))?;
+    let header = transform_block(&rpc::get_block_header(hex_to_u64(&tx.block_number)).await?);
+    Ok(details(&tx, &receipt, header.datetime, header.base_fee_per_gas))
+}
+
+fn eth(wei: u128) -> f64 {
+    wei as f64 / WEI_PER_ETH
+}
+
+// One line per indexed parameter, named and typed when the event signature is known
+fn render_log(log: &Value, lines: &mut Vec<String>) {
+    let index = hex_to_u64(log["logIndex"].as_str().unwrap_or_default());
+    let event = log["event_signature"].as_str().or(log["topic0"].as_str()).unwrap_or("anonymous");
+    lines.push(format!("  log {:<4} {} {}", index, log["address"].as_str().unwrap_or_default(), event));
+    for (position, param) in log["indexed"].as_array().into_iter().flatten().enumerate() {
+        let name = param["name"].as_str().map_or_else(|| format!("topic{}", position + 1), str::to_string);
+        let value = param["value"].as_str().map_or_else(|| param["value"].to_string(), str::to_string);
+        lines.push(format!("           {} ({}) = {}", name, param["type"].as_str().unwrap_or_default(), value));
+    }
+    let data = log["data"].as_str().unwrap_or("0x");
+    if data.len() > 2 {
+        lines.push(format!("           data = {}", data));
+    }
+}
+
+pub fn render(details: &TxDetails) -> String {
+    let (tx, receipt) = (&details.transaction, &details.receipt);
+    let value = u128::try_from(tx.value_wei()).unwrap_or(u128::MAX);
+    let fee = receipt.gas_used as u128 * receipt.effective_gas_price as u128;
+    let mut lines = vec![
+        format!("hash              {}", tx.hash),
+        format!("status            {}", if receipt.status { "success" } else { "failed" }),
+        format!("block             {} ({}), index {}", tx.block_number, tx.block_hash, tx.transaction_index),
+        format!("time              {}", tx.datetime.to_rfc3339()),
+        format!("from              {} (nonce {})", tx.from, tx.nonce),
+    ];
+    match (&tx.to, &receipt.contract_address) {
+        (Some(to), _) => lines.push(format!("to                {}", to)),
+        (None, Some(created)) => lines.push(format!("created contract  {}", created)),
+        (None, None) => lines.push("to                (contract creation)".to_string()),
+    }
+    lines.push(format!("value             {:.6} {} ({} wei)", eth(value), currency(), tx.value));
+    lines.push(format!("type              {}", tx.tx_type));
+    lines.push(format!("gas               {} used of {} ({:.1}%)", receipt.gas_used, tx.gas, receipt.gas_used as f64 * 100.0 / tx.gas.max(1) as f64));
+    lines.push(format!("gas price         {} wei effective", receipt.effective_gas_price));
+    match tx.burnt_fee.as_deref().and_then(|burnt| burnt.parse::<u128>().ok()) {
+        Some(burnt) => lines.push(format!("fee               {0:.6} {1} ({2:.6} {1} burnt)", eth(fee), currency(), eth(burnt))),
+        None => lines.push(format!("fee               {:.6} {}", eth(fee), currency())),
+    }
+    match (&tx.method_id, &tx.method_signature) {
+        (Some(id), Some(signature)) => lines.push(format!("method            {} {}", id, signature)),
+        (Some(id), None) => lines.push(format!("method            {}", id)),
+        (None, _) => {},
+    }
+    lines.push(format!("input             {} bytes", tx.input.trim_start_matches("0x").len() / 2));
+    lines.push(format!("logs              {}", receipt.logs.len()));
+    for log in &receipt.logs {
+        render_log(log, &mut lines);
+    }
+    for transfer in &details.token_transfers {
+        lines.push(format!("token transfer    {} {} -> {}: {}", transfer.token_address, transfer.from, transfer.to, transfer.value));
+    }
+    for transfer in &details.nft_transfers {
+        lines.push(format!(
+            "nft transfer      {} #{} x{} {} -> {}",
+            transfer.token_address, transfer.token_id, transfer.amount, transfer.from, transfer.to
+        ));
+    }
+    lines.join("\n")
+}
+
+pub async fn run(hash: &str, json: bool) -> Result<()> {
+    let details = fetch(hash).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&details)?);
+    } else {
+        println!("{}", render(&details));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_details_and_render() {
+        let tx: Transaction = serde_json::from_value(json!({
+            "blockHash": "0xb", "blockNumber": "0x5", "chainId": "0xe708", "from": "0xf", "gas": "0x10000",
+            "gasPrice": "0x2", "hash": "0xh", "input": "0xa9059cbb0000", "nonce": "0x3", "r": "0x", "s": "0x",
+            "to": "0xc", "transactionIndex": "0x0", "type": "0x2", "v": "0x", "value": "0xde0b6b3a7640000",
+        }))
+        .unwrap();
+        let receipt: Receipt = serde_json::from_value(json!({
+            "blockHash": "0xb", "blockNumber": "0x5", "contractAddress": null, "cumulativeGasUsed": "0x8000",
+            "effectiveGasPrice": "0x2", "from": "0xf", "gasUsed": "0x8000", "logsBloom": "0x", "status": "0x1",
+            "to": "0xc", "transactionHash": "0xh", "transactionIndex": "0x0", "type": "0x2",
+            "logs": [{
+                "address": "0x00000000000000000000000000000000000000cc", "logIndex": "0x7",
+                "topics": [
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                    "0x00000000000000000000000000000000000000000000000000000000000000aa",
+                    "0x00000000000000000000000000000000000000000000000000000000000000bb",
+                ],
+                "data": "0x00000000000000000000000000000000000000000000000000000000000003e8",
+            }],
+        }))
+        .unwrap();
+
+        let details = details(&tx, &receipt, Utc::now(), Some(1));
+        assert_eq!(details.transaction.method_id.as_deref(), Some("0xa9059cbb"));
+        assert_eq!((details.transaction.status, details.transaction.gas_used, details.transaction.effective_gas_price), (Some(true), Some(32768), Some(2)));
+        let fees = (details.transaction.fee.as_deref(), details.transaction.burnt_fee.as_deref(), details.transaction.priority_fee.as_deref());
+        assert_eq!(fees, (Some("65536"), Some("32768"), Some("32768")));
+        assert_eq!(details.token_transfers.len(), 1);
+        assert_eq!(details.token_transfers[0].value, "1000");
+        assert!(details.nft_transfers.is_empty());
+
+        let text = render(&details);
+        assert!(text.contains("status            success"));
+        assert!(text.contains("value             1.000000 ETH (1000000000000000000 wei)"));
+        assert!(text.contains("gas               32768 used of 65536 (50.0%)"));
+        assert!(text.contains("log 7    0x00000000000000000000000000000000000000cc Transfer(address,address,uint256)"));
+        assert!(text.contains("from (address) = 0x00000000000000000000000000000000000000aa"));
+        assert!(text.contains(
+            "token transfer    0x00000000000000000000000000000000000000cc 0x00000000000000000000000000000000000000aa -> 0x00000000000000000000000000000000000000bb: 1000"
+        ));
+    }
+}
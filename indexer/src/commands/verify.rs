@@ -0,0 +1,117 @@
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use serde_json::Value;
+
+use crate::rpc::{get_raw_block, get_raw_block_receipts};
+use crate::transform::hex_to_u64;
+use crate::trie::{encode_hex, encode_receipt, encode_transaction, ordered_trie_root};
+
+#[derive(Debug)]
+struct RootCheck {
+    number: u64,
+    transactions_root: (String, String),
+    receipts_root: (String, String),
+}
+
+impl RootCheck {
+    fn is_consistent(&self) -> bool {
+        self.transactions_root.0 == self.transactions_root.1 && self.receipts_root.0 == self.receipts_root.1
+    }
+}
+
+fn compute_roots(number: u64, block: &Value, receipts: &Value) -> Result<RootCheck> {
+    let transactions = block["transactions"].as_array().cloned().unwrap_or_default();
+    let mut receipts = receipts.as_array().cloned().unwrap_or_default();
+    receipts.sort_by_key(|r| r["transactionIndex"].as_str().map(hex_to_u64).unwrap_or(0));
+
+    let encoded_transactions = transactions.iter().map(encode_transaction).collect::<Result<Vec<_>>>()?;
+    let encoded_receipts = receipts.iter().map(encode_receipt).collect::<Result<Vec<_>>>()?;
+
+    let header_root = |key: &str| block[key].as_str().unwrap_or_default().to_lowercase();
+
+    Ok(RootCheck {
+        number,
+        transactions_root: (header_root("transactionsRoot"), encode_hex(&ordered_trie_root(encoded_transactions))),
+        receipts_root: (header_root("receiptsRoot"), encode_hex(&ordered_trie_root(encoded_receipts))),
+    })
+}
+
+async fn verify_block(number: u64) -> Result<RootCheck> {
+    let (block, receipts) = tokio::try_join!(get_raw_block(number), get_raw_block_receipts(number))?;
+    compute_roots(number, &block, &receipts)
+}
+
+// Recomputes transactionsRoot and receiptsRoot for a range and flags blocks that disagree with their header
+pub async fn run(start: u64, count: u64, concurrency: usize) -> Result<()> {
+    log::info!("Verifying trie roots for blocks {} to {}", start, start + count.saturating_sub(1));
+
+    let mut checks = stream::iter(start..start + count)
+        .map(|number| async move { (number, verify_block(number).await) })
+        .buffered(concurrency);
+
+    let mut verified = 0;
+    let mut inconsistent = Vec::new();
+    let mut failed = Vec::new();
+
+    while let Some((number, result)) = checks.next().await {
+        match result {
+            Ok(check) if check.is_consistent() => {
+                log::info!("Block {} roots match", check.number);
+                verified += 1;
+            },
+            Ok(check) => {
+                if check.transactions_root.0 != check.transactions_root.1 {
+                    println!(
+                        "Block {}: transactionsRoot mismatch (header {}, computed {})",
+                        check.number, check.transactions_root.0, check.transactions_root.1
+                    );
+                }
+                if check.receipts_root.0 != check.receipts_root.1 {
+                    println!(
+                        "Block {}: receiptsRoot mismatch (header {}, computed {})",
+                        check.number, check.receipts_root.0, check.receipts_root.1
+                    );
+                }
+                inconsistent.push(check.number);
+            },
+            Err(e) => {
+                println!("Block {}: could not verify: {}", number, e);
+                failed.push(number);
+            }
+        }
+    }
+
+    println!(
+        "Verified {} blocks: {} consistent, {} inconsistent, {} failed",
+        count, verified, inconsistent.len(), failed.len()
+    );
+
+    if !inconsistent.is_empty() || !failed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Verification failed for blocks {:?}",
+            inconsistent.iter().chain(failed.iter()).collect::<Vec<_>>()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_empty_block_roots_are_consistent() {
+        let empty_root = "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+        let block = json!({
+            "transactions": [],
+            "transactionsRoot": empty_root,
+            "receiptsRoot": empty_root.to_uppercase().replace("0X", "0x")
+        });
+        let check = compute_roots(1, &block, &json!([])).unwrap();
+        assert!(check.is_consistent());
+
+        let bad = json!({"transactions": [], "transactionsRoot": "0x00", "receiptsRoot": empty_root});
+        assert!(!compute_roots(1, &bad, &json!([])).unwrap().is_consistent());
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::env;
+use std::str::FromStr;
+
+// Reads an env var and parses it, falling back to `default` when unset
+pub fn env_or<T: FromStr>(key: &str, default: T) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => Ok(value.trim().parse::<T>()?),
+        _ => Ok(default),
+    }
+}
+
+fn cpu_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+// Tokio runtime sizing and per-stage concurrency limits.
+// Defaults scale with the CPU count so the same binary behaves on a 2-core VPS and a 64-core box.
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub worker_threads: usize,
+    pub max_blocking_threads: usize,
+    // Blocks fetched from the RPC concurrently (network bound)
+    pub fetch_concurrency: usize,
+    // Blocks transformed concurrently on the blocking pool (CPU bound)
+    pub transform_concurrency: usize,
+    // Files written concurrently on the blocking pool (disk bound)
+    pub write_concurrency: usize,
+}
+
+impl RuntimeConfig {
+    pub fn defaults_for(cpus: usize) -> Self {
+        let cpus = cpus.max(1);
+        RuntimeConfig {
+            worker_threads: cpus,
+            max_blocking_threads: (cpus * 4).max(16),
+            fetch_concurrency: (cpus * 2).clamp(4, 64),
+            transform_concurrency: cpus,
+            write_concurrency: (cpus * 2).clamp(2, 32),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::defaults_for(cpu_count());
+        let config = RuntimeConfig {
+            worker_threads: env_or("WORKER_THREADS", defaults.worker_threads)?,
+            max_blocking_threads: env_or("MAX_BLOCKING_THREADS", defaults.max_blocking_threads)?,
+            fetch_concurrency: env_or("FETCH_CONCURRENCY", defaults.fetch_concurrency)?,
+            transform_concurrency: env_or("TRANSFORM_CONCURRENCY", defaults.transform_concurrency)?,
+            write_concurrency: env_or("WRITE_CONCURRENCY", defaults.write_concurrency)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("WORKER_THREADS", self.worker_threads),
+            ("MAX_BLOCKING_THREADS", self.max_blocking_threads),
+            ("FETCH_CONCURRENCY", self.fetch_concurrency),
+            ("TRANSFORM_CONCURRENCY", self.transform_concurrency),
+            ("WRITE_CONCURRENCY", self.write_concurrency),
+        ] {
+            if value == 0 {
+                return Err(anyhow::anyhow!("{} must be greater than 0", name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build_runtime(&self) -> Result<tokio::runtime::Runtime> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.worker_threads)
+            .max_blocking_threads(self.max_blocking_threads)
+            .enable_all()
+            .build()?;
+        Ok(runtime)
+    }
+}
+
+// Runtime for embedders (Python, C) calling in from outside tokio; built once from the environment
+pub fn shared_runtime() -> Result<&'static tokio::runtime::Runtime> {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = RuntimeConfig::from_env()?.build_runtime()?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_scale_with_cpus() {
+        let small = RuntimeConfig::defaults_for(1);
+        assert_eq!(small.worker_threads, 1);
+        assert_eq!(small.max_blocking_threads, 16);
+        assert_eq!(small.fetch_concurrency, 4);
+        assert_eq!(small.write_concurrency, 2);
+
+        let large = RuntimeConfig::defaults_for(64);
+        assert_eq!(large.worker_threads, 64);
+        assert_eq!(large.max_blocking_threads, 256);
+        assert_eq!(large.fetch_concurrency, 64);
+        assert_eq!(large.transform_concurrency, 64);
+        assert_eq!(large.write_concurrency, 32);
+    }
+
+    #[test]
+    fn test_zero_limits_are_rejected() {
+        let mut config = RuntimeConfig::defaults_for(4);
+        assert!(config.validate().is_ok());
+        config.fetch_concurrency = 0;
+        assert!(config.validate().is_err());
+    }
+}
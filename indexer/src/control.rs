@@ -0,0 +1,285 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Notify;
+
+use crate::rpc;
+
+// Runtime state an operator can inspect and adjust through the admin socket
+#[derive(Default)]
+pub struct Control {
+    paused: AtomicBool,
+    resumed: Notify,
+    watch_addresses: RwLock<BTreeSet<String>>,
+    current_block: AtomicU64,
+    blocks_done: AtomicU64,
+    blocks_total: AtomicU64,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub async fn wait_while_paused(&self) {
+        loop {
+            let resumed = self.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    pub fn add_watch_address(&self, address: &str) -> bool {
+        self.watch_addresses.write().unwrap().insert(address.to_lowercase())
+    }
+
+    pub fn is_watched(&self, address: &str) -> bool {
+        let watched = self.watch_addresses.read().unwrap();
+        !watched.is_empty() && watched.contains(&address.to_lowercase())
+    }
+
+    // Starts counting the blocks of a new run; pause state and watch addresses carry over
+    pub fn start_run(&self, blocks: u64) {
+        self.blocks_total.store(blocks, Ordering::SeqCst);
+        self.blocks_done.store(0, Ordering::SeqCst);
+        self.current_block.store(0, Ordering::SeqCst);
+    }
+
+    pub fn start_block(&self, number: u64) {
+        self.current_block.fetch_max(number, Ordering::SeqCst);
+    }
+
+    pub fn finish_block(&self) {
+        self.blocks_done.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn status(&self) -> Value {
+        json!({
+            "paused": self.is_paused(),
+            "rps": rpc::rps(),
+            "current_block": self.current_block.load(Ordering::SeqCst),
+            "blocks_done": self.blocks_done.load(Ordering::SeqCst),
+            "blocks_total": self.blocks_total.load(Ordering::SeqCst),
+            "watch_addresses": *self.watch_addresses.read().unwrap(),
+        })
+    }
+}
+
+// Applies one admin command line, e.g. `set-rps 20`, and returns the JSON response
+pub fn handle_command(control: &Control, line: &str) -> Value {
+    let mut parts = line.split_whitespace();
+    let command = parts.next().unwrap_or_default();
+    let argument = parts.next();
+
+    let result: Result<Value> = match (command, argument) {
+        ("status", None) => Ok(control.status()),
+        ("pause", None) => {
+            control.pause();
+            log::warn!("Indexing paused via admin socket");
+            Ok(control.status())
+        },
+        ("resume", None) => {
+            control.resume();
+            log::warn!("Indexing resumed via admin socket");
+            Ok(control.status())
+        },
+        ("set-rps", Some(rps)) => rps
+            .parse::<u32>()
+            .map_err(|e| anyhow::anyhow!("Invalid rps {}: {}", rps, e))
+            .map(|rps| {
+                rpc::set_rps(rps);
+                log::warn!("RPC rate limit set to {} requests/s via admin socket", rps);
+                control.status()
+            }),
+        ("add-watch-address", Some(address)) => {
+            if control.add_watch_address(address) {
+                log::warn!("Watching address {} via admin socket", address);
+            }
+            Ok(control.status())
+        },
+        _ => Err(anyhow::anyhow!("Unknown command: {}", line.trim())),
+    };
+
+    match result {
+        Ok(status) => json!({ "ok": true, "status": status }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+async fn handle_connection(control: Arc<Control>, stream: UnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_command(&control, &line);
+        writer.write_all(format!("{}\n", response).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+static SHARED: OnceLock<Arc<Control>> = OnceLock::new();
+static ADMIN_SOCKET: AtomicBool = AtomicBool::new(false);
+
+// The control state of the process, serving the admin socket at `socket` once it binds.
+// `daemon` and work stealing index through many runs, which all share it, so a pause or a watch
+// address sent with `ctl` outlives the run it arrived during. A socket that fails to bind or stops
+// is opened again by the next run.
+pub fn shared(socket: Option<PathBuf>) -> Arc<Control> {
+    let control = Arc::clone(SHARED.get_or_init(|| Arc::new(Control::new())));
+    let Some(socket) = socket else {
+        return control;
+    };
+    if ADMIN_SOCKET.swap(true, Ordering::SeqCst) {
+        return control;
+    }
+    match bind_admin_socket(&socket) {
+        Ok(listener) => {
+            let control = Arc::clone(&control);
+            tokio::spawn(async move {
+                if let Err(e) = accept_admin_connections(control, listener).await {
+                    log::error!("Admin socket stopped: {}", e);
+                }
+                ADMIN_SOCKET.store(false, Ordering::SeqCst);
+            });
+        }
+        Err(e) => {
+            ADMIN_SOCKET.store(false, Ordering::SeqCst);
+            log::error!("Could not open admin socket {}: {}", socket.display(), e);
+        }
+    }
+    control
+}
+
+// Serves line-based admin commands on a Unix socket until the process exits
+pub async fn serve_admin_socket(control: Arc<Control>, path: PathBuf) -> Result<()> {
+    let listener = bind_admin_socket(&path)?;
+    accept_admin_connections(control, listener).await
+}
+
+fn bind_admin_socket(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("Admin socket listening on {}", path.display());
+    Ok(listener)
+}
+
+async fn accept_admin_connections(control: Arc<Control>, listener: UnixListener) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let control = Arc::clone(&control);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(control, stream).await {
+                log::warn!("Admin connection error: {}", e);
+            }
+        });
+    }
+}
+
+// Client side used by `ctl`: sends one command and returns the response
+pub async fn send_command(path: &Path, command: &str) -> Result<Value> {
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not connect to admin socket {}: {}", path.display(), e))?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{}\n", command).as_bytes()).await?;
+    writer.shutdown().await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Admin socket closed without a response"))?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_commands() {
+        let control = Control::new();
+
+        let response = handle_command(&control, "pause");
+        assert_eq!(response["ok"], json!(true));
+        assert!(control.is_paused());
+
+        handle_command(&control, "resume");
+        assert!(!control.is_paused());
+
+        handle_command(&control, "add-watch-address 0xABCdef");
+        assert!(control.is_watched("0xabcdef"));
+        assert!(!control.is_watched("0x123"));
+
+        let response = handle_command(&control, "set-rps fast");
+        assert_eq!(response["ok"], json!(false));
+
+        let response = handle_command(&control, "explode");
+        assert_eq!(response["ok"], json!(false));
+
+        // A new run restarts the counts but keeps what the operator set
+        control.pause();
+        control.start_block(7);
+        control.finish_block();
+        control.start_run(3);
+        let status = control.status();
+        assert_eq!((status["blocks_done"].clone(), status["blocks_total"].clone(), status["current_block"].clone()), (json!(0), json!(3), json!(0)));
+        assert!(control.is_paused() && control.is_watched("0xabcdef"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_while_paused_returns_on_resume() {
+        let control = Arc::new(Control::new());
+        control.pause();
+
+        let waiter = {
+            let control = Arc::clone(&control);
+            tokio::spawn(async move { control.wait_while_paused().await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        control.resume();
+        waiter.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shared_serves_admin_socket_only_once_bound() {
+        let dir = std::env::temp_dir().join(format!("sweet-potato-control-{}", std::process::id()));
+        let socket = dir.join("admin.sock");
+        // The directory is missing, so binding fails and the next run gets to try again
+        let control = shared(Some(socket.clone()));
+        assert!(!ADMIN_SOCKET.load(Ordering::SeqCst));
+
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(Arc::ptr_eq(&shared(Some(socket.clone())), &control));
+        assert!(ADMIN_SOCKET.load(Ordering::SeqCst));
+        let response = send_command(&socket, "status").await.unwrap();
+        assert_eq!(response["ok"], json!(true));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
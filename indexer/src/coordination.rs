@@ -0,0 +1,417 @@
+//! Work stealing between indexer replicas that share `RAW_DATA_PATH` (a shared volume or bucket
+//! mount). A backfill range is split into chunks; a replica claims a chunk by creating its lease
+//! file, renews the lease while indexing it and marks it done afterwards. Leases that are not
+//! renewed expire, so chunks of crashed or scaled-down replicas are picked up by the others.
+//!
+//! Outside work stealing, [`InstanceLock`] keeps two processes from indexing overlapping ranges of
+//! the same chain into one `RAW_DATA_PATH` (and so into the same sinks). Each run holds an OS file
+//! lock on `raw_data/locks/<chain>_<first>_<last>_<replica>.lock`, which the OS releases when the
+//! process dies, so a crashed run never blocks the next one.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct WorkStealingConfig {
+    /// Split the range into chunks claimed through lease files, so every replica started on the same range and RAW_DATA_PATH takes a share
+    #[arg(long, env = "WORK_STEALING")]
+    pub work_stealing: bool,
+
+    /// Blocks per claimed chunk
+    #[arg(long, env = "WORK_CHUNK_BLOCKS", default_value_t = 1000)]
+    pub work_chunk_blocks: u64,
+
+    /// Seconds a claim stays valid without renewal before another replica may steal it
+    #[arg(long, env = "WORK_LEASE_SECS", default_value_t = 300)]
+    pub work_lease_secs: u64,
+}
+
+// Pod name under Kubernetes, plus the pid so replicas on one host differ too
+pub fn replica_id() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "local".to_string());
+    format!("{}-{}", host, std::process::id())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl Chunk {
+    pub fn count(&self) -> u64 {
+        self.last - self.first + 1
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    owner: String,
+    // Unix seconds
+    expires_at: i64,
+}
+
+pub struct WorkQueue {
+    dir: PathBuf,
+    chunks: Vec<Chunk>,
+    owner: String,
+    lease: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl WorkQueue {
+    // Replicas coordinate through `<root>/work/<start>_<count>_<chunk size>`, so they must agree on all three
+    pub fn open(root: &str, start: u64, count: u64, chunk_blocks: u64, lease: Duration, owner: String) -> Result<Self> {
+        if chunk_blocks == 0 {
+            return Err(anyhow::anyhow!("WORK_CHUNK_BLOCKS must be greater than 0"));
+        }
+        let dir = Path::new(root).join("work").join(format!("{}_{}_{}", start, count, chunk_blocks));
+        fs::create_dir_all(&dir)?;
+        let end = start + count;
+        let chunks = (start..end)
+            .step_by(chunk_blocks as usize)
+            .map(|first| Chunk { first, last: (first + chunk_blocks).min(end) - 1 })
+            .collect();
+        Ok(Self { dir, chunks, owner, lease, clock: clock::current() })
+    }
+
+    // Lease expiry is judged by this clock instead of the process clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    fn lease_path(&self, chunk: &Chunk) -> PathBuf {
+        self.dir.join(format!("{}.lease", chunk.first))
+    }
+
+    fn done_path(&self, chunk: &Chunk) -> PathBuf {
+        self.dir.join(format!("{}.done", chunk.first))
+    }
+
+    fn new_lease(&self) -> Lease {
+        Lease { owner: self.owner.clone(), expires_at: self.clock.now().timestamp() + self.lease.as_secs() as i64 }
+    }
+
+    // None while the lease file is being written; a file left empty by a crash counts as expired
+    // once it is older than a lease
+    fn read_lease(&self, chunk: &Chunk) -> Option<Lease> {
+        let path = self.lease_path(chunk);
+        let content = fs::read(&path).ok()?;
+        match serde_json::from_slice(&content) {
+            Ok(lease) => Some(lease),
+            Err(_) => {
+                let modified: DateTime<Utc> = fs::metadata(&path).and_then(|m| m.modified()).ok()?.into();
+                let age = (self.clock.now() - modified).to_std().unwrap_or_default();
+                (age > self.lease).then(|| Lease { owner: String::new(), expires_at: 0 })
+            },
+        }
+    }
+
+    fn is_live(&self, chunk: &Chunk) -> bool {
+        match self.read_lease(chunk) {
+            Some(lease) => lease.expires_at > self.clock.now().timestamp(),
+            None => self.lease_path(chunk).exists(),
+        }
+    }
+
+    // Replaces the lease file in one rename; when two replicas steal at once the last rename wins
+    // and the other one sees a foreign owner on read-back
+    fn write_lease(&self, chunk: &Chunk) -> Result<bool> {
+        let tmp = self.dir.join(format!("{}.lease.{}.tmp", chunk.first, self.owner));
+        fs::write(&tmp, serde_json::to_vec(&self.new_lease())?)?;
+        fs::rename(&tmp, self.lease_path(chunk))?;
+        Ok(self.read_lease(chunk).is_some_and(|lease| lease.owner == self.owner))
+    }
+
+    // Claims the first chunk that is neither done nor under a live lease
+    pub fn claim_next(&self) -> Result<Option<Chunk>> {
+        for chunk in &self.chunks {
+            if self.done_path(chunk).exists() {
+                continue;
+            }
+            match fs::OpenOptions::new().write(true).create_new(true).open(self.lease_path(chunk)) {
+                Ok(mut file) => {
+                    file.write_all(&serde_json::to_vec(&self.new_lease())?)?;
+                    return Ok(Some(*chunk));
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !self.is_live(chunk) && self.write_lease(chunk)? {
+                        log::warn!("Took over blocks {} to {} from an expired lease", chunk.first, chunk.last);
+                        return Ok(Some(*chunk));
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    // Extends our lease; false when another replica has taken the chunk over
+    pub fn renew(&self, chunk: &Chunk) -> Result<bool> {
+        if self.read_lease(chunk).is_none_or(|lease| lease.owner != self.owner) {
+            return Ok(false);
+        }
+        self.write_lease(chunk)
+    }
+
+    pub fn complete(&self, chunk: &Chunk) -> Result<()> {
+        fs::write(self.done_path(chunk), &self.owner)?;
+        let _ = fs::remove_file(self.lease_path(chunk));
+        Ok(())
+    }
+
+    // Gives an unfinished chunk back so another replica can claim it right away
+    pub fn release(&self, chunk: &Chunk) {
+        if self.read_lease(chunk).is_some_and(|lease| lease.owner == self.owner) {
+            let _ = fs::remove_file(self.lease_path(chunk));
+        }
+    }
+
+    // Blocks no replica is working on yet
+    pub fn unclaimed_blocks(&self) -> u64 {
+        self.chunks
+            .iter()
+            .filter(|chunk| !self.done_path(chunk).exists() && !self.is_live(chunk))
+            .map(Chunk::count)
+            .sum()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.chunks.iter().all(|chunk| self.done_path(chunk).exists())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LockOwner {
+    owner: String,
+    chain_id: u64,
+    first: u64,
+    last: u64,
+    started_at: DateTime<Utc>,
+}
+
+// Held for the duration of a run; dropping it releases the lock and removes the file
+pub struct InstanceLock {
+    path: PathBuf,
+    file: fs::File,
+}
+
+// Attempts at publishing our lock when another process removes it in the meantime
+const LOCK_ATTEMPTS: usize = 3;
+
+// Whether `file` is still the one at `path`, rather than one unlinked or replaced since it was opened
+#[cfg(unix)]
+fn is_linked(file: &fs::File, path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let linked = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let open = file.metadata()?;
+    Ok(open.dev() == linked.dev() && open.ino() == linked.ino())
+}
+
+// Open files can be neither removed nor replaced elsewhere
+#[cfg(not(unix))]
+fn is_linked(_file: &fs::File, path: &Path) -> Result<bool> {
+    Ok(path.exists())
+}
+
+impl InstanceLock {
+    // Locks blocks `first` to `last` of the chain, refusing when a live process holds a lock on an
+    // overlapping range. Chain 0 stands for an unknown chain and overlaps every chain. Lock files of
+    // dead processes are removed on the way.
+    pub fn acquire(root: &str, chain_id: u64, first: u64, last: u64, owner: String) -> Result<Self> {
+        let dir = Path::new(root).join("locks");
+        fs::create_dir_all(&dir)?;
+        let me = LockOwner { owner, chain_id, first, last, started_at: clock::now() };
+        for _ in 0..LOCK_ATTEMPTS {
+            let lock = Self::publish(&dir, &me)?;
+            // Taking ours first means two processes starting at once both see the other and both
+            // refuse, rather than both going ahead
+            lock.check_others(&dir, &me)?;
+            // A process that opened an earlier file at our path may have mistaken ours for it
+            if is_linked(&lock.file, &lock.path)? {
+                return Ok(lock);
+            }
+            log::debug!("Lock {} was removed while it was taken, taking it again", lock.path.display());
+        }
+        Err(anyhow::anyhow!("Could not keep a lock in {} for blocks {} to {}", dir.display(), first, last))
+    }
+
+    // Writes the owner record to a locked file and renames it into place, so every lock file another
+    // process finds is complete and held
+    fn publish(dir: &Path, me: &LockOwner) -> Result<Self> {
+        let path = dir.join(format!("{}_{}_{}_{}.lock", me.chain_id, me.first, me.last, me.owner));
+        let tmp = path.with_extension("lock.tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.try_lock().map_err(|e| anyhow::anyhow!("Could not lock {}: {}", tmp.display(), e))?;
+        file.write_all(&serde_json::to_vec(me)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(Self { path, file })
+    }
+
+    fn check_others(&self, dir: &Path, me: &LockOwner) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let other = entry?.path();
+            if other == self.path || other.extension().is_none_or(|ext| ext != "lock") {
+                continue;
+            }
+            let Ok(mut file) = fs::File::open(&other) else { continue };
+            let mut data = Vec::new();
+            let held = file.read_to_end(&mut data).ok().and_then(|_| serde_json::from_slice::<LockOwner>(&data).ok());
+            match file.try_lock() {
+                Ok(()) => {
+                    // Removed only while it is still the file we locked, not one published since
+                    if is_linked(&file, &other)? {
+                        match &held {
+                            Some(held) => log::warn!("Removing the lock of blocks {} to {} left by {}, which is no longer running", held.first, held.last, held.owner),
+                            None => log::warn!("Removing {}, left by a process that is no longer running", other.display()),
+                        }
+                        let _ = fs::remove_file(&other);
+                    }
+                },
+                Err(fs::TryLockError::WouldBlock) => {
+                    // Lock files are published whole, so a live one that cannot be read was not
+                    // written by this version; its range is unknown
+                    let Some(held) = held else {
+                        return Err(anyhow::anyhow!("{} is held by a running process but cannot be read; stop that process or remove the file", other.display()));
+                    };
+                    let same_chain = held.chain_id == me.chain_id || held.chain_id == 0 || me.chain_id == 0;
+                    if !same_chain || held.last < me.first || held.first > me.last {
+                        continue;
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Blocks {} to {} of chain {} are being indexed by {} since {} ({}); wait for it, pick a range that does \
+                         not overlap, or run every replica with --work-stealing to share the range",
+                        held.first,
+                        held.last,
+                        me.chain_id,
+                        held.owner,
+                        held.started_at,
+                        other.display()
+                    ));
+                },
+                Err(fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if is_linked(&self.file, &self.path).unwrap_or(false) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_steal_and_complete() {
+        let root = "test_coordination_work_queue";
+        let _ = fs::remove_dir_all(root);
+        let open = |owner: &str, lease_secs: u64| {
+            WorkQueue::open(root, 0, 25, 10, Duration::from_secs(lease_secs), owner.to_string()).unwrap()
+        };
+        let (a, b, c) = (open("a", 300), open("b", 300), open("c", 0));
+
+        assert_eq!(a.claim_next().unwrap(), Some(Chunk { first: 0, last: 9 }));
+        assert_eq!(b.claim_next().unwrap(), Some(Chunk { first: 10, last: 19 }));
+        assert_eq!(a.unclaimed_blocks(), 5);
+        a.complete(&Chunk { first: 0, last: 9 }).unwrap();
+
+        // c's lease expires immediately, so b takes its chunk over
+        let last = Chunk { first: 20, last: 24 };
+        assert_eq!(c.claim_next().unwrap(), Some(last));
+        assert_eq!(b.claim_next().unwrap(), Some(last));
+        assert!(!c.renew(&last).unwrap());
+        assert!(b.renew(&last).unwrap());
+        assert_eq!(a.claim_next().unwrap(), None);
+        assert!(!a.is_finished());
+
+        b.complete(&Chunk { first: 10, last: 19 }).unwrap();
+        b.complete(&last).unwrap();
+        assert!(a.is_finished());
+        assert_eq!(a.unclaimed_blocks(), 0);
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_lease_expires_on_the_clock() {
+        let root = "test_coordination_clock";
+        let _ = fs::remove_dir_all(root);
+        let clock = clock::ManualClock::new(Utc::now());
+        let open = |owner: &str| {
+            WorkQueue::open(root, 0, 10, 10, Duration::from_secs(300), owner.to_string()).unwrap().with_clock(clock.clone())
+        };
+        let (a, b) = (open("a"), open("b"));
+        let chunk = Chunk { first: 0, last: 9 };
+
+        assert_eq!(a.claim_next().unwrap(), Some(chunk));
+        clock.advance(Duration::from_secs(299));
+        assert_eq!(b.claim_next().unwrap(), None);
+        // Renewal pushes the expiry out from the clock's current time
+        assert!(a.renew(&chunk).unwrap());
+        clock.advance(Duration::from_secs(299));
+        assert_eq!(b.claim_next().unwrap(), None);
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(b.claim_next().unwrap(), Some(chunk));
+        assert!(!a.renew(&chunk).unwrap());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_instance_lock_refuses_overlapping_ranges() {
+        let root = "test_coordination_instance_lock";
+        let _ = fs::remove_dir_all(root);
+        let first = InstanceLock::acquire(root, 1, 100, 199, "a".to_string()).unwrap();
+        let error = InstanceLock::acquire(root, 1, 150, 249, "b".to_string()).err().unwrap();
+        assert!(error.to_string().starts_with("Blocks 100 to 199 of chain 1 are being indexed by a since"));
+        // Other chains and ranges next to it are free
+        let other_chain = InstanceLock::acquire(root, 10, 150, 249, "b".to_string()).unwrap();
+        let after = InstanceLock::acquire(root, 1, 200, 299, "c".to_string()).unwrap();
+
+        drop(first);
+        let again = InstanceLock::acquire(root, 1, 150, 199, "b".to_string()).unwrap();
+        assert_eq!(fs::read_dir(format!("{}/locks", root)).unwrap().count(), 3);
+        drop((other_chain, after, again));
+        assert_eq!(fs::read_dir(format!("{}/locks", root)).unwrap().count(), 0);
+
+        // A lock file nobody holds was left by a dead process, readable or not
+        let stale = format!("{}/locks/1_0_999_dead.lock", root);
+        fs::write(&stale, "{").unwrap();
+        drop(InstanceLock::acquire(root, 1, 100, 199, "a".to_string()).unwrap());
+        assert!(!Path::new(&stale).exists());
+        // One that is held but cannot be read has an unknown range
+        let held = fs::File::create(&stale).unwrap();
+        held.try_lock().unwrap();
+        let error = InstanceLock::acquire(root, 10, 100, 199, "a".to_string()).err().unwrap();
+        assert!(error.to_string().ends_with("is held by a running process but cannot be read; stop that process or remove the file"));
+        drop(held);
+
+        // A lock replaced at its path is not removed by the run that held the old file
+        let lock = InstanceLock::acquire(root, 1, 100, 199, "a".to_string()).unwrap();
+        let path = lock.path.clone();
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "{}").unwrap();
+        drop(lock);
+        assert!(path.exists());
+        let _ = fs::remove_dir_all(root);
+    }
+}
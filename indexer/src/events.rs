@@ -0,0 +1,164 @@
+//! Decoding of standard token events from the raw RPC logs kept in receipts.
+
+use primitive_types::U256;
+use serde_json::Value;
+
+use crate::trie::decode_hex;
+
+// keccak256("Transfer(address,address,uint256)"), shared by ERC-20 and ERC-721
+pub const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+// keccak256("Approval(address,address,uint256)")
+pub const APPROVAL_TOPIC: &str = "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+// keccak256("TransferSingle(address,address,address,uint256,uint256)")
+pub const TRANSFER_SINGLE_TOPIC: &str = "0xc3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62";
+// keccak256("TransferBatch(address,address,address,uint256[],uint256[])")
+pub const TRANSFER_BATCH_TOPIC: &str = "0x4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb";
+
+pub const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenEvent {
+    // ERC-20 Transfer: the amount is in the data
+    Transfer { token: String, from: String, to: String, value: U256 },
+    // ERC-721 Transfer: the token id is the third indexed topic
+    NftTransfer { token: String, from: String, to: String, token_id: U256 },
+    // ERC-20 Approval; ERC-721 approvals of a single token are not decoded
+    Approval { token: String, owner: String, spender: String, value: U256 },
+    // ERC-1155 TransferSingle (one id) or TransferBatch (parallel id and amount arrays)
+    MultiTransfer { token: String, operator: String, from: String, to: String, ids: Vec<U256>, amounts: Vec<U256> },
+}
+
+// Address held in the low 20 bytes of a 32-byte topic
+pub fn topic_address(topic: &str) -> Option<String> {
+    let digits = topic.strip_prefix("0x").unwrap_or(topic);
+    (digits.len() == 64).then(|| format!("0x{}", digits[24..].to_lowercase()))
+}
+
+// A 32-byte word as an unsigned integer
+pub fn word(hex: &str) -> Option<U256> {
+    let bytes = decode_hex(hex).ok()?;
+    (bytes.len() == 32).then(|| U256::from_big_endian(&bytes))
+}
+
+// ABI `uint256[]` whose head word sits at `index` in `data`
+fn word_array(data: &[u8], index: usize) -> Option<Vec<U256>> {
+    let word_at = |offset: usize| data.get(offset..offset + 32).map(U256::from_big_endian);
+    let small = |word: U256| (word <= U256::from(u32::MAX)).then(|| word.as_usize());
+    let offset = small(word_at(index * 32)?)?;
+    let length = small(word_at(offset)?)?;
+    // Every element needs 32 bytes; reject lengths the data cannot hold before allocating
+    if length > data.len() / 32 {
+        return None;
+    }
+    (0..length).map(|i| word_at(offset + 32 + i * 32)).collect()
+}
+
+pub fn decode(log: &Value) -> Option<TokenEvent> {
+    let topics: Vec<&str> = log["topics"].as_array()?.iter().filter_map(Value::as_str).collect();
+    let token = log["address"].as_str()?.to_lowercase();
+    let data = log["data"].as_str().unwrap_or("0x");
+    let topic0 = topics.first()?.to_lowercase();
+
+    match (topic0.as_str(), topics.len()) {
+        (TRANSFER_TOPIC, 3) => Some(TokenEvent::Transfer {
+            token,
+            from: topic_address(topics[1])?,
+            to: topic_address(topics[2])?,
+            value: word(data)?,
+        }),
+        (TRANSFER_TOPIC, 4) => Some(TokenEvent::NftTransfer {
+            token,
+            from: topic_address(topics[1])?,
+            to: topic_address(topics[2])?,
+            token_id: word(topics[3])?,
+        }),
+        (TRANSFER_SINGLE_TOPIC, 4) => {
+            let data = decode_hex(data).ok()?;
+            if data.len() != 64 {
+                return None;
+            }
+            Some(TokenEvent::MultiTransfer {
+                token,
+                operator: topic_address(topics[1])?,
+                from: topic_address(topics[2])?,
+                to: topic_address(topics[3])?,
+                ids: vec![U256::from_big_endian(&data[..32])],
+                amounts: vec![U256::from_big_endian(&data[32..])],
+            })
+        },
+        (TRANSFER_BATCH_TOPIC, 4) => {
+            let data = decode_hex(data).ok()?;
+            let (ids, amounts) = (word_array(&data, 0)?, word_array(&data, 1)?);
+            if ids.len() != amounts.len() {
+                return None;
+            }
+            Some(TokenEvent::MultiTransfer {
+                token,
+                operator: topic_address(topics[1])?,
+                from: topic_address(topics[2])?,
+                to: topic_address(topics[3])?,
+                ids,
+                amounts,
+            })
+        },
+        (APPROVAL_TOPIC, 3) => Some(TokenEvent::Approval {
+            token,
+            owner: topic_address(topics[1])?,
+            spender: topic_address(topics[2])?,
+            value: word(data)?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::{encode_hex, keccak256};
+    use serde_json::json;
+
+    fn padded(address: &str) -> String {
+        format!("0x{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    #[test]
+    fn test_decode_token_events() {
+        assert_eq!(TRANSFER_TOPIC, encode_hex(&keccak256(b"Transfer(address,address,uint256)")));
+        assert_eq!(APPROVAL_TOPIC, encode_hex(&keccak256(b"Approval(address,address,uint256)")));
+        assert_eq!(TRANSFER_SINGLE_TOPIC, encode_hex(&keccak256(b"TransferSingle(address,address,address,uint256,uint256)")));
+        assert_eq!(TRANSFER_BATCH_TOPIC, encode_hex(&keccak256(b"TransferBatch(address,address,address,uint256[],uint256[])")));
+
+        let erc20 = json!({
+            "address": "0xTOKEN", "data": padded("0x3e8"),
+            "topics": [TRANSFER_TOPIC, padded("0xaa"), padded("0xBB")],
+        });
+        assert_eq!(
+            decode(&erc20),
+            Some(TokenEvent::Transfer {
+                token: "0xtoken".to_string(),
+                from: format!("0x{:0>40}", "aa"),
+                to: format!("0x{:0>40}", "bb"),
+                value: U256::from(1000),
+            })
+        );
+
+        let erc721 = json!({ "address": "0xnft", "data": "0x", "topics": [TRANSFER_TOPIC, padded("0x0"), padded("0xaa"), padded("0x7")] });
+        assert!(matches!(decode(&erc721), Some(TokenEvent::NftTransfer { token_id, .. }) if token_id == U256::from(7)));
+
+        // ids [1, 2], amounts [10, 20]
+        let words = ["40", "a0", "2", "1", "2", "2", "a", "14"];
+        let data = format!("0x{}", words.iter().map(|w| format!("{:0>64}", w)).collect::<String>());
+        let batch = json!({ "address": "0xmulti", "data": data, "topics": [TRANSFER_BATCH_TOPIC, padded("0xcc"), padded("0xaa"), padded("0xbb")] });
+        match decode(&batch) {
+            Some(TokenEvent::MultiTransfer { ids, amounts, operator, .. }) => {
+                assert_eq!(ids, vec![U256::from(1), U256::from(2)]);
+                assert_eq!(amounts, vec![U256::from(10), U256::from(20)]);
+                assert_eq!(operator, format!("0x{:0>40}", "cc"));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let malformed = json!({ "address": "0xtoken", "data": "0x01", "topics": [TRANSFER_TOPIC, padded("0xaa"), padded("0xbb")] });
+        assert_eq!(decode(&malformed), None);
+    }
+}
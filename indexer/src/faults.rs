@@ -0,0 +1,181 @@
+//! Fault injection for chaos testing: delays, fails or corrupts RPC responses and fails output
+//! writes at configured rates. Everything is off unless one of the `FAULT_*` rates is set.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::clock;
+use crate::config::env_or;
+
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    // Probabilities between 0 and 1, checked independently per call
+    pub rpc_fail_rate: f64,
+    pub rpc_delay_rate: f64,
+    pub rpc_delay: Duration,
+    pub rpc_corrupt_rate: f64,
+    pub write_fail_rate: f64,
+    // Same seed, same sequence of faults for the same sequence of calls
+    pub seed: u64,
+}
+
+impl FaultConfig {
+    pub fn from_env() -> Result<Self> {
+        let config = Self {
+            rpc_fail_rate: env_or("FAULT_RPC_FAIL_RATE", 0.0)?,
+            rpc_delay_rate: env_or("FAULT_RPC_DELAY_RATE", 0.0)?,
+            rpc_delay: Duration::from_millis(env_or("FAULT_RPC_DELAY_MS", 1000)?),
+            rpc_corrupt_rate: env_or("FAULT_RPC_CORRUPT_RATE", 0.0)?,
+            write_fail_rate: env_or("FAULT_WRITE_FAIL_RATE", 0.0)?,
+            seed: env_or("FAULT_SEED", 0x5eed)?,
+        };
+        for rate in [config.rpc_fail_rate, config.rpc_delay_rate, config.rpc_corrupt_rate, config.write_fail_rate] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(anyhow::anyhow!("Fault rates must be between 0 and 1, got {}", rate));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rpc_fail_rate > 0.0 || self.rpc_delay_rate > 0.0 || self.rpc_corrupt_rate > 0.0 || self.write_fail_rate > 0.0
+    }
+}
+
+// xorshift64*; statistical quality is plenty for picking faults
+struct Injector {
+    config: FaultConfig,
+    state: u64,
+}
+
+impl Injector {
+    fn new(config: FaultConfig) -> Self {
+        let state = config.seed.max(1);
+        Self { config, state }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn roll(&mut self, rate: f64) -> bool {
+        let sample = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        rate > 0.0 && sample < rate
+    }
+
+    // Either drops a field of an object or garbles a string, somewhere in the value
+    fn corrupt(&mut self, value: &mut Value) -> bool {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                let index = (self.next() % map.len() as u64) as usize;
+                if self.next().is_multiple_of(2) {
+                    let key = map.keys().nth(index).cloned().unwrap_or_default();
+                    map.shift_remove(&key);
+                    true
+                } else {
+                    self.corrupt(map.values_mut().nth(index).unwrap())
+                }
+            },
+            Value::Array(items) if !items.is_empty() => {
+                let index = (self.next() % items.len() as u64) as usize;
+                self.corrupt(&mut items[index])
+            },
+            Value::String(text) => {
+                *text = format!("{}zz", text.trim_end_matches(|c: char| c.is_ascii_hexdigit()));
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static INJECTOR: Mutex<Option<Injector>> = Mutex::new(None);
+
+pub fn configure(config: FaultConfig) {
+    if config.is_enabled() {
+        log::warn!("Fault injection enabled: {:?}", config);
+    }
+    ENABLED.store(config.is_enabled(), Ordering::SeqCst);
+    *INJECTOR.lock().unwrap() = Some(Injector::new(config));
+}
+
+fn with_injector<T>(f: impl FnOnce(&mut Injector) -> T) -> Option<T> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    INJECTOR.lock().unwrap().as_mut().map(f)
+}
+
+// Called before every RPC request: may sleep, then may fail it
+pub async fn before_rpc(method: &str) -> Result<()> {
+    let Some((delay, fail)) = with_injector(|injector| {
+        let config = injector.config.clone();
+        (injector.roll(config.rpc_delay_rate).then_some(config.rpc_delay), injector.roll(config.rpc_fail_rate))
+    }) else {
+        return Ok(());
+    };
+    if let Some(delay) = delay {
+        log::debug!("Injected {:?} delay into {}", delay, method);
+        clock::sleep(delay).await;
+    }
+    if fail {
+        return Err(anyhow::anyhow!("Injected fault: {} failed", method));
+    }
+    Ok(())
+}
+
+// Called on every RPC result before it is parsed
+pub fn corrupt_rpc(method: &str, result: &mut Value) {
+    let corrupted = with_injector(|injector| {
+        let rate = injector.config.rpc_corrupt_rate;
+        injector.roll(rate) && injector.corrupt(result)
+    });
+    if corrupted == Some(true) {
+        log::debug!("Injected corruption into the {} response", method);
+    }
+}
+
+// Called before every output file or sink write
+pub fn before_write(target: &str) -> Result<()> {
+    match with_injector(|injector| injector.roll(injector.config.write_fail_rate)) {
+        Some(true) => Err(anyhow::anyhow!("Injected fault: write to {} failed", target)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_injector() {
+        let config = FaultConfig { rpc_fail_rate: 0.25, seed: 42, ..Default::default() };
+        let mut injector = Injector::new(config.clone());
+        let failures = (0..10_000).filter(|_| injector.roll(0.25)).count();
+        assert!((2300..2700).contains(&failures), "{} failures", failures);
+        assert!(!(0..1000).any(|_| injector.roll(0.0)));
+        assert!((0..1000).all(|_| injector.roll(1.0)));
+
+        // Same seed, same faults
+        let sequence = |config: &FaultConfig| {
+            let mut injector = Injector::new(config.clone());
+            (0..64).map(|_| injector.roll(0.5)).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(&config), sequence(&config));
+
+        let original = json!({ "number": "0x10", "transactions": [{ "hash": "0xab" }] });
+        for _ in 0..20 {
+            let mut value = original.clone();
+            assert!(injector.corrupt(&mut value));
+            assert_ne!(value, original);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! C ABI (`ffi` feature) for embedding the indexer in non-Rust services.
+//! The matching declarations are in `include/sweet_potato.h`.
+//!
+//! Every function returns 0 on success and -1 on failure; the message for the last failure
+//! on the calling thread is available from `sp_last_error`. Records are passed to callbacks
+//! as NUL-terminated JSON strings that are only valid for the duration of the call.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::time::Duration;
+
+use crate::clock;
+use crate::config::{shared_runtime, RuntimeConfig};
+use crate::pipeline;
+use crate::rpc;
+use crate::store::{self, Compression, OutputConfig, OutputFormat};
+use crate::transform::transform_block_data;
+
+pub const SP_OK: c_int = 0;
+pub const SP_ERROR: c_int = -1;
+
+// Receives one JSON record; returning non-zero stops the call early
+pub type RecordCallback = extern "C" fn(record_json: *const c_char, user_data: *mut c_void) -> c_int;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => SP_OK,
+        Err(e) => {
+            set_last_error(e.to_string());
+            SP_ERROR
+        },
+    }
+}
+
+// NULL means "use the default"
+unsafe fn optional_str(ptr: *const c_char) -> Result<Option<String>> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(ptr).to_str()?.to_string()))
+}
+
+fn emit<T: Serialize>(callback: RecordCallback, record: &T, user_data: *mut c_void) -> Result<bool> {
+    let json = CString::new(serde_json::to_string(record)?)?;
+    Ok(callback(json.as_ptr(), user_data) == 0)
+}
+
+/// Version of the library as a static NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn sp_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Message for the last failed call on this thread, or NULL. Owned by the library and valid
+/// until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn sp_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+/// Fetches `count` blocks from `start` and writes them under `raw_data_path` (NULL for
+/// `RAW_DATA_PATH`). `output_format` is "json" or "ndjson" and `compress` is "none", "gzip"
+/// or "zstd"; NULL selects the default.
+///
+/// # Safety
+/// String arguments must be NULL or valid NUL-terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn sp_index_range(
+    start: u64,
+    count: u64,
+    raw_data_path: *const c_char,
+    output_format: *const c_char,
+    compress: *const c_char,
+) -> c_int {
+    status((|| {
+        let mut output = OutputConfig::default();
+        if let Some(format) = optional_str(output_format)? {
+            output.format = OutputFormat::from_str(&format, true).map_err(anyhow::Error::msg)?;
+        }
+        if let Some(compress) = optional_str(compress)? {
+            output.compress = Compression::from_str(&compress, true).map_err(anyhow::Error::msg)?;
+        }
+        let raw_data_path = optional_str(raw_data_path)?.unwrap_or_else(store::raw_data_path);
+        let concurrency = RuntimeConfig::from_env()?.fetch_concurrency;
+        shared_runtime()?.block_on(pipeline::index_range(start, count, &raw_data_path, output, concurrency))?;
+        Ok(())
+    })())
+}
+
+/// Follows the chain from `start`, calling `callback` once per block in order with
+/// `{"block": .., "transactions": [..], "receipts": [..]}`. Waits `poll_interval_ms` whenever it
+/// reaches the chain head, and returns when the callback returns non-zero.
+///
+/// # Safety
+/// `callback` must be safe to call with `user_data` from the calling thread.
+#[no_mangle]
+pub unsafe extern "C" fn sp_follow(start: u64, poll_interval_ms: u64, callback: RecordCallback, user_data: *mut c_void) -> c_int {
+    status((|| {
+        shared_runtime()?.block_on(async {
+            let mut next = start;
+            loop {
+                let head = rpc::get_latest_block_number().await?;
+                while next <= head {
+                    let (block, transactions, receipts) = rpc::fetch_block_data(next).await?;
+                    let (block, transactions, receipts) = transform_block_data(&block, &transactions, &receipts);
+                    let record = json!({ "block": block, "transactions": transactions, "receipts": receipts });
+                    if !emit(callback, &record, user_data)? {
+                        return Ok(());
+                    }
+                    next += 1;
+                }
+                clock::sleep(Duration::from_millis(poll_interval_ms)).await;
+            }
+        })
+    })())
+}
+
+/// Reads `dataset` (one of "blocks", "transactions", "receipts", "logs", "contracts", "data_usage",
+/// "ordering_analysis", "nft_transfers", "token_transfers", "bridge_messages", "balances", "contract_state", "addresses" or "data_quality") for blocks `first..=last` from
+/// indexed output under `raw_data_path` (NULL for `RAW_DATA_PATH`), calling `callback` once per
+/// record until it returns non-zero.
+///
+/// # Safety
+/// String arguments must be NULL (for `raw_data_path`) or valid NUL-terminated UTF-8, and
+/// `callback` must be safe to call with `user_data` from the calling thread.
+#[no_mangle]
+pub unsafe extern "C" fn sp_query(
+    dataset: *const c_char,
+    first: u64,
+    last: u64,
+    raw_data_path: *const c_char,
+    callback: RecordCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    status((|| {
+        let dataset = optional_str(dataset)?.ok_or_else(|| anyhow::anyhow!("dataset must not be NULL"))?;
+        if !store::DATASETS.contains(&dataset.as_str()) {
+            return Err(anyhow::anyhow!("Unknown dataset {}", dataset));
+        }
+        let raw_data_path = optional_str(raw_data_path)?.unwrap_or_else(store::raw_data_path);
+        for record in store::read_range::<Value>(&raw_data_path, &dataset, first, last)? {
+            if !emit(callback, &record, user_data)? {
+                break;
+            }
+        }
+        Ok(())
+    })())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn count_records(_record: *const c_char, user_data: *mut c_void) -> c_int {
+        unsafe { *(user_data as *mut usize) += 1 };
+        0
+    }
+
+    #[test]
+    fn test_query_reports_errors() {
+        let dataset = CString::new("uncles").unwrap();
+        let mut seen = 0usize;
+        let status = unsafe {
+            sp_query(dataset.as_ptr(), 0, 10, std::ptr::null(), count_records, &mut seen as *mut usize as *mut c_void)
+        };
+        assert_eq!(status, SP_ERROR);
+        let message = unsafe { CStr::from_ptr(sp_last_error()) }.to_str().unwrap();
+        assert_eq!(message, "Unknown dataset uncles");
+        assert_eq!(seen, 0);
+    }
+}
@@ -0,0 +1,425 @@
+//! Include/exclude rules applied to a batch before it is written. Rules are grouped into stages in a
+//! JSON file:
+//!
+//! ```json
+//! { "stages": [
+//!     { "name": "spam", "dataset": "token_transfers",
+//!       "exclude": [{ "token_address": ["0xspam1...", "0xspam2..."] }] },
+//!     { "name": "watchlist", "dataset": "token_transfers",
+//!       "include": [{ "from": ["0xwatched..."] }, { "to": ["0xwatched..."] }],
+//!       "exclude": [{ "value": "0" }] }
+//! ] }
+//! ```
+//!
+//! Precedence:
+//! 1. Stages run in file order and a record is kept only if every stage for its dataset keeps it.
+//! 2. Within a stage, exclude rules come first: a record matching any of them is dropped, whatever
+//!    the include rules say.
+//! 3. Otherwise a stage with include rules keeps the record only if it matches at least one of
+//!    them; a stage without include rules keeps everything it did not exclude.
+//! 4. A rule matches when all of its fields match. A field's value matches by equality (strings
+//!    ignore case, numbers and decimal strings compare as integers), a list matches any of its
+//!    values, and `{ "gt": .., "gte": .., "lt": .., "lte": .. }` compares numerically. A missing
+//!    field is null. Nested fields are written with dots, e.g. `extra.l1_fee`.
+//!
+//! Blocks are never filtered: checkpoints and gap detection rely on them.
+//!
+//! For contract-specific indexing, [`AddressFilter`] (`--filter-address`, `--filter-to`) drops the
+//! transactions and receipts not involving the given addresses before they are even transformed.
+//! [`TopicFilter`] (`--topic0`) does the same for event-specific indexing: only logs with the given
+//! topic0s are kept, and transactions left without any are dropped with their receipts.
+
+use anyhow::Result;
+use primitive_types::U256;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::models::{Receipt, Transaction};
+use crate::pipeline::TransformedBatch;
+use crate::store::{self, Record};
+use crate::topics;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Bounds {
+    gt: Option<Value>,
+    gte: Option<Value>,
+    lt: Option<Value>,
+    lte: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Matcher {
+    Range(Box<Bounds>),
+    AnyOf(Vec<Value>),
+    Equals(Value),
+}
+
+type Rule = BTreeMap<String, Matcher>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stage {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub dataset: String,
+    #[serde(default)]
+    include: Vec<Rule>,
+    #[serde(default)]
+    exclude: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    pub stages: Vec<Stage>,
+}
+
+fn integer(value: &Value) -> Option<U256> {
+    match value {
+        Value::Number(number) => number.as_u64().map(U256::from),
+        Value::String(text) if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) => U256::from_dec_str(text).ok(),
+        _ => None,
+    }
+}
+
+fn float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (integer(actual), integer(expected)) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => float(actual)?.partial_cmp(&float(expected)?),
+    }
+}
+
+fn equals(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::String(a), Value::String(b)) => a.eq_ignore_ascii_case(b),
+        (Value::Number(_), _) | (_, Value::Number(_)) => compare(actual, expected) == Some(Ordering::Equal),
+        _ => actual == expected,
+    }
+}
+
+impl Matcher {
+    fn matches(&self, actual: &Value) -> bool {
+        match self {
+            Matcher::Equals(expected) => equals(actual, expected),
+            Matcher::AnyOf(values) => values.iter().any(|expected| equals(actual, expected)),
+            Matcher::Range(bounds) => {
+                let check = |bound: &Option<Value>, accept: fn(Ordering) -> bool| {
+                    bound.as_ref().is_none_or(|bound| compare(actual, bound).is_some_and(accept))
+                };
+                check(&bounds.gt, Ordering::is_gt)
+                    && check(&bounds.gte, Ordering::is_ge)
+                    && check(&bounds.lt, Ordering::is_lt)
+                    && check(&bounds.lte, Ordering::is_le)
+            },
+        }
+    }
+}
+
+fn field<'a>(record: &'a Value, path: &str) -> &'a Value {
+    path.split('.').fold(record, |value, key| value.get(key).unwrap_or(&Value::Null))
+}
+
+fn matches(rule: &Rule, record: &Value) -> bool {
+    rule.iter().all(|(path, matcher)| matcher.matches(field(record, path)))
+}
+
+impl Stage {
+    fn keeps(&self, record: &Value) -> bool {
+        if self.exclude.iter().any(|rule| matches(rule, record)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|rule| matches(rule, record))
+    }
+}
+
+impl Filters {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+        let filters: Filters = serde_json::from_slice(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid filters in {}: {}", path.display(), e))?;
+        for stage in &filters.stages {
+            if stage.dataset == "blocks" || !store::DATASETS.contains(&stage.dataset.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Filter stage {} targets {}; expected one of {:?} other than blocks",
+                    stage.name.as_deref().unwrap_or("(unnamed)"),
+                    stage.dataset,
+                    store::DATASETS
+                ));
+            }
+        }
+        Ok(filters)
+    }
+
+    // Drops the records some stage does not keep, returning the dataset and how many were dropped
+    fn retain<T: Record>(&self, records: &mut Vec<T>) -> Result<(&'static str, usize)> {
+        let stages: Vec<&Stage> = self.stages.iter().filter(|stage| stage.dataset == T::DATASET).collect();
+        if stages.is_empty() {
+            return Ok((T::DATASET, 0));
+        }
+        let before = records.len();
+        let mut keep = Vec::with_capacity(before);
+        for record in records.iter() {
+            let value = serde_json::to_value(record)?;
+            keep.push(stages.iter().all(|stage| stage.keeps(&value)));
+        }
+        let mut keep = keep.into_iter();
+        records.retain(|_| keep.next().unwrap_or(true));
+        Ok((T::DATASET, before - records.len()))
+    }
+
+    // Applies every stage to the batch, returning the records dropped per dataset
+    pub fn apply(&self, batch: &mut TransformedBatch) -> Result<BTreeMap<&'static str, usize>> {
+        let dropped = [
+            self.retain(&mut batch.transactions)?,
+            self.retain(&mut batch.receipts)?,
+            self.retain(&mut batch.logs)?,
+            self.retain(&mut batch.contracts)?,
+            self.retain(&mut batch.data_usage)?,
+            self.retain(&mut batch.ordering_analysis)?,
+            self.retain(&mut batch.nft_transfers)?,
+            self.retain(&mut batch.token_transfers)?,
+            self.retain(&mut batch.bridge_messages)?,
+            self.retain(&mut batch.balances)?,
+            self.retain(&mut batch.contract_state)?,
+            self.retain(&mut batch.addresses)?,
+            self.retain(&mut batch.data_quality)?,
+        ];
+        Ok(dropped.into_iter().filter(|(_, count)| *count > 0).collect())
+    }
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct AddressFilter {
+    /// Only keep transactions (and their receipts) sent from or to one of these addresses, deploying one, or with a log it emitted
+    #[arg(long = "filter-address", env = "FILTER_ADDRESSES", value_delimiter = ',')]
+    pub addresses: Vec<String>,
+
+    /// Only keep transactions (and their receipts) calling or deploying one of these contracts
+    #[arg(long = "filter-to", env = "FILTER_TO", value_delimiter = ',')]
+    pub to: Vec<String>,
+}
+
+impl AddressFilter {
+    pub fn is_active(&self) -> bool {
+        !self.addresses.is_empty() || !self.to.is_empty()
+    }
+
+    // Whether a transaction involves a filtered address, judged from the transaction, its receipt
+    // or both (either may be missing at lower hydration levels)
+    fn involves(&self, tx: Option<&Transaction>, receipt: Option<&Receipt>) -> bool {
+        let listed = |list: &[String], address: Option<&str>| address.is_some_and(|address| list.iter().any(|a| a.eq_ignore_ascii_case(address)));
+        let to = tx.and_then(|tx| tx.to.as_deref()).or(receipt.and_then(|receipt| receipt.to.as_deref()));
+        let deployed = receipt.and_then(|receipt| receipt.contract_address.as_deref());
+        if listed(&self.to, to) || listed(&self.to, deployed) {
+            return true;
+        }
+        let from = tx.map(|tx| tx.from.as_str()).or(receipt.map(|receipt| receipt.from.as_str()));
+        let mut emitted = receipt.into_iter().flat_map(|receipt| &receipt.logs).filter_map(|log| log["address"].as_str());
+        [from, to, deployed].into_iter().any(|address| listed(&self.addresses, address))
+            || emitted.any(|address| listed(&self.addresses, Some(address)))
+    }
+
+    // Drops the fetched transactions and per-block receipts that involve no filtered address,
+    // keeping each transaction and its receipt together. Returns how many transactions went.
+    pub fn retain(&self, transactions: &mut Vec<Transaction>, receipts: &mut [Vec<Receipt>]) -> usize {
+        let by_hash: BTreeMap<&str, &Receipt> = receipts.iter().flatten().map(|receipt| (receipt.transaction_hash.as_str(), receipt)).collect();
+        let mut kept: BTreeSet<String> = transactions
+            .iter()
+            .filter(|tx| self.involves(Some(tx), by_hash.get(tx.hash.as_str()).copied()))
+            .map(|tx| tx.hash.clone())
+            .collect();
+        let fetched: BTreeSet<&str> = transactions.iter().map(|tx| tx.hash.as_str()).collect();
+        kept.extend(
+            by_hash
+                .iter()
+                .filter(|(hash, receipt)| !fetched.contains(*hash) && self.involves(None, Some(receipt)))
+                .map(|(hash, _)| hash.to_string()),
+        );
+        let before = transactions.len();
+        transactions.retain(|tx| kept.contains(&tx.hash));
+        for block_receipts in receipts {
+            block_receipts.retain(|receipt| kept.contains(&receipt.transaction_hash));
+        }
+        before - transactions.len()
+    }
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct TopicFilter {
+    /// Only keep logs with one of these topic0s, as hashes or event signatures such as `Transfer(address,address,uint256)`, and the transactions that emitted them (repeat the option or separate with `;`)
+    #[arg(long = "topic0", env = "TOPIC0", value_delimiter = ';')]
+    pub topic0: Vec<String>,
+}
+
+impl TopicFilter {
+    // The lowercase topic0 hashes, checked before anything is fetched
+    pub fn topics(&self) -> Result<BTreeSet<String>> {
+        self.topic0
+            .iter()
+            .map(|value| {
+                let value = value.trim();
+                if let Some(hex) = value.strip_prefix("0x") {
+                    let valid = hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit());
+                    return valid.then(|| value.to_lowercase()).ok_or_else(|| anyhow::anyhow!("Invalid --topic0 {}: expected a 32-byte hash", value));
+                }
+                let declaration = topics::parse_declaration(value)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid --topic0 {}: expected a 32-byte hash or an event signature", value))?;
+                Ok(topics::topic_of(&declaration.signature))
+            })
+            .collect()
+    }
+}
+
+// Drops the logs whose topic0 is not in `topics`, then the transactions and receipts left without
+// logs. Returns how many logs and transactions were dropped.
+pub fn retain_topics(topics: &BTreeSet<String>, transactions: &mut Vec<Transaction>, receipts: &mut [Vec<Receipt>]) -> (usize, usize) {
+    let mut dropped_logs = 0;
+    let mut kept = BTreeSet::new();
+    for receipt in receipts.iter_mut().flatten() {
+        let before = receipt.logs.len();
+        receipt.logs.retain(|log| log["topics"][0].as_str().is_some_and(|topic0| topics.contains(&topic0.to_lowercase())));
+        dropped_logs += before - receipt.logs.len();
+        if !receipt.logs.is_empty() {
+            kept.insert(receipt.transaction_hash.clone());
+        }
+    }
+    for block_receipts in receipts {
+        block_receipts.retain(|receipt| kept.contains(&receipt.transaction_hash));
+    }
+    let before = transactions.len();
+    transactions.retain(|tx| kept.contains(&tx.hash));
+    (dropped_logs, before - transactions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_precedence() {
+        let filters: Filters = serde_json::from_value(json!({ "stages": [
+            { "name": "spam", "dataset": "token_transfers", "exclude": [{ "token_address": ["0xSPAM"] }] },
+            { "name": "watchlist", "dataset": "token_transfers",
+              "include": [{ "from": "0xwatched" }, { "to": "0xwatched" }],
+              "exclude": [{ "value": 0 }] },
+            { "dataset": "token_transfers", "exclude": [{ "token_decimals": { "gt": 18 } }] },
+        ] }))
+        .unwrap();
+        let transfer = |token: &str, from: &str, to: &str, value: &str| {
+            json!({ "token_address": token, "from": from, "to": to, "value": value, "token_decimals": 18 })
+        };
+        let keeps = |record: &Value| filters.stages.iter().all(|stage| stage.keeps(record));
+
+        assert!(keeps(&transfer("0xusdc", "0xWATCHED", "0xother", "5")));
+        assert!(keeps(&transfer("0xusdc", "0xother", "0xwatched", "1000000000000000000000000")));
+        // Not on the watchlist
+        assert!(!keeps(&transfer("0xusdc", "0xother", "0xother", "5")));
+        // Excluded even though the watchlist includes it
+        assert!(!keeps(&transfer("0xspam", "0xwatched", "0xother", "5")));
+        assert!(!keeps(&transfer("0xusdc", "0xwatched", "0xother", "0")));
+        let mut odd = transfer("0xusdc", "0xwatched", "0xother", "5");
+        odd["token_decimals"] = json!(24);
+        assert!(!keeps(&odd));
+
+        let range = Matcher::Range(Box::new(Bounds { gt: None, gte: Some(json!("10")), lt: Some(json!(20)), lte: None }));
+        assert!(range.matches(&json!(10)) && range.matches(&json!("19")) && !range.matches(&json!(20)));
+        assert!(!range.matches(&Value::Null));
+        assert_eq!(field(&json!({ "extra": { "l1_fee": 3 } }), "extra.l1_fee"), &json!(3));
+
+        let path = std::env::temp_dir().join(format!("filters_test_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "stages": [{ "dataset": "blocks", "exclude": [{ "miner": "0x0" }] }] }"#).unwrap();
+        assert!(Filters::load(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_address_filter() {
+        let tx = |hash: &str, from: &str, to: Option<&str>| -> Transaction {
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x7", "from": from, "gas": "0x0", "hash": hash, "input": "0x",
+                "nonce": "0x0", "to": to, "transactionIndex": "0x0", "type": "0x2", "value": "0x0",
+            }))
+            .unwrap()
+        };
+        let receipt = |hash: &str, from: &str, to: Option<&str>, emitters: &[&str]| -> Receipt {
+            let logs: Vec<Value> = emitters.iter().map(|address| json!({ "address": address })).collect();
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x7", "contractAddress": null, "cumulativeGasUsed": "0x0", "from": from,
+                "gasUsed": "0x0", "logs": logs, "logsBloom": "0x", "status": "0x1", "to": to, "transactionHash": hash,
+                "transactionIndex": "0x0", "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let fetch = || {
+            let transactions = vec![tx("0x1", "0xa", Some("0xUSDC")), tx("0x2", "0xa", Some("0xrouter")), tx("0x3", "0xa", Some("0xb"))];
+            let receipts = vec![vec![
+                receipt("0x1", "0xa", Some("0xusdc"), &[]),
+                receipt("0x2", "0xa", Some("0xrouter"), &["0xusdc"]),
+                receipt("0x3", "0xa", Some("0xb"), &[]),
+            ]];
+            (transactions, receipts)
+        };
+        let hashes = |transactions: &[Transaction], receipts: &[Vec<Receipt>]| {
+            let receipts: Vec<&str> = receipts.iter().flatten().map(|receipt| receipt.transaction_hash.as_str()).collect();
+            (transactions.iter().map(|tx| tx.hash.clone()).collect::<Vec<_>>(), receipts.join(","))
+        };
+
+        // A call through a router counts for --filter-address by the log it emitted, not for --filter-to
+        let (mut transactions, mut receipts) = fetch();
+        let filter = AddressFilter { addresses: vec!["0xusdc".to_string()], to: Vec::new() };
+        assert_eq!(filter.retain(&mut transactions, &mut receipts), 1);
+        assert_eq!(hashes(&transactions, &receipts), (vec!["0x1".to_string(), "0x2".to_string()], "0x1,0x2".to_string()));
+
+        let (mut transactions, mut receipts) = fetch();
+        let filter = AddressFilter { addresses: Vec::new(), to: vec!["0xusdc".to_string()] };
+        assert_eq!(filter.retain(&mut transactions, &mut receipts), 2);
+        assert_eq!(hashes(&transactions, &receipts), (vec!["0x1".to_string()], "0x1".to_string()));
+        assert!(!AddressFilter::default().is_active());
+    }
+
+    #[test]
+    fn test_topic_filter() {
+        let transfer = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+        let filter = TopicFilter { topic0: vec!["event Transfer(address indexed from, address indexed to, uint256 value)".to_string()] };
+        let topics = filter.topics().unwrap();
+        assert_eq!(topics, BTreeSet::from([transfer.to_string()]));
+        assert!(TopicFilter { topic0: vec!["0x1234".to_string()] }.topics().is_err());
+
+        let receipt = |hash: &str, topic0s: &[&str]| -> Receipt {
+            let logs: Vec<Value> = topic0s.iter().map(|topic0| json!({ "address": "0xc", "topics": [topic0] })).collect();
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x7", "contractAddress": null, "cumulativeGasUsed": "0x0", "from": "0xa",
+                "gasUsed": "0x0", "logs": logs, "logsBloom": "0x", "status": "0x1", "to": "0xc", "transactionHash": hash,
+                "transactionIndex": "0x0", "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let tx = |hash: &str| -> Transaction {
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x7", "from": "0xa", "gas": "0x0", "hash": hash, "input": "0x",
+                "nonce": "0x0", "to": "0xc", "transactionIndex": "0x0", "type": "0x2", "value": "0x0",
+            }))
+            .unwrap()
+        };
+        let mut transactions = vec![tx("0x1"), tx("0x2")];
+        let mut receipts = vec![vec![receipt("0x1", &[&transfer.to_uppercase().replace("0X", "0x"), "0xapproval"]), receipt("0x2", &["0xapproval"])]];
+        assert_eq!(retain_topics(&topics, &mut transactions, &mut receipts), (2, 1));
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(receipts[0].len(), 1);
+        assert_eq!(receipts[0][0].logs.len(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+//! Transformed records for unit tests. Every field is empty or zero unless named by the arguments;
+//! a test sets what it checks with struct update syntax, e.g. `TransformedBlock { gas_used: 5, ..block(7) }`.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Map;
+
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::transform::row_id;
+
+pub fn datetime() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+}
+
+// Hash of the transaction at `index` in block `number`
+pub fn transaction_hash(number: u64, index: u64) -> String {
+    format!("0x{:x}{:04x}", number, index)
+}
+
+pub fn block(number: u64) -> TransformedBlock {
+    TransformedBlock {
+        base_fee_per_gas: None,
+        difficulty: 0,
+        extra_data: "0x".to_string(),
+        gas_limit: 0,
+        gas_used: 0,
+        hash: format!("0x{:x}", number),
+        logs_bloom: "0x".to_string(),
+        miner: "0x".to_string(),
+        mix_hash: "0x".to_string(),
+        nonce: "0x".to_string(),
+        number,
+        parent_hash: "0x".to_string(),
+        receipts_root: "0x".to_string(),
+        sha3_uncles: "0x".to_string(),
+        size: 0,
+        state_root: "0x".to_string(),
+        datetime: datetime(),
+        total_difficulty: 0,
+        transaction_hashes: Vec::new(),
+        transactions_root: "0x".to_string(),
+        uncles: Vec::new(),
+        total_fees: None,
+        burnt_fees: None,
+        base_fee_per_gas_gwei: None,
+        total_fees_ether: None,
+        burnt_fees_ether: None,
+        extra: Map::new(),
+    }
+}
+
+pub fn transaction(block_number: u64, index: u64) -> TransformedTransaction {
+    TransformedTransaction {
+        id: row_id(block_number, index),
+        block_hash: format!("0x{:x}", block_number),
+        block_number,
+        chain_id: 1,
+        from: "0xf".to_string(),
+        gas: 21000,
+        gas_price: 0,
+        hash: transaction_hash(block_number, index),
+        input: "0x".to_string(),
+        method_id: None,
+        method_signature: None,
+        nonce: 0,
+        r: "0x".to_string(),
+        s: "0x".to_string(),
+        to: Some("0xt".to_string()),
+        transaction_index: index,
+        tx_type: 2,
+        v: "0x".to_string(),
+        value: "0".to_string(),
+        status: None,
+        gas_used: None,
+        effective_gas_price: None,
+        contract_address: None,
+        fee: None,
+        burnt_fee: None,
+        priority_fee: None,
+        value_ether: None,
+        gas_price_gwei: None,
+        effective_gas_price_gwei: None,
+        fee_ether: None,
+        burnt_fee_ether: None,
+        priority_fee_ether: None,
+        datetime: datetime(),
+        extra: Map::new(),
+    }
+}
+
+// The successful receipt of `transaction(block_number, index)`
+pub fn receipt(block_number: u64, index: u64) -> TransformedReceipt {
+    TransformedReceipt {
+        id: row_id(block_number, index),
+        block_hash: format!("0x{:x}", block_number),
+        block_number,
+        contract_address: None,
+        cumulative_gas_used: 0,
+        effective_gas_price: 0,
+        from: "0xf".to_string(),
+        gas_used: 0,
+        logs: Vec::new(),
+        logs_bloom: "0x".to_string(),
+        status: true,
+        to: Some("0xt".to_string()),
+        transaction_hash: transaction_hash(block_number, index),
+        transaction_index: index,
+        tx_type: 2,
+        datetime: datetime(),
+        extra: Map::new(),
+    }
+}
@@ -0,0 +1,401 @@
+//! Arrow Flight server (`flight` feature) for indexed datasets.
+//!
+//! Implements the read side of `arrow.flight.protocol.FlightService` (ListFlights,
+//! GetFlightInfo, GetSchema and DoGet) so pyarrow, the R arrow package or the Java Flight
+//! client can pull blocks, transactions and receipts as Arrow record batches. The protocol
+//! messages are declared by hand below, which keeps the dependency footprint to tonic/prost.
+
+// tonic::Status is the error type of every gRPC handler
+#![allow(clippy::result_large_err)]
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::{write_message, DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+use arrow_schema::Schema;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio_stream::Stream;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, StdError};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+use crate::access_stats;
+use crate::arrow::{dataset_schema, to_record_batches};
+use crate::store::{self, DATASETS};
+const SERVICE_NAME: &str = "arrow.flight.protocol.FlightService";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Criteria {
+    #[prost(bytes = "vec", tag = "1")]
+    pub expression: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlightDescriptor {
+    // 1 = PATH, 2 = CMD
+    #[prost(int32, tag = "1")]
+    pub r#type: i32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub cmd: Vec<u8>,
+    #[prost(string, repeated, tag = "3")]
+    pub path: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Ticket {
+    #[prost(bytes = "vec", tag = "1")]
+    pub ticket: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Location {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlightEndpoint {
+    #[prost(message, optional, tag = "1")]
+    pub ticket: Option<Ticket>,
+    #[prost(message, repeated, tag = "2")]
+    pub location: Vec<Location>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlightInfo {
+    #[prost(bytes = "vec", tag = "1")]
+    pub schema: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub flight_descriptor: Option<FlightDescriptor>,
+    #[prost(message, repeated, tag = "3")]
+    pub endpoint: Vec<FlightEndpoint>,
+    #[prost(int64, tag = "4")]
+    pub total_records: i64,
+    #[prost(int64, tag = "5")]
+    pub total_bytes: i64,
+    #[prost(bool, tag = "6")]
+    pub ordered: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SchemaResult {
+    #[prost(bytes = "vec", tag = "1")]
+    pub schema: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlightData {
+    #[prost(message, optional, tag = "1")]
+    pub flight_descriptor: Option<FlightDescriptor>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data_header: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub app_metadata: Vec<u8>,
+    #[prost(bytes = "vec", tag = "1000")]
+    pub data_body: Vec<u8>,
+}
+
+// What a ticket (or a PATH descriptor `[dataset, first?, last?]`) asks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub dataset: String,
+    pub first: u64,
+    pub last: u64,
+}
+
+impl Selection {
+    fn from_path(path: &[String]) -> Result<Self, Status> {
+        let dataset = path.first().ok_or_else(|| Status::invalid_argument("Descriptor path is empty"))?;
+        let bound = |index: usize, default: u64| {
+            path.get(index)
+                .map(|value| value.parse::<u64>().map_err(|_| Status::invalid_argument(format!("Invalid block number {}", value))))
+                .unwrap_or(Ok(default))
+        };
+        Self::new(dataset, bound(1, 0)?, bound(2, u64::MAX)?)
+    }
+
+    fn from_ticket(ticket: &[u8]) -> Result<Self, Status> {
+        let value: Value = serde_json::from_slice(ticket).map_err(|e| Status::invalid_argument(format!("Invalid ticket: {}", e)))?;
+        let dataset = value["dataset"].as_str().unwrap_or_default();
+        Self::new(dataset, value["first"].as_u64().unwrap_or(0), value["last"].as_u64().unwrap_or(u64::MAX))
+    }
+
+    fn new(dataset: &str, first: u64, last: u64) -> Result<Self, Status> {
+        if !DATASETS.contains(&dataset) {
+            return Err(Status::not_found(format!("Unknown dataset {}, expected one of {:?}", dataset, DATASETS)));
+        }
+        Ok(Self { dataset: dataset.to_string(), first, last })
+    }
+
+    fn ticket(&self) -> Ticket {
+        let ticket = json!({ "dataset": self.dataset, "first": self.first, "last": self.last });
+        Ticket { ticket: ticket.to_string().into_bytes() }
+    }
+
+    fn descriptor(&self) -> FlightDescriptor {
+        FlightDescriptor {
+            r#type: 1,
+            cmd: Vec::new(),
+            path: vec![self.dataset.clone(), self.first.to_string(), self.last.to_string()],
+        }
+    }
+}
+
+// Schema as an encapsulated IPC message, the form FlightInfo and SchemaResult carry
+fn schema_bytes(schema: &Schema) -> Result<Vec<u8>> {
+    let options = IpcWriteOptions::default();
+    let mut tracker = DictionaryTracker::new(false);
+    let encoded = IpcDataGenerator::default().schema_to_bytes_with_dictionary_tracker(schema, &mut tracker, &options);
+    let mut bytes = Vec::new();
+    write_message(&mut bytes, encoded, &options)?;
+    Ok(bytes)
+}
+
+pub fn flight_data(schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<FlightData>> {
+    let options = IpcWriteOptions::default();
+    let generator = IpcDataGenerator::default();
+    let mut tracker = DictionaryTracker::new(false);
+
+    let schema_message = generator.schema_to_bytes_with_dictionary_tracker(schema, &mut tracker, &options);
+    let mut messages = vec![FlightData { data_header: schema_message.ipc_message, ..Default::default() }];
+    for batch in batches {
+        let (dictionaries, encoded) = generator.encoded_batch(batch, &mut tracker, &options)?;
+        for encoded in dictionaries.into_iter().chain(std::iter::once(encoded)) {
+            messages.push(FlightData { data_header: encoded.ipc_message, data_body: encoded.arrow_data, ..Default::default() });
+        }
+    }
+    Ok(messages)
+}
+
+fn internal(e: anyhow::Error) -> Status {
+    Status::internal(e.to_string())
+}
+
+#[derive(Clone)]
+pub struct FlightService {
+    raw_data_path: Arc<String>,
+}
+
+type FlightStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+impl FlightService {
+    pub fn new(raw_data_path: String) -> Self {
+        Self { raw_data_path: Arc::new(raw_data_path) }
+    }
+
+    fn flight_info(&self, selection: &Selection) -> Result<FlightInfo, Status> {
+        let schema = dataset_schema(&selection.dataset);
+        Ok(FlightInfo {
+            schema: schema_bytes(&schema).map_err(internal)?,
+            flight_descriptor: Some(selection.descriptor()),
+            endpoint: vec![FlightEndpoint { ticket: Some(selection.ticket()), location: Vec::new() }],
+            total_records: -1,
+            total_bytes: -1,
+            ordered: true,
+        })
+    }
+
+    async fn list_flights(&self, _request: Request<Criteria>) -> Result<Response<FlightStream<FlightInfo>>, Status> {
+        let infos: Vec<Result<FlightInfo, Status>> = DATASETS
+            .iter()
+            .map(|dataset| self.flight_info(&Selection::new(dataset, 0, u64::MAX)?))
+            .collect();
+        Ok(Response::new(Box::pin(tokio_stream::iter(infos))))
+    }
+
+    async fn get_flight_info(&self, request: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        let selection = Selection::from_path(&request.into_inner().path)?;
+        Ok(Response::new(self.flight_info(&selection)?))
+    }
+
+    async fn get_schema(&self, request: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        let selection = Selection::from_path(&request.into_inner().path)?;
+        let schema = schema_bytes(&dataset_schema(&selection.dataset)).map_err(internal)?;
+        Ok(Response::new(SchemaResult { schema }))
+    }
+
+    async fn do_get(&self, request: Request<Ticket>) -> Result<Response<FlightStream<FlightData>>, Status> {
+        let selection = Selection::from_ticket(&request.into_inner().ticket)?;
+        access_stats::record_whole(&selection.dataset);
+        let raw_data_path = Arc::clone(&self.raw_data_path);
+        let messages = tokio::task::spawn_blocking(move || {
+            let schema = dataset_schema(&selection.dataset);
+            let records: Vec<Value> = store::read_range(&raw_data_path, &selection.dataset, selection.first, selection.last)?;
+            log::info!("Flight: serving {} {} for blocks {}-{}", records.len(), selection.dataset, selection.first, selection.last);
+            flight_data(&schema, &to_record_batches(Arc::clone(&schema), records)?)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(internal)?;
+        Ok(Response::new(Box::pin(tokio_stream::iter(messages.into_iter().map(Ok)))))
+    }
+}
+
+// Adapts one FlightService method to tonic's unary/server-streaming handlers
+macro_rules! flight_method {
+    ($name:ident, $request:ty, unary, $response:ty) => {
+        pub(super) struct $name(pub(super) FlightService);
+        impl tonic::server::UnaryService<$request> for $name {
+            type Response = $response;
+            type Future = BoxFuture<Response<$response>, Status>;
+            fn call(&mut self, request: Request<$request>) -> Self::Future {
+                let service = self.0.clone();
+                Box::pin(async move { service.$name(request).await })
+            }
+        }
+    };
+    ($name:ident, $request:ty, streaming, $response:ty) => {
+        pub(super) struct $name(pub(super) FlightService);
+        impl tonic::server::ServerStreamingService<$request> for $name {
+            type Response = $response;
+            type ResponseStream = FlightStream<$response>;
+            type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+            fn call(&mut self, request: Request<$request>) -> Self::Future {
+                let service = self.0.clone();
+                Box::pin(async move { service.$name(request).await })
+            }
+        }
+    };
+}
+
+#[allow(non_camel_case_types)]
+mod methods {
+    use super::*;
+    flight_method!(list_flights, Criteria, streaming, FlightInfo);
+    flight_method!(get_flight_info, FlightDescriptor, unary, FlightInfo);
+    flight_method!(get_schema, FlightDescriptor, unary, SchemaResult);
+    flight_method!(do_get, Ticket, streaming, FlightData);
+}
+
+fn grpc<T, U>() -> tonic::server::Grpc<tonic::codec::ProstCodec<T, U>>
+where
+    T: prost::Message + Send + 'static,
+    U: prost::Message + Default + Send + 'static,
+{
+    tonic::server::Grpc::new(tonic::codec::ProstCodec::default())
+}
+
+impl<B> tonic::codegen::Service<http::Request<B>> for FlightService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        match request.uri().path().strip_prefix(&format!("/{}/", SERVICE_NAME)) {
+            Some("ListFlights") => Box::pin(async move {
+                Ok(grpc().server_streaming(methods::list_flights(service), request).await)
+            }),
+            Some("GetFlightInfo") => Box::pin(async move {
+                Ok(grpc().unary(methods::get_flight_info(service), request).await)
+            }),
+            Some("GetSchema") => Box::pin(async move { Ok(grpc().unary(methods::get_schema(service), request).await) }),
+            Some("DoGet") => Box::pin(async move { Ok(grpc().server_streaming(methods::do_get(service), request).await) }),
+            // Handshake, DoPut, DoExchange, DoAction and friends: this server is read-only
+            _ => Box::pin(async move {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert(Status::GRPC_STATUS, (tonic::Code::Unimplemented as i32).into());
+                headers.insert(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE);
+                Ok(response)
+            }),
+        }
+    }
+}
+
+impl tonic::server::NamedService for FlightService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+// Serves until the process exits; runs after the first one in a process (`daemon` cycles, work
+// stealing chunks) find it already serving. A bind failure or a stopped server gives the flag
+// back, so the next run tries again instead of assuming a server that never came up.
+pub async fn serve(addr: SocketAddr, raw_data_path: String) -> Result<()> {
+    if SERVING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let incoming = match TcpIncoming::new(addr, false, None) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            SERVING.store(false, Ordering::SeqCst);
+            return Err(anyhow::anyhow!("Could not listen on {}: {}", addr, e));
+        }
+    };
+    log::info!("Arrow Flight server listening on {} (serving {})", addr, raw_data_path);
+    let served = tonic::transport::Server::builder()
+        .add_service(FlightService::new(raw_data_path))
+        .serve_with_incoming(incoming)
+        .await;
+    SERVING.store(false, Ordering::SeqCst);
+    Ok(served?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransformedReceipt;
+
+    #[test]
+    fn test_tickets_and_paths() {
+        let selection = Selection::from_path(&["transactions".to_string(), "10".to_string()]).unwrap();
+        assert_eq!(selection, Selection { dataset: "transactions".to_string(), first: 10, last: u64::MAX });
+        assert_eq!(Selection::from_ticket(&selection.ticket().ticket).unwrap(), selection);
+        assert!(Selection::from_path(&["uncles".to_string()]).is_err());
+        assert!(Selection::from_ticket(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_receipts_to_flight_data() {
+        let receipt = TransformedReceipt {
+            id: 7_000_000,
+            block_hash: "0xb".to_string(),
+            block_number: 7,
+            contract_address: None,
+            cumulative_gas_used: 21000,
+            effective_gas_price: 1,
+            from: "0xf".to_string(),
+            gas_used: 21000,
+            logs: vec![json!({ "address": "0xa", "topics": [] })],
+            logs_bloom: "0x0".to_string(),
+            status: true,
+            to: Some("0xt".to_string()),
+            transaction_hash: "0xh".to_string(),
+            transaction_index: 0,
+            tx_type: 2,
+            datetime: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            extra: Default::default(),
+        };
+        let schema = dataset_schema("receipts");
+        let batches = to_record_batches(Arc::clone(&schema), vec![serde_json::to_value(&receipt).unwrap()]).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+        assert_eq!(batches[0].schema(), schema);
+
+        let messages = flight_data(&schema, &batches).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].data_body.is_empty());
+        assert!(!messages[1].data_body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_serve_gives_the_flag_back_when_bind_fails() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+        assert!(serve(addr, String::new()).await.is_err());
+        assert!(!SERVING.load(Ordering::SeqCst));
+    }
+}
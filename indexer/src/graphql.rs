@@ -0,0 +1,322 @@
+//! GraphQL schema (`api` feature) over the indexed store, served by `serve` at `/graphql`.
+//!
+//! List queries take an optional block range (defaulting to the last 100 indexed blocks),
+//! field filters and `first`/`offset` pagination.
+
+use anyhow::Result;
+use async_graphql::{ComplexObject, Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::access_stats;
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::store;
+use crate::transform::{hex_to_u64, normalize_address};
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const DEFAULT_SPAN: u64 = 100;
+const MAX_SPAN: u64 = 10_000;
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+// Root of the store the resolvers read, stored in the schema data
+struct RawDataPath(String);
+
+pub fn schema(raw_data_path: String) -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription).data(RawDataPath(raw_data_path)).finish()
+}
+
+#[derive(SimpleObject, Debug, PartialEq)]
+pub struct Log {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
+}
+
+impl Log {
+    // Receipts keep the RPC log objects as-is (camelCase, hex quantities)
+    fn from_rpc(log: &Value) -> Self {
+        let text = |key: &str| log[key].as_str().unwrap_or_default().to_string();
+        Log {
+            address: normalize_address(&text("address")),
+            topics: log["topics"].as_array().into_iter().flatten().filter_map(|t| t.as_str().map(str::to_string)).collect(),
+            data: text("data"),
+            block_number: hex_to_u64(&text("blockNumber")),
+            transaction_hash: text("transactionHash"),
+            transaction_index: hex_to_u64(&text("transactionIndex")),
+            log_index: hex_to_u64(&text("logIndex")),
+        }
+    }
+}
+
+#[derive(InputObject, Default)]
+pub struct BlockFilter {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub miner: Option<String>,
+}
+
+#[derive(InputObject, Default)]
+pub struct TransactionFilter {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub method_id: Option<String>,
+}
+
+#[derive(InputObject, Default)]
+pub struct ReceiptFilter {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub status: Option<bool>,
+    pub contract_address: Option<String>,
+}
+
+#[derive(InputObject, Default)]
+pub struct LogFilter {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub address: Option<String>,
+    pub topic0: Option<String>,
+}
+
+fn matches(filter: &Option<String>, value: Option<&str>) -> bool {
+    filter.as_ref().is_none_or(|filter| value.is_some_and(|value| value.eq_ignore_ascii_case(filter)))
+}
+
+fn page<T>(records: Vec<T>, first: Option<usize>, offset: Option<usize>) -> Vec<T> {
+    let first = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    records.into_iter().skip(offset.unwrap_or(0)).take(first).collect()
+}
+
+// Counts the fields the query selects from `dataset`, by their stored (snake_case) names
+fn note_fields(ctx: &Context<'_>, dataset: &str) {
+    let snake_case = |name: &str| {
+        name.chars().fold(String::new(), |mut field, c| {
+            if c.is_ascii_uppercase() {
+                field.push('_');
+            }
+            field.push(c.to_ascii_lowercase());
+            field
+        })
+    };
+    access_stats::record_fields(dataset, ctx.field().selection_set().map(|field| snake_case(field.name())));
+}
+
+// Reads a dataset over the requested range on the blocking pool
+async fn read<T: DeserializeOwned + Send + 'static>(
+    ctx: &Context<'_>,
+    dataset: &'static str,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+) -> async_graphql::Result<Vec<T>> {
+    let raw_data_path = ctx.data::<RawDataPath>()?.0.clone();
+    let records = tokio::task::spawn_blocking(move || -> Result<Vec<T>> {
+        let to_block = match to_block {
+            Some(to_block) => to_block,
+            None => match store::indexed_block_numbers(&raw_data_path)?.last() {
+                Some(head) => *head,
+                None => return Ok(Vec::new()),
+            },
+        };
+        let from_block = from_block.unwrap_or(to_block.saturating_sub(DEFAULT_SPAN - 1));
+        if from_block > to_block || to_block - from_block >= MAX_SPAN {
+            return Err(anyhow::anyhow!("Block range must be ascending and span at most {} blocks", MAX_SPAN));
+        }
+        store::read_range(&raw_data_path, dataset, from_block, to_block)
+    })
+    .await??;
+    Ok(records)
+}
+
+async fn find<T: DeserializeOwned>(
+    ctx: &Context<'_>,
+    dataset: &'static str,
+    file_name: String,
+    field: &'static str,
+    value: String,
+) -> async_graphql::Result<Option<T>> {
+    let raw_data_path = ctx.data::<RawDataPath>()?.0.clone();
+    let value = value.to_lowercase();
+    let record = tokio::task::spawn_blocking(move || store::find_record(&raw_data_path, dataset, &file_name, field, &value, None))
+        .await??;
+    Ok(record.map(serde_json::from_value).transpose()?)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn block(&self, ctx: &Context<'_>, number: u64) -> async_graphql::Result<Option<TransformedBlock>> {
+        note_fields(ctx, "blocks");
+        Ok(read(ctx, "blocks", Some(number), Some(number)).await?.into_iter().next())
+    }
+
+    async fn blocks(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<BlockFilter>,
+        first: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<TransformedBlock>> {
+        note_fields(ctx, "blocks");
+        let filter = filter.unwrap_or_default();
+        let mut blocks: Vec<TransformedBlock> = read(ctx, "blocks", filter.from_block, filter.to_block).await?;
+        blocks.retain(|block| matches(&filter.miner, Some(&block.miner)));
+        blocks.sort_by_key(|block| block.number);
+        Ok(page(blocks, first, offset))
+    }
+
+    async fn transaction(&self, ctx: &Context<'_>, hash: String) -> async_graphql::Result<Option<TransformedTransaction>> {
+        note_fields(ctx, "transactions");
+        find(ctx, "transactions", format!("tx_{}", hash.to_lowercase()), "hash", hash).await
+    }
+
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TransactionFilter>,
+        first: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<TransformedTransaction>> {
+        note_fields(ctx, "transactions");
+        let filter = filter.unwrap_or_default();
+        let mut transactions: Vec<TransformedTransaction> =
+            read(ctx, "transactions", filter.from_block, filter.to_block).await?;
+        transactions.retain(|tx| {
+            matches(&filter.from, Some(&tx.from))
+                && matches(&filter.to, tx.to.as_deref())
+                && matches(&filter.method_id, tx.method_id.as_deref())
+        });
+        transactions.sort_by_key(|tx| (tx.block_number, tx.transaction_index));
+        Ok(page(transactions, first, offset))
+    }
+
+    async fn receipt(&self, ctx: &Context<'_>, transaction_hash: String) -> async_graphql::Result<Option<TransformedReceipt>> {
+        note_fields(ctx, "receipts");
+        let file_name = format!("receipt_{}", transaction_hash.to_lowercase());
+        find(ctx, "receipts", file_name, "transaction_hash", transaction_hash).await
+    }
+
+    async fn receipts(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<ReceiptFilter>,
+        first: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<TransformedReceipt>> {
+        note_fields(ctx, "receipts");
+        let filter = filter.unwrap_or_default();
+        let mut receipts: Vec<TransformedReceipt> = read(ctx, "receipts", filter.from_block, filter.to_block).await?;
+        receipts.retain(|receipt| {
+            filter.status.is_none_or(|status| receipt.status == status)
+                && matches(&filter.contract_address, receipt.contract_address.as_deref())
+        });
+        receipts.sort_by_key(|receipt| (receipt.block_number, receipt.transaction_index));
+        Ok(page(receipts, first, offset))
+    }
+
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<LogFilter>,
+        first: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<Log>> {
+        access_stats::record_fields("receipts", ["logs".to_string()]);
+        let filter = filter.unwrap_or_default();
+        let mut receipts: Vec<TransformedReceipt> = read(ctx, "receipts", filter.from_block, filter.to_block).await?;
+        receipts.sort_by_key(|receipt| (receipt.block_number, receipt.transaction_index));
+        let logs = receipts
+            .iter()
+            .flat_map(|receipt| receipt.logs.iter().map(Log::from_rpc))
+            .filter(|log| {
+                matches(&filter.address, Some(&log.address))
+                    && matches(&filter.topic0, log.topics.first().map(String::as_str))
+            })
+            .collect();
+        Ok(page(logs, first, offset))
+    }
+}
+
+#[ComplexObject]
+impl TransformedBlock {
+    async fn transactions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TransformedTransaction>> {
+        note_fields(ctx, "transactions");
+        let mut transactions: Vec<TransformedTransaction> = read(ctx, "transactions", Some(self.number), Some(self.number)).await?;
+        transactions.sort_by_key(|tx| tx.transaction_index);
+        Ok(transactions)
+    }
+}
+
+#[ComplexObject]
+impl TransformedTransaction {
+    async fn receipt(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<TransformedReceipt>> {
+        note_fields(ctx, "receipts");
+        find(ctx, "receipts", format!("receipt_{}", self.hash), "transaction_hash", self.hash.clone()).await
+    }
+}
+
+#[ComplexObject]
+impl TransformedReceipt {
+    async fn logs(&self) -> Vec<Log> {
+        self.logs.iter().map(Log::from_rpc).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::pipeline::TransformedBatch;
+    use crate::store::{OutputConfig, OutputDirs};
+    use crate::transform::extract_logs;
+
+    #[tokio::test]
+    async fn test_filters_and_logs() {
+        let root = "test_graphql_store";
+        let _ = std::fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let receipt = |hash: &str, block_number: u64, status: bool, emitter: &str| TransformedReceipt {
+            from: "0xcafe".to_string(),
+            logs: vec![serde_json::json!({ "address": emitter, "topics": ["0xddf2", "0x01"], "data": "0x", "blockNumber": "0x5",
+                                           "transactionHash": hash, "transactionIndex": "0x0", "logIndex": "0x3" })],
+            status,
+            to: Some("0xbeef".to_string()),
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(block_number, 0)
+        };
+        let receipts = vec![receipt("0xa1", 5, true, "0xToken"), receipt("0xa2", 6, false, "0xother")];
+        let batch = TransformedBatch { logs: extract_logs(&receipts), receipts, ..Default::default() };
+        store::persist(&dirs, OutputConfig::default(), None, &batch, 2)
+            .await
+            .unwrap();
+
+        let schema = schema(root.to_string());
+        let response = schema
+            .execute(
+                r#"{
+                    failed: receipts(filter: { fromBlock: 1, toBlock: 10, status: false }) { transactionHash }
+                    logs(filter: { fromBlock: 1, toBlock: 10, address: "0xtoken", topic0: "0xDDF2" }) { address logIndex }
+                    receipt(transactionHash: "0xA1") { logs { topics } }
+                }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["failed"], serde_json::json!([{ "transactionHash": "0xa2" }]));
+        assert_eq!(data["logs"], serde_json::json!([{ "address": "0xtoken", "logIndex": 3 }]));
+        assert_eq!(data["receipt"]["logs"][0]["topics"], serde_json::json!(["0xddf2", "0x01"]));
+
+        let response = schema.execute("{ blocks(filter: { fromBlock: 0, toBlock: 20000 }) { number } }").await;
+        assert_eq!(response.errors.len(), 1);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
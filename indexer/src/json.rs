@@ -0,0 +1,79 @@
+//! Parsing of RPC response bodies, which for full blocks run to several megabytes each. With the
+//! `simd` feature they are parsed with simd-json; a body it rejects is parsed again with serde_json,
+//! so the feature never turns a response the indexer used to accept into an error.
+//!
+//! Bytes parsed and time spent are counted either way, so the two parsers can be compared on a
+//! real backfill: `run` logs the totals and `/metrics` exports them.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+static PARSED_BYTES: AtomicU64 = AtomicU64::new(0);
+static PARSE_NANOS: AtomicU64 = AtomicU64::new(0);
+// Bodies simd-json rejected and serde_json parsed instead
+static FALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+pub const PARSER: &str = if cfg!(feature = "simd") { "simd-json" } else { "serde_json" };
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParseStats {
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub fallbacks: u64,
+}
+
+pub fn stats() -> ParseStats {
+    ParseStats {
+        bytes: PARSED_BYTES.load(Ordering::Relaxed),
+        elapsed: Duration::from_nanos(PARSE_NANOS.load(Ordering::Relaxed)),
+        fallbacks: FALLBACKS.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(feature = "simd")]
+fn parse_with_fallback(body: &[u8]) -> Result<Value> {
+    // simd-json parses in place, so it gets a copy and the original stays around for the fallback
+    let mut scratch = body.to_vec();
+    match simd_json::serde::from_slice::<Value>(&mut scratch) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            FALLBACKS.fetch_add(1, Ordering::Relaxed);
+            log::debug!("simd-json rejected a {} byte body ({}), parsing it with serde_json", body.len(), e);
+            Ok(serde_json::from_slice(body)?)
+        },
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+fn parse_with_fallback(body: &[u8]) -> Result<Value> {
+    Ok(serde_json::from_slice(body)?)
+}
+
+pub fn parse(body: &[u8]) -> Result<Value> {
+    let started = Instant::now();
+    let value = parse_with_fallback(body);
+    PARSE_NANOS.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    PARSED_BYTES.fetch_add(body.len() as u64, Ordering::Relaxed);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let body = br#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10","transactions":[{"hash":"0xab","value":"0x0"}],"size":18446744073709551615}}"#;
+        let before = stats();
+        let value = parse(body).unwrap();
+        assert_eq!(value["result"]["transactions"][0]["hash"], "0xab");
+        assert_eq!(value["result"]["size"], json!(u64::MAX));
+        // Field order is kept as with serde_json
+        assert_eq!(value["result"].as_object().unwrap().keys().next().unwrap(), "number");
+        assert!(stats().bytes >= before.bytes + body.len() as u64);
+        assert!(parse(b"{\"result\":").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Library side of the indexer: fetching, transforming and storing EVM block data.
+//!
+//! Optional subsystems are gated behind cargo features so library users and minimal
+//! deployments only build what they need:
+//! - `parquet`: Parquet input for `bootstrap`
+//! - `postgres`: PostgreSQL sink
+//! - `nats`: NATS/JetStream sink
+//! - `redis`: Redis Streams sink
+//! - `bigquery`: Google BigQuery sink
+//! - `api`: HTTP/GraphQL query API and its result cache (optionally in Redis)
+//! - `xlsx`: spreadsheet `export` (see `commands::export`)
+//! - `python`: PyO3 bindings (see `pyproject.toml`)
+//! - `ffi`: C ABI for embedding (see `include/sweet_potato.h`)
+//! - `arrow`: Arrow record batches of the datasets and the `arrow` output format
+//! - `flight`: Arrow Flight server for indexed datasets (includes `arrow`)
+//! - `otel`: span export to an OTLP collector (see `telemetry`)
+//! - `relay`: websocket fan-out of indexed blocks and transfers
+//! - `simd`: simd-json parsing of RPC responses (see `json`)
+//! - `sql`: in-process SQL over the indexed files for `query --embedded` (see `sql`)
+
+pub mod access_stats;
+pub mod activity;
+pub mod addresses;
+pub mod alerts;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod avro;
+pub mod backfill;
+pub mod balances;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod block_cache;
+pub mod bloom;
+pub mod bridge;
+pub mod certificate;
+#[cfg(feature = "api")]
+pub mod cache;
+pub mod clock;
+pub mod commands;
+pub mod config;
+pub mod control;
+pub mod coordination;
+pub mod events;
+pub mod faults;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filters;
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "flight")]
+pub mod flight;
+#[cfg(feature = "api")]
+pub mod graphql;
+pub mod json;
+pub mod linkage;
+pub mod manifest;
+pub mod mempool;
+pub mod metrics;
+pub mod migrate;
+pub mod models;
+pub mod networks;
+pub mod pipeline;
+pub mod presets;
+pub mod progress;
+pub mod quality;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "relay")]
+pub mod relay;
+pub mod raw;
+pub mod rpc;
+pub mod sampling;
+pub mod schedule;
+pub mod selector_stats;
+pub mod selectors;
+pub mod shutdown;
+pub mod sinks;
+pub mod spam;
+#[cfg(feature = "sql")]
+pub mod sql;
+pub mod state_calls;
+pub mod store;
+pub mod telemetry;
+pub mod token_metadata;
+pub mod topics;
+pub mod transform;
+pub mod transformers;
+pub mod trie;
@@ -0,0 +1,156 @@
+//! Checks that a block's receipts line up with its transactions before anything is derived from
+//! them: one receipt per transaction hash, receipts in transaction order with matching
+//! `transactionIndex`, receipts of this block, and `cumulativeGasUsed` never going down.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::models::{Block, Receipt};
+use crate::transform::hex_to_u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    Off,
+    // Log mismatches and keep the block
+    Warn,
+    // Fail the block's fetch, leaving a gap that `gaps --heal` re-fetches
+    Fail,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Warn as u8);
+
+impl Mode {
+    // RECEIPT_VALIDATION: off, warn (default) or fail
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("RECEIPT_VALIDATION").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "warn" => Ok(Mode::Warn),
+            "off" => Ok(Mode::Off),
+            "fail" => Ok(Mode::Fail),
+            other => Err(anyhow::anyhow!("RECEIPT_VALIDATION must be off, warn or fail, got {}", other)),
+        }
+    }
+}
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::SeqCst);
+}
+
+fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => Mode::Off,
+        1 => Mode::Warn,
+        _ => Mode::Fail,
+    }
+}
+
+// Every mismatch between the block's transaction list and its receipts, empty when they line up
+pub fn check(block: &Block, receipts: &[Receipt]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for receipt in receipts {
+        *counts.entry(receipt.transaction_hash.as_str()).or_default() += 1;
+    }
+    for hash in &block.transaction_hashes {
+        match counts.get(hash.as_str()).copied().unwrap_or_default() {
+            0 => problems.push(format!("no receipt for transaction {}", hash)),
+            1 => {},
+            n => problems.push(format!("{} receipts for transaction {}", n, hash)),
+        }
+    }
+    for receipt in receipts {
+        if !block.transaction_hashes.contains(&receipt.transaction_hash) {
+            problems.push(format!("receipt for transaction {} not in the block", receipt.transaction_hash));
+        }
+    }
+
+    let mut previous_gas = 0;
+    for (position, receipt) in receipts.iter().enumerate() {
+        let index = hex_to_u64(&receipt.transaction_index);
+        if index != position as u64 {
+            problems.push(format!("receipt {} at position {} has transactionIndex {}", receipt.transaction_hash, position, index));
+        } else if block.transaction_hashes.get(position) != Some(&receipt.transaction_hash) {
+            problems.push(format!("receipt {} at position {} belongs to another transaction", receipt.transaction_hash, position));
+        }
+        if receipt.block_hash != block.hash {
+            problems.push(format!("receipt {} has block hash {}", receipt.transaction_hash, receipt.block_hash));
+        }
+        let cumulative_gas = hex_to_u64(&receipt.cumulative_gas_used);
+        if cumulative_gas < previous_gas {
+            problems.push(format!(
+                "cumulativeGasUsed drops from {} to {} at receipt {}",
+                previous_gas, cumulative_gas, receipt.transaction_hash
+            ));
+        }
+        previous_gas = previous_gas.max(cumulative_gas);
+    }
+    problems
+}
+
+// Applies the configured mode to a fetched block
+pub fn enforce(block_number: u64, block: &Block, receipts: &[Receipt]) -> Result<()> {
+    let mode = mode();
+    if mode == Mode::Off {
+        return Ok(());
+    }
+    let problems = check(block, receipts);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let message = format!("Receipts of block {} do not match its transactions: {}", block_number, problems.join("; "));
+    match mode {
+        Mode::Fail => Err(anyhow::anyhow!(message)),
+        _ => {
+            log::warn!("{}", message);
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check() {
+        let receipt = |hash: &str, index: u64, cumulative: u64| -> Receipt {
+            serde_json::from_value(json!({
+                "blockHash": "0xb", "blockNumber": "0x1", "contractAddress": null,
+                "cumulativeGasUsed": format!("0x{:x}", cumulative), "from": "0xf", "gasUsed": "0x0", "logs": [],
+                "logsBloom": "0x", "status": "0x1", "to": null, "transactionHash": hash,
+                "transactionIndex": format!("0x{:x}", index), "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let mut block: Block = serde_json::from_value(json!({
+            "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x0", "gasUsed": "0x0", "hash": "0xb",
+            "logsBloom": "0x", "miner": "0xm", "mixHash": "0x", "nonce": "0x0", "number": "0x1", "parentHash": "0x",
+            "receiptsRoot": "0x", "sha3Uncles": "0x", "size": "0x0", "stateRoot": "0x", "timestamp": "0x0",
+            "totalDifficulty": "0x0", "transactions": ["0xa", "0xc"], "transactionsRoot": "0x", "uncles": [],
+        }))
+        .unwrap();
+
+        let good = vec![receipt("0xa", 0, 21000), receipt("0xc", 1, 42000)];
+        assert!(check(&block, &good).is_empty());
+
+        let swapped = vec![receipt("0xc", 1, 21000), receipt("0xa", 0, 42000)];
+        assert_eq!(check(&block, &swapped).len(), 2);
+
+        let duplicated = vec![receipt("0xa", 0, 21000), receipt("0xa", 0, 21000)];
+        let problems = check(&block, &duplicated);
+        assert!(problems.contains(&"no receipt for transaction 0xc".to_string()));
+        assert!(problems.contains(&"2 receipts for transaction 0xa".to_string()));
+
+        let dropping = vec![receipt("0xa", 0, 42000), receipt("0xc", 1, 21000)];
+        assert_eq!(check(&block, &dropping), vec!["cumulativeGasUsed drops from 42000 to 21000 at receipt 0xc"]);
+
+        block.hash = "0xother".to_string();
+        assert_eq!(check(&block, &good).len(), 2);
+        set_mode(Mode::Fail);
+        assert!(enforce(1, &block, &good).is_err());
+        set_mode(Mode::Warn);
+        assert!(enforce(1, &block, &good).is_ok());
+    }
+}
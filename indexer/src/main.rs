@@ -1,320 +1,1525 @@
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use clap::{Args, Parser, Subcommand};
 use std::env;
 use std::time::Instant;
-use chrono::{DateTime, Utc, TimeZone};
-use std::fs;
-use std::path::Path;
-
-const RPC_URL: &str = match option_env!("RPC_URL") {
-    Some(url) => url,
-    None => "https://rpc.sepolia.linea.build",
-};
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Transaction {
-    #[serde(rename = "blockHash")]
-    block_hash: String,
-    #[serde(rename = "blockNumber")]
-    block_number: String,
-    #[serde(rename = "chainId")]
-    chain_id: String,
-    from: String,
-    gas: String,
-    #[serde(rename = "gasPrice")]
-    gas_price: String,
-    hash: String,
-    input: String,
-    nonce: String,
-    r: String,
-    s: String,
-    to: Option<String>,
-    #[serde(rename = "transactionIndex")]
-    transaction_index: String,
-    #[serde(rename = "type")]
-    tx_type: String,
-    v: String,
-    value: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Block {
-    #[serde(rename = "baseFeePerGas")]
-    base_fee_per_gas: Option<String>,
-    difficulty: String,
-    #[serde(rename = "extraData")]
-    extra_data: String,
-    #[serde(rename = "gasLimit")]
-    gas_limit: String,
-    #[serde(rename = "gasUsed")]
-    gas_used: String,
-    hash: String,
-    #[serde(rename = "logsBloom")]
-    logs_bloom: String,
-    miner: String,
-    #[serde(rename = "mixHash")]
-    mix_hash: String,
-    nonce: String,
-    number: String,
-    #[serde(rename = "parentHash")]
-    parent_hash: String,
-    #[serde(rename = "receiptsRoot")]
-    receipts_root: String,
-    #[serde(rename = "sha3Uncles")]
-    sha3_uncles: String,
-    size: String,
-    #[serde(rename = "stateRoot")]
-    state_root: String,
-    timestamp: String,
-    #[serde(rename = "totalDifficulty")]
-    total_difficulty: String,
-    #[serde(rename = "transactions")]
-    transaction_hashes: Vec<String>,
-    #[serde(rename = "transactionsRoot")]
-    transactions_root: String,
-    uncles: Vec<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Receipt {
-    #[serde(rename = "blockHash")]
-    block_hash: String,
-    #[serde(rename = "blockNumber")]
-    block_number: String,
-    #[serde(rename = "contractAddress")]
-    contract_address: Option<String>,
-    #[serde(rename = "cumulativeGasUsed")]
-    cumulative_gas_used: String,
-    #[serde(rename = "effectiveGasPrice")]
-    effective_gas_price: String,
-    from: String,
-    #[serde(rename = "gasUsed")]
-    gas_used: String,
-    logs: Vec<Value>,
-    #[serde(rename = "logsBloom")]
-    logs_bloom: String,
-    status: String,
-    to: Option<String>,
-    #[serde(rename = "transactionHash")]
-    transaction_hash: String,
-    #[serde(rename = "transactionIndex")]
-    transaction_index: String,
-    #[serde(rename = "type")]
-    tx_type: String,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize)]
-struct TransformedReceipt {
-    block_hash: String,
-    block_number: u64,
-    contract_address: Option<String>,
-    cumulative_gas_used: u64,
-    effective_gas_price: u64,
-    from: String,
-    gas_used: u64,
-    logs: Vec<Value>,
-    logs_bloom: String,
-    status: bool,
-    to: Option<String>,
-    transaction_hash: String,
-    transaction_index: u64,
-    tx_type: u64,
-    datetime: DateTime<Utc>,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize)]
-struct TransformedTransaction {
-    block_hash: String,
-    block_number: u64,
+use std::sync::Arc;
+use futures::{stream, StreamExt, TryStreamExt};
+use tracing::Instrument;
+
+use indexer::config::{env_or, RuntimeConfig};
+use indexer::control;
+use indexer::models::{ActivityRole, TransformedBlock, TransformedReceipt, TransformedTransaction};
+use indexer::rpc::{self, Hydration};
+use indexer::sinks::clickhouse::{ClickHouseConfig, ClickHouseSink};
+use indexer::sinks::duckdb::{self, DuckDbConfig, DuckDbSink};
+use indexer::sinks::fanout::{Delivery, FanOut};
+#[cfg(feature = "postgres")]
+use indexer::sinks::{postgres::{PostgresConfig, PostgresSink}, Sink};
+use indexer::transform::{apply_native_units, attach_receipts, data_usage, extract_bridge_messages, extract_contracts, extract_logs, extract_nft_transfers, extract_token_transfers, ordering_analysis, hex_to_u64, receipt_fee_totals, set_address_case, set_fee_totals, set_native_units, set_transaction_fees, sort_by_index, transform_block, transform_receipt, transform_transaction, AddressCase};
+use indexer::{access_stats, addresses, alerts, backfill, balances, block_cache, certificate, commands, coordination, faults, filters, linkage, manifest, mempool, metrics, migrate, networks, pipeline, presets, progress, quality, sampling, schedule, selector_stats, shutdown, spam, state_calls, store, telemetry, token_metadata, transformers};
+
+#[derive(Parser)]
+#[command(name = "sweet-potato", about = "EVM node indexing pipeline", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    index: IndexArgs,
+
+    #[command(flatten)]
+    rpc: rpc::ClientConfig,
+
+    #[command(flatten)]
+    receipts: rpc::ReceiptConfig,
+
+    /// Case of the addresses written: `lower`, or `checksum` for EIP-55 mixed case
+    #[arg(long = "address-case", env = "ADDRESS_CASE", value_enum, default_value = "lower", global = true)]
+    address_case: AddressCase,
+
+    /// Also write value and fees in ether and gas prices in gwei, as exact decimal strings
+    #[arg(long = "native-units", env = "NATIVE_UNITS", global = true)]
+    native_units: bool,
+
+    /// Built-in network to index, e.g. `linea-mainnet`: sets the default RPC endpoint and checks its chain id (see `networks`)
+    #[arg(long, env = "NETWORK", value_parser = networks::parse, global = true)]
+    network: Option<&'static networks::Network>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch, transform and store a block range (the default)
+    Index(IndexArgs),
+    /// Keep indexing: catch up with the chain and heal gaps on cron schedules until stopped
+    Daemon(Box<DaemonArgs>),
+    /// Recompute transactionsRoot/receiptsRoot from fetched data and compare them with the headers
+    Verify(RangeArgs),
+    /// Inspect or adjust a running indexer through its admin socket
+    Ctl(CtlArgs),
+    /// Find block numbers missing from the output store and optionally re-fetch them
+    Gaps(GapsArgs),
+    /// Pre-populate storage from CSV/Parquet block exports, then continue from the RPC
+    Bootstrap(BootstrapArgs),
+    /// Fetch a small block range and export it as a spreadsheet
+    #[cfg(feature = "xlsx")]
+    Export(ExportArgs),
+    /// List indexed transactions touching an address (requires indexing with --address-index)
+    Activity(ActivityArgs),
+    /// Summarise an indexed block range as a human-readable HTML or Markdown report
+    Report(ReportArgs),
+    /// Fetch a block range and print summary statistics without writing anything
+    Peek(PeekArgs),
+    /// Fetch one transaction with its receipt and print it transformed, with decoded method, logs and token transfers
+    Tx {
+        /// Transaction hash
+        hash: String,
+
+        /// Print the transaction as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Fetch one block and print an explorer-style summary: time, miner, gas used, transactions, top value transfers
+    Block {
+        /// Block number (decimal or 0x-prefixed) or block hash
+        id: String,
+
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List token balance, ownership and allowance changes between two indexed heights
+    Diff(DiffArgs),
+    /// Estimate RPC requests, cost, runtime and storage of indexing a range
+    Plan(PlanArgs),
+    /// Rebuild a range's datasets from the raw RPC responses kept by `index --keep-raw`, offline
+    Transform(TransformArgs),
+    /// Run SQL over the indexed datasets with DuckDB
+    Query(QueryArgs),
+    /// Aggregate calls per day and function selector of an indexed range into `selector_stats`
+    SelectorStats(RangeArgs),
+    /// Show each database sink's checkpoint and queued batches, or replay the queues with --flush
+    Sinks(SinksArgs),
+    /// Rebuild logs blooms of an indexed range from its logs, compare them with the headers and measure false positives
+    BloomAudit(BloomAuditArgs),
+    /// Sign a completion certificate for an indexed range, or verify one against the local store
+    Certificate(CertificateArgs),
+    /// Locate and index the transactions an address sent in a nonce range, without scanning every block
+    Nonces(NonceArgs),
+    /// List the run manifests written after a given one, or check a manifest's files against their checksums
+    Manifest(ManifestArgs),
+    /// Upgrade the files and databases written by an older version of the indexer to the current dataset schemas
+    Migrate {
+        /// Only report what would be migrated
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show how often each dataset was read through the API and `query`, and the stored fields no read names
+    AccessStats {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the built-in networks `--network` accepts, with their chain id, currency, block time and default RPC
+    Networks,
+    /// Print the data dictionary: every dataset field with its type, RPC source and transform
+    Describe {
+        /// Only this dataset
+        dataset: Option<String>,
+
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: commands::describe::DescribeFormat,
+    },
+    /// Print the Avro schema `--output-format avro` writes a dataset with, e.g. for a schema registry
+    AvroSchema { dataset: String },
+    /// Serve the indexed store over a REST API
+    #[cfg(feature = "api")]
+    Serve(ServeArgs),
+    /// Serve indexed datasets over Arrow Flight
+    #[cfg(feature = "flight")]
+    Flight(FlightArgs),
+}
+
+#[cfg(feature = "api")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, env = "API_ADDR", default_value = "0.0.0.0:8080")]
+    listen: std::net::SocketAddr,
+}
+
+#[cfg(feature = "flight")]
+#[derive(Args, Debug)]
+struct FlightArgs {
+    /// Address to listen on
+    #[arg(long, env = "FLIGHT_ADDR", default_value = "0.0.0.0:50051")]
+    listen: std::net::SocketAddr,
+}
+
+#[derive(Args, Debug)]
+struct NonceArgs {
+    /// Sender whose transactions are indexed
+    address: String,
+
+    /// First nonce to index
+    #[arg(long = "from-nonce", default_value_t = 0)]
+    from_nonce: u64,
+
+    /// Last nonce to index (default: the latest the address has used)
+    #[arg(long = "to-nonce")]
+    to_nonce: Option<u64>,
+
+    /// How each nonce's block is found
+    #[arg(long, env = "NONCE_LOCATOR", value_enum, default_value = "binary-search")]
+    locator: commands::nonces::Locator,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+}
+
+#[derive(Args, Debug)]
+struct ActivityArgs {
+    address: String,
+
+    /// Only show entries where the address had this role
+    #[arg(long, value_enum)]
+    role: Option<ActivityRole>,
+
+    /// Index to read
+    #[arg(long, value_enum, default_value = "files")]
+    source: StoreSource,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// Height the changes are measured from (state after this block)
+    #[arg(long)]
+    from: u64,
+
+    /// Height the changes are measured to (state after this block)
+    #[arg(long)]
+    to: u64,
+
+    /// Only consider these token contracts
+    #[arg(long = "token", value_delimiter = ',')]
+    tokens: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct TransformArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+}
+
+#[derive(Args, Debug)]
+struct PeekArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Print the statistics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// SQL to run; every dataset is a table, e.g. "select count(*) from transactions where value > 0"
+    sql: String,
+
+    /// Query the DUCKDB_PATH database loaded by `index` instead of the indexed files
+    #[arg(long)]
+    database: bool,
+
+    /// Run the query in-process with Polars' SQL engine instead of through the `duckdb` client
+    #[cfg(feature = "sql")]
+    #[arg(long, conflicts_with = "database")]
+    embedded: bool,
+
+    #[arg(long, value_enum, default_value = "table")]
+    format: duckdb::QueryFormat,
+}
+
+#[derive(Args, Debug)]
+struct PlanArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    #[command(flatten)]
+    hydration: rpc::HydrationConfig,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+
+    #[command(flatten)]
+    balances: balances::BalanceConfig,
+
+    /// Contract calls that will run at every block (see `index --state-calls`)
+    #[arg(long, env = "STATE_CALLS_PATH")]
+    state_calls: Option<std::path::PathBuf>,
+
+    /// Share of the range that will be indexed (see `index --sample`)
+    #[arg(long, env = "SAMPLE")]
+    sample: Option<sampling::Sample>,
+
+    /// Blocks fetched across the range to measure requests, latency and size
+    #[arg(long, default_value_t = 5)]
+    probe_blocks: u64,
+
+    /// Provider price per million requests, for the cost estimate
+    #[arg(long, env = "RPC_COST_PER_MILLION", default_value_t = 0.0)]
+    cost_per_million: f64,
+
+    /// Print the plan as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
+    #[command(subcommand)]
+    kind: Option<ReportKind>,
+
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "html")]
+    format: commands::report::ReportFormat,
+
+    /// Language for labels and number formatting
+    #[arg(long, value_enum, env = "REPORT_LOCALE", default_value = "en")]
+    locale: commands::report::Locale,
+
+    /// Output file (default: report_<start>_<end>.html|md)
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum ReportKind {
+    /// Per-block gas, base fee and gas price percentiles, and the contracts using the most gas
+    Gas(GasReportArgs),
+}
+
+#[derive(Args, Debug)]
+struct GasReportArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    #[arg(long, value_enum, default_value = "json")]
+    format: commands::gas::GasReportFormat,
+
+    /// Table written as CSV
+    #[arg(long, value_enum, default_value = "blocks")]
+    table: commands::gas::GasTable,
+
+    /// Output file (default: stdout)
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SinksArgs {
+    /// Deliver queued batches to the sinks that are reachable again
+    #[arg(long)]
+    flush: bool,
+
+    /// Only these sinks (default: every configured one)
+    #[arg(long = "sink", env = "SINKS", value_delimiter = ',')]
+    sinks: Vec<String>,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+}
+
+#[derive(Args, Debug)]
+struct BloomAuditArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Addresses or topics to measure false positives with (default: every log-emitting address in the range)
+    #[arg(long = "probe", value_delimiter = ',')]
+    probes: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct CertificateArgs {
+    #[command(subcommand)]
+    action: CertificateAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum CertificateAction {
+    /// Checksum an indexed range and sign it with CERT_SIGNING_KEY
+    Sign(RangeArgs),
+    /// Check a certificate's signature and recompute its checksums from the local store
+    Verify {
+        /// Certificate file
+        path: std::path::PathBuf,
+
+        /// Only accept certificates signed by this hex ed25519 public key
+        #[arg(long = "public-key", env = "CERT_PUBLIC_KEY")]
+        public_key: Option<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ManifestArgs {
+    #[command(subcommand)]
+    action: ManifestAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum ManifestAction {
+    /// Print the run manifests in the order they were written
+    List {
+        /// Only manifests written after this one (file name), e.g. the last one a loader loaded
+        #[arg(long)]
+        after: Option<String>,
+    },
+    /// Check that every file a manifest lists is still there with the same checksum
+    Verify {
+        /// Manifest file
+        path: std::path::PathBuf,
+    },
+}
+
+#[cfg(feature = "xlsx")]
+#[derive(Args, Debug)]
+struct ExportArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Export format
+    #[arg(long, value_enum, default_value = "xlsx")]
+    format: commands::export::ExportFormat,
+
+    /// Output file (default: blocks_<start>_<end>.xlsx)
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct BootstrapArgs {
+    /// Blocks dataset (.csv or .parquet)
+    #[arg(long)]
+    blocks: std::path::PathBuf,
+
+    /// Transactions dataset (.csv or .parquet)
+    #[arg(long)]
+    transactions: Option<std::path::PathBuf>,
+
+    /// Receipts dataset (.csv or .parquet)
+    #[arg(long)]
+    receipts: Option<std::path::PathBuf>,
+
+    /// Ignore dataset rows above this block
+    #[arg(long, default_value_t = u64::MAX)]
+    up_to: u64,
+
+    /// Chain id used when the transactions dataset has no chain_id column
+    #[arg(long, default_value_t = 0)]
     chain_id: u64,
-    from: String,
-    gas: u64,
-    gas_price: u64,
-    hash: String,
-    input: String,
-    nonce: u64,
-    r: String,
-    s: String,
-    to: Option<String>,
-    transaction_index: u64,
-    tx_type: u64,
-    v: String,
-    value: u64,
-    datetime: DateTime<Utc>,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize)]
-struct TransformedBlock {
-    base_fee_per_gas: Option<u64>,
-    difficulty: u64,
-    extra_data: String,
-    gas_limit: u64,
-    gas_used: u64,
-    hash: String,
-    logs_bloom: String,
-    miner: String,
-    mix_hash: String,
-    nonce: String,
-    number: u64,
-    parent_hash: String,
-    receipts_root: String,
-    sha3_uncles: String,
-    size: u64,
-    state_root: String,
-    datetime: DateTime<Utc>,
-    total_difficulty: u64,
-    transaction_hashes: Vec<String>,
-    transactions_root: String,
-    uncles: Vec<String>,
-}
-
-// Helper functions
-fn hex_to_u64(hex: &str) -> u64 {
-    if let Some(hex_str) = hex.strip_prefix("0x") {
-        u64::from_str_radix(hex_str, 16).unwrap_or(0)
+
+    /// Blocks to index from the RPC after the last bootstrapped block
+    #[arg(long, default_value_t = 0)]
+    then_count: u64,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum StoreSource {
+    Files,
+    Clickhouse,
+}
+
+#[derive(Args, Debug)]
+struct GapsArgs {
+    /// First block of the scanned range (default: lowest indexed block)
+    #[arg(long)]
+    start: Option<u64>,
+
+    /// Number of blocks to scan (default: up to the highest indexed block)
+    #[arg(long)]
+    count: Option<u64>,
+
+    /// Store to scan
+    #[arg(long, value_enum, default_value = "files")]
+    source: StoreSource,
+
+    /// Re-fetch and store the missing blocks
+    #[arg(long)]
+    heal: bool,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+}
+
+#[derive(Args, Debug)]
+struct DaemonArgs {
+    /// Settings of every run; --start is where indexing begins when nothing is indexed yet
+    #[command(flatten)]
+    index: IndexArgs,
+
+    /// When to index the blocks added since the last run, as a cron schedule in UTC
+    #[arg(long, env = "DAEMON_SCHEDULE", default_value = "* * * * *")]
+    schedule: schedule::Schedule,
+
+    /// When to re-fetch blocks missing between --start and the last indexed block, as a cron schedule in UTC
+    #[arg(long, env = "DAEMON_HEAL_SCHEDULE", default_value = "0 * * * *")]
+    heal_schedule: schedule::Schedule,
+
+    /// Most blocks indexed per run; a longer backlog is caught up over several runs
+    #[arg(long, env = "DAEMON_MAX_BLOCKS", default_value_t = 10_000)]
+    max_blocks: u64,
+}
+
+#[derive(Args, Debug)]
+struct CtlArgs {
+    /// Admin socket of the running indexer
+    #[arg(long, env = "ADMIN_SOCKET", default_value = "./sweet-potato.sock")]
+    socket: std::path::PathBuf,
+
+    #[command(subcommand)]
+    action: CtlAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlAction {
+    /// Show progress, pause state, rate limit and watched addresses
+    Status,
+    /// Stop fetching new blocks until resumed
+    Pause,
+    /// Continue fetching after a pause
+    Resume,
+    /// Change the RPC requests-per-second limit (0 = unlimited)
+    SetRps { rps: u32 },
+    /// Log every transaction sent from or to this address
+    AddWatchAddress { address: String },
+}
+
+impl CtlAction {
+    fn to_command(&self) -> String {
+        match self {
+            CtlAction::Status => "status".to_string(),
+            CtlAction::Pause => "pause".to_string(),
+            CtlAction::Resume => "resume".to_string(),
+            CtlAction::SetRps { rps } => format!("set-rps {}", rps),
+            CtlAction::AddWatchAddress { address } => format!("add-watch-address {}", address),
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+struct IndexArgs {
+    #[command(flatten)]
+    range: RangeArgs,
+
+    /// Bundle of settings for a common job; flags and variables set explicitly take precedence
+    #[arg(long, env = "PRESET", value_enum)]
+    preset: Option<presets::Preset>,
+
+    #[command(flatten)]
+    hydration: rpc::HydrationConfig,
+
+    #[command(flatten)]
+    output: store::OutputConfig,
+
+    /// Disable the progress bar (it is also hidden when stderr is not a terminal)
+    #[arg(long, env = "NO_PROGRESS")]
+    no_progress: bool,
+
+    /// Record first-seen times of pending transactions for the ordering analysis
+    #[arg(long, env = "MEMPOOL")]
+    mempool: bool,
+
+    /// Only index a reproducible share of the range, e.g. `1%` or `0.01`
+    #[arg(long, env = "SAMPLE")]
+    sample: Option<sampling::Sample>,
+
+    /// Seed choosing which blocks are in the sample
+    #[arg(long, env = "SAMPLE_SEED", default_value_t = 0)]
+    sample_seed: u64,
+
+    /// JSON file of contract calls to run with eth_call at every indexed block (see `state_calls`)
+    #[arg(long, env = "STATE_CALLS_PATH")]
+    state_calls: Option<std::path::PathBuf>,
+
+    /// Look up symbol, name and decimals of transferred ERC-20 tokens with eth_call (cached in RAW_DATA_PATH)
+    #[arg(long, env = "TOKEN_METADATA")]
+    token_metadata: bool,
+
+    /// JSON file of include/exclude rules applied to every batch before it is written (see `filters`)
+    #[arg(long, env = "FILTERS_PATH")]
+    filters: Option<std::path::PathBuf>,
+
+    #[command(flatten)]
+    address_filter: filters::AddressFilter,
+
+    #[command(flatten)]
+    topic_filter: filters::TopicFilter,
+
+    #[command(flatten)]
+    balances: balances::BalanceConfig,
+
+    #[command(flatten)]
+    spam: spam::SpamConfig,
+
+    #[command(flatten)]
+    work: coordination::WorkStealingConfig,
+
+    /// Only index blocks at least this many blocks below the chain tip; the rest of the range is left for a later run
+    #[arg(long, env = "CONFIRMATIONS", default_value_t = 0)]
+    confirmations: u64,
+
+    /// Fetch and transform, then print what would be written where (and check the sinks' schemas) without writing anything
+    #[arg(long, env = "DRY_RUN")]
+    dry_run: bool,
+
+    /// Do not decode already indexed blocks for newly enabled decoders (--token-metadata, --state-calls)
+    #[arg(long, env = "NO_BACKFILL")]
+    no_backfill: bool,
+
+    /// Keep every fully fetched block's raw RPC responses under raw_data/raw_rpc, for `transform`
+    #[arg(long = "keep-raw", env = "KEEP_RAW")]
+    keep_raw: bool,
+
+    /// Sinks to deliver batches to, e.g. `clickhouse,duckdb` (default: every sink configured in the environment)
+    #[arg(long = "sink", env = "SINKS", value_delimiter = ',')]
+    sinks: Vec<String>,
+}
+
+impl IndexArgs {
+    // A range with default settings, for commands that index as a follow-up
+    fn plain(range: RangeArgs, output: store::OutputConfig) -> Self {
+        Self {
+            range,
+            preset: None,
+            hydration: Default::default(),
+            output,
+            no_progress: false,
+            mempool: false,
+            sample: None,
+            sample_seed: 0,
+            state_calls: None,
+            token_metadata: false,
+            filters: None,
+            address_filter: Default::default(),
+            topic_filter: Default::default(),
+            balances: Default::default(),
+            spam: Default::default(),
+            work: Default::default(),
+            confirmations: 0,
+            dry_run: false,
+            no_backfill: false,
+            keep_raw: false,
+            sinks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Args, Clone, Copy, Debug)]
+struct RangeArgs {
+    /// First block to process
+    #[arg(long, env = "START", default_value_t = 1)]
+    start: u64,
+
+    /// Number of blocks to process
+    #[arg(long, env = "COUNT", default_value_t = 1)]
+    count: u64,
+}
+
+fn main() -> Result<()> {
+    dotenv::from_path("../.env").ok();
+    let presets::Parsed { cli, preset, applied: preset_settings } =
+        presets::parse::<Cli>(&std::env::args().collect::<Vec<_>>()).unwrap_or_else(|e| e.exit());
+
+    let runtime_config = RuntimeConfig::from_env()?;
+    let runtime = runtime_config.build_runtime()?;
+    // The OTLP exporter runs on the runtime, so logging starts once it exists
+    let _telemetry = {
+        let _entered = runtime.enter();
+        telemetry::init(&telemetry::TelemetryConfig::from_env()?)?
+    };
+    rpc::configure_client(&cli.rpc)?;
+    if let Some(network) = cli.network {
+        networks::select(network);
+    }
+    rpc::set_receipt_config(&cli.receipts);
+    set_address_case(cli.address_case);
+    set_native_units(cli.native_units);
+    rpc::set_rps(env_or("RPC_RPS", 0)?);
+    rpc::set_strict_schema(env_or("RPC_STRICT_SCHEMA", false)?);
+    faults::configure(faults::FaultConfig::from_env()?);
+    block_cache::configure(&block_cache::BlockCacheConfig::from_env()?)?;
+    linkage::set_mode(linkage::Mode::from_env()?);
+    if let Some(preset) = preset {
+        let settings: Vec<String> = preset_settings.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        log::info!("Preset {}: {}", preset.name(), settings.join(" "));
+    }
+    log::info!(
+        "Runtime: {} worker threads, {} blocking threads, concurrency fetch={} transform={} write={}",
+        runtime_config.worker_threads,
+        runtime_config.max_blocking_threads,
+        runtime_config.fetch_concurrency,
+        runtime_config.transform_concurrency,
+        runtime_config.write_concurrency
+    );
+
+    match cli.command.unwrap_or(Command::Index(cli.index)) {
+        Command::Index(args) if args.work.work_stealing => runtime.block_on(async {
+            run_work_stealing(args, runtime_config).await?;
+            backfill::finish().await;
+            Ok(())
+        }),
+        Command::Index(args) => runtime.block_on(async {
+            let _lock = lock_range(&args).await?;
+            run(args, runtime_config).await?;
+            backfill::finish().await;
+            Ok(())
+        }),
+        Command::Daemon(args) => runtime.block_on(daemon(*args, runtime_config)),
+        Command::Verify(range) => runtime.block_on(commands::verify::run(
+            range.start,
+            range.count,
+            runtime_config.fetch_concurrency,
+        )),
+        Command::Gaps(args) => runtime.block_on(gaps(args, runtime_config)),
+        Command::Bootstrap(args) => runtime.block_on(bootstrap(args, runtime_config)),
+        #[cfg(feature = "xlsx")]
+        Command::Export(args) => runtime.block_on(export(args, runtime_config)),
+        Command::Activity(args) => runtime.block_on(activity(args)),
+        Command::Report(args) => report(args),
+        Command::Peek(args) => runtime.block_on(peek(args, runtime_config)),
+        Command::Tx { hash, json } => runtime.block_on(commands::tx::run(&hash, json)),
+        Command::Block { id, json } => runtime.block_on(commands::block::run(&id, json)),
+        Command::Diff(args) => diff(args),
+        Command::Plan(args) => runtime.block_on(plan(args, runtime_config)),
+        Command::Transform(args) => runtime.block_on(commands::transform::run(
+            &store::raw_data_path(),
+            args.range.start,
+            args.range.count,
+            args.output,
+            runtime_config.write_concurrency,
+        )),
+        Command::Query(args) => runtime.block_on(query(args)),
+        Command::SelectorStats(range) => selector_stats(range),
+        Command::Sinks(args) => runtime.block_on(sinks(args)),
+        Command::BloomAudit(args) => {
+            let RangeArgs { start, count } = args.range;
+            commands::bloom_audit::run(&store::raw_data_path(), start, start + count.saturating_sub(1), &args.probes)
+        },
+        Command::Certificate(args) => certificate(args),
+        Command::Nonces(args) => runtime.block_on(nonces(args, runtime_config)),
+        Command::Manifest(args) => run_manifests(args),
+        Command::Migrate { dry_run } => runtime.block_on(run_migrations(dry_run)),
+        Command::AccessStats { json } => print_access_stats(json),
+        Command::Networks => {
+            println!("{}", networks::render());
+            Ok(())
+        },
+        Command::Describe { dataset, format } => {
+            let docs = commands::describe::describe(dataset.as_deref())?;
+            print!("{}", commands::describe::render(&docs, format)?);
+            Ok(())
+        },
+        Command::AvroSchema { dataset } => {
+            println!("{}", serde_json::to_string_pretty(&indexer::avro::dataset_schema(&dataset)?.to_json())?);
+            Ok(())
+        },
+        #[cfg(feature = "api")]
+        Command::Serve(args) => runtime.block_on(indexer::api::serve(args.listen, store::raw_data_path())),
+        #[cfg(feature = "flight")]
+        Command::Flight(args) => runtime.block_on(indexer::flight::serve(args.listen, store::raw_data_path())),
+        Command::Ctl(args) => runtime.block_on(async {
+            let response = control::send_command(&args.socket, &args.action.to_command()).await?;
+            println!("{}", serde_json::to_string_pretty(&response)?);
+            if response["ok"] != serde_json::json!(true) {
+                return Err(anyhow::anyhow!("Command rejected by the indexer"));
+            }
+            Ok(())
+        }),
+    }
+}
+
+async fn gaps(args: GapsArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let present = match args.source {
+        StoreSource::Files => store::indexed_block_numbers(&store::raw_data_path())?,
+        StoreSource::Clickhouse => {
+            let config = ClickHouseConfig::from_env()?
+                .ok_or_else(|| anyhow::anyhow!("CLICKHOUSE_URL must be set to scan ClickHouse"))?;
+            ClickHouseSink::connect(config).await?.block_numbers().await?
+        },
+    };
+
+    let (first, last) = commands::gaps::scan_range(&present, args.start, args.count)?;
+    let gaps = commands::gaps::find_gaps(&present, first, last);
+    commands::gaps::print_gaps(&gaps, first, last);
+
+    if args.heal {
+        for (gap_start, gap_end) in gaps {
+            if shutdown::is_requested() {
+                break;
+            }
+            log::info!("Healing blocks {} to {}", gap_start, gap_end);
+            let range = RangeArgs { start: gap_start, count: gap_end - gap_start + 1 };
+            let heal = IndexArgs::plain(range, args.output);
+            let _lock = lock_range(&heal).await?;
+            Box::pin(run(heal, runtime_config.clone())).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn bootstrap(args: BootstrapArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let clickhouse = match ClickHouseConfig::from_env()? {
+        Some(config) => Some(ClickHouseSink::connect(config.with_partitioning(args.output.partition, args.output.partition_size)).await?),
+        None => None,
+    };
+    let dirs = store::OutputDirs::create(&store::raw_data_path())?;
+
+    let input = commands::bootstrap::BootstrapInput {
+        blocks: &args.blocks,
+        transactions: args.transactions.as_deref(),
+        receipts: args.receipts.as_deref(),
+        up_to: args.up_to,
+        chain_id: args.chain_id,
+    };
+    let highest = commands::bootstrap::ingest(input, &dirs, args.output, clickhouse.as_ref(), runtime_config.write_concurrency).await?;
+
+    match highest {
+        Some(highest) => {
+            log::info!("Bootstrapped storage up to block {}", highest);
+            if args.then_count > 0 {
+                let range = RangeArgs { start: highest + 1, count: args.then_count };
+                run(IndexArgs::plain(range, args.output), runtime_config).await?;
+            }
+        },
+        None => log::warn!("No blocks found in {}", args.blocks.display()),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "xlsx")]
+async fn export(args: ExportArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let RangeArgs { start, count } = args.range;
+    let batch = pipeline::fetch_range(start, count, runtime_config.fetch_concurrency).await?;
+    match args.format {
+        commands::export::ExportFormat::Xlsx => {
+            let path = args.output.unwrap_or_else(|| {
+                format!("blocks_{}_{}.xlsx", start, start + count.saturating_sub(1)).into()
+            });
+            commands::export::write_xlsx(&batch, &path)?;
+            println!(
+                "Exported {} blocks, {} transactions and {} receipts to {}",
+                batch.blocks.len(),
+                batch.transactions.len(),
+                batch.receipts.len(),
+                path.display()
+            );
+        },
+    }
+    Ok(())
+}
+
+async fn peek(args: PeekArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let RangeArgs { start, count } = args.range;
+    let batch = pipeline::fetch_range(start, count, runtime_config.fetch_concurrency).await?;
+    let stats = commands::peek::compute(&batch);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
     } else {
-        u64::from_str_radix(hex, 16).unwrap_or(0)
+        println!("{}", commands::peek::render(&stats));
     }
+    Ok(())
 }
 
-fn hex_to_bool(hex: &str) -> bool {
-    hex_to_u64(hex) == 1
+async fn nonces(args: NonceArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let dirs = store::OutputDirs::create(&store::raw_data_path())?;
+    let nonces = args.from_nonce..=args.to_nonce.unwrap_or(u64::MAX);
+    let report = commands::nonces::index(
+        &args.address,
+        nonces,
+        args.locator,
+        &dirs,
+        args.output,
+        runtime_config.fetch_concurrency,
+        runtime_config.write_concurrency,
+    )
+    .await?;
+    println!(
+        "Located {} nonces of {} in {} blocks, wrote {} transactions{}",
+        report.located,
+        args.address,
+        report.blocks,
+        report.written,
+        match report.missing.is_empty() {
+            true => String::new(),
+            false => format!(" ({} nonces without a transaction)", report.missing.len()),
+        }
+    );
+    Ok(())
 }
 
-async fn get_block(number: u64) -> Result<(Block, Vec<Transaction>)> {
-    let start = Instant::now();
-    let client = reqwest::Client::new();
-    let hex_number = format!("0x{:x}", number);
-    
-    log::info!("Fetching block {}", number);
-    
-    let response = client
-        .post(RPC_URL)
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "eth_getBlockByNumber",
-            "params": [hex_number, true]
-        }))
-        .send()
-        .await?;
+async fn activity(args: ActivityArgs) -> Result<()> {
+    let entries = match args.source {
+        StoreSource::Files => indexer::activity::lookup(&store::raw_data_path(), &args.address, args.role)?,
+        StoreSource::Clickhouse => {
+            let config = ClickHouseConfig::from_env()?
+                .ok_or_else(|| anyhow::anyhow!("CLICKHOUSE_URL must be set to read from ClickHouse"))?;
+            ClickHouseSink::connect(config).await?.address_activity(&args.address, args.role).await?
+        },
+    };
+    for entry in &entries {
+        println!("{}", serde_json::to_string(entry)?);
+    }
+    log::info!("{} entries for {}", entries.len(), args.address);
+    Ok(())
+}
 
-    let data: Value = response.json().await?;
-    let elapsed = start.elapsed();
-    
-    match data.get("result") {
-        Some(result) => {
-            // First, parse the full response to get transactions
-            let full_block: Value = result.clone();
-            let transactions: Vec<Transaction> = serde_json::from_value(full_block["transactions"].clone())?;
-            
-            // Then modify the transactions field to only contain hashes
-            let mut block_value = result.clone();
-            if let Some(txs) = block_value.as_object_mut() {
-                let tx_hashes: Vec<String> = transactions.iter()
-                    .map(|tx| tx.hash.clone())
-                    .collect();
-                txs["transactions"] = json!(tx_hashes);
+async fn plan(args: PlanArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let RangeArgs { start, count } = args.range;
+    let probes = commands::plan::sample_blocks(start, count, args.probe_blocks);
+    log::info!("Fetching {} blocks to measure the range", probes.len());
+    let sample = commands::plan::measure(&probes, &args.hydration, args.output).await?;
+
+    let state_calls = match &args.state_calls {
+        Some(path) => state_calls::load(path)?.len(),
+        None => 0,
+    };
+    let balance_lookups = args.balances.balance_addresses.len() as f64 / args.balances.balance_every.max(1) as f64;
+    let inputs = commands::plan::PlanInputs {
+        blocks: args.sample.map_or(count, |sample| (count as f64 * sample.rate()).ceil() as u64),
+        extra_requests_per_block: balance_lookups + state_calls as f64,
+        rps: rpc::rps(),
+        fetch_concurrency: runtime_config.fetch_concurrency,
+        cost_per_million_requests: args.cost_per_million,
+    };
+    let plan = commands::plan::estimate(&sample, &inputs);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+    } else {
+        println!("{}", commands::plan::render(&plan));
+    }
+    Ok(())
+}
+
+async fn query(args: QueryArgs) -> Result<()> {
+    #[cfg(feature = "sql")]
+    if args.embedded {
+        print!("{}", indexer::sql::query(&store::raw_data_path(), args.format, &args.sql).await?);
+        access_stats::record_sql(&args.sql);
+        return access_stats::flush(&store::raw_data_path());
+    }
+    let config = DuckDbConfig::from_env()?;
+    let database = if args.database {
+        Some(config.database.clone().ok_or_else(|| anyhow::anyhow!("DUCKDB_PATH must be set to query the database"))?)
+    } else {
+        None
+    };
+    let output = duckdb::query(&config, &store::raw_data_path(), database.as_deref(), args.format, &args.sql).await?;
+    print!("{}", output);
+    access_stats::record_sql(&args.sql);
+    access_stats::flush(&store::raw_data_path())
+}
+
+fn diff(args: DiffArgs) -> Result<()> {
+    if args.from >= args.to {
+        return Err(anyhow::anyhow!("--from must be lower than --to"));
+    }
+    // Balances and owners are replayed from the first indexed block
+    let mut receipts: Vec<TransformedReceipt> = store::read_range(&store::raw_data_path(), "receipts", 0, args.to)?;
+    let changes = commands::diff::compute(&mut receipts, args.from, args.to, &args.tokens);
+    for change in &changes {
+        println!("{}", serde_json::to_string(change)?);
+    }
+    log::info!("{} state changes between blocks {} and {}", changes.len(), args.from, args.to);
+    Ok(())
+}
+
+fn report(args: ReportArgs) -> Result<()> {
+    if let Some(ReportKind::Gas(args)) = args.kind {
+        return gas_report(args);
+    }
+    let RangeArgs { start, count } = args.range;
+    let last = start + count.saturating_sub(1);
+    let raw_data_path = store::raw_data_path();
+
+    let blocks: Vec<TransformedBlock> = store::read_range(&raw_data_path, "blocks", start, last)?;
+    if blocks.is_empty() {
+        return Err(anyhow::anyhow!("No indexed blocks found between {} and {} in {}", start, last, raw_data_path));
+    }
+    let transactions: Vec<TransformedTransaction> = store::read_range(&raw_data_path, "transactions", start, last)?;
+    let receipts: Vec<TransformedReceipt> = store::read_range(&raw_data_path, "receipts", start, last)?;
+
+    let data = commands::report::compute(&blocks, &transactions, &receipts);
+    let rendered = commands::report::render(&data, args.format, args.locale);
+    let path = args.output.unwrap_or_else(|| {
+        let extension = match args.format {
+            commands::report::ReportFormat::Html => "html",
+            commands::report::ReportFormat::Markdown => "md",
+        };
+        format!("report_{}_{}.{}", start, last, extension).into()
+    });
+    std::fs::write(&path, rendered)?;
+    println!("Wrote report for {} blocks to {}", data.blocks, path.display());
+    Ok(())
+}
+
+fn selector_stats(range: RangeArgs) -> Result<()> {
+    let RangeArgs { start, count } = range;
+    let last = start + count.saturating_sub(1);
+    let raw_data_path = store::raw_data_path();
+
+    let transactions: Vec<TransformedTransaction> = store::read_range(&raw_data_path, "transactions", start, last)?;
+    if transactions.is_empty() {
+        return Err(anyhow::anyhow!("No indexed transactions found between {} and {} in {}", start, last, raw_data_path));
+    }
+    let receipts: Vec<TransformedReceipt> = store::read_range(&raw_data_path, "receipts", start, last)?;
+    let stats = selector_stats::compute(&transactions, &receipts);
+    let written = selector_stats::write(&raw_data_path, &stats)?;
+    log::info!("Wrote {} selector stats rows for {} days to {}/{}", stats.len(), written.len(), raw_data_path, selector_stats::DIR);
+    Ok(())
+}
+
+async fn sinks(args: SinksArgs) -> Result<()> {
+    let fanout = FanOut::from_env(&store::raw_data_path(), &args.sinks, args.output)?;
+    if fanout.sinks().is_empty() {
+        return Err(anyhow::anyhow!("No sinks configured; set CLICKHOUSE_URL, DUCKDB_PATH, POSTGRES_URL or WEBHOOK_URL"));
+    }
+    fanout.reconcile().await?;
+    if args.flush {
+        for (sink, delivered, error) in fanout.flush().await? {
+            match error {
+                Some(e) => println!("{}: delivered {} queued batches, stopped at: {}", sink, delivered, e),
+                None => println!("{}: delivered {} queued batches", sink, delivered),
+            }
+        }
+    }
+    for sink in fanout.sinks() {
+        let checkpoint = fanout.checkpoint(sink.as_ref())?;
+        println!(
+            "{}: next block {}, {} batches queued{}",
+            sink.name(),
+            checkpoint.next_block,
+            checkpoint.queued_batches,
+            checkpoint.last_error.map(|e| format!(", last error: {}", e)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+fn certificate(args: CertificateArgs) -> Result<()> {
+    let raw_data_path = store::raw_data_path();
+    match args.action {
+        CertificateAction::Sign(RangeArgs { start, count }) => {
+            let key = certificate::signing_key_from_env()?
+                .ok_or_else(|| anyhow::anyhow!("Set CERT_SIGNING_KEY or CERT_SIGNING_KEY_PATH to sign certificates"))?;
+            let path = certificate::certify(&raw_data_path, start, start + count.saturating_sub(1), &key)?;
+            println!("Wrote {}", path.display());
+        },
+        CertificateAction::Verify { path, public_key } => {
+            let trusted = public_key.as_deref().map(certificate::parse_public_key).transpose()?;
+            let certificate = certificate::verify(&raw_data_path, &path, trusted.as_ref())?;
+            println!(
+                "Certificate for blocks {} to {} is valid, signed by {} at {}",
+                certificate.first_block, certificate.last_block, certificate.public_key, certificate.created_at
+            );
+            for (dataset, checksum) in &certificate.datasets {
+                println!("  {}: {} records, sha256 {}", dataset, checksum.records, checksum.sha256);
             }
-            
-            let block: Block = serde_json::from_value(block_value)?;
-            log::info!("Block {} fetched in {:?}", number, elapsed);
-            Ok((block, transactions))
         },
-        None => Err(anyhow::anyhow!("No result field in response"))
     }
+    Ok(())
 }
 
-async fn get_block_receipts(number: u64) -> Result<Vec<Receipt>> {
-    let start = Instant::now();
-    let client = reqwest::Client::new();
-    let hex_number = format!("0x{:x}", number);
-    
-    log::info!("Fetching receipts for block {}", number);
-    
-    let response = client
-        .post(RPC_URL)
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "eth_getBlockReceipts",
-            "params": [hex_number]
-        }))
-        .send()
-        .await?;
+fn run_manifests(args: ManifestArgs) -> Result<()> {
+    let raw_data_path = store::raw_data_path();
+    match args.action {
+        ManifestAction::List { after } => {
+            for path in manifest::list(&raw_data_path, after.as_deref())? {
+                let run = manifest::read(&path)?;
+                let rows: usize = run.datasets.values().map(|dataset| dataset.rows).sum();
+                let files: usize = run.datasets.values().map(|dataset| dataset.files.len()).sum();
+                println!("{}: blocks {} to {}, {} rows in {} files", path.display(), run.first_block, run.last_block, rows, files);
+            }
+        },
+        ManifestAction::Verify { path } => {
+            let run = manifest::read(&path)?;
+            let problems = manifest::verify(&raw_data_path, &run)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("  {}", problem);
+                }
+                return Err(anyhow::anyhow!("{} of the manifest's files are missing or changed", problems.len()));
+            }
+            println!("All files of {} match their checksums", path.display());
+        },
+    }
+    Ok(())
+}
 
-    let data: Value = response.json().await?;
-    let elapsed = start.elapsed();
-    
-    match data.get("result") {
-        Some(result) => {
-            let receipts: Vec<Receipt> = serde_json::from_value(result.clone())?;
-            log::info!("Receipts for block {} fetched in {:?}", number, elapsed);
-            Ok(receipts)
+fn print_access_stats(json: bool) -> Result<()> {
+    let (stats, reports) = access_stats::report(&store::raw_data_path())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    match stats.since {
+        Some(since) => println!("Reads counted since {}", since.to_rfc3339()),
+        None => println!("No reads counted yet; `serve` and `query` count them"),
+    }
+    for report in &reports {
+        println!("{}", access_stats::render(report, 0.0));
+    }
+    Ok(())
+}
+
+fn print_migrations(target: &str, from: u32, migrations: &[&migrate::Migration], dry_run: bool) {
+    if migrations.is_empty() {
+        println!("{}: schema version {}, up to date", target, from);
+        return;
+    }
+    let verb = if dry_run { "would migrate" } else { "migrated" };
+    println!("{}: {} from schema version {} to {}", target, verb, from, store::SCHEMA_VERSION);
+    for migration in migrations {
+        println!("  v{} {}: {}", migration.version, migration.dataset, migration.description);
+    }
+}
+
+async fn run_migrations(dry_run: bool) -> Result<()> {
+    let raw_data_path = store::raw_data_path();
+    let report = migrate::migrate_files(&raw_data_path, dry_run)?;
+    print_migrations(&raw_data_path, report.from, &migrate::pending(report.from).collect::<Vec<_>>(), dry_run);
+    if report.files > 0 {
+        println!("  {} records in {} files", report.records, report.files);
+    }
+
+    if let Some(config) = ClickHouseConfig::from_env()? {
+        let database = config.database.clone();
+        match ClickHouseSink::migrate(config, dry_run).await? {
+            (Some(from), migrations) => print_migrations(&format!("ClickHouse {}", database), from, &migrations, dry_run),
+            (None, _) => println!("ClickHouse {}: no tables yet", database),
+        }
+    }
+    if let Some(sink) = DuckDbSink::new(DuckDbConfig::from_env()?) {
+        let (from, migrations) = sink.migrate(dry_run).await?;
+        print_migrations(&format!("DuckDB {}", sink.database().display()), from, &migrations, dry_run);
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(config) = PostgresConfig::from_env()? {
+        let sink = PostgresSink::new(config);
+        let (from, migrations) = sink.migrate(dry_run).await?;
+        print_migrations(&sink.describe(), from, &migrations, dry_run);
+    }
+    Ok(())
+}
+
+fn gas_report(args: GasReportArgs) -> Result<()> {
+    let RangeArgs { start, count } = args.range;
+    let last = start + count.saturating_sub(1);
+    let raw_data_path = store::raw_data_path();
+
+    let blocks: Vec<TransformedBlock> = store::read_range(&raw_data_path, "blocks", start, last)?;
+    if blocks.is_empty() {
+        return Err(anyhow::anyhow!("No indexed blocks found between {} and {} in {}", start, last, raw_data_path));
+    }
+    let transactions: Vec<TransformedTransaction> = store::read_range(&raw_data_path, "transactions", start, last)?;
+    let receipts: Vec<TransformedReceipt> = store::read_range(&raw_data_path, "receipts", start, last)?;
+
+    let report = commands::gas::compute(&blocks, &transactions, &receipts);
+    match args.output {
+        Some(path) => {
+            commands::gas::write(&report, args.format, args.table, std::io::BufWriter::new(std::fs::File::create(&path)?))?;
+            log::info!("Wrote gas report for {} blocks to {}", report.summary.blocks, path.display());
         },
-        None => Err(anyhow::anyhow!("No result field in response"))
+        None => commands::gas::write(&report, args.format, args.table, std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+// Refuses to index a range another process is indexing into the same RAW_DATA_PATH; work stealing
+// coordinates through its leases instead, and a dry run writes nothing
+async fn lock_range(args: &IndexArgs) -> Result<Option<coordination::InstanceLock>> {
+    if args.dry_run {
+        return Ok(None);
     }
+    let chain_id = rpc::get_chain_id().await.unwrap_or_else(|e| {
+        log::warn!("Could not fetch the chain id, locking the range for every chain: {}", e);
+        0
+    });
+    let RangeArgs { start, count } = args.range;
+    let last = (start + count).saturating_sub(1);
+    coordination::InstanceLock::acquire(&store::raw_data_path(), chain_id, start, last, coordination::replica_id()).map(Some)
 }
 
-// Add this function near other helper functions
-fn ensure_directory(path: &str) -> Result<()> {
-    if !Path::new(path).exists() {
-        fs::create_dir_all(path)?;
+// Indexes chunks of the range claimed from the shared work queue until every chunk is done, so
+// replicas added by an autoscaler take over part of the remaining backfill
+async fn run_work_stealing(args: IndexArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    let RangeArgs { start, count } = args.range;
+    if args.dry_run {
+        return Err(anyhow::anyhow!("--dry-run cannot be combined with --work-stealing, which records finished chunks"));
+    }
+    // Replicas share the queue of one fixed range, so it cannot be trimmed to what each one sees
+    let (head, tip) = safe_head(args.confirmations).await?;
+    if rpc::blocks_up_to(start, count, head) < count {
+        return Err(anyhow::anyhow!(
+            "Work stealing needs the whole range at or below the safe head {} (tip {}, {} confirmations); lower --count to at most {}",
+            head,
+            tip,
+            args.confirmations,
+            rpc::blocks_up_to(start, count, head)
+        ));
+    }
+    let lease = std::time::Duration::from_secs(args.work.work_lease_secs);
+    let queue = Arc::new(coordination::WorkQueue::open(
+        &store::raw_data_path(),
+        start,
+        count,
+        args.work.work_chunk_blocks,
+        lease,
+        coordination::replica_id(),
+    )?);
+    shutdown::listen();
+
+    while !shutdown::is_requested() {
+        metrics::set_backlog(queue.unclaimed_blocks());
+        let Some(chunk) = queue.claim_next()? else {
+            if queue.is_finished() {
+                log::info!("Every chunk of blocks {} to {} is indexed", start, start + count.saturating_sub(1));
+                break;
+            }
+            // The rest is claimed by other replicas; wait in case one of them stops renewing its lease
+            tokio::select! {
+                _ = indexer::clock::sleep(lease / 4) => {},
+                _ = shutdown::wait() => {},
+            }
+            continue;
+        };
+        metrics::set_backlog(queue.unclaimed_blocks());
+        log::info!("Claimed blocks {} to {}", chunk.first, chunk.last);
+
+        let heartbeat = {
+            let queue = Arc::clone(&queue);
+            tokio::spawn(async move {
+                loop {
+                    indexer::clock::sleep(lease / 3).await;
+                    match queue.renew(&chunk) {
+                        Ok(true) => {},
+                        Ok(false) => log::warn!("Lost the lease on blocks {} to {} to another replica", chunk.first, chunk.last),
+                        Err(e) => log::warn!("Could not renew the lease on blocks {} to {}: {}", chunk.first, chunk.last, e),
+                    }
+                }
+            })
+        };
+        let range = RangeArgs { start: chunk.first, count: chunk.count() };
+        let result = Box::pin(run(IndexArgs { range, work: Default::default(), ..args.clone() }, runtime_config.clone())).await;
+        heartbeat.abort();
+
+        if result.is_err() || shutdown::is_requested() {
+            queue.release(&chunk);
+            result?;
+        } else {
+            queue.complete(&chunk)?;
+        }
     }
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    dotenv::from_path("../.env").ok();
-    env_logger::init();
+// Catches up with the chain and heals gaps whenever their schedules are due, until a shutdown signal.
+// A failed run is logged and retried at the next due time.
+async fn daemon(args: DaemonArgs, runtime_config: RuntimeConfig) -> Result<()> {
+    if args.index.work.work_stealing {
+        return Err(anyhow::anyhow!("--work-stealing cannot be combined with daemon, which picks its own ranges"));
+    }
+    if args.max_blocks == 0 {
+        return Err(anyhow::anyhow!("--max-blocks must be greater than 0"));
+    }
+    let raw_data_path = store::raw_data_path();
+    let highest = store::indexed_block_numbers(&raw_data_path)?.last().map(|number| number + 1);
+    let mut next = [Some(args.index.range.start), store::read_checkpoint(&raw_data_path)?, highest].into_iter().flatten().max().unwrap_or_default();
+    shutdown::listen();
+    // Probes answer between runs too
+    start_monitoring()?;
+    metrics::set_indexed(next.saturating_sub(1));
+    log::info!(
+        "Daemon: indexing from block {} on `{}`, healing gaps on `{}` (UTC)",
+        next,
+        args.schedule,
+        args.heal_schedule
+    );
+
+    // The first catch-up runs right away rather than at the first due time
+    let mut catch_up_at = Some(indexer::clock::now());
+    let mut heal_at = args.heal_schedule.next_after(indexer::clock::now());
+    while !shutdown::is_requested() {
+        if catch_up_at.is_some_and(|at| at <= indexer::clock::now()) {
+            match catch_up(&args, next, &runtime_config).await {
+                Ok(caught_up) => {
+                    next = caught_up;
+                    metrics::set_indexed(next.saturating_sub(1));
+                },
+                Err(e) => log::error!("Indexing from block {} failed: {}", next, e),
+            }
+            catch_up_at = args.schedule.next_after(indexer::clock::now());
+        }
+        if heal_at.is_some_and(|at| at <= indexer::clock::now()) && !shutdown::is_requested() {
+            if let Err(e) = heal(&args, next, &runtime_config).await {
+                log::error!("Healing gaps failed: {}", e);
+            }
+            heal_at = args.heal_schedule.next_after(indexer::clock::now());
+        }
+        let Some(wake) = [catch_up_at, heal_at].into_iter().flatten().min() else {
+            log::warn!("Neither schedule is due again, stopping");
+            break;
+        };
+        tokio::select! {
+            _ = indexer::clock::sleep((wake - indexer::clock::now()).to_std().unwrap_or_default()) => {},
+            _ = shutdown::wait() => {},
+        }
+    }
+    backfill::finish().await;
+    Ok(())
+}
+
+// Indexes up to --max-blocks blocks from `next` towards the safe head, returning the block to continue from
+async fn catch_up(args: &DaemonArgs, next: u64, runtime_config: &RuntimeConfig) -> Result<u64> {
+    let (head, tip) = safe_head(args.index.confirmations).await?;
+    if head < next {
+        log::debug!("No new blocks at or below the safe head {} (tip {})", head, tip);
+        return Ok(next);
+    }
+    let range = RangeArgs { start: next, count: (head + 1 - next).min(args.max_blocks) };
+    let index = IndexArgs { range, ..args.index.clone() };
+    let _lock = lock_range(&index).await?;
+    Box::pin(run(index, runtime_config.clone())).await?;
+    // The checkpoint stops short of the range when the run was interrupted
+    Ok(store::read_checkpoint(&store::raw_data_path())?.unwrap_or(next).max(next))
+}
+
+// Re-indexes the blocks missing between --start and `next`
+async fn heal(args: &DaemonArgs, next: u64, runtime_config: &RuntimeConfig) -> Result<()> {
+    let start = args.index.range.start;
+    // Blocks left out of a sample are missing by design
+    if next <= start || args.index.sample.is_some() {
+        return Ok(());
+    }
+    let present = store::indexed_block_numbers(&store::raw_data_path())?;
+    let gaps = commands::gaps::find_gaps(&present, start, next - 1);
+    let missing: u64 = gaps.iter().map(|(first, last)| last - first + 1).sum();
+    log::info!("Found {} missing blocks in {} gaps between blocks {} and {}", missing, gaps.len(), start, next - 1);
+    for (first, last) in gaps {
+        if shutdown::is_requested() {
+            break;
+        }
+        log::info!("Healing blocks {} to {}", first, last);
+        let index = IndexArgs { range: RangeArgs { start: first, count: last - first + 1 }, ..args.index.clone() };
+        let _lock = lock_range(&index).await?;
+        Box::pin(run(index, runtime_config.clone())).await?;
+    }
+    Ok(())
+}
+
+// Lets an HPA or KEDA scale replicas on how far behind the head and how much work is queued,
+// Kubernetes probe their health and a hook page someone when they fall behind; once per process
+fn start_monitoring() -> Result<()> {
+    if let Ok(addr) = env::var("METRICS_ADDR") {
+        let addr = addr.parse().map_err(|e| anyhow::anyhow!("Invalid METRICS_ADDR {}: {}", addr, e))?;
+        metrics::set_ready_max_lag(env_or("READY_MAX_LAG", 100)?);
+        metrics::serve(addr, std::time::Duration::from_secs(env_or("METRICS_HEAD_POLL_SECS", 15)?));
+    }
+    alerts::watch(alerts::LagAlertConfig::from_env()?);
+    Ok(())
+}
+
+// The safe head (the tip minus `confirmations`) and the chain tip
+async fn safe_head(confirmations: u64) -> Result<(u64, u64)> {
+    let tip = rpc::get_latest_block_number().await?;
+    Ok((tip.saturating_sub(confirmations), tip))
+}
+
+async fn run(args: IndexArgs, runtime_config: RuntimeConfig) -> Result<()> {
     let start_time = Instant::now();
-    
-    let start = env::var("START")
-        .unwrap_or_else(|_| "1".to_string())
-        .parse::<u64>()?;
-    
-    let count = env::var("COUNT")
-        .unwrap_or_else(|_| "1".to_string())
-        .parse::<u64>()?;
+    let RangeArgs { start, count } = args.range;
+
+    // Blocks past the safe head do not exist yet or may still be reorged out
+    let (head, tip) = safe_head(args.confirmations).await?;
+    let available = rpc::blocks_up_to(start, count, head);
+    if available < count {
+        log::warn!(
+            "Blocks {} to {} are beyond the safe head {} (tip {}, {} confirmations); resume with --start {} once they are",
+            start + available,
+            start + count - 1,
+            head,
+            tip,
+            args.confirmations,
+            start + available
+        );
+        if available == 0 {
+            return Ok(());
+        }
+    }
+    let count = available;
 
     log::info!("Starting indexing from block {} for {} blocks", start, count);
+    let (sample, sample_seed) = (args.sample, args.sample_seed);
+    let selected = move |block_number: &u64| sample.is_none_or(|sample| sample.includes(*block_number, sample_seed));
+    let total = match sample {
+        Some(sample) => {
+            let total = (start..start + count).filter(selected).count() as u64;
+            log::info!("Sampling {} of the range (seed {}): {} blocks", sample, sample_seed, total);
+            total
+        },
+        None => count,
+    };
+    let topic_filter = args.topic_filter.topics()?;
+    let mut hydration = args.hydration.clone();
+    if hydration.prescreen(&topic_filter) {
+        log::warn!(
+            "--topic0 hydrates only blocks whose logs bloom matches its events; the others get no transactions, \
+             data_usage or fee totals. Pass --hydrate full to fetch every block"
+        );
+    }
+    if hydration.level() == Hydration::Bloom && hydration.bloom_items.is_empty() {
+        return Err(anyhow::anyhow!("--hydrate bloom needs at least one --bloom-item address or topic"));
+    }
+    let hydration = Arc::new(hydration);
+    // Parsed before fetching so a bad call definition fails fast
+    let contract_calls = args.state_calls.as_deref().map(state_calls::load).transpose()?;
+    let filters = args.filters.as_deref().map(filters::Filters::load).transpose()?;
+    let spam_rules = spam::SpamRules::load(&args.spam)?;
+    let signing_key = certificate::signing_key_from_env()?;
+    if spam_rules.known_tokens() > 0 {
+        log::info!("Flagging transfers of {} known spam tokens", spam_rules.known_tokens());
+    }
+
+    // Connect to ClickHouse up front so schema drift is reported before any fetching
+    let sinks = FanOut::from_env(&store::raw_data_path(), &args.sinks, args.output)?;
+    for sink in sinks.sinks() {
+        log::info!("Delivering batches to {}", sink.describe());
+    }
+    // Connecting creates missing tables, so a dry run only inspects them at the end
+    if !args.dry_run {
+        sinks.connect().await;
+    }
+
+    // Admin socket lets `ctl` pause/resume, change the rate limit and watch addresses
+    let control = control::shared(env::var_os("ADMIN_SOCKET").map(Into::into));
+    control.start_run(total);
+    metrics::set_queued(total);
+    let progress = progress::Progress::new(total, !args.no_progress);
+    start_monitoring()?;
+    // Lets analytical clients read what has been indexed so far while the run continues
+    #[cfg(feature = "flight")]
+    if let Ok(addr) = env::var("FLIGHT_ADDR") {
+        let addr = addr.parse().map_err(|e| anyhow::anyhow!("Invalid FLIGHT_ADDR {}: {}", addr, e))?;
+        tokio::spawn(async move {
+            if let Err(e) = indexer::flight::serve(addr, store::raw_data_path()).await {
+                log::error!("Arrow Flight server stopped: {}", e);
+            }
+        });
+    }
+
+    // Lets local tools follow what is indexed without their own RPC subscriptions
+    #[cfg(feature = "relay")]
+    if let Ok(addr) = env::var("RELAY_ADDR") {
+        let addr = addr.parse().map_err(|e| anyhow::anyhow!("Invalid RELAY_ADDR {}: {}", addr, e))?;
+        let buffer = env_or("RELAY_BUFFER", 1024)?;
+        tokio::spawn(async move {
+            if let Err(e) = indexer::relay::serve(addr, buffer).await {
+                log::error!("Websocket relay stopped: {}", e);
+            }
+        });
+    }
+
+    if args.keep_raw {
+        indexer::raw::keep(&store::raw_data_path());
+    }
+    if args.mempool {
+        mempool::watch(std::time::Duration::from_millis(env_or("MEMPOOL_POLL_MS", 500)?));
+    }
 
     // Create vectors to store all data
     let mut all_blocks = Vec::new();
     let mut all_transactions = Vec::new();
+    let mut unhydrated = 0;
     let mut all_receipts = Vec::new();
+    let mut all_data_usage = Vec::new();
 
-    for block_number in start..start + count {
-        let block_start = Instant::now();
-        log::info!("Processing block {}", block_number);
-        
-        let (block_result, receipts_result) = tokio::join!(
-            get_block(block_number),
-            get_block_receipts(block_number)
-        );
+    // Fetch stage: up to `fetch_concurrency` blocks in flight, results kept in block order.
+    // On shutdown no new blocks are scheduled, but the ones in flight are finished and written.
+    shutdown::listen();
+    let mut fetches = stream::iter((start..start + count).filter(selected))
+        .take_while(|_| std::future::ready(!shutdown::is_requested()))
+        .map(|block_number| {
+            let control = Arc::clone(&control);
+            let hydration = Arc::clone(&hydration);
+            async move {
+                tokio::select! {
+                    _ = control.wait_while_paused() => {},
+                    _ = shutdown::wait() => return None,
+                }
+                control.start_block(block_number);
+                let span = tracing::info_span!("block", number = block_number);
+                Some(rpc::fetch_block_data_with(block_number, &hydration).instrument(span).await)
+            }
+        })
+        .buffered(runtime_config.fetch_concurrency);
 
-        match (block_result, receipts_result) {
-            (Ok((block, block_transactions)), Ok(receipts)) => {
-                log::info!("Block {} processed in {:?}", block_number, block_start.elapsed());
-                
-                // Store the results
+    while let Some(result) = fetches.next().await {
+        let Some(result) = result else { continue };
+        control.finish_block();
+        match &result {
+            Ok((block, block_transactions, _)) => {
+                progress.block_done(hex_to_u64(&block.number), block_transactions.len());
+                metrics::block_done(hex_to_u64(&block.number));
+            },
+            Err(_) => {
+                progress.block_done(0, 0);
+                metrics::block_done(0);
+            },
+        }
+        match result {
+            Ok((block, block_transactions, receipts)) => {
+                for tx in &block_transactions {
+                    let to = tx.to.as_deref().unwrap_or_default();
+                    if control.is_watched(&tx.from) || control.is_watched(to) {
+                        log::warn!("Watched address in tx {} (block {}): {} -> {}", tx.hash, hex_to_u64(&tx.block_number), tx.from, to);
+                    }
+                }
+                // Store the results; calldata usage needs the transactions, so skip unhydrated blocks
+                if rpc::is_hydrated(&block, &block_transactions) {
+                    all_data_usage.push(data_usage(&block, &block_transactions));
+                } else {
+                    unhydrated += 1;
+                }
                 all_transactions.extend(block_transactions);
                 all_blocks.push(block);
                 all_receipts.push(receipts);
             },
-            (Err(e), _) => {
-                log::error!("Error fetching block {}: {}", block_number, e);
-            },
-            (_, Err(e)) => {
-                log::error!("Error fetching receipts for block {}: {}", block_number, e);
+            Err(e) => {
+                log::error!("{}", e);
             }
         }
     }
+    progress.finish();
+    if hydration.level() == Hydration::Bloom {
+        log::info!("Logs bloom matched no item in {} of {} blocks, which were left unhydrated", unhydrated, all_blocks.len());
+    }
+    // Block fee totals cover every transaction, so they are summed before the filters drop any
+    let fee_totals = receipt_fee_totals(&all_blocks, &all_receipts);
+    if args.address_filter.is_active() {
+        let dropped = args.address_filter.retain(&mut all_transactions, &mut all_receipts);
+        log::info!("Address filter dropped {} transactions and their receipts", dropped);
+    }
+    if !topic_filter.is_empty() {
+        let (logs, transactions) = filters::retain_topics(&topic_filter, &mut all_transactions, &mut all_receipts);
+        log::info!("Topic filter dropped {} logs and {} transactions left without logs", logs, transactions);
+    }
 
     // Print summary with logging levels
     log::info!("=== Processing Summary ===");
@@ -322,6 +1527,14 @@ async fn main() -> Result<()> {
     log::info!("Blocks processed: {}", all_blocks.len());
     log::info!("Transactions processed: {}", all_transactions.len());
     log::info!("Total receipts processed: {}", all_receipts.iter().map(|r| r.len()).sum::<usize>());
+    let parse = indexer::json::stats();
+    log::info!(
+        "RPC responses parsed with {}: {:.1} MB in {:?} ({} fallbacks to serde_json)",
+        indexer::json::PARSER,
+        parse.bytes as f64 / 1e6,
+        parse.elapsed,
+        parse.fallbacks
+    );
     
     // Only print detailed data when debug level is enabled
     log::debug!("\n=== All Blocks ===");
@@ -335,90 +1548,49 @@ async fn main() -> Result<()> {
 
     // Transform the data
     log::info!("Converting hex values to appropriate types...");
-    
-    let transformed_blocks: Vec<TransformedBlock> = all_blocks.iter().map(|block| {
-        let ts = hex_to_u64(&block.timestamp);
-        let datetime = Utc.timestamp_opt(ts as i64, 0).single().unwrap_or_default();
-        TransformedBlock {
-            base_fee_per_gas: block.base_fee_per_gas.as_ref().map(|x| hex_to_u64(x)),
-            difficulty: hex_to_u64(&block.difficulty),
-            extra_data: block.extra_data.clone(),
-            gas_limit: hex_to_u64(&block.gas_limit),
-            gas_used: hex_to_u64(&block.gas_used),
-            hash: block.hash.clone(),
-            logs_bloom: block.logs_bloom.clone(),
-            miner: block.miner.clone(),
-            mix_hash: block.mix_hash.clone(),
-            nonce: block.nonce.clone(),
-            number: hex_to_u64(&block.number),
-            parent_hash: block.parent_hash.clone(),
-            receipts_root: block.receipts_root.clone(),
-            sha3_uncles: block.sha3_uncles.clone(),
-            size: hex_to_u64(&block.size),
-            state_root: block.state_root.clone(),
-            datetime,
-            total_difficulty: hex_to_u64(&block.total_difficulty),
-            transaction_hashes: block.transaction_hashes.clone(),
-            transactions_root: block.transactions_root.clone(),
-            uncles: block.uncles.clone(),
-        }
-    }).collect();
 
-    let transformed_transactions: Vec<TransformedTransaction> = all_transactions.iter().map(|tx| {
-        let block_number = hex_to_u64(&tx.block_number);
-        let block = transformed_blocks
+    // Transform stage: CPU bound, so it runs on the blocking pool in bounded chunks
+    let transform_span = tracing::info_span!("transform", blocks = all_blocks.len());
+    let all_blocks = Arc::new(all_blocks);
+    let chunk_size = all_blocks.len().div_ceil(runtime_config.transform_concurrency).max(1);
+    let block_chunks: Vec<Vec<TransformedBlock>> = stream::iter((0..all_blocks.len()).step_by(chunk_size))
+        .map(|offset| {
+            let blocks = Arc::clone(&all_blocks);
+            let span = transform_span.clone();
+            tokio::task::spawn_blocking(move || {
+                let _entered = span.enter();
+                let end = (offset + chunk_size).min(blocks.len());
+                blocks[offset..end].iter().map(transform_block).collect::<Vec<_>>()
+            })
+        })
+        .buffered(runtime_config.transform_concurrency)
+        .try_collect()
+        .await?;
+    let mut transformed_blocks: Vec<TransformedBlock> = block_chunks.into_iter().flatten().collect();
+
+    let datetime_for = |block_number: u64| {
+        transformed_blocks
             .iter()
             .find(|b| b.number == block_number)
-            .unwrap_or(&transformed_blocks[0]);
-        
-        TransformedTransaction {
-            block_hash: tx.block_hash.clone(),
-            block_number: hex_to_u64(&tx.block_number),
-            chain_id: hex_to_u64(&tx.chain_id),
-            from: tx.from.clone(),
-            gas: hex_to_u64(&tx.gas),
-            gas_price: hex_to_u64(&tx.gas_price),
-            hash: tx.hash.clone(),
-            input: tx.input.clone(),
-            nonce: hex_to_u64(&tx.nonce),
-            r: tx.r.clone(),
-            s: tx.s.clone(),
-            to: tx.to.clone(),
-            transaction_index: hex_to_u64(&tx.transaction_index),
-            tx_type: hex_to_u64(&tx.tx_type),
-            v: tx.v.clone(),
-            value: hex_to_u64(&tx.value),
-            datetime: block.datetime,
-        }
-    }).collect();
-
-    let transformed_receipts: Vec<Vec<TransformedReceipt>> = all_receipts.iter().map(|block_receipts| {
-        block_receipts.iter().map(|receipt| {
-            let block_number = hex_to_u64(&receipt.block_number);
-            let block = transformed_blocks
-                .iter()
-                .find(|b| b.number == block_number)
-                .unwrap_or(&transformed_blocks[0]);
-
-            TransformedReceipt {
-                block_hash: receipt.block_hash.clone(),
-                block_number: hex_to_u64(&receipt.block_number),
-                contract_address: receipt.contract_address.clone(),
-                cumulative_gas_used: hex_to_u64(&receipt.cumulative_gas_used),
-                effective_gas_price: hex_to_u64(&receipt.effective_gas_price),
-                from: receipt.from.clone(),
-                gas_used: hex_to_u64(&receipt.gas_used),
-                logs: receipt.logs.clone(),
-                logs_bloom: receipt.logs_bloom.clone(),
-                status: hex_to_bool(&receipt.status),
-                to: receipt.to.clone(),
-                transaction_hash: receipt.transaction_hash.clone(),
-                transaction_index: hex_to_u64(&receipt.transaction_index),
-                tx_type: hex_to_u64(&receipt.tx_type),
-                datetime: block.datetime,
-            }
-        }).collect()
-    }).collect();
+            .unwrap_or(&transformed_blocks[0])
+            .datetime
+    };
+
+    let (mut transformed_transactions, transformed_receipts) = transform_span.in_scope(|| {
+        let mut transactions: Vec<TransformedTransaction> = all_transactions.iter().map(|tx| {
+            transform_transaction(tx, datetime_for(hex_to_u64(&tx.block_number)))
+        }).collect();
+        sort_by_index(&mut transactions, &mut []);
+        let receipts: Vec<Vec<TransformedReceipt>> = all_receipts.iter().map(|block_receipts| {
+            let mut receipts: Vec<TransformedReceipt> = block_receipts.iter().map(|receipt| {
+                transform_receipt(receipt, datetime_for(hex_to_u64(&receipt.block_number)))
+            }).collect();
+            sort_by_index(&mut [], &mut receipts);
+            receipts
+        }).collect();
+        (transactions, receipts)
+    });
+    drop(transform_span);
 
     // Print comparison of original and transformed data
     log::info!("\n=== Data Transformation Results ===");
@@ -440,172 +1612,169 @@ async fn main() -> Result<()> {
     log::debug!("{:#?}", transformed_receipts);
 
     // Get RAW_DATA_PATH from environment
-    let raw_data_path = env::var("RAW_DATA_PATH")
-        .unwrap_or_else(|_| "./raw_data".to_string());
-
-    // Ensure directories exist
-    let blocks_dir = format!("{}/blocks", raw_data_path);
-    let transactions_dir = format!("{}/transactions", raw_data_path);
-    let receipts_dir = format!("{}/receipts", raw_data_path);
-
-    ensure_directory(&blocks_dir)?;
-    ensure_directory(&transactions_dir)?;
-    ensure_directory(&receipts_dir)?;
-
-    // Save transformed blocks
-    for block in &transformed_blocks {
-        let filename = format!("{}/block_{}.json", blocks_dir, block.number);
-        let json = serde_json::to_string_pretty(block)?;
-        fs::write(filename, json)?;
-    }
-
-    // Save transformed transactions
-    for tx in &transformed_transactions {
-        let filename = format!("{}/tx_{}.json", transactions_dir, tx.hash);
-        let json = serde_json::to_string_pretty(tx)?;
-        fs::write(filename, json)?;
-    }
-
-    // Save transformed receipts
-    for (_block_index, receipt_block) in transformed_receipts.iter().enumerate() {
-        for receipt in receipt_block {
-            let filename = format!("{}/receipt_{}.json", receipts_dir, receipt.transaction_hash);
-            let json = serde_json::to_string_pretty(receipt)?;
-            fs::write(filename, json)?;
+    let raw_data_path = store::raw_data_path();
+    let dirs = match args.dry_run {
+        true => store::OutputDirs::new(&raw_data_path),
+        false => store::OutputDirs::create(&raw_data_path)?,
+    };
+
+    let ordering = transformed_blocks
+        .iter()
+        .zip(&transformed_receipts)
+        .filter(|(block, receipts)| block.transaction_hashes.len() == receipts.len())
+        .map(|(block, receipts)| ordering_analysis(block, receipts, mempool::first_seen))
+        .collect();
+    let flat_receipts: Vec<TransformedReceipt> = transformed_receipts.into_iter().flatten().collect();
+    attach_receipts(&mut transformed_transactions, &flat_receipts);
+    set_transaction_fees(&transformed_blocks, &mut transformed_transactions);
+    set_fee_totals(&mut transformed_blocks, &fee_totals);
+    apply_native_units(&mut transformed_blocks, &mut transformed_transactions);
+    let mut token_transfers = extract_token_transfers(&flat_receipts);
+    if args.token_metadata {
+        token_metadata::enrich(&mut token_transfers, &raw_data_path, runtime_config.fetch_concurrency, !args.dry_run).await?;
+    }
+    spam::flag(&mut token_transfers, &spam_rules, args.token_metadata);
+    let block_times: Vec<_> = transformed_blocks.iter().map(|b| (b.number, b.datetime)).collect();
+    let balances = if args.balances.balance_addresses.is_empty() {
+        Vec::new()
+    } else {
+        balances::fetch(&args.balances, &block_times, runtime_config.fetch_concurrency).await?
+    };
+    let contract_state = match &contract_calls {
+        Some(calls) => state_calls::sample(calls, &block_times, runtime_config.fetch_concurrency).await,
+        None => Vec::new(),
+    };
+    let mut data_quality = quality::check_gas(&transformed_blocks, &flat_receipts);
+    data_quality.extend(quality::check_blocks(&transformed_blocks));
+    for (block, receipts) in transformed_blocks.iter().zip(&all_receipts) {
+        data_quality.extend(quality::check_receipt_status(block, receipts));
+    }
+    let mut batch = pipeline::TransformedBatch {
+        data_quality,
+        contracts: extract_contracts(&transformed_transactions, &flat_receipts),
+        logs: extract_logs(&flat_receipts),
+        nft_transfers: extract_nft_transfers(&flat_receipts),
+        token_transfers,
+        bridge_messages: extract_bridge_messages(&flat_receipts),
+        balances,
+        contract_state,
+        blocks: transformed_blocks,
+        transactions: transformed_transactions,
+        receipts: flat_receipts,
+        data_usage: all_data_usage,
+        ordering_analysis: ordering,
+        addresses: Vec::new(),
+    };
+    transformers::apply(&mut batch)?;
+    if let Some(filters) = &filters {
+        for (dataset, dropped) in filters.apply(&mut batch)? {
+            log::info!("Filtered out {} {}", dropped, dataset);
         }
     }
-
-    log::info!("Data saved to directories:");
-    log::info!("  Blocks: {}", blocks_dir);
-    log::info!("  Transactions: {}", transactions_dir);
-    log::info!("  Receipts: {}", receipts_dir);
-
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::TimeZone;
-
-    #[test]
-    fn test_hex_to_u64() {
-        assert_eq!(hex_to_u64("0x0"), 0);
-        assert_eq!(hex_to_u64("0x1"), 1);
-        assert_eq!(hex_to_u64("0xa"), 10);
-        assert_eq!(hex_to_u64("0xff"), 255);
-        assert_eq!(hex_to_u64("ff"), 255); // Test without 0x prefix
-        assert_eq!(hex_to_u64("invalid"), 0); // Test invalid input
-    }
-
-    #[test]
-    fn test_hex_to_bool() {
-        assert_eq!(hex_to_bool("0x0"), false);
-        assert_eq!(hex_to_bool("0x1"), true);
-        assert_eq!(hex_to_bool("0x2"), false); // Any non-1 value should be false
-        assert_eq!(hex_to_bool("invalid"), false); // Invalid input should return false
-    }
-
-    #[test]
-    fn test_block_transformation() {
-        let block = Block {
-            base_fee_per_gas: Some("0xa".to_string()),
-            difficulty: "0x5".to_string(),
-            extra_data: "0x".to_string(),
-            gas_limit: "0x1234".to_string(),
-            gas_used: "0x1000".to_string(),
-            hash: "0xabc".to_string(),
-            logs_bloom: "0x0".to_string(),
-            miner: "0xdef".to_string(),
-            mix_hash: "0x123".to_string(),
-            nonce: "0x1".to_string(),
-            number: "0x1".to_string(),
-            parent_hash: "0x456".to_string(),
-            receipts_root: "0x789".to_string(),
-            sha3_uncles: "0x111".to_string(),
-            size: "0x100".to_string(),
-            state_root: "0x222".to_string(),
-            timestamp: "0x60000000".to_string(), // Unix timestamp in hex
-            total_difficulty: "0x10".to_string(),
-            transaction_hashes: vec!["0xtx1".to_string()],
-            transactions_root: "0x333".to_string(),
-            uncles: vec![],
-        };
-
-        let transformed = TransformedBlock {
-            base_fee_per_gas: Some(10),
-            difficulty: 5,
-            extra_data: "0x".to_string(),
-            gas_limit: 0x1234,
-            gas_used: 0x1000,
-            hash: "0xabc".to_string(),
-            logs_bloom: "0x0".to_string(),
-            miner: "0xdef".to_string(),
-            mix_hash: "0x123".to_string(),
-            nonce: "0x1".to_string(),
-            number: 1,
-            parent_hash: "0x456".to_string(),
-            receipts_root: "0x789".to_string(),
-            sha3_uncles: "0x111".to_string(),
-            size: 0x100,
-            state_root: "0x222".to_string(),
-            datetime: Utc.timestamp_opt(0x60000000, 0).unwrap(),
-            total_difficulty: 16,
-            transaction_hashes: vec!["0xtx1".to_string()],
-            transactions_root: "0x333".to_string(),
-            uncles: vec![],
+    log::info!("Logs: {}", batch.logs.len());
+    log::info!("Contracts created: {}", batch.contracts.len());
+    log::info!("NFT transfers: {}", batch.nft_transfers.len());
+    log::info!(
+        "Token transfers: {} ({} flagged as spam)",
+        batch.token_transfers.len(),
+        batch.token_transfers.iter().filter(|t| t.spam).count()
+    );
+    log::info!("Bridge messages: {}", batch.bridge_messages.len());
+    log::info!("Balance snapshots: {}", batch.balances.len());
+    log::info!("Contract state rows: {}", batch.contract_state.len());
+    if !batch.data_quality.is_empty() {
+        log::warn!("Data quality violations: {} (see {})", batch.data_quality.len(), dirs.data_quality);
+        metrics::data_quality_violations(batch.data_quality.len() as u64);
+    }
+    // Rows are computed in a dry run too, but the dimension is only saved once the batch is written
+    let mut dimension = None;
+    if args.output.address_dimension {
+        let mut known = addresses::Dimension::load(&raw_data_path)?;
+        batch.addresses = known.update(&batch);
+        log::info!("Address dimension: {} new or changed of {} addresses", batch.addresses.len(), known.len());
+        dimension = Some(known);
+    }
+    if args.dry_run {
+        return commands::dry_run::report(&dirs, args.output, &sinks, &batch).await;
+    }
+    let mut decoders = Vec::new();
+    if args.token_metadata {
+        decoders.push((backfill::Decoder::TokenMetadata, backfill::fingerprint(b"")));
+    }
+    if let Some(path) = &args.state_calls {
+        decoders.push((backfill::Decoder::StateCalls, backfill::fingerprint(&std::fs::read(path)?)));
+    }
+    let pending = backfill::register(&raw_data_path, start, &decoders)?;
+    let plans = store::plan_writes(&dirs, args.output, &batch)?;
+    store::persist(&dirs, args.output, None, &batch, runtime_config.write_concurrency).await?;
+    // Decoders enabled on a store with history catch up on it while indexing goes on
+    if !args.no_backfill {
+        let backfill = backfill::Backfill {
+            raw_data_path: raw_data_path.clone(),
+            output: args.output,
+            state_calls: args.state_calls.clone(),
+            spam: args.spam.clone(),
+            concurrency: runtime_config.fetch_concurrency,
         };
+        backfill::spawn(backfill, pending);
+    }
+    if let Some(dimension) = &dimension {
+        dimension.save()?;
+    }
+    let chain_id = match rpc::get_chain_id().await {
+        Ok(chain_id) => Some(chain_id),
+        Err(e) => {
+            log::warn!("Could not fetch the chain id for the run manifest: {}", e);
+            batch.transactions.iter().map(|tx| tx.chain_id).find(|chain_id| *chain_id != 0)
+        },
+    };
+    let first = batch.blocks.first().map_or(start, |block| block.number);
+    let last = batch.blocks.last().map_or((start + count).saturating_sub(1), |block| block.number);
+    let run_manifest = manifest::build(&raw_data_path, args.output, &plans, chain_id, first, last)?;
+    log::info!("Wrote run manifest {}", manifest::write(&raw_data_path, &run_manifest)?.display());
+    let quality_report = quality::report(&batch, first, last);
+    log::info!("Wrote data quality report {}", quality::write_report(&raw_data_path, &quality_report)?.display());
+    for (sink, delivery) in sinks.deliver(&batch).await? {
+        match delivery {
+            Delivery::Delivered => metrics::set_sink_status(sink, None),
+            Delivery::Queued(error) => {
+                log::warn!("Queued the batch for {}: {}", sink, error);
+                metrics::set_sink_status(sink, Some(error));
+            },
+        }
+    }
+    #[cfg(feature = "relay")]
+    indexer::relay::publish(&batch)?;
 
-        let ts = hex_to_u64(&block.timestamp);
-        let datetime = Utc.timestamp_opt(ts as i64, 0).single().unwrap_or_default();
-        let result = TransformedBlock {
-            base_fee_per_gas: block.base_fee_per_gas.as_ref().map(|x| hex_to_u64(x)),
-            difficulty: hex_to_u64(&block.difficulty),
-            extra_data: block.extra_data.clone(),
-            gas_limit: hex_to_u64(&block.gas_limit),
-            gas_used: hex_to_u64(&block.gas_used),
-            hash: block.hash.clone(),
-            logs_bloom: block.logs_bloom.clone(),
-            miner: block.miner.clone(),
-            mix_hash: block.mix_hash.clone(),
-            nonce: block.nonce.clone(),
-            number: hex_to_u64(&block.number),
-            parent_hash: block.parent_hash.clone(),
-            receipts_root: block.receipts_root.clone(),
-            sha3_uncles: block.sha3_uncles.clone(),
-            size: hex_to_u64(&block.size),
-            state_root: block.state_root.clone(),
-            datetime,
-            total_difficulty: hex_to_u64(&block.total_difficulty),
-            transaction_hashes: block.transaction_hashes.clone(),
-            transactions_root: block.transactions_root.clone(),
-            uncles: block.uncles.clone(),
-        };
+    // Resume point: the block after the contiguous prefix of the range that was written
+    let written: std::collections::BTreeSet<u64> = batch.blocks.iter().map(|b| b.number).collect();
+    let next_block = (start..start + count).find(|n| selected(n) && !written.contains(n)).unwrap_or(start + count);
+    let checkpoint = store::write_checkpoint(&raw_data_path, next_block)?;
+    if shutdown::is_requested() {
+        log::warn!("Stopped early; resume with --start {} (checkpoint {})", next_block, checkpoint.display());
+    }
+    if let Some(key) = signing_key.filter(|_| next_block == start + count && count > 0) {
+        let path = certificate::certify(&raw_data_path, start, start + count - 1, &key)?;
+        log::info!("Wrote completion certificate {}", path.display());
+    }
 
-        assert_eq!(result.base_fee_per_gas, transformed.base_fee_per_gas);
-        assert_eq!(result.difficulty, transformed.difficulty);
-        assert_eq!(result.gas_limit, transformed.gas_limit);
-        assert_eq!(result.gas_used, transformed.gas_used);
-        assert_eq!(result.number, transformed.number);
-        assert_eq!(result.size, transformed.size);
-        assert_eq!(result.total_difficulty, transformed.total_difficulty);
-    }
-
-    #[test]
-    fn test_ensure_directory() {
-        let test_dir = "test_dir";
-        
-        // Clean up any existing test directory
-        if Path::new(test_dir).exists() {
-            fs::remove_dir_all(test_dir).unwrap();
-        }
-        
-        // Test directory creation
-        assert!(!Path::new(test_dir).exists());
-        ensure_directory(test_dir).unwrap();
-        assert!(Path::new(test_dir).exists());
-        
-        // Clean up
-        fs::remove_dir_all(test_dir).unwrap();
+    log::info!("Data saved to directories:");
+    log::info!("  Blocks: {}", dirs.blocks);
+    log::info!("  Transactions: {}", dirs.transactions);
+    log::info!("  Receipts: {}", dirs.receipts);
+    log::info!("  Logs: {}", dirs.logs);
+    log::info!("  Contracts: {}", dirs.contracts);
+    log::info!("  Data usage: {}", dirs.data_usage);
+    log::info!("  Ordering analysis: {}", dirs.ordering_analysis);
+    log::info!("  NFT transfers: {}", dirs.nft_transfers);
+    log::info!("  Token transfers: {}", dirs.token_transfers);
+    log::info!("  Bridge messages: {}", dirs.bridge_messages);
+    log::info!("  Balances: {}", dirs.balances);
+    log::info!("  Contract state: {}", dirs.contract_state);
+    log::info!("  Data quality: {}", dirs.data_quality);
+    if args.output.address_dimension {
+        log::info!("  Addresses: {}", dirs.addresses);
     }
+
+    Ok(())
 }
@@ -0,0 +1,186 @@
+//! Per-run manifests of what an `index` run wrote, so downstream loaders can pick up new data and
+//! check it without listing the dataset directories.
+//!
+//! Every run that writes files adds `raw_data/manifests/run_<created_at>_<first>_<last>.json`:
+//! the block range, chain id, schema version, output format and, per dataset, the rows written and
+//! each file with its size and SHA-256 (of the bytes on disk, so compressed files are checked as
+//! stored). Names sort in the order the runs finished, so a loader remembers the last manifest it
+//! loaded and reads the ones after it. Files skipped by `--write-policy skip-existing` are not
+//! listed; a later run that rewrites a file lists it again with its new checksum.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock;
+use crate::store::{self, OutputConfig, WritePlan, SCHEMA_VERSION};
+use crate::trie::encode_hex;
+
+pub const MANIFEST_DIR: &str = "manifests";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    // Relative to RAW_DATA_PATH
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestDataset {
+    pub rows: usize,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub created_at: DateTime<Utc>,
+    pub indexer_version: String,
+    pub schema_version: u32,
+    pub chain_id: Option<u64>,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub format: String,
+    pub compression: String,
+    pub datasets: BTreeMap<String, ManifestDataset>,
+}
+
+// How the option is spelled on the command line, e.g. `ndjson` or `zstd`
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+fn file_entry(raw_data_path: &str, path: &str) -> Result<ManifestFile> {
+    let data = fs::read(path).map_err(|e| anyhow::anyhow!("Could not read written file {}: {}", path, e))?;
+    let relative = Path::new(path).strip_prefix(raw_data_path).unwrap_or(Path::new(path));
+    Ok(ManifestFile {
+        path: relative.to_string_lossy().to_string(),
+        bytes: data.len() as u64,
+        sha256: encode_hex(&Sha256::digest(&data)),
+    })
+}
+
+// The manifest of a run that wrote `plans` (as planned before writing) for blocks `first..=last`
+pub fn build(raw_data_path: &str, output: OutputConfig, plans: &[WritePlan], chain_id: Option<u64>, first: u64, last: u64) -> Result<RunManifest> {
+    let mut datasets = BTreeMap::new();
+    for plan in plans.iter().filter(|plan| plan.records > 0) {
+        let files = plan.files.iter().map(|path| file_entry(raw_data_path, path)).collect::<Result<Vec<_>>>()?;
+        datasets.insert(plan.dataset.to_string(), ManifestDataset { rows: plan.records, files });
+    }
+    Ok(RunManifest {
+        created_at: clock::now(),
+        indexer_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: SCHEMA_VERSION,
+        chain_id,
+        first_block: first,
+        last_block: last,
+        format: value_name(output.format),
+        compression: value_name(output.compress),
+        datasets,
+    })
+}
+
+pub fn write(raw_data_path: &str, manifest: &RunManifest) -> Result<PathBuf> {
+    let dir = Path::new(raw_data_path).join(MANIFEST_DIR);
+    store::ensure_directory(&dir.to_string_lossy())?;
+    let name = format!(
+        "run_{}_{}_{}.json",
+        manifest.created_at.format("%Y%m%dT%H%M%S%.6fZ"),
+        manifest.first_block,
+        manifest.last_block
+    );
+    let path = dir.join(name);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(manifest)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+// Manifest files in the order they were written, only those after `after` (a file name) if given
+pub fn list(raw_data_path: &str, after: Option<&str>) -> Result<Vec<PathBuf>> {
+    let dir = Path::new(raw_data_path).join(MANIFEST_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with("run_") && name.ends_with(".json") && after.is_none_or(|after| name.as_str() > after) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn read(path: &Path) -> Result<RunManifest> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+// Files of the manifest that are missing or no longer match their checksum
+pub fn verify(raw_data_path: &str, manifest: &RunManifest) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    for dataset in manifest.datasets.values() {
+        for file in &dataset.files {
+            let path = Path::new(raw_data_path).join(&file.path);
+            match fs::read(&path) {
+                Ok(data) if encode_hex(&Sha256::digest(&data)) == file.sha256 => {},
+                Ok(_) => problems.push(format!("{}: checksum differs", file.path)),
+                Err(e) => problems.push(format!("{}: {}", file.path, e)),
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransformedBlock;
+    use crate::pipeline::TransformedBatch;
+    use crate::store::{OutputDirs, OutputFormat};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_manifest_lists_written_files() {
+        let root = "test_run_manifest";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let blocks: Vec<TransformedBlock> = (10..12)
+            .map(|number| {
+                let block = serde_json::from_value(json!({
+                    "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x1", "gasUsed": "0x0", "hash": "0xa",
+                    "logsBloom": "0x", "miner": "0x0", "mixHash": "0x0", "nonce": "0x0", "number": format!("0x{:x}", number),
+                    "parentHash": "0x0", "receiptsRoot": "0x0", "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0",
+                    "timestamp": "0x1", "totalDifficulty": "0x0", "transactions": [], "transactionsRoot": "0x0", "uncles": []
+                }))
+                .unwrap();
+                crate::transform::transform_block(&block)
+            })
+            .collect();
+        let batch = TransformedBatch { blocks, ..Default::default() };
+        let output = OutputConfig { format: OutputFormat::Ndjson, ..Default::default() };
+        let plans = store::plan_writes(&dirs, output, &batch).unwrap();
+        store::persist(&dirs, output, None, &batch, 2).await.unwrap();
+
+        let manifest = build(root, output, &plans, Some(59144), 10, 11).unwrap();
+        assert_eq!(manifest.datasets.keys().collect::<Vec<_>>(), vec!["blocks"]);
+        assert_eq!(manifest.datasets["blocks"].rows, 2);
+        assert_eq!(manifest.datasets["blocks"].files[0].path, "blocks/blocks_10_11.ndjson");
+        let path = write(root, &manifest).unwrap();
+        assert_eq!(list(root, None).unwrap(), vec![path.clone()]);
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(list(root, Some(&name)).unwrap().is_empty());
+        assert!(verify(root, &read(&path).unwrap()).unwrap().is_empty());
+
+        fs::write(format!("{}/blocks/blocks_10_11.ndjson", root), b"{}\n").unwrap();
+        assert_eq!(verify(root, &manifest).unwrap(), vec!["blocks/blocks_10_11.ndjson: checksum differs"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
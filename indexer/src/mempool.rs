@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::clock;
+use crate::rpc;
+
+// Hash -> when a pending transaction filter first reported it
+static FIRST_SEEN: LazyLock<Mutex<HashMap<String, DateTime<Utc>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+// Entries older than this are dropped; transactions pending longer are treated as never seen
+const RETENTION: chrono::Duration = chrono::Duration::hours(6);
+
+pub fn record(hashes: impl IntoIterator<Item = String>, seen_at: DateTime<Utc>) {
+    let mut first_seen = FIRST_SEEN.lock().unwrap();
+    for hash in hashes {
+        first_seen.entry(hash.to_lowercase()).or_insert(seen_at);
+    }
+    first_seen.retain(|_, seen| seen_at - *seen < RETENTION);
+}
+
+pub fn first_seen(hash: &str) -> Option<DateTime<Utc>> {
+    FIRST_SEEN.lock().unwrap().get(&hash.to_lowercase()).copied()
+}
+
+// Polls a pending transaction filter in the background, once per process. First-seen times are
+// only as precise as the poll interval and only cover what the RPC node's mempool saw.
+pub fn watch(poll_interval: Duration) {
+    if WATCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    log::info!("Recording mempool first-seen times every {:?}", poll_interval);
+    tokio::spawn(async move {
+        let mut filter: Option<String> = None;
+        loop {
+            let id = match &filter {
+                Some(id) => id.clone(),
+                None => match rpc::rpc_call("eth_newPendingTransactionFilter", json!([])).await {
+                    Ok(id) => {
+                        let id = id.as_str().unwrap_or_default().to_string();
+                        filter = Some(id.clone());
+                        id
+                    },
+                    Err(e) => {
+                        log::warn!("Could not create a pending transaction filter: {}", e);
+                        clock::sleep(poll_interval * 10).await;
+                        continue;
+                    },
+                },
+            };
+            match rpc::rpc_call("eth_getFilterChanges", json!([id])).await {
+                Ok(hashes) => {
+                    let hashes = hashes.as_array().into_iter().flatten().filter_map(|h| h.as_str().map(str::to_string));
+                    record(hashes, clock::now());
+                },
+                // Filters expire when not polled for a while; create a new one
+                Err(e) => {
+                    log::warn!("Pending transaction filter failed, recreating it: {}", e);
+                    filter = None;
+                },
+            }
+            clock::sleep(poll_interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_first_sighting() {
+        let start = Utc::now();
+        record(["0xAA01".to_string()], start);
+        record(["0xaa01".to_string(), "0xaa02".to_string()], start + chrono::Duration::seconds(2));
+        assert_eq!(first_seen("0xaa01"), Some(start));
+        assert_eq!(first_seen("0xAA02"), Some(start + chrono::Duration::seconds(2)));
+
+        record(["0xaa03".to_string()], start + RETENTION + chrono::Duration::seconds(1));
+        assert_eq!(first_seen("0xaa01"), None);
+        assert!(first_seen("0xaa03").is_some());
+    }
+}
@@ -0,0 +1,275 @@
+//! Autoscaling metrics: Prometheus text on `/metrics` (HPA through prometheus-adapter, KEDA's
+//! `prometheus` scaler) and a flat JSON object on `/scale` (KEDA's `metrics-api` scaler).
+//!
+//! The same server answers Kubernetes probes: `/healthz` whenever the process is serving, and
+//! `/readyz` with 200 only while the RPC answers, the replica is at most `READY_MAX_LAG` blocks
+//! behind the head and no sink is queueing batches (503 otherwise). Both return the checks as JSON.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{clock, json, rpc};
+
+static HEAD: AtomicU64 = AtomicU64::new(0);
+static HIGHEST_FETCHED: AtomicU64 = AtomicU64::new(0);
+static BLOCKS_FETCHED: AtomicU64 = AtomicU64::new(0);
+// Blocks of the running range not fetched yet
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+// Blocks of a shared work-stealing range that no replica has claimed
+static BACKLOG: AtomicU64 = AtomicU64::new(0);
+// Rows written to the `data_quality` dataset
+static DATA_QUALITY_VIOLATIONS: AtomicU64 = AtomicU64::new(0);
+static LAG_ALERT_FIRING: AtomicBool = AtomicBool::new(false);
+static SERVING: AtomicBool = AtomicBool::new(false);
+static POLLING: AtomicBool = AtomicBool::new(false);
+// Result of the latest chain head poll, None before the first one
+static RPC_STATUS: Mutex<Option<Result<(), String>>> = Mutex::new(None);
+// Error of each sink's latest delivery, None once a batch is delivered
+static SINK_ERRORS: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+static READY_MAX_LAG: AtomicU64 = AtomicU64::new(u64::MAX);
+
+pub fn set_queued(blocks: u64) {
+    QUEUED.store(blocks, Ordering::SeqCst);
+}
+
+pub fn set_backlog(blocks: u64) {
+    BACKLOG.store(blocks, Ordering::SeqCst);
+}
+
+pub fn set_head(block_number: u64) {
+    HEAD.store(block_number, Ordering::SeqCst);
+}
+
+// Block number 0 is passed for failed fetches, which still leave the queue
+pub fn block_done(block_number: u64) {
+    BLOCKS_FETCHED.fetch_add(1, Ordering::SeqCst);
+    HIGHEST_FETCHED.fetch_max(block_number, Ordering::SeqCst);
+    let _ = QUEUED.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| Some(queued.saturating_sub(1)));
+}
+
+// Counts blocks indexed before this process started as fetched, so a replica with nothing new
+// to fetch is not reported behind the head
+pub fn set_indexed(block_number: u64) {
+    HIGHEST_FETCHED.fetch_max(block_number, Ordering::SeqCst);
+}
+
+pub fn set_rpc_status(status: Result<(), String>) {
+    *RPC_STATUS.lock().unwrap_or_else(|e| e.into_inner()) = Some(status);
+}
+
+pub fn set_sink_status(sink: &str, error: Option<String>) {
+    SINK_ERRORS.lock().unwrap_or_else(|e| e.into_inner()).insert(sink.to_string(), error);
+}
+
+pub fn set_ready_max_lag(blocks: u64) {
+    READY_MAX_LAG.store(blocks, Ordering::SeqCst);
+}
+
+pub fn data_quality_violations(count: u64) {
+    DATA_QUALITY_VIOLATIONS.fetch_add(count, Ordering::SeqCst);
+}
+
+pub fn set_lag_alert_firing(firing: bool) {
+    LAG_ALERT_FIRING.store(firing, Ordering::SeqCst);
+}
+
+pub fn head_block() -> u64 {
+    HEAD.load(Ordering::SeqCst)
+}
+
+pub fn highest_fetched() -> u64 {
+    HIGHEST_FETCHED.load(Ordering::SeqCst)
+}
+
+pub fn blocks_behind_head() -> u64 {
+    HEAD.load(Ordering::SeqCst).saturating_sub(HIGHEST_FETCHED.load(Ordering::SeqCst))
+}
+
+pub fn queue_depth() -> u64 {
+    QUEUED.load(Ordering::SeqCst) + BACKLOG.load(Ordering::SeqCst)
+}
+
+pub fn render() -> String {
+    let parse = json::stats();
+    let metrics = [
+        ("blocks_behind_head", "gauge", "Chain head minus the highest block fetched", blocks_behind_head()),
+        ("queue_depth", "gauge", "Blocks waiting to be fetched, including unclaimed work-stealing chunks", queue_depth()),
+        ("head_block", "gauge", "Latest block number reported by the RPC", HEAD.load(Ordering::SeqCst)),
+        ("highest_fetched_block", "gauge", "Highest block number fetched by this replica", HIGHEST_FETCHED.load(Ordering::SeqCst)),
+        ("blocks_fetched_total", "counter", "Blocks fetched by this replica", BLOCKS_FETCHED.load(Ordering::SeqCst)),
+        ("json_parsed_bytes_total", "counter", "Bytes of RPC responses parsed", parse.bytes),
+        ("json_parse_microseconds_total", "counter", "Time spent parsing RPC responses", parse.elapsed.as_micros() as u64),
+        ("json_parse_fallbacks_total", "counter", "Responses simd-json rejected and serde_json parsed", parse.fallbacks),
+        ("data_quality_violations_total", "counter", "Blocks and receipts that failed a data quality check", DATA_QUALITY_VIOLATIONS.load(Ordering::SeqCst)),
+        ("lag_alert_firing", "gauge", "1 while blocks_behind_head is above LAG_ALERT_BLOCKS", LAG_ALERT_FIRING.load(Ordering::SeqCst) as u64),
+    ];
+    metrics
+        .iter()
+        .map(|(name, kind, help, value)| {
+            format!("# HELP sweet_potato_{name} {help}\n# TYPE sweet_potato_{name} {kind}\nsweet_potato_{name} {value}\n")
+        })
+        .collect()
+}
+
+pub fn scale() -> Value {
+    json!({
+        "blocks_behind_head": blocks_behind_head(),
+        "queue_depth": queue_depth(),
+    })
+}
+
+// Whether the replica should receive probes' traffic, and the checks behind it
+pub fn readiness() -> (bool, Value) {
+    let rpc = RPC_STATUS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let rpc_ok = matches!(rpc, Some(Ok(())));
+    let lag = blocks_behind_head();
+    let max_lag = READY_MAX_LAG.load(Ordering::SeqCst);
+    let sinks = SINK_ERRORS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let sinks_ok = sinks.values().all(Option::is_none);
+    let ready = rpc_ok && lag <= max_lag && sinks_ok;
+    let checks = json!({
+        "ready": ready,
+        "rpc": {
+            "reachable": rpc_ok,
+            "error": match rpc {
+                Some(Err(e)) => Some(e),
+                Some(Ok(())) => None,
+                None => Some("chain head not polled yet".to_string()),
+            },
+        },
+        "lag": { "blocks_behind_head": lag, "max": max_lag, "ok": lag <= max_lag },
+        "sinks": sinks
+            .into_iter()
+            .map(|(sink, error)| (sink, json!({ "ok": error.is_none(), "error": error })))
+            .collect::<serde_json::Map<_, _>>(),
+    });
+    (ready, checks)
+}
+
+async fn handle_connection(mut stream: TcpStream) -> Result<()> {
+    let mut request = vec![0; 4096];
+    let read = stream.read(&mut request).await?;
+    let request = String::from_utf8_lossy(&request[..read]);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, content_type, body) = match path {
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4", render()),
+        "/scale" => ("200 OK", "application/json", scale().to_string()),
+        "/healthz" => ("200 OK", "application/json", json!({ "status": "ok" }).to_string()),
+        "/readyz" => match readiness() {
+            (true, checks) => ("200 OK", "application/json", checks.to_string()),
+            (false, checks) => ("503 Service Unavailable", "application/json", checks.to_string()),
+        },
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+// Reads the chain head in the background, once per process
+pub fn poll_head(interval: Duration) {
+    if POLLING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            match rpc::get_latest_block_number().await {
+                Ok(head) => {
+                    set_head(head);
+                    set_rpc_status(Ok(()));
+                },
+                Err(e) => {
+                    log::warn!("Could not read the chain head for metrics: {}", e);
+                    set_rpc_status(Err(e.to_string()));
+                },
+            }
+            clock::sleep(interval).await;
+        }
+    });
+}
+
+// Serves the metrics and polls the chain head in the background, once per process
+pub fn serve(addr: SocketAddr, head_poll: Duration) {
+    if SERVING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    poll_head(head_poll);
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("Could not serve metrics on {}: {}", addr, e);
+                return;
+            },
+        };
+        log::info!("Serving autoscaling metrics on http://{}/metrics", addr);
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::warn!("Metrics connection failed: {}", e);
+                    continue;
+                },
+            };
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream).await {
+                    log::warn!("Metrics connection error: {}", e);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauges() {
+        set_head(120);
+        set_queued(3);
+        set_backlog(1000);
+        block_done(100);
+        block_done(0);
+        assert_eq!(blocks_behind_head(), 20);
+        assert_eq!(queue_depth(), 1001);
+        assert_eq!(scale(), json!({ "blocks_behind_head": 20, "queue_depth": 1001 }));
+
+        let text = render();
+        assert!(text.contains("# TYPE sweet_potato_queue_depth gauge\nsweet_potato_queue_depth 1001\n"));
+        assert!(text.contains("sweet_potato_blocks_fetched_total 2\n"));
+    }
+
+    #[test]
+    fn test_readiness() {
+        // Above any lag test_gauges leaves behind
+        set_ready_max_lag(1000);
+        set_rpc_status(Err("connection refused".into()));
+        let (ready, checks) = readiness();
+        assert!(!ready);
+        assert_eq!(checks["rpc"], json!({ "reachable": false, "error": "connection refused" }));
+
+        set_rpc_status(Ok(()));
+        set_sink_status("duckdb", Some("database is locked".into()));
+        let (ready, checks) = readiness();
+        assert!(!ready);
+        assert_eq!(checks["sinks"]["duckdb"], json!({ "ok": false, "error": "database is locked" }));
+
+        set_sink_status("duckdb", None);
+        assert!(readiness().0);
+    }
+}
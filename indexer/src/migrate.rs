@@ -0,0 +1,475 @@
+//! Dataset schema versions and the migrations between them.
+//!
+//! Outputs carry the schema version they were written with: `raw_data/schema.json` for the files,
+//! a `schema_version` table in ClickHouse, DuckDB and PostgreSQL, and `schema_version` in run manifests. Data
+//! written before versioning counts as version 1. A store or database holding an older version is
+//! refused by `index` until `migrate` has upgraded it, so a dataset never mixes old and new rows.
+//!
+//! A migration upgrades one dataset to the next version: a rewrite of each record for the files,
+//! and statements run against the dataset's table in each database.
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::models::{EventLog, TransformedReceipt};
+use crate::store::{self, Record, SCHEMA_VERSION};
+use crate::transform::{self, extract_logs};
+
+// Given the store's path, a file and its records before the upgrade, writes what the upgrade takes
+// out of them to another dataset
+pub type Carry = fn(&str, &Path, &[Value]) -> Result<()>;
+
+pub struct Migration {
+    // Version the migration upgrades to
+    pub version: u32,
+    pub dataset: &'static str,
+    pub description: &'static str,
+    // Rewrites a record in place, returning whether it changed
+    pub upgrade: fn(&mut Map<String, Value>) -> bool,
+    // Runs on each file before its records are upgraded
+    pub carry: Option<Carry>,
+    // Statements upgrading the dataset's table, see `statement`
+    pub clickhouse: &'static [&'static str],
+    pub duckdb: &'static [&'static str],
+    pub postgres: &'static [&'static str],
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        dataset: "transactions",
+        description: "`value` holds the full 256-bit wei amount as a decimal string instead of a u64",
+        upgrade: value_to_decimal_string,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} MODIFY COLUMN `value` UInt256"],
+        duckdb: &["ALTER TABLE {table} ALTER value TYPE VARCHAR"],
+        // The PostgreSQL sink came after version 2
+        postgres: &[],
+    },
+    // Database rows keep their logs: the logs table is only created by `connect`, after the
+    // migration, and receives the logs of blocks indexed from then on
+    Migration {
+        version: 3,
+        dataset: "receipts",
+        description: "`logs` is written empty, the receipt's logs are rows of the `logs` dataset",
+        upgrade: empty_logs,
+        carry: Some(carry_logs),
+        clickhouse: &[],
+        duckdb: &[],
+        postgres: &[],
+    },
+    Migration {
+        version: 4,
+        dataset: "transactions",
+        description: "`status`, `gas_used`, `effective_gas_price` and `contract_address` copied from the receipt",
+        upgrade: receipt_columns,
+        carry: None,
+        clickhouse: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `status` Nullable(Bool), \
+             ADD COLUMN IF NOT EXISTS `gas_used` Nullable(UInt64), \
+             ADD COLUMN IF NOT EXISTS `effective_gas_price` Nullable(UInt64), \
+             ADD COLUMN IF NOT EXISTS `contract_address` Nullable(String)",
+        ],
+        duckdb: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS status BOOLEAN",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS gas_used BIGINT",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS effective_gas_price BIGINT",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS contract_address VARCHAR",
+        ],
+        postgres: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS status boolean, ADD COLUMN IF NOT EXISTS gas_used bigint, \
+             ADD COLUMN IF NOT EXISTS effective_gas_price numeric(20, 0), ADD COLUMN IF NOT EXISTS contract_address text",
+        ],
+    },
+    Migration {
+        version: 5,
+        dataset: "transactions",
+        description: "`fee`, `burnt_fee` and `priority_fee` in wei",
+        upgrade: fee_columns,
+        carry: None,
+        clickhouse: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `fee` Nullable(UInt256), \
+             ADD COLUMN IF NOT EXISTS `burnt_fee` Nullable(UInt256), ADD COLUMN IF NOT EXISTS `priority_fee` Nullable(UInt256)",
+        ],
+        duckdb: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS fee VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS burnt_fee VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS priority_fee VARCHAR",
+        ],
+        postgres: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS fee numeric(78, 0), ADD COLUMN IF NOT EXISTS burnt_fee numeric(78, 0), \
+             ADD COLUMN IF NOT EXISTS priority_fee numeric(78, 0)",
+        ],
+    },
+    Migration {
+        version: 5,
+        dataset: "blocks",
+        description: "`total_fees` and `burnt_fees` in wei",
+        upgrade: fee_total_columns,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `total_fees` Nullable(UInt256), ADD COLUMN IF NOT EXISTS `burnt_fees` Nullable(UInt256)"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS total_fees VARCHAR", "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS burnt_fees VARCHAR"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS total_fees numeric(78, 0), ADD COLUMN IF NOT EXISTS burnt_fees numeric(78, 0)"],
+    },
+    Migration {
+        version: 6,
+        dataset: "transactions",
+        description: "ether and gwei decimal strings of the wei amounts, filled with --native-units",
+        upgrade: native_unit_columns,
+        carry: None,
+        clickhouse: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `value_ether` Nullable(String), \
+             ADD COLUMN IF NOT EXISTS `gas_price_gwei` Nullable(String), ADD COLUMN IF NOT EXISTS `effective_gas_price_gwei` Nullable(String), \
+             ADD COLUMN IF NOT EXISTS `fee_ether` Nullable(String), ADD COLUMN IF NOT EXISTS `burnt_fee_ether` Nullable(String), \
+             ADD COLUMN IF NOT EXISTS `priority_fee_ether` Nullable(String)",
+        ],
+        duckdb: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS value_ether VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS gas_price_gwei VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS effective_gas_price_gwei VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS fee_ether VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS burnt_fee_ether VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS priority_fee_ether VARCHAR",
+        ],
+        postgres: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS value_ether text, ADD COLUMN IF NOT EXISTS gas_price_gwei text, \
+             ADD COLUMN IF NOT EXISTS effective_gas_price_gwei text, ADD COLUMN IF NOT EXISTS fee_ether text, \
+             ADD COLUMN IF NOT EXISTS burnt_fee_ether text, ADD COLUMN IF NOT EXISTS priority_fee_ether text",
+        ],
+    },
+    Migration {
+        version: 6,
+        dataset: "blocks",
+        description: "ether and gwei decimal strings of the base fee and fee totals, filled with --native-units",
+        upgrade: native_unit_total_columns,
+        carry: None,
+        clickhouse: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `base_fee_per_gas_gwei` Nullable(String), \
+             ADD COLUMN IF NOT EXISTS `total_fees_ether` Nullable(String), ADD COLUMN IF NOT EXISTS `burnt_fees_ether` Nullable(String)",
+        ],
+        duckdb: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS base_fee_per_gas_gwei VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS total_fees_ether VARCHAR",
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS burnt_fees_ether VARCHAR",
+        ],
+        postgres: &[
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS base_fee_per_gas_gwei text, ADD COLUMN IF NOT EXISTS total_fees_ether text, \
+             ADD COLUMN IF NOT EXISTS burnt_fees_ether text",
+        ],
+    },
+    // Stores written before the surrogate keys; `logs` always had them
+    Migration {
+        version: 7,
+        dataset: "transactions",
+        description: "`id` surrogate key from the block number and transaction index",
+        upgrade: transaction_row_id,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `id` UInt64 DEFAULT block_number * {row_id_stride} + transaction_index"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id UBIGINT", "UPDATE {table} SET id = block_number * {row_id_stride} + transaction_index"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id bigint", "UPDATE {table} SET id = block_number * {row_id_stride} + transaction_index"],
+    },
+    Migration {
+        version: 7,
+        dataset: "receipts",
+        description: "`id` surrogate key from the block number and transaction index",
+        upgrade: transaction_row_id,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `id` UInt64 DEFAULT block_number * {row_id_stride} + transaction_index"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id UBIGINT", "UPDATE {table} SET id = block_number * {row_id_stride} + transaction_index"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id bigint", "UPDATE {table} SET id = block_number * {row_id_stride} + transaction_index"],
+    },
+    Migration {
+        version: 7,
+        dataset: "nft_transfers",
+        description: "`id` surrogate key from the block number and log index",
+        upgrade: log_row_id,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `id` UInt64 DEFAULT block_number * {row_id_stride} + log_index"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id UBIGINT", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id bigint", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+    },
+    Migration {
+        version: 7,
+        dataset: "token_transfers",
+        description: "`id` surrogate key from the block number and log index",
+        upgrade: log_row_id,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `id` UInt64 DEFAULT block_number * {row_id_stride} + log_index"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id UBIGINT", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id bigint", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+    },
+    Migration {
+        version: 7,
+        dataset: "bridge_messages",
+        description: "`id` surrogate key from the block number and log index",
+        upgrade: log_row_id,
+        carry: None,
+        clickhouse: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS `id` UInt64 DEFAULT block_number * {row_id_stride} + log_index"],
+        duckdb: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id UBIGINT", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+        postgres: &["ALTER TABLE {table} ADD COLUMN IF NOT EXISTS id bigint", "UPDATE {table} SET id = block_number * {row_id_stride} + log_index"],
+    },
+];
+
+// Version 1 wrote values above u64::MAX as 0; those can only be recovered by re-indexing
+fn value_to_decimal_string(record: &mut Map<String, Value>) -> bool {
+    match record.get("value") {
+        Some(Value::Number(number)) => {
+            let decimal = number.to_string();
+            record.insert("value".to_string(), Value::String(decimal));
+            true
+        },
+        _ => false,
+    }
+}
+
+// Adds columns a version introduced as the nulls the transform writes when it has no value for
+// them. Older records cannot be filled in without the receipts; re-index blocks to get them.
+fn add_null_columns(record: &mut Map<String, Value>, columns: &[&str]) -> bool {
+    let mut changed = false;
+    for column in columns {
+        if !record.contains_key(*column) {
+            record.insert(column.to_string(), Value::Null);
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn receipt_columns(record: &mut Map<String, Value>) -> bool {
+    add_null_columns(record, &["status", "gas_used", "effective_gas_price", "contract_address"])
+}
+
+fn fee_columns(record: &mut Map<String, Value>) -> bool {
+    add_null_columns(record, &["fee", "burnt_fee", "priority_fee"])
+}
+
+fn fee_total_columns(record: &mut Map<String, Value>) -> bool {
+    add_null_columns(record, &["total_fees", "burnt_fees"])
+}
+
+fn native_unit_columns(record: &mut Map<String, Value>) -> bool {
+    add_null_columns(record, &["value_ether", "gas_price_gwei", "effective_gas_price_gwei", "fee_ether", "burnt_fee_ether", "priority_fee_ether"])
+}
+
+fn native_unit_total_columns(record: &mut Map<String, Value>) -> bool {
+    add_null_columns(record, &["base_fee_per_gas_gwei", "total_fees_ether", "burnt_fees_ether"])
+}
+
+// Computes the surrogate key the transform assigns from the block number and the row's index
+fn add_row_id(record: &mut Map<String, Value>, index: &str) -> bool {
+    if record.contains_key("id") {
+        return false;
+    }
+    let field = |name: &str| record.get(name).and_then(Value::as_u64).unwrap_or(0);
+    let id = transform::row_id(field("block_number"), field(index));
+    record.insert("id".to_string(), Value::from(id));
+    true
+}
+
+fn transaction_row_id(record: &mut Map<String, Value>) -> bool {
+    add_row_id(record, "transaction_index")
+}
+
+fn log_row_id(record: &mut Map<String, Value>) -> bool {
+    add_row_id(record, "log_index")
+}
+
+fn empty_logs(record: &mut Map<String, Value>) -> bool {
+    match record.get_mut("logs") {
+        Some(Value::Array(logs)) if !logs.is_empty() => {
+            logs.clear();
+            true
+        },
+        _ => false,
+    }
+}
+
+// Writes the logs of a receipts file to the logs dataset, in the same partition, layout and
+// compression: a chunk's to the logs chunk of its blocks, a receipt file's to one file per log
+fn carry_logs(raw_data_path: &str, path: &Path, records: &[Value]) -> Result<()> {
+    let receipts = records.iter().map(|record| serde_json::from_value(record.clone())).collect::<serde_json::Result<Vec<TransformedReceipt>>>()?;
+    let logs = extract_logs(&receipts);
+    if logs.is_empty() {
+        return Ok(());
+    }
+    let relative = path.strip_prefix(Path::new(raw_data_path).join(TransformedReceipt::DATASET))?;
+    let dir = Path::new(raw_data_path).join(EventLog::DATASET).join(relative.parent().unwrap_or(Path::new("")));
+    let name = relative.file_name().unwrap_or_default().to_string_lossy();
+    fs::create_dir_all(&dir)?;
+    match name.strip_prefix("receipts_") {
+        Some(chunk) => {
+            let records = logs.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+            store::write_records_like(&dir.join(format!("logs_{}", chunk)), path, EventLog::DATASET, &records)
+        },
+        None => {
+            let extension = &name[name.find('.').unwrap_or(name.len())..];
+            for log in &logs {
+                let file = dir.join(format!("{}{}", log.file_name(), extension));
+                store::write_records_like(&file, path, EventLog::DATASET, &[serde_json::to_value(log)?])?;
+            }
+            Ok(())
+        },
+    }
+}
+
+// A migration statement for `table`: `{table}` is replaced with its name and
+// `{row_id_stride}` with `transform::ROW_ID_STRIDE`
+pub fn statement(statement: &str, table: &str) -> String {
+    statement.replace("{table}", table).replace("{row_id_stride}", &transform::ROW_ID_STRIDE.to_string())
+}
+
+// Migrations upgrading data of version `from` to the current one, in order
+pub fn pending(from: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |migration| migration.version > from && migration.version <= SCHEMA_VERSION)
+}
+
+#[derive(Debug, Default)]
+pub struct FilesReport {
+    pub from: u32,
+    pub files: usize,
+    pub records: usize,
+}
+
+// Upgrades the files under `raw_data_path` in place and stamps the store with the current version.
+// A file goes through every pending migration of its dataset at once and is replaced whole; the
+// upgrades leave upgraded records alone, so an interrupted migration can simply be run again.
+pub fn migrate_files(raw_data_path: &str, dry_run: bool) -> Result<FilesReport> {
+    let from = store::schema_version(raw_data_path)?;
+    let mut report = FilesReport { from, ..Default::default() };
+    if from > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!("{} holds schema version {}, newer than this indexer's {}", raw_data_path, from, SCHEMA_VERSION));
+    }
+    let migrations: Vec<&Migration> = pending(from).collect();
+    let datasets: BTreeSet<&str> = migrations.iter().map(|migration| migration.dataset).collect();
+    for dataset in datasets {
+        let migrations: Vec<&Migration> = migrations.iter().copied().filter(|migration| migration.dataset == dataset).collect();
+        let carries: Vec<Carry> = migrations.iter().filter_map(|migration| migration.carry).collect();
+        for path in store::dataset_files(raw_data_path, dataset)? {
+            let mut records = store::read_records(&path)?;
+            let original = (!carries.is_empty()).then(|| records.clone());
+            let mut changed = 0;
+            for record in records.iter_mut().filter_map(Value::as_object_mut) {
+                let upgraded = migrations.iter().fold(false, |upgraded, migration| (migration.upgrade)(record) | upgraded);
+                changed += upgraded as usize;
+            }
+            if changed == 0 {
+                continue;
+            }
+            if !dry_run {
+                // Carried first, so an interrupted migration still finds what to carry when run again
+                for carry in &carries {
+                    carry(raw_data_path, &path, original.as_deref().unwrap_or_default())?;
+                }
+                store::rewrite_records(&path, dataset, &records)?;
+            }
+            report.files += 1;
+            report.records += changed;
+        }
+    }
+    if !dry_run && !migrations.is_empty() {
+        store::write_schema_version(raw_data_path, SCHEMA_VERSION)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::TransformedTransaction;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_migrate_files() {
+        let root = "test_migrate_files";
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(format!("{}/transactions", root)).unwrap();
+        // `value` as version 1 wrote it
+        let mut tx = serde_json::to_value(fixtures::transaction(7, 3)).unwrap();
+        tx["value"] = json!(1_000_000_000_000_000_000u64);
+        tx.as_object_mut().unwrap().remove("id");
+        let added = ["status", "gas_used", "effective_gas_price", "contract_address", "fee", "burnt_fee", "priority_fee", "value_ether", "fee_ether"];
+        for column in added {
+            tx.as_object_mut().unwrap().remove(column);
+        }
+        let path = format!("{}/transactions/transactions_7_7.ndjson", root);
+        fs::write(&path, format!("{}\n", tx)).unwrap();
+        assert_eq!(store::schema_version(root).unwrap(), 1);
+        assert!(store::OutputDirs::create(root).err().unwrap().to_string().contains("run `migrate`"));
+
+        let report = migrate_files(root, false).unwrap();
+        assert_eq!((report.from, report.files, report.records), (1, 1, 1));
+        assert_eq!(store::schema_version(root).unwrap(), SCHEMA_VERSION);
+        let records = store::read_records(std::path::Path::new(&path)).unwrap();
+        assert_eq!(records[0]["value"], "1000000000000000000");
+        assert!(added.iter().all(|column| records[0].as_object().unwrap().contains_key(*column) && records[0][column].is_null()));
+        let migrated: TransformedTransaction = serde_json::from_value(records[0].clone()).unwrap();
+        assert_eq!(migrated.value_wei(), primitive_types::U256::exp10(18));
+        assert_eq!(migrated.id, fixtures::transaction(7, 3).id);
+
+        // Already current: nothing to do
+        assert_eq!(migrate_files(root, false).unwrap().files, 0);
+        store::OutputDirs::create(root).unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_row_id_statements() {
+        // The key formula of every database follows ROW_ID_STRIDE instead of spelling it out
+        let stride = transform::ROW_ID_STRIDE.to_string();
+        let keyed: Vec<&Migration> = MIGRATIONS.iter().filter(|migration| migration.description.contains("`id`")).collect();
+        assert_eq!(keyed.len(), 5);
+        for migration in keyed {
+            let index = if migration.description.contains("transaction index") { "transaction_index" } else { "log_index" };
+            for sql in migration.clickhouse.iter().chain(migration.duckdb).chain(migration.postgres) {
+                assert!(!sql.contains(&stride), "{}", sql);
+            }
+            let formula = format!("block_number * {} + {}", stride, index);
+            let expanded = statement(migration.clickhouse[0], migration.dataset);
+            assert!(expanded.starts_with(&format!("ALTER TABLE {} ", migration.dataset)) && expanded.ends_with(&format!("DEFAULT {}", formula)));
+            assert_eq!(statement(migration.duckdb[1], "t"), format!("UPDATE t SET id = {}", formula));
+            assert_eq!(statement(migration.postgres[1], "t"), format!("UPDATE t SET id = {}", formula));
+        }
+    }
+
+    #[test]
+    fn test_migrate_receipt_logs() {
+        let root = "test_migrate_receipt_logs";
+        let _ = fs::remove_dir_all(root);
+        let record = |hash: &str| {
+            json!({
+                "block_hash": "0xb", "block_number": 7, "contract_address": null, "cumulative_gas_used": 0, "effective_gas_price": 0,
+                "from": "0xf", "gas_used": 0, "logs_bloom": "0x", "status": true, "to": null, "transaction_hash": hash,
+                "transaction_index": 0, "tx_type": 2, "datetime": "2024-01-01T00:00:00Z",
+                "logs": [{ "address": "0xa", "blockHash": "0xb", "blockNumber": "0x7", "data": "0x", "logIndex": "0x3", "removed": false,
+                           "topics": [], "transactionHash": hash, "transactionIndex": "0x0" }],
+            })
+        };
+        // A chunk in a partition and a one-file-per-record receipt, as version 2 wrote them
+        fs::create_dir_all(format!("{}/receipts/dt=2024-01-01", root)).unwrap();
+        let chunk = format!("{}/receipts/dt=2024-01-01/receipts_7_7.ndjson", root);
+        fs::write(&chunk, format!("{}\n", record("0x1"))).unwrap();
+        let single = format!("{}/receipts/receipt_0x2.json", root);
+        fs::write(&single, record("0x2").to_string()).unwrap();
+        store::write_schema_version(root, 2).unwrap();
+
+        let report = migrate_files(root, false).unwrap();
+        assert_eq!((report.from, report.files, report.records), (2, 2, 2));
+        for path in [&chunk, &single] {
+            assert_eq!(store::read_records(Path::new(path)).unwrap()[0]["logs"], json!([]));
+        }
+        let logs = store::read_records(Path::new(&format!("{}/logs/dt=2024-01-01/logs_7_7.ndjson", root))).unwrap();
+        assert_eq!((&logs[0]["id"], &logs[0]["transaction_hash"]), (&json!(7_000_003), &json!("0x1")));
+        assert!(Path::new(&format!("{}/logs/log_0x2_3.json", root)).exists());
+
+        // Readers still see the receipts with their logs
+        let receipts: Vec<TransformedReceipt> = store::read_range(root, "receipts", 7, 7).unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts.iter().all(|receipt| json!(receipt.logs) == record(&receipt.transaction_hash)["logs"]));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,503 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use primitive_types::U256;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transaction {
+    #[serde(rename = "blockHash")]
+    pub block_hash: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "chainId", default)]
+    pub chain_id: String,
+    pub from: String,
+    pub gas: String,
+    #[serde(rename = "gasPrice", default)]
+    pub gas_price: String,
+    pub hash: String,
+    pub input: String,
+    pub nonce: String,
+    #[serde(default)]
+    pub r: String,
+    #[serde(default)]
+    pub s: String,
+    pub to: Option<String>,
+    #[serde(rename = "transactionIndex")]
+    pub transaction_index: String,
+    #[serde(rename = "type")]
+    pub tx_type: String,
+    #[serde(default)]
+    pub v: String,
+    pub value: String,
+    // EIP-4844 blob transactions only
+    #[serde(rename = "blobVersionedHashes", default, skip_serializing_if = "Option::is_none")]
+    pub blob_versioned_hashes: Option<Vec<String>>,
+    // Fields the RPC returned that this struct does not map, kept instead of dropped
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Block {
+    #[serde(rename = "baseFeePerGas")]
+    pub base_fee_per_gas: Option<String>,
+    // Present from Cancun onwards
+    #[serde(rename = "blobGasUsed", default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<String>,
+    #[serde(rename = "excessBlobGas", default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<String>,
+    #[serde(default)]
+    pub difficulty: String,
+    #[serde(rename = "extraData")]
+    pub extra_data: String,
+    #[serde(rename = "gasLimit")]
+    pub gas_limit: String,
+    #[serde(rename = "gasUsed")]
+    pub gas_used: String,
+    pub hash: String,
+    #[serde(rename = "logsBloom")]
+    pub logs_bloom: String,
+    pub miner: String,
+    #[serde(rename = "mixHash", default)]
+    pub mix_hash: String,
+    #[serde(default)]
+    pub nonce: String,
+    pub number: String,
+    #[serde(rename = "parentHash")]
+    pub parent_hash: String,
+    #[serde(rename = "receiptsRoot")]
+    pub receipts_root: String,
+    #[serde(rename = "sha3Uncles")]
+    pub sha3_uncles: String,
+    pub size: String,
+    #[serde(rename = "stateRoot")]
+    pub state_root: String,
+    pub timestamp: String,
+    #[serde(rename = "totalDifficulty", default)]
+    pub total_difficulty: String,
+    #[serde(rename = "transactions")]
+    pub transaction_hashes: Vec<String>,
+    #[serde(rename = "transactionsRoot")]
+    pub transactions_root: String,
+    pub uncles: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(rename = "blockHash")]
+    pub block_hash: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "contractAddress")]
+    pub contract_address: Option<String>,
+    #[serde(rename = "cumulativeGasUsed")]
+    pub cumulative_gas_used: String,
+    #[serde(rename = "effectiveGasPrice", default)]
+    pub effective_gas_price: String,
+    pub from: String,
+    #[serde(rename = "gasUsed")]
+    pub gas_used: String,
+    pub logs: Vec<Value>,
+    #[serde(rename = "logsBloom")]
+    pub logs_bloom: String,
+    pub status: String,
+    pub to: Option<String>,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+    #[serde(rename = "transactionIndex")]
+    pub transaction_index: String,
+    #[serde(rename = "type")]
+    pub tx_type: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// A contract deployed by a top-level creation transaction (`to` is null)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformedContract {
+    pub address: String,
+    pub creator: String,
+    pub transaction_hash: String,
+    pub block_number: u64,
+    // keccak256 of the creation transaction's input
+    pub init_code_hash: String,
+    pub datetime: DateTime<Utc>,
+}
+
+// Calldata and blob usage of one block, for data availability cost modelling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockDataUsage {
+    pub block_number: u64,
+    pub datetime: DateTime<Utc>,
+    pub size: u64,
+    pub gas_used: u64,
+    pub transaction_count: u64,
+    pub calldata_bytes: u64,
+    pub calldata_zero_bytes: u64,
+    pub calldata_nonzero_bytes: u64,
+    // Intrinsic calldata gas: 4 per zero byte, 16 per non-zero byte (EIP-2028)
+    pub calldata_gas: u64,
+    pub blob_transactions: u64,
+    pub blob_count: u64,
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
+}
+
+// How closely a block's transaction order follows priority fees (and, with mempool mode, arrival
+// order). Adherence is 1 - inversions / pairs: 1.0 means perfectly ordered, null for fewer than 2 txs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderingAnalysis {
+    pub block_number: u64,
+    pub datetime: DateTime<Utc>,
+    pub transaction_count: u64,
+    pub base_fee_per_gas: Option<u64>,
+    pub min_priority_fee: Option<u64>,
+    pub max_priority_fee: Option<u64>,
+    // Pairs where a later transaction pays a strictly higher priority fee
+    pub priority_fee_inversions: u64,
+    pub priority_fee_adherence: Option<f64>,
+    // Transactions with a mempool first-seen time, and pairs included in the block out of arrival order
+    pub first_seen_count: u64,
+    pub first_seen_inversions: u64,
+    pub first_seen_adherence: Option<f64>,
+}
+
+// One receipt log as a row of the `logs` dataset, linked to its transaction by `transaction_hash`
+// and ordered within the block by `log_index`. Topics are split into columns so events and their
+// indexed parameters can be filtered without unnesting the receipt's `logs` array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventLog {
+    // Surrogate key derived from the block number and log index, see transform::row_id
+    pub id: u64,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
+    pub address: String,
+    pub topic0: Option<String>,
+    pub topic1: Option<String>,
+    pub topic2: Option<String>,
+    pub topic3: Option<String>,
+    pub data: String,
+    pub event_signature: Option<String>,
+    pub removed: bool,
+    pub datetime: DateTime<Utc>,
+    // Unmapped fields of the RPC log, so receipts can be given back their logs as fetched
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+// One Linea message service event. `MessageSent` is a message leaving Linea for Ethereum, with its
+// fee, value and calldata; `MessageClaimed` is a message from Ethereum delivered on Linea and only
+// names its hash. Both ends of a message share `message_hash`. Amounts are wei as decimal strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BridgeMessage {
+    // Surrogate key of the log, see transform::row_id
+    #[serde(default)]
+    pub id: u64,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub log_index: u64,
+    pub contract_address: String,
+    // `MessageSent` or `MessageClaimed`
+    pub event: String,
+    // `l2_to_l1` for sent messages, `l1_to_l2` for claimed ones
+    pub direction: String,
+    pub message_hash: String,
+    // Set for sent messages only
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub fee: Option<String>,
+    pub value: Option<String>,
+    pub nonce: Option<String>,
+    pub calldata: Option<String>,
+    pub datetime: DateTime<Utc>,
+}
+
+// One token moved by an ERC-721 Transfer or ERC-1155 TransferSingle/TransferBatch event. Token ids
+// and amounts are uint256, kept as decimal strings; ERC-721 transfers always have amount 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NftTransfer {
+    // Surrogate key of the log the transfer was decoded from, shared by the rows of one TransferBatch
+    #[serde(default)]
+    pub id: u64,
+    pub token_address: String,
+    pub token_id: String,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+    // "erc721" or "erc1155"
+    pub standard: String,
+    // ERC-1155 only: the account that moved the tokens
+    pub operator: Option<String>,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    pub log_index: u64,
+    // Position within a TransferBatch, 0 otherwise
+    pub batch_index: u64,
+    pub datetime: DateTime<Utc>,
+}
+
+// One ERC-20 Transfer event. The value is a uint256 kept as a decimal string; the token fields and
+// `value_scaled` (value / 10^decimals) are only filled when token metadata lookups are enabled.
+// `spam` is set when one of the heuristics in `spam_reasons` flagged the transfer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    // Surrogate key of the log, see transform::row_id
+    #[serde(default)]
+    pub id: u64,
+    pub token_address: String,
+    pub from: String,
+    pub to: String,
+    pub value: String,
+    pub token_symbol: Option<String>,
+    pub token_name: Option<String>,
+    pub token_decimals: Option<u8>,
+    pub value_scaled: Option<String>,
+    #[serde(default)]
+    pub spam: bool,
+    #[serde(default)]
+    pub spam_reasons: Vec<String>,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    pub log_index: u64,
+    pub datetime: DateTime<Utc>,
+}
+
+// Native balance of a configured address at the end of a block, in wei as a decimal string
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub address: String,
+    pub block_number: u64,
+    pub balance: String,
+    pub datetime: DateTime<Utc>,
+}
+
+// Decoded result of a configured `eth_call` at one block. Values are rendered as strings (integers
+// in decimal, addresses and bytes in hex); `error` is set instead when the call failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContractState {
+    pub name: String,
+    pub contract: String,
+    pub function: String,
+    pub block_number: u64,
+    pub values: Vec<String>,
+    pub error: Option<String>,
+    pub datetime: DateTime<Utc>,
+}
+
+// Row of the `addresses` dimension, written when an address is first seen or one of its
+// attributes changes. `updated_block` (and `datetime`, that block's time) versions the row: the
+// row with the highest `updated_block` per address is the current one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressDimension {
+    pub address: String,
+    pub is_contract: bool,
+    pub is_token: bool,
+    pub label: Option<String>,
+    pub first_seen_block: u64,
+    pub first_seen_at: DateTime<Utc>,
+    pub updated_block: u64,
+    pub datetime: DateTime<Utc>,
+}
+
+// A block or receipt that failed one of the `quality` checks. `expected` and `actual` are the
+// values as text, numbers in decimal; `transaction_hash` is set for checks of a single receipt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataQualityIssue {
+    pub block_number: u64,
+    pub check: String,
+    pub transaction_hash: Option<String>,
+    pub expected: String,
+    pub actual: String,
+    pub datetime: DateTime<Utc>,
+}
+
+// Usage of one function selector on one UTC day. Value is the wei sent along with the calls, as a
+// decimal string; `first_block`/`last_block` show which part of the day was indexed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectorStats {
+    pub day: NaiveDate,
+    pub selector: String,
+    pub signature: Option<String>,
+    pub calls: u64,
+    pub failed_calls: u64,
+    pub unique_callers: u64,
+    pub unique_contracts: u64,
+    pub gas_used: u64,
+    pub value: String,
+    pub first_block: u64,
+    pub last_block: u64,
+}
+
+// How an address took part in a transaction
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityRole {
+    From,
+    To,
+    LogEmitter,
+}
+
+impl ActivityRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityRole::From => "from",
+            ActivityRole::To => "to",
+            ActivityRole::LogEmitter => "log_emitter",
+        }
+    }
+}
+
+// One row of the address activity index: `address` appeared in `transaction_hash` as `role`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct AddressActivity {
+    pub address: String,
+    pub block_number: u64,
+    pub transaction_hash: String,
+    pub role: ActivityRole,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "api", derive(async_graphql::SimpleObject), graphql(name = "Receipt", complex))]
+pub struct TransformedReceipt {
+    // Surrogate key derived from the block number and transaction index, see transform::row_id
+    #[serde(default)]
+    pub id: u64,
+    pub block_hash: String,
+    pub block_number: u64,
+    pub contract_address: Option<String>,
+    pub cumulative_gas_used: u64,
+    pub effective_gas_price: u64,
+    pub from: String,
+    pub gas_used: u64,
+    #[cfg_attr(feature = "api", graphql(skip))]
+    pub logs: Vec<Value>,
+    pub logs_bloom: String,
+    pub status: bool,
+    pub to: Option<String>,
+    pub transaction_hash: String,
+    pub transaction_index: u64,
+    pub tx_type: u64,
+    pub datetime: DateTime<Utc>,
+    // Unmapped RPC fields carried through from the raw object
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    #[cfg_attr(feature = "api", graphql(skip))]
+    pub extra: Map<String, Value>,
+}
+
+// A decimal integer field written as a string, accepting the JSON numbers older files hold
+pub fn decimal_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Decimal {
+        Number(u64),
+        Text(String),
+    }
+    Ok(match Decimal::deserialize(deserializer)? {
+        Decimal::Number(number) => number.to_string(),
+        Decimal::Text(text) => text,
+    })
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "api", derive(async_graphql::SimpleObject), graphql(name = "Transaction", complex))]
+pub struct TransformedTransaction {
+    // Surrogate key derived from the block number and transaction index, see transform::row_id
+    #[serde(default)]
+    pub id: u64,
+    pub block_hash: String,
+    pub block_number: u64,
+    pub chain_id: u64,
+    pub from: String,
+    pub gas: u64,
+    pub gas_price: u64,
+    pub hash: String,
+    pub input: String,
+    // 4-byte selector of `input` and its signature, when known
+    pub method_id: Option<String>,
+    pub method_signature: Option<String>,
+    pub nonce: u64,
+    pub r: String,
+    pub s: String,
+    pub to: Option<String>,
+    pub transaction_index: u64,
+    pub tx_type: u64,
+    pub v: String,
+    // Wei as a decimal string; schema version 1 wrote a u64, which is still read
+    #[serde(deserialize_with = "decimal_string")]
+    pub value: String,
+    // Outcome copied from the receipt, null when the receipt was not fetched or in older files
+    pub status: Option<bool>,
+    pub gas_used: Option<u64>,
+    pub effective_gas_price: Option<u64>,
+    pub contract_address: Option<String>,
+    // Wei as decimal strings, null without a receipt: the whole fee (gas_used * effective_gas_price),
+    // the part burnt by the EIP-1559 base fee and the priority fee left to the block producer
+    pub fee: Option<String>,
+    pub burnt_fee: Option<String>,
+    pub priority_fee: Option<String>,
+    // Exact decimal conversions of the wei amounts above, filled only with --native-units
+    pub value_ether: Option<String>,
+    pub gas_price_gwei: Option<String>,
+    pub effective_gas_price_gwei: Option<String>,
+    pub fee_ether: Option<String>,
+    pub burnt_fee_ether: Option<String>,
+    pub priority_fee_ether: Option<String>,
+    pub datetime: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    #[cfg_attr(feature = "api", graphql(skip))]
+    pub extra: Map<String, Value>,
+}
+
+impl TransformedTransaction {
+    pub fn value_wei(&self) -> U256 {
+        U256::from_dec_str(&self.value).unwrap_or_default()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "api", derive(async_graphql::SimpleObject), graphql(name = "Block", complex))]
+pub struct TransformedBlock {
+    pub base_fee_per_gas: Option<u64>,
+    pub difficulty: u64,
+    pub extra_data: String,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub hash: String,
+    pub logs_bloom: String,
+    pub miner: String,
+    pub mix_hash: String,
+    pub nonce: String,
+    pub number: u64,
+    pub parent_hash: String,
+    pub receipts_root: String,
+    pub sha3_uncles: String,
+    pub size: u64,
+    pub state_root: String,
+    pub datetime: DateTime<Utc>,
+    pub total_difficulty: u64,
+    pub transaction_hashes: Vec<String>,
+    pub transactions_root: String,
+    pub uncles: Vec<String>,
+    // Sums of `fee` and `burnt_fee` over every transaction of the block, filtered out ones included,
+    // null unless every transaction has a receipt
+    pub total_fees: Option<String>,
+    pub burnt_fees: Option<String>,
+    // Exact decimal conversions, filled only with --native-units
+    pub base_fee_per_gas_gwei: Option<String>,
+    pub total_fees_ether: Option<String>,
+    pub burnt_fees_ether: Option<String>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    #[cfg_attr(feature = "api", graphql(skip))]
+    pub extra: Map<String, Value>,
+}
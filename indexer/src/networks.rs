@@ -0,0 +1,128 @@
+//! Built-in registry of networks, picked with `--network` (`NETWORK`).
+//!
+//! A network brings its chain id, a public RPC endpoint used when `--rpc-url` (`RPC_URL`) is not
+//! given, its native currency and its target block time. Once a network is selected, the first RPC
+//! request checks `eth_chainId` against the registry, so an endpoint serving another chain fails the
+//! run before anything is written instead of mixing chains in one output directory.
+
+use anyhow::Result;
+use std::sync::OnceLock;
+use tokio::sync::OnceCell;
+
+use crate::rpc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Network {
+    pub name: &'static str,
+    pub chain_id: u64,
+    pub rpc_url: &'static str,
+    pub currency: &'static str,
+    pub block_time_ms: u64,
+}
+
+const fn network(name: &'static str, chain_id: u64, rpc_url: &'static str, currency: &'static str, block_time_ms: u64) -> Network {
+    Network { name, chain_id, rpc_url, currency, block_time_ms }
+}
+
+pub const NETWORKS: &[Network] = &[
+    network("linea-mainnet", 59144, "https://rpc.linea.build", "ETH", 2_000),
+    network("linea-sepolia", 59141, "https://rpc.sepolia.linea.build", "ETH", 2_000),
+    network("ethereum-mainnet", 1, "https://ethereum-rpc.publicnode.com", "ETH", 12_000),
+    network("ethereum-sepolia", 11155111, "https://ethereum-sepolia-rpc.publicnode.com", "ETH", 12_000),
+    network("base-mainnet", 8453, "https://mainnet.base.org", "ETH", 2_000),
+    network("optimism-mainnet", 10, "https://mainnet.optimism.io", "ETH", 2_000),
+    network("arbitrum-one", 42161, "https://arb1.arbitrum.io/rpc", "ETH", 250),
+    network("polygon-mainnet", 137, "https://polygon-rpc.com", "POL", 2_000),
+    network("gnosis", 100, "https://rpc.gnosischain.com", "xDAI", 5_000),
+];
+
+// A registered network by name, for clap's `--network`
+pub fn parse(name: &str) -> Result<&'static Network> {
+    let name = name.trim();
+    NETWORKS.iter().find(|network| network.name.eq_ignore_ascii_case(name)).ok_or_else(|| {
+        let known: Vec<&str> = NETWORKS.iter().map(|network| network.name).collect();
+        anyhow::anyhow!("Unknown network {}; available: {}", name, known.join(", "))
+    })
+}
+
+pub fn by_chain_id(chain_id: u64) -> Option<&'static Network> {
+    NETWORKS.iter().find(|network| network.chain_id == chain_id)
+}
+
+static SELECTED: OnceLock<&'static Network> = OnceLock::new();
+// None once the endpoint's chain id matched, the mismatch otherwise
+static CHAIN_CHECK: OnceCell<Option<String>> = OnceCell::const_new();
+
+// Selects the network for this process. Called after `rpc::configure_client`, so an explicit
+// --rpc-url beats the network's default endpoint.
+pub fn select(network: &'static Network) {
+    let _ = SELECTED.set(network);
+    rpc::set_url(network.rpc_url);
+}
+
+pub fn selected() -> Option<&'static Network> {
+    SELECTED.get().copied()
+}
+
+// Symbol of the native currency amounts are shown in
+pub fn currency() -> &'static str {
+    selected().map_or("ETH", |network| network.currency)
+}
+
+fn mismatch(network: &Network, chain_id: u64) -> Option<String> {
+    (chain_id != network.chain_id).then(|| {
+        let actual = by_chain_id(chain_id).map_or_else(|| format!("chain id {}", chain_id), |other| format!("{} ({})", other.name, chain_id));
+        format!("{} serves {}, not {} ({}); check --network and --rpc-url", rpc::url(), actual, network.name, network.chain_id)
+    })
+}
+
+// Checks once that the endpoint serves the selected network. A mismatch fails every later request
+// too; a failed eth_chainId call is asked again by the next request.
+pub async fn verify() -> Result<()> {
+    let Some(network) = selected() else { return Ok(()) };
+    let check = CHAIN_CHECK
+        .get_or_try_init(|| async {
+            // Boxed, since the call goes through the request path that calls this
+            let chain_id = Box::pin(rpc::get_chain_id()).await?;
+            let error = mismatch(network, chain_id);
+            if error.is_none() {
+                log::info!("Network {} (chain id {}) at {}", network.name, chain_id, rpc::url());
+            }
+            Ok::<_, anyhow::Error>(error)
+        })
+        .await?;
+    match check {
+        Some(error) => Err(anyhow::anyhow!("{}", error)),
+        None => Ok(()),
+    }
+}
+
+pub fn render() -> String {
+    let mut lines = vec![format!("{:<18} {:>9} {:<8} {:>10}  {}", "network", "chain id", "currency", "block time", "default rpc")];
+    for network in NETWORKS {
+        let block_time = format!("{}s", network.block_time_ms as f64 / 1000.0);
+        lines.push(format!("{:<18} {:>9} {:<8} {:>10}  {}", network.name, network.chain_id, network.currency, block_time, network.rpc_url));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup_and_mismatch() {
+        assert_eq!(parse("Linea-Mainnet").unwrap().chain_id, 59144);
+        assert_eq!(parse("linea").err().unwrap().to_string().split(';').next(), Some("Unknown network linea"));
+        assert_eq!(by_chain_id(59141).map(|network| network.name), Some("linea-sepolia"));
+        for (i, network) in NETWORKS.iter().enumerate() {
+            assert!(NETWORKS[i + 1..].iter().all(|other| other.name != network.name && other.chain_id != network.chain_id));
+        }
+
+        let linea = parse("linea-mainnet").unwrap();
+        assert_eq!(mismatch(linea, 59144), None);
+        assert!(mismatch(linea, 59141).unwrap().contains("serves linea-sepolia (59141), not linea-mainnet (59144)"));
+        assert!(mismatch(linea, 424242).unwrap().contains("serves chain id 424242"));
+        assert!(render().contains("arbitrum-one           42161 ETH           0.25s  https://arb1.arbitrum.io/rpc"));
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::models::{AddressDimension, Balance, Block, BlockDataUsage, ContractState, BridgeMessage, DataQualityIssue, EventLog, NftTransfer, OrderingAnalysis, Receipt, TokenTransfer, Transaction, TransformedBlock, TransformedContract, TransformedReceipt, TransformedTransaction};
+use crate::quality;
+use crate::rpc::{fetch_block_data, is_hydrated};
+use crate::spam::{self, SpamRules};
+use crate::store::{self, OutputConfig, OutputDirs};
+use crate::transform::{data_usage, extract_bridge_messages, extract_contracts, extract_logs, extract_nft_transfers, extract_token_transfers, ordering_analysis, transform_block_data};
+use crate::transformers;
+
+// Transformed datasets for a block range, in block order
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TransformedBatch {
+    pub blocks: Vec<TransformedBlock>,
+    pub transactions: Vec<TransformedTransaction>,
+    pub receipts: Vec<TransformedReceipt>,
+    #[serde(default)]
+    pub logs: Vec<EventLog>,
+    pub contracts: Vec<TransformedContract>,
+    pub data_usage: Vec<BlockDataUsage>,
+    pub ordering_analysis: Vec<OrderingAnalysis>,
+    pub nft_transfers: Vec<NftTransfer>,
+    pub token_transfers: Vec<TokenTransfer>,
+    #[serde(default)]
+    pub bridge_messages: Vec<BridgeMessage>,
+    pub balances: Vec<Balance>,
+    pub contract_state: Vec<ContractState>,
+    #[serde(default)]
+    pub addresses: Vec<AddressDimension>,
+    #[serde(default)]
+    pub data_quality: Vec<DataQualityIssue>,
+}
+
+impl TransformedBatch {
+    // Transforms one fetched block and appends it with everything derived from it
+    pub fn push_block(&mut self, block: &Block, transactions: &[Transaction], raw_receipts: &[Receipt]) {
+        if is_hydrated(block, transactions) {
+            self.data_usage.push(data_usage(block, transactions));
+        }
+        let (block, transactions, receipts) = transform_block_data(block, transactions, raw_receipts);
+        self.contracts.extend(extract_contracts(&transactions, &receipts));
+        self.ordering_analysis.push(ordering_analysis(&block, &receipts, |_| None));
+        self.logs.extend(extract_logs(&receipts));
+        self.nft_transfers.extend(extract_nft_transfers(&receipts));
+        let mut token_transfers = extract_token_transfers(&receipts);
+        spam::flag(&mut token_transfers, &SpamRules::default(), false);
+        self.token_transfers.extend(token_transfers);
+        self.bridge_messages.extend(extract_bridge_messages(&receipts));
+        self.data_quality.extend(quality::check_block(&block, self.blocks.last()));
+        self.data_quality.extend(quality::check_receipt_status(&block, raw_receipts));
+        self.data_quality.extend(quality::check_gas(std::slice::from_ref(&block), &receipts));
+        self.blocks.push(block);
+        self.transactions.extend(transactions);
+        self.receipts.extend(receipts);
+    }
+}
+
+// Fetches and transforms a range for commands that need all of it (export, reports), running the
+// registered transformers; unlike `index`, any block that fails to fetch fails the whole range
+pub async fn fetch_range(start: u64, count: u64, concurrency: usize) -> Result<TransformedBatch> {
+    let fetched: Vec<_> = stream::iter(start..start + count)
+        .map(|number| fetch_block_data(number).instrument(tracing::info_span!("block", number)))
+        .buffered(concurrency)
+        .try_collect()
+        .await?;
+
+    let mut batch = TransformedBatch::default();
+    for (block, transactions, receipts) in &fetched {
+        batch.push_block(block, transactions, receipts);
+    }
+    transformers::apply(&mut batch)?;
+    Ok(batch)
+}
+
+// Fetches a range and writes it to files under `raw_data_path` (no ClickHouse); used by the
+// embedding interfaces, which want one call per range rather than the full `index` command
+pub async fn index_range(start: u64, count: u64, raw_data_path: &str, output: OutputConfig, concurrency: usize) -> Result<usize> {
+    let batch = fetch_range(start, count, concurrency).await?;
+    let dirs = OutputDirs::create(raw_data_path)?;
+    store::persist(&dirs, output, None, &batch, concurrency).await?;
+    Ok(batch.blocks.len())
+}
@@ -0,0 +1,180 @@
+//! Built-in bundles of `index` settings for common jobs, picked with `--preset` (`PRESET`).
+//!
+//! A preset is a list of settings named by the environment variable of their arg. They become the
+//! defaults of those args, so an explicit flag beats the environment, which beats the preset:
+//! `TOKEN_METADATA=false` with `--preset erc20` keeps everything else the preset brings. A setting
+//! whose arg conflicts with an explicit one is dropped, so `--preset headers --hydrate bloom`
+//! hydrates by bloom instead of failing.
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command, CommandFactory, FromArgMatches, ValueEnum};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    // ERC-20 (and ERC-721) Transfer logs with token metadata, their transactions and the
+    // address dimension; blocks without a Transfer are screened out by their logs bloom
+    Erc20,
+    // Every block fully hydrated, with the address activity index, the address dimension and
+    // token metadata
+    Full,
+    // Block headers only
+    Headers,
+}
+
+impl Preset {
+    pub fn settings(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::Erc20 => &[
+                ("TOPIC0", "Transfer(address,address,uint256)"),
+                ("TOKEN_METADATA", "true"),
+                ("ADDRESS_DIMENSION", "true"),
+            ],
+            Preset::Full => &[
+                ("HYDRATE", "full"),
+                ("ADDRESS_INDEX", "true"),
+                ("ADDRESS_DIMENSION", "true"),
+                ("TOKEN_METADATA", "true"),
+            ],
+            Preset::Headers => &[("HEADERS_ONLY", "true")],
+        }
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+    }
+}
+
+pub struct Parsed<C> {
+    pub cli: C,
+    pub preset: Option<Preset>,
+    // The preset's settings that became defaults
+    pub applied: Vec<(&'static str, &'static str)>,
+}
+
+// Whether an arg's value came from the command line or the environment rather than its default
+fn explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
+
+// The preset of the innermost (sub)command that has one, with the subcommand names leading to it
+fn find(matches: &ArgMatches) -> Option<(Vec<String>, Preset)> {
+    if let Some((name, sub)) = matches.subcommand() {
+        if let Some((mut path, preset)) = find(sub) {
+            path.insert(0, name.to_string());
+            return Some((path, preset));
+        }
+    }
+    matches.try_get_one::<Preset>("preset").ok().flatten().map(|preset| (Vec::new(), *preset))
+}
+
+// Args clap refuses next to `arg`, declared on either side
+fn conflicting<'a>(command: &'a Command, arg: &'a Arg) -> impl Iterator<Item = &'a Arg> {
+    let declared = command.get_arg_conflicts_with(arg);
+    let reverse = command.get_arguments().filter(move |other| command.get_arg_conflicts_with(other).iter().any(|a| a.get_id() == arg.get_id()));
+    declared.into_iter().chain(reverse)
+}
+
+// The preset's settings that can become defaults of `command`: their arg exists and neither it nor
+// an arg conflicting with it was given explicitly
+fn settings_for(command: &Command, matches: &ArgMatches, preset: Preset) -> Vec<(String, &'static str, &'static str)> {
+    let mut defaults = Vec::new();
+    for &(name, value) in preset.settings() {
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_env().is_some_and(|env| env == name)) else { continue };
+        if explicit(matches, arg.get_id().as_str()) || conflicting(command, arg).any(|other| explicit(matches, other.get_id().as_str())) {
+            continue;
+        }
+        defaults.push((arg.get_id().to_string(), name, value));
+    }
+    defaults
+}
+
+fn with_defaults(command: Command, path: &[String], defaults: &[(String, &'static str, &'static str)]) -> Command {
+    match path.split_first() {
+        Some((name, rest)) => command.mut_subcommand(name, |sub| with_defaults(sub, rest, defaults)),
+        None => defaults.iter().fold(command, |command, (id, _, value)| command.mut_arg(id, |arg| arg.default_value(*value))),
+    }
+}
+
+// Parses the command line, then again with the preset it names (`--preset` or PRESET) as defaults
+pub fn parse<C: CommandFactory + FromArgMatches>(args: &[String]) -> Result<Parsed<C>, clap::Error> {
+    let matches = C::command().try_get_matches_from(args)?;
+    let (matches, preset, applied) = match find(&matches) {
+        Some((path, preset)) => {
+            let command = C::command();
+            let level = path.iter().fold(&command, |command, name| command.find_subcommand(name).expect("matched subcommand"));
+            let level_matches = path.iter().fold(&matches, |matches, name| matches.subcommand_matches(name).expect("matched subcommand"));
+            let defaults = settings_for(level, level_matches, preset);
+            let applied = defaults.iter().map(|(_, name, value)| (*name, *value)).collect();
+            (with_defaults(C::command(), &path, &defaults).try_get_matches_from(args)?, Some(preset), applied)
+        },
+        None => (matches, None, Vec::new()),
+    };
+    let cli = C::from_arg_matches(&matches).map_err(|e| e.format(&mut C::command()))?;
+    Ok(Parsed { cli, preset, applied })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::TopicFilter;
+    use crate::rpc::{Hydration, HydrationConfig};
+
+    #[derive(clap::Args, Debug)]
+    struct TestArgs {
+        #[arg(long, env = "PRESET", value_enum)]
+        preset: Option<Preset>,
+        #[command(flatten)]
+        hydration: HydrationConfig,
+        #[arg(long, env = "TOKEN_METADATA")]
+        token_metadata: bool,
+        #[command(flatten)]
+        topic_filter: TopicFilter,
+    }
+
+    #[derive(clap::Parser, Debug)]
+    #[command(args_conflicts_with_subcommands = true)]
+    struct TestCli {
+        #[command(subcommand)]
+        command: Option<TestCommand>,
+        #[command(flatten)]
+        index: TestArgs,
+    }
+
+    #[derive(clap::Subcommand, Debug)]
+    enum TestCommand {
+        Index(TestArgs),
+    }
+
+    #[test]
+    fn test_preset_defaults() {
+        let parse = |line: &str| parse::<TestCli>(&line.split_whitespace().map(str::to_string).collect::<Vec<_>>());
+
+        let parsed = parse("sweet-potato --preset full").unwrap();
+        assert_eq!(parsed.preset, Some(Preset::Full));
+        assert_eq!(parsed.applied, vec![("HYDRATE", "full"), ("TOKEN_METADATA", "true")]);
+        assert!(parsed.cli.index.token_metadata);
+
+        let parsed = parse("sweet-potato index --preset=erc20").unwrap();
+        let Some(TestCommand::Index(args)) = parsed.cli.command else { panic!("index subcommand expected") };
+        assert_eq!(args.topic_filter.topic0, vec!["Transfer(address,address,uint256)"]);
+        assert!(args.token_metadata);
+
+        // Explicit flags win over the preset, including ones clap refuses next to a preset setting
+        let parsed = parse("sweet-potato --preset headers --hydrate bloom").unwrap();
+        assert!(parsed.applied.is_empty());
+        assert_eq!(parsed.cli.index.hydration.level(), Hydration::Bloom);
+        let parsed = parse("sweet-potato --preset full --headers-only").unwrap();
+        assert_eq!(parsed.applied, vec![("TOKEN_METADATA", "true")]);
+        assert_eq!(parsed.cli.index.hydration.level(), Hydration::Hashes);
+        let parsed = parse("sweet-potato --preset headers").unwrap();
+        assert_eq!(parsed.cli.index.hydration.level(), Hydration::Hashes);
+
+        assert_eq!(parse("sweet-potato --preset nft").err().map(|e| e.kind()), Some(clap::error::ErrorKind::InvalidValue));
+        assert_eq!(Preset::Erc20.name(), "erc20");
+        for preset in Preset::value_variants() {
+            let mut names: Vec<&str> = preset.settings().iter().map(|(name, _)| *name).collect();
+            names.dedup();
+            assert_eq!(names.len(), preset.settings().len());
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+// Every bar is registered here so log lines are printed above the bars instead of through them
+static BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+// Without a terminal the bar stays hidden, so log a summary every this many percent instead
+const LOG_EVERY_PERCENT: u64 = 10;
+
+// Log output is written through this so lines are printed above the bars instead of through them
+pub struct BarWriter;
+
+impl Write for BarWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        BARS.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+pub struct Progress {
+    bar: ProgressBar,
+    started: Instant,
+    total: u64,
+    transactions: AtomicU64,
+    current_block: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(total: u64, enabled: bool) -> Self {
+        let target = if enabled { ProgressDrawTarget::stderr() } else { ProgressDrawTarget::hidden() };
+        let bar = BARS.add(ProgressBar::with_draw_target(Some(total), target));
+        bar.set_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {wide_bar:.cyan/blue} {pos}/{len} blocks | {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+        );
+        bar.enable_steady_tick(Duration::from_millis(250));
+        Self { bar, started: Instant::now(), total, transactions: AtomicU64::new(0), current_block: AtomicU64::new(0) }
+    }
+
+    pub fn block_done(&self, number: u64, transactions: usize) {
+        let transactions = self.transactions.fetch_add(transactions as u64, Ordering::SeqCst) + transactions as u64;
+        let current = self.current_block.fetch_max(number, Ordering::SeqCst).max(number);
+        self.bar.inc(1);
+        let done = self.bar.position();
+        let message = status_message(current, done, self.total, transactions, self.started.elapsed());
+        if self.bar.is_hidden() {
+            let step = (self.total * LOG_EVERY_PERCENT / 100).max(1);
+            if done.is_multiple_of(step) || done == self.total {
+                log::info!("Progress: {}/{} blocks | {}", done, self.total, message);
+            }
+        }
+        self.bar.set_message(message);
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+        BARS.remove(&self.bar);
+    }
+}
+
+// e.g. "block 1200 | 35.2 blocks/s | 410.7 tx/s | 800 remaining | ETA 23s"
+pub fn status_message(current_block: u64, done: u64, total: u64, transactions: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let (blocks_per_sec, txs_per_sec) = if seconds > 0.0 {
+        (done as f64 / seconds, transactions as f64 / seconds)
+    } else {
+        (0.0, 0.0)
+    };
+    let remaining = total.saturating_sub(done);
+    let eta = if blocks_per_sec > 0.0 {
+        format_duration(Duration::from_secs_f64(remaining as f64 / blocks_per_sec))
+    } else {
+        "-".to_string()
+    };
+    format!(
+        "block {} | {:.1} blocks/s | {:.1} tx/s | {} remaining | ETA {}",
+        current_block, blocks_per_sec, txs_per_sec, remaining, eta
+    )
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_message() {
+        let message = status_message(1200, 200, 1000, 4000, Duration::from_secs(10));
+        assert_eq!(message, "block 1200 | 20.0 blocks/s | 400.0 tx/s | 800 remaining | ETA 40s");
+
+        let message = status_message(1, 0, 10, 0, Duration::ZERO);
+        assert!(message.ends_with("10 remaining | ETA -"));
+
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1h02m05s");
+    }
+}
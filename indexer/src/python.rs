@@ -0,0 +1,116 @@
+//! Python bindings (`python` feature), built into a wheel with `maturin build --release`.
+//!
+//! Records cross the boundary as JSON, so Python receives plain dicts and lists that
+//! `pandas.DataFrame` accepts directly.
+
+use clap::ValueEnum;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::shared_runtime;
+use crate::pipeline;
+use crate::store::{self, Compression, OutputConfig, OutputFormat, DATASETS};
+
+fn runtime_error(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn to_python<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+fn check_dataset(dataset: &str) -> PyResult<()> {
+    if DATASETS.contains(&dataset) {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!("Unknown dataset {}, expected one of {:?}", dataset, DATASETS)))
+    }
+}
+
+/// Fetch and transform `count` blocks starting at `start` without writing anything.
+/// Returns a dict with one list per dataset (`blocks`, `transactions`, `receipts`, `logs`, `contracts`, `data_usage`, `ordering_analysis`, `nft_transfers`, `token_transfers`, `bridge_messages`).
+#[pyfunction]
+#[pyo3(signature = (start, count, concurrency = 8))]
+fn fetch_range(py: Python<'_>, start: u64, count: u64, concurrency: usize) -> PyResult<PyObject> {
+    let runtime = shared_runtime().map_err(runtime_error)?;
+    let batch = py
+        .allow_threads(|| runtime.block_on(pipeline::fetch_range(start, count, concurrency.max(1))))
+        .map_err(runtime_error)?;
+
+    let result = PyDict::new(py);
+    result.set_item("blocks", to_python(py, &batch.blocks)?)?;
+    result.set_item("transactions", to_python(py, &batch.transactions)?)?;
+    result.set_item("receipts", to_python(py, &batch.receipts)?)?;
+    result.set_item("logs", to_python(py, &batch.logs)?)?;
+    result.set_item("contracts", to_python(py, &batch.contracts)?)?;
+    result.set_item("data_usage", to_python(py, &batch.data_usage)?)?;
+    result.set_item("ordering_analysis", to_python(py, &batch.ordering_analysis)?)?;
+    result.set_item("nft_transfers", to_python(py, &batch.nft_transfers)?)?;
+    result.set_item("token_transfers", to_python(py, &batch.token_transfers)?)?;
+    result.set_item("bridge_messages", to_python(py, &batch.bridge_messages)?)?;
+    Ok(result.into_any().unbind())
+}
+
+/// Fetch, transform and write a block range to `raw_data_path` (default: `RAW_DATA_PATH`),
+/// using the same layout as the `sweet-potato` binary. Returns the number of blocks written.
+#[pyfunction]
+#[pyo3(signature = (start, count, raw_data_path = None, output_format = "json", compress = "none", chunk_size = 1000, concurrency = 8))]
+#[allow(clippy::too_many_arguments)]
+fn index_range(
+    py: Python<'_>,
+    start: u64,
+    count: u64,
+    raw_data_path: Option<String>,
+    output_format: &str,
+    compress: &str,
+    chunk_size: u64,
+    concurrency: usize,
+) -> PyResult<usize> {
+    let output = OutputConfig {
+        format: OutputFormat::from_str(output_format, true).map_err(PyValueError::new_err)?,
+        compress: Compression::from_str(compress, true).map_err(PyValueError::new_err)?,
+        chunk_size: chunk_size.max(1),
+        ..Default::default()
+    };
+    let raw_data_path = raw_data_path.unwrap_or_else(store::raw_data_path);
+    let runtime = shared_runtime().map_err(runtime_error)?;
+
+    py.allow_threads(|| runtime.block_on(pipeline::index_range(start, count, &raw_data_path, output, concurrency.max(1))))
+        .map_err(runtime_error)
+}
+
+/// Read one dataset (`blocks`, `transactions`, `receipts`, `logs`, `contracts`, `data_usage`, `ordering_analysis`, `nft_transfers`, `token_transfers`, `bridge_messages`, `balances`, `contract_state`, `addresses` or `data_quality`) for blocks `first..=last`
+/// from indexed output, in any of the formats the indexer writes. Returns a list of dicts.
+#[pyfunction]
+#[pyo3(signature = (dataset, first, last, raw_data_path = None))]
+fn read_range(py: Python<'_>, dataset: &str, first: u64, last: u64, raw_data_path: Option<String>) -> PyResult<PyObject> {
+    check_dataset(dataset)?;
+    let raw_data_path = raw_data_path.unwrap_or_else(store::raw_data_path);
+    let records: Vec<Value> = py
+        .allow_threads(|| store::read_range(&raw_data_path, dataset, first, last))
+        .map_err(runtime_error)?;
+    to_python(py, &records)
+}
+
+/// Same as `read_range`, returned as a `pandas.DataFrame` (requires pandas).
+#[pyfunction]
+#[pyo3(signature = (dataset, first, last, raw_data_path = None))]
+fn read_dataframe(py: Python<'_>, dataset: &str, first: u64, last: u64, raw_data_path: Option<String>) -> PyResult<PyObject> {
+    let pandas = py.import("pandas")?;
+    let records = read_range(py, dataset, first, last, raw_data_path)?;
+    Ok(pandas.call_method1("DataFrame", (records,))?.unbind())
+}
+
+#[pymodule]
+#[pyo3(name = "sweet_potato")]
+fn sweet_potato(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(fetch_range, m)?)?;
+    m.add_function(wrap_pyfunction!(index_range, m)?)?;
+    m.add_function(wrap_pyfunction!(read_range, m)?)?;
+    m.add_function(wrap_pyfunction!(read_dataframe, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Consistency checks every provider's data should pass. A block or receipt that fails one becomes
+//! a row of the `data_quality` dataset (and counts towards `data_quality_violations_total` on
+//! `/metrics`), so a node serving broken receipts shows up without re-deriving anything by hand.
+//!
+//! Gas checks, per block whose receipts are all in the batch:
+//! - `cumulative_gas_used`: the last receipt's `cumulative_gas_used` equals the header's `gas_used`
+//! - `receipt_gas_used`: each receipt's `gas_used` is its `cumulative_gas_used` minus the previous one's
+//! - `effective_gas_price`: no receipt pays less than the block's base fee (deposit transactions,
+//!   type 0x7e, pay no gas price and are skipped)
+//!
+//! Record checks, per block and raw receipt:
+//! - `zero_block_hash`: the block hash is not empty or all zeros
+//! - `gas_used_over_limit`: the header's `gas_used` is at most its `gas_limit`
+//! - `timestamp_order`: a block is not older than the block before it (only when both are in the batch)
+//! - `receipt_status`: the RPC's receipt `status` is `0x0` or `0x1`, before it becomes a bool
+//!
+//! Every `index` run also writes `raw_data/quality_reports/run_<created_at>_<first>_<last>.json`
+//! with the violations per check, zero included, and the rows themselves.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock;
+use crate::models::{DataQualityIssue, Receipt, TransformedBlock, TransformedReceipt};
+use crate::pipeline::TransformedBatch;
+use crate::store;
+
+pub const REPORT_DIR: &str = "quality_reports";
+
+pub const CHECKS: &[&str] = &[
+    "cumulative_gas_used",
+    "receipt_gas_used",
+    "effective_gas_price",
+    "zero_block_hash",
+    "gas_used_over_limit",
+    "timestamp_order",
+    "receipt_status",
+];
+
+// OP-stack deposit transactions are paid for on L1
+const DEPOSIT_TX_TYPE: u64 = 0x7e;
+
+fn issue(block: &TransformedBlock, check: &str, transaction_hash: Option<&str>, expected: String, actual: String) -> DataQualityIssue {
+    DataQualityIssue {
+        block_number: block.number,
+        check: check.to_string(),
+        transaction_hash: transaction_hash.map(str::to_string),
+        expected,
+        actual,
+        datetime: block.datetime,
+    }
+}
+
+pub fn check_gas(blocks: &[TransformedBlock], receipts: &[TransformedReceipt]) -> Vec<DataQualityIssue> {
+    let mut by_block: BTreeMap<u64, Vec<&TransformedReceipt>> = BTreeMap::new();
+    for receipt in receipts {
+        by_block.entry(receipt.block_number).or_default().push(receipt);
+    }
+    let mut issues = Vec::new();
+    for block in blocks {
+        let mut receipts = by_block.remove(&block.number).unwrap_or_default();
+        receipts.sort_by_key(|receipt| receipt.transaction_index);
+
+        if let Some(base_fee) = block.base_fee_per_gas {
+            for receipt in receipts.iter().filter(|receipt| receipt.tx_type != DEPOSIT_TX_TYPE && receipt.effective_gas_price < base_fee) {
+                issues.push(issue(block, "effective_gas_price", Some(&receipt.transaction_hash), format!(">= {}", base_fee), receipt.effective_gas_price.to_string()));
+            }
+        }
+
+        // The cumulative checks need every receipt of the block
+        if receipts.len() != block.transaction_hashes.len() {
+            continue;
+        }
+        let mut previous = 0;
+        for receipt in &receipts {
+            let expected = receipt.cumulative_gas_used.checked_sub(previous);
+            if expected != Some(receipt.gas_used) {
+                let expected = expected.map_or(format!("cumulative_gas_used >= {}", previous), |gas| gas.to_string());
+                issues.push(issue(block, "receipt_gas_used", Some(&receipt.transaction_hash), expected, receipt.gas_used.to_string()));
+            }
+            previous = receipt.cumulative_gas_used;
+        }
+        if previous != block.gas_used {
+            issues.push(issue(block, "cumulative_gas_used", receipts.last().map(|receipt| receipt.transaction_hash.as_str()), block.gas_used.to_string(), previous.to_string()));
+        }
+    }
+    issues
+}
+
+// Header checks of a block, and of its order after `previous` when that is its parent's height
+pub fn check_block(block: &TransformedBlock, previous: Option<&TransformedBlock>) -> Vec<DataQualityIssue> {
+    let mut issues = Vec::new();
+    if block.hash.trim_start_matches("0x").trim_start_matches('0').is_empty() {
+        issues.push(issue(block, "zero_block_hash", None, "non-zero hash".to_string(), block.hash.clone()));
+    }
+    if block.gas_used > block.gas_limit {
+        issues.push(issue(block, "gas_used_over_limit", None, format!("<= {}", block.gas_limit), block.gas_used.to_string()));
+    }
+    if let Some(previous) = previous.filter(|previous| previous.number + 1 == block.number && block.datetime < previous.datetime) {
+        issues.push(issue(block, "timestamp_order", None, format!(">= {}", previous.datetime.to_rfc3339()), block.datetime.to_rfc3339()));
+    }
+    issues
+}
+
+// Header checks of blocks in block order
+pub fn check_blocks(blocks: &[TransformedBlock]) -> Vec<DataQualityIssue> {
+    blocks.iter().enumerate().flat_map(|(i, block)| check_block(block, i.checked_sub(1).map(|i| &blocks[i]))).collect()
+}
+
+// Receipt statuses as the RPC returned them; the transformed receipt only keeps whether it is 0x1
+pub fn check_receipt_status(block: &TransformedBlock, receipts: &[Receipt]) -> Vec<DataQualityIssue> {
+    receipts
+        .iter()
+        .filter(|receipt| !["0x0", "0x1"].contains(&receipt.status.as_str()))
+        .map(|receipt| issue(block, "receipt_status", Some(&receipt.transaction_hash), "0x0 or 0x1".to_string(), receipt.status.clone()))
+        .collect()
+}
+
+// What one `index` run found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    pub created_at: DateTime<Utc>,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub blocks_checked: usize,
+    pub receipts_checked: usize,
+    // Violations per check, every check listed
+    pub violations: BTreeMap<String, usize>,
+    pub issues: Vec<DataQualityIssue>,
+}
+
+pub fn report(batch: &TransformedBatch, first: u64, last: u64) -> QualityReport {
+    let mut violations: BTreeMap<String, usize> = CHECKS.iter().map(|check| (check.to_string(), 0)).collect();
+    for issue in &batch.data_quality {
+        *violations.entry(issue.check.clone()).or_default() += 1;
+    }
+    QualityReport {
+        created_at: clock::now(),
+        first_block: first,
+        last_block: last,
+        blocks_checked: batch.blocks.len(),
+        receipts_checked: batch.receipts.len(),
+        violations,
+        issues: batch.data_quality.clone(),
+    }
+}
+
+pub fn write_report(raw_data_path: &str, report: &QualityReport) -> Result<PathBuf> {
+    let dir = Path::new(raw_data_path).join(REPORT_DIR);
+    store::ensure_directory(&dir.to_string_lossy())?;
+    let name = format!("run_{}_{}_{}.json", report.created_at.format("%Y%m%dT%H%M%S%.6fZ"), report.first_block, report.last_block);
+    let path = dir.join(name);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(report)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn receipt(index: u64, gas_used: u64, cumulative: u64, price: u64, tx_type: u64) -> TransformedReceipt {
+        TransformedReceipt {
+            cumulative_gas_used: cumulative,
+            effective_gas_price: price,
+            gas_used,
+            transaction_hash: format!("0x{}", index),
+            tx_type,
+            ..fixtures::receipt(5, index)
+        }
+    }
+
+    #[test]
+    fn test_gas_invariants() {
+        let mut block = TransformedBlock {
+            base_fee_per_gas: Some(7),
+            gas_limit: 30_000_000,
+            gas_used: 63_000,
+            transaction_hashes: vec!["0x0".to_string(), "0x1".to_string(), "0x2".to_string()],
+            ..fixtures::block(5)
+        };
+        let receipts = vec![receipt(1, 21_000, 42_000, 9, 2), receipt(0, 21_000, 21_000, 0, DEPOSIT_TX_TYPE), receipt(2, 21_000, 63_000, 8, 2)];
+        assert!(check_gas(std::slice::from_ref(&block), &receipts).is_empty());
+
+        block.gas_used = 64_000;
+        let receipts = vec![receipt(0, 21_000, 21_000, 6, 2), receipt(1, 20_000, 42_000, 9, 2), receipt(2, 21_000, 63_000, 8, 2)];
+        let issues: Vec<_> = check_gas(std::slice::from_ref(&block), &receipts)
+            .into_iter()
+            .map(|issue| (issue.check, issue.transaction_hash.unwrap_or_default(), issue.expected, issue.actual))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                ("effective_gas_price".to_string(), "0x0".to_string(), ">= 7".to_string(), "6".to_string()),
+                ("receipt_gas_used".to_string(), "0x1".to_string(), "21000".to_string(), "20000".to_string()),
+                ("cumulative_gas_used".to_string(), "0x2".to_string(), "64000".to_string(), "63000".to_string()),
+            ]
+        );
+        // Without every receipt only the base fee is checked
+        assert_eq!(check_gas(std::slice::from_ref(&block), &receipts[..2]).len(), 1);
+    }
+
+    #[test]
+    fn test_record_checks() {
+        let block = |number: u64, hash: &str, gas_used: u64, datetime: &str| TransformedBlock {
+            gas_limit: 30_000_000,
+            gas_used,
+            hash: hash.to_string(),
+            datetime: datetime.parse().unwrap(),
+            ..fixtures::block(number)
+        };
+        let blocks = vec![
+            block(5, "0xb5", 21_000, "2024-01-01T00:00:12Z"),
+            block(6, &format!("0x{:0>64}", ""), 30_000_001, "2024-01-01T00:00:00Z"),
+            // Not the next height, so its time is not compared with block 6
+            block(8, "0xb8", 0, "2023-12-31T00:00:00Z"),
+        ];
+        let checks: Vec<_> = check_blocks(&blocks).into_iter().map(|issue| (issue.block_number, issue.check, issue.actual)).collect();
+        assert_eq!(
+            checks,
+            vec![
+                (6, "zero_block_hash".to_string(), format!("0x{:0>64}", "")),
+                (6, "gas_used_over_limit".to_string(), "30000001".to_string()),
+                (6, "timestamp_order".to_string(), "2024-01-01T00:00:00+00:00".to_string()),
+            ]
+        );
+
+        let receipt = |status: &str| -> Receipt {
+            serde_json::from_value(json!({
+                "blockHash": "0xb5", "blockNumber": "0x5", "contractAddress": null, "cumulativeGasUsed": "0x0", "from": "0xf",
+                "gasUsed": "0x0", "logs": [], "logsBloom": "0x", "status": status, "to": null, "transactionHash": format!("0x{}", status),
+                "transactionIndex": "0x0", "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let issues = check_receipt_status(&blocks[0], &[receipt("0x1"), receipt("0x0"), receipt("0x2")]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].transaction_hash.as_deref(), issues[0].actual.as_str()), (Some("0x0x2"), "0x2"));
+
+        let batch = TransformedBatch { data_quality: issues, ..Default::default() };
+        let report = report(&batch, 5, 8);
+        assert_eq!((report.violations.len(), report.violations["receipt_status"], report.violations["timestamp_order"]), (CHECKS.len(), 1, 0));
+    }
+}
@@ -0,0 +1,79 @@
+//! Raw RPC responses kept by `index --keep-raw`, so `transform` can rebuild the datasets offline
+//! after a schema or decoder change. Every fully fetched block is saved as
+//! `raw_data/raw_rpc/<number / 10000>/block_<number>.json.zst`: the `eth_getBlockByNumber`
+//! response with full transactions and the block's receipts, exactly as the RPC returned them.
+//!
+//! Blocks left unhydrated by `--hydrate hashes` or `bloom` are not kept. Fetching a block again
+//! replaces its file, so after a reorg the cache holds the block that was indexed last.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::store;
+
+pub const RAW_DIR: &str = "raw_rpc";
+// Blocks per directory, so no directory grows without bound
+const SHARD_SIZE: u64 = 10_000;
+
+// RAW_DATA_PATH responses are kept under, once `keep` was called
+static KEEP_PATH: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawBlock {
+    pub block: Value,
+    pub receipts: Value,
+}
+
+// Keeps the responses of every block fetched from now on
+pub fn keep(raw_data_path: &str) {
+    let _ = KEEP_PATH.set(raw_data_path.to_string());
+}
+
+pub fn is_kept() -> bool {
+    KEEP_PATH.get().is_some()
+}
+
+pub fn path(raw_data_path: &str, number: u64) -> PathBuf {
+    Path::new(raw_data_path)
+        .join(RAW_DIR)
+        .join((number / SHARD_SIZE).to_string())
+        .join(format!("block_{}.json.zst", number))
+}
+
+pub fn write(raw_data_path: &str, number: u64, raw: &RawBlock) -> Result<PathBuf> {
+    let path = path(raw_data_path, number);
+    if let Some(dir) = path.parent() {
+        store::ensure_directory(&dir.to_string_lossy())?;
+    }
+    let data = zstd::encode_all(serde_json::to_vec(raw)?.as_slice(), 0)?;
+    let tmp = path.with_extension("zst.tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+// Saves a fetched block's responses when they are kept
+pub async fn save(number: u64, raw: RawBlock) -> Result<()> {
+    let Some(raw_data_path) = KEEP_PATH.get() else {
+        return Ok(());
+    };
+    let raw_data_path = raw_data_path.clone();
+    tokio::task::spawn_blocking(move || write(&raw_data_path, number, &raw).map(|_| ())).await?
+}
+
+// A block's kept responses, None when it was never kept
+pub fn load(raw_data_path: &str, number: u64) -> Result<Option<RawBlock>> {
+    let path = path(raw_data_path, number);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow::anyhow!("Could not read {}: {}", path.display(), e)),
+    };
+    let json = zstd::decode_all(data.as_slice()).map_err(|e| anyhow::anyhow!("Could not decompress {}: {}", path.display(), e))?;
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
@@ -0,0 +1,177 @@
+//! Websocket fan-out of what the indexer writes, so one indexer (and one upstream RPC) serves any
+//! number of local consumers instead of each tool holding its own subscription.
+//!
+//! Every written batch is published as one text message per record: a summary of each block and
+//! each token or NFT transfer that made it through `--filters`, as
+//! `{"dataset": .., "block_number": .., "data": {..}}`. Consumers narrow the feed with query
+//! parameters, e.g. `ws://localhost:9200/?datasets=token_transfers&addresses=0xa0b8...`: `datasets`
+//! keeps only those datasets and `addresses` only records whose token, sender or recipient is one
+//! of them (blocks always pass the address check). A consumer that falls more than `RELAY_BUFFER`
+//! messages behind gets `{"lagged": <skipped messages>}` and continues from the newest ones.
+
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Query;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::broadcast;
+
+use crate::pipeline::TransformedBatch;
+use crate::store::Record;
+
+static FEED: OnceLock<broadcast::Sender<Arc<Update>>> = OnceLock::new();
+
+#[derive(Debug, Serialize)]
+pub struct Update {
+    pub dataset: &'static str,
+    pub block_number: u64,
+    pub data: Value,
+    // Lowercase token, sender and recipient addresses, for the `addresses` parameter
+    #[serde(skip)]
+    addresses: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Subscription {
+    datasets: Option<HashSet<String>>,
+    addresses: Option<HashSet<String>>,
+}
+
+impl Subscription {
+    fn from_query(query: &HashMap<String, String>) -> Self {
+        let list = |key: &str| {
+            query.get(key).map(|value| {
+                value.split(',').map(|item| item.trim().to_lowercase()).filter(|item| !item.is_empty()).collect()
+            })
+        };
+        Self { datasets: list("datasets"), addresses: list("addresses") }
+    }
+
+    fn wants(&self, update: &Update) -> bool {
+        self.datasets.as_ref().is_none_or(|datasets| datasets.contains(update.dataset))
+            && (update.dataset == "blocks"
+                || self.addresses.as_ref().is_none_or(|addresses| update.addresses.iter().any(|a| addresses.contains(a))))
+    }
+}
+
+fn transfer<T: Record + Serialize>(record: &T, token: &str, from: &str, to: &str) -> Result<Update> {
+    Ok(Update {
+        dataset: T::DATASET,
+        block_number: record.block_number(),
+        data: serde_json::to_value(record)?,
+        addresses: [token, from, to].map(str::to_lowercase).to_vec(),
+    })
+}
+
+// The messages a written batch turns into, blocks first
+pub fn updates(batch: &TransformedBatch) -> Result<Vec<Update>> {
+    let mut updates: Vec<Update> = batch
+        .blocks
+        .iter()
+        .map(|block| Update {
+            dataset: "blocks",
+            block_number: block.number,
+            data: json!({
+                "number": block.number,
+                "hash": block.hash,
+                "parent_hash": block.parent_hash,
+                "datetime": block.datetime,
+                "gas_used": block.gas_used,
+                "transactions": block.transaction_hashes.len(),
+            }),
+            addresses: Vec::new(),
+        })
+        .collect();
+    for t in &batch.token_transfers {
+        updates.push(transfer(t, &t.token_address, &t.from, &t.to)?);
+    }
+    for t in &batch.nft_transfers {
+        updates.push(transfer(t, &t.token_address, &t.from, &t.to)?);
+    }
+    Ok(updates)
+}
+
+// Sends a written batch to every connected consumer; a no-op when the relay is not running or
+// nobody is connected
+pub fn publish(batch: &TransformedBatch) -> Result<()> {
+    let Some(feed) = FEED.get() else { return Ok(()) };
+    if feed.receiver_count() == 0 {
+        return Ok(());
+    }
+    for update in updates(batch)? {
+        let _ = feed.send(Arc::new(update));
+    }
+    Ok(())
+}
+
+async fn relay(mut socket: WebSocket, subscription: Subscription, mut feed: broadcast::Receiver<Arc<Update>>) {
+    loop {
+        let text = tokio::select! {
+            received = feed.recv() => match received {
+                Ok(update) if subscription.wants(&update) => serde_json::to_string(&*update).unwrap_or_default(),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => json!({ "lagged": skipped }).to_string(),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // Consumers only ever close the connection; anything else they send is ignored
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn connect(upgrade: WebSocketUpgrade, Query(query): Query<HashMap<String, String>>) -> Response {
+    let subscription = Subscription::from_query(&query);
+    let feed = FEED.get().expect("relay feed is created before serving").subscribe();
+    upgrade.on_upgrade(move |socket| relay(socket, subscription, feed))
+}
+
+// Starts the relay once per process; `buffer` is how many messages a slow consumer may fall behind
+pub async fn serve(addr: SocketAddr, buffer: usize) -> Result<()> {
+    if FEED.set(broadcast::channel(buffer.max(1)).0).is_err() {
+        return Ok(());
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log::info!("Relaying indexed blocks and transfers on ws://{}/", addr);
+    let app = Router::new().route("/", get(connect));
+    // Not stopped on shutdown, so consumers still receive the batch written while stopping
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription() {
+        let update = |dataset: &'static str, addresses: &[&str]| Update {
+            dataset,
+            block_number: 1,
+            data: Value::Null,
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+        };
+        let query: HashMap<String, String> = [
+            ("datasets".to_string(), "blocks, token_transfers".to_string()),
+            ("addresses".to_string(), "0xAAA".to_string()),
+        ]
+        .into();
+        let subscription = Subscription::from_query(&query);
+        assert!(subscription.wants(&update("blocks", &[])));
+        assert!(subscription.wants(&update("token_transfers", &["0xusdc", "0xb", "0xaaa"])));
+        assert!(!subscription.wants(&update("token_transfers", &["0xusdc", "0xb", "0xc"])));
+        assert!(!subscription.wants(&update("nft_transfers", &["0xaaa"])));
+        assert!(Subscription::default().wants(&update("nft_transfers", &[])));
+    }
+}
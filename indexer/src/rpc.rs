@@ -0,0 +1,666 @@
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+use crate::block_cache;
+use crate::bloom::bloom_matches_any;
+use crate::faults;
+use crate::json;
+use crate::linkage;
+use crate::models::{Block, Receipt, Transaction};
+use crate::networks;
+use crate::raw::{self, RawBlock};
+
+pub const RPC_URL: &str = match option_env!("RPC_URL") {
+    Some(url) => url,
+    None => "https://rpc.sepolia.linea.build",
+};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct ClientConfig {
+    /// JSON-RPC endpoint; defaults to the --network's public endpoint, else the one built in
+    #[arg(long = "rpc-url", env = "RPC_URL", global = true)]
+    pub rpc_url: Option<String>,
+
+    /// Seconds an RPC request may take from connecting to the last byte of the response, 0 for no limit
+    #[arg(long = "rpc-timeout", env = "RPC_TIMEOUT_SECS", default_value_t = 30, global = true)]
+    pub rpc_timeout: u64,
+
+    /// Seconds to wait for a connection to the RPC
+    #[arg(long = "rpc-connect-timeout", env = "RPC_CONNECT_TIMEOUT_SECS", default_value_t = 10, global = true)]
+    pub rpc_connect_timeout: u64,
+
+    /// Seconds idle RPC connections are kept open for reuse (and the TCP keep-alive interval)
+    #[arg(long = "rpc-keep-alive", env = "RPC_KEEP_ALIVE_SECS", default_value_t = 90, global = true)]
+    pub rpc_keep_alive: u64,
+
+    /// Speak HTTP/2 to the RPC from the first request; the provider must support it
+    #[arg(long = "rpc-http2", env = "RPC_HTTP2", global = true)]
+    pub rpc_http2: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self { rpc_url: None, rpc_timeout: 30, rpc_connect_timeout: 10, rpc_keep_alive: 90, rpc_http2: false }
+    }
+}
+
+impl ClientConfig {
+    fn build(&self) -> Result<reqwest::Client> {
+        let keep_alive = Duration::from_secs(self.rpc_keep_alive);
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(self.rpc_connect_timeout))
+            .pool_idle_timeout(keep_alive)
+            .tcp_keepalive(keep_alive);
+        if self.rpc_timeout > 0 {
+            builder = builder.timeout(Duration::from_secs(self.rpc_timeout));
+        }
+        if self.rpc_http2 {
+            builder = builder.http2_prior_knowledge().http2_keep_alive_interval(keep_alive).http2_keep_alive_while_idle(true);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(u8)]
+pub enum ReceiptStrategy {
+    // One `eth_getBlockReceipts` call per block
+    #[default]
+    Block,
+    // `eth_getTransactionReceipt` per transaction, sent in JSON-RPC batches
+    Transaction,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct ReceiptConfig {
+    /// How receipts are fetched: `block` uses eth_getBlockReceipts, `transaction` batches
+    /// eth_getTransactionReceipt calls for providers without (or with a slow) eth_getBlockReceipts
+    #[arg(long = "receipts", env = "RECEIPT_STRATEGY", value_enum, default_value = "block", global = true)]
+    pub strategy: ReceiptStrategy,
+
+    /// eth_getTransactionReceipt calls per JSON-RPC batch with `--receipts transaction`
+    #[arg(long = "receipt-batch-size", env = "RECEIPT_BATCH_SIZE", default_value_t = 100, global = true)]
+    pub batch_size: usize,
+}
+
+static RECEIPT_STRATEGY: AtomicU8 = AtomicU8::new(ReceiptStrategy::Block as u8);
+static RECEIPT_BATCH_SIZE: AtomicUsize = AtomicUsize::new(100);
+
+pub fn set_receipt_config(config: &ReceiptConfig) {
+    RECEIPT_STRATEGY.store(config.strategy as u8, Ordering::SeqCst);
+    RECEIPT_BATCH_SIZE.store(config.batch_size.max(1), Ordering::SeqCst);
+}
+
+fn receipt_strategy() -> ReceiptStrategy {
+    match RECEIPT_STRATEGY.load(Ordering::Relaxed) {
+        0 => ReceiptStrategy::Block,
+        _ => ReceiptStrategy::Transaction,
+    }
+}
+
+// One client for every RPC call, so connections are pooled and reused across requests
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+// Sets up the shared client; has no effect once a request has been sent
+pub fn configure_client(config: &ClientConfig) -> Result<()> {
+    let _ = CLIENT.set(config.build()?);
+    if let Some(url) = &config.rpc_url {
+        set_url(url);
+    }
+    Ok(())
+}
+
+static URL: OnceLock<String> = OnceLock::new();
+
+// Sets the endpoint every request goes to; the first one set stays
+pub fn set_url(url: &str) {
+    let _ = URL.set(url.to_string());
+}
+
+pub fn url() -> &'static str {
+    URL.get().map_or(RPC_URL, String::as_str)
+}
+
+fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(|| ClientConfig::default().build().unwrap_or_default())
+}
+
+// Requests per second allowed against the RPC, 0 means unlimited
+static RPS_LIMIT: AtomicU32 = AtomicU32::new(0);
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static NEXT_REQUEST_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub fn set_rps(rps: u32) {
+    RPS_LIMIT.store(rps, Ordering::SeqCst);
+}
+
+pub fn rps() -> u32 {
+    RPS_LIMIT.load(Ordering::SeqCst)
+}
+
+// JSON-RPC requests sent by this process so far
+pub fn request_count() -> u64 {
+    REQUESTS.load(Ordering::SeqCst)
+}
+
+// Unmapped RPC fields are always kept in `extra`; strict mode also logs each one the first time
+static STRICT_SCHEMA: AtomicBool = AtomicBool::new(false);
+static REPORTED_FIELDS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+pub fn set_strict_schema(strict: bool) {
+    STRICT_SCHEMA.store(strict, Ordering::SeqCst);
+}
+
+// Field names of `kind` objects not seen before, as `kind.field`
+fn new_unmapped_fields<'a>(kind: &str, extras: impl IntoIterator<Item = &'a Map<String, Value>>) -> Vec<String> {
+    let mut reported = REPORTED_FIELDS.lock().unwrap();
+    let mut fields = Vec::new();
+    for name in extras.into_iter().flat_map(|extra| extra.keys()) {
+        let field = format!("{}.{}", kind, name);
+        if reported.insert(field.clone()) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+fn check_schema<'a>(kind: &str, extras: impl IntoIterator<Item = &'a Map<String, Value>>) {
+    if !STRICT_SCHEMA.load(Ordering::Relaxed) {
+        return;
+    }
+    for field in new_unmapped_fields(kind, extras) {
+        log::warn!("RPC returned unmapped field {}, keeping it in `extra`", field);
+    }
+}
+
+// Spaces requests evenly so the configured rate is never exceeded
+async fn throttle() {
+    let rps = rps();
+    if rps == 0 {
+        return;
+    }
+    let interval = Duration::from_secs_f64(1.0 / rps as f64);
+    let wait = {
+        let mut next = NEXT_REQUEST_AT.lock().unwrap();
+        let now = Instant::now();
+        let slot = next.map_or(now, |next| next.max(now));
+        *next = Some(slot + interval);
+        slot - now
+    };
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+// Sends a JSON-RPC request and returns its `result` field. Each call gets an `rpc` span carrying
+// its latency, including time spent waiting for the rate limit.
+pub async fn rpc_call(method: &str, params: Value) -> Result<Value> {
+    let span = tracing::info_span!("rpc", method, latency_ms = tracing::field::Empty, ok = tracing::field::Empty);
+    let started = Instant::now();
+    let result = send(method, params).instrument(span.clone()).await;
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    span.record("ok", result.is_ok());
+    result
+}
+
+// Posts a request body (one call or a batch of `calls`) and returns the parsed response
+async fn post(method: &str, calls: usize, body: &Value) -> Result<Value> {
+    for _ in 0..calls {
+        throttle().await;
+    }
+    if method != "eth_chainId" {
+        networks::verify().await?;
+    }
+    faults::before_rpc(method).await?;
+    REQUESTS.fetch_add(calls as u64, Ordering::SeqCst);
+    let timed_out = |e: reqwest::Error| match e.is_timeout() {
+        true => anyhow::anyhow!("{} timed out: {}", method, e),
+        false => e.into(),
+    };
+
+    let response = client().post(url()).json(body).send().await.map_err(timed_out)?;
+    let body = response.bytes().await.map_err(timed_out)?;
+    json::parse(&body)
+}
+
+async fn send(method: &str, params: Value) -> Result<Value> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params
+    });
+    let data = post(method, 1, &body).await?;
+    result_of(method, data)
+}
+
+// Sends the calls of one method as a JSON-RPC batch, returning their results in call order
+pub async fn rpc_batch(method: &str, params: Vec<Value>) -> Result<Vec<Value>> {
+    if params.is_empty() {
+        return Ok(Vec::new());
+    }
+    let span = tracing::info_span!("rpc", method, calls = params.len(), latency_ms = tracing::field::Empty, ok = tracing::field::Empty);
+    let started = Instant::now();
+    let result = send_batch(method, params).instrument(span.clone()).await;
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    span.record("ok", result.is_ok());
+    result
+}
+
+async fn send_batch(method: &str, params: Vec<Value>) -> Result<Vec<Value>> {
+    let calls = params.len();
+    let body: Vec<Value> = params
+        .into_iter()
+        .enumerate()
+        .map(|(id, params)| json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+        .collect();
+    let data = post(method, calls, &Value::Array(body)).await?;
+    batch_results(method, calls, data)
+}
+
+// Matches batch responses to calls by id
+fn batch_results(method: &str, calls: usize, data: Value) -> Result<Vec<Value>> {
+    let Value::Array(responses) = data else {
+        // Providers without batch support answer with a single error object
+        return Err(anyhow::anyhow!("{} batch was not answered with a batch: {}", method, data));
+    };
+    // Responses may come back in any order
+    let mut results: Vec<Option<Value>> = vec![None; calls];
+    for response in responses {
+        let id = response["id"].as_u64().map(|id| id as usize).filter(|id| *id < calls);
+        let Some(id) = id else {
+            return Err(anyhow::anyhow!("{} batch answered with unknown id {}", method, response["id"]));
+        };
+        results[id] = Some(result_of(method, response)?);
+    }
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(id, result)| result.ok_or_else(|| anyhow::anyhow!("{} batch left call {} unanswered", method, id)))
+        .collect()
+}
+
+// The `result` of a JSON-RPC response, or its error
+fn result_of(method: &str, mut data: Value) -> Result<Value> {
+    if let Some(error) = data.get("error") {
+        return Err(anyhow::anyhow!("{} failed: {}", method, error));
+    }
+    match data.get_mut("result") {
+        Some(result) if !result.is_null() => {
+            faults::corrupt_rpc(method, result);
+            Ok(result.take())
+        },
+        Some(_) => Err(anyhow::anyhow!("{} returned null", method)),
+        None => Err(anyhow::anyhow!("No result field in response"))
+    }
+}
+
+// Raw block JSON with hydrated transactions
+pub async fn get_raw_block(number: u64) -> Result<Value> {
+    get_raw_block_with(number, true).await
+}
+
+// Raw block JSON; `full` = false returns transaction hashes only
+pub async fn get_raw_block_with(number: u64, full: bool) -> Result<Value> {
+    let hex_number = format!("0x{:x}", number);
+    rpc_call("eth_getBlockByNumber", json!([hex_number, full])).await
+}
+
+pub async fn get_latest_block_number() -> Result<u64> {
+    let result = rpc_call("eth_blockNumber", json!([])).await?;
+    hex_quantity("eth_blockNumber", &result)
+}
+
+pub async fn get_chain_id() -> Result<u64> {
+    let result = rpc_call("eth_chainId", json!([])).await?;
+    hex_quantity("eth_chainId", &result)
+}
+
+fn hex_quantity(method: &str, result: &Value) -> Result<u64> {
+    let hex_number = result.as_str().ok_or_else(|| anyhow::anyhow!("{} returned {}", method, result))?;
+    Ok(u64::from_str_radix(hex_number.trim_start_matches("0x"), 16)?)
+}
+
+// Transactions `address` had sent by the end of `block` (its nonce there); needs an archive node for old blocks
+pub async fn get_transaction_count(address: &str, block: u64) -> Result<u64> {
+    let result = rpc_call("eth_getTransactionCount", json!([address, format!("0x{:x}", block)])).await?;
+    hex_quantity("eth_getTransactionCount", &result)
+}
+
+// Number of the block with `hash`; None when the node does not know it
+pub async fn get_block_number_by_hash(hash: &str) -> Result<Option<u64>> {
+    let header = rpc_call("eth_getBlockByHash", json!([hash, false])).await?;
+    match &header["number"] {
+        Value::Null => Ok(None),
+        number => hex_quantity("eth_getBlockByHash", number).map(Some),
+    }
+}
+
+// Transaction as `eth_getTransactionByHash` returned it; null when the node does not know it
+pub async fn get_raw_transaction(hash: &str) -> Result<Value> {
+    rpc_call("eth_getTransactionByHash", json!([hash])).await
+}
+
+// Block of the transaction `address` sent with `nonce`, through the Otterscan API (Erigon, Anvil,
+// Reth); None when there is no such transaction yet
+pub async fn get_block_by_sender_and_nonce(address: &str, nonce: u64) -> Result<Option<u64>> {
+    let hash = rpc_call("ots_getTransactionBySenderAndNonce", json!([address, format!("0x{:x}", nonce)])).await?;
+    if hash.is_null() {
+        return Ok(None);
+    }
+    let transaction = rpc_call("eth_getTransactionByHash", json!([hash])).await?;
+    match &transaction["blockNumber"] {
+        Value::Null => Ok(None),
+        number => hex_quantity("eth_getTransactionByHash", number).map(Some),
+    }
+}
+
+// How many blocks of `start..start + count` are at or below `safe_head`
+pub fn blocks_up_to(start: u64, count: u64, safe_head: u64) -> u64 {
+    (safe_head + 1).saturating_sub(start).min(count)
+}
+
+// Receipts of a block, fetched with the configured strategy
+pub async fn get_raw_block_receipts(number: u64) -> Result<Value> {
+    match receipt_strategy() {
+        ReceiptStrategy::Block => {
+            let hex_number = format!("0x{:x}", number);
+            rpc_call("eth_getBlockReceipts", json!([hex_number])).await
+        },
+        ReceiptStrategy::Transaction => {
+            let header = get_raw_block_with(number, false).await?;
+            let hashes: Vec<String> = serde_json::from_value(header["transactions"].clone())?;
+            get_raw_transaction_receipts(&hashes).await
+        },
+    }
+}
+
+// Receipts of the transactions, in order, as batched `eth_getTransactionReceipt` calls
+pub async fn get_raw_transaction_receipts(hashes: &[String]) -> Result<Value> {
+    let batch_size = RECEIPT_BATCH_SIZE.load(Ordering::Relaxed);
+    let batches = hashes
+        .chunks(batch_size)
+        .map(|chunk| rpc_batch("eth_getTransactionReceipt", chunk.iter().map(|hash| json!([hash])).collect()));
+    let receipts: Vec<Vec<Value>> = futures::future::try_join_all(batches).await?;
+    Ok(Value::Array(receipts.into_iter().flatten().collect()))
+}
+
+// Splits an `eth_getBlockByNumber` response with full transactions into the block (transaction
+// hashes only) and its transactions
+pub fn parse_block(result: Value) -> Result<(Block, Vec<Transaction>)> {
+    // First, parse the full response to get transactions
+    let transactions: Vec<Transaction> = serde_json::from_value(result["transactions"].clone())?;
+
+    // Then modify the transactions field to only contain hashes
+    let mut block_value = result;
+    if let Some(txs) = block_value.as_object_mut() {
+        let tx_hashes: Vec<String> = transactions.iter()
+            .map(|tx| tx.hash.clone())
+            .collect();
+        txs["transactions"] = json!(tx_hashes);
+    }
+
+    let block: Block = serde_json::from_value(block_value)?;
+    check_schema("block", [&block.extra]);
+    check_schema("transaction", transactions.iter().map(|tx| &tx.extra));
+    Ok((block, transactions))
+}
+
+pub fn parse_receipts(result: Value) -> Result<Vec<Receipt>> {
+    let receipts: Vec<Receipt> = serde_json::from_value(result)?;
+    check_schema("receipt", receipts.iter().map(|receipt| &receipt.extra));
+    Ok(receipts)
+}
+
+// Block with full transactions, as the RPC returned it
+async fn fetch_raw_block(number: u64) -> Result<Value> {
+    let start = Instant::now();
+    log::info!("Fetching block {}", number);
+    let result = get_raw_block(number).await?;
+    log::info!("Block {} fetched in {:?}", number, start.elapsed());
+    Ok(result)
+}
+
+// Receipts of a block, looked up by its transaction hashes when given (skipping the header call
+// of `--receipts transaction`)
+async fn fetch_raw_receipts(number: u64, hashes: Option<&[String]>) -> Result<Value> {
+    let start = Instant::now();
+    let result = match hashes {
+        Some(hashes) => {
+            log::info!("Fetching {} transaction receipts for block {}", hashes.len(), number);
+            get_raw_transaction_receipts(hashes).await?
+        },
+        None => {
+            log::info!("Fetching receipts for block {}", number);
+            get_raw_block_receipts(number).await?
+        },
+    };
+    log::info!("Receipts for block {} fetched in {:?}", number, start.elapsed());
+    Ok(result)
+}
+
+// Block header only (`eth_getBlockByNumber` with `false`), transaction hashes included
+pub async fn get_block_header(number: u64) -> Result<Block> {
+    let block: Block = serde_json::from_value(get_raw_block_with(number, false).await?)?;
+    check_schema("block", [&block.extra]);
+    Ok(block)
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Hydration {
+    // Full transactions and receipts for every block
+    #[default]
+    Full,
+    // Headers with transaction hashes only; no transactions or receipts
+    Hashes,
+    // Headers first, then transactions and receipts only when the logs bloom matches
+    Bloom,
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct HydrationConfig {
+    /// How much of each block to fetch (default: full, or bloom with --topic0)
+    #[arg(long = "hydrate", env = "HYDRATE", value_enum)]
+    pub hydration: Option<Hydration>,
+
+    /// Log-emitting addresses and topics that make a block worth hydrating with `--hydrate bloom`
+    #[arg(long = "bloom-item", env = "BLOOM_ITEMS", value_delimiter = ',')]
+    pub bloom_items: Vec<String>,
+
+    /// Only index block headers (times, gas, base fees), skipping transactions and receipts; same as `--hydrate hashes`
+    #[arg(long = "headers-only", env = "HEADERS_ONLY", conflicts_with = "hydration")]
+    pub headers_only: bool,
+}
+
+impl HydrationConfig {
+    pub fn level(&self) -> Hydration {
+        match self.headers_only {
+            true => Hydration::Hashes,
+            false => self.hydration.unwrap_or_default(),
+        }
+    }
+
+    // A block whose logs bloom has none of the `--topic0` events cannot keep a transaction. Unless a
+    // level was chosen, only blocks whose bloom matches are hydrated; returns whether the level changed.
+    pub fn prescreen(&mut self, topics: &BTreeSet<String>) -> bool {
+        if topics.is_empty() || !self.bloom_items.is_empty() {
+            return false;
+        }
+        match (self.headers_only, self.hydration) {
+            (false, None) => {
+                self.hydration = Some(Hydration::Bloom);
+                self.bloom_items = topics.iter().cloned().collect();
+                true
+            },
+            (false, Some(Hydration::Bloom)) => {
+                self.bloom_items = topics.iter().cloned().collect();
+                false
+            },
+            _ => false,
+        }
+    }
+}
+
+// Fetches a block at the configured hydration level. Blocks left unhydrated come back with their
+// header (and transaction hashes) but no transactions or receipts.
+pub async fn fetch_block_data_with(
+    block_number: u64,
+    config: &HydrationConfig,
+) -> Result<(Block, Vec<Transaction>, Vec<Receipt>)> {
+    let header = match config.level() {
+        Hydration::Full => return fetch_block_data(block_number).await,
+        Hydration::Hashes | Hydration::Bloom => get_block_header(block_number)
+            .await
+            .map_err(|e| anyhow::anyhow!("Error fetching block {}: {}", block_number, e))?,
+    };
+    if config.level() == Hydration::Bloom && bloom_matches_any(&header.logs_bloom, &config.bloom_items) {
+        return fetch_block_data(block_number).await;
+    }
+    log::debug!("Block {} left unhydrated", block_number);
+    Ok((header, Vec::new(), Vec::new()))
+}
+
+// Whether a fetched block came with all of its transactions
+pub fn is_hydrated(block: &Block, transactions: &[Transaction]) -> bool {
+    block.transaction_hashes.len() == transactions.len()
+}
+
+// Block and receipts responses of a block, as the RPC returned them
+async fn fetch_raw_block_data(block_number: u64) -> Result<RawBlock> {
+    let fetch_block = fetch_raw_block(block_number).instrument(tracing::info_span!("fetch_block", number = block_number));
+    let receipts_span = tracing::info_span!("fetch_receipts", number = block_number);
+    let (block_result, receipts_result) = match receipt_strategy() {
+        ReceiptStrategy::Block => tokio::join!(fetch_block, fetch_raw_receipts(block_number, None).instrument(receipts_span)),
+        // Receipts are looked up by the hashes of the block's transactions
+        ReceiptStrategy::Transaction => {
+            let block_result = fetch_block.await;
+            let receipts_result = match &block_result {
+                Ok(block) => {
+                    let transactions = block["transactions"].as_array().into_iter().flatten();
+                    let hashes: Vec<String> = transactions.filter_map(|tx| tx["hash"].as_str().map(str::to_string)).collect();
+                    fetch_raw_receipts(block_number, Some(&hashes)).instrument(receipts_span).await
+                },
+                Err(_) => Ok(Value::Null),
+            };
+            (block_result, receipts_result)
+        },
+    };
+
+    match (block_result, receipts_result) {
+        (Ok(block), Ok(receipts)) => Ok(RawBlock { block, receipts }),
+        (Err(e), _) => Err(anyhow::anyhow!("Error fetching block {}: {}", block_number, e)),
+        (_, Err(e)) => Err(anyhow::anyhow!("Error fetching receipts for block {}: {}", block_number, e)),
+    }
+}
+
+// Fetches a block together with its receipts, from the block cache when it holds them
+pub async fn fetch_block_data(block_number: u64) -> Result<(Block, Vec<Transaction>, Vec<Receipt>)> {
+    let block_start = Instant::now();
+    log::info!("Processing block {}", block_number);
+
+    let raw = match block_cache::lookup(block_number).await {
+        Some(cached) => cached,
+        None => {
+            let fetched = fetch_raw_block_data(block_number).await?;
+            block_cache::store(&fetched).await;
+            fetched
+        },
+    };
+    if raw::is_kept() {
+        if let Err(e) = raw::save(block_number, raw.clone()).await {
+            log::warn!("Could not keep the raw responses of block {}: {}", block_number, e);
+        }
+    }
+    let (block, block_transactions) = parse_block(raw.block).map_err(|e| anyhow::anyhow!("Error fetching block {}: {}", block_number, e))?;
+    let receipts = parse_receipts(raw.receipts).map_err(|e| anyhow::anyhow!("Error fetching receipts for block {}: {}", block_number, e))?;
+    linkage::enforce(block_number, &block, &receipts)?;
+    log::info!("Block {} processed in {:?}", block_number, block_start.elapsed());
+    Ok((block, block_transactions, receipts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_fields_are_kept_and_reported_once() {
+        let receipt: Receipt = serde_json::from_value(json!({
+            "blockHash": "0xb", "blockNumber": "0x1", "contractAddress": null, "cumulativeGasUsed": "0x0",
+            "from": "0xf", "gasUsed": "0x0", "logs": [], "logsBloom": "0x", "status": "0x1", "to": null,
+            "transactionHash": "0xh", "transactionIndex": "0x0", "type": "0x2",
+            "l1GasUsed": "0x640", "l1Fee": "0x10",
+        }))
+        .unwrap();
+        assert_eq!(receipt.effective_gas_price, "");
+        assert_eq!(receipt.extra.len(), 2);
+        let transformed = crate::transform::transform_receipt(&receipt, chrono::Utc::now());
+        assert_eq!(serde_json::to_value(&transformed).unwrap()["extra"]["l1GasUsed"], json!("0x640"));
+        assert_eq!(transformed.id, 1_000_000);
+
+        let fields = new_unmapped_fields("test_receipt", [&receipt.extra, &receipt.extra]);
+        assert_eq!(fields, vec!["test_receipt.l1GasUsed".to_string(), "test_receipt.l1Fee".to_string()]);
+        assert!(new_unmapped_fields("test_receipt", [&receipt.extra]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hung_provider_times_out() {
+        // Accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                open.push(stream);
+            }
+        });
+        let client = ClientConfig { rpc_timeout: 1, ..Default::default() }.build().unwrap();
+        let started = Instant::now();
+        let error = client.post(format!("http://{}", addr)).send().await.unwrap_err();
+        assert!(error.is_timeout());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_batch_results() {
+        let response = |id: u64, result: Value| json!({ "jsonrpc": "2.0", "id": id, "result": result });
+        let answered = json!([response(1, json!("b")), response(0, json!("a"))]);
+        assert_eq!(batch_results("m", 2, answered).unwrap(), vec![json!("a"), json!("b")]);
+
+        assert!(batch_results("m", 2, json!([response(0, json!("a"))])).is_err());
+        assert!(batch_results("m", 1, json!([response(0, Value::Null)])).is_err());
+        assert!(batch_results("m", 1, json!({ "error": { "code": -32600, "message": "batch not supported" } })).is_err());
+    }
+
+    #[test]
+    fn test_blocks_up_to() {
+        assert_eq!(blocks_up_to(100, 10, 200), 10);
+        assert_eq!(blocks_up_to(100, 10, 104), 5);
+        assert_eq!(blocks_up_to(100, 10, 100), 1);
+        assert_eq!(blocks_up_to(100, 10, 99), 0);
+        assert_eq!(blocks_up_to(0, 10, 0), 1);
+    }
+
+    #[test]
+    fn test_topic0_prescreen_keeps_a_chosen_level() {
+        let topics = BTreeSet::from(["0xddf2".to_string()]);
+        let mut default = HydrationConfig::default();
+        assert!(default.prescreen(&topics));
+        assert_eq!((default.level(), default.bloom_items.clone()), (Hydration::Bloom, vec!["0xddf2".to_string()]));
+
+        let mut full = HydrationConfig { hydration: Some(Hydration::Full), ..Default::default() };
+        assert!(!full.prescreen(&topics));
+        assert_eq!(full.level(), Hydration::Full);
+        // An explicit bloom level screens by the topics unless items were given
+        let mut bloom = HydrationConfig { hydration: Some(Hydration::Bloom), ..Default::default() };
+        assert!(!bloom.prescreen(&topics));
+        assert_eq!(bloom.bloom_items.len(), 1);
+        let mut headers = HydrationConfig { headers_only: true, ..Default::default() };
+        assert!(!headers.prescreen(&topics) && headers.bloom_items.is_empty());
+        assert!(!HydrationConfig::default().prescreen(&BTreeSet::new()));
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Share of blocks to index, written as `1%` or `0.01`. Whether a block is in the sample only
+// depends on its number and the seed, so the same sample comes back for any range or chunking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    rate: f64,
+}
+
+impl Sample {
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn includes(&self, block_number: u64, seed: u64) -> bool {
+        // splitmix64 finalizer: consecutive block numbers land far apart
+        let mut z = block_number ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.rate
+    }
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let rate = match text.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+            None => text.parse::<f64>(),
+        }
+        .map_err(|e| format!("Invalid sample {}: {}", text, e))?;
+        if !(rate > 0.0 && rate <= 1.0) {
+            return Err(format!("Sample {} must be above 0% and at most 100%", text));
+        }
+        Ok(Self { rate })
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.rate * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        assert_eq!("1%".parse::<Sample>().unwrap().rate(), 0.01);
+        assert_eq!("0.25".parse::<Sample>().unwrap().rate(), 0.25);
+        assert!("0%".parse::<Sample>().is_err());
+        assert!("150%".parse::<Sample>().is_err());
+        assert!("some".parse::<Sample>().is_err());
+
+        let sample: Sample = "1%".parse().unwrap();
+        let picked: Vec<u64> = (0..1_000_000).filter(|n| sample.includes(*n, 7)).collect();
+        assert!((9_000..11_000).contains(&picked.len()), "{} blocks", picked.len());
+        // Same seed, same blocks; another seed, other blocks
+        assert!(picked.iter().all(|n| sample.includes(*n, 7)));
+        assert_ne!(picked, (0..1_000_000).filter(|n| sample.includes(*n, 8)).collect::<Vec<_>>());
+        assert!((0..1000).all(|n| "100%".parse::<Sample>().unwrap().includes(n, 7)));
+    }
+}
@@ -0,0 +1,136 @@
+//! Cron schedules for `daemon`: five fields (minute, hour, day of month, month, day of week) in
+//! UTC, each `*`, a number, a range `a-b` or a comma-separated list of them, optionally stepped
+//! with `/n`. `*/5 * * * *` is every five minutes, `0 3 * * 1-5` 03:00 on weekdays. Days of the
+//! week run from 0 (Sunday) to 6, with 7 also Sunday. As in cron, when both day fields are
+//! restricted a day matching either one is due.
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    // Bit n set when value n matches
+    bits: u64,
+    // `*` (possibly stepped), for the day-of-month/day-of-week rule
+    any: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32) -> Result<Self> {
+        let mut bits = 0u64;
+        for item in text.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)),
+                None => (item, Some(1)),
+            };
+            let step = step.ok_or_else(|| anyhow::anyhow!("Invalid step in {}", item))?;
+            let number = |value: &str| value.parse::<u32>().ok().filter(|value| (min..=max).contains(value));
+            let (first, last) = match range.split_once('-') {
+                _ if range == "*" => (Some(min), Some(max)),
+                Some((first, last)) => (number(first), number(last)),
+                None if step > 1 => (number(range), Some(max)),
+                None => (number(range), number(range)),
+            };
+            let (Some(first), Some(last)) = (first, last) else {
+                return Err(anyhow::anyhow!("Invalid value {} (expected {} to {})", item, min, max));
+            };
+            for value in (first..=last).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Self { bits, any: text.starts_with('*') })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    text: String,
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow::anyhow!("Invalid schedule {:?}: expected five fields (minute hour day month weekday)", text));
+        };
+        let field = |name: &str, value: &str, min: u32, max: u32| {
+            Field::parse(value, min, max).map_err(|e| anyhow::anyhow!("Invalid schedule {:?}: {} field: {}", text, name, e))
+        };
+        let mut weekday = field("weekday", weekday, 0, 7)?;
+        // 7 is Sunday too
+        if weekday.matches(7) {
+            weekday.bits |= 1;
+        }
+        Ok(Self {
+            text: text.trim().to_string(),
+            minute: field("minute", minute, 0, 59)?,
+            hour: field("hour", hour, 0, 23)?,
+            day: field("day", day, 1, 31)?,
+            month: field("month", month, 1, 12)?,
+            weekday,
+        })
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Schedule {
+    fn is_due(&self, time: DateTime<Utc>) -> bool {
+        let day = self.day.matches(time.day());
+        let weekday = self.weekday.matches(time.weekday().num_days_from_sunday());
+        let day = match (self.day.any, self.weekday.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day && self.minute.matches(time.minute()) && self.hour.matches(time.hour()) && self.month.matches(time.month())
+    }
+
+    // The first minute strictly after `time` the schedule is due, None when it never is (e.g. 31 February)
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        // Every minute of four years covers any combination of day, month and weekday
+        (0..4 * 366 * 24 * 60).map(|minute| start + Duration::minutes(minute)).find(|time| self.is_due(*time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_next_after() {
+        let at = |day, hour, minute| Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap();
+        let next = |schedule: &str, time| schedule.parse::<Schedule>().unwrap().next_after(time).unwrap();
+        // 2024-01-01 was a Monday
+        assert_eq!(next("* * * * *", at(1, 10, 7) + Duration::seconds(30)), at(1, 10, 8));
+        assert_eq!(next("*/15 * * * *", at(1, 10, 7)), at(1, 10, 15));
+        assert_eq!(next("0 3 * * 1-5", at(5, 4, 0)), at(8, 3, 0));
+        assert_eq!(next("30 2,14 * * *", at(1, 2, 30)), at(1, 14, 30));
+        // Either day field when both are restricted: the 15th or a Sunday
+        assert_eq!(next("0 0 15 * 0", at(1, 0, 0)), at(7, 0, 0));
+        assert_eq!(next("0 0 * * 7", at(1, 0, 0)), at(7, 0, 0));
+
+        assert!("0 0 31 2 *".parse::<Schedule>().unwrap().next_after(at(1, 0, 0)).is_none());
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert_eq!("5/20 * * * *".parse::<Schedule>().unwrap().minute.bits, 1 << 5 | 1 << 25 | 1 << 45);
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use primitive_types::U256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::models::{SelectorStats, TransformedReceipt, TransformedTransaction};
+use crate::store;
+
+// Directory under RAW_DATA_PATH holding the dataset, one ndjson file per UTC day
+pub const DIR: &str = "selector_stats";
+
+#[derive(Default)]
+struct Totals<'a> {
+    signature: Option<&'a str>,
+    calls: u64,
+    failed_calls: u64,
+    callers: HashSet<&'a str>,
+    contracts: HashSet<&'a str>,
+    gas_used: u64,
+    value: U256,
+    first_block: u64,
+    last_block: u64,
+}
+
+// Aggregates calls carrying a selector per day and selector. Plain transfers and contract
+// creations have none and are left out. Unique counts need all of a day's transactions at once,
+// which is why this runs over indexed data rather than per batch.
+pub fn compute(transactions: &[TransformedTransaction], receipts: &[TransformedReceipt]) -> Vec<SelectorStats> {
+    let receipt_by_hash: HashMap<&str, &TransformedReceipt> =
+        receipts.iter().map(|r| (r.transaction_hash.as_str(), r)).collect();
+    let mut groups: BTreeMap<(NaiveDate, &str), Totals> = BTreeMap::new();
+    for tx in transactions {
+        let (Some(selector), Some(to)) = (tx.method_id.as_deref(), tx.to.as_deref()) else {
+            continue;
+        };
+        let receipt = receipt_by_hash.get(tx.hash.as_str());
+        let totals = groups.entry((tx.datetime.date_naive(), selector)).or_insert_with(|| Totals {
+            first_block: tx.block_number,
+            last_block: tx.block_number,
+            ..Default::default()
+        });
+        totals.signature = totals.signature.or(tx.method_signature.as_deref());
+        totals.calls += 1;
+        totals.failed_calls += receipt.is_some_and(|r| !r.status) as u64;
+        totals.callers.insert(&tx.from);
+        totals.contracts.insert(to);
+        totals.gas_used += receipt.map(|r| r.gas_used).unwrap_or_default();
+        totals.value = totals.value.saturating_add(tx.value_wei());
+        totals.first_block = totals.first_block.min(tx.block_number);
+        totals.last_block = totals.last_block.max(tx.block_number);
+    }
+    groups
+        .into_iter()
+        .map(|((day, selector), totals)| SelectorStats {
+            day,
+            selector: selector.to_string(),
+            signature: totals.signature.map(str::to_string),
+            calls: totals.calls,
+            failed_calls: totals.failed_calls,
+            unique_callers: totals.callers.len() as u64,
+            unique_contracts: totals.contracts.len() as u64,
+            gas_used: totals.gas_used,
+            value: totals.value.to_string(),
+            first_block: totals.first_block,
+            last_block: totals.last_block,
+        })
+        .collect()
+}
+
+fn day_path(raw_data_path: &str, day: NaiveDate) -> PathBuf {
+    Path::new(raw_data_path).join(DIR).join(format!("{}_{}.ndjson", DIR, day))
+}
+
+// Replaces the file of every day in `stats`, returning the files written
+pub fn write(raw_data_path: &str, stats: &[SelectorStats]) -> Result<Vec<PathBuf>> {
+    let mut days: BTreeMap<NaiveDate, Vec<u8>> = BTreeMap::new();
+    for row in stats {
+        let data = days.entry(row.day).or_default();
+        serde_json::to_writer(&mut *data, row)?;
+        data.push(b'\n');
+    }
+    store::ensure_directory(&format!("{}/{}", raw_data_path, DIR))?;
+    let mut written = Vec::new();
+    for (day, data) in days {
+        let path = day_path(raw_data_path, day);
+        let tmp = path.with_extension(format!("ndjson.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_selector_stats() {
+        let tx = |hash: &str, from: &str, to: Option<&str>, method_id: Option<&str>, value: u64, datetime: &str| TransformedTransaction {
+            from: from.to_string(),
+            hash: hash.to_string(),
+            method_id: method_id.map(str::to_string),
+            method_signature: Some("transfer(address,uint256)".to_string()),
+            to: to.map(str::to_string),
+            value: value.to_string(),
+            datetime: datetime.parse().unwrap(),
+            ..fixtures::transaction(hash.len() as u64, 0)
+        };
+        let receipt = |hash: &str, gas_used: u64, status: bool| TransformedReceipt {
+            gas_used,
+            status,
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(0, 0)
+        };
+        let day1 = "2024-01-01T10:00:00Z";
+        let transactions = vec![
+            tx("0x1", "0xa", Some("0xt1"), Some("0xa9059cbb"), 0, day1),
+            tx("0x22", "0xa", Some("0xt2"), Some("0xa9059cbb"), u64::MAX, day1),
+            tx("0x333", "0xb", Some("0xt1"), Some("0xa9059cbb"), u64::MAX, day1),
+            tx("0x4", "0xb", Some("0xt1"), Some("0xa9059cbb"), 0, "2024-01-02T00:00:00Z"),
+            tx("0x5", "0xc", Some("0xw"), None, 1, day1),
+            tx("0x6", "0xc", None, Some("0x60806040"), 0, day1),
+        ];
+        let receipts = vec![receipt("0x1", 50, true), receipt("0x22", 70, false), receipt("0x333", 30, true)];
+
+        let stats = compute(&transactions, &receipts);
+        assert_eq!(stats.len(), 2);
+        let first = &stats[0];
+        assert_eq!(first.day.to_string(), "2024-01-01");
+        assert_eq!((first.calls, first.failed_calls, first.unique_callers, first.unique_contracts), (3, 1, 2, 2));
+        assert_eq!(first.gas_used, 150);
+        assert_eq!(first.value, (2 * u64::MAX as u128).to_string());
+        assert_eq!((first.first_block, first.last_block), (3, 5));
+        assert_eq!(first.signature.as_deref(), Some("transfer(address,uint256)"));
+
+        let root = "test_selector_stats";
+        let _ = std::fs::remove_dir_all(root);
+        let written = write(root, &stats).unwrap();
+        assert_eq!(written.len(), 2);
+        let rows = store::read_records(&written[0]).unwrap();
+        assert_eq!(rows[0]["day"], "2024-01-01");
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
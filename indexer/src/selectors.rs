@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::LazyLock;
+
+use crate::trie::{encode_hex, keccak256};
+
+const BUNDLED_SIGNATURES: &str = include_str!("../data/signatures.txt");
+
+// Selector ("0xa9059cbb") -> signature ("transfer(address,uint256)"), bundled entries first,
+// then SELECTORS_PATH so local additions win on collisions
+static SELECTORS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    let mut selectors = parse_signatures(BUNDLED_SIGNATURES);
+    if let Ok(path) = env::var("SELECTORS_PATH") {
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let extra = parse_signatures(&contents);
+                log::info!("Loaded {} selectors from {}", extra.len(), path);
+                selectors.extend(extra);
+            },
+            Err(e) => log::warn!("Could not read SELECTORS_PATH {}: {}", path, e),
+        }
+    }
+    selectors
+});
+
+pub fn selector_of(signature: &str) -> String {
+    encode_hex(&keccak256(signature.as_bytes())[..4])
+}
+
+// Accepts one entry per line, either a bare signature or `<selector><tab|comma|space><signature>`
+// (the 4byte.directory export format); blank lines and `#` comments are skipped
+pub fn parse_signatures(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match line.split_once(['\t', ',', ' ']) {
+            Some((selector, signature)) if selector.starts_with("0x") && selector.len() == 10 => {
+                Some((selector.to_lowercase(), signature.trim().to_string()))
+            },
+            _ if line.contains('(') => Some((selector_of(line), line.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+// First four bytes of calldata as a lowercase selector, if there are four bytes
+pub fn method_id(input: &str) -> Option<String> {
+    input
+        .get(..10)
+        .filter(|prefix| prefix.starts_with("0x") && prefix[2..].chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+pub fn lookup(selector: &str) -> Option<String> {
+    SELECTORS.get(selector).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_selectors() {
+        assert_eq!(selector_of("transfer(address,uint256)"), "0xa9059cbb");
+        assert_eq!(lookup("0xa9059cbb").as_deref(), Some("transfer(address,uint256)"));
+        assert_eq!(lookup("0x095ea7b3").as_deref(), Some("approve(address,uint256)"));
+        assert_eq!(lookup("0xd0e30db0").as_deref(), Some("deposit()"));
+    }
+
+    #[test]
+    fn test_parse_signatures_and_method_id() {
+        let parsed = parse_signatures("# comment\n0xDEADBEEF,doSomething(uint256)\n\nfoo()\nnot a signature\n");
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["0xdeadbeef"], "doSomething(uint256)");
+        assert_eq!(parsed[&selector_of("foo()")], "foo()");
+
+        assert_eq!(method_id("0xA9059CBB000000").as_deref(), Some("0xa9059cbb"));
+        assert_eq!(method_id("0x"), None);
+        assert_eq!(method_id("0xzz059cbb"), None);
+    }
+}
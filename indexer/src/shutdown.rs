@@ -0,0 +1,70 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static LISTENING: AtomicBool = AtomicBool::new(false);
+static NOTIFY: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+    NOTIFY.notify_waiters();
+}
+
+pub async fn wait() {
+    loop {
+        let notified = NOTIFY.notified();
+        if is_requested() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+// Installs the SIGINT/SIGTERM handler once per process. The first signal asks the pipeline
+// to stop scheduling blocks and flush what it has; a second one exits immediately.
+pub fn listen() {
+    if LISTENING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async {
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                log::error!("Could not install SIGTERM handler: {}", e);
+                return;
+            },
+        };
+        loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+            if is_requested() {
+                log::error!("Second shutdown signal received, exiting without flushing");
+                std::process::exit(130);
+            }
+            log::warn!("Shutdown requested: finishing in-flight blocks and flushing output (signal again to force)");
+            request();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_returns_after_request() {
+        let waiter = tokio::spawn(wait());
+        tokio::task::yield_now().await;
+        request();
+        waiter.await.unwrap();
+        assert!(is_requested());
+    }
+}
@@ -0,0 +1,418 @@
+//! Google BigQuery through its REST API (streaming inserts), so no client library is linked. With
+//! `BIGQUERY_PROJECT` set, `index` streams every batch into one table per dataset of
+//! `BIGQUERY_DATASET`.
+//!
+//! The BigQuery dataset and its tables are created on first use from the ClickHouse columns, each
+//! table partitioned by day on `datetime` and clustered by block number. Wei amounts are `NUMERIC`,
+//! uint256 values `STRING`, string arrays `REPEATED STRING`, logs and the fields a dataset does not
+//! name (the RPC extras) `JSON`. Columns added to a dataset later are added to its table, which
+//! BigQuery allows for nullable columns; other schema changes are left to the operator.
+//!
+//! Requests are authorized with `BIGQUERY_ACCESS_TOKEN` when set (e.g. `gcloud auth
+//! print-access-token`, which expires after an hour), otherwise with the service account of the
+//! VM, Cloud Run service or GKE workload through the metadata server. `BIGQUERY_API_URL` points the
+//! sink at an emulator instead, which needs no token.
+//!
+//! Streaming inserts append: every row carries an `insertId` derived from its content, so BigQuery
+//! drops the copies a replayed batch sends within about a minute, but rows of a re-indexed range
+//! are stored again. The address dimension is upserted elsewhere and not streamed.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::avro::WEI_COLUMNS;
+use crate::config::env_or;
+use crate::models::{
+    Balance, BlockDataUsage, BridgeMessage, ContractState, DataQualityIssue, EventLog, NftTransfer, OrderingAnalysis, TokenTransfer, TransformedBlock, TransformedContract,
+    TransformedReceipt, TransformedTransaction,
+};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::clickhouse::{columns_for, key_columns, EXTRAS_COLUMN, TABLES};
+use crate::sinks::Sink;
+use crate::store::{self, OutputConfig, Record, WritePlan};
+use crate::trie::encode_hex;
+
+const API_URL: &str = "https://bigquery.googleapis.com/bigquery/v2";
+const METADATA_TOKEN_URL: &str = "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// Metadata server tokens are refreshed this long before they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct BigQueryConfig {
+    pub project: String,
+    pub dataset: String,
+    // Location the dataset is created in
+    pub location: String,
+    pub api_url: String,
+    // None: ask the metadata server, or send no token to an emulator
+    pub access_token: Option<String>,
+    // Rows per insertAll request
+    pub batch_rows: usize,
+}
+
+impl BigQueryConfig {
+    // The sink is only enabled when BIGQUERY_PROJECT is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let project = match std::env::var("BIGQUERY_PROJECT") {
+            Ok(project) if !project.trim().is_empty() => project.trim().to_string(),
+            _ => return Ok(None),
+        };
+        Ok(Some(Self {
+            project,
+            dataset: env_or("BIGQUERY_DATASET", "sweet_potato".to_string())?,
+            location: env_or("BIGQUERY_LOCATION", "US".to_string())?,
+            api_url: env_or("BIGQUERY_API_URL", API_URL.to_string())?.trim_end_matches('/').to_string(),
+            access_token: std::env::var("BIGQUERY_ACCESS_TOKEN").ok().filter(|token| !token.trim().is_empty()),
+            batch_rows: env_or("BIGQUERY_BATCH_ROWS", 500usize)?.max(1),
+        }))
+    }
+
+    fn dataset_url(&self) -> String {
+        format!("{}/projects/{}/datasets/{}", self.api_url, self.project, self.dataset)
+    }
+}
+
+// Column holding the block number of a dataset's rows, the clustering column
+fn block_column(dataset: &str) -> &'static str {
+    match dataset {
+        "blocks" => "number",
+        _ => "block_number",
+    }
+}
+
+fn field_type(dataset: &str, name: &str, clickhouse_type: &str) -> (&'static str, &'static str) {
+    let inner = clickhouse_type.strip_prefix("Nullable(").and_then(|t| t.strip_suffix(')')).unwrap_or(clickhouse_type);
+    match inner {
+        // Receipt logs are objects with nested topics
+        _ if dataset == "receipts" && name == "logs" => ("JSON", "NULLABLE"),
+        t if t.starts_with("Array(") => ("STRING", "REPEATED"),
+        "UInt64" if WEI_COLUMNS.contains(&name) => ("NUMERIC", "NULLABLE"),
+        "UInt64" | "UInt32" | "UInt8" => ("INT64", "NULLABLE"),
+        "Bool" => ("BOOL", "NULLABLE"),
+        "Float64" => ("FLOAT64", "NULLABLE"),
+        "DateTime" => ("TIMESTAMP", "NULLABLE"),
+        // uint256 values do not fit BIGNUMERIC
+        _ => ("STRING", "NULLABLE"),
+    }
+}
+
+// Table schema of a dataset: its ClickHouse columns, then `extras`
+pub fn schema_fields(dataset: &str) -> Vec<Value> {
+    columns_for(dataset)
+        .iter()
+        .map(|(name, clickhouse_type)| {
+            let (kind, mode) = field_type(dataset, name, clickhouse_type);
+            json!({ "name": name, "type": kind, "mode": mode })
+        })
+        .chain([json!({ "name": EXTRAS_COLUMN, "type": "JSON", "mode": "NULLABLE" })])
+        .collect()
+}
+
+pub fn table_resource(config: &BigQueryConfig, dataset: &str) -> Value {
+    let mut table = json!({
+        "tableReference": { "projectId": config.project, "datasetId": config.dataset, "tableId": dataset },
+        "schema": { "fields": schema_fields(dataset) },
+    });
+    let columns = columns_for(dataset);
+    if columns.iter().any(|(name, _)| *name == "datetime") {
+        table["timePartitioning"] = json!({ "type": "DAY", "field": "datetime" });
+    }
+    if columns.iter().any(|(name, _)| *name == block_column(dataset)) {
+        table["clustering"] = json!({ "fields": [block_column(dataset)] });
+    }
+    // BigQuery does not enforce the key, but declares it for joins and the query planner
+    if !key_columns(dataset).is_empty() {
+        table["tableConstraints"] = json!({ "primaryKey": { "columns": key_columns(dataset) } });
+    }
+    table
+}
+
+// The schema fields a table lacks, in dataset order
+pub fn missing_fields(dataset: &str, table: &Value) -> Vec<Value> {
+    let existing: Vec<&str> = table["schema"]["fields"].as_array().into_iter().flatten().filter_map(|field| field["name"].as_str()).collect();
+    schema_fields(dataset).into_iter().filter(|field| !existing.contains(&field["name"].as_str().unwrap_or_default())).collect()
+}
+
+// A record as an insertAll row: named columns converted to their field types, everything else in `extras`
+pub fn row<T: Record>(record: &T) -> Result<Value> {
+    let Value::Object(mut fields) = serde_json::to_value(record)? else {
+        return Err(anyhow::anyhow!("{} record is not an object", T::DATASET));
+    };
+    let mut row = Map::new();
+    for (name, clickhouse_type) in columns_for(T::DATASET) {
+        let value = fields.remove(*name).unwrap_or(Value::Null);
+        let value = match (field_type(T::DATASET, name, clickhouse_type), value) {
+            // JSON columns take the document as a string
+            (("JSON", _), Value::Null) => Value::Null,
+            (("JSON", _), value) => Value::String(value.to_string()),
+            (("STRING", "REPEATED"), Value::Null) => json!([]),
+            // NUMERIC from a string keeps every digit
+            (("NUMERIC", _), Value::Number(number)) => Value::String(number.to_string()),
+            (_, value) => value,
+        };
+        row.insert(name.to_string(), value);
+    }
+    // `extra` is the one unnamed field of the core records; keep whatever else a record carries too
+    if let Some(Value::Object(extra)) = fields.remove("extra") {
+        fields.extend(extra);
+    }
+    let extras = (!fields.is_empty()).then(|| Value::String(Value::Object(fields).to_string()));
+    row.insert(EXTRAS_COLUMN.to_string(), extras.unwrap_or(Value::Null));
+    Ok(Value::Object(row))
+}
+
+// insertAll request bodies of the records, `batch_rows` rows each. A row's insertId is a hash of
+// its dataset and content, so resending it within the deduplication window is dropped.
+pub fn insert_bodies<T: Record>(records: &[T], batch_rows: usize) -> Result<Vec<Value>> {
+    let rows: Vec<Value> = records
+        .iter()
+        .map(|record| {
+            let row = row(record)?;
+            let digest = Sha256::new().chain_update(T::DATASET).chain_update(row.to_string()).finalize();
+            Ok(json!({ "insertId": &encode_hex(&digest)[2..34], "json": row }))
+        })
+        .collect::<Result<_>>()?;
+    Ok(rows.chunks(batch_rows).map(|rows| json!({ "kind": "bigquery#tableDataInsertAllRequest", "rows": rows })).collect())
+}
+
+// The first row error of an insertAll answer, if any
+pub fn insert_error(dataset: &str, answer: &Value) -> Option<anyhow::Error> {
+    let failed = answer["insertErrors"].as_array()?;
+    let first = failed.first()?;
+    let reason = &first["errors"][0];
+    Some(anyhow::anyhow!(
+        "BigQuery rejected {} rows of {}, first at {}: {} ({})",
+        failed.len(),
+        dataset,
+        first["index"],
+        reason["message"].as_str().unwrap_or_default(),
+        reason["reason"].as_str().unwrap_or_default()
+    ))
+}
+
+pub struct BigQuerySink {
+    config: BigQueryConfig,
+    client: reqwest::Client,
+    // Metadata server token and when it expires
+    token: Mutex<Option<(String, Instant)>>,
+    tables: OnceCell<()>,
+}
+
+impl BigQuerySink {
+    pub fn new(config: BigQueryConfig) -> Self {
+        Self { config, client: reqwest::Client::new(), token: Mutex::new(None), tables: OnceCell::new() }
+    }
+
+    async fn access_token(&self) -> Result<Option<String>> {
+        if let Some(token) = &self.config.access_token {
+            return Ok(Some(token.clone()));
+        }
+        if self.config.api_url != API_URL {
+            return Ok(None);
+        }
+        let mut cached = self.token.lock().await;
+        if let Some((token, _)) = cached.as_ref().filter(|(_, expires)| Instant::now() + TOKEN_MARGIN < *expires) {
+            return Ok(Some(token.clone()));
+        }
+        let answer: Value = self
+            .client
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("No BIGQUERY_ACCESS_TOKEN set and the metadata server is unreachable: {}", e))?
+            .error_for_status()?
+            .json()
+            .await?;
+        let token = answer["access_token"].as_str().ok_or_else(|| anyhow::anyhow!("Metadata server returned no access token"))?.to_string();
+        let expires = Instant::now() + Duration::from_secs(answer["expires_in"].as_u64().unwrap_or(3600));
+        *cached = Some((token.clone(), expires));
+        Ok(Some(token))
+    }
+
+    // Sends a request and returns the status with the JSON answer (null when empty)
+    async fn call(&self, method: reqwest::Method, url: &str, body: Option<&Value>) -> Result<(reqwest::StatusCode, Value)> {
+        let mut request = self.client.request(method, url).timeout(REQUEST_TIMEOUT);
+        if let Some(token) = self.access_token().await? {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(|e| anyhow::anyhow!("BigQuery request failed: {}", e))?;
+        let status = response.status();
+        let text = response.text().await?;
+        Ok((status, serde_json::from_str(&text).unwrap_or(Value::Null)))
+    }
+
+    async fn expect(&self, method: reqwest::Method, url: &str, body: Option<&Value>) -> Result<Value> {
+        let (status, answer) = self.call(method, url, body).await?;
+        if !status.is_success() {
+            let message = answer["error"]["message"].as_str().map_or_else(|| answer.to_string(), str::to_string);
+            return Err(anyhow::anyhow!("BigQuery answered {}: {}", status, message));
+        }
+        Ok(answer)
+    }
+
+    // Creates the dataset and every table it lacks, and adds new columns to existing tables
+    async fn prepare(&self) -> Result<()> {
+        let dataset_url = self.config.dataset_url();
+        let (status, _) = self.call(reqwest::Method::GET, &dataset_url, None).await?;
+        if status == reqwest::StatusCode::NOT_FOUND {
+            let dataset = json!({
+                "datasetReference": { "projectId": self.config.project, "datasetId": self.config.dataset },
+                "location": self.config.location,
+            });
+            let url = format!("{}/projects/{}/datasets", self.config.api_url, self.config.project);
+            self.expect(reqwest::Method::POST, &url, Some(&dataset)).await?;
+            log::info!("Created BigQuery dataset {}.{}", self.config.project, self.config.dataset);
+        }
+        for (dataset, _) in TABLES.iter().filter(|(dataset, _)| *dataset != "addresses") {
+            let url = format!("{}/tables/{}", dataset_url, dataset);
+            let (status, table) = self.call(reqwest::Method::GET, &url, None).await?;
+            if status == reqwest::StatusCode::NOT_FOUND {
+                self.expect(reqwest::Method::POST, &format!("{}/tables", dataset_url), Some(&table_resource(&self.config, dataset))).await?;
+                log::info!("Created BigQuery table {}", dataset);
+                continue;
+            }
+            let missing = missing_fields(dataset, &table);
+            if missing.is_empty() {
+                continue;
+            }
+            let names: Vec<&str> = missing.iter().filter_map(|field| field["name"].as_str()).collect();
+            log::info!("Adding columns {} to BigQuery table {}", names.join(", "), dataset);
+            let mut fields = table["schema"]["fields"].as_array().cloned().unwrap_or_default();
+            fields.extend(missing);
+            self.expect(reqwest::Method::PATCH, &url, Some(&json!({ "schema": { "fields": fields } }))).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_records<T: Record>(&self, records: &[T]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.tables.get_or_try_init(|| self.prepare()).await?;
+        let url = format!("{}/tables/{}/insertAll", self.config.dataset_url(), T::DATASET);
+        for body in insert_bodies(records, self.config.batch_rows)? {
+            let answer = self.expect(reqwest::Method::POST, &url, Some(&body)).await?;
+            if let Some(error) = insert_error(T::DATASET, &answer) {
+                return Err(error);
+            }
+        }
+        log::debug!("Streamed {} {} rows to BigQuery", records.len(), T::DATASET);
+        Ok(())
+    }
+
+    async fn insert(&self, batch: &TransformedBatch, output: OutputConfig) -> Result<()> {
+        self.insert_records::<TransformedBlock>(&batch.blocks).await?;
+        self.insert_records::<TransformedTransaction>(&batch.transactions).await?;
+        self.insert_records::<TransformedReceipt>(&store::written_receipts(output, &batch.receipts)).await?;
+        self.insert_records::<EventLog>(&batch.logs).await?;
+        self.insert_records::<TransformedContract>(&batch.contracts).await?;
+        self.insert_records::<BlockDataUsage>(&batch.data_usage).await?;
+        self.insert_records::<OrderingAnalysis>(&batch.ordering_analysis).await?;
+        self.insert_records::<NftTransfer>(&batch.nft_transfers).await?;
+        self.insert_records::<TokenTransfer>(&batch.token_transfers).await?;
+        self.insert_records::<BridgeMessage>(&batch.bridge_messages).await?;
+        self.insert_records::<Balance>(&batch.balances).await?;
+        self.insert_records::<ContractState>(&batch.contract_state).await?;
+        self.insert_records::<DataQualityIssue>(&batch.data_quality).await
+    }
+}
+
+pub fn sink_from_env(_output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    Ok(BigQueryConfig::from_env()?.map(|config| Box::new(BigQuerySink::new(config)) as Box<dyn Sink>))
+}
+
+impl Sink for BigQuerySink {
+    fn name(&self) -> &'static str {
+        "bigquery"
+    }
+
+    fn describe(&self) -> String {
+        format!("BigQuery {}.{}", self.config.project, self.config.dataset)
+    }
+
+    fn connect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { self.tables.get_or_try_init(|| self.prepare()).await.map(|_| ()) })
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(blocks))
+    }
+
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(transactions))
+    }
+
+    fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(receipts))
+    }
+
+    // Every dataset but the address dimension
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert(batch, output))
+    }
+
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        let lines = rows.iter().filter(|plan| plan.records > 0 && plan.dataset != "addresses");
+        let lines = lines.map(|plan| format!("{} table {}: {} rows", self.describe(), plan.dataset, plan.records)).collect();
+        Box::pin(async { Ok(lines) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_schema_and_rows() {
+        let config = BigQueryConfig {
+            project: "chain-data".to_string(),
+            dataset: "linea".to_string(),
+            location: "EU".to_string(),
+            api_url: API_URL.to_string(),
+            access_token: None,
+            batch_rows: 2,
+        };
+        let table = table_resource(&config, "blocks");
+        assert_eq!(table["tableReference"], json!({ "projectId": "chain-data", "datasetId": "linea", "tableId": "blocks" }));
+        assert_eq!((&table["timePartitioning"]["field"], &table["clustering"]["fields"][0]), (&json!("datetime"), &json!("number")));
+        assert_eq!(table_resource(&config, "nft_transfers")["tableConstraints"], json!({ "primaryKey": { "columns": ["id", "batch_index"] } }));
+        let fields = schema_fields("blocks");
+        let field = |name: &str| fields.iter().find(|field| field["name"] == name).cloned().unwrap();
+        assert_eq!(field("base_fee_per_gas"), json!({ "name": "base_fee_per_gas", "type": "NUMERIC", "mode": "NULLABLE" }));
+        assert_eq!((field("uncles")["mode"].as_str(), field("datetime")["type"].as_str()), (Some("REPEATED"), Some("TIMESTAMP")));
+        assert_eq!(schema_fields("receipts").iter().find(|field| field["name"] == "logs").unwrap()["type"], "JSON");
+        let existing = json!({ "schema": { "fields": fields[..fields.len() - 2] } });
+        let missing: Vec<Value> = missing_fields("blocks", &existing).iter().map(|field| field["name"].clone()).collect();
+        assert_eq!(missing, vec![fields[fields.len() - 2]["name"].clone(), json!("extras")]);
+
+        let block = |number: u64| TransformedBlock {
+            base_fee_per_gas: Some(7),
+            transaction_hashes: vec!["0x1".to_string()],
+            extra: json!({ "l1BatchNumber": "0x2" }).as_object().unwrap().clone(),
+            ..fixtures::block(number)
+        };
+        let row = row(&block(7)).unwrap();
+        assert_eq!((&row["number"], &row["base_fee_per_gas"], &row["transaction_hashes"]), (&json!(7), &json!("7"), &json!(["0x1"])));
+        assert_eq!(row["extras"], json!("{\"l1BatchNumber\":\"0x2\"}"));
+
+        let bodies = insert_bodies(&[block(1), block(2), block(1)], config.batch_rows).unwrap();
+        assert_eq!(bodies.len(), 2);
+        let ids: Vec<&str> = bodies.iter().flat_map(|body| body["rows"].as_array().unwrap()).map(|row| row["insertId"].as_str().unwrap()).collect();
+        assert_eq!((ids[0].len(), ids[0] == ids[2], ids[0] == ids[1]), (32, true, false));
+
+        let answer = json!({ "insertErrors": [{ "index": 3, "errors": [{ "reason": "invalid", "message": "no such field" }] }] });
+        assert_eq!(insert_error("blocks", &answer).unwrap().to_string(), "BigQuery rejected 1 rows of blocks, first at 3: no such field (invalid)");
+        assert!(insert_error("blocks", &json!({ "kind": "bigquery#tableDataInsertAllResponse" })).is_none());
+    }
+}
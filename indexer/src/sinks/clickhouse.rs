@@ -0,0 +1,853 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tokio::sync::OnceCell;
+
+use crate::activity;
+use crate::clock;
+use crate::config::env_or;
+use crate::faults;
+use crate::migrate::{self, Migration};
+use crate::models::{ActivityRole, AddressActivity, TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::Sink;
+use crate::store::{self, OutputConfig, Partitioning, Record, WritePlan, SCHEMA_VERSION};
+
+// Column that absorbs fields the live table has no column for
+pub const EXTRAS_COLUMN: &str = "extras";
+
+pub const BLOCK_COLUMNS: &[(&str, &str)] = &[
+    ("base_fee_per_gas", "Nullable(UInt64)"),
+    ("difficulty", "UInt64"),
+    ("extra_data", "String"),
+    ("gas_limit", "UInt64"),
+    ("gas_used", "UInt64"),
+    ("hash", "String"),
+    ("logs_bloom", "String"),
+    ("miner", "String"),
+    ("mix_hash", "String"),
+    ("nonce", "String"),
+    ("number", "UInt64"),
+    ("parent_hash", "String"),
+    ("receipts_root", "String"),
+    ("sha3_uncles", "String"),
+    ("size", "UInt64"),
+    ("state_root", "String"),
+    ("datetime", "DateTime"),
+    ("total_difficulty", "UInt64"),
+    ("transaction_hashes", "Array(String)"),
+    ("transactions_root", "String"),
+    ("uncles", "Array(String)"),
+    ("total_fees", "Nullable(UInt256)"),
+    ("burnt_fees", "Nullable(UInt256)"),
+    ("base_fee_per_gas_gwei", "Nullable(String)"),
+    ("total_fees_ether", "Nullable(String)"),
+    ("burnt_fees_ether", "Nullable(String)"),
+];
+
+pub const TRANSACTION_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("block_hash", "String"),
+    ("block_number", "UInt64"),
+    ("chain_id", "UInt64"),
+    ("from", "String"),
+    ("gas", "UInt64"),
+    ("gas_price", "UInt64"),
+    ("hash", "String"),
+    ("input", "String"),
+    ("method_id", "Nullable(String)"),
+    ("method_signature", "Nullable(String)"),
+    ("nonce", "UInt64"),
+    ("r", "String"),
+    ("s", "String"),
+    ("to", "Nullable(String)"),
+    ("transaction_index", "UInt64"),
+    ("tx_type", "UInt64"),
+    ("v", "String"),
+    ("value", "UInt256"),
+    ("status", "Nullable(Bool)"),
+    ("gas_used", "Nullable(UInt64)"),
+    ("effective_gas_price", "Nullable(UInt64)"),
+    ("contract_address", "Nullable(String)"),
+    ("fee", "Nullable(UInt256)"),
+    ("burnt_fee", "Nullable(UInt256)"),
+    ("priority_fee", "Nullable(UInt256)"),
+    ("value_ether", "Nullable(String)"),
+    ("gas_price_gwei", "Nullable(String)"),
+    ("effective_gas_price_gwei", "Nullable(String)"),
+    ("fee_ether", "Nullable(String)"),
+    ("burnt_fee_ether", "Nullable(String)"),
+    ("priority_fee_ether", "Nullable(String)"),
+    ("datetime", "DateTime"),
+];
+
+pub const RECEIPT_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("block_hash", "String"),
+    ("block_number", "UInt64"),
+    ("contract_address", "Nullable(String)"),
+    ("cumulative_gas_used", "UInt64"),
+    ("effective_gas_price", "UInt64"),
+    ("from", "String"),
+    ("gas_used", "UInt64"),
+    ("logs", "Array(String)"),
+    ("logs_bloom", "String"),
+    ("status", "Bool"),
+    ("to", "Nullable(String)"),
+    ("transaction_hash", "String"),
+    ("transaction_index", "UInt64"),
+    ("tx_type", "UInt64"),
+    ("datetime", "DateTime"),
+];
+
+pub const CONTRACT_COLUMNS: &[(&str, &str)] = &[
+    ("address", "String"),
+    ("creator", "String"),
+    ("transaction_hash", "String"),
+    ("block_number", "UInt64"),
+    ("init_code_hash", "String"),
+    ("datetime", "DateTime"),
+];
+
+pub const DATA_USAGE_COLUMNS: &[(&str, &str)] = &[
+    ("block_number", "UInt64"),
+    ("datetime", "DateTime"),
+    ("size", "UInt64"),
+    ("gas_used", "UInt64"),
+    ("transaction_count", "UInt64"),
+    ("calldata_bytes", "UInt64"),
+    ("calldata_zero_bytes", "UInt64"),
+    ("calldata_nonzero_bytes", "UInt64"),
+    ("calldata_gas", "UInt64"),
+    ("blob_transactions", "UInt64"),
+    ("blob_count", "UInt64"),
+    ("blob_gas_used", "Nullable(UInt64)"),
+    ("excess_blob_gas", "Nullable(UInt64)"),
+];
+
+pub const ORDERING_ANALYSIS_COLUMNS: &[(&str, &str)] = &[
+    ("block_number", "UInt64"),
+    ("datetime", "DateTime"),
+    ("transaction_count", "UInt64"),
+    ("base_fee_per_gas", "Nullable(UInt64)"),
+    ("min_priority_fee", "Nullable(UInt64)"),
+    ("max_priority_fee", "Nullable(UInt64)"),
+    ("priority_fee_inversions", "UInt64"),
+    ("priority_fee_adherence", "Nullable(Float64)"),
+    ("first_seen_count", "UInt64"),
+    ("first_seen_inversions", "UInt64"),
+    ("first_seen_adherence", "Nullable(Float64)"),
+];
+
+pub const LOG_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("block_number", "UInt64"),
+    ("block_hash", "String"),
+    ("transaction_hash", "String"),
+    ("transaction_index", "UInt64"),
+    ("log_index", "UInt64"),
+    ("address", "String"),
+    ("topic0", "Nullable(String)"),
+    ("topic1", "Nullable(String)"),
+    ("topic2", "Nullable(String)"),
+    ("topic3", "Nullable(String)"),
+    ("data", "String"),
+    ("event_signature", "Nullable(String)"),
+    ("removed", "Bool"),
+    ("datetime", "DateTime"),
+];
+
+pub const NFT_TRANSFER_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("token_address", "String"),
+    ("token_id", "UInt256"),
+    ("from", "String"),
+    ("to", "String"),
+    ("amount", "UInt256"),
+    ("standard", "String"),
+    ("operator", "Nullable(String)"),
+    ("block_number", "UInt64"),
+    ("transaction_hash", "String"),
+    ("log_index", "UInt64"),
+    ("batch_index", "UInt64"),
+    ("datetime", "DateTime"),
+];
+
+pub const TOKEN_TRANSFER_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("token_address", "String"),
+    ("from", "String"),
+    ("to", "String"),
+    ("value", "UInt256"),
+    ("token_symbol", "Nullable(String)"),
+    ("token_name", "Nullable(String)"),
+    ("token_decimals", "Nullable(UInt8)"),
+    ("value_scaled", "Nullable(String)"),
+    ("spam", "Bool"),
+    ("spam_reasons", "Array(String)"),
+    ("block_number", "UInt64"),
+    ("transaction_hash", "String"),
+    ("log_index", "UInt64"),
+    ("datetime", "DateTime"),
+];
+
+pub const BRIDGE_MESSAGE_COLUMNS: &[(&str, &str)] = &[
+    ("id", "UInt64"),
+    ("block_number", "UInt64"),
+    ("block_hash", "String"),
+    ("transaction_hash", "String"),
+    ("transaction_index", "UInt64"),
+    ("log_index", "UInt64"),
+    ("contract_address", "String"),
+    ("event", "String"),
+    ("direction", "String"),
+    ("message_hash", "String"),
+    ("sender", "Nullable(String)"),
+    ("recipient", "Nullable(String)"),
+    ("fee", "Nullable(UInt256)"),
+    ("value", "Nullable(UInt256)"),
+    ("nonce", "Nullable(UInt256)"),
+    ("calldata", "Nullable(String)"),
+    ("datetime", "DateTime"),
+];
+
+pub const BALANCE_COLUMNS: &[(&str, &str)] = &[
+    ("address", "String"),
+    ("block_number", "UInt64"),
+    ("balance", "UInt256"),
+    ("datetime", "DateTime"),
+];
+
+pub const CONTRACT_STATE_COLUMNS: &[(&str, &str)] = &[
+    ("name", "String"),
+    ("contract", "String"),
+    ("function", "String"),
+    ("block_number", "UInt64"),
+    ("values", "Array(String)"),
+    ("error", "Nullable(String)"),
+    ("datetime", "DateTime"),
+];
+
+pub const ADDRESS_ACTIVITY_COLUMNS: &[(&str, &str)] = &[
+    ("address", "String"),
+    ("block_number", "UInt64"),
+    ("transaction_hash", "String"),
+    ("role", "String"),
+];
+
+pub const ADDRESS_COLUMNS: &[(&str, &str)] = &[
+    ("address", "String"),
+    ("is_contract", "Bool"),
+    ("is_token", "Bool"),
+    ("label", "Nullable(String)"),
+    ("first_seen_block", "UInt64"),
+    ("first_seen_at", "DateTime"),
+    ("updated_block", "UInt64"),
+    ("datetime", "DateTime"),
+];
+
+pub const DATA_QUALITY_COLUMNS: &[(&str, &str)] = &[
+    ("block_number", "UInt64"),
+    ("check", "String"),
+    ("transaction_hash", "Nullable(String)"),
+    ("expected", "String"),
+    ("actual", "String"),
+    ("datetime", "DateTime"),
+];
+
+// One row per version the database was stamped or migrated to
+const SCHEMA_VERSION_TABLE: &str = "schema_version";
+
+pub const TABLES: [(&str, &[(&str, &str)]); 15] = [
+    ("blocks", BLOCK_COLUMNS),
+    ("transactions", TRANSACTION_COLUMNS),
+    ("receipts", RECEIPT_COLUMNS),
+    ("logs", LOG_COLUMNS),
+    ("contracts", CONTRACT_COLUMNS),
+    ("data_usage", DATA_USAGE_COLUMNS),
+    ("ordering_analysis", ORDERING_ANALYSIS_COLUMNS),
+    ("nft_transfers", NFT_TRANSFER_COLUMNS),
+    ("token_transfers", TOKEN_TRANSFER_COLUMNS),
+    ("bridge_messages", BRIDGE_MESSAGE_COLUMNS),
+    ("balances", BALANCE_COLUMNS),
+    ("contract_state", CONTRACT_STATE_COLUMNS),
+    ("address_activity", ADDRESS_ACTIVITY_COLUMNS),
+    ("addresses", ADDRESS_COLUMNS),
+    ("data_quality", DATA_QUALITY_COLUMNS),
+];
+
+// Column definitions of a dataset, empty for unknown datasets
+pub fn columns_for(dataset: &str) -> &'static [(&'static str, &'static str)] {
+    TABLES.iter().find(|(table, _)| *table == dataset).map(|(_, columns)| *columns).unwrap_or_default()
+}
+
+// Columns that identify a row of a dataset by its surrogate key, empty for datasets without one.
+// NFT rows decoded from one TransferBatch share the id of their log and differ in batch_index.
+pub fn key_columns(dataset: &str) -> &'static [&'static str] {
+    match dataset {
+        "transactions" | "receipts" | "logs" | "token_transfers" | "bridge_messages" => &["id"],
+        "nft_transfers" => &["id", "batch_index"],
+        _ => &[],
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClickHouseConfig {
+    pub url: String,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    // Allow `ALTER TABLE ... ADD COLUMN` for fields the table is missing
+    pub auto_add_columns: bool,
+    // PARTITION BY of tables the sink creates; existing tables keep theirs
+    pub partition: Partitioning,
+    pub partition_size: u64,
+}
+
+impl ClickHouseConfig {
+    // The sink is only enabled when CLICKHOUSE_URL is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("CLICKHOUSE_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().trim_end_matches('/').to_string(),
+            _ => return Ok(None),
+        };
+        Ok(Some(ClickHouseConfig {
+            url,
+            user: env_or("CLICKHOUSE_USER", "default".to_string())?,
+            password: env_or("CLICKHOUSE_PASSWORD", String::new())?,
+            database: env_or("CLICKHOUSE_DB", "raw".to_string())?,
+            auto_add_columns: env_or("CLICKHOUSE_AUTO_ADD_COLUMNS", false)?,
+            partition: Partitioning::Flat,
+            partition_size: 10000,
+        }))
+    }
+
+    pub fn with_partitioning(self, partition: Partitioning, partition_size: u64) -> Self {
+        Self { partition, partition_size, ..self }
+    }
+
+    // PARTITION BY clause for a new table with these columns
+    fn partition_by(&self, block_column: &str, columns: &[(&str, &str)]) -> Option<String> {
+        match self.partition {
+            Partitioning::Flat => None,
+            Partitioning::BlockRange => Some(format!("intDiv({}, {})", block_column, self.partition_size)),
+            // address_activity has no datetime and stays unpartitioned
+            Partitioning::Date => columns.iter().any(|(name, _)| *name == "datetime").then(|| "toDate(datetime)".to_string()),
+        }
+    }
+}
+
+// How rows for one table are shaped after comparing our schema to the live one
+#[derive(Debug, Default, PartialEq)]
+pub struct TablePlan {
+    pub columns_to_add: Vec<(String, String)>,
+    pub fields_to_extras: Vec<String>,
+    pub fields_dropped: Vec<String>,
+    pub unexpected_columns: Vec<String>,
+    pub has_extras: bool,
+}
+
+impl TablePlan {
+    // How the table's drift would be handled, for dry runs
+    pub fn summary(&self) -> String {
+        let mut changes = Vec::new();
+        if !self.columns_to_add.is_empty() {
+            changes.push(format!("would add columns {}", self.columns_to_add.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")));
+        }
+        if !self.fields_to_extras.is_empty() {
+            changes.push(format!("{} to extras", self.fields_to_extras.join(", ")));
+        }
+        if !self.fields_dropped.is_empty() {
+            changes.push(format!("would drop {}", self.fields_dropped.join(", ")));
+        }
+        if !self.unexpected_columns.is_empty() {
+            changes.push(format!("unexpected columns {}", self.unexpected_columns.join(", ")));
+        }
+        match changes.is_empty() {
+            true => "schema matches".to_string(),
+            false => changes.join("; "),
+        }
+    }
+}
+
+pub fn plan_table(
+    expected: &[(&str, &str)],
+    actual: &BTreeMap<String, String>,
+    auto_add_columns: bool,
+) -> TablePlan {
+    let has_extras = actual.contains_key(EXTRAS_COLUMN);
+    let mut plan = TablePlan { has_extras, ..Default::default() };
+
+    for (name, column_type) in expected {
+        if actual.contains_key(*name) {
+            continue;
+        }
+        if auto_add_columns {
+            let nullable = if column_type.starts_with("Nullable(") || column_type.starts_with("Array(") {
+                column_type.to_string()
+            } else {
+                format!("Nullable({})", column_type)
+            };
+            plan.columns_to_add.push((name.to_string(), nullable));
+        } else if has_extras {
+            plan.fields_to_extras.push(name.to_string());
+        } else {
+            plan.fields_dropped.push(name.to_string());
+        }
+    }
+
+    for name in actual.keys() {
+        if name != EXTRAS_COLUMN && !expected.iter().any(|(expected_name, _)| expected_name == name) {
+            plan.unexpected_columns.push(name.clone());
+        }
+    }
+
+    plan
+}
+
+// Moves fields without a matching column, and unmapped RPC fields from `extra`, into the
+// extras column (or drops them)
+pub fn shape_row(mut row: Map<String, Value>, plan: &TablePlan) -> Map<String, Value> {
+    let mut extras = match row.remove("extra") {
+        Some(Value::Object(extra)) if plan.has_extras => extra,
+        _ => Map::new(),
+    };
+    for field in &plan.fields_to_extras {
+        if let Some(value) = row.remove(field) {
+            extras.insert(field.clone(), value);
+        }
+    }
+    for field in &plan.fields_dropped {
+        row.remove(field);
+    }
+    if !plan.fields_to_extras.is_empty() || !extras.is_empty() {
+        row.insert(EXTRAS_COLUMN.to_string(), Value::String(Value::Object(extras).to_string()));
+    }
+    row
+}
+
+pub struct ClickHouseSink {
+    client: reqwest::Client,
+    config: ClickHouseConfig,
+    plans: HashMap<&'static str, TablePlan>,
+}
+
+impl ClickHouseSink {
+    // Creates missing tables, inspects the live schema and reconciles drift before any insert
+    pub async fn connect(config: ClickHouseConfig) -> Result<Self> {
+        let mut sink = ClickHouseSink {
+            client: reqwest::Client::new(),
+            config,
+            plans: HashMap::new(),
+        };
+
+        sink.execute(&format!("CREATE DATABASE IF NOT EXISTS {}", sink.config.database)).await?;
+        match sink.schema_version().await? {
+            None => sink.stamp_schema_version(SCHEMA_VERSION).await?,
+            Some(version) if version != SCHEMA_VERSION => {
+                return Err(anyhow::anyhow!(
+                    "ClickHouse database {} holds schema version {}; run `migrate` to upgrade it to version {}",
+                    sink.config.database, version, SCHEMA_VERSION
+                ))
+            },
+            Some(_) => {},
+        }
+        for (table, columns) in TABLES {
+            sink.create_table(table, columns).await?;
+            let actual = sink.describe(table).await?;
+            let plan = plan_table(columns, &actual, sink.config.auto_add_columns);
+            sink.apply_plan(table, &plan).await?;
+            sink.plans.insert(table, plan);
+        }
+
+        Ok(sink)
+    }
+
+    // Inspects the live schema like `connect` without changing anything: per table, how its drift
+    // would be handled, or None when the table would be created
+    pub async fn preview(config: ClickHouseConfig) -> Result<Vec<(&'static str, Option<TablePlan>)>> {
+        let sink = ClickHouseSink { client: reqwest::Client::new(), config, plans: HashMap::new() };
+        let mut tables = Vec::new();
+        for (table, columns) in TABLES {
+            let exists = sink.execute(&format!("EXISTS TABLE {}.{}", sink.config.database, table)).await?;
+            let plan = match exists.trim() {
+                "1" => Some(plan_table(columns, &sink.describe(table).await?, sink.config.auto_add_columns)),
+                _ => None,
+            };
+            tables.push((table, plan));
+        }
+        Ok(tables)
+    }
+
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        Ok(self.execute(&format!("EXISTS TABLE {}.{}", self.config.database, table)).await?.trim() == "1")
+    }
+
+    // Schema version of the tables: as stamped, 1 for tables created before stamps, None for an
+    // empty database
+    pub async fn schema_version(&self) -> Result<Option<u32>> {
+        if self.table_exists(SCHEMA_VERSION_TABLE).await? {
+            let body = self.execute(&format!("SELECT max(version) FROM {}.{} FORMAT TabSeparated", self.config.database, SCHEMA_VERSION_TABLE)).await?;
+            return Ok(Some(body.trim().parse()?));
+        }
+        Ok(self.table_exists("blocks").await?.then_some(1))
+    }
+
+    async fn stamp_schema_version(&self, version: u32) -> Result<()> {
+        self.execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} (`version` UInt32, `migrated_at` DateTime) ENGINE = MergeTree() ORDER BY version",
+            self.config.database, SCHEMA_VERSION_TABLE
+        ))
+        .await?;
+        let now = clock::now().format("%Y-%m-%d %H:%M:%S");
+        self.execute(&format!("INSERT INTO {}.{} VALUES ({}, '{}')", self.config.database, SCHEMA_VERSION_TABLE, version, now)).await?;
+        Ok(())
+    }
+
+    // Runs the pending migrations' statements and stamps each version, returning the version the
+    // database was at and the migrations applied. Tables a migration targets that do not exist
+    // are skipped; `connect` creates them with the current schema.
+    pub async fn migrate(config: ClickHouseConfig, dry_run: bool) -> Result<(Option<u32>, Vec<&'static Migration>)> {
+        let sink = ClickHouseSink { client: reqwest::Client::new(), config, plans: HashMap::new() };
+        let Some(from) = sink.schema_version().await? else {
+            return Ok((None, Vec::new()));
+        };
+        let migrations: Vec<&Migration> = migrate::pending(from).collect();
+        if dry_run {
+            return Ok((Some(from), migrations));
+        }
+        for (i, migration) in migrations.iter().enumerate() {
+            if sink.table_exists(migration.dataset).await? {
+                let table = format!("{}.{}", sink.config.database, migration.dataset);
+                for statement in migration.clickhouse {
+                    sink.execute(&migrate::statement(statement, &table)).await?;
+                }
+            }
+            // A version can take one migration per dataset; it is stamped once all of them ran
+            if migrations.get(i + 1).is_none_or(|next| next.version != migration.version) {
+                sink.stamp_schema_version(migration.version).await?;
+            }
+        }
+        Ok((Some(from), migrations))
+    }
+
+    async fn execute(&self, query: &str) -> Result<String> {
+        let response = self
+            .client
+            .post(&self.config.url)
+            .basic_auth(&self.config.user, Some(&self.config.password))
+            .body(query.to_string())
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("ClickHouse query failed ({}): {}", status, body.trim()));
+        }
+        Ok(body)
+    }
+
+    async fn create_table(&self, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+        let order_by = match (table, key_columns(table)) {
+            ("blocks", _) => "number".to_string(),
+            ("address_activity", _) => "(address, block_number)".to_string(),
+            ("addresses", _) => "address".to_string(),
+            (_, []) => "block_number".to_string(),
+            (_, key) => format!("({})", key.join(", ")),
+        };
+        // The dimension keeps the latest row per address once parts merge (`FINAL` or argMax on
+        // read until then), and stays unpartitioned so versions of an address can replace each other
+        let (engine, partition_by) = match table {
+            "addresses" => ("ReplacingMergeTree(updated_block)", String::new()),
+            _ => {
+                let block_column = if table == "blocks" { "number" } else { "block_number" };
+                let partition_by = self.config.partition_by(block_column, columns).map(|expr| format!(" PARTITION BY {}", expr));
+                ("MergeTree()", partition_by.unwrap_or_default())
+            },
+        };
+        let column_defs: Vec<String> = columns
+            .iter()
+            .map(|(name, column_type)| format!("`{}` {}", name, column_type))
+            .chain(std::iter::once(format!("`{}` String DEFAULT '{{}}'", EXTRAS_COLUMN)))
+            .collect();
+        self.execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} ({}) ENGINE = {}{} ORDER BY {}",
+            self.config.database,
+            table,
+            column_defs.join(", "),
+            engine,
+            partition_by,
+            order_by
+        ))
+        .await?;
+        Ok(())
+    }
+
+    async fn describe(&self, table: &str) -> Result<BTreeMap<String, String>> {
+        let body = self
+            .execute(&format!(
+                "DESCRIBE TABLE {}.{} FORMAT JSONEachRow",
+                self.config.database, table
+            ))
+            .await?;
+        let mut columns = BTreeMap::new();
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let row: Value = serde_json::from_str(line)?;
+            if let (Some(name), Some(column_type)) = (row["name"].as_str(), row["type"].as_str()) {
+                columns.insert(name.to_string(), column_type.to_string());
+            }
+        }
+        Ok(columns)
+    }
+
+    async fn apply_plan(&self, table: &str, plan: &TablePlan) -> Result<()> {
+        for (name, column_type) in &plan.columns_to_add {
+            log::warn!("ClickHouse table {} is missing column {}, adding it as {}", table, name, column_type);
+            self.execute(&format!(
+                "ALTER TABLE {}.{} ADD COLUMN IF NOT EXISTS `{}` {}",
+                self.config.database, table, name, column_type
+            ))
+            .await?;
+        }
+        for name in &plan.fields_to_extras {
+            log::warn!("ClickHouse table {} is missing column {}, storing it in `{}`", table, name, EXTRAS_COLUMN);
+        }
+        for name in &plan.fields_dropped {
+            log::warn!(
+                "ClickHouse table {} is missing column {} and has no `{}` column, the field will be dropped",
+                table, name, EXTRAS_COLUMN
+            );
+        }
+        for name in &plan.unexpected_columns {
+            log::warn!("ClickHouse table {} has unexpected column {}, it will use its default", table, name);
+        }
+        Ok(())
+    }
+
+    pub async fn block_numbers(&self) -> Result<BTreeSet<u64>> {
+        self.select_block_numbers("1").await
+    }
+
+    pub async fn block_numbers_between(&self, first: u64, last: u64) -> Result<BTreeSet<u64>> {
+        self.select_block_numbers(&format!("number BETWEEN {} AND {}", first, last)).await
+    }
+
+    async fn select_block_numbers(&self, condition: &str) -> Result<BTreeSet<u64>> {
+        let body = self
+            .execute(&format!(
+                "SELECT DISTINCT number FROM {}.blocks WHERE {} FORMAT TabSeparated",
+                self.config.database, condition
+            ))
+            .await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(line.trim().parse::<u64>()?))
+            .collect()
+    }
+
+    // Removes every row of these blocks from all tables (lightweight deletes, ClickHouse 23.3+)
+    // so a re-indexed block never ends up with old and new rows side by side
+    pub async fn delete_blocks(&self, numbers: &BTreeSet<u64>) -> Result<()> {
+        let list = numbers.iter().map(u64::to_string).collect::<Vec<_>>().join(",");
+        for (table, _) in TABLES.into_iter().filter(|(table, _)| *table != "addresses") {
+            let column = if table == "blocks" { "number" } else { "block_number" };
+            self.execute(&format!("DELETE FROM {}.{} WHERE `{}` IN ({})", self.config.database, table, column, list))
+                .await?;
+        }
+        log::info!("Deleted {} previously indexed blocks from ClickHouse before re-inserting them", numbers.len());
+        Ok(())
+    }
+
+    // Address activity rows for one address, deduplicated and ordered by block
+    pub async fn address_activity(&self, address: &str, role: Option<ActivityRole>) -> Result<Vec<AddressActivity>> {
+        let address = address.to_lowercase();
+        if !address.starts_with("0x") || !address[2..].chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("Invalid address {}", address));
+        }
+        let role_filter = match role {
+            Some(role) => format!(" AND role = '{}'", role.as_str()),
+            None => String::new(),
+        };
+        let body = self
+            .execute(&format!(
+                "SELECT DISTINCT address, block_number, transaction_hash, role FROM {}.address_activity \
+                 WHERE address = '{}'{} ORDER BY block_number, transaction_hash, role FORMAT JSONEachRow",
+                self.config.database, address, role_filter
+            ))
+            .await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    pub async fn insert<T: Serialize>(&self, table: &'static str, records: &[T]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        faults::before_write(table)?;
+        let plan = self
+            .plans
+            .get(table)
+            .ok_or_else(|| anyhow::anyhow!("Unknown ClickHouse table {}", table))?;
+
+        let mut body = String::new();
+        for record in records {
+            let row = match serde_json::to_value(record)? {
+                Value::Object(row) => shape_row(row, plan),
+                other => return Err(anyhow::anyhow!("Expected a JSON object, got {}", other)),
+            };
+            body.push_str(&Value::Object(row).to_string());
+            body.push('\n');
+        }
+
+        let query = format!("INSERT INTO {}.{} FORMAT JSONEachRow", self.config.database, table);
+        let response = self
+            .client
+            .post(&self.config.url)
+            .basic_auth(&self.config.user, Some(&self.config.password))
+            .query(&[
+                ("query", query.as_str()),
+                ("date_time_input_format", "best_effort"),
+                ("input_format_json_read_objects_as_strings", "1"),
+            ])
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await?;
+            return Err(anyhow::anyhow!("ClickHouse insert into {} failed ({}): {}", table, status, text.trim()));
+        }
+
+        log::info!("Inserted {} rows into ClickHouse table {}", records.len(), table);
+        Ok(())
+    }
+}
+
+// The `clickhouse` sink: connected on first use, so a database that is down at startup only
+// queues batches
+pub struct ClickHouseOutput {
+    config: ClickHouseConfig,
+    connection: OnceCell<ClickHouseSink>,
+}
+
+impl ClickHouseOutput {
+    pub fn new(config: ClickHouseConfig) -> Self {
+        Self { config, connection: OnceCell::new() }
+    }
+
+    async fn connection(&self) -> Result<&ClickHouseSink> {
+        self.connection.get_or_try_init(|| ClickHouseSink::connect(self.config.clone())).await
+    }
+}
+
+pub fn sink_from_env(output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    let Some(config) = ClickHouseConfig::from_env()? else { return Ok(None) };
+    Ok(Some(Box::new(ClickHouseOutput::new(config.with_partitioning(output.partition, output.partition_size)))))
+}
+
+impl Sink for ClickHouseOutput {
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    fn describe(&self) -> String {
+        format!("ClickHouse at {} (database {})", self.config.url, self.config.database)
+    }
+
+    fn connect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { self.connection().await.map(|_| ()) })
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.connection().await?.insert(TransformedBlock::DATASET, blocks).await })
+    }
+
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.connection().await?.insert(TransformedTransaction::DATASET, transactions).await })
+    }
+
+    fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.connection().await?.insert(TransformedReceipt::DATASET, receipts).await })
+    }
+
+    // Every dataset, with the write policy applied to blocks the tables already hold
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { store::insert_clickhouse(self.connection().await?, output, batch).await })
+    }
+
+    // Inspects the live schema read-only, like `connect` would see it
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move {
+            let mut lines = Vec::new();
+            for (table, plan) in ClickHouseSink::preview(self.config.clone()).await? {
+                let records = match table {
+                    activity::INDEX_TABLE => activity,
+                    _ => rows.iter().find(|p| p.dataset == table).map_or(0, |p| p.records),
+                };
+                let state = plan.as_ref().map_or("table would be created".to_string(), TablePlan::summary);
+                lines.push(format!("clickhouse {}.{}: {} rows, {}", self.config.database, table, records, state));
+            }
+            Ok(lines)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn live_columns(names: &[&str]) -> BTreeMap<String, String> {
+        names.iter().map(|name| (name.to_string(), "String".to_string())).collect()
+    }
+
+    #[test]
+    fn test_plan_adds_nullable_columns_when_allowed() {
+        let expected = [("hash", "String"), ("number", "UInt64"), ("to", "Nullable(String)")];
+        let plan = plan_table(&expected, &live_columns(&["hash", "extras"]), true);
+        assert_eq!(
+            plan.columns_to_add,
+            vec![
+                ("number".to_string(), "Nullable(UInt64)".to_string()),
+                ("to".to_string(), "Nullable(String)".to_string()),
+            ]
+        );
+        assert!(plan.fields_to_extras.is_empty());
+    }
+
+    #[test]
+    fn test_plan_routes_to_extras_or_drops() {
+        let expected = [("hash", "String"), ("number", "UInt64")];
+        let plan = plan_table(&expected, &live_columns(&["hash", "extras", "legacy"]), false);
+        assert_eq!(plan.fields_to_extras, vec!["number".to_string()]);
+        assert_eq!(plan.unexpected_columns, vec!["legacy".to_string()]);
+
+        let plan = plan_table(&expected, &live_columns(&["hash"]), false);
+        assert_eq!(plan.fields_dropped, vec!["number".to_string()]);
+    }
+
+    #[test]
+    fn test_shape_row_moves_fields_into_extras() {
+        let plan = TablePlan {
+            fields_to_extras: vec!["number".to_string()],
+            fields_dropped: vec!["size".to_string()],
+            ..Default::default()
+        };
+        let row = json!({"hash": "0xabc", "number": 7, "size": 100});
+        let shaped = shape_row(row.as_object().unwrap().clone(), &plan);
+        assert_eq!(shaped["hash"], json!("0xabc"));
+        assert!(!shaped.contains_key("number"));
+        assert!(!shaped.contains_key("size"));
+        assert_eq!(shaped[EXTRAS_COLUMN], json!("{\"number\":7}"));
+
+        let row = json!({"hash": "0xabc", "extra": {"l1Fee": "0x10"}});
+        let plan = TablePlan { has_extras: true, ..Default::default() };
+        let shaped = shape_row(row.as_object().unwrap().clone(), &plan);
+        assert!(!shaped.contains_key("extra"));
+        assert_eq!(shaped[EXTRAS_COLUMN], json!("{\"l1Fee\":\"0x10\"}"));
+    }
+}
@@ -0,0 +1,349 @@
+//! DuckDB through its command line client (`duckdb`, https://duckdb.org/docs/installation), so no
+//! native library is linked. `query` runs SQL over views on the indexed files; with `DUCKDB_PATH`
+//! set, `index` also loads every batch into tables of that database file.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::config::env_or;
+use crate::models::{
+    AddressDimension, Balance, BlockDataUsage, BridgeMessage, ContractState, DataQualityIssue, EventLog, NftTransfer, OrderingAnalysis, TokenTransfer, TransformedBlock, TransformedContract,
+    TransformedReceipt, TransformedTransaction,
+};
+use crate::migrate::{self, Migration};
+use crate::pipeline::TransformedBatch;
+use crate::selector_stats;
+use crate::sinks::Sink;
+use crate::store::{self, OutputConfig, Record, WritePlan, WritePolicy, SCHEMA_VERSION};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum QueryFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuckDbConfig {
+    pub binary: String,
+    pub database: Option<PathBuf>,
+}
+
+impl DuckDbConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            binary: env_or("DUCKDB_BIN", "duckdb".to_string())?,
+            database: std::env::var("DUCKDB_PATH").ok().filter(|p| !p.trim().is_empty()).map(PathBuf::from),
+        })
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+// Creates the `schema_version` table of a database that has none
+fn stamp_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS schema_version AS SELECT CASE WHEN EXISTS \
+         (SELECT 1 FROM information_schema.tables WHERE table_name = 'blocks') THEN 1 ELSE {} END AS version;\n",
+        SCHEMA_VERSION
+    )
+}
+
+// Fails the script when the database holds another schema version than this build writes
+fn check_sql() -> String {
+    format!(
+        "SELECT CASE WHEN max(version) <> {v} THEN error('DuckDB database holds schema version ' || max(version) || \
+         ', run `migrate` to upgrade it to version {v}') END FROM schema_version;\n",
+        v = SCHEMA_VERSION
+    )
+}
+
+// Column holding the block number of a dataset's rows
+fn block_column(dataset: &str) -> &'static str {
+    match dataset {
+        "blocks" => "number",
+        // Upserted by address: a newer row replaces the address's previous one
+        "addresses" => "address",
+        _ => "block_number",
+    }
+}
+
+// One view per dataset over its files. Globs are built per file suffix (`*.ndjson.zst`, `*.json`,
+// ...) so DuckDB lists the directory itself, which matters for the one-file-per-record layout.
+// Derived datasets such as `selector_stats` get a view too once they have been built. Avro and
+// Arrow files are left out: reading them needs DuckDB's avro or arrow extension.
+pub fn view_sql(raw_data_path: &str) -> Result<String> {
+    let mut sql = String::new();
+    for dataset in store::DATASETS.into_iter().chain([selector_stats::DIR]) {
+        let files = store::dataset_files(raw_data_path, dataset)?;
+        let globs: BTreeSet<String> = files
+            .iter()
+            .filter(|path| !store::is_avro(path) && !store::is_arrow(path))
+            .filter_map(|path| {
+                let name = path.file_name()?.to_string_lossy().to_string();
+                let suffix = &name[name.find('.')?..];
+                Some(quote(&format!("{}/*{}", path.parent()?.display(), suffix)))
+            })
+            .collect();
+        if globs.is_empty() {
+            continue;
+        }
+        let globs: Vec<String> = globs.into_iter().collect();
+        writeln!(
+            sql,
+            "CREATE OR REPLACE VIEW {} AS SELECT * FROM read_json_auto([{}], union_by_name = true);",
+            dataset,
+            globs.join(", ")
+        )?;
+    }
+    Ok(sql)
+}
+
+async fn execute(config: &DuckDbConfig, database: Option<&Path>, format: QueryFormat, sql: &str) -> Result<String> {
+    let mut command = Command::new(&config.binary);
+    match format {
+        QueryFormat::Table => command.arg("-box"),
+        QueryFormat::Json => command.arg("-json"),
+        QueryFormat::Csv => command.arg("-csv"),
+    };
+    if let Some(database) = database {
+        command.arg(database);
+    }
+    let output = command
+        .arg("-c")
+        .arg(sql)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not run {} (is DuckDB installed?): {}", config.binary, e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("DuckDB failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Runs `sql` against the indexed files, or against the DuckDB database when `database` is given
+pub async fn query(config: &DuckDbConfig, raw_data_path: &str, database: Option<&Path>, format: QueryFormat, sql: &str) -> Result<String> {
+    let script = match database {
+        Some(_) => sql.to_string(),
+        None => format!("{}{}", view_sql(raw_data_path)?, sql),
+    };
+    execute(config, database, format, &script).await
+}
+
+pub struct DuckDbSink {
+    config: DuckDbConfig,
+    database: PathBuf,
+}
+
+impl DuckDbSink {
+    pub fn new(config: DuckDbConfig) -> Option<Self> {
+        let database = config.database.clone()?;
+        Some(Self { config, database })
+    }
+
+    pub fn database(&self) -> &Path {
+        &self.database
+    }
+
+    // Stages the records as ndjson and appends the statements loading them into their table.
+    // Tables are created from the first batch's columns; later batches are inserted by name.
+    fn stage<T: Record>(&self, dir: &Path, records: &[T], policy: WritePolicy, sql: &mut String) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let path = dir.join(format!("{}.ndjson", T::DATASET));
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record)?;
+            data.push(b'\n');
+        }
+        std::fs::write(&path, data)?;
+
+        let (table, column) = (T::DATASET, block_column(T::DATASET));
+        let source = format!("read_json_auto({})", quote(&path.to_string_lossy()));
+        writeln!(sql, "CREATE TABLE IF NOT EXISTS {} AS SELECT * FROM {} LIMIT 0;", table, source)?;
+        match policy {
+            WritePolicy::Overwrite => {
+                writeln!(sql, "DELETE FROM {table} WHERE {column} IN (SELECT {column} FROM {source});")?;
+                writeln!(sql, "INSERT INTO {} BY NAME SELECT * FROM {};", table, source)?;
+            },
+            WritePolicy::SkipExisting => {
+                writeln!(sql, "INSERT INTO {table} BY NAME SELECT * FROM {source} WHERE {column} NOT IN (SELECT {column} FROM {table});")?;
+            },
+        }
+        Ok(())
+    }
+
+    pub fn load_script(&self, dir: &Path, batch: &TransformedBatch, output: OutputConfig) -> Result<String> {
+        let policy = output.write_policy;
+        let mut sql = String::from("BEGIN TRANSACTION;\n");
+        self.stage::<TransformedBlock>(dir, &batch.blocks, policy, &mut sql)?;
+        self.stage::<TransformedTransaction>(dir, &batch.transactions, policy, &mut sql)?;
+        self.stage::<TransformedReceipt>(dir, &store::written_receipts(output, &batch.receipts), policy, &mut sql)?;
+        self.stage::<EventLog>(dir, &batch.logs, policy, &mut sql)?;
+        self.stage::<TransformedContract>(dir, &batch.contracts, policy, &mut sql)?;
+        self.stage::<BlockDataUsage>(dir, &batch.data_usage, policy, &mut sql)?;
+        self.stage::<OrderingAnalysis>(dir, &batch.ordering_analysis, policy, &mut sql)?;
+        self.stage::<NftTransfer>(dir, &batch.nft_transfers, policy, &mut sql)?;
+        self.stage::<TokenTransfer>(dir, &batch.token_transfers, policy, &mut sql)?;
+        self.stage::<BridgeMessage>(dir, &batch.bridge_messages, policy, &mut sql)?;
+        self.stage::<Balance>(dir, &batch.balances, policy, &mut sql)?;
+        self.stage::<ContractState>(dir, &batch.contract_state, policy, &mut sql)?;
+        self.stage::<AddressDimension>(dir, &batch.addresses, WritePolicy::Overwrite, &mut sql)?;
+        self.stage::<DataQualityIssue>(dir, &batch.data_quality, policy, &mut sql)?;
+        sql.push_str("COMMIT;\n");
+        Ok(sql)
+    }
+
+    // Schema version of the database, stamping it first if needed: version 1 when it already has
+    // tables from before stamps, the current version when it is new
+    pub async fn schema_version(&self) -> Result<u32> {
+        let sql = format!("{}SELECT max(version) FROM schema_version;", stamp_sql());
+        let output = execute(&self.config, Some(&self.database), QueryFormat::Csv, &sql).await?;
+        let version = output.lines().last().unwrap_or_default().trim();
+        version.parse().map_err(|_| anyhow::anyhow!("Unexpected DuckDB schema version {:?}", version))
+    }
+
+    // Runs the pending migrations' statements and stamps each version in one transaction,
+    // returning the version the database was at and the migrations applied
+    pub async fn migrate(&self, dry_run: bool) -> Result<(u32, Vec<&'static Migration>)> {
+        let from = self.schema_version().await?;
+        let migrations: Vec<&Migration> = migrate::pending(from).collect();
+        if dry_run || migrations.is_empty() {
+            return Ok((from, migrations));
+        }
+        let tables = execute(&self.config, Some(&self.database), QueryFormat::Csv, "SELECT table_name FROM information_schema.tables;").await?;
+        let tables: BTreeSet<&str> = tables.lines().map(str::trim).collect();
+        let mut sql = String::from("BEGIN TRANSACTION;\n");
+        for migration in &migrations {
+            if tables.contains(migration.dataset) {
+                for statement in migration.duckdb {
+                    writeln!(sql, "{};", migrate::statement(statement, migration.dataset))?;
+                }
+            }
+            writeln!(sql, "INSERT INTO schema_version VALUES ({});", migration.version)?;
+        }
+        sql.push_str("COMMIT;\n");
+        execute(&self.config, Some(&self.database), QueryFormat::Csv, &sql).await?;
+        Ok((from, migrations))
+    }
+
+    // Runs a load script staged in a scratch directory. A database holding another schema version
+    // is refused before anything is loaded.
+    async fn load(&self, script: impl FnOnce(&Path) -> Result<String>) -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("sweet-potato-duckdb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let result = async {
+            let sql = format!("{}{}{}", stamp_sql(), check_sql(), script(&dir)?);
+            execute(&self.config, Some(&self.database), QueryFormat::Csv, &sql).await
+        }
+        .await;
+        let _ = std::fs::remove_dir_all(&dir);
+        result.map(|_| ())
+    }
+
+    // Loads a batch in one transaction, so a failed load leaves the database as it was
+    pub async fn insert(&self, batch: &TransformedBatch, output: OutputConfig) -> Result<()> {
+        self.load(|dir| self.load_script(dir, batch, output)).await
+    }
+
+    // Loads the records of one dataset, replacing the blocks the table already holds
+    async fn insert_records<T: Record>(&self, records: &[T]) -> Result<()> {
+        self.load(|dir| {
+            let mut sql = String::from("BEGIN TRANSACTION;\n");
+            self.stage(dir, records, WritePolicy::Overwrite, &mut sql)?;
+            sql.push_str("COMMIT;\n");
+            Ok(sql)
+        })
+        .await
+    }
+}
+
+pub fn sink_from_env(_output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    Ok(DuckDbSink::new(DuckDbConfig::from_env()?).map(|sink| Box::new(sink) as Box<dyn Sink>))
+}
+
+impl Sink for DuckDbSink {
+    fn name(&self) -> &'static str {
+        "duckdb"
+    }
+
+    fn describe(&self) -> String {
+        format!("DuckDB database {}", self.database.display())
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(blocks))
+    }
+
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(transactions))
+    }
+
+    fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(receipts))
+    }
+
+    // Every dataset in one transaction
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert(batch, output))
+    }
+
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        let lines = rows.iter().filter(|p| p.records > 0);
+        let lines = lines.map(|plan| format!("duckdb {} table {}: {} rows", self.database.display(), plan.dataset, plan.records)).collect();
+        Box::pin(async { Ok(lines) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_sql() {
+        let root = "test_duckdb_views";
+        let _ = std::fs::remove_dir_all(root);
+        std::fs::create_dir_all(format!("{}/blocks", root)).unwrap();
+        std::fs::create_dir_all(format!("{}/receipts", root)).unwrap();
+        for name in ["blocks/blocks_0_999.ndjson.zst", "blocks/blocks_1000_1999.ndjson.zst", "blocks/block_5.json", "receipts/x.tmp"] {
+            std::fs::write(format!("{}/{}", root, name), "").unwrap();
+        }
+        let sql = view_sql(root).unwrap();
+        assert_eq!(
+            sql,
+            "CREATE OR REPLACE VIEW blocks AS SELECT * FROM read_json_auto(['test_duckdb_views/blocks/*.json', \
+             'test_duckdb_views/blocks/*.ndjson.zst'], union_by_name = true);\n"
+        );
+
+        let sink = DuckDbSink::new(DuckDbConfig { binary: "duckdb".to_string(), database: Some("chain.duckdb".into()) }).unwrap();
+        let contract: TransformedContract = serde_json::from_value(serde_json::json!({
+            "address": "0xc", "creator": "0xf", "transaction_hash": "0x1", "block_number": 7, "init_code_hash": "0x",
+            "datetime": "2024-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        let batch = TransformedBatch { contracts: vec![contract], ..Default::default() };
+        let script = sink.load_script(Path::new(root), &batch, OutputConfig { write_policy: WritePolicy::SkipExisting, ..Default::default() }).unwrap();
+        let staged = format!("{}/contracts.ndjson", root);
+        assert!(std::fs::read_to_string(&staged).unwrap().contains("\"block_number\":7"));
+        assert_eq!(
+            script,
+            format!(
+                "BEGIN TRANSACTION;\n\
+                 CREATE TABLE IF NOT EXISTS contracts AS SELECT * FROM read_json_auto('{staged}') LIMIT 0;\n\
+                 INSERT INTO contracts BY NAME SELECT * FROM read_json_auto('{staged}') WHERE block_number NOT IN (SELECT block_number FROM contracts);\n\
+                 COMMIT;\n"
+            )
+        );
+        assert_eq!(quote("it's"), "'it''s'");
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,250 @@
+//! Delivery of written batches to the database sinks, each in its own failure domain. Output files
+//! are always written first; a sink that fails (or cannot even connect) gets the batch queued under
+//! `raw_data/sinks/<sink>/queue/` instead of failing the run, and later batches queue behind it so
+//! every sink receives batches in the order they were written. Queues are replayed before the next
+//! delivery to the sink and by `sinks --flush`.
+//!
+//! `raw_data/sinks/<sink>/checkpoint.json` records the sink's own progress: the block after the
+//! highest one it received, how many batches are queued and the last error. A sink that commits its
+//! own checkpoint with the rows (PostgreSQL) is the authority on its progress: when the file
+//! disagrees, after a crash between the database commit and the file update, [`FanOut::reconcile`]
+//! rewrites the file from the database.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::clock;
+use crate::pipeline::TransformedBatch;
+use crate::sinks::{self, Sink};
+use crate::store::OutputConfig;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SinkCheckpoint {
+    pub next_block: u64,
+    pub queued_batches: usize,
+    pub last_error: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum Delivery {
+    Delivered,
+    // Queued on disk; carries the error, or why the batch had to wait behind earlier ones
+    Queued(String),
+}
+
+pub struct FanOut {
+    root: PathBuf,
+    sinks: Vec<Box<dyn Sink>>,
+    output: OutputConfig,
+}
+
+fn checkpoint_path(dir: &Path) -> PathBuf {
+    dir.join("checkpoint.json")
+}
+
+impl FanOut {
+    pub fn new(raw_data_path: &str, sinks: Vec<Box<dyn Sink>>, output: OutputConfig) -> Self {
+        Self { root: Path::new(raw_data_path).join("sinks"), sinks, output }
+    }
+
+    // The sinks `names` selects from the registry, or every configured one (see `sinks::from_env`)
+    pub fn from_env(raw_data_path: &str, names: &[String], output: OutputConfig) -> Result<Self> {
+        Ok(Self::new(raw_data_path, sinks::from_env(names, output)?, output))
+    }
+
+    pub fn sinks(&self) -> &[Box<dyn Sink>] {
+        &self.sinks
+    }
+
+    fn dir(&self, sink: &dyn Sink) -> PathBuf {
+        self.root.join(sink.name())
+    }
+
+    // Queued batch files of a sink, oldest first
+    fn queue(&self, sink: &dyn Sink) -> Result<Vec<PathBuf>> {
+        let dir = self.dir(sink).join("queue");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        files.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        files.sort();
+        Ok(files)
+    }
+
+    fn enqueue(&self, sink: &dyn Sink, batch: &TransformedBatch) -> Result<PathBuf> {
+        let dir = self.dir(sink).join("queue");
+        std::fs::create_dir_all(&dir)?;
+        // Named by enqueue time so replay keeps the write order
+        let nanos = clock::now().timestamp_nanos_opt().unwrap_or_default();
+        let first = batch.blocks.iter().map(|b| b.number).min().unwrap_or_default();
+        let last = batch.blocks.iter().map(|b| b.number).max().unwrap_or_default();
+        let path = dir.join(format!("{:020}_{}_{}.json", nanos, first, last));
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(batch)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+
+    pub fn checkpoint(&self, sink: &dyn Sink) -> Result<SinkCheckpoint> {
+        match std::fs::read(checkpoint_path(&self.dir(sink))) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SinkCheckpoint::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Records a delivery (`delivered` set) or a failure (`error` set) and the current queue length
+    fn update_checkpoint(&self, sink: &dyn Sink, delivered: Option<&TransformedBatch>, error: Option<String>) -> Result<()> {
+        let mut checkpoint = self.checkpoint(sink)?;
+        if let Some(last) = delivered.and_then(|batch| batch.blocks.iter().map(|b| b.number).max()) {
+            checkpoint.next_block = checkpoint.next_block.max(last + 1);
+        }
+        if delivered.is_some() || error.is_some() {
+            checkpoint.last_error = error;
+        }
+        checkpoint.queued_batches = self.queue(sink)?.len();
+        self.write_checkpoint(sink, checkpoint)
+    }
+
+    fn write_checkpoint(&self, sink: &dyn Sink, mut checkpoint: SinkCheckpoint) -> Result<()> {
+        checkpoint.updated_at = Some(clock::now());
+        let dir = self.dir(sink);
+        std::fs::create_dir_all(&dir)?;
+        let path = checkpoint_path(&dir);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&checkpoint)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    // Takes the progress of sinks that store a checkpoint with their rows from the backend
+    pub async fn reconcile(&self) -> Result<()> {
+        for sink in &self.sinks {
+            let Some(next_block) = sink.checkpoint().await? else { continue };
+            let mut checkpoint = self.checkpoint(sink.as_ref())?;
+            if checkpoint.next_block != next_block {
+                log::warn!(
+                    "Checkpoint file of sink {} says next block {}, the database {}; trusting the database",
+                    sink.name(),
+                    checkpoint.next_block,
+                    next_block
+                );
+                checkpoint.next_block = next_block;
+                self.write_checkpoint(sink.as_ref(), checkpoint)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Replays a sink's queue oldest first, stopping at the first failure. Returns how many
+    // batches were delivered and the error that stopped the replay, if any.
+    async fn drain(&self, sink: &dyn Sink) -> Result<(usize, Option<String>)> {
+        let mut delivered = 0;
+        for path in self.queue(sink)? {
+            let batch: TransformedBatch = serde_json::from_slice(&std::fs::read(&path)?)?;
+            if let Err(e) = sink.write_batch(&batch, self.output).await {
+                self.update_checkpoint(sink, None, Some(e.to_string()))?;
+                return Ok((delivered, Some(e.to_string())));
+            }
+            std::fs::remove_file(&path)?;
+            self.update_checkpoint(sink, Some(&batch), None)?;
+            delivered += 1;
+        }
+        Ok((delivered, None))
+    }
+
+    async fn deliver_to(&self, sink: &dyn Sink, batch: &TransformedBatch) -> Result<Delivery> {
+        let (replayed, error) = self.drain(sink).await?;
+        if replayed > 0 {
+            log::info!("Replayed {} queued batches into {}", replayed, sink.name());
+        }
+        let error = match error {
+            Some(e) => format!("{} (earlier batches still queued)", e),
+            None => match sink.write_batch(batch, self.output).await {
+                Ok(()) => {
+                    self.update_checkpoint(sink, Some(batch), None)?;
+                    return Ok(Delivery::Delivered);
+                },
+                Err(e) => e.to_string(),
+            },
+        };
+        self.enqueue(sink, batch)?;
+        self.update_checkpoint(sink, None, Some(error.clone()))?;
+        Ok(Delivery::Queued(error))
+    }
+
+    // Connects the sinks that need it up front, so schema drift is reported before fetching. A
+    // sink that cannot connect is only logged: its batches queue until it is back.
+    pub async fn connect(&self) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.connect().await {
+                log::warn!("Sink {} is unavailable, its batches will be queued: {}", sink.name(), e);
+            }
+        }
+        if let Err(e) = self.reconcile().await {
+            log::warn!("Could not read the sinks' stored checkpoints: {}", e);
+        }
+    }
+
+    // Delivers the batch to every sink at once. Only a batch that could not even be queued is an
+    // error; a failing sink does not hold up the others.
+    pub async fn deliver(&self, batch: &TransformedBatch) -> Result<Vec<(&'static str, Delivery)>> {
+        let results = join_all(self.sinks.iter().map(|sink| async move { (sink.name(), self.deliver_to(sink.as_ref(), batch).await) })).await;
+        results.into_iter().map(|(name, result)| Ok((name, result?))).collect()
+    }
+
+    // Replays every sink's queue, returning the batches delivered and the error left per sink
+    pub async fn flush(&self) -> Result<Vec<(&'static str, usize, Option<String>)>> {
+        let mut flushed = Vec::new();
+        for sink in &self.sinks {
+            let (delivered, error) = self.drain(sink.as_ref()).await?;
+            flushed.push((sink.name(), delivered, error));
+        }
+        Ok(flushed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::block;
+    use crate::sinks::duckdb::{DuckDbConfig, DuckDbSink};
+
+    #[tokio::test]
+    async fn test_unreachable_sink_queues_in_order() {
+        let root = "test_sink_fanout";
+        let _ = std::fs::remove_dir_all(root);
+        // A DuckDB client that does not exist fails every load
+        let broken = DuckDbSink::new(DuckDbConfig {
+            binary: "/nonexistent/duckdb".to_string(),
+            database: Some(format!("{}/chain.duckdb", root).into()),
+        })
+        .unwrap();
+        let fanout = FanOut::new(root, vec![Box::new(broken)], OutputConfig::default());
+
+        for number in [10, 20] {
+            let batch = TransformedBatch { blocks: vec![block(number)], ..Default::default() };
+            let delivered = fanout.deliver(&batch).await.unwrap();
+            assert!(matches!(delivered[0], ("duckdb", Delivery::Queued(_))));
+        }
+        let sink = fanout.sinks()[0].as_ref();
+        let queue = fanout.queue(sink).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert!(queue[0].to_string_lossy().ends_with("_10_10.json") && queue[1].to_string_lossy().ends_with("_20_20.json"));
+        let checkpoint = fanout.checkpoint(sink).unwrap();
+        assert_eq!((checkpoint.next_block, checkpoint.queued_batches), (0, 2));
+        assert!(checkpoint.last_error.unwrap().contains("earlier batches still queued"));
+
+        let flushed = fanout.flush().await.unwrap();
+        assert_eq!(flushed[0].1, 0);
+        assert!(flushed[0].2.is_some());
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
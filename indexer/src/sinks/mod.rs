@@ -0,0 +1,170 @@
+//! Backends every written batch is delivered to after the output files. A backend implements
+//! [`Sink`] and is listed in [`REGISTRY`], from which `--sink` (`SINKS`) picks the ones to run;
+//! without it every backend configured in the environment is used. `fanout` delivers each batch
+//! to all of them at once, queueing it for the ones that fail.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::store::{OutputConfig, WritePlan};
+
+#[cfg(feature = "bigquery")]
+pub mod bigquery;
+pub mod clickhouse;
+pub mod duckdb;
+pub mod fanout;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod webhook;
+
+pub trait Sink: Send + Sync {
+    // Registry name, also the directory of the sink's queue and checkpoint
+    fn name(&self) -> &'static str;
+
+    // Where the sink writes, for logs
+    fn describe(&self) -> String;
+
+    // Prepares the backend up front (connecting, creating tables) so problems show before fetching
+    fn connect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>>;
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>>;
+    fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>>;
+
+    // Makes everything written so far durable
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // Delivers a whole batch: by default its blocks, transactions and receipts, then a flush.
+    // Backends that keep the derived datasets too, or load a batch atomically, override it.
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, _output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.write_blocks(&batch.blocks).await?;
+            self.write_transactions(&batch.transactions).await?;
+            self.write_receipts(&batch.receipts).await?;
+            self.flush().await
+        })
+    }
+
+    // The block after the highest one the backend has durably stored, for backends that commit
+    // their own checkpoint with the rows; `fanout` trusts it over its checkpoint file
+    fn checkpoint(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    // What a dry run would deliver, one line per table, given the rows per dataset; an error means
+    // the backend is unreachable
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move {
+            let datasets = ["blocks", "transactions", "receipts"];
+            let rows = rows.iter().filter(|plan| plan.records > 0 && datasets.contains(&plan.dataset));
+            Ok(rows.map(|plan| format!("{} {}: {} rows", self.describe(), plan.dataset, plan.records)).collect())
+        })
+    }
+}
+
+// Builds a backend's sink from its environment, None when it is not configured
+pub type SinkConstructor = fn(OutputConfig) -> Result<Option<Box<dyn Sink>>>;
+
+pub struct SinkFactory {
+    pub name: &'static str,
+    // How the backend is configured, for errors and `--help`
+    pub configured_by: &'static str,
+    pub from_env: SinkConstructor,
+}
+
+pub const REGISTRY: &[SinkFactory] = &[
+    SinkFactory { name: "clickhouse", configured_by: "CLICKHOUSE_URL", from_env: clickhouse::sink_from_env },
+    SinkFactory { name: "duckdb", configured_by: "DUCKDB_PATH", from_env: duckdb::sink_from_env },
+    #[cfg(feature = "postgres")]
+    SinkFactory { name: "postgres", configured_by: "POSTGRES_URL", from_env: postgres::sink_from_env },
+    #[cfg(feature = "nats")]
+    SinkFactory { name: "nats", configured_by: "NATS_URL", from_env: nats::sink_from_env },
+    #[cfg(feature = "redis")]
+    SinkFactory { name: "redis", configured_by: "REDIS_URL", from_env: redis::sink_from_env },
+    #[cfg(feature = "bigquery")]
+    SinkFactory { name: "bigquery", configured_by: "BIGQUERY_PROJECT", from_env: bigquery::sink_from_env },
+    SinkFactory { name: "webhook", configured_by: "WEBHOOK_URL", from_env: webhook::sink_from_env },
+];
+
+// The sinks named in `names`, or every configured one when `names` is empty. A named sink whose
+// environment is missing is an error rather than silently left out.
+pub fn from_env(names: &[String], output: OutputConfig) -> Result<Vec<Box<dyn Sink>>> {
+    let known = || REGISTRY.iter().map(|factory| factory.name).collect::<Vec<_>>().join(", ");
+    for name in names {
+        if !REGISTRY.iter().any(|factory| factory.name == name) {
+            return Err(anyhow::anyhow!("Unknown sink {}; available: {}", name, known()));
+        }
+    }
+    let mut sinks = Vec::new();
+    for factory in REGISTRY {
+        let selected = names.iter().any(|name| name == factory.name);
+        match (factory.from_env)(output)? {
+            Some(sink) if names.is_empty() || selected => sinks.push(sink),
+            None if selected => return Err(anyhow::anyhow!("Sink {} is selected but {} is not set", factory.name, factory.configured_by)),
+            _ => {},
+        }
+    }
+    Ok(sinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn record(&self, call: &str, rows: usize) -> BoxFuture<'_, Result<()>> {
+            self.0.lock().unwrap().push(format!("{} {}", call, rows));
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    impl Sink for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn describe(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+            self.record("blocks", blocks.len())
+        }
+
+        fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+            self.record("transactions", transactions.len())
+        }
+
+        fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+            self.record("receipts", receipts.len())
+        }
+
+        fn flush(&self) -> BoxFuture<'_, Result<()>> {
+            self.record("flush", 0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_batch_write_and_registry() {
+        let sink = Recorder::default();
+        sink.write_batch(&TransformedBatch::default(), OutputConfig::default()).await.unwrap();
+        assert_eq!(*sink.0.lock().unwrap(), vec!["blocks 0", "transactions 0", "receipts 0", "flush 0"]);
+
+        let error = from_env(&["kafka".to_string()], OutputConfig::default()).err().unwrap();
+        assert!(error.to_string().starts_with("Unknown sink kafka; available: clickhouse, duckdb"));
+    }
+}
@@ -0,0 +1,445 @@
+//! PostgreSQL through its command line client (`psql`), like the DuckDB sink, so no client library
+//! is linked. With `POSTGRES_URL` set, `index` loads every batch into one table per dataset.
+//!
+//! A batch is loaded exactly once: its rows and the sink's checkpoint (`sweet_potato_checkpoints`)
+//! are written in the same transaction, so the database never holds rows its checkpoint does not
+//! cover or the reverse. The checkpoint also keeps a fingerprint of the last batch committed; a
+//! batch replayed after a crash between the commit and `fanout` updating its own checkpoint file
+//! matches it and is skipped. `fanout` trusts the database checkpoint over the file.
+//!
+//! Tables are created from the ClickHouse columns: wei amounts and uint256 values are `numeric`,
+//! arrays are `jsonb`, and the fields a dataset does not name (the RPC extras) go to `extras`.
+//!
+//! Every block row inserted sends a `NOTIFY` on `POSTGRES_NOTIFY_CHANNEL` (`sweet_potato_blocks`
+//! unless set, empty to turn it off) with a small JSON payload: `number`, `hash`, `datetime` and
+//! `transactions` (the count). Notifications are sent from the load transaction, so listeners only
+//! hear about committed blocks, in block order, and can query the tables right away.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::avro::WEI_COLUMNS;
+use crate::config::env_or;
+use crate::migrate::{self, Migration};
+use crate::models::{
+    AddressDimension, Balance, BlockDataUsage, BridgeMessage, ContractState, DataQualityIssue, EventLog, NftTransfer, OrderingAnalysis, TokenTransfer, TransformedBlock, TransformedContract,
+    TransformedReceipt, TransformedTransaction,
+};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::clickhouse::{columns_for, key_columns, EXTRAS_COLUMN};
+use crate::sinks::Sink;
+use crate::store::{self, OutputConfig, Record, WritePlan, WritePolicy, SCHEMA_VERSION};
+use crate::trie::encode_hex;
+
+pub const CHECKPOINT_TABLE: &str = "sweet_potato_checkpoints";
+// Row of this sink in the checkpoint table, shared by every indexer writing to the database
+const SINK_NAME: &str = "postgres";
+const NOTIFY_CHANNEL: &str = "sweet_potato_blocks";
+
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    pub binary: String,
+    pub url: String,
+    // Channel notified of every inserted block, None for no notifications
+    pub notify_channel: Option<String>,
+}
+
+impl PostgresConfig {
+    // The sink is only enabled when POSTGRES_URL is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("POSTGRES_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let notify_channel = match std::env::var("POSTGRES_NOTIFY_CHANNEL") {
+            Ok(channel) => Some(channel.trim().to_string()).filter(|channel| !channel.is_empty()),
+            Err(_) => Some(NOTIFY_CHANNEL.to_string()),
+        };
+        Ok(Some(Self { binary: env_or("PSQL_BIN", "psql".to_string())?, url, notify_channel }))
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+// Column names such as `from` and `to` are reserved words
+fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn column_type(name: &str, clickhouse_type: &str) -> String {
+    let inner = clickhouse_type.strip_prefix("Nullable(").and_then(|t| t.strip_suffix(')')).unwrap_or(clickhouse_type);
+    match inner {
+        // Wei amounts do not fit a bigint
+        "UInt64" if WEI_COLUMNS.contains(&name) => "numeric(20, 0)",
+        "UInt64" | "UInt32" => "bigint",
+        "UInt8" => "smallint",
+        "UInt256" => "numeric(78, 0)",
+        "Bool" => "boolean",
+        "Float64" => "double precision",
+        "DateTime" => "timestamptz",
+        t if t.starts_with("Array(") => "jsonb",
+        _ => "text",
+    }
+    .to_string()
+}
+
+// Column holding the block number of a dataset's rows
+fn block_column(dataset: &str) -> &'static str {
+    match dataset {
+        "blocks" => "number",
+        // Upserted by address: a newer row replaces the address's previous one
+        "addresses" => "address",
+        _ => "block_number",
+    }
+}
+
+pub fn create_table_sql(dataset: &str) -> String {
+    let columns: Vec<String> = columns_for(dataset)
+        .iter()
+        .map(|(name, clickhouse_type)| format!("{} {}", ident(name), column_type(name, clickhouse_type)))
+        .chain([format!("{} jsonb", ident(EXTRAS_COLUMN))])
+        .chain(match key_columns(dataset) {
+            [] => None,
+            key => Some(format!("PRIMARY KEY ({})", key.iter().map(|name| ident(name)).collect::<Vec<_>>().join(", "))),
+        })
+        .collect();
+    format!("CREATE TABLE IF NOT EXISTS {} ({});\n", dataset, columns.join(", "))
+}
+
+fn checkpoint_table_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (sink text PRIMARY KEY, next_block bigint NOT NULL, last_batch text, updated_at timestamptz NOT NULL);\n",
+        CHECKPOINT_TABLE
+    )
+}
+
+// Stamps a new database with the current schema version
+fn stamp_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS schema_version (version integer NOT NULL);\n\
+         INSERT INTO schema_version SELECT {} WHERE NOT EXISTS (SELECT 1 FROM schema_version);\n",
+        SCHEMA_VERSION
+    )
+}
+
+// Stamps a new database and fails the script on one holding another schema version
+fn version_sql() -> String {
+    format!(
+        "{}DO $$ BEGIN IF (SELECT max(version) FROM schema_version) <> {v} THEN \
+         RAISE EXCEPTION 'PostgreSQL database holds schema version %, run `migrate` to upgrade it to version {v}', (SELECT max(version) FROM schema_version); \
+         END IF; END $$;\n",
+        stamp_sql(),
+        v = SCHEMA_VERSION
+    )
+}
+
+// Statements of the migrations for the tables that exist, and the stamp of each version, in one
+// transaction. Missing tables are created by `connect` with the current schema.
+fn migrate_sql(migrations: &[&Migration], tables: &BTreeSet<&str>) -> Result<String> {
+    let mut sql = String::from("BEGIN;\n");
+    for migration in migrations {
+        if tables.contains(migration.dataset) {
+            for statement in migration.postgres {
+                writeln!(sql, "{};", migrate::statement(statement, migration.dataset))?;
+            }
+        }
+        writeln!(sql, "INSERT INTO schema_version VALUES ({});", migration.version)?;
+    }
+    sql.push_str("COMMIT;\n");
+    Ok(sql)
+}
+
+// Identifies a batch by its content, so a replay of the last committed one is recognized
+pub fn fingerprint(batch: &TransformedBatch) -> Result<String> {
+    Ok(encode_hex(&Sha256::digest(serde_json::to_vec(batch)?)))
+}
+
+// What the checkpoint table records for this sink: the block after the highest one committed and
+// the fingerprint of the last batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredCheckpoint {
+    pub next_block: u64,
+    pub last_batch: Option<String>,
+}
+
+async fn execute(config: &PostgresConfig, args: &[&str]) -> Result<String> {
+    let output = Command::new(&config.binary)
+        .arg(&config.url)
+        .args(["-X", "-q", "-A", "-t", "-v", "ON_ERROR_STOP=1"])
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not run {} (is the PostgreSQL client installed?): {}", config.binary, e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("PostgreSQL failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub struct PostgresSink {
+    config: PostgresConfig,
+}
+
+impl PostgresSink {
+    pub fn new(config: PostgresConfig) -> Self {
+        Self { config }
+    }
+
+    // Stages the records as ndjson and appends the statements loading them into their table. Each
+    // line is copied as one jsonb value and mapped onto the table's columns by name.
+    fn stage<T: Record>(&self, dir: &Path, records: &[T], policy: WritePolicy, sql: &mut String) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let path = dir.join(format!("{}.ndjson", T::DATASET));
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record)?;
+            data.push(b'\n');
+        }
+        std::fs::write(&path, data)?;
+
+        let (table, column) = (T::DATASET, ident(block_column(T::DATASET)));
+        let staged = format!("staged_{}", table);
+        let named: Vec<String> = columns_for(table).iter().map(|(name, _)| quote(name)).collect();
+        sql.push_str(&create_table_sql(table));
+        writeln!(sql, "CREATE TEMP TABLE {} (line jsonb) ON COMMIT DROP;", staged)?;
+        // CSV with quote and delimiter characters that never occur in JSON, so each line is one value
+        writeln!(sql, "\\copy {} (line) FROM {} WITH (FORMAT csv, QUOTE E'\\x01', DELIMITER E'\\x02')", staged, quote(&path.to_string_lossy()))?;
+        writeln!(
+            sql,
+            "CREATE TEMP TABLE rows_{table} ON COMMIT DROP AS SELECT r.* FROM {staged}, \
+             jsonb_populate_record(NULL::{table}, line || jsonb_build_object({extras}, line - ARRAY[{named}])) r;",
+            extras = quote(EXTRAS_COLUMN),
+            named = named.join(", ")
+        )?;
+        let insert = match policy {
+            WritePolicy::Overwrite => {
+                writeln!(sql, "DELETE FROM {table} WHERE {column} IN (SELECT {column} FROM rows_{table});")?;
+                format!("INSERT INTO {table} SELECT * FROM rows_{table}")
+            },
+            WritePolicy::SkipExisting => format!("INSERT INTO {table} SELECT * FROM rows_{table} WHERE {column} NOT IN (SELECT {column} FROM {table})"),
+        };
+        match &self.config.notify_channel {
+            // Only the rows actually inserted are announced, once the transaction commits
+            Some(channel) if table == "blocks" => writeln!(
+                sql,
+                "WITH inserted AS ({insert} RETURNING *) SELECT count(pg_notify({channel}, json_build_object('number', \"number\", 'hash', hash, \
+                 'datetime', datetime, 'transactions', jsonb_array_length(transaction_hashes))::text)) FROM (SELECT * FROM inserted ORDER BY \"number\") i;",
+                channel = quote(channel)
+            )?,
+            _ => writeln!(sql, "{};", insert)?,
+        }
+        Ok(())
+    }
+
+    // Loads every dataset of the batch and advances the checkpoint in one transaction
+    pub fn load_script(&self, dir: &Path, batch: &TransformedBatch, output: OutputConfig) -> Result<String> {
+        let policy = output.write_policy;
+        let mut sql = format!("{}BEGIN;\n", version_sql());
+        sql.push_str(&checkpoint_table_sql());
+        self.stage::<TransformedBlock>(dir, &batch.blocks, policy, &mut sql)?;
+        self.stage::<TransformedTransaction>(dir, &batch.transactions, policy, &mut sql)?;
+        self.stage::<TransformedReceipt>(dir, &store::written_receipts(output, &batch.receipts), policy, &mut sql)?;
+        self.stage::<EventLog>(dir, &batch.logs, policy, &mut sql)?;
+        self.stage::<TransformedContract>(dir, &batch.contracts, policy, &mut sql)?;
+        self.stage::<BlockDataUsage>(dir, &batch.data_usage, policy, &mut sql)?;
+        self.stage::<OrderingAnalysis>(dir, &batch.ordering_analysis, policy, &mut sql)?;
+        self.stage::<NftTransfer>(dir, &batch.nft_transfers, policy, &mut sql)?;
+        self.stage::<TokenTransfer>(dir, &batch.token_transfers, policy, &mut sql)?;
+        self.stage::<BridgeMessage>(dir, &batch.bridge_messages, policy, &mut sql)?;
+        self.stage::<Balance>(dir, &batch.balances, policy, &mut sql)?;
+        self.stage::<ContractState>(dir, &batch.contract_state, policy, &mut sql)?;
+        self.stage::<AddressDimension>(dir, &batch.addresses, WritePolicy::Overwrite, &mut sql)?;
+        self.stage::<DataQualityIssue>(dir, &batch.data_quality, policy, &mut sql)?;
+        let next_block = batch.blocks.iter().map(|b| b.number + 1).max().unwrap_or_default();
+        writeln!(
+            sql,
+            "INSERT INTO {t} (sink, next_block, last_batch, updated_at) VALUES ({}, {}, {}, now()) \
+             ON CONFLICT (sink) DO UPDATE SET next_block = GREATEST({t}.next_block, EXCLUDED.next_block), \
+             last_batch = EXCLUDED.last_batch, updated_at = EXCLUDED.updated_at;",
+            quote(SINK_NAME),
+            next_block,
+            quote(&fingerprint(batch)?),
+            t = CHECKPOINT_TABLE
+        )?;
+        sql.push_str("COMMIT;\n");
+        Ok(sql)
+    }
+
+    pub async fn stored_checkpoint(&self) -> Result<Option<StoredCheckpoint>> {
+        let sql = format!(
+            "{}SELECT next_block || '|' || coalesce(last_batch, '') FROM {} WHERE sink = {};",
+            checkpoint_table_sql(),
+            CHECKPOINT_TABLE,
+            quote(SINK_NAME)
+        );
+        let output = execute(&self.config, &["-c", &sql]).await?;
+        let Some((next_block, last_batch)) = output.lines().last().and_then(|line| line.trim().split_once('|')) else {
+            return Ok(None);
+        };
+        Ok(Some(StoredCheckpoint {
+            next_block: next_block.parse().map_err(|_| anyhow::anyhow!("Unexpected PostgreSQL checkpoint {:?}", next_block))?,
+            last_batch: Some(last_batch.to_string()).filter(|batch| !batch.is_empty()),
+        }))
+    }
+
+    // Loads a batch unless it is the one the checkpoint says was committed last. A failed load
+    // rolls back whole, rows and checkpoint alike.
+    pub async fn insert(&self, batch: &TransformedBatch, output: OutputConfig) -> Result<()> {
+        let batch_fingerprint = fingerprint(batch)?;
+        if self.stored_checkpoint().await?.and_then(|stored| stored.last_batch).is_some_and(|last| last == batch_fingerprint) {
+            log::info!("Batch is already committed to PostgreSQL, skipping it");
+            return Ok(());
+        }
+        self.load(|dir| self.load_script(dir, batch, output)).await
+    }
+
+    // Schema version of the database, stamping a new one with the current version
+    pub async fn schema_version(&self) -> Result<u32> {
+        let output = execute(&self.config, &["-c", &format!("{}SELECT max(version) FROM schema_version;", stamp_sql())]).await?;
+        let version = output.lines().last().unwrap_or_default().trim();
+        version.parse().map_err(|_| anyhow::anyhow!("Unexpected PostgreSQL schema version {:?}", version))
+    }
+
+    // Runs the pending migrations' statements and stamps each version in one transaction,
+    // returning the version the database was at and the migrations applied
+    pub async fn migrate(&self, dry_run: bool) -> Result<(u32, Vec<&'static Migration>)> {
+        let from = self.schema_version().await?;
+        let migrations: Vec<&Migration> = migrate::pending(from).collect();
+        if dry_run || migrations.is_empty() {
+            return Ok((from, migrations));
+        }
+        let tables = execute(&self.config, &["-c", "SELECT table_name FROM information_schema.tables WHERE table_schema = current_schema();"]).await?;
+        let tables: BTreeSet<&str> = tables.lines().map(str::trim).collect();
+        self.load(|_| migrate_sql(&migrations, &tables)).await?;
+        Ok((from, migrations))
+    }
+
+    // Runs a load script staged in a scratch directory
+    async fn load(&self, script: impl FnOnce(&Path) -> Result<String>) -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("sweet-potato-postgres-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let result = async {
+            let path = dir.join("load.sql");
+            std::fs::write(&path, script(&dir)?)?;
+            execute(&self.config, &["-f", &path.to_string_lossy()]).await
+        }
+        .await;
+        let _ = std::fs::remove_dir_all(&dir);
+        result.map(|_| ())
+    }
+
+    // Loads the records of one dataset, replacing the blocks the table already holds. The
+    // checkpoint only moves with whole batches.
+    async fn insert_records<T: Record>(&self, records: &[T]) -> Result<()> {
+        self.load(|dir| {
+            let mut sql = format!("{}BEGIN;\n", version_sql());
+            self.stage(dir, records, WritePolicy::Overwrite, &mut sql)?;
+            sql.push_str("COMMIT;\n");
+            Ok(sql)
+        })
+        .await
+    }
+}
+
+pub fn sink_from_env(_output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    Ok(PostgresConfig::from_env()?.map(|config| Box::new(PostgresSink::new(config)) as Box<dyn Sink>))
+}
+
+impl Sink for PostgresSink {
+    fn name(&self) -> &'static str {
+        SINK_NAME
+    }
+
+    fn describe(&self) -> String {
+        // Leave credentials out of logs
+        let url = self.config.url.split_once('@').map(|(_, host)| host).unwrap_or(&self.config.url);
+        format!("PostgreSQL {}", url)
+    }
+
+    fn connect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let tables: String = crate::sinks::clickhouse::TABLES.iter().map(|(table, _)| create_table_sql(table)).collect();
+            let sql = format!("{}{}{}", version_sql(), checkpoint_table_sql(), tables);
+            execute(&self.config, &["-c", &sql]).await.map(|_| ())
+        })
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(blocks))
+    }
+
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(transactions))
+    }
+
+    fn write_receipts<'a>(&'a self, receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert_records(receipts))
+    }
+
+    // Every dataset and the checkpoint in one transaction
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.insert(batch, output))
+    }
+
+    fn checkpoint(&self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async move { Ok(self.stored_checkpoint().await?.map(|stored| stored.next_block)) })
+    }
+
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        let lines = rows.iter().filter(|p| p.records > 0);
+        let lines = lines.map(|plan| format!("{} table {}: {} rows", self.describe(), plan.dataset, plan.records)).collect();
+        Box::pin(async { Ok(lines) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::transform::ROW_ID_STRIDE;
+
+    #[test]
+    fn test_load_script() {
+        let root = "test_postgres_load";
+        let _ = std::fs::remove_dir_all(root);
+        std::fs::create_dir_all(root).unwrap();
+        let batch = TransformedBatch { blocks: vec![fixtures::block(7)], ..Default::default() };
+        let config = PostgresConfig { binary: "psql".to_string(), url: "postgres://localhost/chain".to_string(), notify_channel: Some("blocks_in".to_string()) };
+        let sink = PostgresSink::new(config);
+        let script = sink.load_script(Path::new(root), &batch, OutputConfig { write_policy: WritePolicy::SkipExisting, ..Default::default() }).unwrap();
+
+        assert!(std::fs::read_to_string(format!("{}/blocks.ndjson", root)).unwrap().contains("\"number\":7"));
+        let create = create_table_sql("blocks");
+        assert!(create.contains("\"base_fee_per_gas\" numeric(20, 0), \"difficulty\" numeric(20, 0)"));
+        assert!(create.contains("\"gas_used\" bigint") && create.contains("\"uncles\" jsonb") && create.ends_with("\"extras\" jsonb);\n"));
+        assert!(create_table_sql("transactions").contains("\"from\" text"));
+        assert!(create_table_sql("nft_transfers").ends_with("\"extras\" jsonb, PRIMARY KEY (\"id\", \"batch_index\"));\n"));
+
+        let rows = script.find("WITH inserted AS (INSERT INTO blocks SELECT * FROM rows_blocks WHERE \"number\" NOT IN").unwrap();
+        assert!(script.contains("RETURNING *) SELECT count(pg_notify('blocks_in', json_build_object('number', \"number\""));
+        let checkpoint = script.find(&format!("INSERT INTO {} (sink, next_block, last_batch, updated_at) VALUES ('postgres', 8, ", CHECKPOINT_TABLE)).unwrap();
+        assert!(script.find("BEGIN;").unwrap() < rows && rows < checkpoint && checkpoint < script.find("COMMIT;").unwrap());
+        assert!(script.contains(&quote(&fingerprint(&batch).unwrap())));
+        assert_eq!(ident("from"), "\"from\"");
+
+        let migrations: Vec<&Migration> = migrate::pending(2).collect();
+        let sql = migrate_sql(&migrations, &BTreeSet::from(["logs"])).unwrap();
+        // No migration alters the logs table; the versions are stamped all the same
+        assert!(sql.starts_with("BEGIN;\nINSERT INTO schema_version VALUES (3);\n") && !sql.contains("ALTER TABLE"));
+        assert!(sql.ends_with(&format!("INSERT INTO schema_version VALUES ({});\nCOMMIT;\n", SCHEMA_VERSION)));
+        let sql = migrate_sql(&migrations, &BTreeSet::from(["blocks", "transactions"])).unwrap();
+        assert!(sql.contains("VALUES (3);\nALTER TABLE transactions ADD COLUMN IF NOT EXISTS status boolean, "));
+        assert!(sql.contains(&format!("UPDATE transactions SET id = block_number * {} + transaction_index;\n", ROW_ID_STRIDE)));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,196 @@
+//! Redis Streams for real-time dashboards. With `REDIS_URL` set, `index` appends every written
+//! block and transaction to the streams `<REDIS_PREFIX>:blocks` and `<REDIS_PREFIX>:transactions`,
+//! trimmed to about `REDIS_STREAM_MAXLEN` entries each. It also keeps `<REDIS_PREFIX>:latest_block`
+//! (the block as JSON) and `<REDIS_PREFIX>:latest_block_number` at the highest block written.
+//! Readers get fresh data with `XREAD BLOCK` or `GET` instead of polling the store.
+//!
+//! Stream entries have the fields `number` (the block number), `hash` and `data` (the record as
+//! JSON). A batch is sent as one MULTI/EXEC transaction. The latest block only moves forward, so
+//! healing a gap or re-indexing an old range leaves it alone; re-indexing the same height (after
+//! a reorg) replaces it.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::config::env_or;
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::Sink;
+use crate::store::{OutputConfig, WritePlan};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Sets the latest block unless a higher one is stored. KEYS: latest_block, latest_block_number;
+// ARGV: number, block JSON
+const SET_LATEST: &str = "if tonumber(ARGV[1]) >= tonumber(redis.call('GET', KEYS[2]) or '-1') then \
+                          redis.call('SET', KEYS[1], ARGV[2]) redis.call('SET', KEYS[2], ARGV[1]) end return 0";
+
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    pub url: String,
+    pub prefix: String,
+    // Approximate entries kept per stream, 0 to keep everything
+    pub max_len: u64,
+}
+
+impl RedisConfig {
+    // The sink is only enabled when REDIS_URL is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("REDIS_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ => return Ok(None),
+        };
+        Ok(Some(Self {
+            url,
+            prefix: env_or("REDIS_PREFIX", "sweet_potato".to_string())?,
+            max_len: env_or("REDIS_STREAM_MAXLEN", 10_000)?,
+        }))
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{}", self.prefix, name)
+    }
+}
+
+fn xadd(pipe: &mut redis::Pipeline, config: &RedisConfig, dataset: &str, number: u64, hash: &str, data: String) {
+    let command = pipe.cmd("XADD").arg(config.key(dataset));
+    if config.max_len > 0 {
+        command.arg("MAXLEN").arg("~").arg(config.max_len);
+    }
+    command.arg("*").arg("number").arg(number).arg("hash").arg(hash).arg("data").arg(data).ignore();
+}
+
+// The transaction delivering blocks and transactions, None when there is nothing to send
+pub fn pipeline(config: &RedisConfig, blocks: &[TransformedBlock], transactions: &[TransformedTransaction]) -> Result<Option<redis::Pipeline>> {
+    if blocks.is_empty() && transactions.is_empty() {
+        return Ok(None);
+    }
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for block in blocks {
+        xadd(&mut pipe, config, "blocks", block.number, &block.hash, serde_json::to_string(block)?);
+    }
+    for tx in transactions {
+        xadd(&mut pipe, config, "transactions", tx.block_number, &tx.hash, serde_json::to_string(tx)?);
+    }
+    if let Some(latest) = blocks.iter().max_by_key(|block| block.number) {
+        pipe.cmd("EVAL")
+            .arg(SET_LATEST)
+            .arg(2)
+            .arg(config.key("latest_block"))
+            .arg(config.key("latest_block_number"))
+            .arg(latest.number)
+            .arg(serde_json::to_string(latest)?)
+            .ignore();
+    }
+    Ok(Some(pipe))
+}
+
+pub struct RedisSink {
+    config: RedisConfig,
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl RedisSink {
+    pub fn new(config: RedisConfig) -> Self {
+        Self { config, connection: OnceCell::new() }
+    }
+
+    // One connection per process, reconnecting by itself after failures
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| async {
+                let client = redis::Client::open(self.config.url.as_str())?;
+                tokio::time::timeout(CONNECT_TIMEOUT, ConnectionManager::new(client))
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Timed out connecting to Redis"))?
+                    .map_err(anyhow::Error::from)
+            })
+            .await?;
+        Ok(connection.clone())
+    }
+
+    async fn send(&self, blocks: &[TransformedBlock], transactions: &[TransformedTransaction]) -> Result<()> {
+        let Some(pipe) = pipeline(&self.config, blocks, transactions)? else {
+            return Ok(());
+        };
+        let mut connection = self.connection().await?;
+        pipe.query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+}
+
+pub fn sink_from_env(_output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    Ok(RedisConfig::from_env()?.map(|config| Box::new(RedisSink::new(config)) as Box<dyn Sink>))
+}
+
+impl Sink for RedisSink {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn describe(&self) -> String {
+        // Leave credentials out of logs
+        let url = self.config.url.rsplit_once('@').map(|(_, host)| host).unwrap_or(&self.config.url);
+        format!("Redis {} ({}:*)", url.trim_start_matches("redis://"), self.config.prefix)
+    }
+
+    fn connect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            redis::cmd("PING").query_async::<()>(&mut connection).await?;
+            Ok(())
+        })
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.send(blocks, &[]))
+    }
+
+    fn write_transactions<'a>(&'a self, transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.send(&[], transactions))
+    }
+
+    // Dashboards read blocks and transactions; receipts stay in the store
+    fn write_receipts<'a>(&'a self, _receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // Blocks, transactions and the latest block in one transaction
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, _output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.send(&batch.blocks, &batch.transactions))
+    }
+
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        let lines = rows.iter().filter(|plan| plan.records > 0 && ["blocks", "transactions"].contains(&plan.dataset));
+        let lines = lines.map(|plan| format!("{} stream {}: {} entries", self.describe(), self.config.key(plan.dataset), plan.records)).collect();
+        Box::pin(async { Ok(lines) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::block;
+
+    #[test]
+    fn test_pipeline() {
+        let config = RedisConfig { url: "redis://localhost".into(), prefix: "chain".into(), max_len: 500 };
+        assert!(pipeline(&config, &[], &[]).unwrap().is_none());
+
+        let packed = pipeline(&config, &[block(8), block(7)], &[]).unwrap().unwrap().get_packed_pipeline();
+        let commands = String::from_utf8_lossy(&packed);
+        assert!(commands.starts_with("*1\r\n$5\r\nMULTI\r\n"));
+        assert_eq!(commands.matches("$12\r\nchain:blocks\r\n$6\r\nMAXLEN\r\n$1\r\n~\r\n$3\r\n500\r\n").count(), 2);
+        // Only the highest block of the batch is offered as the latest one
+        assert!(commands.contains("$18\r\nchain:latest_block\r\n$25\r\nchain:latest_block_number\r\n$1\r\n8\r\n"));
+
+        let unbounded = RedisConfig { max_len: 0, ..config };
+        let packed = pipeline(&unbounded, &[block(1)], &[]).unwrap().unwrap().get_packed_pipeline();
+        assert!(!String::from_utf8_lossy(&packed).contains("MAXLEN"));
+    }
+}
@@ -0,0 +1,217 @@
+//! HTTP webhook: with `WEBHOOK_URL` set, `index` POSTs every written block to that URL as JSON,
+//! one request per block in block order, optionally with the block's transactions and receipts
+//! (`WEBHOOK_INCLUDE`). Blocks are posted once they are written, so `--confirmations` decides how
+//! final they are.
+//!
+//! With `WEBHOOK_SECRET` set every request carries `X-Sweet-Potato-Signature: sha256=<hex>`, the
+//! HMAC-SHA256 of the body under the secret. Connection errors, timeouts, 429 and 5xx answers are
+//! retried with exponential backoff; once the retries run out the batch is queued by `fanout` and
+//! resent from its first block, so delivery is at least once and receivers should deduplicate on
+//! `X-Sweet-Potato-Block-Hash`.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::clock;
+use crate::config::env_or;
+use crate::models::{TransformedBlock, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::Sink;
+use crate::store::{OutputConfig, WritePlan};
+use crate::trie::encode_hex;
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: Option<String>,
+    pub transactions: bool,
+    pub receipts: bool,
+    // Attempts after the first one
+    pub retries: u32,
+    pub timeout: Duration,
+}
+
+impl WebhookConfig {
+    // The sink is only enabled when WEBHOOK_URL is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("WEBHOOK_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let include = env_or("WEBHOOK_INCLUDE", String::new())?;
+        let include: Vec<&str> = include.split(',').map(str::trim).filter(|part| !part.is_empty()).collect();
+        if let Some(unknown) = include.iter().find(|part| !["transactions", "receipts"].contains(part)) {
+            return Err(anyhow::anyhow!("Unknown WEBHOOK_INCLUDE entry {}; expected transactions or receipts", unknown));
+        }
+        Ok(Some(Self {
+            url,
+            secret: std::env::var("WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty()),
+            transactions: include.contains(&"transactions"),
+            receipts: include.contains(&"receipts"),
+            retries: env_or("WEBHOOK_RETRIES", 3)?,
+            timeout: Duration::from_secs(env_or("WEBHOOK_TIMEOUT_SECS", 10)?),
+        }))
+    }
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new().chain_update(block.map(|b| b ^ 0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(block.map(|b| b ^ 0x5c)).chain_update(inner).finalize().into()
+}
+
+// One request body per block, in block order
+pub fn payloads(config: &WebhookConfig, blocks: &[TransformedBlock], transactions: &[TransformedTransaction], receipts: &[TransformedReceipt]) -> Result<Vec<(u64, String, Value)>> {
+    let mut block_transactions: BTreeMap<u64, Vec<&TransformedTransaction>> = BTreeMap::new();
+    for tx in transactions.iter().filter(|_| config.transactions) {
+        block_transactions.entry(tx.block_number).or_default().push(tx);
+    }
+    let mut block_receipts: BTreeMap<u64, Vec<&TransformedReceipt>> = BTreeMap::new();
+    for receipt in receipts.iter().filter(|_| config.receipts) {
+        block_receipts.entry(receipt.block_number).or_default().push(receipt);
+    }
+    let mut blocks: Vec<&TransformedBlock> = blocks.iter().collect();
+    blocks.sort_by_key(|block| block.number);
+    blocks
+        .into_iter()
+        .map(|block| {
+            let mut payload = json!({ "block": block });
+            if config.transactions {
+                payload["transactions"] = serde_json::to_value(block_transactions.remove(&block.number).unwrap_or_default())?;
+            }
+            if config.receipts {
+                payload["receipts"] = serde_json::to_value(block_receipts.remove(&block.number).unwrap_or_default())?;
+            }
+            Ok((block.number, block.hash.clone(), payload))
+        })
+        .collect()
+}
+
+pub struct WebhookSink {
+    config: WebhookConfig,
+    client: reqwest::Client,
+}
+
+impl WebhookSink {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self { config, client: reqwest::Client::new() }
+    }
+
+    async fn post(&self, number: u64, hash: &str, payload: &Value) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(&self.config.url)
+                .timeout(self.config.timeout)
+                .header("Content-Type", "application/json")
+                .header("X-Sweet-Potato-Block", number.to_string())
+                .header("X-Sweet-Potato-Block-Hash", hash);
+            if let Some(secret) = &self.config.secret {
+                let signature = encode_hex(&hmac_sha256(secret.as_bytes(), &body));
+                request = request.header("X-Sweet-Potato-Signature", format!("sha256={}", &signature[2..]));
+            }
+            let error = match request.body(body.clone()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let error = anyhow::anyhow!("Webhook answered {} for block {}", status, number);
+                    // Other client errors will not change on a retry
+                    if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                        return Err(error);
+                    }
+                    error
+                },
+                Err(e) => anyhow::anyhow!("Webhook request for block {} failed: {}", number, e),
+            };
+            if attempt >= self.config.retries {
+                return Err(error);
+            }
+            let backoff = Duration::from_secs(1 << attempt.min(6));
+            log::warn!("{}; retrying in {}s", error, backoff.as_secs());
+            clock::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post_blocks(&self, blocks: &[TransformedBlock], transactions: &[TransformedTransaction], receipts: &[TransformedReceipt]) -> Result<()> {
+        for (number, hash, payload) in payloads(&self.config, blocks, transactions, receipts)? {
+            self.post(number, &hash, &payload).await?;
+        }
+        Ok(())
+    }
+}
+
+pub fn sink_from_env(_output: OutputConfig) -> Result<Option<Box<dyn Sink>>> {
+    Ok(WebhookConfig::from_env()?.map(|config| Box::new(WebhookSink::new(config)) as Box<dyn Sink>))
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn describe(&self) -> String {
+        // Leave query-string tokens out of logs
+        let url = self.config.url.split_once('?').map(|(url, _)| url).unwrap_or(&self.config.url);
+        format!("webhook {}", url)
+    }
+
+    fn write_blocks<'a>(&'a self, blocks: &'a [TransformedBlock]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.post_blocks(blocks, &[], &[]))
+    }
+
+    // Transactions and receipts only travel with their blocks
+    fn write_transactions<'a>(&'a self, _transactions: &'a [TransformedTransaction]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn write_receipts<'a>(&'a self, _receipts: &'a [TransformedReceipt]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn write_batch<'a>(&'a self, batch: &'a TransformedBatch, _output: OutputConfig) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.post_blocks(&batch.blocks, &batch.transactions, &batch.receipts))
+    }
+
+    fn preview<'a>(&'a self, rows: &'a [WritePlan], _activity: usize) -> BoxFuture<'a, Result<Vec<String>>> {
+        let blocks = rows.iter().find(|plan| plan.dataset == "blocks").map_or(0, |plan| plan.records);
+        let lines = vec![format!("{}: {} requests", self.describe(), blocks)];
+        Box::pin(async { Ok(lines) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_payloads_and_signature() {
+        // RFC 4231 test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(encode_hex(&mac), "0x5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        let mut config = WebhookConfig { url: "http://localhost".into(), secret: None, transactions: false, receipts: false, retries: 0, timeout: Duration::from_secs(1) };
+        let blocks = [fixtures::block(8), fixtures::block(7)];
+        let transactions = [fixtures::transaction(8, 0), fixtures::transaction(8, 1)];
+        let bodies = payloads(&config, &blocks, &transactions, &[]).unwrap();
+        assert_eq!(bodies.iter().map(|(number, hash, _)| (*number, hash.as_str())).collect::<Vec<_>>(), [(7, "0x7"), (8, "0x8")]);
+        assert!(bodies[0].2.get("transactions").is_none());
+
+        config.transactions = true;
+        let bodies = payloads(&config, &blocks, &transactions, &[]).unwrap();
+        assert_eq!(bodies[0].2["transactions"], json!([]));
+        assert_eq!(bodies[1].2["transactions"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,156 @@
+//! Heuristics flagging probable spam and airdrop token transfers. Flagged transfers are kept, with
+//! `spam` set and the matching heuristics in `spam_reasons`:
+//!
+//! - `known_spam`: the token is on the configured spam list
+//! - `mass_transfer`: one transaction sends the token's same amount from the same sender to many
+//!   recipients, the usual shape of unsolicited airdrops
+//! - `zero_value`: nothing is transferred, as in address poisoning
+//! - `no_metadata`: metadata lookups are enabled and the token answers none of them
+//! - `suspicious_metadata`: the token's name or symbol advertises a link or a claim
+
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::models::TokenTransfer;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct SpamConfig {
+    /// File of known spam token addresses, one per line (`#` starts a comment)
+    #[arg(long = "spam-list", env = "SPAM_LIST_PATH")]
+    pub spam_list: Option<PathBuf>,
+
+    /// Recipients of identical transfers in one transaction that make it a mass transfer
+    #[arg(long = "spam-min-recipients", env = "SPAM_MIN_RECIPIENTS", default_value_t = 10)]
+    pub spam_min_recipients: usize,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self { spam_list: None, spam_min_recipients: 10 }
+    }
+}
+
+// Words that legitimate token names and symbols practically never contain
+const SUSPICIOUS: &[&str] = &["http", "www.", ".com", ".io", ".org", ".net", ".xyz", "t.me/", "claim", "visit", "reward"];
+
+#[derive(Debug)]
+pub struct SpamRules {
+    known: HashSet<String>,
+    min_recipients: usize,
+}
+
+// Heuristics only, without a spam list
+impl Default for SpamRules {
+    fn default() -> Self {
+        Self { known: HashSet::new(), min_recipients: SpamConfig::default().spam_min_recipients }
+    }
+}
+
+impl SpamRules {
+    pub fn load(config: &SpamConfig) -> Result<Self> {
+        let mut known = HashSet::new();
+        if let Some(path) = &config.spam_list {
+            let content = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+            for line in content.lines() {
+                let address = line.split('#').next().unwrap_or_default().trim();
+                if !address.is_empty() {
+                    known.insert(address.to_lowercase());
+                }
+            }
+        }
+        Ok(Self { known, min_recipients: config.spam_min_recipients.max(2) })
+    }
+
+    pub fn known_tokens(&self) -> usize {
+        self.known.len()
+    }
+}
+
+fn suspicious(text: Option<&str>) -> bool {
+    text.is_some_and(|text| {
+        let text = text.to_lowercase();
+        SUSPICIOUS.iter().any(|word| text.contains(word))
+    })
+}
+
+// Sets `spam` and `spam_reasons` on every transfer. `metadata_checked` tells whether the token
+// fields were looked up, so that missing metadata means the token did not answer.
+pub fn flag(transfers: &mut [TokenTransfer], rules: &SpamRules, metadata_checked: bool) {
+    let mut recipients: HashMap<(&str, &str, &str, &str), HashSet<&str>> = HashMap::new();
+    for transfer in transfers.iter() {
+        let key = (transfer.transaction_hash.as_str(), transfer.token_address.as_str(), transfer.from.as_str(), transfer.value.as_str());
+        recipients.entry(key).or_default().insert(transfer.to.as_str());
+    }
+    let mass: HashSet<(String, String)> = recipients
+        .into_iter()
+        .filter(|(_, to)| to.len() >= rules.min_recipients)
+        .map(|((hash, token, _, _), _)| (hash.to_string(), token.to_string()))
+        .collect();
+
+    for transfer in transfers.iter_mut() {
+        let mut reasons = Vec::new();
+        if rules.known.contains(&transfer.token_address.to_lowercase()) {
+            reasons.push("known_spam");
+        }
+        if mass.contains(&(transfer.transaction_hash.clone(), transfer.token_address.clone())) {
+            reasons.push("mass_transfer");
+        }
+        if transfer.value == "0" {
+            reasons.push("zero_value");
+        }
+        if metadata_checked && transfer.token_symbol.is_none() && transfer.token_name.is_none() && transfer.token_decimals.is_none() {
+            reasons.push("no_metadata");
+        }
+        if suspicious(transfer.token_name.as_deref()) || suspicious(transfer.token_symbol.as_deref()) {
+            reasons.push("suspicious_metadata");
+        }
+        transfer.spam = !reasons.is_empty();
+        transfer.spam_reasons = reasons.into_iter().map(str::to_string).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flag() {
+        let transfer = |hash: &str, token: &str, to: &str, value: &str, name: Option<&str>| -> TokenTransfer {
+            serde_json::from_value(json!({
+                "token_address": token, "from": "0xf", "to": to, "value": value, "token_symbol": "TKN",
+                "token_name": name, "token_decimals": 18, "value_scaled": null, "block_number": 1,
+                "transaction_hash": hash, "log_index": 0, "datetime": "2024-01-01T00:00:00Z",
+            }))
+            .unwrap()
+        };
+        let mut transfers: Vec<TokenTransfer> =
+            (0..3).map(|i| transfer("0xdrop", "0xairdrop", &format!("0xr{}", i), "100", Some("Token"))).collect();
+        transfers.push(transfer("0xpay", "0xusdc", "0xr0", "100", Some("USD Coin")));
+        transfers.push(transfer("0xpay", "0xusdc", "0xr1", "100", Some("USD Coin")));
+        transfers.push(transfer("0xpoison", "0xusdc", "0xr1", "0", Some("USD Coin")));
+        transfers.push(transfer("0xlure", "0xBAD", "0xr1", "5", Some("Visit claim-usdc.com")));
+        let mut unknown = transfer("0xq", "0xquiet", "0xr1", "5", None);
+        unknown.token_symbol = None;
+        unknown.token_decimals = None;
+        transfers.push(unknown);
+
+        let path = std::env::temp_dir().join(format!("spam_list_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "# scam tokens\n0xbad  # lure\n\n").unwrap();
+        let rules = SpamRules::load(&SpamConfig { spam_list: Some(path.clone()), spam_min_recipients: 3 }).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(rules.known_tokens(), 1);
+
+        flag(&mut transfers, &rules, true);
+        let reasons: Vec<Vec<String>> = transfers.iter().map(|t| t.spam_reasons.clone()).collect();
+        assert_eq!(reasons[0], vec!["mass_transfer"]);
+        assert!(!transfers[3].spam && !transfers[4].spam);
+        assert_eq!(reasons[5], vec!["zero_value"]);
+        assert_eq!(reasons[6], vec!["known_spam", "suspicious_metadata"]);
+        assert_eq!(reasons[7], vec!["no_metadata"]);
+
+        flag(&mut transfers, &rules, false);
+        assert!(!transfers[7].spam);
+    }
+}
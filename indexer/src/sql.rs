@@ -0,0 +1,239 @@
+//! In-process SQL over the indexed files with Polars' SQL engine, for `query --embedded`: the same
+//! tables as the `duckdb` client path, without a DuckDB install.
+//!
+//! ndjson files (compressed or not), Arrow files and Parquet files placed in a dataset's directory
+//! are scanned lazily, so only the columns and rows a statement needs are read. Polars has no
+//! scanner for the one-record-per-file json layout or for our Avro containers; those files are
+//! decoded a chunk at a time. Files with different columns are combined by name, missing ones as nulls.
+
+use anyhow::Result;
+use polars::prelude::*;
+use polars::sql::SQLContext;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::selector_stats;
+use crate::sinks::clickhouse::columns_for;
+use crate::sinks::duckdb::QueryFormat;
+use crate::store;
+
+// Records of the json and Avro layouts decoded into one frame at a time
+const DECODE_CHUNK: usize = 10_000;
+
+// Columns that hold JSON objects, which the typed scan cannot read as strings
+const NESTED_COLUMNS: &[(&str, &str)] = &[("receipts", "logs")];
+
+// Polars type of a ClickHouse column; uint256 amounts and times stay the strings the files hold
+fn data_type(column_type: &str) -> DataType {
+    let inner = column_type.strip_prefix("Nullable(").and_then(|t| t.strip_suffix(')')).unwrap_or(column_type);
+    match inner {
+        "UInt64" | "UInt32" => DataType::UInt64,
+        "UInt8" => DataType::UInt8,
+        "Float64" => DataType::Float64,
+        "Bool" => DataType::Boolean,
+        t if t.starts_with("Array(") => DataType::List(Box::new(DataType::String)),
+        _ => DataType::String,
+    }
+}
+
+// Schema of a dataset's records, so every file reads with the same types; None lets Polars infer
+// it for datasets without ClickHouse columns
+fn dataset_schema(dataset: &str) -> Option<SchemaRef> {
+    let columns = columns_for(dataset);
+    if columns.is_empty() {
+        return None;
+    }
+    let fields = columns
+        .iter()
+        .filter(|(name, _)| !NESTED_COLUMNS.contains(&(dataset, name)))
+        .map(|(name, column_type)| Field::new((*name).into(), data_type(column_type)));
+    Some(Arc::new(Schema::from_iter(fields)))
+}
+
+// Parquet files of a dataset directory and its partition directories, sorted by path
+fn parquet_files(raw_data_path: &str, dataset: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::from(format!("{}/{}", raw_data_path, dataset))];
+    while let Some(dir) = dirs.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for path in std::fs::read_dir(&dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "parquet") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn pl_path(path: &Path) -> PlPath {
+    PlPath::new(&path.to_string_lossy())
+}
+
+// Records of files Polars cannot scan, as frames of up to DECODE_CHUNK rows
+fn decode_files(paths: &[PathBuf], schema: Option<&SchemaRef>) -> Result<Vec<LazyFrame>> {
+    let mut frames = Vec::new();
+    let (mut lines, mut rows) = (Vec::new(), 0);
+    for (position, path) in paths.iter().enumerate() {
+        for record in store::read_records(path)? {
+            serde_json::to_writer(&mut lines, &record)?;
+            lines.push(b'\n');
+            rows += 1;
+        }
+        if rows >= DECODE_CHUNK || (position + 1 == paths.len() && rows > 0) {
+            let mut reader = JsonLineReader::new(Cursor::new(std::mem::take(&mut lines))).infer_schema_len(None);
+            if let Some(schema) = schema {
+                reader = reader.with_schema(schema.clone());
+            }
+            frames.push(reader.finish()?.lazy());
+            rows = 0;
+        }
+    }
+    Ok(frames)
+}
+
+// All records of a dataset as one lazy frame, or None when nothing of it has been indexed
+fn dataset_frame(raw_data_path: &str, dataset: &str) -> Result<Option<LazyFrame>> {
+    let schema = dataset_schema(dataset);
+    let (mut ndjson, mut decoded, mut frames) = (Vec::new(), Vec::new(), Vec::new());
+    for path in store::dataset_files(raw_data_path, dataset)? {
+        if store::is_ndjson(&path) {
+            ndjson.push(pl_path(&path));
+        } else if store::is_arrow(&path) {
+            frames.push(LazyFrame::scan_ipc(pl_path(&path), ScanArgsIpc::default())?);
+        } else {
+            decoded.push(path);
+        }
+    }
+    if !ndjson.is_empty() {
+        let reader = LazyJsonLineReader::new_paths(ndjson.into()).with_infer_schema_length(None).with_schema(schema.clone());
+        frames.push(reader.finish()?);
+    }
+    frames.extend(decode_files(&decoded, schema.as_ref())?);
+    for path in parquet_files(raw_data_path, dataset)? {
+        frames.push(LazyFrame::scan_parquet(pl_path(&path), ScanArgsParquet::default())?);
+    }
+    match frames.len() {
+        0 => Ok(None),
+        1 => Ok(frames.pop()),
+        _ => Ok(Some(concat_lf_diagonal(frames, UnionArgs { to_supertypes: true, ..Default::default() })?)),
+    }
+}
+
+// Datasets, derived ones included, whose name appears in `sql` as a word
+fn tables(sql: &str) -> Vec<&'static str> {
+    let sql = sql.to_lowercase();
+    let words: Vec<&str> = sql.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').collect();
+    store::DATASETS.into_iter().chain([selector_stats::DIR]).filter(|dataset| words.contains(dataset)).collect()
+}
+
+// Columns padded to their widest value under a header, nulls as NULL like the duckdb client
+fn render_table(frame: &DataFrame) -> Result<String> {
+    let mut columns = Vec::new();
+    for column in frame.get_columns() {
+        let series = column.as_materialized_series();
+        let mut cells = vec![column.name().to_string()];
+        for row in 0..frame.height() {
+            cells.push(match series.get(row)? {
+                AnyValue::Null => "NULL".to_string(),
+                _ => series.str_value(row)?.into_owned(),
+            });
+        }
+        columns.push(cells);
+    }
+    let widths: Vec<usize> = columns.iter().map(|cells| cells.iter().map(|cell| cell.chars().count()).max().unwrap_or(0)).collect();
+    let mut output = String::new();
+    for row in 0..=frame.height() {
+        let line: Vec<String> = columns.iter().zip(&widths).map(|(cells, width)| format!("{:<width$}", cells[row], width = width)).collect();
+        output.push_str(line.join("  ").trim_end());
+        output.push('\n');
+        if row == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            output.push_str(&rule.join("  "));
+            output.push('\n');
+        }
+    }
+    output.push_str(&format!("({} rows)\n", frame.height()));
+    Ok(output)
+}
+
+fn run(raw_data_path: &str, format: QueryFormat, sql: &str) -> Result<String> {
+    let mut context = SQLContext::new();
+    for dataset in tables(sql) {
+        if let Some(frame) = dataset_frame(raw_data_path, dataset)? {
+            context.register(dataset, frame);
+        }
+    }
+    let mut result = context.execute(sql)?.collect()?;
+    let mut output = Vec::new();
+    match format {
+        QueryFormat::Table => return render_table(&result),
+        QueryFormat::Json => JsonWriter::new(&mut output).with_json_format(JsonFormat::Json).finish(&mut result)?,
+        QueryFormat::Csv => CsvWriter::new(&mut output).finish(&mut result)?,
+    }
+    Ok(String::from_utf8(output)?)
+}
+
+// Runs `sql` against the indexed files and renders the result like the `duckdb` client does. Polars
+// blocks on its own runtime while scanning, so the query runs on a blocking thread.
+pub async fn query(raw_data_path: &str, format: QueryFormat, sql: &str) -> Result<String> {
+    let (raw_data_path, sql) = (raw_data_path.to_string(), sql.to_string());
+    tokio::task::spawn_blocking(move || run(&raw_data_path, format, &sql)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_query_indexed_files() {
+        let root = "test_sql_query";
+        let _ = fs::remove_dir_all(root);
+        let block = |number: u64, gas_used: u64| {
+            json!({
+                "base_fee_per_gas": null, "difficulty": 0, "extra_data": "0x", "gas_limit": 0, "gas_used": gas_used,
+                "hash": format!("0x{:x}", number), "logs_bloom": "0x", "miner": "0x", "mix_hash": "0x", "nonce": "0x",
+                "number": number, "parent_hash": "0x", "receipts_root": "0x", "sha3_uncles": "0x", "size": 0,
+                "state_root": "0x", "total_difficulty": 0, "transaction_hashes": [], "transactions_root": "0x",
+                "uncles": [], "datetime": "2024-01-01T00:00:00Z", "extra": { "l1BatchNumber": "0x2" },
+            })
+        };
+        let transaction = |block_number: u64, index: u64| json!({ "block_number": block_number, "hash": format!("0x{}{}", block_number, index) });
+        let write = |path: &str, records: &[serde_json::Value]| {
+            let lines: Vec<String> = records.iter().map(|record| record.to_string()).collect();
+            fs::create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+            fs::write(path, lines.join("\n") + "\n").unwrap();
+        };
+        // Blocks in the ndjson layout, transactions one file per record
+        write(&format!("{}/blocks/blocks_7_8.ndjson", root), &[block(7, 21000), block(8, 0)]);
+        for (block_number, index) in [(7, 0), (7, 1), (8, 0)] {
+            write(&format!("{}/transactions/{}_{}.json", root, block_number, index), &[transaction(block_number, index)]);
+        }
+
+        let sql = "select count(*) as blocks, max(number) as last from blocks";
+        assert_eq!(query(root, QueryFormat::Csv, sql).await.unwrap(), "blocks,last\n2,8\n");
+        let sql = "select b.number, count(t.hash) as transactions from blocks b join transactions t on t.block_number = b.number \
+                   where b.gas_used > 0 group by b.number";
+        assert_eq!(query(root, QueryFormat::Csv, sql).await.unwrap(), "number,transactions\n7,2\n");
+        let json: serde_json::Value = serde_json::from_str(&query(root, QueryFormat::Json, "select number from blocks order by number").await.unwrap()).unwrap();
+        assert_eq!(json, json!([{ "number": 7 }, { "number": 8 }]));
+        let table = query(root, QueryFormat::Table, "select number, base_fee_per_gas from blocks order by number").await.unwrap();
+        assert_eq!(table, "number  base_fee_per_gas\n------  ----------------\n7       NULL\n8       NULL\n(2 rows)\n");
+
+        // A Parquet file in the dataset directory is read along with the indexed files; its
+        // missing columns are nulls
+        let mut frame = df!("number" => [9u64], "hash" => ["0x9"]).unwrap();
+        ParquetWriter::new(fs::File::create(format!("{}/blocks/extra.parquet", root)).unwrap()).finish(&mut frame).unwrap();
+        let sql = "select count(*) as blocks, count(gas_used) as with_gas_used from blocks";
+        assert_eq!(query(root, QueryFormat::Csv, sql).await.unwrap(), "blocks,with_gas_used\n3,2\n");
+        assert!(query(root, QueryFormat::Csv, "select * from receipts").await.is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! Contract state sampled with `eth_call` at every indexed block, e.g. a token's `totalSupply()`
+//! or an oracle's `latestAnswer()`. Calls are configured in a JSON file:
+//!
+//! ```json
+//! [{ "name": "usdc_supply", "contract": "0x...", "function": "totalSupply()", "returns": ["uint256"] },
+//!  { "name": "vault_balance", "contract": "0x...", "function": "balanceOf(address)",
+//!    "args": ["0x..."], "returns": ["uint256"] }]
+//! ```
+//!
+//! Arguments and return values may be `address`, `bool`, `uint<N>`, `int<N>` and `bytes<N>`;
+//! return values may also be `string`.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use primitive_types::U256;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::models::ContractState;
+use crate::rpc;
+use crate::trie::{decode_hex, encode_hex, keccak256};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateCall {
+    pub name: String,
+    pub contract: String,
+    // Canonical signature, e.g. `balanceOf(address)`
+    pub function: String,
+    #[serde(default)]
+    pub args: Vec<Value>,
+    pub returns: Vec<String>,
+}
+
+// A configured call with its calldata encoded once
+#[derive(Debug, Clone)]
+pub struct PreparedCall {
+    pub call: StateCall,
+    data: String,
+}
+
+fn param_types(signature: &str) -> Result<Vec<String>> {
+    let inner = signature
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .ok_or_else(|| anyhow::anyhow!("Invalid function signature {}", signature))?;
+    Ok(inner.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect())
+}
+
+// Bit width of `uint<N>`/`int<N>` (256 when omitted) or byte width of `bytes<N>`
+fn width(ty: &str, prefix: &str, default: usize, max: usize) -> Option<usize> {
+    let digits = ty.strip_prefix(prefix)?;
+    let width = if digits.is_empty() { default } else { digits.parse().ok()? };
+    (width > 0 && width <= max).then_some(width)
+}
+
+fn check_type(ty: &str, allow_string: bool) -> Result<()> {
+    let supported = ty == "address"
+        || ty == "bool"
+        || (ty == "string" && allow_string)
+        || width(ty, "uint", 256, 256).is_some()
+        || width(ty, "int", 256, 256).is_some()
+        || (ty != "bytes" && width(ty, "bytes", 32, 32).is_some());
+    if supported {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Unsupported ABI type {}", ty))
+    }
+}
+
+fn parse_integer(value: &Value) -> Result<(bool, U256)> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.trim().to_string(),
+        _ => return Err(anyhow::anyhow!("Expected an integer, got {}", value)),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(digits).ok(),
+    }
+    .ok_or_else(|| anyhow::anyhow!("Invalid integer {}", text))?;
+    Ok((negative, magnitude))
+}
+
+fn encode_arg(ty: &str, value: &Value) -> Result<[u8; 32]> {
+    let mut word = [0u8; 32];
+    match ty {
+        "address" => {
+            let bytes = decode_hex(value.as_str().unwrap_or_default())?;
+            if bytes.len() != 20 {
+                return Err(anyhow::anyhow!("Invalid address {}", value));
+            }
+            word[12..].copy_from_slice(&bytes);
+        },
+        "bool" => word[31] = value.as_bool().ok_or_else(|| anyhow::anyhow!("Expected a bool, got {}", value))? as u8,
+        _ if ty.starts_with("bytes") => {
+            let bytes = decode_hex(value.as_str().unwrap_or_default())?;
+            if Some(bytes.len()) != width(ty, "bytes", 32, 32) {
+                return Err(anyhow::anyhow!("Expected {} bytes for {}, got {}", ty.trim_start_matches("bytes"), ty, value));
+            }
+            word[..bytes.len()].copy_from_slice(&bytes);
+        },
+        _ => {
+            let (negative, magnitude) = parse_integer(value)?;
+            if negative && ty.starts_with("uint") {
+                return Err(anyhow::anyhow!("Negative value {} for {}", value, ty));
+            }
+            let integer = if negative { (!magnitude).overflowing_add(U256::one()).0 } else { magnitude };
+            integer.to_big_endian(&mut word);
+        },
+    }
+    Ok(word)
+}
+
+fn decode_returns(types: &[String], data: &[u8]) -> Result<Vec<String>> {
+    let word = |index: usize| {
+        data.get(index * 32..index * 32 + 32).ok_or_else(|| anyhow::anyhow!("Return data too short for {:?}", types))
+    };
+    types
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| {
+            let head = word(index)?;
+            Ok(match ty.as_str() {
+                "address" => encode_hex(&head[12..]),
+                "bool" => (head[31] != 0).to_string(),
+                "string" => {
+                    let short = || anyhow::anyhow!("Return data too short for string");
+                    // Offsets and lengths past the data cannot be valid
+                    let small = |value: U256| (value <= U256::from(data.len())).then(|| value.as_usize()).ok_or_else(short);
+                    let offset = small(U256::from_big_endian(head))?;
+                    let length = small(data.get(offset..offset + 32).map(U256::from_big_endian).ok_or_else(short)?)?;
+                    let bytes = data.get(offset + 32..offset + 32 + length).ok_or_else(short)?;
+                    String::from_utf8_lossy(bytes).into_owned()
+                },
+                _ if ty.starts_with("bytes") => encode_hex(&head[..width(ty, "bytes", 32, 32).unwrap_or(32)]),
+                _ if ty.starts_with("int") && head[0] & 0x80 != 0 => {
+                    format!("-{}", (!U256::from_big_endian(head)).overflowing_add(U256::one()).0)
+                },
+                _ => U256::from_big_endian(head).to_string(),
+            })
+        })
+        .collect()
+}
+
+impl PreparedCall {
+    pub fn new(call: StateCall) -> Result<Self> {
+        let context = |e: anyhow::Error| anyhow::anyhow!("State call {}: {}", call.name, e);
+        let types = param_types(&call.function).map_err(context)?;
+        if types.len() != call.args.len() {
+            return Err(anyhow::anyhow!("State call {}: {} takes {} arguments, {} given", call.name, call.function, types.len(), call.args.len()));
+        }
+        let mut data = keccak256(call.function.replace(' ', "").as_bytes())[..4].to_vec();
+        for (ty, arg) in types.iter().zip(&call.args) {
+            check_type(ty, false).map_err(context)?;
+            data.extend(encode_arg(ty, arg).map_err(context)?);
+        }
+        for ty in &call.returns {
+            check_type(ty, true).map_err(context)?;
+        }
+        Ok(Self { data: encode_hex(&data), call })
+    }
+
+    async fn run(&self, block_number: u64, datetime: DateTime<Utc>) -> ContractState {
+        let request = json!([{ "to": self.call.contract, "data": self.data }, format!("0x{:x}", block_number)]);
+        let result = rpc::rpc_call("eth_call", request)
+            .await
+            .and_then(|result| decode_hex(result.as_str().unwrap_or_default()))
+            .and_then(|data| decode_returns(&self.call.returns, &data));
+        let (values, error) = match result {
+            Ok(values) => (values, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        ContractState {
+            name: self.call.name.clone(),
+            contract: self.call.contract.to_lowercase(),
+            function: self.call.function.clone(),
+            block_number,
+            values,
+            error,
+            datetime,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<PreparedCall>> {
+    let content = std::fs::read(path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+    let calls: Vec<StateCall> = serde_json::from_slice(&content)?;
+    calls.into_iter().map(PreparedCall::new).collect()
+}
+
+// One row per call and block. A call that reverts or returns something undecodable is kept as a
+// row with `error` set, so gaps in the series are visible.
+pub async fn sample(calls: &[PreparedCall], blocks: &[(u64, DateTime<Utc>)], concurrency: usize) -> Vec<ContractState> {
+    let jobs = blocks.iter().flat_map(|(number, datetime)| calls.iter().map(move |call| (call, *number, *datetime)));
+    stream::iter(jobs)
+        .map(|(call, number, datetime)| call.run(number, datetime))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let call: StateCall = serde_json::from_value(json!({
+            "name": "balance", "contract": "0xToken", "function": "balanceOf(address)",
+            "args": [format!("0x{}", "ab".repeat(20))], "returns": ["uint256"],
+        }))
+        .unwrap();
+        let prepared = PreparedCall::new(call.clone()).unwrap();
+        assert_eq!(prepared.data, format!("0x70a08231{:0>64}", "ab".repeat(20)));
+
+        let wrong_args = StateCall { args: vec![], ..call.clone() };
+        assert!(PreparedCall::new(wrong_args).is_err());
+        let unsupported = StateCall { returns: vec!["uint256[]".to_string()], ..call };
+        assert!(PreparedCall::new(unsupported).is_err());
+
+        assert_eq!(encode_arg("int256", &json!(-1)).unwrap(), [0xff; 32]);
+        assert_eq!(encode_arg("uint8", &json!("0x2a")).unwrap()[31], 42);
+
+        // (int256 -5, bool true, string "ETH")
+        let words = [
+            "f".repeat(63) + "b",
+            format!("{:0>64}", "1"),
+            format!("{:0>64}", "60"),
+            format!("{:0>64}", "3"),
+            format!("{:0<64}", "455448"),
+        ];
+        let data = decode_hex(&words.concat()).unwrap();
+        let types = ["int256", "bool", "string"].map(str::to_string);
+        assert_eq!(decode_returns(&types, &data).unwrap(), vec!["-5", "true", "ETH"]);
+        assert!(decode_returns(&types, &data[..64]).is_err());
+    }
+}
@@ -0,0 +1,1356 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::activity;
+use crate::avro;
+use crate::clock;
+use crate::faults;
+use crate::models::{AddressDimension, Balance, BlockDataUsage, ContractState, BridgeMessage, DataQualityIssue, EventLog, NftTransfer, OrderingAnalysis, TokenTransfer, TransformedBlock, TransformedContract, TransformedReceipt, TransformedTransaction};
+use crate::pipeline::TransformedBatch;
+use crate::sinks::clickhouse::ClickHouseSink;
+use crate::topics;
+use crate::transform;
+
+// Block-range datasets under RAW_DATA_PATH, readable with `read_range`
+pub const DATASETS: [&str; 14] = [
+    "blocks",
+    "transactions",
+    "receipts",
+    "logs",
+    "contracts",
+    "data_usage",
+    "ordering_analysis",
+    "nft_transfers",
+    "token_transfers",
+    "bridge_messages",
+    "balances",
+    "contract_state",
+    "addresses",
+    "data_quality",
+];
+
+// Version of the dataset schemas, stamped in `raw_data/schema.json`, run manifests and the database
+// sinks. Bumped with an entry in `migrate::MIGRATIONS` when a field changes type or meaning.
+pub const SCHEMA_VERSION: u32 = 7;
+
+#[derive(Serialize, Deserialize)]
+struct SchemaStamp {
+    version: u32,
+}
+
+fn schema_path(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("schema.json")
+}
+
+// Schema version of the datasets under `raw_data_path`: as stamped, 1 for datasets written before
+// stamps existed, or the current version for a store without datasets
+pub fn schema_version(raw_data_path: &str) -> Result<u32> {
+    match fs::read(schema_path(raw_data_path)) {
+        Ok(data) => Ok(serde_json::from_slice::<SchemaStamp>(&data)?.version),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let has_data = DATASETS.iter().any(|dataset| {
+                fs::read_dir(Path::new(raw_data_path).join(dataset)).is_ok_and(|mut entries| entries.next().is_some())
+            });
+            Ok(if has_data { 1 } else { SCHEMA_VERSION })
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn write_schema_version(raw_data_path: &str, version: u32) -> Result<()> {
+    let path = schema_path(raw_data_path);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&SchemaStamp { version })?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// Root of the JSON output, shared by the indexer and the commands reading it back
+pub fn raw_data_path() -> String {
+    env::var("RAW_DATA_PATH").unwrap_or_else(|_| "./raw_data".to_string())
+}
+
+pub fn ensure_directory(path: &str) -> Result<()> {
+    if !Path::new(path).exists() {
+        fs::create_dir_all(path)?;
+    }
+    Ok(())
+}
+
+pub struct OutputDirs {
+    pub root: String,
+    pub blocks: String,
+    pub transactions: String,
+    pub receipts: String,
+    pub logs: String,
+    pub contracts: String,
+    pub data_usage: String,
+    pub ordering_analysis: String,
+    pub nft_transfers: String,
+    pub token_transfers: String,
+    pub bridge_messages: String,
+    pub balances: String,
+    pub contract_state: String,
+    pub addresses: String,
+    pub data_quality: String,
+}
+
+impl OutputDirs {
+    // The directories under `raw_data_path`, without creating them
+    pub fn new(raw_data_path: &str) -> Self {
+        OutputDirs {
+            root: raw_data_path.to_string(),
+            blocks: format!("{}/blocks", raw_data_path),
+            transactions: format!("{}/transactions", raw_data_path),
+            receipts: format!("{}/receipts", raw_data_path),
+            logs: format!("{}/logs", raw_data_path),
+            contracts: format!("{}/contracts", raw_data_path),
+            data_usage: format!("{}/data_usage", raw_data_path),
+            ordering_analysis: format!("{}/ordering_analysis", raw_data_path),
+            nft_transfers: format!("{}/nft_transfers", raw_data_path),
+            token_transfers: format!("{}/token_transfers", raw_data_path),
+            bridge_messages: format!("{}/bridge_messages", raw_data_path),
+            balances: format!("{}/balances", raw_data_path),
+            contract_state: format!("{}/contract_state", raw_data_path),
+            addresses: format!("{}/addresses", raw_data_path),
+            data_quality: format!("{}/data_quality", raw_data_path),
+        }
+    }
+
+    // Creates the directories, refusing a store holding another schema version than this build writes
+    pub fn create(raw_data_path: &str) -> Result<Self> {
+        match schema_version(raw_data_path)? {
+            version if version < SCHEMA_VERSION => {
+                return Err(anyhow::anyhow!(
+                    "{} holds schema version {} datasets; run `migrate` to upgrade them to version {}",
+                    raw_data_path, version, SCHEMA_VERSION
+                ))
+            },
+            version if version > SCHEMA_VERSION => {
+                return Err(anyhow::anyhow!(
+                    "{} holds schema version {} datasets, written by a newer indexer (this one writes version {})",
+                    raw_data_path, version, SCHEMA_VERSION
+                ))
+            },
+            _ => {},
+        }
+        let dirs = Self::new(raw_data_path);
+        ensure_directory(&dirs.blocks)?;
+        ensure_directory(&dirs.transactions)?;
+        ensure_directory(&dirs.receipts)?;
+        ensure_directory(&dirs.logs)?;
+        ensure_directory(&dirs.contracts)?;
+        ensure_directory(&dirs.data_usage)?;
+        ensure_directory(&dirs.ordering_analysis)?;
+        ensure_directory(&dirs.nft_transfers)?;
+        ensure_directory(&dirs.token_transfers)?;
+        ensure_directory(&dirs.bridge_messages)?;
+        ensure_directory(&dirs.balances)?;
+        ensure_directory(&dirs.contract_state)?;
+        ensure_directory(&dirs.addresses)?;
+        ensure_directory(&dirs.data_quality)?;
+        if !schema_path(raw_data_path).exists() {
+            write_schema_version(raw_data_path, SCHEMA_VERSION)?;
+        }
+        Ok(dirs)
+    }
+
+    pub fn dataset(&self, dataset: &str) -> String {
+        format!("{}/{}", self.root, dataset)
+    }
+}
+
+// A row of an output dataset: where it goes and which block it belongs to
+pub trait Record: Serialize + Sync {
+    // Directory, ndjson file prefix and ClickHouse table
+    const DATASET: &'static str;
+
+    fn block_number(&self) -> u64;
+
+    // Time of the record's block, for date partitions
+    fn datetime(&self) -> DateTime<Utc>;
+
+    // File name (without extension) in the one-file-per-record layout
+    fn file_name(&self) -> String;
+}
+
+impl Record for TransformedBlock {
+    const DATASET: &'static str = "blocks";
+
+    fn block_number(&self) -> u64 {
+        self.number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("block_{}", self.number)
+    }
+}
+
+impl Record for TransformedTransaction {
+    const DATASET: &'static str = "transactions";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("tx_{}", self.hash)
+    }
+}
+
+impl Record for TransformedReceipt {
+    const DATASET: &'static str = "receipts";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("receipt_{}", self.transaction_hash)
+    }
+}
+
+impl Record for EventLog {
+    const DATASET: &'static str = "logs";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("log_{}_{}", self.transaction_hash, self.log_index)
+    }
+}
+
+impl Record for TransformedContract {
+    const DATASET: &'static str = "contracts";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("contract_{}", self.address)
+    }
+}
+
+impl Record for BlockDataUsage {
+    const DATASET: &'static str = "data_usage";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("data_usage_{}", self.block_number)
+    }
+}
+
+impl Record for OrderingAnalysis {
+    const DATASET: &'static str = "ordering_analysis";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("ordering_{}", self.block_number)
+    }
+}
+
+impl Record for NftTransfer {
+    const DATASET: &'static str = "nft_transfers";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("nft_transfer_{}_{}_{}", self.transaction_hash, self.log_index, self.batch_index)
+    }
+}
+
+impl Record for TokenTransfer {
+    const DATASET: &'static str = "token_transfers";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("token_transfer_{}_{}", self.transaction_hash, self.log_index)
+    }
+}
+
+impl Record for BridgeMessage {
+    const DATASET: &'static str = "bridge_messages";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("bridge_message_{}_{}", self.transaction_hash, self.log_index)
+    }
+}
+
+impl Record for Balance {
+    const DATASET: &'static str = "balances";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("balance_{}_{}", self.address, self.block_number)
+    }
+}
+
+impl Record for ContractState {
+    const DATASET: &'static str = "contract_state";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("contract_state_{}_{}", self.name, self.block_number)
+    }
+}
+
+// Filed under the block of its latest change; in the one-file-per-record layout the file of an
+// address is replaced by each newer row
+impl Record for AddressDimension {
+    const DATASET: &'static str = "addresses";
+
+    fn block_number(&self) -> u64 {
+        self.updated_block
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("address_{}", self.address)
+    }
+}
+
+impl Record for DataQualityIssue {
+    const DATASET: &'static str = "data_quality";
+
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn datetime(&self) -> DateTime<Utc> {
+        self.datetime
+    }
+
+    fn file_name(&self) -> String {
+        format!("data_quality_{}_{}_{}", self.block_number, self.check, self.transaction_hash.as_deref().unwrap_or("block"))
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    // One pretty-printed file per record
+    #[default]
+    Json,
+    // One file per dataset per block range, one record per line
+    Ndjson,
+    // One Avro object container file per dataset per block range, schema embedded
+    Avro,
+    // One Arrow IPC file (Feather v2) per dataset per block range, uncompressed (`arrow` feature)
+    Arrow,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&data)?;
+                Ok(encoder.finish()?)
+            },
+            Compression::Zstd => Ok(zstd::encode_all(data.as_slice(), 0)?),
+        }
+    }
+
+    // Avro files compress their blocks with the matching codec instead
+    fn avro_codec(&self) -> avro::Codec {
+        match self {
+            Compression::None => avro::Codec::Null,
+            Compression::Gzip => avro::Codec::Deflate,
+            Compression::Zstd => avro::Codec::Zstandard,
+        }
+    }
+}
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct OutputConfig {
+    /// Output file layout
+    #[arg(long = "output-format", env = "OUTPUT_FORMAT", value_enum, default_value = "json")]
+    pub format: OutputFormat,
+
+    /// Compress output files
+    #[arg(long, env = "COMPRESS", value_enum, default_value = "none")]
+    pub compress: Compression,
+
+    /// Blocks per ndjson file; chunks are aligned to multiples of this size
+    #[arg(long, env = "CHUNK_SIZE", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub chunk_size: u64,
+
+    /// Also append every (address, block, transaction, role) to the address activity index
+    #[arg(long, env = "ADDRESS_INDEX")]
+    pub address_index: bool,
+
+    /// Also maintain the `addresses` dimension (contract and token flags, first-seen block, label)
+    #[arg(long, env = "ADDRESS_DIMENSION")]
+    pub address_dimension: bool,
+
+    /// Also write each receipt's `logs`, which the `logs` dataset holds on its own otherwise
+    #[arg(long = "embed-receipt-logs", env = "EMBED_RECEIPT_LOGS")]
+    pub embed_receipt_logs: bool,
+
+    /// What to do with blocks that were already written
+    #[arg(long = "write-policy", env = "WRITE_POLICY", value_enum, default_value = "overwrite")]
+    pub write_policy: WritePolicy,
+
+    /// Directories files are partitioned into within each dataset (and ClickHouse's PARTITION BY for new tables)
+    #[arg(long, env = "PARTITION", value_enum, default_value = "flat")]
+    pub partition: Partitioning,
+
+    /// Blocks per `block_range=` partition
+    #[arg(long = "partition-size", env = "PARTITION_SIZE", default_value_t = 10000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub partition_size: u64,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Partitioning {
+    // Every file directly in its dataset directory
+    #[default]
+    Flat,
+    // Hive-style `block_range=<first>_<last>/` directories of `partition_size` aligned blocks
+    BlockRange,
+    // Hive-style `dt=YYYY-MM-DD/` directories by block date (UTC)
+    Date,
+}
+
+impl OutputConfig {
+    // Partition directory of a record, relative to its dataset directory
+    pub fn partition_of<T: Record>(&self, record: &T) -> Option<String> {
+        match self.partition {
+            Partitioning::Flat => None,
+            Partitioning::BlockRange => {
+                let first = record.block_number() / self.partition_size * self.partition_size;
+                Some(format!("block_range={}_{}", first, first + self.partition_size - 1))
+            },
+            Partitioning::Date => Some(format!("dt={}", record.datetime().format("%Y-%m-%d"))),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum WritePolicy {
+    // Replace existing files, and delete existing ClickHouse rows of the batch's blocks first
+    #[default]
+    Overwrite,
+    // Leave existing files alone and only insert blocks ClickHouse does not have yet
+    SkipExisting,
+}
+
+// Compresses and writes a file on the blocking pool. The data goes to a temporary file that is
+// renamed over the target, so readers and re-runs never see a partially written file.
+async fn write_file(filename: String, data: Vec<u8>, compression: Compression) -> Result<()> {
+    faults::before_write(&filename)?;
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let data = compression.compress(data)?;
+        let tmp = format!("{}.{}.tmp", filename, std::process::id());
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &filename)?;
+        Ok(())
+    })
+    .await??;
+    Ok(())
+}
+
+// Writes one record as pretty JSON
+async fn write_json_file<T: Serialize>(filename: String, record: &T, compression: Compression) -> Result<()> {
+    let json = serde_json::to_vec_pretty(record)?;
+    write_file(filename, json, compression).await
+}
+
+// Writes records as newline-delimited JSON
+async fn write_ndjson_file<T: Serialize>(filename: String, records: &[T], compression: Compression) -> Result<()> {
+    let mut data = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut data, record)?;
+        data.push(b'\n');
+    }
+    write_file(filename, data, compression).await
+}
+
+// Writes records as one Avro object container file
+async fn write_avro_file<T: Serialize>(filename: String, dataset: &str, records: &[T], compression: Compression) -> Result<()> {
+    let records = records.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+    let data = avro::write_container(&avro::dataset_schema(dataset)?, &records, compression.avro_codec())?;
+    write_file(filename, data, Compression::None).await
+}
+
+#[cfg(feature = "arrow")]
+fn encode_arrow(dataset: &str, records: &[Value]) -> Result<Vec<u8>> {
+    crate::arrow::write_file(dataset, records)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn encode_arrow(dataset: &str, _records: &[Value]) -> Result<Vec<u8>> {
+    Err(anyhow::anyhow!("Cannot write {} as Arrow: this build was compiled without the `arrow` feature", dataset))
+}
+
+#[cfg(feature = "arrow")]
+fn decode_arrow(path: &Path) -> Result<Vec<Value>> {
+    crate::arrow::read_file(&fs::read(path)?)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn decode_arrow(path: &Path) -> Result<Vec<Value>> {
+    Err(anyhow::anyhow!("Cannot read {}: this build was compiled without the `arrow` feature", path.display()))
+}
+
+async fn write_arrow_file<T: Serialize>(filename: String, dataset: &str, records: &[T]) -> Result<()> {
+    let records = records.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+    write_file(filename, encode_arrow(dataset, &records)?, Compression::None).await
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            format: OutputFormat::default(),
+            compress: Compression::default(),
+            chunk_size: 1000,
+            address_index: false,
+            address_dimension: false,
+            embed_receipt_logs: false,
+            write_policy: WritePolicy::default(),
+            partition: Partitioning::default(),
+            partition_size: 10000,
+        }
+    }
+}
+
+// First and last block present in each aligned chunk across all datasets of a batch, so every
+// dataset of a chunk shares one file name and re-running a range reproduces the same boundaries
+fn chunk_ranges(chunk_size: u64, block_numbers: impl Iterator<Item = u64>) -> BTreeMap<u64, (u64, u64)> {
+    let mut ranges: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    for number in block_numbers {
+        ranges
+            .entry(number / chunk_size)
+            .and_modify(|(first, last)| {
+                *first = (*first).min(number);
+                *last = (*last).max(number);
+            })
+            .or_insert((number, number));
+    }
+    ranges
+}
+
+// Output files of the records, each with the records it holds
+fn files_for<'a, T: Record>(
+    dirs: &OutputDirs,
+    output: OutputConfig,
+    ranges: &BTreeMap<u64, (u64, u64)>,
+    records: &'a [T],
+) -> BTreeMap<String, Vec<&'a T>> {
+    let ext = output.compress.extension();
+    let root = dirs.dataset(T::DATASET);
+    let mut files: BTreeMap<String, Vec<&T>> = BTreeMap::new();
+    for record in records {
+        let dir = match output.partition_of(record) {
+            Some(partition) => format!("{}/{}", root, partition),
+            None => root.clone(),
+        };
+        let filename = match output.format {
+            OutputFormat::Json => format!("{}/{}.json{}", dir, record.file_name(), ext),
+            OutputFormat::Ndjson => {
+                let (first, last) = ranges[&(record.block_number() / output.chunk_size)];
+                format!("{}/{}_{}_{}.ndjson{}", dir, T::DATASET, first, last, ext)
+            },
+            OutputFormat::Avro => {
+                let (first, last) = ranges[&(record.block_number() / output.chunk_size)];
+                format!("{}/{}_{}_{}.avro", dir, T::DATASET, first, last)
+            },
+            OutputFormat::Arrow => {
+                let (first, last) = ranges[&(record.block_number() / output.chunk_size)];
+                format!("{}/{}_{}_{}.arrow", dir, T::DATASET, first, last)
+            },
+        };
+        files.entry(filename).or_default().push(record);
+    }
+    files
+}
+
+#[tracing::instrument(skip_all, fields(dataset = T::DATASET, records = records.len()))]
+async fn write_dataset<T: Record>(
+    dirs: &OutputDirs,
+    output: OutputConfig,
+    ranges: &BTreeMap<u64, (u64, u64)>,
+    records: &[T],
+    write_concurrency: usize,
+) -> Result<()> {
+    let skip = |filename: &str| output.write_policy == WritePolicy::SkipExisting && Path::new(filename).exists();
+    let files: Vec<(String, Vec<&T>)> = files_for(dirs, output, ranges, records).into_iter().filter(|(filename, _)| !skip(filename)).collect();
+    if output.partition != Partitioning::Flat {
+        let partitions: BTreeSet<&Path> = files.iter().filter_map(|(filename, _)| Path::new(filename).parent()).collect();
+        for partition in partitions {
+            fs::create_dir_all(partition)?;
+        }
+    }
+
+    stream::iter(files)
+        .map(|(filename, records)| async move {
+            match output.format {
+                // A record written twice in one batch keeps its last version
+                OutputFormat::Json => write_json_file(filename, records[records.len() - 1], output.compress).await,
+                OutputFormat::Ndjson => write_ndjson_file(filename, &records, output.compress).await,
+                OutputFormat::Avro => write_avro_file(filename, T::DATASET, &records, output.compress).await,
+                OutputFormat::Arrow => write_arrow_file(filename, T::DATASET, &records).await,
+            }
+        })
+        .buffer_unordered(write_concurrency)
+        .try_collect::<()>()
+        .await
+}
+
+// What `write_dataset` would write for a dataset, for `--dry-run`
+#[derive(Debug)]
+pub struct WritePlan {
+    pub dataset: &'static str,
+    pub records: usize,
+    // Uncompressed size of the records as they would be serialized
+    pub bytes: usize,
+    pub files: Vec<String>,
+}
+
+fn plan_dataset<T: Record>(dirs: &OutputDirs, output: OutputConfig, ranges: &BTreeMap<u64, (u64, u64)>, records: &[T]) -> Result<WritePlan> {
+    let skip = |filename: &str| output.write_policy == WritePolicy::SkipExisting && Path::new(filename).exists();
+    let mut plan = WritePlan { dataset: T::DATASET, records: 0, bytes: 0, files: Vec::new() };
+    let schema = match output.format {
+        OutputFormat::Avro => Some(avro::dataset_schema(T::DATASET)?),
+        _ => None,
+    };
+    for (filename, records) in files_for(dirs, output, ranges, records).into_iter().filter(|(filename, _)| !skip(filename)) {
+        for record in &records {
+            plan.bytes += match (output.format, &schema) {
+                (OutputFormat::Avro, Some(schema)) => avro::encode_record(schema, &serde_json::to_value(record)?)?.len(),
+                (OutputFormat::Json, _) => serde_json::to_vec_pretty(record)?.len(),
+                _ => serde_json::to_vec(record)?.len() + 1,
+            };
+        }
+        plan.records += records.len();
+        plan.files.push(filename);
+    }
+    Ok(plan)
+}
+
+// What `persist` would write for the batch, per dataset, without touching the disk
+pub fn plan_writes(dirs: &OutputDirs, output: OutputConfig, batch: &TransformedBatch) -> Result<Vec<WritePlan>> {
+    let ranges = chunk_ranges(output.chunk_size, batch_block_numbers(batch));
+    Ok(vec![
+        plan_dataset(dirs, output, &ranges, &batch.blocks)?,
+        plan_dataset(dirs, output, &ranges, &batch.transactions)?,
+        plan_dataset(dirs, output, &ranges, &written_receipts(output, &batch.receipts))?,
+        plan_dataset(dirs, output, &ranges, &batch.logs)?,
+        plan_dataset(dirs, output, &ranges, &batch.contracts)?,
+        plan_dataset(dirs, output, &ranges, &batch.data_usage)?,
+        plan_dataset(dirs, output, &ranges, &batch.ordering_analysis)?,
+        plan_dataset(dirs, output, &ranges, &batch.nft_transfers)?,
+        plan_dataset(dirs, output, &ranges, &batch.token_transfers)?,
+        plan_dataset(dirs, output, &ranges, &batch.bridge_messages)?,
+        plan_dataset(dirs, output, &ranges, &batch.balances)?,
+        plan_dataset(dirs, output, &ranges, &batch.contract_state)?,
+        plan_dataset(dirs, output, &ranges, &batch.addresses)?,
+        plan_dataset(dirs, output, &ranges, &batch.data_quality)?,
+    ])
+}
+
+// Receipts as they are stored: without their logs, which the logs dataset holds, unless
+// --embed-receipt-logs. The topic and address indexes read the logs before they are left out.
+pub fn written_receipts(output: OutputConfig, receipts: &[TransformedReceipt]) -> Cow<'_, [TransformedReceipt]> {
+    match output.embed_receipt_logs {
+        true => Cow::Borrowed(receipts),
+        false => Cow::Owned(receipts.iter().map(|receipt| TransformedReceipt { logs: Vec::new(), ..receipt.clone() }).collect()),
+    }
+}
+
+fn not_in<'a, T: Record>(records: &'a [T], blocks: &BTreeSet<u64>) -> Vec<&'a T> {
+    records.iter().filter(|record| !blocks.contains(&record.block_number())).collect()
+}
+
+// Block numbers of every record in the batch
+fn batch_block_numbers(batch: &TransformedBatch) -> impl Iterator<Item = u64> + '_ {
+    batch
+        .blocks
+        .iter()
+        .map(Record::block_number)
+        .chain(batch.transactions.iter().map(Record::block_number))
+        .chain(batch.receipts.iter().map(Record::block_number))
+        .chain(batch.logs.iter().map(Record::block_number))
+        .chain(batch.contracts.iter().map(Record::block_number))
+        .chain(batch.data_usage.iter().map(Record::block_number))
+        .chain(batch.ordering_analysis.iter().map(Record::block_number))
+        .chain(batch.nft_transfers.iter().map(Record::block_number))
+        .chain(batch.token_transfers.iter().map(Record::block_number))
+        .chain(batch.bridge_messages.iter().map(Record::block_number))
+        .chain(batch.balances.iter().map(Record::block_number))
+        .chain(batch.contract_state.iter().map(Record::block_number))
+        .chain(batch.addresses.iter().map(Record::block_number))
+        .chain(batch.data_quality.iter().map(Record::block_number))
+}
+
+// Writes the transformed records as files (up to `write_concurrency` at once) and to ClickHouse when enabled
+#[tracing::instrument(name = "write", skip_all, fields(blocks = batch.blocks.len()))]
+pub async fn persist(
+    dirs: &OutputDirs,
+    output: OutputConfig,
+    clickhouse: Option<&ClickHouseSink>,
+    batch: &TransformedBatch,
+    write_concurrency: usize,
+) -> Result<()> {
+    let ranges = chunk_ranges(output.chunk_size, batch_block_numbers(batch));
+    write_dataset(dirs, output, &ranges, &batch.blocks, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.transactions, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &written_receipts(output, &batch.receipts), write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.logs, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.contracts, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.data_usage, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.ordering_analysis, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.nft_transfers, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.token_transfers, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.bridge_messages, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.balances, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.contract_state, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.addresses, write_concurrency).await?;
+    write_dataset(dirs, output, &ranges, &batch.data_quality, write_concurrency).await?;
+
+    topics::update_index(&dirs.root, &batch.receipts)?;
+
+    if let Some(clickhouse) = clickhouse {
+        insert_clickhouse(clickhouse, output, batch).await?;
+    }
+    if output.address_index {
+        let entries = activity::extract(&batch.receipts);
+        let root = dirs.root.clone();
+        tokio::task::spawn_blocking(move || activity::append(&root, &entries)).await??;
+    }
+    Ok(())
+}
+
+// Inserts the batch into ClickHouse, applying the write policy to blocks it already holds
+pub async fn insert_clickhouse(clickhouse: &ClickHouseSink, output: OutputConfig, batch: &TransformedBatch) -> Result<()> {
+    // Blocks of this batch ClickHouse already holds: replaced on overwrite, left alone otherwise
+    let mut existing = BTreeSet::new();
+    let first = batch_block_numbers(batch).min();
+    let last = batch_block_numbers(batch).max();
+    if let (Some(first), Some(last)) = (first, last) {
+        existing = clickhouse.block_numbers_between(first, last).await?;
+        if output.write_policy == WritePolicy::Overwrite && !existing.is_empty() {
+            clickhouse.delete_blocks(&existing).await?;
+            existing.clear();
+        }
+    }
+    clickhouse.insert(TransformedBlock::DATASET, &not_in(&batch.blocks, &existing)).await?;
+    clickhouse.insert(TransformedTransaction::DATASET, &not_in(&batch.transactions, &existing)).await?;
+    clickhouse.insert(TransformedReceipt::DATASET, &not_in(&written_receipts(output, &batch.receipts), &existing)).await?;
+    clickhouse.insert(EventLog::DATASET, &not_in(&batch.logs, &existing)).await?;
+    clickhouse.insert(TransformedContract::DATASET, &not_in(&batch.contracts, &existing)).await?;
+    clickhouse.insert(BlockDataUsage::DATASET, &not_in(&batch.data_usage, &existing)).await?;
+    clickhouse.insert(OrderingAnalysis::DATASET, &not_in(&batch.ordering_analysis, &existing)).await?;
+    clickhouse.insert(NftTransfer::DATASET, &not_in(&batch.nft_transfers, &existing)).await?;
+    clickhouse.insert(TokenTransfer::DATASET, &not_in(&batch.token_transfers, &existing)).await?;
+    clickhouse.insert(BridgeMessage::DATASET, &not_in(&batch.bridge_messages, &existing)).await?;
+    clickhouse.insert(Balance::DATASET, &not_in(&batch.balances, &existing)).await?;
+    clickhouse.insert(ContractState::DATASET, &not_in(&batch.contract_state, &existing)).await?;
+    clickhouse.insert(DataQualityIssue::DATASET, &not_in(&batch.data_quality, &existing)).await?;
+    // Dimension rows are versions, not facts of a block: they are never deleted or skipped
+    clickhouse.insert(AddressDimension::DATASET, &batch.addresses).await?;
+
+    if output.address_index {
+        let entries = activity::extract(&batch.receipts);
+        let fresh: Vec<_> = entries.iter().filter(|entry| !existing.contains(&entry.block_number)).collect();
+        clickhouse.insert(activity::INDEX_TABLE, &fresh).await?;
+    }
+    Ok(())
+}
+
+// Opens an output file, decompressing based on its extension
+pub fn open_file(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = fs::File::open(path)?;
+    let name = path.to_string_lossy();
+    let reader: Box<dyn Read> = if name.ends_with(".gz") {
+        Box::new(flate2::read::MultiGzDecoder::new(file))
+    } else if name.ends_with(".zst") {
+        Box::new(zstd::Decoder::new(file)?)
+    } else {
+        Box::new(file)
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+pub fn is_ndjson(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".ndjson") || name.ends_with(".ndjson.gz") || name.ends_with(".ndjson.zst")
+}
+
+pub fn is_avro(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "avro")
+}
+
+pub fn is_arrow(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "arrow")
+}
+
+// Files holding a chunk of blocks rather than one record
+fn is_chunk(path: &Path) -> bool {
+    is_ndjson(path) || is_avro(path) || is_arrow(path)
+}
+
+// All records in one output file, whichever layout and compression it uses
+pub fn read_records(path: &Path) -> Result<Vec<Value>> {
+    if is_avro(path) {
+        return avro::read_container(&fs::read(path)?);
+    }
+    if is_arrow(path) {
+        return decode_arrow(path);
+    }
+    let mut reader = open_file(path)?;
+    if is_ndjson(path) {
+        reader
+            .lines()
+            .filter(|line| line.as_ref().map(|l| !l.trim().is_empty()).unwrap_or(true))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    } else {
+        let mut data = String::new();
+        reader.read_to_string(&mut data)?;
+        Ok(vec![serde_json::from_str(&data)?])
+    }
+}
+
+// Replaces an output file with these records, keeping its layout and compression
+pub fn rewrite_records(path: &Path, dataset: &str, records: &[Value]) -> Result<()> {
+    write_records_like(path, path, dataset, records)
+}
+
+// Writes records of `dataset` to `path` in the layout and compression of the output file `like`
+pub fn write_records_like(path: &Path, like: &Path, dataset: &str, records: &[Value]) -> Result<()> {
+    let name = like.to_string_lossy();
+    let compression = if name.ends_with(".gz") {
+        Compression::Gzip
+    } else if name.ends_with(".zst") {
+        Compression::Zstd
+    } else {
+        Compression::None
+    };
+    let data = if is_avro(like) {
+        avro::write_container(&avro::dataset_schema(dataset)?, records, avro::container_codec(&fs::read(like)?)?)?
+    } else if is_arrow(like) {
+        encode_arrow(dataset, records)?
+    } else if is_ndjson(like) {
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record)?;
+            data.push(b'\n');
+        }
+        compression.compress(data)?
+    } else {
+        let record = records.first().ok_or_else(|| anyhow::anyhow!("{} would be left without its record", path.display()))?;
+        compression.compress(serde_json::to_vec_pretty(record)?)?
+    };
+    let tmp = format!("{}.{}.tmp", path.display(), std::process::id());
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// Output files of one dataset directory (see `DATASETS`) and its partition directories, sorted by path
+pub fn dataset_files(raw_data_path: &str, dataset: &str) -> Result<Vec<PathBuf>> {
+    let dir = PathBuf::from(format!("{}/{}", raw_data_path, dataset));
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![dir];
+    while let Some(dir) = dirs.pop() {
+        for path in fs::read_dir(&dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let name = path.to_string_lossy();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_avro(&path)
+                || is_arrow(&path)
+                || [".json", ".ndjson"]
+                    .iter()
+                    .any(|ext| [ext.to_string(), format!("{}.gz", ext), format!("{}.zst", ext)].iter().any(|e| name.ends_with(e.as_str())))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Records of a dataset whose block number falls in `first..=last`
+pub fn read_range<T: DeserializeOwned>(raw_data_path: &str, dataset: &str, first: u64, last: u64) -> Result<Vec<T>> {
+    let number_field = if dataset == "blocks" { "number" } else { "block_number" };
+    let mut records = Vec::new();
+    for path in dataset_files(raw_data_path, dataset)? {
+        // Per-record block files carry their number in the name, skip the ones outside the range
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(number) = name
+            .strip_prefix("block_")
+            .and_then(|name| name.split('.').next())
+            .and_then(|number| number.parse::<u64>().ok())
+        {
+            if number < first || number > last {
+                continue;
+            }
+        }
+        for record in read_records(&path)? {
+            let number = record[number_field].as_u64().unwrap_or_default();
+            if number >= first && number <= last {
+                records.push(record);
+            }
+        }
+    }
+    if dataset == TransformedReceipt::DATASET {
+        attach_logs(raw_data_path, &mut records)?;
+    }
+    records.into_iter().map(|record| Ok(serde_json::from_value(record)?)).collect()
+}
+
+// Gives receipts stored without their logs the rows of the logs dataset back, so readers see
+// receipts as fetched whether or not they were written with --embed-receipt-logs
+fn attach_logs(raw_data_path: &str, receipts: &mut [Value]) -> Result<()> {
+    let without_logs = |receipt: &Value| receipt["logs"].as_array().is_some_and(Vec::is_empty);
+    let blocks: Vec<u64> = receipts.iter().filter(|receipt| without_logs(receipt)).filter_map(|receipt| receipt["block_number"].as_u64()).collect();
+    let (Some(first), Some(last)) = (blocks.iter().min(), blocks.iter().max()) else {
+        return Ok(());
+    };
+    let mut by_transaction: HashMap<(String, String), Vec<EventLog>> = HashMap::new();
+    for log in read_range::<EventLog>(raw_data_path, EventLog::DATASET, *first, *last)? {
+        by_transaction.entry((log.block_hash.clone(), log.transaction_hash.clone())).or_default().push(log);
+    }
+    for receipt in receipts.iter_mut().filter(|receipt| without_logs(receipt)) {
+        let key = (receipt["block_hash"].as_str().unwrap_or_default().to_string(), receipt["transaction_hash"].as_str().unwrap_or_default().to_string());
+        if let Some(mut logs) = by_transaction.remove(&key) {
+            logs.sort_by_key(|log| log.log_index);
+            receipt["logs"] = Value::Array(logs.iter().map(transform::receipt_log).collect());
+        }
+    }
+    Ok(())
+}
+
+// Block range covered by an ndjson chunk file, from its `<dataset>_<first>_<last>.ndjson` name
+fn chunk_bounds(path: &Path) -> Option<(u64, u64)> {
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let mut parts = name.split('.').next()?.rsplitn(3, '_');
+    let last = parts.next()?.parse().ok()?;
+    let first = parts.next()?.parse().ok()?;
+    Some((first, last))
+}
+
+// The record of a dataset whose `field` equals `value`: read straight from `<file_name>.json` in
+// the one-file-per-record layout, otherwise found by scanning the ndjson/Avro chunks (only the chunk
+// covering `block_hint` when it is known)
+pub fn find_record(
+    raw_data_path: &str,
+    dataset: &str,
+    file_name: &str,
+    field: &str,
+    value: &str,
+    block_hint: Option<u64>,
+) -> Result<Option<Value>> {
+    let mut record = find_stored_record(raw_data_path, dataset, file_name, field, value, block_hint)?;
+    if let Some(receipt) = record.as_mut().filter(|_| dataset == TransformedReceipt::DATASET) {
+        attach_logs(raw_data_path, std::slice::from_mut(receipt))?;
+    }
+    Ok(record)
+}
+
+fn find_stored_record(
+    raw_data_path: &str,
+    dataset: &str,
+    file_name: &str,
+    field: &str,
+    value: &str,
+    block_hint: Option<u64>,
+) -> Result<Option<Value>> {
+    let names: Vec<String> = ["", ".gz", ".zst"].iter().map(|ext| format!("{}.json{}", file_name, ext)).collect();
+    for name in &names {
+        let path = Path::new(raw_data_path).join(dataset).join(name);
+        if path.exists() {
+            return Ok(read_records(&path)?.into_iter().next());
+        }
+    }
+    let files = dataset_files(raw_data_path, dataset)?;
+    // Partitioned per-record files are in a subdirectory
+    let named = |path: &PathBuf| path.file_name().is_some_and(|name| names.iter().any(|n| name.to_string_lossy() == *n));
+    if let Some(path) = files.iter().find(|path| named(path)) {
+        return Ok(read_records(path)?.into_iter().next());
+    }
+    for path in files.into_iter().filter(|path| is_chunk(path)) {
+        if let (Some(block), Some((first, last))) = (block_hint, chunk_bounds(&path)) {
+            if block < first || block > last {
+                continue;
+            }
+        }
+        if let Some(record) = read_records(&path)?.into_iter().find(|record| record[field].as_str().is_some_and(|field| field.eq_ignore_ascii_case(value))) {
+            return Ok(Some(record));
+        }
+    }
+    Ok(None)
+}
+
+// Block numbers present in `<raw_data_path>/blocks`, from `block_<number>.json` names or chunk contents
+pub fn indexed_block_numbers(raw_data_path: &str) -> Result<BTreeSet<u64>> {
+    let mut numbers = BTreeSet::new();
+    for path in dataset_files(raw_data_path, "blocks")? {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if is_chunk(&path) {
+            for record in read_records(&path)? {
+                if let Some(number) = record["number"].as_u64() {
+                    numbers.insert(number);
+                }
+            }
+        } else if let Some(number) = name
+            .strip_prefix("block_")
+            .and_then(|name| name.split('.').next())
+            .and_then(|number| number.parse::<u64>().ok())
+        {
+            numbers.insert(number);
+        }
+    }
+    Ok(numbers)
+}
+
+// Records the next block to index so an interrupted run can be resumed with `--start`.
+// Written to a temporary file first so a crash never leaves a truncated checkpoint.
+pub fn write_checkpoint(raw_data_path: &str, next_block: u64) -> Result<PathBuf> {
+    let path = Path::new(raw_data_path).join("checkpoint.json");
+    let tmp = path.with_extension("json.tmp");
+    let checkpoint = serde_json::json!({ "next_block": next_block, "updated_at": clock::now() });
+    fs::write(&tmp, serde_json::to_vec_pretty(&checkpoint)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+// The next block recorded by the latest checkpoint, None before the first run
+pub fn read_checkpoint(raw_data_path: &str) -> Result<Option<u64>> {
+    let path = Path::new(raw_data_path).join("checkpoint.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let checkpoint: Value = serde_json::from_slice(&fs::read(&path)?)?;
+    Ok(checkpoint["next_block"].as_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_ensure_directory() {
+        let test_dir = "test_dir";
+        
+        // Clean up any existing test directory
+        if Path::new(test_dir).exists() {
+            fs::remove_dir_all(test_dir).unwrap();
+        }
+        
+        // Test directory creation
+        assert!(!Path::new(test_dir).exists());
+        ensure_directory(test_dir).unwrap();
+        assert!(Path::new(test_dir).exists());
+        
+        // Clean up
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_indexed_block_numbers() {
+        let root = "test_store_blocks";
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(format!("{}/blocks", root)).unwrap();
+        for name in ["block_1.json", "block_3.json", "block_x.json", "notes.txt"] {
+            fs::write(format!("{}/blocks/{}", root, name), "{}").unwrap();
+        }
+
+        let numbers = indexed_block_numbers(root).unwrap();
+        assert_eq!(numbers.into_iter().collect::<Vec<_>>(), vec![1, 3]);
+        assert!(indexed_block_numbers("missing_store_dir").unwrap().is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compressed_ndjson_round_trip() {
+        let root = "test_store_ndjson";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let batch = TransformedBatch { blocks: vec![fixtures::block(7), fixtures::block(9)], ..Default::default() };
+
+        for compress in [Compression::Gzip, Compression::Zstd] {
+            let output = OutputConfig { format: OutputFormat::Ndjson, compress, ..Default::default() };
+            persist(&dirs, output, None, &batch, 2).await.unwrap();
+        }
+
+        assert!(Path::new(&format!("{}/blocks_7_9.ndjson.gz", dirs.blocks)).exists());
+        assert!(Path::new(&format!("{}/blocks_7_9.ndjson.zst", dirs.blocks)).exists());
+        let records: usize = dataset_files(root, "blocks").unwrap().iter().map(|path| read_records(path).unwrap().len()).sum();
+        assert_eq!(records, 4);
+        assert_eq!(indexed_block_numbers(root).unwrap().into_iter().collect::<Vec<_>>(), vec![7, 9]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_avro_chunks_read_back() {
+        let root = "test_store_avro";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let batch = TransformedBatch { blocks: vec![fixtures::block(7), fixtures::block(9)], ..Default::default() };
+        let output = OutputConfig { format: OutputFormat::Avro, compress: Compression::Zstd, ..Default::default() };
+        persist(&dirs, output, None, &batch, 2).await.unwrap();
+
+        assert!(Path::new(&format!("{}/blocks_7_9.avro", dirs.blocks)).exists());
+        let blocks: Vec<TransformedBlock> = read_range(root, "blocks", 0, 10).unwrap();
+        assert_eq!(serde_json::to_value(&blocks).unwrap(), serde_json::to_value(&batch.blocks).unwrap());
+        assert_eq!(indexed_block_numbers(root).unwrap().into_iter().collect::<Vec<_>>(), vec![7, 9]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_policy() {
+        let root = "test_store_write_policy";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let path = format!("{}/block_3.json", dirs.blocks);
+        let batch = TransformedBatch { blocks: vec![fixtures::block(3)], ..Default::default() };
+        let skip = OutputConfig { write_policy: WritePolicy::SkipExisting, ..Default::default() };
+
+        persist(&dirs, skip, None, &batch, 2).await.unwrap();
+        fs::write(&path, "{}").unwrap();
+        persist(&dirs, skip, None, &batch, 2).await.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        persist(&dirs, OutputConfig::default(), None, &batch, 2).await.unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 3"));
+        assert_eq!(fs::read_dir(&dirs.blocks).unwrap().count(), 1);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plan_writes_matches_persist() {
+        let root = "test_store_plan";
+        let _ = fs::remove_dir_all(root);
+        let batch = TransformedBatch { blocks: vec![fixtures::block(5), fixtures::block(1200)], ..Default::default() };
+        let output = OutputConfig { format: OutputFormat::Ndjson, ..Default::default() };
+
+        // Planning leaves the disk alone
+        let plans = plan_writes(&OutputDirs::new(root), output, &batch).unwrap();
+        assert!(!Path::new(root).exists());
+        assert_eq!((plans[0].dataset, plans[0].records, plans[0].files.len()), ("blocks", 2, 2));
+        assert!(plans[1..].iter().all(|plan| plan.records == 0 && plan.files.is_empty()));
+
+        let dirs = OutputDirs::create(root).unwrap();
+        persist(&dirs, output, None, &batch, 2).await.unwrap();
+        let written: u64 = plans[0].files.iter().map(|file| fs::metadata(file).unwrap().len()).sum();
+        assert_eq!(written, plans[0].bytes as u64);
+        let skip = OutputConfig { write_policy: WritePolicy::SkipExisting, ..output };
+        assert_eq!(plan_writes(&dirs, skip, &batch).unwrap()[0].records, 0);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_receipt_logs() {
+        let root = "test_store_receipt_logs";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let raw: crate::models::Receipt = serde_json::from_value(serde_json::json!({
+            "blockHash": "0xb", "blockNumber": "0x5", "contractAddress": null, "cumulativeGasUsed": "0x0", "from": "0xf",
+            "gasUsed": "0x0", "logsBloom": "0x", "status": "0x1", "to": "0xd", "transactionHash": "0xh", "transactionIndex": "0x0", "type": "0x2",
+            "logs": [{ "address": "0xc", "blockHash": "0xb", "blockNumber": "0x5", "data": "0x", "logIndex": "0x0", "removed": false,
+                       "topics": ["0x01"], "transactionHash": "0xh", "transactionIndex": "0x0" }],
+        }))
+        .unwrap();
+        let receipt = transform::transform_receipt(&raw, DateTime::default());
+        let batch = TransformedBatch { logs: transform::extract_logs(std::slice::from_ref(&receipt)), receipts: vec![receipt], ..Default::default() };
+        let path = format!("{}/receipt_0xh.json", dirs.receipts);
+
+        // Receipts are stored without the logs, which the indexes still see and readers get back
+        let output = OutputConfig { address_index: true, ..Default::default() };
+        persist(&dirs, output, None, &batch, 2).await.unwrap();
+        assert_eq!(read_records(Path::new(&path)).unwrap()[0]["logs"], serde_json::json!([]));
+        assert!(activity::lookup(root, "0xc", None).unwrap().iter().any(|entry| entry.block_number == 5));
+        let read: Vec<TransformedReceipt> = read_range(root, "receipts", 5, 5).unwrap();
+        assert_eq!(read[0].logs, batch.receipts[0].logs);
+        let found = find_record(root, "receipts", "receipt_0xh", "transaction_hash", "0xh", None).unwrap().unwrap();
+        assert_eq!(found["logs"], serde_json::to_value(&batch.receipts[0].logs).unwrap());
+
+        let embedded = OutputConfig { embed_receipt_logs: true, ..output };
+        persist(&dirs, embedded, None, &batch, 2).await.unwrap();
+        assert_eq!(read_records(Path::new(&path)).unwrap()[0]["logs"].as_array().unwrap().len(), 1);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_find_record_in_both_layouts() {
+        let root = "test_store_find";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let mut block = fixtures::block(1200);
+        block.hash = "0xjson".to_string();
+        persist(&dirs, OutputConfig::default(), None, &TransformedBatch { blocks: vec![block], ..Default::default() }, 2)
+            .await
+            .unwrap();
+        let blocks = [1500, 2500].into_iter().map(|n| TransformedBlock { hash: format!("0x{}", n), ..fixtures::block(n) }).collect();
+        let output = OutputConfig { format: OutputFormat::Ndjson, ..Default::default() };
+        persist(&dirs, output, None, &TransformedBatch { blocks, ..Default::default() }, 2).await.unwrap();
+
+        let found = find_record(root, "blocks", "block_1200", "hash", "0xjson", None).unwrap().unwrap();
+        assert_eq!(found["number"], 1200);
+        let found = find_record(root, "blocks", "block_x", "hash", "0x2500", Some(2500)).unwrap().unwrap();
+        assert_eq!(found["number"], 2500);
+        assert!(find_record(root, "blocks", "block_x", "hash", "0x2500", Some(1500)).unwrap().is_none());
+        assert!(find_record(root, "blocks", "block_x", "hash", "0xmissing", None).unwrap().is_none());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_layouts() {
+        let root = "test_store_partitions";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let blocks = [9999, 10000].into_iter().map(|n| TransformedBlock { hash: format!("0x{}", n), ..fixtures::block(n) }).collect();
+        let batch = TransformedBatch { blocks, ..Default::default() };
+
+        let by_range = OutputConfig { partition: Partitioning::BlockRange, ..Default::default() };
+        persist(&dirs, by_range, None, &batch, 2).await.unwrap();
+        assert!(Path::new(&format!("{}/block_range=0_9999/block_9999.json", dirs.blocks)).exists());
+        assert!(Path::new(&format!("{}/block_range=10000_19999/block_10000.json", dirs.blocks)).exists());
+        let found = find_record(root, "blocks", "block_10000", "hash", "0x10000", None).unwrap().unwrap();
+        assert_eq!(found["number"], 10000);
+
+        let by_date = OutputConfig { format: OutputFormat::Ndjson, partition: Partitioning::Date, ..Default::default() };
+        persist(&dirs, by_date, None, &batch, 2).await.unwrap();
+        // The test blocks have timestamp 1
+        assert!(Path::new(&format!("{}/dt=2024-01-01/blocks_9999_9999.ndjson", dirs.blocks)).exists());
+        assert_eq!(read_range::<Value>(root, "blocks", 10000, 10000).unwrap().len(), 2);
+        assert_eq!(indexed_block_numbers(root).unwrap().into_iter().collect::<Vec<_>>(), vec![9999, 10000]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_chunks_are_aligned() {
+        let root = "test_store_chunks";
+        let _ = fs::remove_dir_all(root);
+        let dirs = OutputDirs::create(root).unwrap();
+        let blocks: Vec<TransformedBlock> = [998, 999, 1000, 1001, 2500].into_iter().map(fixtures::block).collect();
+        let batch = TransformedBatch { blocks, ..Default::default() };
+
+        let output = OutputConfig { format: OutputFormat::Ndjson, ..Default::default() };
+        persist(&dirs, output, None, &batch, 2).await.unwrap();
+
+        let names: Vec<String> = dataset_files(root, "blocks")
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["blocks_1000_1001.ndjson", "blocks_2500_2500.ndjson", "blocks_998_999.ndjson"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
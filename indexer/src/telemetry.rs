@@ -0,0 +1,161 @@
+//! Logging and tracing through the `tracing` ecosystem. Records written with the `log` macros (here
+//! and in dependencies) are forwarded into it, so every line carries the spans it happened in:
+//! a `block` span per fetched block and an `rpc` span per JSON-RPC call with its latency.
+//!
+//! `RUST_LOG` filters the output as before; `LOG_FORMAT=json` switches to one JSON object per line
+//! and `LOG_SPANS=true` adds a line whenever a span closes. With the `otel` feature and
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are also exported to an OTLP collector over gRPC.
+//!
+//! Each fetched block is a trace of its own: the `block` span with `fetch_block`, `fetch_receipts`
+//! and `rpc` children. The `transform` and `write` stages of a batch, with a `write_dataset` span
+//! per dataset, are traces too. `TRACE_SAMPLE_RATIO` keeps only a share of them on long backfills.
+
+use anyhow::Result;
+use std::io::IsTerminal;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::config::env_or;
+use crate::progress::BarWriter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(anyhow::anyhow!("LOG_FORMAT must be text or json, got {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub format: LogFormat,
+    // Log a line with the span's duration whenever a span closes
+    pub spans: bool,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    // Share of traces exported, between 0 and 1
+    pub sample_ratio: f64,
+}
+
+impl TelemetryConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            format: LogFormat::parse(&std::env::var("LOG_FORMAT").unwrap_or_default())?,
+            spans: env_or("LOG_SPANS", false)?,
+            otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|e| !e.trim().is_empty()),
+            service_name: env_or("OTEL_SERVICE_NAME", "sweet-potato".to_string())?,
+            sample_ratio: sample_ratio(env_or("TRACE_SAMPLE_RATIO", 1.0)?)?,
+        })
+    }
+}
+
+fn sample_ratio(ratio: f64) -> Result<f64> {
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(anyhow::anyhow!("TRACE_SAMPLE_RATIO must be between 0 and 1, got {}", ratio));
+    }
+    Ok(ratio)
+}
+
+// Flushes exported spans when dropped; keep it alive until the end of `main`
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Could not flush traces: {}", e);
+            }
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn fmt_layer(config: &TelemetryConfig) -> BoxedLayer {
+    // Without RUST_LOG only errors are printed, as with env_logger
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error"));
+    let span_events = if config.spans { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    let layer = tracing_subscriber::fmt::layer().with_writer(|| BarWriter).with_span_events(span_events);
+    match config.format {
+        LogFormat::Text => layer.with_ansi(std::io::stderr().is_terminal()).with_filter(filter).boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(true).with_filter(filter).boxed(),
+    }
+}
+
+// Spans and events of this crate only, so the exporter's own gRPC traffic is not traced
+#[cfg(feature = "otel")]
+fn otlp_layer(config: &TelemetryConfig, endpoint: &str) -> Result<(BoxedLayer, opentelemetry_sdk::trace::TracerProvider)> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::Sampler;
+    use tracing_subscriber::filter::{LevelFilter, Targets};
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder().with_tonic().with_endpoint(endpoint).build()?;
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        // Child spans follow their root, so sampled traces are complete
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+        .with_resource(opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build();
+    let targets = Targets::new().with_target("indexer", LevelFilter::INFO).with_target("sweet_potato", LevelFilter::INFO);
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("sweet-potato")).with_filter(targets).boxed();
+    Ok((layer, provider))
+}
+
+// Installs the global subscriber. Must run inside the Tokio runtime, which the exporter runs on.
+pub fn init(config: &TelemetryConfig) -> Result<Telemetry> {
+    #[allow(unused_mut)]
+    let mut telemetry = Telemetry::default();
+    let otlp: Option<BoxedLayer> = match config.otlp_endpoint.as_deref() {
+        #[cfg(feature = "otel")]
+        Some(endpoint) => {
+            let (layer, provider) = otlp_layer(config, endpoint)?;
+            telemetry.provider = Some(provider);
+            Some(layer)
+        },
+        #[cfg(not(feature = "otel"))]
+        Some(_) => {
+            eprintln!("OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build lacks the otel feature; not exporting traces");
+            None
+        },
+        None => None,
+    };
+    let layers: Vec<BoxedLayer> = std::iter::once(fmt_layer(config)).chain(otlp).collect();
+    tracing_subscriber::registry().with(layers).try_init()?;
+    if let Some(endpoint) = &config.otlp_endpoint {
+        log::info!("Exporting {}% of traces to {} as {}", config.sample_ratio * 100.0, endpoint, config.service_name);
+    }
+    Ok(telemetry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(LogFormat::parse("").unwrap(), LogFormat::Text);
+        assert_eq!(LogFormat::parse(" JSON ").unwrap(), LogFormat::Json);
+        assert!(LogFormat::parse("logfmt").is_err());
+        assert_eq!(sample_ratio(0.25).unwrap(), 0.25);
+        assert!(sample_ratio(1.5).is_err());
+    }
+}
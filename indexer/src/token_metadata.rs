@@ -0,0 +1,182 @@
+//! ERC-20 `symbol()`, `name()` and `decimals()` read with `eth_call`, cached in
+//! `<RAW_DATA_PATH>/token_metadata.json` so every token is only asked once.
+
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::TokenTransfer;
+use crate::rpc;
+use crate::trie::decode_hex;
+
+const SYMBOL_SELECTOR: &str = "0x95d89b41";
+const NAME_SELECTOR: &str = "0x06fdde03";
+const DECIMALS_SELECTOR: &str = "0x313ce567";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+// ABI `string`, or the `bytes32` some early tokens (e.g. MKR) return instead
+pub fn decode_string(hex: &str) -> Option<String> {
+    let data = decode_hex(hex).ok()?;
+    let bytes = if data.len() == 32 {
+        let end = data.iter().position(|b| *b == 0).unwrap_or(32);
+        data[..end].to_vec()
+    } else {
+        let word = |offset: usize| -> Option<usize> {
+            let word = data.get(offset..offset + 32)?;
+            // Anything beyond 4 bytes cannot be a valid offset or length here
+            word[..28].iter().all(|b| *b == 0).then(|| u32::from_be_bytes(word[28..].try_into().unwrap()) as usize)
+        };
+        let offset = word(0)?;
+        let length = word(offset)?;
+        data.get(offset + 32..offset + 32 + length)?.to_vec()
+    };
+    let text = String::from_utf8(bytes).ok()?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+pub fn decode_decimals(hex: &str) -> Option<u8> {
+    let data = decode_hex(hex).ok()?;
+    (data.len() == 32 && data[..31].iter().all(|b| *b == 0)).then(|| data[31])
+}
+
+// `value` (a decimal integer string) divided by 10^decimals, without trailing zeros
+pub fn scale(value: &str, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return value.to_string();
+    }
+    let padded = format!("{:0>width$}", value, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+async fn call(token: &str, selector: &str) -> Result<String> {
+    let result = rpc::rpc_call("eth_call", json!([{ "to": token, "data": selector }, "latest"])).await?;
+    Ok(result.as_str().unwrap_or_default().to_string())
+}
+
+// None when every call failed, which is more likely a transient RPC error than a token without
+// any of the three functions, so nothing gets cached
+async fn fetch(token: &str) -> Option<TokenMetadata> {
+    let (symbol, name, decimals) =
+        tokio::join!(call(token, SYMBOL_SELECTOR), call(token, NAME_SELECTOR), call(token, DECIMALS_SELECTOR));
+    if symbol.is_err() && name.is_err() && decimals.is_err() {
+        return None;
+    }
+    Some(TokenMetadata {
+        symbol: symbol.ok().as_deref().and_then(decode_string),
+        name: name.ok().as_deref().and_then(decode_string),
+        decimals: decimals.ok().as_deref().and_then(decode_decimals),
+    })
+}
+
+pub struct MetadataCache {
+    path: PathBuf,
+    tokens: BTreeMap<String, TokenMetadata>,
+}
+
+impl MetadataCache {
+    pub fn load(raw_data_path: &str) -> Result<Self> {
+        let path = Path::new(raw_data_path).join("token_metadata.json");
+        let tokens = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, tokens })
+    }
+
+    pub fn get(&self, token: &str) -> Option<&TokenMetadata> {
+        self.tokens.get(token)
+    }
+
+    // Looks up tokens not cached yet, `concurrency` at a time; true when any was looked up
+    pub async fn fetch_missing(&mut self, tokens: BTreeSet<String>, concurrency: usize) -> Result<bool> {
+        let missing: Vec<String> = tokens.into_iter().filter(|token| !self.tokens.contains_key(token)).collect();
+        if missing.is_empty() {
+            return Ok(false);
+        }
+        log::info!("Fetching metadata for {} tokens", missing.len());
+        let fetched: Vec<(String, Option<TokenMetadata>)> = stream::iter(missing)
+            .map(|token| async move {
+                let metadata = fetch(&token).await;
+                (token, metadata)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        for (token, metadata) in fetched {
+            match metadata {
+                Some(metadata) => {
+                    self.tokens.insert(token, metadata);
+                },
+                None => log::warn!("Could not read metadata of token {}, retrying next run", token),
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.tokens)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+// Fills symbol, name, decimals and the scaled value of every transfer, fetching unknown tokens
+// first; `save` keeps what was fetched in the cache
+pub async fn enrich(transfers: &mut [TokenTransfer], raw_data_path: &str, concurrency: usize, save: bool) -> Result<()> {
+    let mut cache = MetadataCache::load(raw_data_path)?;
+    let fetched = cache.fetch_missing(transfers.iter().map(|t| t.token_address.clone()).collect(), concurrency).await?;
+    if fetched && save {
+        cache.save()?;
+    }
+    for transfer in transfers {
+        let Some(metadata) = cache.get(&transfer.token_address) else { continue };
+        transfer.token_symbol = metadata.symbol.clone();
+        transfer.token_name = metadata.name.clone();
+        transfer.token_decimals = metadata.decimals;
+        transfer.value_scaled = metadata.decimals.map(|decimals| scale(&transfer.value, decimals));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_and_scale() {
+        // "USDC" as an ABI string
+        let string = format!("0x{:0>64}{:0>64}{:0<64}", "20", "4", "55534443");
+        assert_eq!(decode_string(&string), Some("USDC".to_string()));
+        assert_eq!(decode_string(&format!("0x{:0<64}", "4d4b52")), Some("MKR".to_string()));
+        assert_eq!(decode_string("0x"), None);
+        assert_eq!(decode_string(&format!("0x{:0>64}{:0>64}", "20", "ff")), None);
+
+        assert_eq!(decode_decimals(&format!("0x{:0>64}", "12")), Some(18));
+        assert_eq!(decode_decimals(&format!("0x{:0>64}", "100")), None);
+
+        assert_eq!(scale("1500000", 6), "1.5");
+        assert_eq!(scale("5", 18), "0.000000000000000005");
+        assert_eq!(scale("2000000000000000000", 18), "2");
+        assert_eq!(scale("42", 0), "42");
+    }
+}
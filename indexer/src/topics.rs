@@ -0,0 +1,264 @@
+//! Typed log topics and the topic0 → event signature index.
+//!
+//! Every log written in `receipts` gets `topic0`, `event_signature` (when topic0 is in the
+//! signature database) and `indexed`: one `{name, type, value}` per topic after topic0. Topics are
+//! typed from the declaration whose indexed parameter count matches the log, which also tells
+//! ERC-20 from ERC-721 `Transfer`. Values of fixed-width types are decoded (addresses, `bool`,
+//! `uintN`/`intN` as decimal strings, `bytesN`); indexed strings, bytes, arrays and tuples are
+//! only stored as their hash, so they and topics of unknown events stay raw `bytes32` hex.
+//!
+//! The database is `data/events.txt` extended by `EVENT_SIGNATURES_PATH`. `raw_data/event_signatures.json`
+//! keeps every topic0 the indexer has written with its signature and log count, for `/events`.
+
+use anyhow::Result;
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::models::TransformedReceipt;
+use crate::trie::{decode_hex, encode_hex, keccak256};
+
+const BUNDLED_EVENTS: &str = include_str!("../data/events.txt");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Option<String>,
+    pub kind: String,
+    pub indexed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventDeclaration {
+    // Canonical signature, e.g. `Transfer(address,address,uint256)`
+    pub signature: String,
+    pub params: Vec<Param>,
+}
+
+impl EventDeclaration {
+    fn indexed(&self) -> impl Iterator<Item = &Param> {
+        self.params.iter().filter(|param| param.indexed)
+    }
+}
+
+// topic0 -> declarations with that signature, bundled first, then EVENT_SIGNATURES_PATH
+static EVENTS: LazyLock<HashMap<String, Vec<EventDeclaration>>> = LazyLock::new(|| {
+    let mut events = parse_events(BUNDLED_EVENTS);
+    if let Ok(path) = env::var("EVENT_SIGNATURES_PATH") {
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let extra = parse_events(&contents);
+                log::info!("Loaded {} event signatures from {}", extra.len(), path);
+                for (topic0, declarations) in extra {
+                    // Local declarations are tried first
+                    let known = events.entry(topic0).or_default();
+                    known.splice(0..0, declarations);
+                }
+            },
+            Err(e) => log::warn!("Could not read EVENT_SIGNATURES_PATH {}: {}", path, e),
+        }
+    }
+    events
+});
+
+pub fn topic_of(signature: &str) -> String {
+    encode_hex(&keccak256(signature.as_bytes()))
+}
+
+// Splits on commas outside parentheses, so tuple types stay whole
+fn split_params(params: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(params[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(params[start..].trim());
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+// `Name(type [indexed] [name], ...)`; a bare signature has no indexed parameters
+pub fn parse_declaration(line: &str) -> Option<EventDeclaration> {
+    let line = line.trim().trim_start_matches("event ").trim_end_matches(';');
+    let (name, rest) = line.split_once('(')?;
+    let params = rest.strip_suffix(')')?;
+    let params: Vec<Param> = split_params(params)
+        .into_iter()
+        .map(|param| {
+            // The type ends at the first space outside a tuple
+            let split = param.rfind(')').map_or(0, |end| end + 1);
+            let (kind, words) = match param[split..].find(' ') {
+                Some(space) => param.split_at(split + space),
+                None => (param, ""),
+            };
+            let words: Vec<&str> = words.split_whitespace().collect();
+            Param {
+                kind: kind.trim().to_string(),
+                indexed: words.contains(&"indexed"),
+                name: words.into_iter().find(|word| *word != "indexed").map(str::to_string),
+            }
+        })
+        .collect();
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let types: Vec<&str> = params.iter().map(|param| param.kind.as_str()).collect();
+    Some(EventDeclaration { signature: format!("{}({})", name, types.join(",")), params })
+}
+
+// One declaration per line; blank lines and `#` comments are skipped
+pub fn parse_events(contents: &str) -> HashMap<String, Vec<EventDeclaration>> {
+    let mut events: HashMap<String, Vec<EventDeclaration>> = HashMap::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        if let Some(declaration) = parse_declaration(line) {
+            events.entry(topic_of(&declaration.signature)).or_default().push(declaration);
+        }
+    }
+    events
+}
+
+pub fn lookup(topic0: &str) -> Option<String> {
+    EVENTS.get(&topic0.to_lowercase()).and_then(|declarations| declarations.first()).map(|d| d.signature.clone())
+}
+
+// The declaration of `topic0` that has `indexed` indexed parameters
+fn declaration_for(topic0: &str, indexed: usize) -> Option<&'static EventDeclaration> {
+    EVENTS.get(&topic0.to_lowercase())?.iter().find(|declaration| declaration.indexed().count() == indexed)
+}
+
+// A 32-byte topic as a value of a fixed-width ABI type, or None for types that are hashed into topics
+pub fn typed_value(kind: &str, topic: &str) -> Option<Value> {
+    let bytes = decode_hex(topic).ok().filter(|bytes| bytes.len() == 32)?;
+    let word = U256::from_big_endian(&bytes);
+    let width = |prefix: &str| kind.strip_prefix(prefix).map(|bits| if bits.is_empty() { Some(256) } else { bits.parse::<usize>().ok() });
+    match kind {
+        "address" => Some(json!(encode_hex(&bytes[12..]))),
+        "bool" => Some(json!(!word.is_zero())),
+        _ if kind.starts_with("uint") => width("uint").flatten().filter(|bits| *bits <= 256).map(|_| json!(word.to_string())),
+        _ if kind.starts_with("int") => width("int").flatten().filter(|bits| *bits <= 256).map(|_| match word.bit(255) {
+            // Two's complement: the magnitude of a negative value is !word + 1
+            true => json!(format!("-{}", (!word).overflowing_add(U256::one()).0)),
+            false => json!(word.to_string()),
+        }),
+        _ if kind.starts_with("bytes") => {
+            let size = kind["bytes".len()..].parse::<usize>().ok().filter(|size| (1..=32).contains(size))?;
+            Some(json!(encode_hex(&bytes[..size])))
+        },
+        _ => None,
+    }
+}
+
+// The log with `topic0`, `event_signature` and its typed `indexed` topics added
+pub fn annotate(log: &Value) -> Value {
+    let mut log = log.clone();
+    let topics: Vec<String> = log["topics"].as_array().into_iter().flatten().filter_map(Value::as_str).map(str::to_string).collect();
+    let Some(topic0) = topics.first() else { return log };
+    let declaration = declaration_for(topic0, topics.len() - 1);
+    let indexed: Vec<Value> = topics[1..]
+        .iter()
+        .enumerate()
+        .map(|(i, topic)| {
+            let param = declaration.and_then(|declaration| declaration.indexed().nth(i));
+            let typed = param.and_then(|param| typed_value(&param.kind, topic).map(|value| (param.kind.as_str(), value)));
+            let (kind, value) = typed.unwrap_or(("bytes32", json!(topic)));
+            json!({ "name": param.and_then(|param| param.name.clone()), "type": kind, "value": value })
+        })
+        .collect();
+    if let Some(object) = log.as_object_mut() {
+        object.insert("topic0".to_string(), json!(topic0));
+        object.insert("event_signature".to_string(), json!(declaration.map(|d| d.signature.clone()).or_else(|| lookup(topic0))));
+        object.insert("indexed".to_string(), Value::Array(indexed));
+    }
+    log
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexedEvent {
+    pub signature: Option<String>,
+    pub logs: u64,
+}
+
+fn index_path(raw_data_path: &str) -> PathBuf {
+    Path::new(raw_data_path).join("event_signatures.json")
+}
+
+// topic0 -> signature and number of logs written, as kept next to the datasets
+pub fn read_index(raw_data_path: &str) -> Result<BTreeMap<String, IndexedEvent>> {
+    match fs::read(index_path(raw_data_path)) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Adds the logs of written receipts to the index. Signatures learned since (a new
+// EVENT_SIGNATURES_PATH entry) are filled in for topics already indexed.
+pub fn update_index(raw_data_path: &str, receipts: &[TransformedReceipt]) -> Result<()> {
+    let mut index = read_index(raw_data_path)?;
+    let topics = receipts.iter().flat_map(|receipt| &receipt.logs).filter_map(|log| log["topics"][0].as_str());
+    let mut changed = false;
+    for topic0 in topics {
+        let entry = index.entry(topic0.to_lowercase()).or_default();
+        entry.logs += 1;
+        changed = true;
+    }
+    if !changed {
+        return Ok(());
+    }
+    for (topic0, entry) in index.iter_mut() {
+        entry.signature = entry.signature.take().or_else(|| lookup(topic0));
+    }
+    let path = index_path(raw_data_path);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&index)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::TRANSFER_TOPIC;
+
+    #[test]
+    fn test_typed_topics() {
+        let declaration = parse_declaration("event Mint(address sender, address indexed owner, int24 indexed tickLower, (uint8,bytes) data);").unwrap();
+        assert_eq!(declaration.signature, "Mint(address,address,int24,(uint8,bytes))");
+        let indexed: Vec<_> = declaration.indexed().map(|param| param.name.as_deref().unwrap()).collect();
+        assert_eq!(indexed, vec!["owner", "tickLower"]);
+        assert_eq!(topic_of("Transfer(address,address,uint256)"), TRANSFER_TOPIC);
+        assert_eq!(lookup(TRANSFER_TOPIC).as_deref(), Some("Transfer(address,address,uint256)"));
+
+        let address = format!("0x{}{}", "0".repeat(24), "ab".repeat(20));
+        let seven = format!("0x{:064x}", 7);
+        let minus_two = format!("0x{}fe", "f".repeat(62));
+        assert_eq!(typed_value("int24", &minus_two), Some(json!("-2")));
+        assert_eq!(typed_value("bytes4", &minus_two), Some(json!("0xffffffff")));
+        assert_eq!(typed_value("bool", &seven), Some(json!(true)));
+        assert_eq!(typed_value("string", &seven), None);
+
+        // Three indexed topics make it the ERC-721 Transfer
+        let log = json!({ "address": "0xt", "topics": [TRANSFER_TOPIC, address, address, seven], "data": "0x" });
+        let annotated = annotate(&log);
+        assert_eq!(annotated["event_signature"], "Transfer(address,address,uint256)");
+        assert_eq!(annotated["indexed"][0], json!({ "name": "from", "type": "address", "value": format!("0x{}", "ab".repeat(20)) }));
+        assert_eq!(annotated["indexed"][2], json!({ "name": "tokenId", "type": "uint256", "value": "7" }));
+
+        // Unknown events keep raw topics
+        let unknown = annotate(&json!({ "topics": [seven, seven] }));
+        assert_eq!(unknown["event_signature"], Value::Null);
+        assert_eq!(unknown["indexed"][0], json!({ "name": null, "type": "bytes32", "value": seven }));
+    }
+}
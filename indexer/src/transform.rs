@@ -0,0 +1,1053 @@
+use chrono::{DateTime, TimeZone, Utc};
+use primitive_types::U256;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::bridge::{self, BridgeEvent};
+use crate::events::{self, TokenEvent};
+use crate::models::{
+    Block, BlockDataUsage, BridgeMessage, EventLog, NftTransfer, OrderingAnalysis, Receipt, TokenTransfer, Transaction, TransformedBlock,
+    TransformedContract, TransformedReceipt, TransformedTransaction,
+};
+use crate::selectors;
+use crate::token_metadata::scale;
+use crate::topics;
+use crate::trie::{decode_hex, encode_hex, keccak256};
+
+// Helper functions
+pub fn hex_to_u64(hex: &str) -> u64 {
+    if let Some(hex_str) = hex.strip_prefix("0x") {
+        u64::from_str_radix(hex_str, 16).unwrap_or(0)
+    } else {
+        u64::from_str_radix(hex, 16).unwrap_or(0)
+    }
+}
+
+// Rows a block may hold per dataset before the surrogate keys of consecutive blocks would overlap
+pub const ROW_ID_STRIDE: u64 = 1_000_000;
+
+// Surrogate key of the row at `index` (transaction or log index) of a block. It only depends on the
+// chain, so every sink and every re-index of the block assign the same key, and keys sort like the chain.
+pub fn row_id(block_number: u64, index: u64) -> u64 {
+    assert!(index < ROW_ID_STRIDE, "row index {} of block {} does not fit the surrogate key stride", index, block_number);
+    block_number * ROW_ID_STRIDE + index
+}
+
+// A quantity of up to 256 bits, such as a wei amount, as a decimal string
+pub fn hex_to_decimal(hex: &str) -> String {
+    U256::from_str_radix(hex.trim_start_matches("0x"), 16).unwrap_or_default().to_string()
+}
+
+pub fn hex_to_bool(hex: &str) -> bool {
+    hex_to_u64(hex) == 1
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(u8)]
+pub enum AddressCase {
+    // All lowercase, as most nodes return them
+    #[default]
+    Lower,
+    // EIP-55 mixed-case checksum
+    Checksum,
+}
+
+static ADDRESS_CASE: AtomicU8 = AtomicU8::new(AddressCase::Lower as u8);
+
+pub fn set_address_case(case: AddressCase) {
+    ADDRESS_CASE.store(case as u8, Ordering::SeqCst);
+}
+
+// EIP-55: a hex letter is uppercase when the matching nibble of the lowercase address's keccak is 8 or more
+pub fn checksum_address(address: &str) -> String {
+    let hex = address.strip_prefix("0x").unwrap_or(address).to_lowercase();
+    let hash = keccak256(hex.as_bytes());
+    let digits: String = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match (hash[i / 2] >> (4 * (1 - i % 2))) & 0xf {
+            nibble if nibble >= 8 => c.to_ascii_uppercase(),
+            _ => c,
+        })
+        .collect();
+    format!("0x{}", digits)
+}
+
+// An address in the configured case; anything that is not a 20-byte hex address is only lowercased
+pub fn normalize_address(address: &str) -> String {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    let is_address = hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit());
+    if is_address && ADDRESS_CASE.load(Ordering::Relaxed) == AddressCase::Checksum as u8 {
+        checksum_address(address)
+    } else {
+        address.to_lowercase()
+    }
+}
+
+// RPC objects with their keys sorted at every level, so the output does not depend on the order
+// a provider writes fields in
+fn canonical(map: &Map<String, Value>) -> Map<String, Value> {
+    let mut map = map.clone();
+    map.sort_keys();
+    map.values_mut().for_each(Value::sort_all_objects);
+    map
+}
+
+// Transactions and receipts in (block, transactionIndex) order, whatever order the RPC answered in
+pub fn sort_by_index(transactions: &mut [TransformedTransaction], receipts: &mut [TransformedReceipt]) {
+    transactions.sort_by_key(|tx| (tx.block_number, tx.transaction_index));
+    receipts.sort_by_key(|receipt| (receipt.block_number, receipt.transaction_index));
+}
+
+pub fn transform_block(block: &Block) -> TransformedBlock {
+    let ts = hex_to_u64(&block.timestamp);
+    let datetime = Utc.timestamp_opt(ts as i64, 0).single().unwrap_or_default();
+    TransformedBlock {
+        base_fee_per_gas: block.base_fee_per_gas.as_ref().map(|x| hex_to_u64(x)),
+        difficulty: hex_to_u64(&block.difficulty),
+        extra_data: block.extra_data.clone(),
+        gas_limit: hex_to_u64(&block.gas_limit),
+        gas_used: hex_to_u64(&block.gas_used),
+        hash: block.hash.clone(),
+        logs_bloom: block.logs_bloom.clone(),
+        miner: normalize_address(&block.miner),
+        mix_hash: block.mix_hash.clone(),
+        nonce: block.nonce.clone(),
+        number: hex_to_u64(&block.number),
+        parent_hash: block.parent_hash.clone(),
+        receipts_root: block.receipts_root.clone(),
+        sha3_uncles: block.sha3_uncles.clone(),
+        size: hex_to_u64(&block.size),
+        state_root: block.state_root.clone(),
+        datetime,
+        total_difficulty: hex_to_u64(&block.total_difficulty),
+        transaction_hashes: block.transaction_hashes.clone(),
+        transactions_root: block.transactions_root.clone(),
+        uncles: block.uncles.clone(),
+        total_fees: None,
+        burnt_fees: None,
+        base_fee_per_gas_gwei: None,
+        total_fees_ether: None,
+        burnt_fees_ether: None,
+        extra: canonical(&block.extra),
+    }
+}
+
+pub fn transform_transaction(tx: &Transaction, datetime: DateTime<Utc>) -> TransformedTransaction {
+    let method_id = selectors::method_id(&tx.input);
+    let (block_number, transaction_index) = (hex_to_u64(&tx.block_number), hex_to_u64(&tx.transaction_index));
+    TransformedTransaction {
+        id: row_id(block_number, transaction_index),
+        block_hash: tx.block_hash.clone(),
+        block_number,
+        chain_id: hex_to_u64(&tx.chain_id),
+        from: normalize_address(&tx.from),
+        gas: hex_to_u64(&tx.gas),
+        gas_price: hex_to_u64(&tx.gas_price),
+        hash: tx.hash.clone(),
+        input: tx.input.clone(),
+        method_signature: method_id.as_deref().and_then(selectors::lookup),
+        method_id,
+        nonce: hex_to_u64(&tx.nonce),
+        r: tx.r.clone(),
+        s: tx.s.clone(),
+        to: tx.to.as_deref().map(normalize_address),
+        transaction_index,
+        tx_type: hex_to_u64(&tx.tx_type),
+        v: tx.v.clone(),
+        value: hex_to_decimal(&tx.value),
+        status: None,
+        gas_used: None,
+        effective_gas_price: None,
+        contract_address: None,
+        fee: None,
+        burnt_fee: None,
+        priority_fee: None,
+        value_ether: None,
+        gas_price_gwei: None,
+        effective_gas_price_gwei: None,
+        fee_ether: None,
+        burnt_fee_ether: None,
+        priority_fee_ether: None,
+        datetime,
+        extra: canonical(&tx.extra),
+    }
+}
+
+// Copies each transaction's receipt outcome onto it, matched by transaction hash
+pub fn attach_receipts(transactions: &mut [TransformedTransaction], receipts: &[TransformedReceipt]) {
+    let by_hash: HashMap<&str, &TransformedReceipt> = receipts.iter().map(|receipt| (receipt.transaction_hash.as_str(), receipt)).collect();
+    for tx in transactions {
+        if let Some(receipt) = by_hash.get(tx.hash.as_str()) {
+            tx.status = Some(receipt.status);
+            tx.gas_used = Some(receipt.gas_used);
+            tx.effective_gas_price = Some(receipt.effective_gas_price);
+            tx.contract_address = receipt.contract_address.clone();
+        }
+    }
+}
+
+// Splits the fee of a transaction with its receipt outcome attached into the part burnt at the
+// block's base fee and the priority fee. Transactions paying less than the base fee, such as
+// OP-stack deposits, burn what they paid; before London nothing is burnt.
+pub fn set_fees(tx: &mut TransformedTransaction, base_fee_per_gas: Option<u64>) {
+    let (Some(gas_used), Some(price)) = (tx.gas_used, tx.effective_gas_price) else { return };
+    let (fee, burnt, priority) = split_fee(gas_used, price, base_fee_per_gas);
+    tx.fee = Some(fee.to_string());
+    tx.burnt_fee = Some(burnt.to_string());
+    tx.priority_fee = Some(priority.to_string());
+}
+
+// (fee, burnt, priority fee) of a transaction
+fn split_fee(gas_used: u64, price: u64, base_fee_per_gas: Option<u64>) -> (U256, U256, U256) {
+    let burnt_price = base_fee_per_gas.unwrap_or(0).min(price);
+    let gas_used = U256::from(gas_used);
+    (gas_used * U256::from(price), gas_used * U256::from(burnt_price), gas_used * U256::from(price - burnt_price))
+}
+
+// (transactions counted, total fees, burnt fees) per block number
+pub type FeeTotals = HashMap<u64, (usize, U256, U256)>;
+
+fn add_fee(totals: &mut FeeTotals, block_number: u64, fee: U256, burnt: U256) {
+    let total = totals.entry(block_number).or_default();
+    total.0 += 1;
+    total.1 = total.1.saturating_add(fee);
+    total.2 = total.2.saturating_add(burnt);
+}
+
+// Fee totals of raw blocks over all their receipts. Filters drop transactions before the transform,
+// so `index` sums these first and the block totals still cover every transaction.
+pub fn receipt_fee_totals(blocks: &[Block], receipts: &[Vec<Receipt>]) -> FeeTotals {
+    let base_fees: HashMap<u64, Option<u64>> =
+        blocks.iter().map(|block| (hex_to_u64(&block.number), block.base_fee_per_gas.as_deref().map(hex_to_u64))).collect();
+    let mut totals = FeeTotals::new();
+    for receipt in receipts.iter().flatten() {
+        let block_number = hex_to_u64(&receipt.block_number);
+        let base_fee = base_fees.get(&block_number).copied().flatten();
+        let (fee, burnt, _) = split_fee(hex_to_u64(&receipt.gas_used), hex_to_u64(&receipt.effective_gas_price), base_fee);
+        add_fee(&mut totals, block_number, fee, burnt);
+    }
+    totals
+}
+
+// Sets the totals of each block, null unless they cover every transaction of the block
+pub fn set_fee_totals(blocks: &mut [TransformedBlock], totals: &FeeTotals) {
+    for block in blocks {
+        let (count, total, burnt) = totals.get(&block.number).copied().unwrap_or_default();
+        let complete = count == block.transaction_hashes.len();
+        block.total_fees = complete.then(|| total.to_string());
+        block.burnt_fees = complete.then(|| burnt.to_string());
+    }
+}
+
+// Sets the fees of the transactions
+pub fn set_transaction_fees(blocks: &[TransformedBlock], transactions: &mut [TransformedTransaction]) {
+    let base_fees: HashMap<u64, Option<u64>> = blocks.iter().map(|block| (block.number, block.base_fee_per_gas)).collect();
+    for tx in transactions {
+        set_fees(tx, base_fees.get(&tx.block_number).copied().flatten());
+    }
+}
+
+// Sets the fees of the transactions and the totals of their blocks, for blocks holding all of
+// their transactions
+pub fn apply_fees(blocks: &mut [TransformedBlock], transactions: &mut [TransformedTransaction]) {
+    set_transaction_fees(blocks, transactions);
+    let mut totals = FeeTotals::new();
+    for tx in transactions.iter() {
+        if let (Some(fee), Some(burnt)) = (&tx.fee, &tx.burnt_fee) {
+            add_fee(&mut totals, tx.block_number, U256::from_dec_str(fee).unwrap_or_default(), U256::from_dec_str(burnt).unwrap_or_default());
+        }
+    }
+    set_fee_totals(blocks, &totals);
+}
+
+static NATIVE_UNITS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_native_units(enabled: bool) {
+    NATIVE_UNITS.store(enabled, Ordering::SeqCst);
+}
+
+const ETHER_DECIMALS: u8 = 18;
+const GWEI_DECIMALS: u8 = 9;
+
+// With --native-units, fills the ether and gwei columns from the wei amounts
+pub fn apply_native_units(blocks: &mut [TransformedBlock], transactions: &mut [TransformedTransaction]) {
+    if NATIVE_UNITS.load(Ordering::Relaxed) {
+        convert_native_units(blocks, transactions);
+    }
+}
+
+// Exact decimal strings, so no precision is lost on amounts beyond what a float holds; the wei
+// columns are left as they are
+fn convert_native_units(blocks: &mut [TransformedBlock], transactions: &mut [TransformedTransaction]) {
+    let ether = |wei: &Option<String>| wei.as_deref().map(|wei| scale(wei, ETHER_DECIMALS));
+    let gwei = |wei: Option<u64>| wei.map(|wei| scale(&wei.to_string(), GWEI_DECIMALS));
+    for block in blocks {
+        block.base_fee_per_gas_gwei = gwei(block.base_fee_per_gas);
+        block.total_fees_ether = ether(&block.total_fees);
+        block.burnt_fees_ether = ether(&block.burnt_fees);
+    }
+    for tx in transactions {
+        tx.value_ether = Some(scale(&tx.value, ETHER_DECIMALS));
+        tx.gas_price_gwei = gwei(Some(tx.gas_price));
+        tx.effective_gas_price_gwei = gwei(tx.effective_gas_price);
+        tx.fee_ether = ether(&tx.fee);
+        tx.burnt_fee_ether = ether(&tx.burnt_fee);
+        tx.priority_fee_ether = ether(&tx.priority_fee);
+    }
+}
+
+pub fn transform_receipt(receipt: &Receipt, datetime: DateTime<Utc>) -> TransformedReceipt {
+    let (block_number, transaction_index) = (hex_to_u64(&receipt.block_number), hex_to_u64(&receipt.transaction_index));
+    TransformedReceipt {
+        id: row_id(block_number, transaction_index),
+        block_hash: receipt.block_hash.clone(),
+        block_number,
+        contract_address: receipt.contract_address.as_deref().map(normalize_address),
+        cumulative_gas_used: hex_to_u64(&receipt.cumulative_gas_used),
+        effective_gas_price: hex_to_u64(&receipt.effective_gas_price),
+        from: normalize_address(&receipt.from),
+        gas_used: hex_to_u64(&receipt.gas_used),
+        logs: receipt
+            .logs
+            .iter()
+            .map(|log| {
+                let mut log = topics::annotate(log);
+                if let Some(address) = log["address"].as_str().map(normalize_address) {
+                    log["address"] = Value::String(address);
+                }
+                log.sort_all_objects();
+                log
+            })
+            .collect(),
+        logs_bloom: receipt.logs_bloom.clone(),
+        status: hex_to_bool(&receipt.status),
+        to: receipt.to.as_deref().map(normalize_address),
+        transaction_hash: receipt.transaction_hash.clone(),
+        transaction_index,
+        tx_type: hex_to_u64(&receipt.tx_type),
+        datetime,
+        extra: canonical(&receipt.extra),
+    }
+}
+
+// Transforms one fetched block with its transactions and receipts, stamping them with the block time
+pub fn transform_block_data(
+    block: &Block,
+    transactions: &[Transaction],
+    receipts: &[Receipt],
+) -> (TransformedBlock, Vec<TransformedTransaction>, Vec<TransformedReceipt>) {
+    let mut transformed = transform_block(block);
+    let datetime = transformed.datetime;
+    let mut transactions: Vec<_> = transactions.iter().map(|tx| transform_transaction(tx, datetime)).collect();
+    let mut receipts: Vec<_> = receipts.iter().map(|receipt| transform_receipt(receipt, datetime)).collect();
+    sort_by_index(&mut transactions, &mut receipts);
+    attach_receipts(&mut transactions, &receipts);
+    apply_fees(std::slice::from_mut(&mut transformed), &mut transactions);
+    apply_native_units(std::slice::from_mut(&mut transformed), &mut transactions);
+    (transformed, transactions, receipts)
+}
+
+// (zero, non-zero) byte counts of a hex string, without decoding it
+pub fn calldata_byte_counts(input: &str) -> (u64, u64) {
+    let hex = input.strip_prefix("0x").unwrap_or(input).as_bytes();
+    hex.chunks_exact(2).fold((0, 0), |(zero, nonzero), byte| {
+        if byte == b"00" {
+            (zero + 1, nonzero)
+        } else {
+            (zero, nonzero + 1)
+        }
+    })
+}
+
+// Calldata and blob usage of a fetched block; needs the raw transactions for blob hashes
+pub fn data_usage(block: &Block, transactions: &[Transaction]) -> BlockDataUsage {
+    let (zero, nonzero) = transactions
+        .iter()
+        .map(|tx| calldata_byte_counts(&tx.input))
+        .fold((0, 0), |(zero, nonzero), (z, n)| (zero + z, nonzero + n));
+    let blob_counts: Vec<u64> = transactions
+        .iter()
+        .filter_map(|tx| tx.blob_versioned_hashes.as_ref())
+        .map(|hashes| hashes.len() as u64)
+        .filter(|count| *count > 0)
+        .collect();
+    BlockDataUsage {
+        block_number: hex_to_u64(&block.number),
+        datetime: Utc.timestamp_opt(hex_to_u64(&block.timestamp) as i64, 0).single().unwrap_or_default(),
+        size: hex_to_u64(&block.size),
+        gas_used: hex_to_u64(&block.gas_used),
+        transaction_count: transactions.len() as u64,
+        calldata_bytes: zero + nonzero,
+        calldata_zero_bytes: zero,
+        calldata_nonzero_bytes: nonzero,
+        calldata_gas: 4 * zero + 16 * nonzero,
+        blob_transactions: blob_counts.len() as u64,
+        blob_count: blob_counts.iter().sum(),
+        blob_gas_used: block.blob_gas_used.as_deref().map(hex_to_u64),
+        excess_blob_gas: block.excess_blob_gas.as_deref().map(hex_to_u64),
+    }
+}
+
+// Pairs i < j with values[i] > values[j], by merge sort
+pub fn count_inversions<T: Ord + Clone>(values: &[T]) -> u64 {
+    fn sort<T: Ord + Clone>(values: &mut [T]) -> u64 {
+        if values.len() < 2 {
+            return 0;
+        }
+        let mid = values.len() / 2;
+        let mut inversions = sort(&mut values[..mid]) + sort(&mut values[mid..]);
+        let (left, right) = (values[..mid].to_vec(), values[mid..].to_vec());
+        let (mut i, mut j) = (0, 0);
+        for slot in values.iter_mut() {
+            if j == right.len() || (i < left.len() && left[i] <= right[j]) {
+                *slot = left[i].clone();
+                i += 1;
+            } else {
+                *slot = right[j].clone();
+                inversions += (left.len() - i) as u64;
+                j += 1;
+            }
+        }
+        inversions
+    }
+    sort(&mut values.to_vec())
+}
+
+fn adherence(inversions: u64, count: usize) -> Option<f64> {
+    let pairs = (count as u64 * count.saturating_sub(1) as u64) / 2;
+    (pairs > 0).then(|| 1.0 - inversions as f64 / pairs as f64)
+}
+
+// Ordering of a block's receipts (one per transaction) against priority fees and first-seen times
+pub fn ordering_analysis(
+    block: &TransformedBlock,
+    receipts: &[TransformedReceipt],
+    first_seen: impl Fn(&str) -> Option<DateTime<Utc>>,
+) -> OrderingAnalysis {
+    let mut receipts: Vec<&TransformedReceipt> = receipts.iter().collect();
+    receipts.sort_by_key(|receipt| receipt.transaction_index);
+
+    let base_fee = block.base_fee_per_gas.unwrap_or_default();
+    let fees: Vec<u64> = receipts.iter().map(|receipt| receipt.effective_gas_price.saturating_sub(base_fee)).collect();
+    // A later transaction paying more is an inversion of descending order
+    let priority_fee_inversions = count_inversions(&fees.iter().map(|fee| std::cmp::Reverse(*fee)).collect::<Vec<_>>());
+    let seen: Vec<DateTime<Utc>> = receipts.iter().filter_map(|receipt| first_seen(&receipt.transaction_hash)).collect();
+    let first_seen_inversions = count_inversions(&seen);
+
+    OrderingAnalysis {
+        block_number: block.number,
+        datetime: block.datetime,
+        transaction_count: receipts.len() as u64,
+        base_fee_per_gas: block.base_fee_per_gas,
+        min_priority_fee: fees.iter().min().copied(),
+        max_priority_fee: fees.iter().max().copied(),
+        priority_fee_inversions,
+        priority_fee_adherence: adherence(priority_fee_inversions, fees.len()),
+        first_seen_count: seen.len() as u64,
+        first_seen_inversions,
+        first_seen_adherence: adherence(first_seen_inversions, seen.len()),
+    }
+}
+
+pub fn init_code_hash(input: &str) -> String {
+    encode_hex(&keccak256(&decode_hex(input).unwrap_or_default()))
+}
+
+// Contracts created by the given transactions: `to` is null and the receipt carries the new address
+pub fn extract_contracts(transactions: &[TransformedTransaction], receipts: &[TransformedReceipt]) -> Vec<TransformedContract> {
+    let creations: HashMap<&str, &TransformedTransaction> = transactions
+        .iter()
+        .filter(|tx| tx.to.is_none())
+        .map(|tx| (tx.hash.as_str(), tx))
+        .collect();
+    receipts
+        .iter()
+        .filter_map(|receipt| {
+            let address = receipt.contract_address.as_ref()?;
+            let tx = creations.get(receipt.transaction_hash.as_str())?;
+            Some(TransformedContract {
+                address: address.clone(),
+                creator: tx.from.clone(),
+                transaction_hash: tx.hash.clone(),
+                block_number: tx.block_number,
+                init_code_hash: init_code_hash(&tx.input),
+                datetime: tx.datetime,
+            })
+        })
+        .collect()
+}
+
+// Fields of an RPC log that the logs dataset has columns for, or that `topics::annotate` derives
+const LOG_FIELDS: &[&str] = &[
+    "address", "blockHash", "blockNumber", "data", "logIndex", "removed", "topics", "transactionHash", "transactionIndex",
+    "topic0", "event_signature", "indexed",
+];
+
+// The receipts' logs as rows of their own, one per log
+pub fn extract_logs(receipts: &[TransformedReceipt]) -> Vec<EventLog> {
+    let mut logs = Vec::new();
+    for receipt in receipts {
+        for log in &receipt.logs {
+            let topic = |position: usize| log["topics"][position].as_str().map(str::to_string);
+            let log_index = hex_to_u64(log["logIndex"].as_str().unwrap_or_default());
+            let extra = log.as_object().into_iter().flatten().filter(|(key, _)| !LOG_FIELDS.contains(&key.as_str()));
+            logs.push(EventLog {
+                id: row_id(receipt.block_number, log_index),
+                block_number: receipt.block_number,
+                block_hash: receipt.block_hash.clone(),
+                transaction_hash: receipt.transaction_hash.clone(),
+                transaction_index: receipt.transaction_index,
+                log_index,
+                address: log["address"].as_str().unwrap_or_default().to_string(),
+                topic0: topic(0),
+                topic1: topic(1),
+                topic2: topic(2),
+                topic3: topic(3),
+                data: log["data"].as_str().unwrap_or("0x").to_string(),
+                event_signature: log["event_signature"].as_str().map(str::to_string),
+                removed: log["removed"].as_bool().unwrap_or(false),
+                datetime: receipt.datetime,
+                extra: extra.map(|(key, value)| (key.clone(), value.clone())).collect(),
+            });
+        }
+    }
+    logs
+}
+
+// A row of the logs dataset as the receipt log it was extracted from, annotated like
+// transform_receipt leaves it
+pub fn receipt_log(log: &EventLog) -> Value {
+    let topics: Vec<&str> = [&log.topic0, &log.topic1, &log.topic2, &log.topic3].into_iter().map_while(Option::as_deref).collect();
+    let mut fields = log.extra.clone();
+    fields.extend([
+        ("address", Value::from(log.address.as_str())),
+        ("blockHash", Value::from(log.block_hash.as_str())),
+        ("blockNumber", Value::from(format!("{:#x}", log.block_number))),
+        ("data", Value::from(log.data.as_str())),
+        ("logIndex", Value::from(format!("{:#x}", log.log_index))),
+        ("removed", Value::from(log.removed)),
+        ("topics", Value::from(topics)),
+        ("transactionHash", Value::from(log.transaction_hash.as_str())),
+        ("transactionIndex", Value::from(format!("{:#x}", log.transaction_index))),
+    ]
+    .map(|(key, value)| (key.to_string(), value)));
+    let mut log = topics::annotate(&Value::Object(fields));
+    log.sort_all_objects();
+    log
+}
+
+// NFT movements decoded from the receipts' logs, one row per token id
+pub fn extract_nft_transfers(receipts: &[TransformedReceipt]) -> Vec<NftTransfer> {
+    let mut transfers = Vec::new();
+    for receipt in receipts {
+        for log in &receipt.logs {
+            let (token, from, to, operator, moved) = match events::decode(log) {
+                Some(TokenEvent::NftTransfer { token, from, to, token_id }) => (token, from, to, None, vec![(token_id, U256::one())]),
+                Some(TokenEvent::MultiTransfer { token, operator, from, to, ids, amounts }) => {
+                    (token, from, to, Some(operator), ids.into_iter().zip(amounts).collect())
+                },
+                _ => continue,
+            };
+            let log_index = hex_to_u64(log["logIndex"].as_str().unwrap_or_default());
+            let (token, from, to, operator) = (normalize_address(&token), normalize_address(&from), normalize_address(&to), operator.as_deref().map(normalize_address));
+            for (batch_index, (token_id, amount)) in moved.into_iter().enumerate() {
+                transfers.push(NftTransfer {
+                    id: row_id(receipt.block_number, log_index),
+                    token_address: token.clone(),
+                    token_id: token_id.to_string(),
+                    from: from.clone(),
+                    to: to.clone(),
+                    amount: amount.to_string(),
+                    standard: if operator.is_some() { "erc1155" } else { "erc721" }.to_string(),
+                    operator: operator.clone(),
+                    block_number: receipt.block_number,
+                    transaction_hash: receipt.transaction_hash.clone(),
+                    log_index,
+                    batch_index: batch_index as u64,
+                    datetime: receipt.datetime,
+                });
+            }
+        }
+    }
+    transfers
+}
+
+pub fn extract_token_transfers(receipts: &[TransformedReceipt]) -> Vec<TokenTransfer> {
+    let mut transfers = Vec::new();
+    for receipt in receipts {
+        for log in &receipt.logs {
+            let Some(TokenEvent::Transfer { token, from, to, value }) = events::decode(log) else { continue };
+            let log_index = hex_to_u64(log["logIndex"].as_str().unwrap_or_default());
+            transfers.push(TokenTransfer {
+                id: row_id(receipt.block_number, log_index),
+                token_address: normalize_address(&token),
+                from: normalize_address(&from),
+                to: normalize_address(&to),
+                value: value.to_string(),
+                token_symbol: None,
+                token_name: None,
+                token_decimals: None,
+                value_scaled: None,
+                spam: false,
+                spam_reasons: Vec::new(),
+                block_number: receipt.block_number,
+                transaction_hash: receipt.transaction_hash.clone(),
+                log_index,
+                datetime: receipt.datetime,
+            });
+        }
+    }
+    transfers
+}
+
+// Linea message service events decoded from the receipts' logs, one row per event
+pub fn extract_bridge_messages(receipts: &[TransformedReceipt]) -> Vec<BridgeMessage> {
+    let mut messages = Vec::new();
+    for receipt in receipts {
+        for log in &receipt.logs {
+            let Some(event) = bridge::decode(log) else { continue };
+            let log_index = hex_to_u64(log["logIndex"].as_str().unwrap_or_default());
+            let message = |contract: &str, event: &str, direction: &str, message_hash: String| BridgeMessage {
+                id: row_id(receipt.block_number, log_index),
+                block_number: receipt.block_number,
+                block_hash: receipt.block_hash.clone(),
+                transaction_hash: receipt.transaction_hash.clone(),
+                transaction_index: receipt.transaction_index,
+                log_index,
+                contract_address: normalize_address(contract),
+                event: event.to_string(),
+                direction: direction.to_string(),
+                message_hash,
+                sender: None,
+                recipient: None,
+                fee: None,
+                value: None,
+                nonce: None,
+                calldata: None,
+                datetime: receipt.datetime,
+            };
+            messages.push(match event {
+                BridgeEvent::Sent { contract, message_hash, from, to, fee, value, nonce, calldata } => BridgeMessage {
+                    sender: Some(normalize_address(&from)),
+                    recipient: Some(normalize_address(&to)),
+                    fee: Some(fee.to_string()),
+                    value: Some(value.to_string()),
+                    nonce: Some(nonce.to_string()),
+                    calldata: Some(calldata),
+                    ..message(&contract, "MessageSent", bridge::L2_TO_L1, message_hash)
+                },
+                BridgeEvent::Claimed { contract, message_hash } => message(&contract, "MessageClaimed", bridge::L1_TO_L2, message_hash),
+            });
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::Block;
+    use serde_json::json;
+
+    #[test]
+    fn test_row_id() {
+        assert_eq!(row_id(7, 3), 7_000_003);
+        assert!(row_id(7, ROW_ID_STRIDE - 1) < row_id(8, 0));
+        assert!(std::panic::catch_unwind(|| row_id(7, ROW_ID_STRIDE)).is_err());
+    }
+
+    #[test]
+    fn test_hex_to_u64() {
+        assert_eq!(hex_to_u64("0x0"), 0);
+        assert_eq!(hex_to_u64("0x1"), 1);
+        assert_eq!(hex_to_u64("0xa"), 10);
+        assert_eq!(hex_to_u64("0xff"), 255);
+        assert_eq!(hex_to_u64("ff"), 255); // Test without 0x prefix
+        assert_eq!(hex_to_u64("invalid"), 0); // Test invalid input
+    }
+
+    #[test]
+    fn test_hex_to_bool() {
+        assert!(!hex_to_bool("0x0"));
+        assert!(hex_to_bool("0x1"));
+        assert!(!hex_to_bool("0x2")); // Any non-1 value should be false
+        assert!(!hex_to_bool("invalid")); // Invalid input should return false
+    }
+
+    #[test]
+    fn test_address_case() {
+        // EIP-55 test vectors
+        for address in ["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359", "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB"] {
+            assert_eq!(checksum_address(&address.to_lowercase()), address);
+        }
+        assert_eq!(normalize_address("0xFB6916095CA1DF60BB79CE92CE3EA74C37C5D359"), "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359");
+    }
+
+    #[test]
+    fn test_block_transformation() {
+        let block = Block {
+            base_fee_per_gas: Some("0xa".to_string()),
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            difficulty: "0x5".to_string(),
+            extra_data: "0x".to_string(),
+            gas_limit: "0x1234".to_string(),
+            gas_used: "0x1000".to_string(),
+            hash: "0xabc".to_string(),
+            logs_bloom: "0x0".to_string(),
+            miner: "0xdef".to_string(),
+            mix_hash: "0x123".to_string(),
+            nonce: "0x1".to_string(),
+            number: "0x1".to_string(),
+            parent_hash: "0x456".to_string(),
+            receipts_root: "0x789".to_string(),
+            sha3_uncles: "0x111".to_string(),
+            size: "0x100".to_string(),
+            state_root: "0x222".to_string(),
+            timestamp: "0x60000000".to_string(), // Unix timestamp in hex
+            total_difficulty: "0x10".to_string(),
+            transaction_hashes: vec!["0xtx1".to_string()],
+            transactions_root: "0x333".to_string(),
+            uncles: vec![],
+            extra: Default::default(),
+        };
+
+        let transformed = TransformedBlock {
+            base_fee_per_gas: Some(10),
+            difficulty: 5,
+            extra_data: "0x".to_string(),
+            gas_limit: 0x1234,
+            gas_used: 0x1000,
+            hash: "0xabc".to_string(),
+            logs_bloom: "0x0".to_string(),
+            miner: "0xdef".to_string(),
+            mix_hash: "0x123".to_string(),
+            nonce: "0x1".to_string(),
+            number: 1,
+            parent_hash: "0x456".to_string(),
+            receipts_root: "0x789".to_string(),
+            sha3_uncles: "0x111".to_string(),
+            size: 0x100,
+            state_root: "0x222".to_string(),
+            datetime: Utc.timestamp_opt(0x60000000, 0).unwrap(),
+            total_difficulty: 16,
+            transaction_hashes: vec!["0xtx1".to_string()],
+            transactions_root: "0x333".to_string(),
+            uncles: vec![],
+            total_fees: None,
+            burnt_fees: None,
+            base_fee_per_gas_gwei: None,
+            total_fees_ether: None,
+            burnt_fees_ether: None,
+            extra: Default::default(),
+        };
+
+        let result = transform_block(&block);
+
+        assert_eq!(result.base_fee_per_gas, transformed.base_fee_per_gas);
+        assert_eq!(result.difficulty, transformed.difficulty);
+        assert_eq!(result.gas_limit, transformed.gas_limit);
+        assert_eq!(result.gas_used, transformed.gas_used);
+        assert_eq!(result.number, transformed.number);
+        assert_eq!(result.size, transformed.size);
+        assert_eq!(result.total_difficulty, transformed.total_difficulty);
+        assert_eq!(result.datetime, transformed.datetime);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        let block: Block = serde_json::from_value(serde_json::json!({
+            "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x1", "gasUsed": "0x0", "hash": "0xa", "logsBloom": "0x",
+            "miner": "0x0", "mixHash": "0x0", "nonce": "0x0", "number": "0x7", "parentHash": "0x0", "receiptsRoot": "0x0",
+            "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0", "timestamp": "0x1", "totalDifficulty": "0x0",
+            "transactions": ["0x1", "0x0"], "transactionsRoot": "0x0", "uncles": [],
+            "zeta": "0x1", "alpha": { "y": 1, "b": 2 },
+        }))
+        .unwrap();
+        let tx = |index: u64| -> Transaction {
+            serde_json::from_value(serde_json::json!({
+                "blockHash": "0xa", "blockNumber": "0x7", "chainId": "0x1", "from": "0x1", "gas": "0x0",
+                "gasPrice": "0x0", "hash": format!("0x{}", index), "input": "0x", "nonce": "0x0", "r": "0x", "s": "0x",
+                "to": null, "transactionIndex": format!("0x{:x}", index), "type": "0x2", "v": "0x", "value": "0x0",
+            }))
+            .unwrap()
+        };
+        let receipt = |index: u64| -> Receipt {
+            serde_json::from_value(serde_json::json!({
+                "blockHash": "0xa", "blockNumber": "0x7", "contractAddress": null, "cumulativeGasUsed": "0x0",
+                "from": "0x1", "gasUsed": "0x0", "logsBloom": "0x", "status": "0x1", "to": null,
+                "transactionHash": format!("0x{}", index), "transactionIndex": format!("0x{:x}", index), "type": "0x2",
+                "logs": [{ "topics": [], "data": "0x", "address": "0xc" }],
+            }))
+            .unwrap()
+        };
+
+        let (block, transactions, receipts) = transform_block_data(&block, &[tx(10), tx(2)], &[receipt(10), receipt(2)]);
+        assert_eq!(transactions.iter().map(|tx| tx.transaction_index).collect::<Vec<_>>(), [2, 10]);
+        assert_eq!(receipts.iter().map(|receipt| receipt.transaction_hash.as_str()).collect::<Vec<_>>(), ["0x2", "0x10"]);
+        let json = serde_json::to_string(&block).unwrap();
+        assert!(json.ends_with(r#""extra":{"alpha":{"b":2,"y":1},"zeta":"0x1"}}"#));
+        assert!(serde_json::to_string(&receipts[0].logs[0]).unwrap().starts_with(r#"{"address":"0xc","data":"0x","#));
+    }
+
+    #[test]
+    fn test_apply_fees() {
+        let block = |number: u64, base_fee: Option<u64>, transactions: usize| TransformedBlock {
+            base_fee_per_gas: base_fee,
+            transaction_hashes: vec!["0xh".to_string(); transactions],
+            ..fixtures::block(number)
+        };
+        let tx = |block_number: u64, receipt: Option<(u64, u64)>| TransformedTransaction {
+            gas_used: receipt.map(|(gas, _)| gas),
+            effective_gas_price: receipt.map(|(_, price)| price),
+            ..fixtures::transaction(block_number, 0)
+        };
+        // London block, a deposit paying nothing, a pre-London block and a block missing a receipt
+        let mut blocks = vec![block(1, Some(10), 2), block(2, None, 1), block(3, Some(10), 2)];
+        let mut transactions = vec![tx(1, Some((21000, 12))), tx(1, Some((50000, 0))), tx(2, Some((100, 5))), tx(3, Some((1, 10))), tx(3, None)];
+        apply_fees(&mut blocks, &mut transactions);
+
+        let fees = |tx: &TransformedTransaction| (tx.fee.clone(), tx.burnt_fee.clone(), tx.priority_fee.clone());
+        let some = |fee: &str, burnt: &str, priority: &str| (Some(fee.to_string()), Some(burnt.to_string()), Some(priority.to_string()));
+        assert_eq!(fees(&transactions[0]), some("252000", "210000", "42000"));
+        assert_eq!(fees(&transactions[1]), some("0", "0", "0"));
+        assert_eq!(fees(&transactions[2]), some("500", "0", "500"));
+        assert_eq!(fees(&transactions[4]), (None, None, None));
+        let totals: Vec<_> = blocks.iter().map(|block| (block.total_fees.as_deref(), block.burnt_fees.as_deref())).collect();
+        assert_eq!(totals, vec![(Some("252000"), Some("210000")), (Some("500"), Some("0")), (None, None)]);
+
+        // Summed over the raw receipts, the totals do not depend on which transactions are kept
+        let raw_block: Block = serde_json::from_value(json!({
+            "baseFeePerGas": "0xa", "difficulty": "0x0", "extraData": "0x", "gasLimit": "0x1", "gasUsed": "0x0", "hash": "0x1",
+            "logsBloom": "0x", "miner": "0x0", "mixHash": "0x0", "nonce": "0x0", "number": "0x1", "parentHash": "0x0",
+            "receiptsRoot": "0x0", "sha3Uncles": "0x0", "size": "0x1", "stateRoot": "0x0", "timestamp": "0x1",
+            "totalDifficulty": "0x0", "transactions": ["0xh", "0xh"], "transactionsRoot": "0x0", "uncles": [],
+        }))
+        .unwrap();
+        let raw_receipt = |block_number: u64, gas_used: u64, price: u64| -> Receipt {
+            serde_json::from_value(json!({
+                "blockHash": "0x1", "blockNumber": format!("0x{:x}", block_number), "contractAddress": null, "cumulativeGasUsed": "0x0",
+                "effectiveGasPrice": format!("0x{:x}", price), "from": "0xf", "gasUsed": format!("0x{:x}", gas_used), "logs": [],
+                "logsBloom": "0x", "status": "0x1", "to": null, "transactionHash": "0xh", "transactionIndex": "0x0", "type": "0x2",
+            }))
+            .unwrap()
+        };
+        let receipt_totals = receipt_fee_totals(&[raw_block], &[vec![raw_receipt(1, 21000, 12), raw_receipt(1, 50000, 0)]]);
+        let mut filtered = vec![block(1, Some(10), 2)];
+        let mut kept = vec![tx(1, Some((21000, 12)))];
+        apply_fees(&mut filtered, &mut kept);
+        assert_eq!(filtered[0].total_fees, None);
+        set_fee_totals(&mut filtered, &receipt_totals);
+        assert_eq!((filtered[0].total_fees.as_deref(), filtered[0].burnt_fees.as_deref()), (Some("252000"), Some("210000")));
+
+        // Native units are exact, beyond what an f64 holds
+        transactions[0].value = "123456789012345678901234567".to_string();
+        convert_native_units(&mut blocks, &mut transactions);
+        let tx = &transactions[0];
+        assert_eq!(tx.value_ether.as_deref(), Some("123456789.012345678901234567"));
+        assert_eq!((tx.gas_price_gwei.as_deref(), tx.effective_gas_price_gwei.as_deref()), (Some("0"), Some("0.000000012")));
+        assert_eq!((tx.fee_ether.as_deref(), tx.burnt_fee_ether.as_deref()), (Some("0.000000000000252"), Some("0.00000000000021")));
+        assert_eq!(transactions[4].fee_ether, None);
+        assert_eq!((blocks[0].base_fee_per_gas_gwei.as_deref(), blocks[0].total_fees_ether.as_deref()), (Some("0.00000001"), Some("0.000000000000252")));
+        assert_eq!((blocks[1].base_fee_per_gas_gwei.as_deref(), blocks[2].total_fees_ether.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn test_native_units() {
+        let mut blocks = vec![fixtures::block(1)];
+        let one_wei = TransformedTransaction {
+            value: "1".to_string(),
+            gas_price: 1,
+            effective_gas_price: Some(1),
+            fee: Some("1".to_string()),
+            ..fixtures::transaction(1, 0)
+        };
+        // No receipt, so no effective gas price or fees
+        let mut transactions = vec![one_wei, fixtures::transaction(1, 1)];
+        convert_native_units(&mut blocks, &mut transactions);
+
+        let tx = &transactions[0];
+        assert_eq!((tx.value_ether.as_deref(), tx.fee_ether.as_deref()), (Some("0.000000000000000001"), Some("0.000000000000000001")));
+        assert_eq!((tx.gas_price_gwei.as_deref(), tx.effective_gas_price_gwei.as_deref()), (Some("0.000000001"), Some("0.000000001")));
+        assert_eq!((tx.burnt_fee_ether.as_deref(), tx.priority_fee_ether.as_deref()), (None, None));
+        let tx = &transactions[1];
+        assert_eq!((tx.value_ether.as_deref(), tx.effective_gas_price_gwei.as_deref()), (Some("0"), None));
+        assert_eq!((tx.fee_ether.as_deref(), tx.burnt_fee_ether.as_deref(), tx.priority_fee_ether.as_deref()), (None, None, None));
+        let block = &blocks[0];
+        assert_eq!((block.base_fee_per_gas_gwei.as_deref(), block.total_fees_ether.as_deref(), block.burnt_fees_ether.as_deref()), (None, None, None));
+    }
+
+    #[test]
+    fn test_extract_contracts() {
+        let tx = |hash: &str, to: Option<&str>| TransformedTransaction {
+            from: "0xcreator".to_string(),
+            hash: hash.to_string(),
+            input: "0x6080".to_string(),
+            to: to.map(str::to_string),
+            ..fixtures::transaction(7, 0)
+        };
+        let receipt = |hash: &str, contract_address: Option<&str>| TransformedReceipt {
+            contract_address: contract_address.map(str::to_string),
+            transaction_hash: hash.to_string(),
+            ..fixtures::receipt(7, 0)
+        };
+
+        let transactions = vec![tx("0x1", None), tx("0x2", Some("0xdead"))];
+        let receipts = vec![receipt("0x1", Some("0xnew")), receipt("0x2", None)];
+        let contracts = extract_contracts(&transactions, &receipts);
+
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0].address, "0xnew");
+        assert_eq!(contracts[0].creator, "0xcreator");
+        assert_eq!(contracts[0].block_number, 7);
+        assert_eq!(contracts[0].init_code_hash, encode_hex(&keccak256(&[0x60, 0x80])));
+    }
+
+    #[test]
+    fn test_extract_token_transfers() {
+        let topic = |value: &str| format!("0x{:0>64}", value);
+        let receipt = TransformedReceipt {
+            logs: vec![
+                json!({ "address": "0xnft", "logIndex": "0x1", "data": "0x",
+                        "topics": [events::TRANSFER_TOPIC, topic("0"), topic("aa"), topic("2a")] }),
+                json!({ "address": "0xmulti", "logIndex": "0x2", "data": format!("{}{:0>64}", topic("5"), "3"),
+                        "topics": [events::TRANSFER_SINGLE_TOPIC, topic("cc"), topic("aa"), topic("bb")] }),
+                json!({ "address": "0xerc20", "logIndex": "0x3", "data": topic("1"),
+                        "topics": [events::TRANSFER_TOPIC, topic("aa"), topic("bb")] }),
+            ],
+            ..fixtures::receipt(7, 0)
+        };
+
+        let erc20 = extract_token_transfers(std::slice::from_ref(&receipt));
+        assert_eq!(erc20.len(), 1);
+        assert_eq!((erc20[0].token_address.as_str(), erc20[0].value.as_str(), erc20[0].log_index), ("0xerc20", "1", 3));
+        assert_eq!((erc20[0].value_scaled.as_deref(), erc20[0].id), (None, 7_000_003));
+
+        let transfers = extract_nft_transfers(&[receipt]);
+        assert_eq!(transfers.len(), 2);
+        assert_eq!((transfers[0].standard.as_str(), transfers[0].token_id.as_str(), transfers[0].amount.as_str()), ("erc721", "42", "1"));
+        assert_eq!(transfers[0].from, events::ZERO_ADDRESS);
+        assert_eq!((transfers[1].standard.as_str(), transfers[1].token_id.as_str(), transfers[1].amount.as_str()), ("erc1155", "5", "3"));
+        assert_eq!(transfers[1].operator.as_deref(), Some(format!("0x{:0>40}", "cc").as_str()));
+        assert_eq!((transfers[1].log_index, transfers[1].block_number, transfers[1].id), (2, 7, 7_000_002));
+    }
+
+    #[test]
+    fn test_extract_logs() {
+        let receipt = TransformedReceipt {
+            logs: vec![
+                json!({ "address": "0xa", "logIndex": "0x5", "data": "0x01", "topics": [events::TRANSFER_TOPIC, "0x1", "0x2"],
+                        "event_signature": "Transfer(address,address,uint256)" }),
+                json!({ "address": "0xb", "logIndex": "0x6", "data": "0x", "topics": [], "removed": true }),
+            ],
+            ..fixtures::receipt(7, 3)
+        };
+
+        let logs = extract_logs(&[receipt]);
+        assert_eq!(logs.len(), 2);
+        assert_eq!((logs[0].transaction_hash.clone(), logs[0].transaction_index, logs[0].log_index), (fixtures::transaction_hash(7, 3), 3, 5));
+        assert_eq!((logs[0].topic0.as_deref(), logs[0].topic2.as_deref(), logs[0].topic3.as_deref()), (Some(events::TRANSFER_TOPIC), Some("0x2"), None));
+        assert_eq!(logs[0].event_signature.as_deref(), Some("Transfer(address,address,uint256)"));
+        assert_eq!((logs[1].topic0.as_deref(), logs[1].data.as_str(), logs[1].removed), (None, "0x", true));
+        assert_eq!((logs[0].id, logs[1].id), (7_000_005, 7_000_006));
+
+        // The rows give a fetched receipt its logs back, unmapped fields included
+        let raw: Receipt = serde_json::from_value(serde_json::json!({
+            "blockHash": "0xb", "blockNumber": "0x7", "contractAddress": null, "cumulativeGasUsed": "0x0", "from": "0xf",
+            "gasUsed": "0x0", "logsBloom": "0x", "status": "0x1", "to": null, "transactionHash": "0xh", "transactionIndex": "0x3", "type": "0x2",
+            "logs": [
+                { "address": "0xA", "blockHash": "0xb", "blockNumber": "0x7", "blockTimestamp": "0x10", "data": "0x01", "logIndex": "0x5",
+                  "removed": false, "topics": [events::TRANSFER_TOPIC, "0x1", "0x2"], "transactionHash": "0xh", "transactionIndex": "0x3" },
+                { "address": "0xb", "blockHash": "0xb", "blockNumber": "0x7", "data": "0x", "logIndex": "0x6", "removed": false,
+                  "topics": [], "transactionHash": "0xh", "transactionIndex": "0x3" },
+            ],
+        }))
+        .unwrap();
+        let receipt = transform_receipt(&raw, DateTime::default());
+        let logs = extract_logs(std::slice::from_ref(&receipt));
+        assert_eq!(logs[0].extra["blockTimestamp"], "0x10");
+        assert_eq!(logs.iter().map(receipt_log).collect::<Vec<_>>(), receipt.logs);
+    }
+
+    #[test]
+    fn test_extract_bridge_messages() {
+        let hash = format!("0x{}", "cd".repeat(32));
+        let receipt = TransformedReceipt {
+            logs: vec![
+                json!({ "address": bridge::MESSAGE_SERVICES[1], "logIndex": "0x4", "data": "0x", "topics": [bridge::MESSAGE_CLAIMED_TOPIC, hash] }),
+                json!({ "address": "0xa", "logIndex": "0x5", "data": "0x", "topics": [bridge::MESSAGE_CLAIMED_TOPIC, hash] }),
+            ],
+            ..fixtures::receipt(7, 3)
+        };
+
+        let messages = extract_bridge_messages(&[receipt]);
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!((message.event.as_str(), message.direction.as_str(), message.message_hash.as_str()), ("MessageClaimed", "l1_to_l2", hash.as_str()));
+        assert_eq!((message.transaction_index, message.log_index, message.contract_address.as_str()), (3, 4, bridge::MESSAGE_SERVICES[1]));
+        assert_eq!((message.sender.as_deref(), message.value.as_deref(), message.id), (None, None, 7_000_004));
+    }
+
+    #[test]
+    fn test_data_usage() {
+        let block: Block = serde_json::from_value(serde_json::json!({
+            "blobGasUsed": "0x60000", "excessBlobGas": "0x0", "difficulty": "0x0", "extraData": "0x",
+            "gasLimit": "0x1", "gasUsed": "0x5208", "hash": "0xa", "logsBloom": "0x", "miner": "0x0",
+            "mixHash": "0x0", "nonce": "0x0", "number": "0x10", "parentHash": "0x0", "receiptsRoot": "0x0",
+            "sha3Uncles": "0x0", "size": "0x200", "stateRoot": "0x0", "timestamp": "0x1", "totalDifficulty": "0x0",
+            "transactions": [], "transactionsRoot": "0x0", "uncles": []
+        }))
+        .unwrap();
+        let tx = |input: &str, blobs: Option<Vec<&str>>| -> Transaction {
+            serde_json::from_value(serde_json::json!({
+                "blockHash": "0xa", "blockNumber": "0x10", "chainId": "0x1", "from": "0x1", "gas": "0x0",
+                "gasPrice": "0x0", "hash": "0xh", "input": input, "nonce": "0x0", "r": "0x", "s": "0x",
+                "to": null, "transactionIndex": "0x0", "type": "0x3", "v": "0x", "value": "0x0",
+                "blobVersionedHashes": blobs,
+            }))
+            .unwrap()
+        };
+        let transactions = vec![tx("0xa9059cbb0000", None), tx("0x", Some(vec!["0x01", "0x01", "0x01"]))];
+
+        let usage = data_usage(&block, &transactions);
+        assert_eq!((usage.calldata_zero_bytes, usage.calldata_nonzero_bytes), (2, 4));
+        assert_eq!(usage.calldata_bytes, 6);
+        assert_eq!(usage.calldata_gas, 2 * 4 + 4 * 16);
+        assert_eq!((usage.blob_transactions, usage.blob_count), (1, 3));
+        assert_eq!(usage.blob_gas_used, Some(3 * 131072));
+        assert_eq!(usage.transaction_count, 2);
+    }
+
+    #[test]
+    fn test_ordering_analysis() {
+        assert_eq!(count_inversions(&[1, 2, 3]), 0);
+        assert_eq!(count_inversions(&[3, 1, 2]), 2);
+        assert_eq!(count_inversions(&[4, 3, 2, 1]), 6);
+
+        let block = TransformedBlock { base_fee_per_gas: Some(10), ..fixtures::block(7) };
+        let receipt = |index: u64, price: u64| TransformedReceipt {
+            effective_gas_price: price,
+            transaction_hash: format!("0x{}", index),
+            ..fixtures::receipt(7, index)
+        };
+        // Fees 5, 3, 4: one later transaction outbids an earlier one
+        let receipts = vec![receipt(2, 14), receipt(0, 15), receipt(1, 13)];
+        let start = Utc.timestamp_opt(100, 0).unwrap();
+        let first_seen = |hash: &str| match hash {
+            "0x0" => Some(start + chrono::Duration::seconds(5)),
+            "0x1" => Some(start),
+            _ => None,
+        };
+
+        let analysis = ordering_analysis(&block, &receipts, first_seen);
+        assert_eq!(analysis.transaction_count, 3);
+        assert_eq!((analysis.min_priority_fee, analysis.max_priority_fee), (Some(3), Some(5)));
+        assert_eq!(analysis.priority_fee_inversions, 1);
+        assert_eq!(analysis.priority_fee_adherence, Some(1.0 - 1.0 / 3.0));
+        assert_eq!((analysis.first_seen_count, analysis.first_seen_inversions), (2, 1));
+        assert_eq!(analysis.first_seen_adherence, Some(0.0));
+    }
+}
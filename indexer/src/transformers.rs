@@ -0,0 +1,126 @@
+//! Custom enrichment steps for library users, run on every transformed batch before it is filtered
+//! and written. A step implements [`Transformer`] and is added with [`register`] once at startup:
+//!
+//! ```no_run
+//! use indexer::pipeline::TransformedBatch;
+//! use indexer::transformers::{self, Transformer};
+//!
+//! struct UsdcOnly;
+//!
+//! impl Transformer for UsdcOnly {
+//!     fn name(&self) -> &str {
+//!         "usdc_only"
+//!     }
+//!
+//!     fn transform(&self, batch: &mut TransformedBatch) -> anyhow::Result<()> {
+//!         let usdc = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+//!         batch.token_transfers.retain(|transfer| transfer.token_address == usdc);
+//!         for tx in &mut batch.transactions {
+//!             tx.extra.insert("calls_usdc".into(), (tx.to.as_deref() == Some(usdc)).into());
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! transformers::register(UsdcOnly);
+//! ```
+//!
+//! Steps run in registration order on whole blocks: `index` and `fetch_range`/`index_range` (and
+//! so the Python and C interfaces) hand them everything fetched for a batch, blocks in order.
+//! Derived values go into the `extra` map of blocks, transactions and receipts, which is written
+//! with the record. An error fails the batch like any other transform error.
+
+use anyhow::Result;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use crate::pipeline::TransformedBatch;
+
+pub trait Transformer: Send + Sync {
+    // For logs and errors
+    fn name(&self) -> &str;
+
+    fn transform(&self, batch: &mut TransformedBatch) -> Result<()>;
+}
+
+#[derive(Clone, Default)]
+pub struct Transformers(Vec<Arc<dyn Transformer>>);
+
+impl Transformers {
+    pub fn push(&mut self, transformer: impl Transformer + 'static) {
+        self.0.push(Arc::new(transformer));
+    }
+
+    pub fn apply(&self, batch: &mut TransformedBatch) -> Result<()> {
+        for transformer in &self.0 {
+            transformer
+                .transform(batch)
+                .map_err(|e| anyhow::anyhow!("Transformer {} failed: {}", transformer.name(), e))?;
+        }
+        Ok(())
+    }
+}
+
+static REGISTERED: LazyLock<RwLock<Transformers>> = LazyLock::new(|| RwLock::new(Transformers::default()));
+
+// Adds a step to every batch transformed from now on
+pub fn register(transformer: impl Transformer + 'static) {
+    let mut registered = REGISTERED.write().unwrap_or_else(|e| e.into_inner());
+    log::info!("Registered transformer {}", transformer.name());
+    registered.push(transformer);
+}
+
+// Runs the registered steps on a batch
+pub fn apply(batch: &mut TransformedBatch) -> Result<()> {
+    let registered = REGISTERED.read().unwrap_or_else(|e| e.into_inner()).clone();
+    registered.apply(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::TransformedTransaction;
+
+    struct ToContract(&'static str);
+
+    impl Transformer for ToContract {
+        fn name(&self) -> &str {
+            "to_contract"
+        }
+
+        fn transform(&self, batch: &mut TransformedBatch) -> Result<()> {
+            batch.transactions.retain(|tx| tx.to.as_deref() == Some(self.0));
+            for tx in &mut batch.transactions {
+                tx.extra.insert("watched".into(), true.into());
+            }
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    impl Transformer for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn transform(&self, _batch: &mut TransformedBatch) -> Result<()> {
+            Err(anyhow::anyhow!("no price feed"))
+        }
+    }
+
+    #[test]
+    fn test_transformers_run_in_order() {
+        let tx = |to: &str| TransformedTransaction { to: Some(to.to_string()), ..fixtures::transaction(7, 0) };
+        let mut batch = TransformedBatch { transactions: vec![tx("0xa"), tx("0xb")], ..Default::default() };
+        let mut transformers = Transformers::default();
+        transformers.push(ToContract("0xa"));
+        transformers.apply(&mut batch).unwrap();
+        assert_eq!(batch.transactions.len(), 1);
+        assert_eq!(batch.transactions[0].extra["watched"], true);
+
+        transformers.push(Failing);
+        let error = transformers.apply(&mut batch).err().unwrap();
+        assert_eq!(error.to_string(), "Transformer failing failed: no price feed");
+    }
+}
@@ -0,0 +1,426 @@
+use anyhow::Result;
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+
+// Minimal RLP encoding, Merkle Patricia trie root and the typed transaction/receipt
+// envelopes needed to recompute `transactionsRoot` and `receiptsRoot` from RPC JSON.
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = trim_leading_zeros(&len.to_be_bytes()).to_vec();
+        let mut out = vec![offset + 55 + len_bytes.len() as u8];
+        out.extend(len_bytes);
+        out
+    }
+}
+
+pub fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = encode_length(bytes.len(), 0x80);
+    out.extend_from_slice(bytes);
+    out
+}
+
+// Wraps already-encoded items into a list
+pub fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload: Vec<u8> = items.concat();
+    let mut out = encode_length(payload.len(), 0xc0);
+    out.extend(payload);
+    out
+}
+
+pub fn rlp_u64(value: u64) -> Vec<u8> {
+    rlp_bytes(trim_leading_zeros(&value.to_be_bytes()))
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[first..]
+}
+
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    let padded = if digits.len() % 2 == 1 {
+        format!("0{}", digits)
+    } else {
+        digits.to_string()
+    };
+    (0..padded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&padded[i..i + 2], 16).map_err(|e| anyhow::anyhow!("Invalid hex {}: {}", hex, e)))
+        .collect()
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for b in bytes {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a str> {
+    value[key]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Missing field {}", key))
+}
+
+// Quantity fields are encoded as minimal big-endian integers
+fn rlp_quantity(value: &Value, key: &str) -> Result<Vec<u8>> {
+    Ok(rlp_bytes(trim_leading_zeros(&decode_hex(field(value, key)?)?)))
+}
+
+fn rlp_data(value: &Value, key: &str) -> Result<Vec<u8>> {
+    Ok(rlp_bytes(&decode_hex(field(value, key)?)?))
+}
+
+// `to` is empty for contract creations
+fn rlp_optional_address(value: &Value, key: &str) -> Result<Vec<u8>> {
+    match value[key].as_str() {
+        Some(address) => Ok(rlp_bytes(&decode_hex(address)?)),
+        None => Ok(rlp_bytes(&[])),
+    }
+}
+
+fn rlp_hex_list(value: &Value, key: &str) -> Result<Vec<u8>> {
+    let items = value[key]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| {
+                    let hex = item.as_str().ok_or_else(|| anyhow::anyhow!("Invalid entry in {}", key))?;
+                    Ok(rlp_bytes(&decode_hex(hex)?))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    Ok(rlp_list(&items))
+}
+
+fn rlp_access_list(tx: &Value) -> Result<Vec<u8>> {
+    let entries = match tx["accessList"].as_array() {
+        Some(entries) => entries
+            .iter()
+            .map(|entry| Ok(rlp_list(&[rlp_data(entry, "address")?, rlp_hex_list(entry, "storageKeys")?])))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    Ok(rlp_list(&entries))
+}
+
+fn rlp_authorization_list(tx: &Value) -> Result<Vec<u8>> {
+    let entries = match tx["authorizationList"].as_array() {
+        Some(entries) => entries
+            .iter()
+            .map(|auth| {
+                Ok(rlp_list(&[
+                    rlp_quantity(auth, "chainId")?,
+                    rlp_data(auth, "address")?,
+                    rlp_quantity(auth, "nonce")?,
+                    rlp_quantity(auth, "yParity")?,
+                    rlp_quantity(auth, "r")?,
+                    rlp_quantity(auth, "s")?,
+                ]))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    Ok(rlp_list(&entries))
+}
+
+// Typed transactions use yParity; older nodes only return `v`
+fn rlp_y_parity(tx: &Value) -> Result<Vec<u8>> {
+    if tx["yParity"].is_string() {
+        rlp_quantity(tx, "yParity")
+    } else {
+        rlp_quantity(tx, "v")
+    }
+}
+
+fn tx_type(value: &Value) -> u64 {
+    value["type"]
+        .as_str()
+        .map(crate::transform::hex_to_u64)
+        .unwrap_or(0)
+}
+
+// Consensus encoding of a transaction as it appears in the transactions trie
+pub fn encode_transaction(tx: &Value) -> Result<Vec<u8>> {
+    let kind = tx_type(tx);
+    let fields = match kind {
+        0 => vec![
+            rlp_quantity(tx, "nonce")?,
+            rlp_quantity(tx, "gasPrice")?,
+            rlp_quantity(tx, "gas")?,
+            rlp_optional_address(tx, "to")?,
+            rlp_quantity(tx, "value")?,
+            rlp_data(tx, "input")?,
+            rlp_quantity(tx, "v")?,
+            rlp_quantity(tx, "r")?,
+            rlp_quantity(tx, "s")?,
+        ],
+        1 => vec![
+            rlp_quantity(tx, "chainId")?,
+            rlp_quantity(tx, "nonce")?,
+            rlp_quantity(tx, "gasPrice")?,
+            rlp_quantity(tx, "gas")?,
+            rlp_optional_address(tx, "to")?,
+            rlp_quantity(tx, "value")?,
+            rlp_data(tx, "input")?,
+            rlp_access_list(tx)?,
+            rlp_y_parity(tx)?,
+            rlp_quantity(tx, "r")?,
+            rlp_quantity(tx, "s")?,
+        ],
+        2 => vec![
+            rlp_quantity(tx, "chainId")?,
+            rlp_quantity(tx, "nonce")?,
+            rlp_quantity(tx, "maxPriorityFeePerGas")?,
+            rlp_quantity(tx, "maxFeePerGas")?,
+            rlp_quantity(tx, "gas")?,
+            rlp_optional_address(tx, "to")?,
+            rlp_quantity(tx, "value")?,
+            rlp_data(tx, "input")?,
+            rlp_access_list(tx)?,
+            rlp_y_parity(tx)?,
+            rlp_quantity(tx, "r")?,
+            rlp_quantity(tx, "s")?,
+        ],
+        3 => vec![
+            rlp_quantity(tx, "chainId")?,
+            rlp_quantity(tx, "nonce")?,
+            rlp_quantity(tx, "maxPriorityFeePerGas")?,
+            rlp_quantity(tx, "maxFeePerGas")?,
+            rlp_quantity(tx, "gas")?,
+            rlp_optional_address(tx, "to")?,
+            rlp_quantity(tx, "value")?,
+            rlp_data(tx, "input")?,
+            rlp_access_list(tx)?,
+            rlp_quantity(tx, "maxFeePerBlobGas")?,
+            rlp_hex_list(tx, "blobVersionedHashes")?,
+            rlp_y_parity(tx)?,
+            rlp_quantity(tx, "r")?,
+            rlp_quantity(tx, "s")?,
+        ],
+        4 => vec![
+            rlp_quantity(tx, "chainId")?,
+            rlp_quantity(tx, "nonce")?,
+            rlp_quantity(tx, "maxPriorityFeePerGas")?,
+            rlp_quantity(tx, "maxFeePerGas")?,
+            rlp_quantity(tx, "gas")?,
+            rlp_optional_address(tx, "to")?,
+            rlp_quantity(tx, "value")?,
+            rlp_data(tx, "input")?,
+            rlp_access_list(tx)?,
+            rlp_authorization_list(tx)?,
+            rlp_y_parity(tx)?,
+            rlp_quantity(tx, "r")?,
+            rlp_quantity(tx, "s")?,
+        ],
+        other => return Err(anyhow::anyhow!("Unsupported transaction type {}", other)),
+    };
+    Ok(with_type_prefix(kind, rlp_list(&fields)))
+}
+
+// Consensus encoding of a receipt as it appears in the receipts trie
+pub fn encode_receipt(receipt: &Value) -> Result<Vec<u8>> {
+    // Pre-Byzantium receipts carry a state root instead of a status code
+    let outcome = match receipt["root"].as_str() {
+        Some(root) if !receipt["status"].is_string() => rlp_bytes(&decode_hex(root)?),
+        _ => rlp_quantity(receipt, "status")?,
+    };
+    let logs = receipt["logs"]
+        .as_array()
+        .map(|logs| {
+            logs.iter()
+                .map(|log| Ok(rlp_list(&[rlp_data(log, "address")?, rlp_hex_list(log, "topics")?, rlp_data(log, "data")?])))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let encoded = rlp_list(&[
+        outcome,
+        rlp_quantity(receipt, "cumulativeGasUsed")?,
+        rlp_data(receipt, "logsBloom")?,
+        rlp_list(&logs),
+    ]);
+    Ok(with_type_prefix(tx_type(receipt), encoded))
+}
+
+fn with_type_prefix(kind: u64, encoded: Vec<u8>) -> Vec<u8> {
+    if kind == 0 {
+        return encoded;
+    }
+    let mut out = vec![kind as u8];
+    out.extend(encoded);
+    out
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+// Hex-prefix encoding of a nibble path for leaf and extension nodes
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        out.push(flag << 4);
+        nibbles
+    };
+    for pair in rest.chunks(2) {
+        out.push((pair[0] << 4) | pair[1]);
+    }
+    out
+}
+
+// Nodes shorter than 32 bytes are embedded in their parent, others are referenced by hash
+fn node_reference(encoded: Vec<u8>) -> Vec<u8> {
+    if encoded.len() < 32 {
+        encoded
+    } else {
+        rlp_bytes(&keccak256(&encoded))
+    }
+}
+
+// `items` must be sorted by key
+fn encode_node(items: &[(Vec<u8>, &[u8])], depth: usize) -> Vec<u8> {
+    match items {
+        [] => rlp_bytes(&[]),
+        [(key, value)] => rlp_list(&[rlp_bytes(&hex_prefix(&key[depth..], true)), rlp_bytes(value)]),
+        _ => {
+            let first = &items[0].0;
+            let last = &items[items.len() - 1].0;
+            let shared = first[depth..]
+                .iter()
+                .zip(&last[depth..])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if shared > 0 {
+                let child = encode_node(items, depth + shared);
+                return rlp_list(&[
+                    rlp_bytes(&hex_prefix(&first[depth..depth + shared], false)),
+                    node_reference(child),
+                ]);
+            }
+
+            let mut branch = Vec::with_capacity(17);
+            let mut value = rlp_bytes(&[]);
+            let mut rest = items;
+            if rest[0].0.len() == depth {
+                value = rlp_bytes(rest[0].1);
+                rest = &rest[1..];
+            }
+            for nibble in 0..16u8 {
+                let count = rest.iter().take_while(|(key, _)| key[depth] == nibble).count();
+                let (group, remaining) = rest.split_at(count);
+                rest = remaining;
+                if group.is_empty() {
+                    branch.push(rlp_bytes(&[]));
+                } else {
+                    branch.push(node_reference(encode_node(group, depth + 1)));
+                }
+            }
+            branch.push(value);
+            rlp_list(&branch)
+        }
+    }
+}
+
+pub fn trie_root(entries: &[(Vec<u8>, Vec<u8>)]) -> [u8; 32] {
+    let mut items: Vec<(Vec<u8>, &[u8])> = entries
+        .iter()
+        .map(|(key, value)| (to_nibbles(key), value.as_slice()))
+        .collect();
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    keccak256(&encode_node(&items, 0))
+}
+
+// Root of a trie keyed by rlp(index), as used for transactions and receipts
+pub fn ordered_trie_root(values: Vec<Vec<u8>>) -> [u8; 32] {
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| (rlp_u64(index as u64), value))
+        .collect();
+    trie_root(&entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn root_hex(entries: &[(&str, &str)]) -> String {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = entries
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect();
+        encode_hex(&trie_root(&entries))
+    }
+
+    #[test]
+    fn test_rlp_encoding() {
+        assert_eq!(rlp_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(rlp_bytes(&[]), vec![0x80]);
+        assert_eq!(rlp_u64(0), vec![0x80]);
+        assert_eq!(rlp_u64(15), vec![0x0f]);
+        assert_eq!(rlp_u64(1024), vec![0x82, 0x04, 0x00]);
+        assert_eq!(
+            rlp_list(&[rlp_bytes(b"cat"), rlp_bytes(b"dog")]),
+            vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+        );
+        let long = rlp_bytes(&[b'a'; 56]);
+        assert_eq!(&long[..2], &[0xb8, 56]);
+    }
+
+    #[test]
+    fn test_empty_trie_root() {
+        assert_eq!(
+            encode_hex(&ordered_trie_root(vec![])),
+            "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+        );
+    }
+
+    #[test]
+    fn test_trie_roots_match_reference_vectors() {
+        assert_eq!(
+            root_hex(&[("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")]),
+            "0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+        );
+        assert_eq!(
+            root_hex(&[("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")]),
+            "0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+        );
+    }
+
+    #[test]
+    fn test_receipt_status_encoding() {
+        let receipt = json!({
+            "type": "0x2",
+            "status": "0x1",
+            "cumulativeGasUsed": "0x5208",
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "logs": []
+        });
+        let encoded = encode_receipt(&receipt).unwrap();
+        assert_eq!(encoded[0], 0x02);
+        // list header, then status 1 and cumulative gas 21000
+        assert_eq!(&encoded[4..8], &[0x01, 0x82, 0x52, 0x08]);
+    }
+}